@@ -0,0 +1,187 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+
+use crate::{resources, texture};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextureHandle(usize);
+
+type PendingLoad = Pin<Box<dyn Future<Output = anyhow::Result<Vec<u8>>>>>;
+
+/*
+The renderer's textures, loaded once per file and shared by every material that uses them.
+Materials hold handles, and their bind groups are rebuilt when a texture behind one changes.
+A texture started with load_texture_async is a placeholder until its file has been read and
+decoded in poll, so a scene can show up before its textures have. The placeholders are one
+texel, mid grey for color textures and a flat normal for linear ones. Images a scene decoded
+in the background (see resources::decode_files) are placeholders too, and poll uploads a few of
+them a frame so a big scene doesn't stall the one it's loaded in. Loading goes through
+&self, since textures are loaded while the scene is borrowed from the renderer's caller.
+Handles stay valid after unload_unused, an unloaded texture's slot keeps a placeholder and the
+file is read again if it's asked for.
+*/
+#[derive(Default)]
+pub struct Assets {
+	textures: RefCell<Vec<texture::Texture>>,
+	files: RefCell<HashMap<(String, texture::TextureType), TextureHandle>>,
+	pending: RefCell<Vec<(TextureHandle, String, texture::TextureType, PendingLoad)>>,
+	uploads: RefCell<VecDeque<(TextureHandle, String, texture::TextureType, image::DynamicImage)>>, // decoded, waiting for poll
+}
+
+impl Assets {
+	const UPLOADS_PER_POLL: usize = 2;
+
+	pub fn texture(&self, handle: TextureHandle) -> texture::Texture {
+		self.textures.borrow()[handle.0].clone()
+	}
+
+	pub fn len(&self) -> usize {
+		self.textures.borrow().len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.textures.borrow().is_empty()
+	}
+
+	// every texture's handle, with the file or name it was loaded from, None for added textures
+	pub fn list(&self) -> Vec<(TextureHandle, Option<String>)> {
+		let files = self.files.borrow();
+		(0..self.len()).map(TextureHandle)
+			.map(|handle| (handle, files.iter().find(|(_, h)| **h == handle).map(|((filename, _), _)| filename.clone())))
+			.collect()
+	}
+
+	// true while the texture is still its placeholder
+	pub fn is_loading(&self, handle: TextureHandle) -> bool {
+		self.pending.borrow().iter().any(|(pending, ..)| *pending == handle)
+			|| self.uploads.borrow().iter().any(|(upload, ..)| *upload == handle)
+	}
+
+	// true while decoded images are still waiting to be uploaded
+	pub fn is_uploading(&self) -> bool {
+		!self.uploads.borrow().is_empty()
+	}
+
+	// a texture that isn't from a file, like one generated at runtime
+	pub fn add_texture(&self, texture: texture::Texture) -> TextureHandle {
+		let mut textures = self.textures.borrow_mut();
+		textures.push(texture);
+		TextureHandle(textures.len() - 1)
+	}
+
+	fn cached(&self, filename: &str, ty: texture::TextureType) -> Option<TextureHandle> {
+		self.files.borrow().get(&(filename.to_string(), ty)).copied()
+	}
+
+	fn insert(&self, filename: &str, ty: texture::TextureType, texture: texture::Texture) -> TextureHandle {
+		let handle = self.add_texture(texture);
+		self.files.borrow_mut().insert((filename.to_string(), ty), handle);
+		handle
+	}
+
+	// the file's texture, read and uploaded the first time it's asked for as this type
+	pub async fn load_texture(&self, filename: &str, ty: texture::TextureType, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<TextureHandle> {
+		if let Some(handle) = self.cached(filename, ty) {
+			return Ok(handle);
+		}
+		if let Some(image) = resources::decoded_image(filename) {
+			let handle = self.insert(filename, ty, Self::placeholder(ty, device, queue));
+			self.uploads.borrow_mut().push_back((handle, filename.to_string(), ty, image));
+			return Ok(handle);
+		}
+		let texture = resources::load_texture(filename, ty, device, queue).await?;
+		Ok(self.insert(filename, ty, texture))
+	}
+
+	// an image already in memory, like one in a glTF binary, cached under a name made up for it
+	pub fn load_texture_from_bytes(&self, name: &str, data: &[u8], ty: texture::TextureType, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<TextureHandle> {
+		if let Some(handle) = self.cached(name, ty) {
+			return Ok(handle);
+		}
+		let texture = texture::Texture::from_bytes(device, queue, data, name, ty)?;
+		Ok(self.insert(name, ty, texture))
+	}
+
+	// like load_texture, without waiting for the file, the handle shows a placeholder until poll loads it
+	pub fn load_texture_async(&self, filename: &str, ty: texture::TextureType, device: &wgpu::Device, queue: &wgpu::Queue) -> TextureHandle {
+		if let Some(handle) = self.cached(filename, ty) {
+			return handle;
+		}
+		let handle = self.insert(filename, ty, Self::placeholder(ty, device, queue));
+		let path = filename.to_string();
+		let load: PendingLoad = Box::pin(async move { resources::load_binary(&path).await });
+		self.pending.borrow_mut().push((handle, filename.to_string(), ty, load));
+		handle
+	}
+
+	fn placeholder(ty: texture::TextureType, device: &wgpu::Device, queue: &wgpu::Queue) -> texture::Texture {
+		let texel = match ty {
+			texture::TextureType::Normal => [128, 128, 255, 255],
+			_ => [128, 128, 128, 255],
+		};
+		let image = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(1, 1, image::Rgba(texel)));
+		texture::Texture::from_images(device, queue, &vec![image], Some("placeholder"), ty).unwrap()
+	}
+
+	/*
+	Uploads the textures whose files have finished reading, and up to UPLOADS_PER_POLL decoded
+	ones, and returns their handles. Native reads finish the first time they're polled, web
+	requests finish in the browser between frames. A texture that fails to load is logged and
+	keeps its placeholder.
+	*/
+	pub fn poll(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Vec<TextureHandle> {
+		let mut context = Context::from_waker(Waker::noop());
+		let mut loaded = vec![];
+		self.pending.borrow_mut().retain_mut(|(handle, filename, ty, load)| {
+			let Poll::Ready(data) = load.as_mut().poll(&mut context) else {
+				return true;
+			};
+			match data.and_then(|data| texture::Texture::from_bytes(device, queue, &data, filename, *ty)) {
+				Ok(texture) => {
+					self.textures.borrow_mut()[handle.0] = texture;
+					loaded.push(*handle);
+				}
+				Err(e) => log::error!("Unable to load {}: {}", filename, e),
+			}
+			false
+		});
+		let mut uploads = self.uploads.borrow_mut();
+		let count = uploads.len().min(Self::UPLOADS_PER_POLL);
+		for (handle, filename, ty, image) in uploads.drain(..count) {
+			match texture::Texture::from_images(device, queue, &vec![image], Some(&filename), ty) {
+				Ok(texture) => {
+					self.textures.borrow_mut()[handle.0] = texture;
+					loaded.push(handle);
+				}
+				Err(e) => log::error!("Unable to load {}: {}", filename, e),
+			}
+		}
+		loaded
+	}
+
+	/*
+	Frees the textures loaded from files that aren't among the handles in use, like the old
+	scene's once another is swapped in, and returns how many there were. Added textures are
+	left alone, they have no file to come back from.
+	*/
+	pub fn unload_unused(&self, in_use: impl IntoIterator<Item = TextureHandle>, device: &wgpu::Device, queue: &wgpu::Queue) -> usize {
+		let in_use = in_use.into_iter().collect::<HashSet<_>>();
+		let mut unloaded = vec![];
+		self.files.borrow_mut().retain(|(_, ty), handle| {
+			let keep = in_use.contains(handle);
+			if !keep {
+				unloaded.push((*handle, *ty));
+			}
+			keep
+		});
+		self.pending.borrow_mut().retain(|(handle, ..)| in_use.contains(handle));
+		self.uploads.borrow_mut().retain(|(handle, ..)| in_use.contains(handle));
+		for &(handle, ty) in &unloaded {
+			self.textures.borrow_mut()[handle.0] = Self::placeholder(ty, device, queue);
+		}
+		unloaded.len()
+	}
+}
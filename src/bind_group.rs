@@ -0,0 +1,128 @@
+use crate::texture;
+
+/*
+Bind group layouts declared a binding at a time. Each method is a kind of binding, so only the
+binding number and what differs between bindings of that kind are written out, and the
+visibility is given once for the bindings that follow it. Layouts sharing their first bindings,
+like the material layouts, clone a builder and add to it.
+*/
+#[derive(Clone, Debug)]
+pub struct LayoutBuilder {
+	visibility: wgpu::ShaderStages,
+	entries: Vec<wgpu::BindGroupLayoutEntry>,
+}
+
+impl LayoutBuilder {
+	pub fn new(visibility: wgpu::ShaderStages) -> Self {
+		Self {
+			visibility,
+			entries: vec![],
+		}
+	}
+
+	// for the bindings added after it
+	pub fn visibility(mut self, visibility: wgpu::ShaderStages) -> Self {
+		self.visibility = visibility;
+		self
+	}
+
+	pub fn entry(mut self, binding: u32, ty: wgpu::BindingType) -> Self {
+		self.entries.push(wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: self.visibility,
+			ty,
+			count: None,
+		});
+		self
+	}
+
+	pub fn buffer(self, binding: u32, ty: wgpu::BufferBindingType) -> Self {
+		self.entry(binding, wgpu::BindingType::Buffer {
+			ty,
+			has_dynamic_offset: false,
+			min_binding_size: None,
+		})
+	}
+
+	pub fn uniform(self, binding: u32) -> Self {
+		self.buffer(binding, wgpu::BufferBindingType::Uniform)
+	}
+
+	// bound at an offset into a larger buffer per draw, with room for count Ts past the offset
+	pub fn dynamic_uniform<T>(self, binding: u32, count: usize) -> Self {
+		self.entry(binding, wgpu::BindingType::Buffer {
+			ty: wgpu::BufferBindingType::Uniform,
+			has_dynamic_offset: true,
+			min_binding_size: wgpu::BufferSize::new((std::mem::size_of::<T>() * count) as u64),
+		})
+	}
+
+	pub fn texture_of(self, binding: u32, sample_type: wgpu::TextureSampleType, view_dimension: wgpu::TextureViewDimension) -> Self {
+		self.entry(binding, wgpu::BindingType::Texture {
+			sample_type,
+			view_dimension,
+			multisampled: false,
+		})
+	}
+
+	// a filterable 2D texture, the common case
+	pub fn texture(self, binding: u32) -> Self {
+		self.texture_of(binding, wgpu::TextureSampleType::Float { filterable: true }, wgpu::TextureViewDimension::D2)
+	}
+
+	pub fn sampler_of(self, binding: u32, ty: wgpu::SamplerBindingType) -> Self {
+		self.entry(binding, wgpu::BindingType::Sampler(ty))
+	}
+
+	pub fn sampler(self, binding: u32) -> Self {
+		self.sampler_of(binding, wgpu::SamplerBindingType::Filtering)
+	}
+
+	pub fn build(&self, device: &wgpu::Device, label: &str) -> wgpu::BindGroupLayout {
+		device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some(label),
+			entries: &self.entries,
+		})
+	}
+}
+
+// the resources of a bind group, added a binding at a time like its layout
+#[derive(Default)]
+pub struct BindGroupBuilder<'a> {
+	entries: Vec<wgpu::BindGroupEntry<'a>>,
+}
+
+impl<'a> BindGroupBuilder<'a> {
+	pub fn resource(mut self, binding: u32, resource: wgpu::BindingResource<'a>) -> Self {
+		self.entries.push(wgpu::BindGroupEntry {
+			binding,
+			resource,
+		});
+		self
+	}
+
+	pub fn buffer(self, binding: u32, buffer: &'a wgpu::Buffer) -> Self {
+		self.resource(binding, buffer.as_entire_binding())
+	}
+
+	pub fn view(self, binding: u32, view: &'a wgpu::TextureView) -> Self {
+		self.resource(binding, wgpu::BindingResource::TextureView(view))
+	}
+
+	pub fn sampler(self, binding: u32, sampler: &'a wgpu::Sampler) -> Self {
+		self.resource(binding, wgpu::BindingResource::Sampler(sampler))
+	}
+
+	// the texture's view at binding and its sampler at the binding after, as the shaders declare them
+	pub fn texture(self, binding: u32, texture: &'a texture::Texture) -> Self {
+		self.view(binding, &texture.view).sampler(binding + 1, &texture.sampler)
+	}
+
+	pub fn build(&self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout, label: &str) -> wgpu::BindGroup {
+		device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some(label),
+			layout,
+			entries: &self.entries,
+		})
+	}
+}
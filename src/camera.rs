@@ -1,5 +1,8 @@
-use winit::keyboard::KeyCode;
+use winit::{event::{MouseButton, MouseScrollDelta}, keyboard::KeyCode};
 
+use crate::{environment, model, post};
+
+#[derive(Clone, Debug)]
 pub struct Camera {
 	pub eye: cgmath::Point3<f32>,
 	pub target: cgmath::Point3<f32>,
@@ -8,19 +11,88 @@ pub struct Camera {
 	pub fovy: f32,
 	pub znear: f32,
 	pub zfar: f32,
+	pub settings: ViewSettings,
+}
+
+/*
+Grading and post processing for one camera, so split screen views, minimaps, and virtual cameras
+don't have to share the main view's. Each setting left at None uses the scene's environment or
+the renderer's post settings. Virtual cameras don't run post processing, so post only applies to
+the camera passed to Renderer::render.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct ViewSettings {
+	pub exposure: Option<f32>,
+	pub tonemap: Option<environment::Tonemap>,
+	pub post: Option<post::PostSettings>,
 }
 
 impl Camera {
-	pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
-		let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
-		let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
+	// slightly above the origin looking down at it
+	pub fn new(aspect: f32) -> Self {
+		Self {
+			eye: (0.0, 1.0, 2.0).into(),
+			target: (0.0, 0.0, 0.0).into(),
+			up: cgmath::Vector3::unit_y(),
+			aspect,
+			fovy: 45.0,
+			znear: 0.1,
+			zfar: 100.0,
+			settings: ViewSettings::default(),
+		}
+	}
+
+	pub fn build_view_matrix(&self) -> cgmath::Matrix4<f32> {
+		cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up)
+	}
+
+	pub fn build_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+		OPENGL_TO_WGPU_MATRIX * cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar)
+	}
 
-		OPENGL_TO_WGPU_MATRIX * proj * view
+	pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
+		self.build_projection_matrix() * self.build_view_matrix()
 	}
 
     pub fn update_aspect(&mut self, width: u32, height: u32) {
         self.aspect = width as f32 / height as f32;
     }
+
+	/*
+	Looks at the center of the box from the current direction, at the closest distance where
+	every corner is in view. padding is the fraction of the view left free around the box.
+	*/
+	pub fn fit_to_bounds(&mut self, aabb: &model::Aabb, padding: f32) {
+		use cgmath::{Angle, InnerSpace};
+
+		let center = cgmath::Point3::from(aabb.center());
+		let forward = (self.target - self.eye).normalize();
+		let right = forward.cross(self.up).normalize();
+		let up = right.cross(forward);
+
+		let scale = 1.0 - padding.clamp(0.0, 0.9);
+		let tan_y = (cgmath::Rad::from(cgmath::Deg(self.fovy)) / 2.0).tan() * scale;
+		let tan_x = tan_y * self.aspect;
+
+		// a corner offset by x to the side and z further away is on the edge of the view at |x| / tan - z
+		let mut distance: f32 = 0.0;
+		let mut depth: f32 = 0.0;
+		for corner in aabb.corners() {
+			let offset = corner - center;
+			let z = offset.dot(forward);
+			distance = distance
+				.max(offset.dot(right).abs() / tan_x - z)
+				.max(offset.dot(up).abs() / tan_y - z);
+			depth = depth.max(z.abs());
+		}
+		// every corner also has to be past the near plane
+		let distance = distance.max(depth + self.znear);
+
+		self.target = center;
+		self.eye = center - forward * distance;
+		// large models would otherwise end up past the far plane
+		self.zfar = self.zfar.max((distance + depth) * 2.0);
+	}
 }
 
 #[rustfmt::skip]
@@ -31,22 +103,88 @@ pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::from_co
     cgmath::Vector4::new(0.0, 0.0, 0.5, 1.0),
 );
 
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct CameraUniform {
-	pub view_proj: [[f32; 4]; 4],
+/*
+Planes of a view projection's clip volume, facing inwards with normalized xyz so the w
+component gives the distance to the plane. The near plane is last so it can be dropped.
+*/
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum {
+	planes: [cgmath::Vector4<f32>; 6], // left, right, bottom, top, far, near
+}
+
+impl Frustum {
+	// wgpu clip space, where depth goes from 0 to 1
+	pub fn from_matrix(view_proj: &cgmath::Matrix4<f32>) -> Self {
+		use cgmath::{InnerSpace, Matrix};
+		let m = view_proj.transpose(); // rows of the view projection
+		let planes = [m.w + m.x, m.w - m.x, m.w + m.y, m.w - m.y, m.w - m.z, m.z]
+			.map(|plane| plane / plane.truncate().magnitude());
+		Self { planes }
+	}
+
+	// used for shadow casters, which cast into the volume from anywhere between it and the light
+	pub fn without_near(mut self) -> Self {
+		self.planes[5] = cgmath::Vector4::new(0.0, 0.0, 0.0, 1.0);
+		self
+	}
+
+	pub fn intersects_sphere(&self, center: cgmath::Point3<f32>, radius: f32) -> bool {
+		use cgmath::InnerSpace;
+		let center = cgmath::Vector4::new(center.x, center.y, center.z, 1.0);
+		self.planes.iter().all(|plane| plane.dot(center) >= -radius)
+	}
+}
+
+gpu_struct! {
+	Uniform
+	#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+	pub struct CameraUniform {
+		pub view: [[f32; 4]; 4],
+		pub proj: [[f32; 4]; 4],
+		pub view_proj: [[f32; 4]; 4],
+		pub inv_view: [[f32; 4]; 4],
+		pub inv_proj: [[f32; 4]; 4],
+		pub inv_view_proj: [[f32; 4]; 4],
+		pub position: [f32; 4], // world space, w is unused
+	}
+}
+
+impl Default for CameraUniform {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl CameraUniform {
 	pub fn new() -> Self {
 		use cgmath::SquareMatrix;
 		Self {
+			view: cgmath::Matrix4::identity().into(),
+			proj: cgmath::Matrix4::identity().into(),
 			view_proj: cgmath::Matrix4::identity().into(),
+			inv_view: cgmath::Matrix4::identity().into(),
+			inv_proj: cgmath::Matrix4::identity().into(),
+			inv_view_proj: cgmath::Matrix4::identity().into(),
+			position: [0.0, 0.0, 0.0, 1.0],
+			..bytemuck::Zeroable::zeroed()
 		}
 	}
 
 	pub fn update_view_proj(&mut self, camera: &Camera) {
-		self.view_proj = camera.build_view_projection_matrix().into();
+		self.update_matrices(camera.build_view_matrix(), camera.build_projection_matrix(), camera.eye);
+	}
+
+	// for views that aren't described by a Camera, like orthographic bakes
+	pub fn update_matrices(&mut self, view: cgmath::Matrix4<f32>, proj: cgmath::Matrix4<f32>, eye: cgmath::Point3<f32>) {
+		use cgmath::SquareMatrix;
+		let view_proj = proj * view;
+		self.view = view.into();
+		self.proj = proj.into();
+		self.view_proj = view_proj.into();
+		self.inv_view = view.invert().unwrap_or(cgmath::Matrix4::identity()).into();
+		self.inv_proj = proj.invert().unwrap_or(cgmath::Matrix4::identity()).into();
+		self.inv_view_proj = view_proj.invert().unwrap_or(cgmath::Matrix4::identity()).into();
+		self.position = eye.to_homogeneous().into();
 	}
 }
 
@@ -118,4 +256,211 @@ impl CameraController {
             camera.eye = camera.target - (forward - right * self.speed).normalize() * forward_mag;
         }
     }
-}
\ No newline at end of file
+}
+/*
+First person controls, the mouse turns the camera and WASD moves it along where it looks,
+with space and shift for up and down. Yaw and pitch are in radians, the camera's target is
+kept at the same distance in front of the eye.
+*/
+pub struct FirstPersonController {
+	pub speed: f32, // world units per frame
+	pub sensitivity: f32, // radians per pixel of mouse motion
+	yaw: f32,
+	pitch: f32,
+	distance: f32, // from the eye to the target
+	is_forward_pressed: bool,
+	is_backward_pressed: bool,
+	is_left_pressed: bool,
+	is_right_pressed: bool,
+	is_up_pressed: bool,
+	is_down_pressed: bool,
+}
+
+impl FirstPersonController {
+	const MAX_PITCH: f32 = 1.55; // just under straight up, where the view matrix flips
+
+	pub fn new(speed: f32, sensitivity: f32) -> Self {
+		Self {
+			speed,
+			sensitivity,
+			yaw: 0.0,
+			pitch: 0.0,
+			distance: 1.0,
+			is_forward_pressed: false,
+			is_backward_pressed: false,
+			is_left_pressed: false,
+			is_right_pressed: false,
+			is_up_pressed: false,
+			is_down_pressed: false,
+		}
+	}
+
+	// picks up the camera's current direction, so taking over from another controller doesn't snap
+	pub fn look_from(&mut self, camera: &Camera) {
+		use cgmath::InnerSpace;
+		let forward = camera.target - camera.eye;
+		self.distance = forward.magnitude().max(0.1);
+		let forward = forward / self.distance;
+		self.yaw = forward.z.atan2(forward.x);
+		self.pitch = forward.y.clamp(-1.0, 1.0).asin().clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+	}
+
+	pub fn handle_key(&mut self, code: KeyCode, is_pressed: bool) -> bool {
+		match code {
+			KeyCode::KeyW | KeyCode::ArrowUp => self.is_forward_pressed = is_pressed,
+			KeyCode::KeyA | KeyCode::ArrowLeft => self.is_left_pressed = is_pressed,
+			KeyCode::KeyS | KeyCode::ArrowDown => self.is_backward_pressed = is_pressed,
+			KeyCode::KeyD | KeyCode::ArrowRight => self.is_right_pressed = is_pressed,
+			KeyCode::Space => self.is_up_pressed = is_pressed,
+			KeyCode::ShiftLeft => self.is_down_pressed = is_pressed,
+			_ => return false,
+		}
+		true
+	}
+
+	// delta is the raw mouse motion in pixels
+	pub fn handle_mouse_motion(&mut self, delta: (f64, f64)) {
+		self.yaw += delta.0 as f32 * self.sensitivity;
+		self.pitch = (self.pitch - delta.1 as f32 * self.sensitivity).clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+	}
+
+	pub fn update_camera(&self, camera: &mut Camera) {
+		use cgmath::InnerSpace;
+		let forward = cgmath::Vector3::new(self.yaw.cos() * self.pitch.cos(), self.pitch.sin(), self.yaw.sin() * self.pitch.cos());
+		let right = forward.cross(camera.up).normalize();
+
+		let mut movement = cgmath::Vector3::new(0.0, 0.0, 0.0);
+		if self.is_forward_pressed {
+			movement += forward;
+		}
+		if self.is_backward_pressed {
+			movement -= forward;
+		}
+		if self.is_right_pressed {
+			movement += right;
+		}
+		if self.is_left_pressed {
+			movement -= right;
+		}
+		if self.is_up_pressed {
+			movement += camera.up;
+		}
+		if self.is_down_pressed {
+			movement -= camera.up;
+		}
+		if movement.magnitude2() > 0.0 {
+			camera.eye += movement.normalize() * self.speed;
+		}
+		camera.target = camera.eye + forward * self.distance;
+	}
+}
+
+// which controller moves the camera, switched at runtime
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CameraMode {
+	FirstPerson, // the key controller, and mouse look while the right button is held
+	Orbit, // drag around the target, for inspecting models
+}
+
+/*
+Orbit controls for inspecting a model, dragging with the left button rotates the eye
+around the target, dragging with the middle button pans both, and scrolling zooms toward
+the target. The camera is changed as the events arrive, so there's nothing to update per frame.
+*/
+pub struct OrbitController {
+	pub rotate_sensitivity: f32, // radians per pixel
+	pub pan_sensitivity: f32, // fraction of the distance to the target per pixel
+	pub zoom_speed: f32, // fraction of the distance per scroll line
+	is_rotating: bool,
+	is_panning: bool,
+	cursor: Option<(f64, f64)>, // last position, None until the cursor moves in the window
+}
+
+impl OrbitController {
+	const MAX_PITCH: f32 = 1.55;
+	const MIN_DISTANCE: f32 = 0.05;
+
+	pub fn new(rotate_sensitivity: f32, pan_sensitivity: f32, zoom_speed: f32) -> Self {
+		Self {
+			rotate_sensitivity,
+			pan_sensitivity,
+			zoom_speed,
+			is_rotating: false,
+			is_panning: false,
+			cursor: None,
+		}
+	}
+
+	pub fn handle_mouse_button(&mut self, button: MouseButton, is_pressed: bool) {
+		match button {
+			MouseButton::Left => self.is_rotating = is_pressed,
+			MouseButton::Middle => self.is_panning = is_pressed,
+			_ => {}
+		}
+	}
+
+	pub fn handle_cursor_moved(&mut self, camera: &mut Camera, position: (f64, f64)) {
+		use cgmath::InnerSpace;
+		let Some(last) = self.cursor.replace(position) else {
+			return;
+		};
+		let dx = (position.0 - last.0) as f32;
+		let dy = (position.1 - last.1) as f32;
+		let offset = camera.eye - camera.target;
+		let distance = offset.magnitude();
+
+		if self.is_rotating {
+			let yaw = offset.z.atan2(offset.x) + dx * self.rotate_sensitivity;
+			let pitch = ((offset.y / distance).clamp(-1.0, 1.0).asin() + dy * self.rotate_sensitivity).clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+			camera.eye = camera.target + cgmath::Vector3::new(yaw.cos() * pitch.cos(), pitch.sin(), yaw.sin() * pitch.cos()) * distance;
+		} else if self.is_panning {
+			let forward = -offset / distance;
+			let right = forward.cross(camera.up).normalize();
+			let up = right.cross(forward);
+			// the point under the cursor stays under it
+			let movement = (up * dy - right * dx) * self.pan_sensitivity * distance;
+			camera.eye += movement;
+			camera.target += movement;
+		}
+	}
+
+	pub fn handle_scroll(&mut self, camera: &mut Camera, delta: MouseScrollDelta) {
+		let lines = match delta {
+			MouseScrollDelta::LineDelta(_, y) => y,
+			MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+		};
+		use cgmath::InnerSpace;
+		let offset = camera.eye - camera.target;
+		let scale = (1.0 - lines * self.zoom_speed).clamp(0.1, 10.0);
+		let distance = (offset.magnitude() * scale).max(Self::MIN_DISTANCE);
+		camera.eye = camera.target + offset.normalize() * distance;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use cgmath::InnerSpace;
+
+	#[test]
+	fn frustum_keeps_spheres_touching_the_view() {
+		let camera = Camera::new(1.0);
+		let frustum = Frustum::from_matrix(&camera.build_view_projection_matrix());
+		let forward = (camera.target - camera.eye).normalize();
+		let along = |distance: f32| camera.eye + forward * distance;
+
+		assert!(frustum.intersects_sphere(camera.target, 0.1));
+		assert!(!frustum.intersects_sphere(camera.eye - forward, 0.5));
+		assert!(!frustum.intersects_sphere(along(150.0), 10.0));
+		assert!(frustum.intersects_sphere(along(150.0), 60.0));
+
+		// 10 units out the view is about 4.1 units either side
+		let side = along(10.0) + cgmath::Vector3::unit_x() * 6.0;
+		assert!(!frustum.intersects_sphere(side, 1.0));
+		assert!(frustum.intersects_sphere(side, 2.0));
+
+		// in front of the near plane only counts without it
+		assert!(!frustum.intersects_sphere(along(0.05), 0.01));
+		assert!(frustum.without_near().intersects_sphere(along(0.05), 0.01));
+	}
+}
@@ -0,0 +1,298 @@
+use std::{fmt, sync::Arc};
+
+use winit::window::Window;
+
+use crate::{ray_tracing, renderer, texture};
+
+/*
+What the adapter and surface support, logged at startup and printed by --gpu-info to help
+triage rendering differences between machines
+*/
+pub struct Capabilities {
+	pub adapter: wgpu::AdapterInfo,
+	pub limits: wgpu::Limits,
+	pub features: wgpu::Features,
+	pub downlevel: wgpu::DownlevelCapabilities,
+	pub surface_formats: Vec<wgpu::TextureFormat>,
+	pub present_modes: Vec<wgpu::PresentMode>,
+	pub alpha_modes: Vec<wgpu::CompositeAlphaMode>,
+	pub surface_usages: wgpu::TextureUsages,
+	pub msaa_sample_counts: Vec<u32>, // supported by both the preferred surface format and the depth format
+	pub hdr_format_features: wgpu::TextureFormatFeatures, // of texture::Texture::HDR_FORMAT, which the scene renders into
+}
+
+impl Capabilities {
+	pub fn new(adapter: &wgpu::Adapter, surface: &wgpu::Surface) -> Self {
+		let surface_caps = surface.get_capabilities(adapter);
+		let color_flags = adapter.get_texture_format_features(Self::preferred_format(&surface_caps.formats)).flags;
+		let depth_flags = adapter.get_texture_format_features(texture::Texture::DEPTH_FORMAT).flags;
+		let msaa_sample_counts = [1, 2, 4, 8, 16].into_iter()
+			.filter(|&count| color_flags.sample_count_supported(count) && depth_flags.sample_count_supported(count))
+			.collect();
+
+		Self {
+			adapter: adapter.get_info(),
+			limits: adapter.limits(),
+			features: adapter.features(),
+			downlevel: adapter.get_downlevel_capabilities(),
+			surface_formats: surface_caps.formats,
+			present_modes: surface_caps.present_modes,
+			alpha_modes: surface_caps.alpha_modes,
+			surface_usages: surface_caps.usages,
+			msaa_sample_counts,
+			hdr_format_features: adapter.get_texture_format_features(texture::Texture::HDR_FORMAT),
+		}
+	}
+
+	// srgb if there is one, so shaders can output linear colors
+	pub fn preferred_surface_format(&self) -> wgpu::TextureFormat {
+		Self::preferred_format(&self.surface_formats)
+	}
+
+	fn preferred_format(formats: &[wgpu::TextureFormat]) -> wgpu::TextureFormat {
+		formats.iter().find(|f| f.is_srgb()).copied().unwrap_or(formats[0])
+	}
+
+	// the requested mode if the surface has it, otherwise fifo, which every surface has
+	pub fn supported_present_mode(&self, present_mode: wgpu::PresentMode) -> wgpu::PresentMode {
+		match present_mode {
+			// wgpu picks from what the surface has for these
+			wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync => present_mode,
+			_ if self.present_modes.contains(&present_mode) => present_mode,
+			_ => {
+				log::warn!("{:?} presenting isn't supported, falling back to Fifo", present_mode);
+				wgpu::PresentMode::Fifo
+			}
+		}
+	}
+
+	// queries the adapter the renderer would pick for this window, without creating a device
+	pub async fn query(window: Arc<Window>) -> anyhow::Result<Self> {
+		let instance = renderer::Renderer::create_instance();
+		let surface = instance.create_surface(window)?;
+		let adapter = renderer::Renderer::request_adapter(&instance, &surface, wgpu::PowerPreference::default()).await?;
+		Ok(Self::new(&adapter, &surface))
+	}
+}
+
+impl fmt::Display for Capabilities {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let info = &self.adapter;
+		writeln!(f, "Adapter: {} ({:?})", info.name, info.device_type)?;
+		writeln!(f, "Backend: {:?}", info.backend)?;
+		writeln!(f, "Driver: {} {}", info.driver, info.driver_info)?;
+		writeln!(f, "Vendor/device: {:#06x}/{:#06x}", info.vendor, info.device)?;
+		writeln!(f, "Shader model: {:?}", self.downlevel.shader_model)?;
+		writeln!(f, "Downlevel flags: {:?}", self.downlevel.flags)?;
+		writeln!(f, "Features: {:?}", self.features)?;
+		writeln!(f, "Surface formats: {:?}", self.surface_formats)?;
+		writeln!(f, "Present modes: {:?}", self.present_modes)?;
+		writeln!(f, "Alpha modes: {:?}", self.alpha_modes)?;
+		writeln!(f, "Surface usages: {:?}", self.surface_usages)?;
+		writeln!(f, "MSAA sample counts: {:?}", self.msaa_sample_counts)?;
+		writeln!(f, "HDR target: {:?} {:?}", self.hdr_format_features.allowed_usages, self.hdr_format_features.flags)?;
+		write!(f, "Limits: {:#?}", self.limits)
+	}
+}
+
+// what the renderer would like to use, before checking what the adapter supports
+#[derive(Clone, Debug)]
+pub struct DesiredFeatures {
+	pub msaa_samples: u32,
+	pub compressed_textures: bool,
+	pub fragment_storage_buffers: bool,
+	pub timestamp_queries: bool,
+	pub compute: bool, // skinning and meshlet culling
+	pub indirect_draws: bool,
+	pub ray_queries: bool,
+	pub screen_copies: bool, // reading back the lit image, used by reflections
+	pub cube_array_textures: bool, // point light shadows
+	pub hdr_targets: bool, // rendering the scene in floating point and tonemapping it on the way to the surface
+	pub wireframe: bool, // line polygon mode for the wireframe view
+}
+
+impl Default for DesiredFeatures {
+	fn default() -> Self {
+		Self {
+			msaa_samples: 4,
+			compressed_textures: true,
+			fragment_storage_buffers: true,
+			timestamp_queries: true,
+			compute: true,
+			indirect_draws: true,
+			ray_queries: true,
+			screen_copies: true,
+			cube_array_textures: true,
+			hdr_targets: true,
+			wireframe: true,
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TextureCompression {
+	Bc,
+	Etc2,
+	Astc,
+}
+
+impl TextureCompression {
+	pub fn feature(&self) -> wgpu::Features {
+		match self {
+			TextureCompression::Bc => wgpu::Features::TEXTURE_COMPRESSION_BC,
+			TextureCompression::Etc2 => wgpu::Features::TEXTURE_COMPRESSION_ETC2,
+			TextureCompression::Astc => wgpu::Features::TEXTURE_COMPRESSION_ASTC,
+		}
+	}
+}
+
+/*
+What the renderer ended up with after mapping the desired features onto the adapter. Every
+feature that had to fall back is recorded, so the reason something is missing shows up in
+the log. WebGL2 ends up with no compute, storage buffers, indirect draws, cube arrays, or timestamps.
+*/
+#[derive(Clone, Debug)]
+pub struct RendererFeatures {
+	pub msaa_samples: u32,
+	pub texture_compression: Option<TextureCompression>,
+	pub fragment_storage_buffers: bool,
+	pub timestamp_queries: bool,
+	pub compute: bool,
+	pub indirect_draws: bool,
+	pub ray_queries: bool,
+	pub screen_copies: bool,
+	pub cube_array_textures: bool,
+	pub hdr_targets: bool,
+	pub wireframe: bool,
+	pub limits: wgpu::Limits,
+	pub fallbacks: Vec<String>,
+}
+
+impl RendererFeatures {
+	pub fn negotiate(desired: &DesiredFeatures, capabilities: &Capabilities) -> Self {
+		let mut fallbacks = vec![];
+		let flags = capabilities.downlevel.flags;
+		let downlevel = !capabilities.downlevel.is_webgpu_compliant() || cfg!(target_arch = "wasm32");
+		let features = capabilities.features;
+
+		let mut limits = if downlevel {
+			wgpu::Limits::downlevel_webgl2_defaults()
+		} else {
+			wgpu::Limits::default()
+		};
+
+		let msaa_samples = capabilities.msaa_sample_counts.iter().copied()
+			.filter(|&count| count <= desired.msaa_samples)
+			.max()
+			.unwrap_or(1);
+		if msaa_samples < desired.msaa_samples {
+			fallbacks.push(format!("MSAA {}x unsupported, using {}x", desired.msaa_samples, msaa_samples));
+		}
+
+		let texture_compression = if desired.compressed_textures {
+			let supported = [TextureCompression::Bc, TextureCompression::Astc, TextureCompression::Etc2].into_iter()
+				.find(|compression| features.contains(compression.feature()));
+			if supported.is_none() {
+				fallbacks.push("no compressed texture formats, textures stay uncompressed".to_string());
+			}
+			supported
+		} else {
+			None
+		};
+
+		let storage_buffers = limits.max_storage_buffers_per_shader_stage > 0;
+		let fragment_storage_buffers = desired.fragment_storage_buffers && storage_buffers
+			&& flags.contains(wgpu::DownlevelFlags::FRAGMENT_STORAGE);
+		if desired.fragment_storage_buffers && !fragment_storage_buffers {
+			fallbacks.push("no storage buffers in fragment shaders".to_string());
+		}
+
+		// the profiler writes its timestamps between passes
+		let timestamp_queries = desired.timestamp_queries
+			&& features.contains(wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS);
+		if desired.timestamp_queries && !timestamp_queries {
+			fallbacks.push("no timestamp queries between passes, GPU timings unavailable".to_string());
+		}
+
+		let compute = desired.compute && storage_buffers && flags.contains(wgpu::DownlevelFlags::COMPUTE_SHADERS);
+		if desired.compute && !compute {
+			fallbacks.push("no compute shaders, skinning and meshlet culling are disabled".to_string());
+		}
+
+		let indirect_draws = desired.indirect_draws && flags.contains(wgpu::DownlevelFlags::INDIRECT_EXECUTION);
+		if desired.indirect_draws && !indirect_draws {
+			fallbacks.push("no indirect draws, meshlets are disabled".to_string());
+		}
+
+		let ray_queries = desired.ray_queries && !downlevel && features.contains(ray_tracing::RayTracing::FEATURES);
+		if desired.ray_queries && !ray_queries {
+			fallbacks.push("no ray queries, ray traced shadows and reflections are disabled".to_string());
+		}
+		if ray_queries {
+			limits = limits.using_minimum_supported_acceleration_structure_values();
+		}
+
+		let screen_copies = desired.screen_copies && capabilities.surface_usages.contains(wgpu::TextureUsages::COPY_SRC);
+		if desired.screen_copies && !screen_copies {
+			fallbacks.push("surface can't be copied from, screen space reflections are disabled".to_string());
+		}
+
+		let cube_array_textures = desired.cube_array_textures && flags.contains(wgpu::DownlevelFlags::CUBE_ARRAY_TEXTURES);
+		if desired.cube_array_textures && !cube_array_textures {
+			fallbacks.push("no cube array textures, point lights don't cast shadows".to_string());
+		}
+
+		// the scene is blended into and the output stage filters it
+		let hdr = &capabilities.hdr_format_features;
+		let hdr_targets = desired.hdr_targets
+			&& hdr.allowed_usages.contains(wgpu::TextureUsages::RENDER_ATTACHMENT)
+			&& hdr.flags.contains(wgpu::TextureFormatFeatureFlags::BLENDABLE | wgpu::TextureFormatFeatureFlags::FILTERABLE);
+		if desired.hdr_targets && !hdr_targets {
+			fallbacks.push("no blendable float render targets, the scene is tonemapped by the material shaders".to_string());
+		}
+
+		let wireframe = desired.wireframe && features.contains(wgpu::Features::POLYGON_MODE_LINE);
+		if desired.wireframe && !wireframe {
+			fallbacks.push("no line polygon mode, the wireframe view is unavailable".to_string());
+		}
+
+		Self {
+			msaa_samples,
+			texture_compression,
+			fragment_storage_buffers,
+			timestamp_queries,
+			compute,
+			indirect_draws,
+			ray_queries,
+			screen_copies,
+			cube_array_textures,
+			hdr_targets,
+			wireframe,
+			limits,
+			fallbacks,
+		}
+	}
+
+	// device features to request for what was negotiated
+	pub fn required_features(&self) -> wgpu::Features {
+		let mut features = wgpu::Features::empty();
+		if let Some(compression) = self.texture_compression {
+			features |= compression.feature();
+		}
+		if self.timestamp_queries {
+			features |= wgpu::Features::TIMESTAMP_QUERY | wgpu::Features::TIMESTAMP_QUERY_INSIDE_ENCODERS;
+		}
+		if self.ray_queries {
+			features |= ray_tracing::RayTracing::FEATURES;
+		}
+		if self.wireframe {
+			features |= wgpu::Features::POLYGON_MODE_LINE;
+		}
+		features
+	}
+
+	// meshlets cull in compute and draw indirectly
+	pub fn meshlets(&self) -> bool {
+		self.compute && self.indirect_draws && self.limits.max_storage_buffers_per_shader_stage >= 2
+	}
+}
@@ -0,0 +1,67 @@
+use cgmath::{InnerSpace, Transform};
+
+use crate::scene;
+
+pub const MAX_CAPSULES: usize = 16;
+
+// segment from a to b swept by a sphere, in the object's space
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Capsule {
+	pub a: [f32; 3],
+	pub b: [f32; 3],
+	pub radius: f32,
+}
+
+impl Capsule {
+	// in world space, xyz of each end with the radius in w
+	fn transformed(&self, transform: &cgmath::Matrix4<f32>) -> [[f32; 4]; 2] {
+		let a = transform.transform_point(self.a.into());
+		let b = transform.transform_point(self.b.into());
+		// the largest axis scale, so non uniformly scaled capsules cover the object
+		let scale = [transform.x, transform.y, transform.z].iter()
+			.map(|axis| axis.truncate().magnitude())
+			.fold(0.0, f32::max);
+		let radius = self.radius * scale;
+		[[a.x, a.y, a.z, radius], [b.x, b.y, b.z, radius]]
+	}
+}
+
+/*
+Analytic soft shadows from capsules approximating characters. Objects list their capsules and
+every lit surface tests its light ray against them in the lighting shader, which is far cheaper
+than rendering the character into shadow maps and works on every tier, so a character can turn
+off casts_shadows and keep a soft shadow on the web target.
+Capsules aren't attached to skin joints, they stay where they're put.
+*/
+gpu_struct! {
+	Uniform
+	#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+	pub struct CapsuleShadowUniform {
+		capsules: [[[f32; 4]; 2]; MAX_CAPSULES],
+		count: u32,
+		softness: f32, // smaller values give softer edges
+	}
+}
+
+impl CapsuleShadowUniform {
+	pub fn new() -> Self {
+		Self {
+			capsules: [[[0.0; 4]; 2]; MAX_CAPSULES],
+			count: 0,
+			softness: 4.0,
+			..bytemuck::Zeroable::zeroed()
+		}
+	}
+
+	// the first MAX_CAPSULES capsules of the scene's objects
+	pub fn from_scene(scene: &scene::Scene) -> Self {
+		let mut uniform = Self::new();
+		let capsules = scene.objects.iter()
+			.flat_map(|obj| obj.shadow_capsules.iter().map(|capsule| capsule.transformed(&obj.transform)));
+		for (i, capsule) in capsules.take(MAX_CAPSULES).enumerate() {
+			uniform.capsules[i] = capsule;
+			uniform.count = i as u32 + 1;
+		}
+		uniform
+	}
+}
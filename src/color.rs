@@ -0,0 +1,171 @@
+use wgpu::util::DeviceExt;
+
+/*
+Color spaces through the pipeline. The working space is linear sRGB (Rec. 709 primaries,
+linear transfer): color textures are sRGB formats decoded when sampled, normal maps are
+not, light colors go through srgb_to_linear, and every shader lights and blends linear
+values, into a floating point target when the adapter has one. The output stage of RenderScale
+tonemaps the frame and applies the output transfer when it's written to the surface.
+*/
+
+// the transfer function the frame is encoded with for the display
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputTransfer {
+	Srgb, // the piecewise sRGB curve, what most displays expect
+	Gamma22, // a pure 2.2 power, what many displays actually do
+	Linear, // no encoding, for float surfaces and captures
+}
+
+impl OutputTransfer {
+	// the value written to the uniform the output stage reads
+	pub fn index(self) -> u32 {
+		match self {
+			OutputTransfer::Srgb => 0,
+			OutputTransfer::Gamma22 => 1,
+			OutputTransfer::Linear => 2,
+		}
+	}
+}
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+	if c <= 0.04045 {
+		c / 12.92
+	} else {
+		((c + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+// calibrated images drawn in place of the scene, to check the output end to end
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TestPattern {
+	Ramps, // gray, red, green, and blue ramps, linear above and perceptual below
+	GrayChart, // sRGB gray steps, and a pixel checker of black and white beside 50% linear gray
+	Primaries, // saturated primaries and secondaries, white, and black
+}
+
+impl TestPattern {
+	fn index(self) -> u32 {
+		match self {
+			TestPattern::Ramps => 0,
+			TestPattern::GrayChart => 1,
+			TestPattern::Primaries => 2,
+		}
+	}
+}
+
+/*
+Draws a test pattern as linear working space values where the scene would be, so it goes
+through the same render scale, output transfer, and overlays as a frame of the scene.
+*/
+pub struct TestPatternPass {
+	pub pattern: Option<TestPattern>, // None draws the scene
+	uploaded: Option<TestPattern>,
+	buffer: wgpu::Buffer,
+	bind_group: wgpu::BindGroup,
+	pipeline: wgpu::RenderPipeline,
+}
+
+impl TestPatternPass {
+	pub fn new(device: &wgpu::Device, color_format: wgpu::TextureFormat) -> Self {
+		let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Test Pattern Buffer"),
+			contents: bytemuck::cast_slice(&[0u32; 4]),
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+		});
+		let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("test_pattern_bind_group_layout"),
+			entries: &[
+				wgpu::BindGroupLayoutEntry {
+					binding: 0,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+			],
+		});
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("test_pattern_bind_group"),
+			layout: &layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: buffer.as_entire_binding(),
+				},
+			],
+		});
+
+		let shader = device.create_shader_module(wgpu::include_wgsl!("test_pattern.wgsl"));
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Test Pattern Pipeline Layout"),
+			bind_group_layouts: &[Some(&layout)],
+			immediate_size: 0,
+		});
+		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("Test Pattern Pipeline"),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: Some("vs_main"),
+				buffers: &[],
+				compilation_options: Default::default(),
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: Some("fs_main"),
+				targets: &[Some(wgpu::ColorTargetState {
+					format: color_format,
+					blend: None,
+					write_mask: wgpu::ColorWrites::ALL,
+				})],
+				compilation_options: Default::default(),
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			multiview_mask: None,
+			cache: None,
+		});
+
+		Self {
+			pattern: None,
+			uploaded: None,
+			buffer,
+			bind_group,
+			pipeline,
+		}
+	}
+
+	pub fn draw(&mut self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+		let Some(pattern) = self.pattern else {
+			return;
+		};
+		if self.uploaded != Some(pattern) {
+			queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[pattern.index(), 0, 0, 0]));
+			self.uploaded = Some(pattern);
+		}
+
+		let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Test Pattern Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+					store: wgpu::StoreOp::Store,
+				},
+				depth_slice: None,
+			})],
+			depth_stencil_attachment: None,
+			occlusion_query_set: None,
+			timestamp_writes: None,
+			multiview_mask: None,
+		});
+		render_pass.set_pipeline(&self.pipeline);
+		render_pass.set_bind_group(0, &self.bind_group, &[]);
+		render_pass.draw(0..3, 0..1);
+	}
+}
@@ -0,0 +1,6 @@
+fn material_surface(in: VertexOutput) -> MaterialSurface {
+	let obj_col = vec4<f32>(material.diffuse_spec.xyz, 1.0);
+	let obj_norm = normalize(in.normal);
+
+	return MaterialSurface(obj_col, obj_norm);
+}
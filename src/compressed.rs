@@ -0,0 +1,209 @@
+use std::io::Read;
+
+use wgpu::util::DeviceExt;
+
+use crate::texture;
+
+/*
+KTX2 textures, uploaded the way they're stored, so block compressed textures stay compressed in
+video memory and skip decoding a PNG on load. BC textures need TEXTURE_COMPRESSION_BC and ETC2
+ones TEXTURE_COMPRESSION_ETC2, which the renderer asks for when the adapter has them. Without the
+feature, BC1 to BC5 are decoded to RGBA8 here. The file's format decides the color space rather
+than the texture type, and normal maps are best stored as BC5, the shaders rebuild z from x and y.
+Zstandard supercompression is undone on load.
+BC7 and ETC2 need the feature, and Basis Universal and DDS files aren't read.
+*/
+
+const MAGIC: [u8; 12] = [0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, b'\r', b'\n', 0x1a, b'\n'];
+
+pub fn is_ktx2(bytes: &[u8]) -> bool {
+	bytes.starts_with(&MAGIC)
+}
+
+fn wgpu_format(format: ktx2::Format) -> Option<wgpu::TextureFormat> {
+	use wgpu::TextureFormat::*;
+	Some(match format {
+		ktx2::Format::R8G8B8A8_UNORM => Rgba8Unorm,
+		ktx2::Format::R8G8B8A8_SRGB => Rgba8UnormSrgb,
+		ktx2::Format::BC1_RGB_UNORM_BLOCK | ktx2::Format::BC1_RGBA_UNORM_BLOCK => Bc1RgbaUnorm,
+		ktx2::Format::BC1_RGB_SRGB_BLOCK | ktx2::Format::BC1_RGBA_SRGB_BLOCK => Bc1RgbaUnormSrgb,
+		ktx2::Format::BC3_UNORM_BLOCK => Bc3RgbaUnorm,
+		ktx2::Format::BC3_SRGB_BLOCK => Bc3RgbaUnormSrgb,
+		ktx2::Format::BC4_UNORM_BLOCK => Bc4RUnorm,
+		ktx2::Format::BC5_UNORM_BLOCK => Bc5RgUnorm,
+		ktx2::Format::BC7_UNORM_BLOCK => Bc7RgbaUnorm,
+		ktx2::Format::BC7_SRGB_BLOCK => Bc7RgbaUnormSrgb,
+		ktx2::Format::ETC2_R8G8B8_UNORM_BLOCK => Etc2Rgb8Unorm,
+		ktx2::Format::ETC2_R8G8B8_SRGB_BLOCK => Etc2Rgb8UnormSrgb,
+		ktx2::Format::ETC2_R8G8B8A1_UNORM_BLOCK => Etc2Rgb8A1Unorm,
+		ktx2::Format::ETC2_R8G8B8A1_SRGB_BLOCK => Etc2Rgb8A1UnormSrgb,
+		ktx2::Format::ETC2_R8G8B8A8_UNORM_BLOCK => Etc2Rgba8Unorm,
+		ktx2::Format::ETC2_R8G8B8A8_SRGB_BLOCK => Etc2Rgba8UnormSrgb,
+		_ => return None,
+	})
+}
+
+pub fn load_ktx2(device: &wgpu::Device, queue: &wgpu::Queue, bytes: &[u8], label: &str, ty: texture::TextureType) -> anyhow::Result<texture::Texture> {
+	let reader = ktx2::Reader::new(bytes)?;
+	let header = reader.header();
+	let Some(mut format) = header.format.and_then(wgpu_format) else {
+		anyhow::bail!("{} is stored as {:?}, which can't be loaded", label, header.format);
+	};
+	if header.pixel_depth > 1 {
+		anyhow::bail!("{} is a 3D texture, which can't be loaded", label);
+	}
+	let cubemap = header.face_count == 6;
+	if ty == texture::TextureType::Cubemap && !cubemap {
+		anyhow::bail!("{} isn't a cubemap", label);
+	}
+
+	// every level's faces and layers, one level after another
+	let mut data = vec![];
+	for level in reader.levels() {
+		match header.supercompression_scheme {
+			None => data.extend_from_slice(level.data),
+			Some(ktx2::SupercompressionScheme::Zstandard) => {
+				ruzstd::decoding::StreamingDecoder::new(level.data)?.read_to_end(&mut data)?;
+			}
+			Some(scheme) => anyhow::bail!("{} is supercompressed with {:?}, which can't be loaded", label, scheme),
+		}
+	}
+
+	let size = wgpu::Extent3d {
+		width: header.pixel_width,
+		height: header.pixel_height.max(1),
+		depth_or_array_layers: header.layer_count.max(1) * header.face_count,
+	};
+	let mip_level_count = header.level_count.max(1);
+	if !device.features().contains(format.required_features()) {
+		data = decode(format, &data, size, mip_level_count)
+			.ok_or_else(|| anyhow::anyhow!("{} is {:?}, which the adapter can't sample and can't be decoded", label, format))?;
+		format = if format.is_srgb() { wgpu::TextureFormat::Rgba8UnormSrgb } else { wgpu::TextureFormat::Rgba8Unorm };
+	}
+
+	let texture = device.create_texture_with_data(queue, &wgpu::TextureDescriptor {
+		label: Some(label),
+		size,
+		mip_level_count,
+		sample_count: 1,
+		dimension: wgpu::TextureDimension::D2,
+		format,
+		usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+		view_formats: &[],
+	}, wgpu::util::TextureDataOrder::MipMajor, &data);
+	let view = texture.create_view(&wgpu::TextureViewDescriptor {
+		label: Some("Texture View"),
+		dimension: Some(if cubemap { wgpu::TextureViewDimension::Cube } else { wgpu::TextureViewDimension::D2 }),
+		..Default::default()
+	});
+	// material textures repeat so they can be tiled
+	let address_mode = if cubemap { wgpu::AddressMode::ClampToEdge } else { wgpu::AddressMode::Repeat };
+	let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+		address_mode_u: address_mode,
+		address_mode_v: address_mode,
+		address_mode_w: address_mode,
+		mag_filter: wgpu::FilterMode::Linear,
+		min_filter: wgpu::FilterMode::Linear,
+		mipmap_filter: wgpu::MipmapFilterMode::Linear,
+		..Default::default()
+	});
+
+	Ok(texture::Texture {
+		texture,
+		view,
+		sampler,
+	})
+}
+
+// decodes BC1 to BC5 blocks to RGBA8, None for formats without a decoder
+fn decode(format: wgpu::TextureFormat, data: &[u8], size: wgpu::Extent3d, mip_level_count: u32) -> Option<Vec<u8>> {
+	use wgpu::TextureFormat::*;
+	let block_size = match format {
+		Bc1RgbaUnorm | Bc1RgbaUnormSrgb | Bc4RUnorm => 8,
+		Bc3RgbaUnorm | Bc3RgbaUnormSrgb | Bc5RgUnorm => 16,
+		_ => return None,
+	};
+
+	let mut blocks = data.chunks_exact(block_size);
+	let mut rgba = vec![];
+	for level in 0..mip_level_count {
+		let width = (size.width >> level).max(1) as usize;
+		let height = (size.height >> level).max(1) as usize;
+		for _ in 0..size.depth_or_array_layers {
+			let mut image = vec![0; width * height * 4];
+			for block_y in 0..height.div_ceil(4) {
+				for block_x in 0..width.div_ceil(4) {
+					let block = blocks.next()?;
+					let texels = match format {
+						Bc1RgbaUnorm | Bc1RgbaUnormSrgb => color_block(block, true),
+						Bc3RgbaUnorm | Bc3RgbaUnormSrgb => {
+							let alpha = channel_block(&block[..8]);
+							let mut texels = color_block(&block[8..], false);
+							for (texel, alpha) in texels.iter_mut().zip(alpha) {
+								texel[3] = alpha;
+							}
+							texels
+						}
+						Bc4RUnorm => channel_block(block).map(|r| [r, 0, 0, 255]),
+						_ => {
+							let (red, green) = (channel_block(&block[..8]), channel_block(&block[8..]));
+							std::array::from_fn(|i| [red[i], green[i], 0, 255])
+						}
+					};
+					// blocks hang over the edges of images that aren't a multiple of 4
+					for (i, texel) in texels.iter().enumerate() {
+						let (x, y) = (block_x * 4 + i % 4, block_y * 4 + i / 4);
+						if x < width && y < height {
+							image[(y * width + x) * 4..][..4].copy_from_slice(texel);
+						}
+					}
+				}
+			}
+			rgba.extend(image);
+		}
+	}
+	Some(rgba)
+}
+
+// the 4x4 texels of a BC1 color block, with_alpha allows its transparent black
+fn color_block(block: &[u8], with_alpha: bool) -> [[u8; 4]; 16] {
+	let endpoint = |bytes: [u8; 2]| {
+		let color = u16::from_le_bytes(bytes) as u32;
+		let expand = |value: u32, bits: u32| (value * 255 / ((1 << bits) - 1)) as u8;
+		[expand(color >> 11, 5), expand((color >> 5) & 63, 6), expand(color & 31, 5), 255]
+	};
+	let (raw0, raw1) = (u16::from_le_bytes([block[0], block[1]]), u16::from_le_bytes([block[2], block[3]]));
+	let (c0, c1) = (endpoint([block[0], block[1]]), endpoint([block[2], block[3]]));
+	let mix = |a: u32, b: u32, divisor: u32| std::array::from_fn::<u8, 4, _>(|i| ((c0[i] as u32 * a + c1[i] as u32 * b) / divisor) as u8);
+	let palette = if raw0 > raw1 || !with_alpha {
+		[c0, c1, mix(2, 1, 3), mix(1, 2, 3)]
+	} else {
+		[c0, c1, mix(1, 1, 2), [0, 0, 0, 0]]
+	};
+	let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+	std::array::from_fn(|i| palette[(indices >> (i * 2)) as usize & 3])
+}
+
+// the 4x4 values of a BC4 block, which BC3 alpha and each BC5 channel are stored as
+fn channel_block(block: &[u8]) -> [u8; 16] {
+	let (a, b) = (block[0] as u32, block[1] as u32);
+	let palette: [u8; 8] = if a > b {
+		std::array::from_fn(|i| match i {
+			0 => a as u8,
+			1 => b as u8,
+			_ => ((a * (8 - i as u32) + b * (i as u32 - 1)) / 7) as u8,
+		})
+	} else {
+		std::array::from_fn(|i| match i {
+			0 => a as u8,
+			1 => b as u8,
+			6 => 0,
+			7 => 255,
+			_ => ((a * (6 - i as u32) + b * (i as u32 - 1)) / 5) as u8,
+		})
+	};
+	let mut bits = [0; 8];
+	bits[..6].copy_from_slice(&block[2..8]);
+	let indices = u64::from_le_bytes(bits);
+	std::array::from_fn(|i| palette[(indices >> (i * 3)) as usize & 7])
+}
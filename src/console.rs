@@ -0,0 +1,820 @@
+use std::collections::BTreeMap;
+
+use winit::{event::KeyEvent, keyboard::{KeyCode, PhysicalKey}};
+
+use crate::{camera, color, debug_draw, environment, light, model, modifier, post, renderer, resources, scene, scene_manager, sequencer, spline, view_mode, particles};
+
+// what commands can change
+pub struct Context<'a> {
+	pub renderer: &'a mut renderer::Renderer,
+	pub scene: &'a mut scene::Scene,
+	pub scenes: &'a mut scene_manager::SceneManager,
+	pub sequencer: &'a mut Option<sequencer::Sequencer>,
+}
+
+type CommandFn = Box<dyn Fn(&mut Context, &[&str]) -> anyhow::Result<String>>;
+
+struct Command {
+	usage: &'static str,
+	run: CommandFn,
+}
+
+/*
+In-app console toggled with ~. A line is split on whitespace, the first word picks the
+registered command and the rest are its arguments. Output is kept here and logged. The input
+line is shown in the window title, and with the text feature the last lines of output are drawn
+over the bottom of the window with the input line under them.
+*/
+pub struct Console {
+	pub open: bool,
+	input: String,
+	history: Vec<String>,
+	history_index: usize,
+	output: Vec<String>,
+	commands: BTreeMap<&'static str, Command>,
+}
+
+impl Console {
+	const MAX_OUTPUT: usize = 256;
+
+	pub fn new() -> Self {
+		Self {
+			open: false,
+			input: String::new(),
+			history: vec![],
+			history_index: 0,
+			output: vec![],
+			commands: BTreeMap::new(),
+		}
+	}
+
+	pub fn register(
+		&mut self,
+		name: &'static str,
+		usage: &'static str,
+		run: impl Fn(&mut Context, &[&str]) -> anyhow::Result<String> + 'static,
+	) {
+		self.commands.insert(name, Command { usage, run: Box::new(run) });
+	}
+
+	pub fn toggle(&mut self) {
+		self.open = !self.open;
+		self.input.clear();
+	}
+
+	pub fn input(&self) -> &str {
+		&self.input
+	}
+
+	// the last lines of output, then the input line with a cursor
+	#[cfg(feature = "text")]
+	pub fn text(&self, lines: usize) -> String {
+		let start = self.output.len().saturating_sub(lines);
+		self.output[start..].iter()
+			.cloned()
+			.chain([format!("> {}_", self.input)])
+			.collect::<Vec<_>>()
+			.join("\n")
+	}
+
+	// edits the input line, returns a line when it's submitted
+	pub fn handle_key(&mut self, event: &KeyEvent) -> Option<String> {
+		if !event.state.is_pressed() {
+			return None;
+		}
+		match event.physical_key {
+			PhysicalKey::Code(KeyCode::Backquote) => self.toggle(),
+			PhysicalKey::Code(KeyCode::Escape) => self.toggle(),
+			PhysicalKey::Code(KeyCode::Enter | KeyCode::NumpadEnter) => return Some(self.submit()),
+			PhysicalKey::Code(KeyCode::Backspace) => {
+				self.input.pop();
+			}
+			PhysicalKey::Code(KeyCode::ArrowUp) => self.recall(-1),
+			PhysicalKey::Code(KeyCode::ArrowDown) => self.recall(1),
+			_ => {
+				if let Some(text) = &event.text {
+					self.input.extend(text.chars().filter(|c| !c.is_control()));
+				}
+			}
+		}
+		None
+	}
+
+	// takes the input line, keeping it in the history
+	fn submit(&mut self) -> String {
+		let line = std::mem::take(&mut self.input);
+		if !line.trim().is_empty() {
+			self.history.push(line.clone());
+		}
+		self.history_index = self.history.len();
+		line
+	}
+
+	fn recall(&mut self, step: isize) {
+		let index = self.history_index as isize + step;
+		if index < 0 || index as usize > self.history.len() {
+			return;
+		}
+		self.history_index = index as usize;
+		self.input = self.history.get(self.history_index).cloned().unwrap_or_default();
+	}
+
+	pub fn execute(&mut self, context: &mut Context, line: &str) {
+		self.print(format!("> {}", line));
+		let Some(result) = self.dispatch(line, |command, args| (command.run)(context, args)) else {
+			return;
+		};
+
+		match result {
+			Ok(text) => {
+				for line in text.lines() {
+					self.print(line.to_string());
+				}
+			}
+			Err(e) => self.print(format!("error: {}", e)),
+		}
+	}
+
+	// splits the line into a command and its arguments and runs it, None for a blank line
+	fn dispatch(&self, line: &str, run: impl FnOnce(&Command, &[&str]) -> anyhow::Result<String>) -> Option<anyhow::Result<String>> {
+		let words = line.split_whitespace().collect::<Vec<_>>();
+		let (&name, args) = words.split_first()?;
+
+		Some(if name == "help" {
+			Ok(self.commands.iter()
+				.map(|(name, command)| format!("{} {}", name, command.usage))
+				.collect::<Vec<_>>()
+				.join("\n"))
+		} else {
+			match self.commands.get(name) {
+				Some(command) => run(command, args),
+				None => Err(anyhow::anyhow!("unknown command {}, try help", name)),
+			}
+		})
+	}
+
+	fn print(&mut self, line: String) {
+		log::info!("{}", line);
+		self.output.push(line);
+		if self.output.len() > Self::MAX_OUTPUT {
+			self.output.remove(0);
+		}
+	}
+
+	// load, scene, light_color, light_add, exposure, and stats
+	pub fn register_default_commands(&mut self) {
+		self.register("load", "<file.obj> [subdivide:levels smooth:iterations:factor noise:amplitude:frequency:seed mirror:x array:count:dx:dy:dz simplify:ratio unwrap ...]", |context, args| {
+			let [filename, modifiers @ ..] = args else {
+				anyhow::bail!("expected a file name");
+			};
+			let modifiers = modifier::ModifierStack::parse(modifiers.iter().copied())?;
+			let load = resources::load_modified_model(filename, &modifiers, context.renderer, context.scene);
+			#[cfg(not(target_arch = "wasm32"))]
+			let model_index = pollster::block_on(load)?;
+			// the web build can't wait on a fetch here, so only dropped files can be loaded
+			#[cfg(target_arch = "wasm32")]
+			let model_index = resources::load_from_memory(load)?;
+
+			context.scene.add_object(model::ModelInstance::new(model_index, cgmath::Matrix4::from_scale(1.0)));
+			Ok(format!("loaded {} as model {}", filename, model_index))
+		});
+
+		// a scene file, or a scene of one model
+		self.register("scene", "<file.ron|file.obj>", |context, args| {
+			let [filename] = args else {
+				anyhow::bail!("expected a file name");
+			};
+			let aspect = context.scene.camera.aspect;
+			let request = if filename.ends_with(".ron") {
+				scene_manager::file_scene(filename, aspect)
+			} else {
+				scene_manager::model_scene(filename, aspect)
+			};
+			context.scenes.request(request);
+			Ok(format!("loading {}", filename))
+		});
+
+		self.register("scene_save", "<file.ron>", |context, args| {
+			let [filename] = args else {
+				anyhow::bail!("expected a file name");
+			};
+			context.scene.save(filename)?;
+			Ok(format!("saved {} objects to {}", context.scene.objects.len(), filename))
+		});
+
+		// plays a sequence file on the current scene, in scene time
+		self.register("sequence", "<file.toml> [loop] | stop", |context, args| {
+			let (filename, looping) = match args {
+				["stop"] => {
+					if let Some(sequencer) = context.sequencer.take() {
+						sequencer.stop(&mut context.renderer.fade);
+					}
+					return Ok(String::new());
+				}
+				[filename] => (filename, false),
+				[filename, "loop"] => (filename, true),
+				_ => anyhow::bail!("expected a sequence file"),
+			};
+			#[cfg(not(target_arch = "wasm32"))]
+			let mut sequencer = pollster::block_on(sequencer::Sequencer::load(filename))?;
+			#[cfg(target_arch = "wasm32")]
+			let mut sequencer = resources::load_from_memory(sequencer::Sequencer::load(filename))?;
+			sequencer.looping = looping;
+			let duration = sequencer.sequence.duration();
+			*context.sequencer = Some(sequencer);
+			Ok(format!("playing {} ({:.1} s)", filename, duration))
+		});
+
+		self.register("light_color", "<r> <g> <b> [light index]", |context, args| {
+			let (color, index) = match args {
+				[r, g, b] => ([r, g, b], "0"),
+				[r, g, b, index] => ([r, g, b], *index),
+				_ => anyhow::bail!("expected a color"),
+			};
+			let color = [color[0].parse()?, color[1].parse()?, color[2].parse()?];
+			let handle = context.scene.light_handle(index.parse()?)
+				.ok_or_else(|| anyhow::anyhow!("no light {}", index))?;
+			context.scene.set_light_color(handle, color);
+			Ok(String::new())
+		});
+
+		self.register("light_add", "<point x y z | directional dx dy dz | spot x y z dx dy dz angle>", |context, args| {
+			let parse = |values: &[&str]| -> anyhow::Result<[f32; 3]> {
+				Ok([values[0].parse()?, values[1].parse()?, values[2].parse()?])
+			};
+			let light = match args {
+				["point", position @ ..] if position.len() == 3 => light::Light {
+					position: parse(position)?,
+					..light::Light::new()
+				},
+				["directional", direction @ ..] if direction.len() == 3 => light::Light::directional(parse(direction)?, 2.0),
+				["spot", values @ .., angle] if values.len() == 6 => {
+					let outer_angle = angle.parse::<f32>()?.to_radians() * 0.5;
+					light::Light::spot(parse(&values[..3])?, parse(&values[3..])?, outer_angle * 0.8, outer_angle)
+				}
+				_ => anyhow::bail!("expected a light type and its position or direction"),
+			};
+			context.scene.add_light(light);
+			Ok(format!("light {}", context.scene.lights().len() - 1))
+		});
+
+		// a folder of six face images, or an equirectangular panorama
+		self.register("skybox", "<folder|file.hdr|none>", |context, args| {
+			let [path] = args else {
+				anyhow::bail!("expected a folder or a file");
+			};
+			if *path == "none" {
+				context.scene.set_skybox(None);
+				return Ok(String::new());
+			}
+			let renderer = &context.renderer;
+			let load = async {
+				match path.rsplit_once('.') {
+					Some(_) => resources::load_equirect_cubemap(path, None, &renderer.device, &renderer.queue).await,
+					None => resources::load_cubemap_texture(path, &renderer.device, &renderer.queue).await,
+				}
+			};
+			#[cfg(not(target_arch = "wasm32"))]
+			let skybox = pollster::block_on(load)?;
+			#[cfg(target_arch = "wasm32")]
+			let skybox = resources::load_from_memory(load)?;
+			context.scene.set_skybox(Some(skybox));
+			Ok(String::new())
+		});
+
+		self.register("exposure", "<multiplier>", |context, args| {
+			let [exposure] = args else {
+				anyhow::bail!("expected an exposure");
+			};
+			context.scene.environment.exposure = exposure.parse()?;
+			Ok(String::new())
+		});
+
+		self.register("weather", "<wetness> <snow>", |context, args| {
+			let [wetness, snow] = args else {
+				anyhow::bail!("expected a wetness and a snow amount, from 0 to 1");
+			};
+			context.scene.environment.weather = environment::Weather {
+				wetness: wetness.parse()?,
+				snow: snow.parse()?,
+			};
+			Ok(String::new())
+		});
+
+		self.register("wind", "<strength> [dx dz]", |context, args| {
+			let wind = &mut context.scene.environment.wind;
+			match args {
+				[strength] => wind.strength = strength.parse()?,
+				[strength, x, z] => {
+					wind.strength = strength.parse()?;
+					wind.direction = [x.parse()?, z.parse()?];
+				}
+				_ => anyhow::bail!("expected a strength and optionally a direction"),
+			}
+			Ok(String::new())
+		});
+
+		// with no argument, lists the scene's presets
+		self.register("lighting", "[preset]", |context, args| {
+			match args {
+				[] => Ok(context.scene.lighting_presets.iter().map(|preset| preset.name.as_str()).collect::<Vec<_>>().join(" ")),
+				[name] => {
+					context.scene.apply_lighting_preset(name)?;
+					Ok(String::new())
+				}
+				_ => anyhow::bail!("expected a preset name"),
+			}
+		});
+
+		self.register("lighting_save", "<preset>", |context, args| {
+			let [name] = args else {
+				anyhow::bail!("expected a preset name");
+			};
+			context.scene.save_lighting_preset(name);
+			Ok(String::new())
+		});
+
+		self.register("tonemap", "<none|reinhard|aces>", |context, args| {
+			let [tonemap] = args else {
+				anyhow::bail!("expected a tonemap");
+			};
+			context.scene.environment.tonemap = match *tonemap {
+				"none" => environment::Tonemap::None,
+				"reinhard" => environment::Tonemap::Reinhard,
+				"aces" => environment::Tonemap::Aces,
+				_ => anyhow::bail!("unknown tonemap {}", tonemap),
+			};
+			Ok(String::new())
+		});
+
+		// the radius and strength are the screen space methods'
+		self.register("ao", "<off|gtao|ssao|rt> [radius] [strength]", |context, args| {
+			let [method, values @ ..] = args else {
+				anyhow::bail!("expected an AO method");
+			};
+			let params = &mut context.renderer.gtao.params;
+			match values {
+				[] => {}
+				[radius] => params.radius = radius.parse()?,
+				[radius, strength] => {
+					params.radius = radius.parse()?;
+					params.strength = strength.parse()?;
+				}
+				_ => anyhow::bail!("expected a radius and a strength"),
+			}
+			context.renderer.post.ao = match *method {
+				"off" => post::AoMethod::Off,
+				"gtao" => post::AoMethod::Gtao,
+				"ssao" => post::AoMethod::Ssao,
+				"rt" if context.renderer.ray_tracing.is_some() => post::AoMethod::RayTraced,
+				"rt" => anyhow::bail!("ray queries aren't supported"),
+				_ => anyhow::bail!("unknown AO method {}", method),
+			};
+			Ok(String::new())
+		});
+
+		self.register("view_mode", "<lit|wireframe|normals|depth>", |context, args| {
+			let [mode] = args else {
+				anyhow::bail!("expected a view mode");
+			};
+			context.renderer.view_mode = match *mode {
+				"lit" => view_mode::ViewMode::Lit,
+				"wireframe" if context.renderer.features.wireframe => view_mode::ViewMode::Wireframe,
+				"wireframe" => anyhow::bail!("line polygon mode isn't supported"),
+				"normals" => view_mode::ViewMode::Normals,
+				"depth" => view_mode::ViewMode::Depth,
+				_ => anyhow::bail!("unknown view mode {}", mode),
+			};
+			Ok(String::new())
+		});
+
+		self.register("interlace", "<on|off>", |context, args| {
+			let [mode] = args else {
+				anyhow::bail!("expected on or off");
+			};
+			context.renderer.post.interlaced = match *mode {
+				"on" => true,
+				"off" => false,
+				_ => anyhow::bail!("expected on or off"),
+			};
+			context.renderer.interlace.reset();
+			Ok(String::new())
+		});
+
+		self.register("render_scale", "<fraction of the window resolution>", |context, args| {
+			let [scale] = args else {
+				anyhow::bail!("expected a scale");
+			};
+			context.renderer.set_render_scale(scale.parse()?);
+			Ok(format!("rendering at {}", context.renderer.render_scale()))
+		});
+
+		self.register("output_transfer", "<srgb|gamma22|linear>", |context, args| {
+			let [transfer] = args else {
+				anyhow::bail!("expected a transfer function");
+			};
+			context.renderer.set_output_transfer(match *transfer {
+				"srgb" => color::OutputTransfer::Srgb,
+				"gamma22" => color::OutputTransfer::Gamma22,
+				"linear" => color::OutputTransfer::Linear,
+				_ => anyhow::bail!("unknown transfer function {}", transfer),
+			});
+			Ok(String::new())
+		});
+
+		self.register("test_pattern", "<off|ramps|gray|primaries>", |context, args| {
+			let [pattern] = args else {
+				anyhow::bail!("expected a pattern");
+			};
+			context.renderer.test_pattern.pattern = match *pattern {
+				"off" => None,
+				"ramps" => Some(color::TestPattern::Ramps),
+				"gray" => Some(color::TestPattern::GrayChart),
+				"primaries" => Some(color::TestPattern::Primaries),
+				_ => anyhow::bail!("unknown pattern {}", pattern),
+			};
+			Ok(String::new())
+		});
+
+		self.register("shadow_faces", "<cube faces rendered per frame>", |context, args| {
+			let [faces] = args else {
+				anyhow::bail!("expected a face count");
+			};
+			let Some(point_shadows) = &mut context.renderer.point_shadows else {
+				anyhow::bail!("point light shadows aren't supported by this adapter");
+			};
+			point_shadows.scheduler.faces_per_frame = faces.parse()?;
+			Ok(String::new())
+		});
+
+		self.register("virtual_camera", "<eye x> <eye y> <eye z> <target x> <target y> <target z>", |context, args| {
+			let [eye_x, eye_y, eye_z, target_x, target_y, target_z] = args else {
+				anyhow::bail!("expected an eye and a target position");
+			};
+			let mut camera = camera::Camera::new(1.0);
+			camera.eye = cgmath::Point3::new(eye_x.parse()?, eye_y.parse()?, eye_z.parse()?);
+			camera.target = cgmath::Point3::new(target_x.parse()?, target_y.parse()?, target_z.parse()?);
+			let Some(handle) = context.renderer.virtual_cameras.add(camera) else {
+				anyhow::bail!("every virtual camera cell is taken");
+			};
+			Ok(format!("virtual camera at {:?} of the atlas", context.renderer.virtual_cameras.uv_rect(handle)))
+		});
+
+		self.register("virtual_camera_remove", "<cell>", |context, args| {
+			let [cell] = args else {
+				anyhow::bail!("expected a cell");
+			};
+			let Some(handle) = context.renderer.virtual_cameras.handle(cell.parse()?) else {
+				anyhow::bail!("no virtual camera in cell {}", cell);
+			};
+			context.renderer.virtual_cameras.remove(handle);
+			Ok(String::new())
+		});
+
+		// the view's own exposure, or the scene's with none
+		self.register("virtual_camera_exposure", "<cell> [multiplier]", |context, args| {
+			let (cell, exposure) = match args {
+				[cell] => (cell, None),
+				[cell, exposure] => (cell, Some(exposure.parse()?)),
+				_ => anyhow::bail!("expected a cell and an optional exposure"),
+			};
+			let Some(handle) = context.renderer.virtual_cameras.handle(cell.parse()?) else {
+				anyhow::bail!("no virtual camera in cell {}", cell);
+			};
+			if let Some(camera) = context.renderer.virtual_cameras.camera_mut(handle) {
+				camera.settings.exposure = exposure;
+			}
+			Ok(String::new())
+		});
+
+		self.register("tint", "<object> <r> <g> <b> [a]", |context, args| {
+			let (object, tint) = match args {
+				[object, r, g, b] => (object, [r.parse()?, g.parse()?, b.parse()?, 1.0]),
+				[object, r, g, b, a] => (object, [r.parse()?, g.parse()?, b.parse()?, a.parse()?]),
+				_ => anyhow::bail!("expected an object and a color"),
+			};
+			let Some(obj) = context.scene.objects.get_mut(object.parse::<usize>()?) else {
+				anyhow::bail!("no object {}", object);
+			};
+			obj.overrides.tint = tint;
+			Ok(String::new())
+		});
+
+		self.register("emissive", "<object> <strength>", |context, args| {
+			let [object, strength] = args else {
+				anyhow::bail!("expected an object and a strength");
+			};
+			let Some(obj) = context.scene.objects.get_mut(object.parse::<usize>()?) else {
+				anyhow::bail!("no object {}", object);
+			};
+			obj.overrides.emissive = strength.parse()?;
+			Ok(String::new())
+		});
+
+		// blends the object's opaque materials too, so a tint's alpha fades it
+		self.register("transparent", "<object> <on|off>", |context, args| {
+			let [object, mode] = args else {
+				anyhow::bail!("expected an object and on or off");
+			};
+			let Some(obj) = context.scene.objects.get_mut(object.parse::<usize>()?) else {
+				anyhow::bail!("no object {}", object);
+			};
+			obj.transparent = match *mode {
+				"on" => true,
+				"off" => false,
+				_ => anyhow::bail!("expected on or off"),
+			};
+			Ok(String::new())
+		});
+
+		// a node in the scene graph, with an object of the model unless it's a group
+		self.register("node", "<parent|root> <model|group> <x> <y> <z>", |context, args| {
+			let [parent, model, x, y, z] = args else {
+				anyhow::bail!("expected a parent, a model, and a position");
+			};
+			let parent = match *parent {
+				"root" => None,
+				parent => Some(context.scene.node_handle(parent.parse()?).ok_or_else(|| anyhow::anyhow!("no node {}", parent))?),
+			};
+			let model_index = match *model {
+				"group" => None,
+				model => Some(model.parse::<usize>()?).filter(|&index| index < context.scene.models.len()),
+			};
+			if model_index.is_none() && *model != "group" {
+				anyhow::bail!("no model {}", model);
+			}
+			let transform = cgmath::Matrix4::from_translation(cgmath::Vector3::new(x.parse()?, y.parse()?, z.parse()?));
+			context.scene.add_node(parent, transform, model_index);
+			Ok(format!("node {}", context.scene.node_count() - 1))
+		});
+
+		// moves a node relative to its parent, its children follow
+		self.register("node_move", "<node> <x> <y> <z>", |context, args| {
+			let [node, x, y, z] = args else {
+				anyhow::bail!("expected a node and a position");
+			};
+			let Some(node) = context.scene.node_handle(node.parse()?) else {
+				anyhow::bail!("no node {}", node);
+			};
+			context.scene.set_local_transform(node, cgmath::Matrix4::from_translation(cgmath::Vector3::new(x.parse()?, y.parse()?, z.parse()?)));
+			Ok(String::new())
+		});
+
+		// sparks from a point, or edits an emitter's rate, a rate of 0 stops it
+		self.register("emitter", "<x> <y> <z> | <emitter> <rate>", |context, args| {
+			match args {
+				[x, y, z] => {
+					let emitter = context.scene.add_emitter(particles::ParticleEmitter::new([x.parse()?, y.parse()?, z.parse()?]));
+					Ok(format!("emitter {}", emitter))
+				}
+				[emitter, rate] => {
+					let Some(emitter) = context.scene.emitters.get_mut(emitter.parse::<usize>()?) else {
+						anyhow::bail!("no emitter {}", emitter);
+					};
+					emitter.rate = rate.parse()?;
+					Ok(String::new())
+				}
+				_ => anyhow::bail!("expected a position, or an emitter and a rate"),
+			}
+		});
+
+		// how an emitter's particles are shaded and how far they fade into the scene, 0 for hard edges
+		self.register("emitter_shading", "<emitter> <emissive|lit> [softness]", |context, args| {
+			let [emitter, lighting, rest @ ..] = args else {
+				anyhow::bail!("expected an emitter and a lighting mode");
+			};
+			let Some(emitter) = context.scene.emitters.get_mut(emitter.parse::<usize>()?) else {
+				anyhow::bail!("no emitter {}", emitter);
+			};
+			emitter.lighting = match *lighting {
+				"emissive" => particles::ParticleLighting::Emissive,
+				"lit" => particles::ParticleLighting::Lit,
+				_ => anyhow::bail!("unknown lighting {}", lighting),
+			};
+			if let [softness] = rest {
+				emitter.softness = softness.parse()?;
+			}
+			Ok(String::new())
+		});
+
+		// a road or path through the points, with the scene's first material
+		self.register("ribbon", "<width> <x y z> <x y z> [x y z ...]", |context, args| {
+			let [width, points @ ..] = args else {
+				anyhow::bail!("expected a width and points");
+			};
+			if points.len() < 6 || points.len() % 3 != 0 {
+				anyhow::bail!("expected at least two points");
+			}
+			if context.scene.materials.is_empty() {
+				anyhow::bail!("the scene has no materials");
+			}
+			let points = points.chunks_exact(3)
+				.map(|point| Ok([point[0].parse()?, point[1].parse()?, point[2].parse()?]))
+				.collect::<anyhow::Result<Vec<_>>>()?;
+			let ribbon = spline::Ribbon::new(spline::Spline::new(points), width.parse()?);
+			let model_index = spline::add_ribbon(context.renderer, context.scene, ribbon, 0);
+			context.scene.add_object(model::ModelInstance::new(model_index, cgmath::Matrix4::from_scale(1.0)));
+			Ok(format!("ribbon {} as model {}", context.scene.ribbons.len() - 1, model_index))
+		});
+
+		// moves a control point, or adds one after the last, the ribbon is rebuilt next update
+		self.register("ribbon_point", "<ribbon> <point> <x> <y> <z>", |context, args| {
+			let [ribbon, point, x, y, z] = args else {
+				anyhow::bail!("expected a ribbon, a point, and a position");
+			};
+			let Some(ribbon) = context.scene.ribbons.get_mut(ribbon.parse::<usize>()?) else {
+				anyhow::bail!("no ribbon {}", ribbon);
+			};
+			let points = &mut ribbon.ribbon.spline.points;
+			let position = [x.parse()?, y.parse()?, z.parse()?];
+			match point.parse::<usize>()? {
+				index if index < points.len() => points[index] = position,
+				index if index == points.len() => points.push(position),
+				index => anyhow::bail!("no point {}", index),
+			}
+			Ok(String::new())
+		});
+
+		self.register("gizmo_depth", "<overlay|tested|xray>", |context, args| {
+			let [mode] = args else {
+				anyhow::bail!("expected a depth mode");
+			};
+			context.renderer.debug_draw.depth = match *mode {
+				"overlay" => debug_draw::GizmoDepth::Overlay,
+				"tested" => debug_draw::GizmoDepth::Tested,
+				"xray" => debug_draw::GizmoDepth::XRay,
+				_ => anyhow::bail!("unknown depth mode {}", mode),
+			};
+			Ok(String::new())
+		});
+
+		self.register("debug", "<lights|bounds|axes> <on|off>", |context, args| {
+			let [helper, mode] = args else {
+				anyhow::bail!("expected a helper and on or off");
+			};
+			let debug_draw = &mut context.renderer.debug_draw;
+			let show = match *helper {
+				"lights" => &mut debug_draw.show_lights,
+				"bounds" => &mut debug_draw.show_bounds,
+				"axes" => &mut debug_draw.show_axes,
+				_ => anyhow::bail!("unknown helper {}", helper),
+			};
+			*show = match *mode {
+				"on" => true,
+				"off" => false,
+				_ => anyhow::bail!("expected on or off"),
+			};
+			Ok(String::new())
+		});
+
+		self.register("navmesh", "<on|off>", |context, args| {
+			let [mode] = args else {
+				anyhow::bail!("expected on or off");
+			};
+			let Some(navmesh) = &mut context.scene.navmesh else {
+				anyhow::bail!("the scene has no navmesh");
+			};
+			navmesh.visible = match *mode {
+				"on" => true,
+				"off" => false,
+				_ => anyhow::bail!("expected on or off"),
+			};
+			Ok(String::new())
+		});
+
+		// the passes of the next frame, listed or written out as Graphviz or JSON
+		self.register("frame_graph", "[file.dot|file.json]", |context, args| {
+			let graph = context.renderer.frame_graph(&context.scene.camera, context.scene);
+			match args {
+				[] => Ok(graph.passes.iter().enumerate()
+					.map(|(index, pass)| format!("{}. {} ({:?}) reads {:?} writes {:?}", index, pass.name, pass.kind, pass.reads, pass.writes))
+					.collect::<Vec<_>>()
+					.join("\n")),
+				[file] => {
+					let contents = if file.ends_with(".json") { graph.to_json() } else { graph.to_dot() };
+					std::fs::write(file, contents)?;
+					Ok(format!("wrote {} passes to {}", graph.passes.len(), file))
+				}
+				_ => anyhow::bail!("expected at most one file"),
+			}
+		});
+
+		self.register("vsync", "<on|off>", |context, args| {
+			let present_mode = match args {
+				["on"] => wgpu::PresentMode::AutoVsync,
+				["off"] => wgpu::PresentMode::AutoNoVsync,
+				_ => anyhow::bail!("expected on or off"),
+			};
+			Ok(format!("presenting with {:?}", context.renderer.set_present_mode(present_mode)))
+		});
+
+		self.register("present_mode", "<fifo|fifo_relaxed|mailbox|immediate>", |context, args| {
+			let [mode] = args else {
+				anyhow::bail!("expected a present mode");
+			};
+			let present_mode = match *mode {
+				"fifo" => wgpu::PresentMode::Fifo,
+				"fifo_relaxed" => wgpu::PresentMode::FifoRelaxed,
+				"mailbox" => wgpu::PresentMode::Mailbox,
+				"immediate" => wgpu::PresentMode::Immediate,
+				_ => anyhow::bail!("unknown present mode {}", mode),
+			};
+			// unsupported modes fall back to fifo
+			Ok(format!("presenting with {:?}", context.renderer.set_present_mode(present_mode)))
+		});
+
+		// a frame rate counter in the corner, needs the HUD font
+		#[cfg(feature = "text")]
+		self.register("fps", "<on|off>", |context, args| {
+			let [mode] = args else {
+				anyhow::bail!("expected on or off");
+			};
+			if context.renderer.text.is_none() {
+				anyhow::bail!("the HUD font isn't loaded");
+			}
+			context.renderer.show_fps = match *mode {
+				"on" => true,
+				"off" => false,
+				_ => anyhow::bail!("expected on or off"),
+			};
+			Ok(String::new())
+		});
+
+		// with no argument, prints the last frame's timings
+		self.register("profile", "[on|off]", |context, args| {
+			let profiler = &mut context.renderer.profiler;
+			match args {
+				[] => return Ok(profiler.stats.to_string()),
+				["on"] if !profiler.is_supported() => anyhow::bail!("GPU timings need timestamp queries between passes, only CPU times are measured"),
+				["on"] => profiler.enabled = true,
+				["off"] => profiler.enabled = false,
+				_ => anyhow::bail!("expected on or off"),
+			}
+			Ok(String::new())
+		});
+
+		self.register("stats", "", |context, _| {
+			let scene = &context.scene;
+			let meshes = scene.models.iter().map(|model| model.meshes.len()).sum::<usize>();
+			Ok(format!(
+				"objects: {}\nmodels: {} ({} meshes)\nmaterials: {}\nlights: {}\nframe time: {:.2} ms\nadapter: {} ({:?})",
+				scene.objects.len(),
+				scene.models.len(),
+				meshes,
+				scene.materials.len(),
+				scene.lights().len(),
+				scene.delta_time * 1000.0,
+				context.renderer.capabilities.adapter.name,
+				context.renderer.capabilities.adapter.backend,
+			))
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// runs a line against the default commands, giving back the usage and arguments of the command it picked
+	fn dispatch(console: &Console, line: &str) -> Option<anyhow::Result<String>> {
+		console.dispatch(line, |command, args| Ok(format!("{} {}", command.usage, args.join(","))))
+	}
+
+	#[test]
+	fn lines_split_into_commands_and_arguments() {
+		let mut console = Console::new();
+		console.register_default_commands();
+
+		assert!(dispatch(&console, "").is_none());
+		assert!(dispatch(&console, "   ").is_none());
+		assert_eq!(dispatch(&console, "  light_color 1 0.5\t0  2 ").unwrap().unwrap(), "<r> <g> <b> [light index] 1,0.5,0,2");
+		assert_eq!(dispatch(&console, "stats").unwrap().unwrap(), " ");
+
+		let error = dispatch(&console, "lights 1").unwrap().unwrap_err();
+		assert_eq!(error.to_string(), "unknown command lights, try help");
+
+		let help = dispatch(&console, "help").unwrap().unwrap();
+		assert!(help.lines().any(|line| line == "exposure <multiplier>"));
+		assert_eq!(help.lines().count(), console.commands.len());
+	}
+
+	#[test]
+	fn submitted_lines_are_recalled_in_order() {
+		let mut console = Console::new();
+		for line in ["exposure 2", "  ", "stats"] {
+			console.input = line.to_string();
+			assert_eq!(console.submit(), line);
+		}
+		assert_eq!(console.history, ["exposure 2", "stats"]);
+
+		console.recall(-1);
+		assert_eq!(console.input(), "stats");
+		console.recall(-1);
+		assert_eq!(console.input(), "exposure 2");
+		console.recall(-1);
+		assert_eq!(console.input(), "exposure 2");
+		console.recall(1);
+		console.recall(1);
+		assert_eq!(console.input(), "");
+	}
+}
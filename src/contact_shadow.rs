@@ -0,0 +1,211 @@
+use wgpu::util::DeviceExt;
+
+use crate::{light, point_shadow, texture};
+
+gpu_struct! {
+	Uniform
+	#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+	pub struct ContactShadowParams {
+		pub strength: f32, // 0 disables the pass
+		pub length: f32, // world units marched toward the light
+		pub thickness: f32, // how far behind the depth buffer a sample still counts as occluded
+		pub steps: u32,
+	}
+}
+
+impl ContactShadowParams {
+	pub fn new() -> Self {
+		Self {
+			strength: 0.7,
+			length: 0.1,
+			thickness: 0.05,
+			steps: 12,
+			..bytemuck::Zeroable::zeroed()
+		}
+	}
+}
+
+/*
+Screen space contact shadows. A short ray is marched toward the light through the depth buffer
+after the lit pass, and hits darken the image where the point shadow maps left it lit, since
+their resolution misses the small gaps where objects touch. Only the first light is marched,
+against the full resolution depth buffer.
+*/
+pub struct ContactShadows {
+	pub params: ContactShadowParams,
+	uploaded: ContactShadowParams,
+	params_buffer: wgpu::Buffer,
+	layout: wgpu::BindGroupLayout,
+	bind_group: wgpu::BindGroup,
+	pipeline: wgpu::RenderPipeline,
+}
+
+impl ContactShadows {
+	pub fn new(
+		device: &wgpu::Device,
+		color_format: wgpu::TextureFormat,
+		depth_texture: &texture::Texture,
+		camera_buffer: &wgpu::Buffer,
+		light_buffer: &wgpu::Buffer,
+		point_shadows: &point_shadow::PointShadows,
+	) -> Self {
+		let params = ContactShadowParams::new();
+		let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Contact Shadow Params Buffer"),
+			contents: bytemuck::cast_slice(&[params]),
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+		});
+
+		let uniform = |binding| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Buffer {
+				ty: wgpu::BufferBindingType::Uniform,
+				has_dynamic_offset: false,
+				min_binding_size: None,
+			},
+			count: None,
+		};
+		let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("contact_shadow_bind_group_layout"),
+			entries: &[
+				wgpu::BindGroupLayoutEntry { // depth
+					binding: 0,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Texture {
+						multisampled: false,
+						view_dimension: wgpu::TextureViewDimension::D2,
+						sample_type: wgpu::TextureSampleType::Depth,
+					},
+					count: None,
+				},
+				uniform(1), // camera
+				light::Lights::storage_entry(2), // lights
+				uniform(3), // params
+			],
+		});
+		let bind_group = Self::create_bind_group(device, &layout, depth_texture, camera_buffer, light_buffer, &params_buffer);
+
+		let shader = device.create_shader_module(wgpu::include_wgsl!("contact_shadow.wgsl"));
+		// group 1 is the point shadow maps, so contact shadows only darken what they left lit
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Contact Shadow Pipeline Layout"),
+			bind_group_layouts: &[Some(&layout), Some(&point_shadows.layout)],
+			immediate_size: 0,
+		});
+		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("Contact Shadow Pipeline"),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: Some("vs_main"),
+				buffers: &[],
+				compilation_options: Default::default(),
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: Some("fs_main"),
+				targets: &[Some(wgpu::ColorTargetState {
+					format: color_format,
+					// multiply the visibility onto the lit image
+					blend: Some(wgpu::BlendState {
+						color: wgpu::BlendComponent {
+							src_factor: wgpu::BlendFactor::Dst,
+							dst_factor: wgpu::BlendFactor::Zero,
+							operation: wgpu::BlendOperation::Add,
+						},
+						alpha: wgpu::BlendComponent {
+							src_factor: wgpu::BlendFactor::Zero,
+							dst_factor: wgpu::BlendFactor::One,
+							operation: wgpu::BlendOperation::Add,
+						},
+					}),
+					write_mask: wgpu::ColorWrites::ALL,
+				})],
+				compilation_options: Default::default(),
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			multiview_mask: None,
+			cache: None,
+		});
+
+		Self {
+			params,
+			uploaded: params,
+			params_buffer,
+			layout,
+			bind_group,
+			pipeline,
+		}
+	}
+
+	fn create_bind_group(
+		device: &wgpu::Device,
+		layout: &wgpu::BindGroupLayout,
+		depth_texture: &texture::Texture,
+		camera_buffer: &wgpu::Buffer,
+		light_buffer: &wgpu::Buffer,
+		params_buffer: &wgpu::Buffer,
+	) -> wgpu::BindGroup {
+		device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("contact_shadow_bind_group"),
+			layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: camera_buffer.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: light_buffer.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 3,
+					resource: params_buffer.as_entire_binding(),
+				},
+			],
+		})
+	}
+
+	// the depth texture is recreated on resize
+	pub fn resize(&mut self, device: &wgpu::Device, depth_texture: &texture::Texture, camera_buffer: &wgpu::Buffer, light_buffer: &wgpu::Buffer) {
+		self.bind_group = Self::create_bind_group(device, &self.layout, depth_texture, camera_buffer, light_buffer, &self.params_buffer);
+	}
+
+	pub fn draw(&mut self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, point_shadows: &point_shadow::PointShadows) {
+		if self.params.strength <= 0.0 || self.params.steps == 0 {
+			return;
+		}
+		if self.params != self.uploaded {
+			queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[self.params]));
+			self.uploaded = self.params;
+		}
+
+		let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Contact Shadow Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: wgpu::StoreOp::Store,
+				},
+				depth_slice: None,
+			})],
+			depth_stencil_attachment: None,
+			occlusion_query_set: None,
+			timestamp_writes: None,
+			multiview_mask: None,
+		});
+		render_pass.set_pipeline(&self.pipeline);
+		render_pass.set_bind_group(0, &self.bind_group, &[]);
+		render_pass.set_bind_group(1, &point_shadows.bind_group, &[]);
+		render_pass.draw(0..3, 0..1);
+	}
+}
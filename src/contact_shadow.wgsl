@@ -0,0 +1,130 @@
+// Full screen pass that marches a short ray toward the light through the depth buffer and
+// darkens what the shadow maps left lit, catching the small gaps their resolution misses
+
+struct Camera {
+	view: mat4x4<f32>,
+	proj: mat4x4<f32>,
+	view_proj: mat4x4<f32>,
+	inv_view: mat4x4<f32>,
+	inv_proj: mat4x4<f32>,
+	inv_view_proj: mat4x4<f32>,
+	position: vec4<f32>,
+};
+
+struct Light {
+	position: vec3<f32>,
+	intensity: f32,
+	color: vec3<f32>,
+	range: f32,
+	direction: vec3<f32>,
+	kind: u32, // 0 point, 1 directional, 2 spot
+	falloff: u32,
+	shadow_index: i32,
+	spot_cos: vec2<f32>,
+	view_proj: mat4x4<f32>,
+};
+struct Lights {
+	count: u32,
+	lights: array<Light>,
+};
+
+struct Params {
+	strength: f32, // 0 disables the pass
+	length: f32, // world units marched toward the light
+	thickness: f32, // how far behind the depth buffer a sample still counts as occluded
+	steps: u32,
+};
+
+@group(0) @binding(0)
+var depth_texture: texture_depth_2d;
+@group(0) @binding(1)
+var<uniform> camera: Camera;
+@group(0) @binding(2)
+var<storage, read> lights: Lights; // rays are marched toward the first light
+@group(0) @binding(3)
+var<uniform> params: Params;
+
+@group(1) @binding(0)
+var point_shadow_maps: texture_depth_cube_array;
+@group(1) @binding(1)
+var point_shadow_sampler: sampler_comparison;
+
+const NORMAL_BIAS: f32 = 0.01;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+	// one triangle covering the screen
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn world_position(pixel: vec2<f32>, depth: f32) -> vec3<f32> {
+	let size = vec2<f32>(textureDimensions(depth_texture));
+	let uv = pixel / size;
+	let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+	let world = camera.inv_view_proj * ndc;
+	return world.xyz / world.w;
+}
+
+// distance in front of the camera
+fn linear_depth(depth: f32) -> f32 {
+	let view = camera.inv_proj * vec4<f32>(0.0, 0.0, depth, 1.0);
+	return -view.z / view.w;
+}
+
+// what the point shadow maps already say about the surface, 1 when lit
+fn shadow_map_visibility(light: Light, position: vec3<f32>, normal: vec3<f32>) -> f32 {
+	if light.shadow_index < 0 {
+		return 1.0;
+	}
+	let to_surface = position + normal * NORMAL_BIAS - light.position;
+	let depth = min(length(to_surface) / light.range, 1.0);
+	return textureSampleCompareLevel(point_shadow_maps, point_shadow_sampler, to_surface, light.shadow_index, depth);
+}
+
+fn march(origin: vec3<f32>, dir: vec3<f32>) -> bool {
+	let size = vec2<f32>(textureDimensions(depth_texture));
+	let step = dir * (params.length / f32(params.steps));
+	var position = origin;
+	for (var i = 0u; i < params.steps; i++) {
+		position += step;
+		let clip = camera.view_proj * vec4<f32>(position, 1.0);
+		let ndc = clip.xyz / clip.w;
+		let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+		if any(uv < vec2<f32>(0.0)) || any(uv >= vec2<f32>(1.0)) || ndc.z <= 0.0 {
+			return false;
+		}
+		let scene_depth = linear_depth(textureLoad(depth_texture, vec2<i32>(uv * size), 0));
+		let behind = linear_depth(ndc.z) - scene_depth;
+		if behind > 0.0 && behind < params.thickness {
+			return true;
+		}
+	}
+	return false;
+}
+
+@fragment
+fn fs_main(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
+	let depth = textureLoad(depth_texture, vec2<i32>(frag_coord.xy), 0);
+	let position = world_position(frag_coord.xy, depth);
+	// derivatives are taken before any early out so they stay defined
+	var normal = normalize(cross(dpdy(position), dpdx(position)));
+	if dot(normal, camera.position.xyz - position) < 0.0 {
+		normal = -normal;
+	}
+	if depth >= 1.0 || lights.count == 0u {
+		return vec4<f32>(1.0);
+	}
+	let light = lights.lights[0];
+	let dir = select(normalize(light.position - position), -normalize(light.direction), light.kind == 1u);
+	if dot(dir, normal) <= 0.0 {
+		return vec4<f32>(1.0);
+	}
+
+	// only darkens what the shadow maps left lit, so shadowed areas aren't darkened twice
+	let lit = shadow_map_visibility(light, position, normal);
+	if lit <= 0.0 || !march(position + normal * NORMAL_BIAS, dir) {
+		return vec4<f32>(1.0);
+	}
+	return vec4<f32>(vec3<f32>(1.0 - params.strength * lit), 1.0);
+}
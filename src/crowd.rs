@@ -0,0 +1,318 @@
+use cgmath::SquareMatrix;
+use wgpu::util::DeviceExt;
+
+use crate::{model, skinning};
+
+const WORKGROUP_SIZE: u32 = 64;
+
+gpu_struct! {
+	Storage
+	#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+	struct InstanceUniform {
+		transform: [[f32; 4]; 4],
+		frames: [u32; 2],
+		blend: f32,
+	}
+}
+
+gpu_struct! {
+	Uniform
+	#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+	struct CrowdParams {
+		joint_count: u32,
+		vertex_count: u32,
+		instance_count: u32,
+	}
+}
+
+/*
+Compute pass for crowds, many animated copies of one skinned model. Instead of a Skin with its
+own joint buffer per character, a crowd shares one palette of baked poses, and each instance
+only picks its frame from its time offset and speed. Every instance is skinned straight into
+world space in one dispatch per mesh, so the whole crowd is drawn from one vertex buffer
+without per instance uniforms.
+*/
+pub struct CrowdPipeline {
+	pipeline: wgpu::ComputePipeline,
+	layout: wgpu::BindGroupLayout,
+}
+
+impl CrowdPipeline {
+	const STORAGE_BUFFERS: u32 = 5;
+
+	// None when the device can't bind the crowd's storage buffers
+	pub fn new(device: &wgpu::Device) -> Option<Self> {
+		if device.limits().max_storage_buffers_per_shader_stage < Self::STORAGE_BUFFERS {
+			return None;
+		}
+		let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::COMPUTE,
+			ty: wgpu::BindingType::Buffer {
+				ty: wgpu::BufferBindingType::Storage { read_only },
+				has_dynamic_offset: false,
+				min_binding_size: None,
+			},
+			count: None,
+		};
+		let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("crowd_bind_group_layout"),
+			entries: &[
+				storage(0, true), // source vertices
+				storage(1, true), // influences
+				storage(2, true), // poses
+				storage(3, true), // instances
+				storage(4, false), // skinned vertices
+				wgpu::BindGroupLayoutEntry { // params
+					binding: 5,
+					visibility: wgpu::ShaderStages::COMPUTE,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+			],
+		});
+
+		let shader = device.create_shader_module(wgpu::include_wgsl!("crowd.wgsl"));
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Crowd Pipeline Layout"),
+			bind_group_layouts: &[Some(&layout)],
+			immediate_size: 0,
+		});
+		let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+			label: Some("Crowd Pipeline"),
+			layout: Some(&pipeline_layout),
+			module: &shader,
+			entry_point: Some("cs_main"),
+			compilation_options: Default::default(),
+			cache: None,
+		});
+
+		Some(Self {
+			pipeline,
+			layout,
+		})
+	}
+
+	// uploads every crowd's instances at the scene time and skins them
+	pub fn dispatch(&self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, crowds: &[Crowd], time: f32) {
+		if crowds.is_empty() {
+			return;
+		}
+		for crowd in crowds {
+			crowd.upload(queue, time);
+		}
+		let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+			label: Some("Crowd Pass"),
+			timestamp_writes: None,
+		});
+		compute_pass.set_pipeline(&self.pipeline);
+		for crowd in crowds {
+			let instance_count = crowd.instance_count();
+			if instance_count == 0 {
+				continue;
+			}
+			for mesh in &crowd.meshes {
+				compute_pass.set_bind_group(0, &mesh.bind_group, &[]);
+				compute_pass.dispatch_workgroups(mesh.vertex_count.div_ceil(WORKGROUP_SIZE), instance_count, 1);
+			}
+		}
+	}
+}
+
+// one character of a crowd
+#[derive(Copy, Clone, Debug)]
+pub struct CrowdInstance {
+	pub transform: cgmath::Matrix4<f32>,
+	pub time_offset: f32, // seconds into the animation at scene time 0, so the crowd isn't in step
+	pub speed: f32, // playback rate, 1 is the baked rate
+}
+
+impl CrowdInstance {
+	pub fn new(transform: cgmath::Matrix4<f32>) -> Self {
+		Self {
+			transform,
+			time_offset: 0.0,
+			speed: 1.0,
+		}
+	}
+}
+
+pub struct CrowdMesh {
+	pub vertex_buffer: wgpu::Buffer, // vertex_count skinned vertices per instance, in world space
+	pub vertex_count: u32,
+	bind_group: wgpu::BindGroup,
+	params_buffer: wgpu::Buffer,
+	_source: wgpu::Buffer,
+	_influences: wgpu::Buffer,
+}
+
+/*
+Instances of one skinned model sharing a looping animation baked into poses. Instances past
+the capacity given at creation aren't drawn. Instances aren't culled against the view and don't
+cast shadows.
+*/
+pub struct Crowd {
+	pub model_index: usize, // the model whose meshes, materials, and index buffers are drawn
+	pub instances: Vec<CrowdInstance>,
+	pub meshes: Vec<CrowdMesh>, // one per mesh of the model
+	capacity: usize,
+	frame_count: usize,
+	frame_rate: f32,
+	instance_buffer: wgpu::Buffer,
+	_pose_buffer: wgpu::Buffer,
+}
+
+impl Crowd {
+	/*
+	poses holds the joint matrices of every frame of the animation, joint_count per frame, taking
+	a vertex from bind pose to model space like Skin::set_joints.
+	*/
+	pub fn new(
+		device: &wgpu::Device,
+		pipeline: &CrowdPipeline,
+		model_index: usize,
+		meshes: &[(&[model::ModelVertex], &[skinning::VertexInfluence])],
+		poses: &[Vec<cgmath::Matrix4<f32>>],
+		frame_rate: f32,
+		capacity: usize,
+	) -> Self {
+		let joint_count = poses.first().map_or(0, Vec::len);
+		assert!(poses.iter().all(|frame| frame.len() == joint_count), "every frame needs the same joints");
+		let mut pose_data = poses.iter()
+			.flatten()
+			.map(|&joint| joint.into())
+			.collect::<Vec<[[f32; 4]; 4]>>();
+		if pose_data.is_empty() {
+			pose_data.push(cgmath::Matrix4::identity().into());
+		}
+		let pose_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Crowd Pose Buffer"),
+			contents: bytemuck::cast_slice(&pose_data),
+			usage: wgpu::BufferUsages::STORAGE,
+		});
+		let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Crowd Instance Buffer"),
+			size: (capacity.max(1) * std::mem::size_of::<InstanceUniform>()) as u64,
+			usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+
+		let meshes = meshes.iter().map(|(vertices, influences)| {
+			assert_eq!(vertices.len(), influences.len(), "every vertex needs an influence");
+
+			let source = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Crowd Source Buffer"),
+				contents: bytemuck::cast_slice(vertices),
+				usage: wgpu::BufferUsages::STORAGE,
+			});
+			let influences = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Crowd Influence Buffer"),
+				contents: bytemuck::cast_slice(influences),
+				usage: wgpu::BufferUsages::STORAGE,
+			});
+			let vertex_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+				label: Some("Crowd Vertex Buffer"),
+				size: (capacity.max(1) * vertices.len() * std::mem::size_of::<model::ModelVertex>()) as u64,
+				usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+				mapped_at_creation: false,
+			});
+			let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Crowd Params Buffer"),
+				contents: bytemuck::cast_slice(&[CrowdParams {
+					joint_count: joint_count as u32,
+					vertex_count: vertices.len() as u32,
+					instance_count: 0,
+					..bytemuck::Zeroable::zeroed()
+				}]),
+				usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			});
+
+			let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+				label: Some("crowd_bind_group"),
+				layout: &pipeline.layout,
+				entries: &[
+					wgpu::BindGroupEntry {
+						binding: 0,
+						resource: source.as_entire_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 1,
+						resource: influences.as_entire_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 2,
+						resource: pose_buffer.as_entire_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 3,
+						resource: instance_buffer.as_entire_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 4,
+						resource: vertex_buffer.as_entire_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 5,
+						resource: params_buffer.as_entire_binding(),
+					},
+				],
+			});
+
+			CrowdMesh {
+				vertex_buffer,
+				vertex_count: vertices.len() as u32,
+				bind_group,
+				params_buffer,
+				_source: source,
+				_influences: influences,
+			}
+		}).collect();
+
+		Self {
+			model_index,
+			instances: vec![],
+			meshes,
+			capacity,
+			frame_count: poses.len().max(1),
+			frame_rate,
+			instance_buffer,
+			_pose_buffer: pose_buffer,
+		}
+	}
+
+	// the instances drawn, the ones within the capacity
+	pub fn instance_count(&self) -> u32 {
+		self.instances.len().min(self.capacity) as u32
+	}
+
+	// the frames each instance blends between at the time, wrapping around the animation
+	fn upload(&self, queue: &wgpu::Queue, time: f32) {
+		let instances = self.instances[..self.instance_count() as usize].iter()
+			.map(|instance| {
+				let frame = ((time * instance.speed + instance.time_offset) * self.frame_rate).rem_euclid(self.frame_count as f32);
+				let first = (frame as usize).min(self.frame_count - 1);
+				InstanceUniform {
+					transform: instance.transform.into(),
+					frames: [first as u32, ((first + 1) % self.frame_count) as u32],
+					blend: frame.fract(),
+					..bytemuck::Zeroable::zeroed()
+				}
+			})
+			.collect::<Vec<_>>();
+		queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&instances));
+		for mesh in &self.meshes {
+			queue.write_buffer(&mesh.params_buffer, 8, bytemuck::cast_slice(&[instances.len() as u32]));
+		}
+	}
+
+	// the vertices are already in world space, so the model uniform only carries the defaults
+	pub fn to_uniform(&self) -> model::ModelUniform {
+		let mut uniform = model::ModelUniform::new();
+		uniform.shadow_flags = model::ModelInstance::RECEIVES_SHADOWS;
+		uniform
+	}
+}
@@ -0,0 +1,81 @@
+// Skins every instance of a crowd into world space, each at its own frame of the shared poses
+
+// matches ModelVertex, read as floats since a vec3 field would be padded to 16 bytes
+const VERTEX_FLOATS: u32 = 14u;
+
+struct Influence {
+	joints: vec4<u32>,
+	weights: vec4<f32>,
+};
+
+struct Instance {
+	transform: mat4x4<f32>,
+	frames: vec2<u32>, // the poses blended between
+	blend: f32,
+};
+
+struct Params {
+	joint_count: u32,
+	vertex_count: u32,
+	instance_count: u32,
+};
+
+@group(0) @binding(0)
+var<storage, read> source: array<f32>;
+@group(0) @binding(1)
+var<storage, read> influences: array<Influence>;
+@group(0) @binding(2)
+var<storage, read> poses: array<mat4x4<f32>>; // joint_count matrices per frame
+@group(0) @binding(3)
+var<storage, read> instances: array<Instance>;
+@group(0) @binding(4)
+var<storage, read_write> skinned: array<f32>; // vertex_count vertices per instance
+@group(0) @binding(5)
+var<uniform> params: Params;
+
+fn read_vec3(i: u32) -> vec3<f32> {
+	return vec3<f32>(source[i], source[i + 1u], source[i + 2u]);
+}
+
+fn write_vec3(i: u32, v: vec3<f32>) {
+	skinned[i] = v.x;
+	skinned[i + 1u] = v.y;
+	skinned[i + 2u] = v.z;
+}
+
+fn joint(instance: Instance, index: u32) -> mat4x4<f32> {
+	let a = poses[instance.frames.x * params.joint_count + index];
+	let b = poses[instance.frames.y * params.joint_count + index];
+	return a * (1.0 - instance.blend) + b * instance.blend;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+	let vertex = id.x;
+	if vertex >= params.vertex_count || id.y >= params.instance_count {
+		return;
+	}
+	let base = vertex * VERTEX_FLOATS;
+	let out = (id.y * params.vertex_count + vertex) * VERTEX_FLOATS;
+
+	let instance = instances[id.y];
+	let influence = influences[vertex];
+	let skin = instance.transform * (joint(instance, influence.joints.x) * influence.weights.x
+		+ joint(instance, influence.joints.y) * influence.weights.y
+		+ joint(instance, influence.joints.z) * influence.weights.z
+		+ joint(instance, influence.joints.w) * influence.weights.w);
+
+	// position
+	write_vec3(out, (skin * vec4<f32>(read_vec3(base), 1.0)).xyz);
+	// tex coords
+	skinned[out + 3u] = source[base + 3u];
+	skinned[out + 4u] = source[base + 4u];
+	// normal
+	write_vec3(out + 5u, normalize((skin * vec4<f32>(read_vec3(base + 5u), 0.0)).xyz));
+	// tangent, keeping the handedness in w
+	write_vec3(out + 8u, normalize((skin * vec4<f32>(read_vec3(base + 8u), 0.0)).xyz));
+	skinned[out + 11u] = source[base + 11u];
+	// lightmap coords
+	skinned[out + 12u] = source[base + 12u];
+	skinned[out + 13u] = source[base + 13u];
+}
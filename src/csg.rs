@@ -0,0 +1,351 @@
+use cgmath::InnerSpace;
+
+use crate::model;
+
+const EPSILON: f32 = 1e-5; // how far from a plane a point still counts as on it
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Operation {
+	Union,
+	Subtract, // the first solid with the second cut out of it
+	Intersect,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Plane {
+	normal: cgmath::Vector3<f32>,
+	w: f32, // distance from the origin along the normal
+}
+
+// where a polygon ended up relative to a splitting plane
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Side {
+	CoplanarFront,
+	CoplanarBack,
+	Front,
+	Back,
+}
+
+impl Plane {
+	// None for degenerate triangles
+	fn from_points(a: cgmath::Vector3<f32>, b: cgmath::Vector3<f32>, c: cgmath::Vector3<f32>) -> Option<Self> {
+		let normal = (b - a).cross(c - a);
+		if normal.magnitude2() < EPSILON * EPSILON {
+			return None;
+		}
+		let normal = normal.normalize();
+		Some(Self { normal, w: normal.dot(a) })
+	}
+
+	fn flip(&mut self) {
+		self.normal = -self.normal;
+		self.w = -self.w;
+	}
+
+	// splits the polygon by the plane, passing on every piece with the side it's on
+	fn split(&self, polygon: Polygon, mut on: impl FnMut(Side, Polygon)) {
+		const COPLANAR: u8 = 0;
+		const FRONT: u8 = 1;
+		const BACK: u8 = 2;
+		const SPANNING: u8 = 3;
+		let types = polygon.vertices.iter()
+			.map(|vertex| {
+				let t = self.normal.dot(vertex.position.into()) - self.w;
+				if t < -EPSILON { BACK } else if t > EPSILON { FRONT } else { COPLANAR }
+			})
+			.collect::<Vec<_>>();
+
+		match types.iter().fold(COPLANAR, |all, t| all | t) {
+			COPLANAR if self.normal.dot(polygon.plane.normal) > 0.0 => on(Side::CoplanarFront, polygon),
+			COPLANAR => on(Side::CoplanarBack, polygon),
+			FRONT => on(Side::Front, polygon),
+			BACK => on(Side::Back, polygon),
+			_ => {
+				let (mut front, mut back) = (vec![], vec![]);
+				let count = polygon.vertices.len();
+				for i in 0..count {
+					let j = (i + 1) % count;
+					let (vi, vj) = (&polygon.vertices[i], &polygon.vertices[j]);
+					if types[i] != BACK {
+						front.push(*vi);
+					}
+					if types[i] != FRONT {
+						back.push(*vi);
+					}
+					if types[i] | types[j] == SPANNING {
+						let (pi, pj) = (cgmath::Vector3::from(vi.position), cgmath::Vector3::from(vj.position));
+						let t = (self.w - self.normal.dot(pi)) / self.normal.dot(pj - pi);
+						let vertex = lerp_vertex(vi, vj, t);
+						front.push(vertex);
+						back.push(vertex);
+					}
+				}
+				if front.len() >= 3 {
+					on(Side::Front, Polygon { vertices: front, plane: polygon.plane });
+				}
+				if back.len() >= 3 {
+					on(Side::Back, Polygon { vertices: back, plane: polygon.plane });
+				}
+			}
+		}
+	}
+}
+
+fn lerp_vertex(a: &model::ModelVertex, b: &model::ModelVertex, t: f32) -> model::ModelVertex {
+	let lerp = |a: f32, b: f32| a + (b - a) * t;
+	model::ModelVertex {
+		position: std::array::from_fn(|i| lerp(a.position[i], b.position[i])),
+		tex_coords: std::array::from_fn(|i| lerp(a.tex_coords[i], b.tex_coords[i])),
+		normal: std::array::from_fn(|i| lerp(a.normal[i], b.normal[i])),
+		tangent: [0.0; 4], // regenerated when the result is uploaded
+		lightmap_coords: std::array::from_fn(|i| lerp(a.lightmap_coords[i], b.lightmap_coords[i])),
+	}
+}
+
+// a convex polygon, the vertices wind counter clockwise around the plane's normal
+#[derive(Clone, Debug)]
+struct Polygon {
+	vertices: Vec<model::ModelVertex>,
+	plane: Plane,
+}
+
+impl Polygon {
+	fn flip(&mut self) {
+		self.vertices.reverse();
+		for vertex in &mut self.vertices {
+			vertex.normal = vertex.normal.map(|c| -c);
+		}
+		self.plane.flip();
+	}
+}
+
+// a BSP tree of the polygons of a solid, the planes are taken from the polygons
+#[derive(Default)]
+struct Node {
+	plane: Option<Plane>,
+	front: Option<Box<Node>>,
+	back: Option<Box<Node>>,
+	polygons: Vec<Polygon>, // the ones on this node's plane
+}
+
+impl Node {
+	fn new(polygons: Vec<Polygon>) -> Self {
+		let mut node = Self::default();
+		node.build(polygons);
+		node
+	}
+
+	// turns the solid inside out
+	fn invert(&mut self) {
+		for polygon in &mut self.polygons {
+			polygon.flip();
+		}
+		if let Some(plane) = &mut self.plane {
+			plane.flip();
+		}
+		for node in [&mut self.front, &mut self.back].into_iter().flatten() {
+			node.invert();
+		}
+		std::mem::swap(&mut self.front, &mut self.back);
+	}
+
+	// the parts of the polygons outside this solid
+	fn clip_polygons(&self, polygons: Vec<Polygon>) -> Vec<Polygon> {
+		let Some(plane) = self.plane else {
+			return polygons;
+		};
+		let (mut front, mut back) = (vec![], vec![]);
+		for polygon in polygons {
+			plane.split(polygon, |side, polygon| match side {
+				Side::CoplanarFront | Side::Front => front.push(polygon),
+				Side::CoplanarBack | Side::Back => back.push(polygon),
+			});
+		}
+		let mut front = match &self.front {
+			Some(node) => node.clip_polygons(front),
+			None => front,
+		};
+		// behind a leaf is inside the solid
+		if let Some(node) = &self.back {
+			front.extend(node.clip_polygons(back));
+		}
+		front
+	}
+
+	// removes every part of this tree's polygons inside the other solid
+	fn clip_to(&mut self, other: &Node) {
+		self.polygons = other.clip_polygons(std::mem::take(&mut self.polygons));
+		for node in [&mut self.front, &mut self.back].into_iter().flatten() {
+			node.clip_to(other);
+		}
+	}
+
+	fn all_polygons(&self) -> Vec<Polygon> {
+		let mut polygons = self.polygons.clone();
+		for node in [&self.front, &self.back].into_iter().flatten() {
+			polygons.extend(node.all_polygons());
+		}
+		polygons
+	}
+
+	fn build(&mut self, polygons: Vec<Polygon>) {
+		let Some(first) = polygons.first() else {
+			return;
+		};
+		let plane = *self.plane.get_or_insert(first.plane);
+		let (mut front, mut back) = (vec![], vec![]);
+		for polygon in polygons {
+			plane.split(polygon, |side, polygon| match side {
+				Side::CoplanarFront | Side::CoplanarBack => self.polygons.push(polygon),
+				Side::Front => front.push(polygon),
+				Side::Back => back.push(polygon),
+			});
+		}
+		if !front.is_empty() {
+			self.front.get_or_insert_with(Default::default).build(front);
+		}
+		if !back.is_empty() {
+			self.back.get_or_insert_with(Default::default).build(back);
+		}
+	}
+}
+
+/*
+A watertight mesh for boolean operations on the CPU, for level blocking and procedural tools.
+Both solids are split by each other's polygons through BSP trees, so the cost grows quickly
+with triangle count, and the result has its own vertices for every polygon. Upload the result
+with resources::create_model, which also generates its tangents.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct Solid {
+	polygons: Vec<Polygon>,
+}
+
+impl Solid {
+	// the triangles of a mesh, moved into a shared space by transform
+	pub fn from_geometry(vertices: &[model::ModelVertex], indices: &[u32], transform: &cgmath::Matrix4<f32>) -> Self {
+		use cgmath::{Matrix, SquareMatrix};
+		let normal_matrix = transform.invert().unwrap_or(cgmath::Matrix4::identity()).transpose();
+		let vertices = vertices.iter()
+			.map(|vertex| {
+				let normal = (normal_matrix * cgmath::Vector3::from(vertex.normal).extend(0.0)).truncate();
+				model::ModelVertex {
+					position: (transform * cgmath::Vector3::from(vertex.position).extend(1.0)).truncate().into(),
+					normal: if normal.magnitude2() > 0.0 { normal.normalize().into() } else { [0.0; 3] },
+					tangent: [0.0; 4],
+					..*vertex
+				}
+			})
+			.collect::<Vec<_>>();
+		let polygons = indices.chunks_exact(3)
+			.filter_map(|triangle| {
+				let triangle = [0, 1, 2].map(|i| vertices[triangle[i] as usize]);
+				let [a, b, c] = triangle.map(|vertex| cgmath::Vector3::from(vertex.position));
+				Some(Polygon { vertices: triangle.to_vec(), plane: Plane::from_points(a, b, c)? })
+			})
+			.collect();
+		Self {
+			polygons,
+		}
+	}
+
+	pub fn apply(&self, operation: Operation, other: &Solid) -> Solid {
+		let mut a = Node::new(self.polygons.clone());
+		let mut b = Node::new(other.polygons.clone());
+		match operation {
+			Operation::Union => {
+				a.clip_to(&b);
+				b.clip_to(&a);
+				b.invert();
+				b.clip_to(&a);
+				b.invert();
+				a.build(b.all_polygons());
+			}
+			Operation::Subtract => {
+				a.invert();
+				a.clip_to(&b);
+				b.clip_to(&a);
+				b.invert();
+				b.clip_to(&a);
+				b.invert();
+				a.build(b.all_polygons());
+				a.invert();
+			}
+			Operation::Intersect => {
+				a.invert();
+				b.clip_to(&a);
+				b.invert();
+				a.clip_to(&b);
+				b.clip_to(&a);
+				a.build(b.all_polygons());
+				a.invert();
+			}
+		}
+		Solid {
+			polygons: a.all_polygons(),
+		}
+	}
+
+	pub fn union(&self, other: &Solid) -> Solid {
+		self.apply(Operation::Union, other)
+	}
+
+	pub fn subtract(&self, other: &Solid) -> Solid {
+		self.apply(Operation::Subtract, other)
+	}
+
+	pub fn intersect(&self, other: &Solid) -> Solid {
+		self.apply(Operation::Intersect, other)
+	}
+
+	// vertices and triangles, each polygon as a fan
+	pub fn to_geometry(&self) -> (Vec<model::ModelVertex>, Vec<u32>) {
+		let mut vertices = vec![];
+		let mut indices = vec![];
+		for polygon in &self.polygons {
+			let base = vertices.len() as u32;
+			vertices.extend_from_slice(&polygon.vertices);
+			for i in 2..polygon.vertices.len() as u32 {
+				indices.extend_from_slice(&[base, base + i - 1, base + i]);
+			}
+		}
+		(vertices, indices)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_meshes;
+
+	fn cube_at(x: f32) -> Solid {
+		let (vertices, indices) = test_meshes::cube();
+		Solid::from_geometry(&vertices, &indices, &cgmath::Matrix4::from_translation(cgmath::vec3(x, 0.0, 0.0)))
+	}
+
+	fn bounds(solid: &Solid) -> ([f32; 3], [f32; 3]) {
+		let (vertices, _) = solid.to_geometry();
+		assert!(!vertices.is_empty());
+		vertices.iter().fold(([f32::MAX; 3], [f32::MIN; 3]), |(min, max), vertex| {
+			(std::array::from_fn(|i| min[i].min(vertex.position[i])), std::array::from_fn(|i| max[i].max(vertex.position[i])))
+		})
+	}
+
+	fn assert_near(actual: [f32; 3], expected: [f32; 3]) {
+		assert!(actual.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-4), "{:?} != {:?}", actual, expected);
+	}
+
+	#[test]
+	fn union_of_overlapping_cubes_covers_both() {
+		let (min, max) = bounds(&cube_at(0.0).union(&cube_at(0.5)));
+		assert_near(min, [-0.5, -0.5, -0.5]);
+		assert_near(max, [1.0, 0.5, 0.5]);
+	}
+
+	#[test]
+	fn subtracting_an_overlapping_cube_cuts_it_off() {
+		let (min, max) = bounds(&cube_at(0.0).subtract(&cube_at(0.5)));
+		assert_near(min, [-0.5, -0.5, -0.5]);
+		assert_near(max, [0.0, 0.5, 0.5]);
+	}
+}
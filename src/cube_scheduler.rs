@@ -0,0 +1,63 @@
+/*
+Spreads the re-rendering of cube maps over frames, so dynamic shadow cubes and probes stay
+affordable as their number grows. Each frame at most faces_per_frame faces are picked: faces
+whose cube was invalidated first, nearest the camera first, then the rest by how many frames
+they've gone without an update over their cube's distance to the camera. Far cubes still get
+their turn, just less often.
+*/
+pub struct CubeScheduler {
+	pub faces_per_frame: usize,
+	faces: Vec<FaceState>, // 6 per cube, in cube face order
+}
+
+#[derive(Copy, Clone, Debug)]
+struct FaceState {
+	dirty: bool, // the cube changed since the face was rendered
+	age: u32, // frames since the face was rendered
+}
+
+impl CubeScheduler {
+	pub fn new(faces_per_frame: usize) -> Self {
+		Self {
+			faces_per_frame,
+			faces: vec![],
+		}
+	}
+
+	// cubes past the old count start out dirty
+	pub fn set_cube_count(&mut self, count: usize) {
+		self.faces.resize(count * 6, FaceState { dirty: true, age: 0 });
+	}
+
+	// every face of the cube is rendered again before any stale face
+	pub fn invalidate(&mut self, cube: usize) {
+		for face in &mut self.faces[cube * 6..cube * 6 + 6] {
+			face.dirty = true;
+		}
+	}
+
+	// the faces to render this frame as cube * 6 + face, centers are the cubes' positions
+	pub fn schedule(&mut self, centers: &[cgmath::Point3<f32>], eye: cgmath::Point3<f32>) -> Vec<usize> {
+		use cgmath::MetricSpace;
+		let mut candidates = (0..self.faces.len())
+			.map(|index| {
+				let face = self.faces[index];
+				let distance = centers[index / 6].distance(eye).max(1.0);
+				(index, face.dirty, (face.age + 1) as f32 / distance)
+			})
+			.collect::<Vec<_>>();
+		// dirty before clean, then the highest priority first
+		candidates.sort_by(|a, b| b.1.cmp(&a.1).then(b.2.total_cmp(&a.2)));
+		candidates.truncate(self.faces_per_frame);
+
+		for face in &mut self.faces {
+			face.age = face.age.saturating_add(1);
+		}
+		candidates.into_iter()
+			.map(|(index, ..)| {
+				self.faces[index] = FaceState { dirty: false, age: 0 };
+				index
+			})
+			.collect()
+	}
+}
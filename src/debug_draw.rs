@@ -0,0 +1,341 @@
+use wgpu::util::DeviceExt;
+
+use cgmath::InnerSpace;
+
+use crate::{camera, light, model, scene, texture};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct DebugVertex {
+	position: [f32; 3],
+	color: [f32; 4],
+}
+
+gpu_struct! {
+	Uniform
+	#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+	struct DebugParams {
+		view_proj: [[f32; 4]; 4],
+		eye: [f32; 4], // w is the depth bias
+		color_scale: [f32; 4],
+	}
+}
+
+// how the debug layer is composited with the scene
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GizmoDepth {
+	Overlay, // on top of everything
+	Tested, // hidden behind the scene
+	XRay, // behind the scene too, but dimmed and stippled
+}
+
+// the attachments the lines are drawn into, scene_depth is false when depth_view doesn't hold
+// this frame's depth, which draws them as an overlay whatever the depth mode
+pub struct DrawTarget<'a> {
+	pub view: &'a wgpu::TextureView,
+	pub depth_view: &'a wgpu::TextureView,
+	pub scene_depth: bool,
+}
+
+/*
+Immediate mode layer for gizmos and debug shapes. Lines are queued during the frame and drawn
+after the post passes, tested against the scene depth buffer. The layer has its own camera
+uniform built from the unjittered camera, so it doesn't shimmer with the interlaced projection,
+and pulls lines towards the camera by depth_bias of their distance so they don't z-fight the
+surfaces they outline. The queue is cleared after every frame. The helpers draw the lights, the
+objects' bounding boxes, and the world axes every frame while they're shown.
+Interlaced frames have no full resolution depth, so the layer draws as an overlay there.
+*/
+pub struct DebugDraw {
+	pub depth: GizmoDepth,
+	pub depth_bias: f32, // fraction of the distance to the camera
+	pub occluded_color: [f32; 4], // multiplier for the hidden parts in x-ray mode
+	pub show_lights: bool,
+	pub show_bounds: bool,
+	pub show_axes: bool,
+	vertices: Vec<DebugVertex>,
+	vertex_buffer: wgpu::Buffer,
+	capacity: usize, // in vertices
+	params_buffer: wgpu::Buffer,
+	bind_group: wgpu::BindGroup,
+	visible_pipeline: wgpu::RenderPipeline,
+	occluded_pipeline: wgpu::RenderPipeline,
+	overlay_pipeline: wgpu::RenderPipeline,
+}
+
+impl DebugDraw {
+	const INITIAL_CAPACITY: usize = 1024;
+
+	pub fn new(device: &wgpu::Device, color_format: wgpu::TextureFormat) -> Self {
+		let vertex_buffer = Self::create_vertex_buffer(device, Self::INITIAL_CAPACITY);
+		let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Debug Draw Params Buffer"),
+			contents: bytemuck::cast_slice(&[DebugParams {
+				view_proj: <cgmath::Matrix4<f32> as cgmath::SquareMatrix>::identity().into(),
+				eye: [0.0; 4],
+				color_scale: [1.0; 4],
+				..bytemuck::Zeroable::zeroed()
+			}]),
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+		});
+		let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("debug_draw_bind_group_layout"),
+			entries: &[
+				wgpu::BindGroupLayoutEntry {
+					binding: 0,
+					visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+			],
+		});
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("debug_draw_bind_group"),
+			layout: &layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: params_buffer.as_entire_binding(),
+				},
+			],
+		});
+
+		let shader = device.create_shader_module(wgpu::include_wgsl!("debug_draw.wgsl"));
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Debug Draw Pipeline Layout"),
+			bind_group_layouts: &[Some(&layout)],
+			immediate_size: 0,
+		});
+		let create_pipeline = |label, fragment_entry, depth_compare| device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some(label),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: Some("vs_main"),
+				buffers: &[wgpu::VertexBufferLayout {
+					array_stride: std::mem::size_of::<DebugVertex>() as wgpu::BufferAddress,
+					step_mode: wgpu::VertexStepMode::Vertex,
+					attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4],
+				}],
+				compilation_options: Default::default(),
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: Some(fragment_entry),
+				targets: &[Some(wgpu::ColorTargetState {
+					format: color_format,
+					blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+					write_mask: wgpu::ColorWrites::COLOR,
+				})],
+				compilation_options: Default::default(),
+			}),
+			primitive: wgpu::PrimitiveState {
+				topology: wgpu::PrimitiveTopology::LineList,
+				..Default::default()
+			},
+			depth_stencil: Some(wgpu::DepthStencilState {
+				format: texture::Texture::DEPTH_FORMAT,
+				depth_write_enabled: Some(false),
+				depth_compare: Some(depth_compare),
+				stencil: wgpu::StencilState::default(),
+				bias: wgpu::DepthBiasState::default(),
+			}),
+			multisample: wgpu::MultisampleState::default(),
+			multiview_mask: None,
+			cache: None,
+		});
+		let visible_pipeline = create_pipeline("Debug Draw Pipeline", "fs_main", wgpu::CompareFunction::LessEqual);
+		let occluded_pipeline = create_pipeline("Debug Draw Occluded Pipeline", "fs_occluded", wgpu::CompareFunction::Greater);
+		let overlay_pipeline = create_pipeline("Debug Draw Overlay Pipeline", "fs_main", wgpu::CompareFunction::Always);
+
+		Self {
+			depth: GizmoDepth::XRay,
+			depth_bias: 0.002,
+			occluded_color: [1.0, 1.0, 1.0, 0.4],
+			show_lights: false,
+			show_bounds: false,
+			show_axes: false,
+			vertices: vec![],
+			vertex_buffer,
+			capacity: Self::INITIAL_CAPACITY,
+			params_buffer,
+			bind_group,
+			visible_pipeline,
+			occluded_pipeline,
+			overlay_pipeline,
+		}
+	}
+
+	fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+		device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Debug Draw Vertex Buffer"),
+			size: (capacity * std::mem::size_of::<DebugVertex>()) as u64,
+			usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		})
+	}
+
+	// queues a line for this frame, color is linear rgba
+	pub fn line(&mut self, a: cgmath::Point3<f32>, b: cgmath::Point3<f32>, color: [f32; 4]) {
+		self.vertices.push(DebugVertex { position: a.into(), color });
+		self.vertices.push(DebugVertex { position: b.into(), color });
+	}
+
+	pub fn aabb(&mut self, aabb: &model::Aabb, color: [f32; 4]) {
+		let corners = aabb.corners();
+		// corners differing in one axis bit share an edge
+		for (i, &corner) in corners.iter().enumerate() {
+			for bit in [1, 2, 4] {
+				if i & bit == 0 {
+					self.line(corner, corners[i | bit], color);
+				}
+			}
+		}
+	}
+
+	// a circle around each axis
+	pub fn sphere(&mut self, center: cgmath::Point3<f32>, radius: f32, color: [f32; 4]) {
+		let x = cgmath::Vector3::unit_x() * radius;
+		let y = cgmath::Vector3::unit_y() * radius;
+		let z = cgmath::Vector3::unit_z() * radius;
+		self.circle(center, x, y, color);
+		self.circle(center, y, z, color);
+		self.circle(center, z, x, color);
+	}
+
+	// the circle through center + u and center + v
+	pub fn circle(&mut self, center: cgmath::Point3<f32>, u: cgmath::Vector3<f32>, v: cgmath::Vector3<f32>, color: [f32; 4]) {
+		const SEGMENTS: usize = 24;
+		let point = |i: usize| {
+			let angle = i as f32 / SEGMENTS as f32 * std::f32::consts::TAU;
+			center + u * angle.cos() + v * angle.sin()
+		};
+		for i in 0..SEGMENTS {
+			self.line(point(i), point(i + 1), color);
+		}
+	}
+
+	// x red, y green, z blue
+	pub fn axes(&mut self, origin: cgmath::Point3<f32>, size: f32) {
+		self.line(origin, origin + cgmath::Vector3::unit_x() * size, [1.0, 0.2, 0.2, 1.0]);
+		self.line(origin, origin + cgmath::Vector3::unit_y() * size, [0.2, 1.0, 0.2, 1.0]);
+		self.line(origin, origin + cgmath::Vector3::unit_z() * size, [0.2, 0.4, 1.0, 1.0]);
+	}
+
+	/*
+	A light in its color: a point light's position in a small sphere with a faint one at its
+	range, a spot light's cone out to its range, and a directional light's direction from its
+	position, which only places the arrow.
+	*/
+	pub fn light(&mut self, light: &light::Light) {
+		let [r, g, b] = light.color;
+		let color = [r, g, b, 1.0];
+		let faint = [r, g, b, 0.25];
+		let position = cgmath::Point3::from(light.position);
+		let direction = cgmath::Vector3::from(light.direction);
+		let direction = if direction.magnitude2() > 0.0 { direction.normalize() } else { -cgmath::Vector3::unit_y() };
+		match light.kind {
+			light::LightKind::Point => {
+				self.sphere(position, 0.1, color);
+				self.sphere(position, light.range, faint);
+			}
+			light::LightKind::Spot { outer_angle, .. } => {
+				self.sphere(position, 0.1, color);
+				let up = if direction.y.abs() < 0.99 { cgmath::Vector3::unit_y() } else { cgmath::Vector3::unit_x() };
+				let u = direction.cross(up).normalize();
+				let v = direction.cross(u);
+				let (end, radius) = (position + direction * light.range, light.range * outer_angle.tan());
+				self.circle(end, u * radius, v * radius, faint);
+				for edge in [u, v, -u, -v] {
+					self.line(position, end + edge * radius, faint);
+				}
+			}
+			light::LightKind::Directional => {
+				let end = position + direction;
+				self.line(position, end, color);
+				let side = direction.cross(if direction.y.abs() < 0.99 { cgmath::Vector3::unit_y() } else { cgmath::Vector3::unit_x() }).normalize();
+				self.line(end, end - direction * 0.2 + side * 0.1, color);
+				self.line(end, end - direction * 0.2 - side * 0.1, color);
+			}
+		}
+	}
+
+	// queues the helpers that are shown, after the scene's transforms are updated
+	pub fn draw_helpers(&mut self, scene: &scene::Scene) {
+		if self.show_axes {
+			self.axes(cgmath::Point3::new(0.0, 0.0, 0.0), 1.0);
+		}
+		if self.show_lights {
+			for light in scene.lights() {
+				self.light(light);
+			}
+		}
+		if self.show_bounds {
+			for index in 0..scene.objects.len() {
+				self.aabb(&scene.object_aabb(index), [1.0, 0.8, 0.2, 1.0]);
+			}
+		}
+	}
+
+	// draws the queued lines into the target and clears the queue
+	pub fn draw(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, camera: &camera::Camera, target: DrawTarget) {
+		let DrawTarget { view, depth_view, scene_depth } = target;
+		if self.vertices.is_empty() {
+			return;
+		}
+		if self.vertices.len() > self.capacity {
+			self.capacity = self.vertices.len().next_power_of_two();
+			self.vertex_buffer = Self::create_vertex_buffer(device, self.capacity);
+		}
+		queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+		queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[DebugParams {
+			view_proj: camera.build_view_projection_matrix().into(),
+			eye: [camera.eye.x, camera.eye.y, camera.eye.z, self.depth_bias],
+			color_scale: self.occluded_color,
+			..bytemuck::Zeroable::zeroed()
+		}]));
+
+		{
+			let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+				label: Some("Debug Draw Pass"),
+				color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+					view,
+					resolve_target: None,
+					ops: wgpu::Operations {
+						load: wgpu::LoadOp::Load,
+						store: wgpu::StoreOp::Store,
+					},
+					depth_slice: None,
+				})],
+				depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+					view: depth_view,
+					depth_ops: Some(wgpu::Operations {
+						load: if scene_depth { wgpu::LoadOp::Load } else { wgpu::LoadOp::Clear(1.0) },
+						store: wgpu::StoreOp::Store,
+					}),
+					stencil_ops: None,
+				}),
+				occlusion_query_set: None,
+				timestamp_writes: None,
+				multiview_mask: None,
+			});
+			render_pass.set_bind_group(0, &self.bind_group, &[]);
+			render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+			let count = self.vertices.len() as u32;
+			let pipelines = match (scene_depth, self.depth) {
+				(false, _) | (true, GizmoDepth::Overlay) => vec![&self.overlay_pipeline],
+				(true, GizmoDepth::Tested) => vec![&self.visible_pipeline],
+				(true, GizmoDepth::XRay) => vec![&self.occluded_pipeline, &self.visible_pipeline],
+			};
+			for pipeline in pipelines {
+				render_pass.set_pipeline(pipeline);
+				render_pass.draw(0..count, 0..1);
+			}
+		}
+		self.vertices.clear();
+	}
+}
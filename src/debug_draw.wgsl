@@ -0,0 +1,39 @@
+// Lines of the debug draw layer, composited against the scene depth
+
+struct Params {
+	view_proj: mat4x4<f32>,
+	eye: vec4<f32>, // xyz camera position, w depth bias
+	color_scale: vec4<f32>, // applied to lines behind the scene in x-ray mode
+};
+@group(0) @binding(0)
+var<uniform> params: Params;
+
+struct VertexOutput {
+	@builtin(position) clip_position: vec4<f32>,
+	@location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>, @location(1) color: vec4<f32>) -> VertexOutput {
+	// pulled towards the camera by a fraction of the distance, lines can't use the pipeline's depth bias
+	let biased = mix(position, params.eye.xyz, params.eye.w);
+	var out: VertexOutput;
+	out.clip_position = params.view_proj * vec4<f32>(biased, 1.0);
+	out.color = color;
+	return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	return in.color;
+}
+
+// the parts hidden by the scene, dimmed and stippled so they read as behind it
+@fragment
+fn fs_occluded(in: VertexOutput) -> @location(0) vec4<f32> {
+	let pixel = vec2<u32>(in.clip_position.xy);
+	if ((pixel.x + pixel.y) / 3u) % 2u == 0u {
+		discard;
+	}
+	return in.color * params.color_scale;
+}
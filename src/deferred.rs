@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+
+use crate::{bind_group, model::{self, Vertex}, renderer, texture};
+
+// how the main view lights its opaque meshes, chosen when the renderer is created
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum RenderPath {
+	#[default]
+	Forward, // every mesh loops over the lights as it's drawn
+	Deferred, // opaque meshes write a G-buffer, lit once per pixel afterwards
+}
+
+// which of the scene's draws a pass makes
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ScenePass {
+	Forward, // everything, lit as it's drawn
+	GBuffer, // the opaque meshes, into the G-buffer
+	Composite, // the rest over the lit G-buffer, imposters, the sky, and blended meshes
+}
+
+struct GBuffer {
+	normal: texture::Texture, // world normal, emissive in w
+	albedo: texture::Texture, // reflection fresnel in a
+	specular: texture::Texture, // shininess in w
+	params: texture::Texture, // illumination model, shadow flags, shadow bias
+}
+
+impl GBuffer {
+	const NORMAL_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+	const ALBEDO_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+	const SPECULAR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+	const PARAMS_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Uint;
+
+	fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> Self {
+		let target = |format, label| texture::Texture::create_render_target(device, config, format, wgpu::TextureUsages::RENDER_ATTACHMENT, label);
+		Self {
+			normal: target(Self::NORMAL_FORMAT, "gbuffer_normal"),
+			albedo: target(Self::ALBEDO_FORMAT, "gbuffer_albedo"),
+			specular: target(Self::SPECULAR_FORMAT, "gbuffer_specular"),
+			params: target(Self::PARAMS_FORMAT, "gbuffer_params"),
+		}
+	}
+}
+
+/*
+The deferred render path of the full shader tier. The material shaders' fs_gbuffer entry point
+writes what light_surface needs into the G-buffer, along with the surface target the reflection
+pass reads, then one full screen pass lights every pixel with the same light_surface, so the
+light loop runs once per pixel instead of once per overlapping mesh. Blended meshes, imposters,
+and the sky still draw forward over the result. The lighting pass binds the material pipelines'
+image based lighting, uniform, and shadow layouts, with the G-buffer in place of the material textures.
+Lights aren't culled per screen tile, every pixel loops over all of them.
+*/
+pub struct Deferred {
+	targets: GBuffer,
+	layout: wgpu::BindGroupLayout,
+	bind_group: wgpu::BindGroup,
+	lighting_pipeline: wgpu::RenderPipeline,
+	pipelines: HashMap<(usize, model::CullMode), wgpu::RenderPipeline>, // by material type and cull mode
+}
+
+impl Deferred {
+	pub fn new(
+		device: &wgpu::Device,
+		config: &wgpu::SurfaceConfiguration,
+		lighting_shader: wgpu::ShaderModuleDescriptor,
+		depth_texture: &texture::Texture,
+		ibl_layout: &wgpu::BindGroupLayout,
+		uniform_layout: &wgpu::BindGroupLayout,
+		shadow_layout: &wgpu::BindGroupLayout,
+	) -> Self {
+		let targets = GBuffer::new(device, config);
+
+		let unfilterable = wgpu::TextureSampleType::Float { filterable: false };
+		let layout = bind_group::LayoutBuilder::new(wgpu::ShaderStages::FRAGMENT)
+			.texture_of(1, unfilterable, wgpu::TextureViewDimension::D2) // normal
+			.texture_of(2, unfilterable, wgpu::TextureViewDimension::D2) // albedo
+			.texture_of(3, unfilterable, wgpu::TextureViewDimension::D2) // specular
+			.texture_of(4, wgpu::TextureSampleType::Uint, wgpu::TextureViewDimension::D2) // params
+			.texture_of(5, wgpu::TextureSampleType::Depth, wgpu::TextureViewDimension::D2)
+			.build(device, "gbuffer_bind_group_layout");
+		let bind_group = Self::create_bind_group(device, &layout, &targets, depth_texture);
+
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Deferred Lighting Pipeline Layout"),
+			bind_group_layouts: &[Some(&layout), Some(ibl_layout), Some(uniform_layout), Some(shadow_layout)],
+			immediate_size: 0,
+		});
+		let shader = device.create_shader_module(lighting_shader);
+		let lighting_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("Deferred Lighting Pipeline"),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: Some("vs_fullscreen"),
+				buffers: &[],
+				compilation_options: Default::default(),
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: Some("fs_lighting"),
+				targets: &[Some(wgpu::ColorTargetState {
+					format: config.format,
+					blend: None,
+					write_mask: wgpu::ColorWrites::ALL,
+				})],
+				compilation_options: Default::default(),
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			multiview_mask: None,
+			cache: None,
+		});
+
+		Self {
+			targets,
+			layout,
+			bind_group,
+			lighting_pipeline,
+			pipelines: HashMap::new(),
+		}
+	}
+
+	fn create_bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, targets: &GBuffer, depth_texture: &texture::Texture) -> wgpu::BindGroup {
+		bind_group::BindGroupBuilder::default()
+			.view(1, &targets.normal.view)
+			.view(2, &targets.albedo.view)
+			.view(3, &targets.specular.view)
+			.view(4, &targets.params.view)
+			.view(5, &depth_texture.view)
+			.build(device, layout, "gbuffer_bind_group")
+	}
+
+	// the targets follow the internal resolution, like the depth texture they're read with
+	pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, depth_texture: &texture::Texture) {
+		self.targets = GBuffer::new(device, config);
+		self.bind_group = Self::create_bind_group(device, &self.layout, &self.targets, depth_texture);
+	}
+
+	// G-buffer pipelines for a material type, from the shader and layout of its forward pipelines
+	pub fn add_material(&mut self, device: &wgpu::Device, material_type: usize, name: &str, layout: &wgpu::PipelineLayout, shader: &wgpu::ShaderModule) {
+		let targets = [
+			GBuffer::NORMAL_FORMAT,
+			GBuffer::ALBEDO_FORMAT,
+			GBuffer::SPECULAR_FORMAT,
+			GBuffer::PARAMS_FORMAT,
+			renderer::Renderer::SURFACE_FORMAT,
+		].map(|format| Some(wgpu::ColorTargetState {
+			format,
+			blend: None,
+			write_mask: wgpu::ColorWrites::ALL,
+		}));
+		for cull in model::CullMode::ALL {
+			let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+				label: Some(&format!("{} {:?} G-buffer Pipeline", name, cull)),
+				layout: Some(layout),
+				vertex: wgpu::VertexState {
+					module: shader,
+					entry_point: Some("vs_main"),
+					buffers: &[model::ModelVertex::desc()],
+					compilation_options: Default::default(),
+				},
+				fragment: Some(wgpu::FragmentState {
+					module: shader,
+					entry_point: Some("fs_gbuffer"),
+					targets: &targets,
+					compilation_options: Default::default(),
+				}),
+				primitive: wgpu::PrimitiveState {
+					cull_mode: cull.face(),
+					..Default::default()
+				},
+				depth_stencil: Some(wgpu::DepthStencilState {
+					format: texture::Texture::DEPTH_FORMAT,
+					depth_write_enabled: Some(true),
+					depth_compare: Some(wgpu::CompareFunction::Less),
+					stencil: wgpu::StencilState::default(),
+					bias: wgpu::DepthBiasState::default(),
+				}),
+				multisample: wgpu::MultisampleState::default(),
+				multiview_mask: None,
+				cache: None,
+			});
+			self.pipelines.insert((material_type, cull), pipeline);
+		}
+	}
+
+	// only opaque pipelines have a G-buffer version, blended meshes are drawn in the composite pass
+	pub fn pipeline(&self, key: model::PipelineKey) -> &wgpu::RenderPipeline {
+		&self.pipelines[&(key.material_type, key.cull)]
+	}
+
+	// the G-buffer pass's color attachments, cleared, with the surface target last
+	pub fn attachments<'a>(&'a self, surface_view: &'a wgpu::TextureView) -> [Option<wgpu::RenderPassColorAttachment<'a>>; 5] {
+		[&self.targets.normal.view, &self.targets.albedo.view, &self.targets.specular.view, &self.targets.params.view, surface_view].map(|view| Some(wgpu::RenderPassColorAttachment {
+			view,
+			resolve_target: None,
+			ops: wgpu::Operations {
+				load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+				store: wgpu::StoreOp::Store,
+			},
+			depth_slice: None,
+		}))
+	}
+
+	// lights the G-buffer into view, pixels nothing was drawn to keep the clear color
+	pub fn light(
+		&self,
+		encoder: &mut wgpu::CommandEncoder,
+		view: &wgpu::TextureView,
+		clear_color: wgpu::Color,
+		ibl_bind_group: &wgpu::BindGroup,
+		uniform_bind_group: &wgpu::BindGroup,
+		shadow_bind_group: &wgpu::BindGroup,
+	) {
+		let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Deferred Lighting Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Clear(clear_color),
+					store: wgpu::StoreOp::Store,
+				},
+				depth_slice: None,
+			})],
+			depth_stencil_attachment: None,
+			occlusion_query_set: None,
+			timestamp_writes: None,
+			multiview_mask: None,
+		});
+		render_pass.set_pipeline(&self.lighting_pipeline);
+		render_pass.set_bind_group(0, &self.bind_group, &[]);
+		render_pass.set_bind_group(1, ibl_bind_group, &[]);
+		// the model uniforms aren't read, any offset does
+		render_pass.set_bind_group(2, uniform_bind_group, &[0]);
+		render_pass.set_bind_group(3, shadow_bind_group, &[]);
+		render_pass.draw(0..3, 0..1);
+	}
+}
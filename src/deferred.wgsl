@@ -0,0 +1,53 @@
+// Full screen lighting of the G-buffer for the deferred render path. Appended to the full tier's
+// mesh, output, lights, and shadows files so it lights with the same light_surface as the
+// forward path, the G-buffer takes the place of the material textures in group 0.
+
+@group(0) @binding(1)
+var gbuffer_normal: texture_2d<f32>;
+@group(0) @binding(2)
+var gbuffer_albedo: texture_2d<f32>;
+@group(0) @binding(3)
+var gbuffer_specular: texture_2d<f32>;
+@group(0) @binding(4)
+var gbuffer_params: texture_2d<u32>;
+@group(0) @binding(5)
+var gbuffer_depth: texture_depth_2d;
+
+@vertex
+fn vs_fullscreen(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+	// one triangle covering the screen
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_lighting(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
+	let pixel = vec2<i32>(frag_coord.xy);
+	let depth = textureLoad(gbuffer_depth, pixel, 0);
+	// the sky is drawn over what's left afterwards
+	if depth >= 1.0 {
+		discard;
+	}
+	let uv = frag_coord.xy / vec2<f32>(textureDimensions(gbuffer_depth));
+	let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+	let world = camera.inv_view_proj * ndc;
+
+	let normal = textureLoad(gbuffer_normal, pixel, 0);
+	let albedo = textureLoad(gbuffer_albedo, pixel, 0);
+	let specular = textureLoad(gbuffer_specular, pixel, 0);
+	let params = textureLoad(gbuffer_params, pixel, 0);
+	model.shadow_flags = params.y;
+	model.shadow_bias = unpack2x16float(params.z).x;
+	let surface = SurfaceParams(
+		world.xyz / world.w,
+		normalize(normal.xyz),
+		normalize(normal.xyz),
+		albedo.xyz,
+		normal.w,
+		albedo.w,
+		specular.xyz,
+		specular.w,
+		params.x,
+	);
+	return vec4<f32>(light_surface(surface), 1.0);
+}
@@ -0,0 +1,36 @@
+@group(0) @binding(1)
+var diffuse_texture: texture_2d<f32>;
+@group(0) @binding(2)
+var diffuse_sampler: sampler;
+@group(0) @binding(3)
+var normal_texture: texture_2d<f32>;
+@group(0) @binding(4)
+var normal_sampler: sampler;
+@group(0) @binding(5)
+var detail_diffuse_texture: texture_2d<f32>;
+@group(0) @binding(6)
+var detail_diffuse_sampler: sampler;
+@group(0) @binding(7)
+var detail_normal_texture: texture_2d<f32>;
+@group(0) @binding(8)
+var detail_normal_sampler: sampler;
+
+fn material_surface(in: VertexOutput) -> MaterialSurface {
+	let detail_tiling = material.detail.x;
+
+	let base_col = sample_material_texture(diffuse_texture, diffuse_sampler, in, 1.0);
+	let detail_col = sample_material_texture(detail_diffuse_texture, detail_diffuse_sampler, in, detail_tiling).xyz;
+	let base_norm = sample_material_normal(normal_texture, normal_sampler, in, 1.0);
+	let detail_norm = sample_material_normal(detail_normal_texture, detail_normal_sampler, in, detail_tiling);
+
+	// detail fades out with distance so it doesn't alias far away
+	let dist = distance(camera.position.xyz, in.position);
+	let detail_weight = (1.0 - smoothstep(material.detail.y, material.detail.z, dist)) * material.detail.w;
+
+	// detail color is centered on mid grey, so 0.5 leaves the base color unchanged
+	let obj_col = vec4<f32>(base_col.xyz * mix(vec3<f32>(1.0), detail_col * 2.0, detail_weight), base_col.w);
+	// add the detail normal's deviation from the surface onto the base normal
+	let obj_norm = normalize(base_norm + (detail_norm - normalize(in.normal)) * detail_weight);
+
+	return MaterialSurface(obj_col, obj_norm);
+}
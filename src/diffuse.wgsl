@@ -0,0 +1,11 @@
+@group(0) @binding(1)
+var diffuse_texture: texture_2d<f32>;
+@group(0) @binding(2)
+var diffuse_sampler: sampler;
+
+fn material_surface(in: VertexOutput) -> MaterialSurface {
+	let obj_col = sample_material_texture(diffuse_texture, diffuse_sampler, in, 1.0);
+	let obj_norm = normalize(in.normal);
+
+	return MaterialSurface(obj_col, obj_norm);
+}
@@ -0,0 +1,264 @@
+use crate::{camera, light};
+
+// curve that maps lit color into the display range, applied after exposure and fog
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Tonemap {
+	None, // clamped by the output
+	Reinhard,
+	Aces, // Narkowicz's fit
+}
+
+impl Tonemap {
+	// as the shaders number them
+	pub fn index(self) -> u32 {
+		match self {
+			Tonemap::None => 0,
+			Tonemap::Reinhard => 1,
+			Tonemap::Aces => 2,
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Fog {
+	pub color: [f32; 3],
+	pub density: f32, // 0 disables fog
+	pub start: f32, // distance from the camera where fog begins
+}
+
+// wet surfaces darken and turn glossy, snow settles on the ones facing up, on every lit material
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Weather {
+	pub wetness: f32, // 0 dry to 1 soaked
+	pub snow: f32, // 0 none to 1 covering everything facing above the horizon
+}
+
+// sways the vertices of materials with wind_height set, see SimpleMaterial::wind
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Wind {
+	pub direction: [f32; 2], // along x and z, normalized in the shader
+	pub strength: f32, // sway in meters at the top of a material with a stiffness of 1, 0 is calm
+}
+
+impl Default for Wind {
+	fn default() -> Self {
+		Self {
+			direction: [1.0, 0.0],
+			strength: 0.0,
+		}
+	}
+}
+
+pub struct Environment {
+	pub clear_color: [f32; 3],
+	pub ambient_color: [f32; 3],
+	pub ambient_intensity: f32,
+	pub exposure: f32, // multiplier on lit color, see light::ev100_to_exposure for physical units
+	pub tonemap: Tonemap,
+	pub fog: Fog,
+	pub weather: Weather,
+	pub wind: Wind,
+}
+
+impl Default for Environment {
+	fn default() -> Self {
+		Self {
+			clear_color: [0.1, 0.2, 0.3],
+			ambient_color: [1.0, 1.0, 1.0],
+			ambient_intensity: 0.1,
+			exposure: 1.0,
+			tonemap: Tonemap::None,
+			fog: Fog {
+				color: [0.1, 0.2, 0.3],
+				density: 0.0,
+				start: 0.0,
+			},
+			weather: Weather::default(),
+			wind: Wind::default(),
+		}
+	}
+}
+
+impl Environment {
+	pub fn clear_color(&self) -> wgpu::Color {
+		wgpu::Color {
+			r: self.clear_color[0] as f64,
+			g: self.clear_color[1] as f64,
+			b: self.clear_color[2] as f64,
+			a: 1.0,
+		}
+	}
+
+	// the exposure and tonemap of a view, the camera's overrides take the place of the scene's grading
+	pub fn grading(&self, view: &camera::ViewSettings) -> (f32, Tonemap) {
+		(view.exposure.unwrap_or(self.exposure), view.tonemap.unwrap_or(self.tonemap))
+	}
+
+	// graded in the shaders, for views that render straight into a displayable target
+	pub fn to_uniform(&self, view: &camera::ViewSettings) -> EnvironmentUniform {
+		let (exposure, tonemap) = self.grading(view);
+		self.uniform(exposure, tonemap)
+	}
+
+	// left linear, for a view the output stage grades, see RenderScale
+	pub fn to_linear_uniform(&self) -> EnvironmentUniform {
+		self.uniform(1.0, Tonemap::None)
+	}
+
+	fn uniform(&self, exposure: f32, tonemap: Tonemap) -> EnvironmentUniform {
+		EnvironmentUniform {
+			fog_color: self.fog.color,
+			fog_density: self.fog.density,
+			ambient_color: self.ambient_color,
+			ambient_intensity: self.ambient_intensity,
+			fog_start: self.fog.start,
+			exposure,
+			tonemap: tonemap.index(),
+			wetness: self.weather.wetness.clamp(0.0, 1.0),
+			snow: self.weather.snow.clamp(0.0, 1.0),
+			wind_strength: self.wind.strength,
+			wind_direction: self.wind.direction,
+			..bytemuck::Zeroable::zeroed()
+		}
+	}
+}
+
+/*
+A named look for a scene, like a time of day, switched between with
+Scene::apply_lighting_preset. The sun is the scene's first directional light, placed by its
+elevation above the horizon and its azimuth clockwise from -z, both in degrees.
+*/
+#[derive(Clone, Debug)]
+pub struct LightingPreset {
+	pub name: String,
+	pub sun_elevation: f32,
+	pub sun_azimuth: f32,
+	pub sun_color: [f32; 3],
+	pub sun_illuminance: f32,
+	pub clear_color: [f32; 3],
+	pub ambient_color: [f32; 3],
+	pub ambient_intensity: f32,
+	pub exposure: f32,
+	pub fog: Fog,
+}
+
+impl LightingPreset {
+	// the scene's current look, to switch back to
+	pub fn capture(name: &str, environment: &Environment, sun: Option<&light::Light>) -> Self {
+		let (sun_elevation, sun_azimuth, sun_color, sun_illuminance) = match sun {
+			Some(sun) => {
+				// the direction points from the sun
+				use cgmath::InnerSpace;
+				let [x, y, z]: [f32; 3] = cgmath::Vector3::from(sun.direction).normalize().into();
+				(
+					(-y).clamp(-1.0, 1.0).asin().to_degrees(),
+					(-x).atan2(z).to_degrees().rem_euclid(360.0),
+					sun.color,
+					sun.intensity,
+				)
+			}
+			None => (90.0, 0.0, [1.0; 3], 0.0),
+		};
+		Self {
+			name: name.to_string(),
+			sun_elevation,
+			sun_azimuth,
+			sun_color,
+			sun_illuminance,
+			clear_color: environment.clear_color,
+			ambient_color: environment.ambient_color,
+			ambient_intensity: environment.ambient_intensity,
+			exposure: environment.exposure,
+			fog: environment.fog,
+		}
+	}
+
+	// where the sun's light travels, from the sun towards the ground
+	pub fn sun_direction(&self) -> [f32; 3] {
+		let (elevation, azimuth) = (self.sun_elevation.to_radians(), self.sun_azimuth.to_radians());
+		[-elevation.cos() * azimuth.sin(), -elevation.sin(), elevation.cos() * azimuth.cos()]
+	}
+
+	pub fn apply(&self, environment: &mut Environment, sun: &mut light::Light) {
+		sun.direction = self.sun_direction();
+		sun.color = self.sun_color;
+		sun.intensity = self.sun_illuminance;
+		environment.clear_color = self.clear_color;
+		environment.ambient_color = self.ambient_color;
+		environment.ambient_intensity = self.ambient_intensity;
+		environment.exposure = self.exposure;
+		environment.fog = self.fog;
+	}
+
+	// what a new scene can switch between
+	pub fn defaults() -> Vec<Self> {
+		vec![
+			Self {
+				name: "morning".to_string(),
+				sun_elevation: 12.0,
+				sun_azimuth: 90.0,
+				sun_color: light::kelvin_to_rgb(3500.0),
+				sun_illuminance: 1.5,
+				clear_color: [0.75, 0.6, 0.5],
+				ambient_color: [0.6, 0.7, 0.9],
+				ambient_intensity: 0.15,
+				exposure: 1.2,
+				fog: Fog {
+					color: [0.75, 0.65, 0.6],
+					density: 0.01,
+					start: 5.0,
+				},
+			},
+			Self {
+				name: "noon".to_string(),
+				sun_elevation: 70.0,
+				sun_azimuth: 180.0,
+				sun_color: light::kelvin_to_rgb(6000.0),
+				sun_illuminance: 3.0,
+				clear_color: [0.4, 0.6, 0.9],
+				ambient_color: [0.7, 0.8, 1.0],
+				ambient_intensity: 0.2,
+				exposure: 0.8,
+				fog: Fog {
+					color: [0.4, 0.6, 0.9],
+					density: 0.0,
+					start: 0.0,
+				},
+			},
+			Self {
+				name: "night".to_string(),
+				sun_elevation: 35.0,
+				sun_azimuth: 220.0,
+				sun_color: light::kelvin_to_rgb(8000.0),
+				sun_illuminance: 0.15,
+				clear_color: [0.01, 0.015, 0.04],
+				ambient_color: [0.3, 0.35, 0.6],
+				ambient_intensity: 0.05,
+				exposure: 3.0,
+				fog: Fog {
+					color: [0.01, 0.015, 0.04],
+					density: 0.02,
+					start: 2.0,
+				},
+			},
+		]
+	}
+}
+
+gpu_struct! {
+	Uniform
+	#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+	pub struct EnvironmentUniform {
+		fog_color: [f32; 3],
+		fog_density: f32,
+		ambient_color: [f32; 3],
+		ambient_intensity: f32,
+		fog_start: f32,
+		exposure: f32,
+		tonemap: u32, // Tonemap::index
+		wetness: f32,
+		snow: f32,
+		wind_strength: f32,
+		wind_direction: [f32; 2],
+	}
+}
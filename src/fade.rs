@@ -0,0 +1,127 @@
+use wgpu::util::DeviceExt;
+
+// full screen pass that blends a color over the finished frame, and letterboxes it
+pub struct FadePass {
+	pub color: [f32; 4], // alpha is how far the fade is, 0 skips the pass
+	pub letterbox: Option<f32>, // aspect ratio of the picture between black bars, for cinematics
+	uploaded: [f32; 8], // the color, then the rows the bars end and start at
+
+	buffer: wgpu::Buffer,
+	bind_group: wgpu::BindGroup,
+	pipeline: wgpu::RenderPipeline,
+}
+
+impl FadePass {
+	pub fn new(device: &wgpu::Device, color_format: wgpu::TextureFormat) -> Self {
+		let uploaded = [0.0; 8];
+		let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Fade Buffer"),
+			contents: bytemuck::cast_slice(&uploaded),
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+		});
+		let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("fade_bind_group_layout"),
+			entries: &[
+				wgpu::BindGroupLayoutEntry {
+					binding: 0,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+			],
+		});
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("fade_bind_group"),
+			layout: &layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: buffer.as_entire_binding(),
+				},
+			],
+		});
+
+		let shader = device.create_shader_module(wgpu::include_wgsl!("fade.wgsl"));
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Fade Pipeline Layout"),
+			bind_group_layouts: &[Some(&layout)],
+			immediate_size: 0,
+		});
+		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("Fade Pipeline"),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: Some("vs_main"),
+				buffers: &[],
+				compilation_options: Default::default(),
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: Some("fs_main"),
+				targets: &[Some(wgpu::ColorTargetState {
+					format: color_format,
+					blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+					write_mask: wgpu::ColorWrites::COLOR,
+				})],
+				compilation_options: Default::default(),
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			multiview_mask: None,
+			cache: None,
+		});
+
+		Self {
+			color: [0.0; 4],
+			letterbox: None,
+			uploaded,
+			buffer,
+			bind_group,
+			pipeline,
+		}
+	}
+
+	pub fn draw(&mut self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, size: [u32; 2]) {
+		let [width, height] = size.map(|size| size as f32);
+		// bars above and below when the screen is taller than the picture
+		let bar = match self.letterbox {
+			Some(aspect) if aspect > 0.0 => ((height - width / aspect) * 0.5).max(0.0),
+			_ => 0.0,
+		};
+		if self.color[3] <= 0.0 && bar <= 0.0 {
+			return;
+		}
+		let [r, g, b, a] = self.color;
+		let uniform = [r, g, b, a, bar, height - bar, 0.0, 0.0];
+		if uniform != self.uploaded {
+			queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&uniform));
+			self.uploaded = uniform;
+		}
+
+		let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Fade Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: wgpu::StoreOp::Store,
+				},
+				depth_slice: None,
+			})],
+			depth_stencil_attachment: None,
+			occlusion_query_set: None,
+			timestamp_writes: None,
+			multiview_mask: None,
+		});
+		render_pass.set_pipeline(&self.pipeline);
+		render_pass.set_bind_group(0, &self.bind_group, &[]);
+		render_pass.draw(0..3, 0..1);
+	}
+}
@@ -0,0 +1,24 @@
+// Blends a flat color over the frame, used to fade between scenes, and draws letterbox bars
+
+struct Fade {
+	color: vec4<f32>, // alpha is how far the fade is
+	bars: vec2<f32>, // rows in pixels, black above the first and below the second
+}
+
+@group(0) @binding(0)
+var<uniform> fade: Fade;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+	// one triangle covering the screen
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+	if position.y < fade.bars.x || position.y > fade.bars.y {
+		return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+	}
+	return fade.color;
+}
@@ -0,0 +1,43 @@
+/*
+Per-frame state for each frame in flight. The CPU cycles through the frames, and before a
+frame's resources are written again it waits for the GPU to finish the submission that last
+used them, so there are never more than frames_in_flight frames queued up.
+*/
+pub struct FrameResources {
+	submission: Option<wgpu::SubmissionIndex>,
+}
+
+pub struct FrameRing {
+	frames: Vec<FrameResources>,
+	current: usize,
+}
+
+impl FrameRing {
+	pub fn new(frames_in_flight: usize) -> Self {
+		assert!(frames_in_flight > 0);
+		Self {
+			frames: (0..frames_in_flight).map(|_| FrameResources { submission: None }).collect(),
+			current: 0,
+		}
+	}
+
+	// index of the frame currently being recorded, used to pick per-frame regions and buffers
+	pub fn index(&self) -> usize {
+		self.current
+	}
+
+	// waits until the GPU is done with the current frame's resources
+	pub fn begin(&mut self, device: &wgpu::Device) {
+		if let Some(submission) = self.frames[self.current].submission.take()
+			&& let Err(e) = device.poll(wgpu::PollType::Wait { submission_index: Some(submission), timeout: None })
+		{
+			log::warn!("Failed waiting for frame {}: {}", self.current, e);
+		}
+	}
+
+	// records the submission that reads the current frame's resources and moves to the next frame
+	pub fn end(&mut self, submission: wgpu::SubmissionIndex) {
+		self.frames[self.current].submission = Some(submission);
+		self.current = (self.current + 1) % self.frames.len();
+	}
+}
@@ -0,0 +1,119 @@
+use std::fmt::Write;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PassKind {
+	Render,
+	Compute,
+	Copy,
+}
+
+// one pass of the frame, with the resources it reads and writes
+#[derive(Clone, Debug)]
+pub struct PassNode {
+	pub name: &'static str,
+	pub kind: PassKind,
+	pub reads: Vec<&'static str>,
+	pub writes: Vec<&'static str>,
+}
+
+/*
+The passes the renderer runs this frame in submission order, for inspecting how they're
+scheduled. It's a description built by Renderer::frame_graph alongside render, not something
+render executes, so passes that are skipped this frame aren't in it. A pass depends on the
+last earlier pass that wrote each resource it reads.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct FrameGraph {
+	pub passes: Vec<PassNode>,
+}
+
+impl FrameGraph {
+	pub fn pass(&mut self, name: &'static str, kind: PassKind, reads: &[&'static str], writes: &[&'static str]) {
+		self.passes.push(PassNode {
+			name,
+			kind,
+			reads: reads.to_vec(),
+			writes: writes.to_vec(),
+		});
+	}
+
+	// every resource in the order it's first used
+	pub fn resources(&self) -> Vec<&'static str> {
+		let mut resources = vec![];
+		for pass in &self.passes {
+			for &resource in pass.reads.iter().chain(&pass.writes) {
+				if !resources.contains(&resource) {
+					resources.push(resource);
+				}
+			}
+		}
+		resources
+	}
+
+	// the earlier passes a pass waits on, with the resource each one hands over
+	pub fn dependencies(&self, pass: usize) -> Vec<(usize, &'static str)> {
+		let mut dependencies = vec![];
+		for &resource in &self.passes[pass].reads {
+			let writer = (0..pass).rev().find(|&earlier| self.passes[earlier].writes.contains(&resource));
+			if let Some(writer) = writer {
+				dependencies.push((writer, resource));
+			}
+		}
+		dependencies
+	}
+
+	// passes as boxes and resources as ellipses, for Graphviz
+	pub fn to_dot(&self) -> String {
+		let mut dot = String::from("digraph frame {\n\trankdir=LR;\n");
+		for (index, resource) in self.resources().iter().enumerate() {
+			writeln!(dot, "\tr{} [label=\"{}\", shape=ellipse];", index, escape(resource)).unwrap();
+		}
+		let resources = self.resources();
+		let resource_id = |name: &str| resources.iter().position(|&resource| resource == name).unwrap();
+		for (index, pass) in self.passes.iter().enumerate() {
+			let style = match pass.kind {
+				PassKind::Render => "",
+				PassKind::Compute => ", style=filled, fillcolor=lightblue",
+				PassKind::Copy => ", style=filled, fillcolor=lightgray",
+			};
+			writeln!(dot, "\tp{} [label=\"{}. {}\", shape=box{}];", index, index, escape(pass.name), style).unwrap();
+			for resource in &pass.reads {
+				writeln!(dot, "\tr{} -> p{};", resource_id(resource), index).unwrap();
+			}
+			for resource in &pass.writes {
+				writeln!(dot, "\tp{} -> r{};", index, resource_id(resource)).unwrap();
+			}
+		}
+		dot.push_str("}\n");
+		dot
+	}
+
+	pub fn to_json(&self) -> String {
+		let strings = |names: &[&str]| names.iter().map(|name| format!("\"{}\"", escape(name))).collect::<Vec<_>>().join(", ");
+		let mut json = String::from("{\n\t\"passes\": [\n");
+		for (index, pass) in self.passes.iter().enumerate() {
+			let dependencies = self.dependencies(index).iter()
+				.map(|(pass, resource)| format!("{{\"pass\": {}, \"resource\": \"{}\"}}", pass, escape(resource)))
+				.collect::<Vec<_>>()
+				.join(", ");
+			write!(
+				json,
+				"\t\t{{\"index\": {}, \"name\": \"{}\", \"kind\": \"{:?}\", \"reads\": [{}], \"writes\": [{}], \"dependencies\": [{}]}}",
+				index,
+				escape(pass.name),
+				pass.kind,
+				strings(&pass.reads),
+				strings(&pass.writes),
+				dependencies,
+			).unwrap();
+			json.push_str(if index + 1 < self.passes.len() { ",\n" } else { "\n" });
+		}
+		write!(json, "\t],\n\t\"resources\": [{}]\n}}\n", strings(&self.resources())).unwrap();
+		json
+	}
+}
+
+// for the quoted strings of both formats
+fn escape(name: &str) -> String {
+	name.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,23 @@
+// G-buffer output of the full tier's material shaders for the deferred render path, lit afterwards by deferred.wgsl
+
+struct GBufferOutput {
+	@location(0) normal: vec4<f32>, // world normal, emissive in w
+	@location(1) albedo: vec4<f32>, // reflection fresnel f0 in a
+	@location(2) specular: vec4<f32>, // shininess in w
+	@location(3) params: vec4<u32>, // illumination model, shadow flags, shadow bias as a half float
+	@location(4) surface: vec4<f32>, // the forward passes' surface target, for the reflection pass
+};
+
+@fragment
+fn fs_gbuffer(in: VertexOutput) -> GBufferOutput {
+	model = models[in.instance];
+	let base = material_surface(in);
+	let surface = material_params(in, base.color, base.normal);
+	var out: GBufferOutput;
+	out.normal = vec4<f32>(surface.normal, surface.emissive);
+	out.albedo = vec4<f32>(surface.color, surface.f0);
+	out.specular = vec4<f32>(surface.specular, surface.shininess);
+	out.params = vec4<u32>(surface.illum, model.shadow_flags, pack2x16float(vec2<f32>(model.shadow_bias, 0.0)), 0u);
+	out.surface = fragment_output(vec4<f32>(0.0), surface.normal).surface;
+	return out;
+}
@@ -0,0 +1,23 @@
+// per-frame values shared by every pipeline
+gpu_struct! {
+	Uniform
+	#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+	pub struct GlobalsUniform {
+		pub camera_pos: [f32; 3],
+		pub time: f32,
+		pub resolution: [f32; 2],
+		pub delta_time: f32,
+	}
+}
+
+impl GlobalsUniform {
+	pub fn new() -> Self {
+		Self {
+			camera_pos: [0.0; 3],
+			time: 0.0,
+			resolution: [1.0, 1.0],
+			delta_time: 0.0,
+			..bytemuck::Zeroable::zeroed()
+		}
+	}
+}
@@ -0,0 +1,367 @@
+use cgmath::InnerSpace;
+use wgpu::util::DeviceExt;
+
+use crate::{temporal, texture, upsample};
+
+gpu_struct! {
+	Uniform
+	#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+	pub struct GtaoParams {
+		pub strength: f32, // 0 disables the pass
+		pub radius: f32, // world units
+		frame: u32,
+	}
+}
+
+impl GtaoParams {
+	pub fn new() -> Self {
+		Self {
+			strength: 0.8,
+			radius: 0.5,
+			frame: 0,
+			..bytemuck::Zeroable::zeroed()
+		}
+	}
+}
+
+// the half resolution occlusion, recreated on resize
+struct Targets {
+	ao: texture::Texture,
+	gtao_bind_group: wgpu::BindGroup,
+	composite_bind_groups: [wgpu::BindGroup; 2], // reading the accumulator's history[i]
+}
+
+// what the GTAO and SSAO passes bind besides the depth and camera, kept across resizes
+struct PassInputs {
+	layout: wgpu::BindGroupLayout,
+	params_buffer: wgpu::Buffer,
+	noise_view: wgpu::TextureView, // SSAO kernel rotations, tiled over the screen
+	kernel_buffer: wgpu::Buffer,
+}
+
+// a pseudo random number from 0 to 1, for the SSAO kernel and noise
+fn random(index: u32) -> f32 {
+	let mut h = index.wrapping_mul(0x9e3779b9) ^ 0x85ebca6b;
+	h ^= h >> 16;
+	h = h.wrapping_mul(0x7feb352d);
+	h ^= h >> 15;
+	h = h.wrapping_mul(0x846ca68b);
+	h ^= h >> 16;
+	h as f32 / u32::MAX as f32
+}
+
+/*
+Ground truth ambient occlusion, an alternative to the ray traced AO for adapters without ray
+queries. Occlusion is found from the horizons in the depth buffer at half resolution, with
+few samples per pixel that are rotated every frame and accumulated over frames, then the
+accumulated occlusion is bilaterally upsampled and multiplied onto the lit image. Classic
+hemisphere SSAO can be traced instead, cheaper on old GPUs but darker in creases and with halos
+around edges. Both reconstruct normals from the depth buffer, so neither needs a normal prepass.
+*/
+pub struct Gtao {
+	pub params: GtaoParams,
+	inputs: PassInputs,
+	targets: Targets,
+	pub temporal: temporal::TemporalAccumulator,
+	gtao_pipeline: wgpu::RenderPipeline,
+	ssao_pipeline: wgpu::RenderPipeline,
+	upsample: upsample::BilateralUpsample,
+}
+
+impl Gtao {
+	const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+	const DIVISOR: u32 = 2; // half resolution
+	const KERNEL_SIZE: usize = 16;
+	const NOISE_SIZE: u32 = 4;
+
+	pub fn new(
+		device: &wgpu::Device,
+		queue: &wgpu::Queue,
+		config: &wgpu::SurfaceConfiguration,
+		depth_texture: &texture::Texture,
+		camera_buffer: &wgpu::Buffer,
+	) -> Self {
+		let params = GtaoParams::new();
+		let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("GTAO Params Buffer"),
+			contents: bytemuck::cast_slice(&[params]),
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+		});
+
+		let gtao_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("gtao_bind_group_layout"),
+			entries: &[
+				wgpu::BindGroupLayoutEntry { // depth
+					binding: 0,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Texture {
+						multisampled: false,
+						view_dimension: wgpu::TextureViewDimension::D2,
+						sample_type: wgpu::TextureSampleType::Depth,
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry { // camera
+					binding: 1,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry { // params
+					binding: 2,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry { // SSAO noise
+					binding: 3,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Texture {
+						multisampled: false,
+						view_dimension: wgpu::TextureViewDimension::D2,
+						sample_type: wgpu::TextureSampleType::Float { filterable: false },
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry { // SSAO kernel
+					binding: 4,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+			],
+		});
+		let gtao_pipeline = Self::create_pipeline(device, &gtao_layout, wgpu::include_wgsl!("gtao.wgsl"), "GTAO");
+		let ssao_pipeline = Self::create_pipeline(device, &gtao_layout, wgpu::include_wgsl!("ssao.wgsl"), "SSAO");
+		let noise_view = Self::create_noise(device, queue);
+		let kernel_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("SSAO Kernel Buffer"),
+			contents: bytemuck::cast_slice(&Self::kernel()),
+			usage: wgpu::BufferUsages::UNIFORM,
+		});
+		let upsample = upsample::BilateralUpsample::new(device, "GTAO", config.format, upsample::Composite::Multiply);
+
+		let ao = Self::create_ao_texture(device, config);
+		let temporal = temporal::TemporalAccumulator::new(device, "GTAO", config, Self::FORMAT, Self::DIVISOR, temporal::FrameInputs {
+			depth_texture,
+			camera_buffer,
+			input: &ao.view,
+		});
+		let inputs = PassInputs {
+			layout: gtao_layout,
+			params_buffer,
+			noise_view,
+			kernel_buffer,
+		};
+		let targets = Self::create_targets(device, ao, &inputs, &temporal, &upsample, depth_texture, camera_buffer);
+
+		Self {
+			params,
+			inputs,
+			targets,
+			temporal,
+			gtao_pipeline,
+			ssao_pipeline,
+			upsample,
+		}
+	}
+
+	fn create_pipeline(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, shader: wgpu::ShaderModuleDescriptor, label: &str) -> wgpu::RenderPipeline {
+		let shader = device.create_shader_module(shader);
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some(&format!("{} Pipeline Layout", label)),
+			bind_group_layouts: &[Some(layout)],
+			immediate_size: 0,
+		});
+		device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some(&format!("{} Pipeline", label)),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: Some("vs_main"),
+				buffers: &[],
+				compilation_options: Default::default(),
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: Some("fs_main"),
+				targets: &[Some(wgpu::ColorTargetState {
+					format: Self::FORMAT,
+					blend: None,
+					write_mask: wgpu::ColorWrites::ALL,
+				})],
+				compilation_options: Default::default(),
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			multiview_mask: None,
+			cache: None,
+		})
+	}
+
+	// points in the hemisphere above z, scaled so more of them are close to the surface
+	fn kernel() -> [[f32; 4]; Self::KERNEL_SIZE] {
+		std::array::from_fn(|i| {
+			let index = i as u32 * 4;
+			let direction = cgmath::Vector3::new(random(index) * 2.0 - 1.0, random(index + 1) * 2.0 - 1.0, random(index + 2).max(0.05));
+			let scale = i as f32 / Self::KERNEL_SIZE as f32;
+			let length = (random(index + 3) * (0.1 + 0.9 * scale * scale)).max(0.1);
+			let point = direction.normalize() * length;
+			[point.x, point.y, point.z, 0.0]
+		})
+	}
+
+	// random rotations around the normal, as unit vectors in the xy plane
+	fn create_noise(device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::TextureView {
+		let size = wgpu::Extent3d {
+			width: Self::NOISE_SIZE,
+			height: Self::NOISE_SIZE,
+			depth_or_array_layers: 1,
+		};
+		let texture = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some("ssao_noise_texture"),
+			size,
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: wgpu::TextureFormat::Rgba8Unorm,
+			usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+			view_formats: &[],
+		});
+		let texels = (0..Self::NOISE_SIZE * Self::NOISE_SIZE)
+			.flat_map(|i| {
+				let angle = random(1000 + i) * std::f32::consts::TAU;
+				[angle.cos(), angle.sin()].map(|x| ((x * 0.5 + 0.5) * 255.0) as u8).into_iter().chain([0, 255])
+			})
+			.collect::<Vec<u8>>();
+		queue.write_texture(
+			wgpu::TexelCopyTextureInfo {
+				texture: &texture,
+				mip_level: 0,
+				origin: wgpu::Origin3d::ZERO,
+				aspect: wgpu::TextureAspect::All,
+			},
+			&texels,
+			wgpu::TexelCopyBufferLayout {
+				offset: 0,
+				bytes_per_row: Some(4 * Self::NOISE_SIZE),
+				rows_per_image: Some(Self::NOISE_SIZE),
+			},
+			size,
+		);
+		texture.create_view(&wgpu::TextureViewDescriptor::default())
+	}
+
+	fn create_ao_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> texture::Texture {
+		texture::Texture::create_render_target(device, &upsample::scaled_config(config, Self::DIVISOR), Self::FORMAT, wgpu::TextureUsages::RENDER_ATTACHMENT, "gtao_texture")
+	}
+
+	fn create_targets(
+		device: &wgpu::Device,
+		ao: texture::Texture,
+		inputs: &PassInputs,
+		temporal: &temporal::TemporalAccumulator,
+		upsample: &upsample::BilateralUpsample,
+		depth_texture: &texture::Texture,
+		camera_buffer: &wgpu::Buffer,
+	) -> Targets {
+		let gtao_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("gtao_bind_group"),
+			layout: &inputs.layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: camera_buffer.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: inputs.params_buffer.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 3,
+					resource: wgpu::BindingResource::TextureView(&inputs.noise_view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 4,
+					resource: inputs.kernel_buffer.as_entire_binding(),
+				},
+			],
+		});
+		let composite_bind_groups = std::array::from_fn(|i| upsample.create_bind_group(device, depth_texture, camera_buffer, &temporal.history()[i].view));
+
+		Targets {
+			ao,
+			gtao_bind_group,
+			composite_bind_groups,
+		}
+	}
+
+	// the depth texture is recreated on resize, the history starts over
+	pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, depth_texture: &texture::Texture, camera_buffer: &wgpu::Buffer) {
+		let ao = Self::create_ao_texture(device, config);
+		self.temporal.resize(device, config, temporal::FrameInputs {
+			depth_texture,
+			camera_buffer,
+			input: &ao.view,
+		});
+		self.targets = Self::create_targets(
+			device,
+			ao,
+			&self.inputs,
+			&self.temporal,
+			&self.upsample,
+			depth_texture,
+			camera_buffer,
+		);
+	}
+
+	// view_proj is this frame's camera, for the temporal reprojection, ssao traces the hemisphere kernel instead
+	pub fn draw(&mut self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, view_proj: cgmath::Matrix4<f32>, ssao: bool) {
+		if self.params.strength <= 0.0 {
+			return;
+		}
+		queue.write_buffer(&self.inputs.params_buffer, 0, bytemuck::cast_slice(&[self.params]));
+		self.params.frame = self.params.frame.wrapping_add(1);
+
+		{
+			let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+				label: Some(if ssao { "SSAO Pass" } else { "GTAO Pass" }),
+				color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+					view: &self.targets.ao.view,
+					resolve_target: None,
+					ops: wgpu::Operations {
+						load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+						store: wgpu::StoreOp::Store,
+					},
+					depth_slice: None,
+				})],
+				depth_stencil_attachment: None,
+				occlusion_query_set: None,
+				timestamp_writes: None,
+				multiview_mask: None,
+			});
+			render_pass.set_pipeline(if ssao { &self.ssao_pipeline } else { &self.gtao_pipeline });
+			render_pass.set_bind_group(0, &self.targets.gtao_bind_group, &[]);
+			render_pass.draw(0..3, 0..1);
+		}
+		self.temporal.accumulate(queue, encoder, view_proj);
+		self.upsample.draw(encoder, view, &self.targets.composite_bind_groups[self.temporal.current()]);
+	}
+}
@@ -0,0 +1,112 @@
+// Ground truth ambient occlusion, after Jimenez et al. 2016. Occlusion is traced at half
+// resolution, accumulated over frames by the temporal accumulator, then upsampled and
+// multiplied onto the lit image.
+
+struct Camera {
+	view: mat4x4<f32>,
+	proj: mat4x4<f32>,
+	view_proj: mat4x4<f32>,
+	inv_view: mat4x4<f32>,
+	inv_proj: mat4x4<f32>,
+	inv_view_proj: mat4x4<f32>,
+	position: vec4<f32>,
+};
+
+struct Params {
+	strength: f32, // 0 disables the pass
+	radius: f32, // world units
+	frame: u32,
+};
+
+@group(0) @binding(0)
+var depth_texture: texture_depth_2d;
+@group(0) @binding(1)
+var<uniform> camera: Camera;
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+const PI: f32 = 3.14159265;
+const HALF_PI: f32 = 1.57079633;
+const SLICES: u32 = 2u;
+const STEPS: u32 = 4u;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+	// one triangle covering the screen
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// view space position of a full resolution pixel
+fn view_position(pixel: vec2<i32>) -> vec3<f32> {
+	let size = vec2<i32>(textureDimensions(depth_texture));
+	let p = clamp(pixel, vec2<i32>(0), size - 1);
+	let depth = textureLoad(depth_texture, p, 0);
+	let uv = (vec2<f32>(p) + 0.5) / vec2<f32>(size);
+	let view = camera.inv_proj * vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+	return view.xyz / view.w;
+}
+
+fn noise(pixel: vec2<u32>) -> vec2<f32> {
+	// interleaved gradient noise, offset every frame so the history converges
+	let p = vec2<f32>(pixel) + f32(params.frame % 64u) * 5.588238;
+	let a = fract(52.9829189 * fract(dot(p, vec2<f32>(0.06711056, 0.00583715))));
+	let b = fract(52.9829189 * fract(dot(p + 17.0, vec2<f32>(0.06711056, 0.00583715))));
+	return vec2<f32>(a, b);
+}
+
+@fragment
+fn fs_main(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
+	let pixel = vec2<i32>(frag_coord.xy) * 2;
+	if textureLoad(depth_texture, pixel, 0) >= 1.0 {
+		return vec4<f32>(1.0);
+	}
+	let position = view_position(pixel);
+	let v = normalize(-position);
+	var normal = normalize(cross(view_position(pixel + vec2<i32>(0, 1)) - position, view_position(pixel + vec2<i32>(1, 0)) - position));
+	if dot(normal, v) < 0.0 {
+		normal = -normal;
+	}
+
+	// the radius in full resolution pixels at this depth
+	let full_size = vec2<f32>(textureDimensions(depth_texture));
+	let radius_pixels = params.radius * camera.proj[1][1] * 0.5 * full_size.y / -position.z;
+	if radius_pixels < 1.0 {
+		return vec4<f32>(1.0);
+	}
+
+	let r = noise(vec2<u32>(frag_coord.xy));
+	var visibility = 0.0;
+	for (var slice = 0u; slice < SLICES; slice++) {
+		let phi = (f32(slice) + r.x) * PI / f32(SLICES);
+		let dir = vec3<f32>(cos(phi), sin(phi), 0.0);
+		// screen space y points down
+		let step = vec2<f32>(dir.x, -dir.y) * radius_pixels / f32(STEPS);
+
+		let ortho = dir - dot(dir, v) * v;
+		let axis = normalize(cross(dir, v));
+		let projected_normal = normal - axis * dot(normal, axis);
+		let projected_length = length(projected_normal);
+		let n = sign(dot(ortho, projected_normal)) * acos(clamp(dot(projected_normal, v) / projected_length, -1.0, 1.0));
+
+		var horizons = vec2<f32>(-1.0);
+		for (var i = 0u; i < STEPS; i++) {
+			let offset = step * (f32(i) + r.y + 0.5);
+			for (var side = 0; side < 2; side++) {
+				let sample_pixel = vec2<i32>(vec2<f32>(pixel) + select(offset, -offset, side == 0));
+				let delta = view_position(sample_pixel) - position;
+				let distance_squared = max(dot(delta, delta), 1e-6);
+				let falloff = clamp(1.0 - distance_squared / (params.radius * params.radius), 0.0, 1.0);
+				let horizon = mix(-1.0, dot(delta, v) * inverseSqrt(distance_squared), falloff);
+				horizons[side] = max(horizons[side], horizon);
+			}
+		}
+
+		let h0 = n + max(-acos(horizons.x) - n, -HALF_PI);
+		let h1 = n + min(acos(horizons.y) - n, HALF_PI);
+		let arc0 = -cos(2.0 * h0 - n) + cos(n) + 2.0 * h0 * sin(n);
+		let arc1 = -cos(2.0 * h1 - n) + cos(n) + 2.0 * h1 * sin(n);
+		visibility += projected_length * 0.25 * (arc0 + arc1);
+	}
+	return vec4<f32>(vec3<f32>(mix(1.0, visibility / f32(SLICES), params.strength)), 1.0);
+}
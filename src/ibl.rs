@@ -0,0 +1,267 @@
+use wgpu::util::DeviceExt;
+
+use crate::{bind_group, texture};
+
+/*
+Image based lighting, the ambient light of the scene's environment cubemap. From the cubemap it
+bakes an irradiance cubemap for the diffuse light and a specular cubemap prefiltered for a
+rougher surface at each mip, and once at startup the BRDF lookup table that scales the specular
+light by the view angle and roughness. The maps are baked again whenever the scene's skybox
+changes, the renderer's own cubemap lights scenes without one. They're bound in group 1 of the
+scene pipelines, where the plain cubemap used to be.
+*/
+pub struct Ibl {
+	pub layout: wgpu::BindGroupLayout,
+	pub bind_group: wgpu::BindGroup,
+	source: Option<wgpu::Texture>, // the cubemap the maps were last baked from
+	irradiance: wgpu::Texture,
+	prefiltered: wgpu::Texture,
+	bake_layout: wgpu::BindGroupLayout,
+	params_buffer: wgpu::Buffer, // a face and roughness per bake pass, at PARAMS_STRIDE
+	irradiance_pipeline: wgpu::RenderPipeline,
+	prefilter_pipeline: wgpu::RenderPipeline,
+}
+
+impl Ibl {
+	const IRRADIANCE_SIZE: u32 = 32;
+	const PREFILTERED_SIZE: u32 = 128;
+	pub const PREFILTERED_MIPS: u32 = 5; // roughness 0 to 1, the shaders' MAX_REFLECTION_LOD + 1
+	const LUT_SIZE: u32 = 128;
+	const LUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+	const PARAMS_STRIDE: u64 = 256;
+
+	// hdr stores the maps in floating point, when the adapter can render to it
+	pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, hdr: bool) -> Self {
+		let format = if hdr { texture::Texture::HDR_FORMAT } else { wgpu::TextureFormat::Rgba8Unorm };
+		let layout = bind_group::LayoutBuilder::new(wgpu::ShaderStages::FRAGMENT)
+			.texture_of(0, wgpu::TextureSampleType::Float { filterable: true }, wgpu::TextureViewDimension::Cube)
+			.sampler(1)
+			.texture_of(2, wgpu::TextureSampleType::Float { filterable: true }, wgpu::TextureViewDimension::Cube)
+			.texture(3)
+			.build(device, "ibl_bind_group_layout");
+		let bake_layout = bind_group::LayoutBuilder::new(wgpu::ShaderStages::FRAGMENT)
+			.texture_of(0, wgpu::TextureSampleType::Float { filterable: true }, wgpu::TextureViewDimension::Cube)
+			.sampler(1)
+			.dynamic_uniform::<[f32; 4]>(2, 1)
+			.build(device, "ibl_bake_bind_group_layout");
+
+		let cube = |label, size, mip_level_count, format| device.create_texture(&wgpu::TextureDescriptor {
+			label: Some(label),
+			size: wgpu::Extent3d {
+				width: size,
+				height: size,
+				depth_or_array_layers: 6,
+			},
+			mip_level_count,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format,
+			usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+			view_formats: &[],
+		});
+		let irradiance = cube("ibl_irradiance_texture", Self::IRRADIANCE_SIZE, 1, format);
+		let prefiltered = cube("ibl_prefiltered_texture", Self::PREFILTERED_SIZE, Self::PREFILTERED_MIPS, format);
+		let brdf_lut = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some("ibl_brdf_lut"),
+			size: wgpu::Extent3d {
+				width: Self::LUT_SIZE,
+				height: Self::LUT_SIZE,
+				depth_or_array_layers: 1,
+			},
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: Self::LUT_FORMAT,
+			usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+			view_formats: &[],
+		});
+		let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+			label: Some("ibl_sampler"),
+			address_mode_u: wgpu::AddressMode::ClampToEdge,
+			address_mode_v: wgpu::AddressMode::ClampToEdge,
+			address_mode_w: wgpu::AddressMode::ClampToEdge,
+			mag_filter: wgpu::FilterMode::Linear,
+			min_filter: wgpu::FilterMode::Linear,
+			mipmap_filter: wgpu::MipmapFilterMode::Linear,
+			..Default::default()
+		});
+
+		// the irradiance faces, then the prefiltered faces of each mip
+		let params = (0..6).map(|face| [face as f32, 0.0, 0.0, 0.0])
+			.chain((0..Self::PREFILTERED_MIPS).flat_map(|mip| {
+				let roughness = mip as f32 / (Self::PREFILTERED_MIPS - 1) as f32;
+				(0..6).map(move |face| [face as f32, roughness, 0.0, 0.0])
+			}))
+			.flat_map(|params| {
+				let mut bytes = bytemuck::cast_slice(&params).to_vec();
+				bytes.resize(Self::PARAMS_STRIDE as usize, 0);
+				bytes
+			})
+			.collect::<Vec<u8>>();
+		let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("IBL Params Buffer"),
+			contents: &params,
+			usage: wgpu::BufferUsages::UNIFORM,
+		});
+
+		let shader = device.create_shader_module(wgpu::include_wgsl!("ibl.wgsl"));
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("IBL Pipeline Layout"),
+			bind_group_layouts: &[Some(&bake_layout)],
+			immediate_size: 0,
+		});
+		let create_pipeline = |label, entry_point, format| device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some(label),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: Some("vs_main"),
+				buffers: &[],
+				compilation_options: Default::default(),
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: Some(entry_point),
+				targets: &[Some(wgpu::ColorTargetState {
+					format,
+					blend: None,
+					write_mask: wgpu::ColorWrites::ALL,
+				})],
+				compilation_options: Default::default(),
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			multiview_mask: None,
+			cache: None,
+		});
+		let irradiance_pipeline = create_pipeline("IBL Irradiance Pipeline", "fs_irradiance", format);
+		let prefilter_pipeline = create_pipeline("IBL Prefilter Pipeline", "fs_prefilter", format);
+		let brdf_pipeline = create_pipeline("IBL BRDF Pipeline", "fs_brdf", Self::LUT_FORMAT);
+
+		// the lookup table doesn't read the environment, any cubemap will do for the bind group
+		let unused_view = irradiance.create_view(&wgpu::TextureViewDescriptor {
+			dimension: Some(wgpu::TextureViewDimension::Cube),
+			..Default::default()
+		});
+		let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+			label: Some("IBL BRDF Encoder"),
+		});
+		{
+			let bake_bind_group = Self::bake_bind_group(device, &bake_layout, &unused_view, &sampler, &params_buffer);
+			let lut_view = brdf_lut.create_view(&wgpu::TextureViewDescriptor::default());
+			let mut render_pass = Self::begin_bake_pass(&mut encoder, &lut_view);
+			render_pass.set_pipeline(&brdf_pipeline);
+			render_pass.set_bind_group(0, &bake_bind_group, &[0]);
+			render_pass.draw(0..3, 0..1);
+		}
+		queue.submit([encoder.finish()]);
+
+		let bind_group = Self::create_bind_group(device, &layout, &prefiltered, &irradiance, &brdf_lut, &sampler);
+
+		Self {
+			layout,
+			bind_group,
+			source: None,
+			irradiance,
+			prefiltered,
+			bake_layout,
+			params_buffer,
+			irradiance_pipeline,
+			prefilter_pipeline,
+		}
+	}
+
+	fn bake_bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, source: &wgpu::TextureView, sampler: &wgpu::Sampler, params_buffer: &wgpu::Buffer) -> wgpu::BindGroup {
+		bind_group::BindGroupBuilder::default()
+			.view(0, source)
+			.sampler(1, sampler)
+			.resource(2, wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+				buffer: params_buffer,
+				offset: 0,
+				size: wgpu::BufferSize::new(16),
+			}))
+			.build(device, layout, "ibl_bake_bind_group")
+	}
+
+	fn create_bind_group(
+		device: &wgpu::Device,
+		layout: &wgpu::BindGroupLayout,
+		prefiltered: &wgpu::Texture,
+		irradiance: &wgpu::Texture,
+		brdf_lut: &wgpu::Texture,
+		sampler: &wgpu::Sampler,
+	) -> wgpu::BindGroup {
+		let cube_view = |texture: &wgpu::Texture| texture.create_view(&wgpu::TextureViewDescriptor {
+			dimension: Some(wgpu::TextureViewDimension::Cube),
+			..Default::default()
+		});
+		bind_group::BindGroupBuilder::default()
+			.view(0, &cube_view(prefiltered))
+			.sampler(1, sampler)
+			.view(2, &cube_view(irradiance))
+			.view(3, &brdf_lut.create_view(&wgpu::TextureViewDescriptor::default()))
+			.build(device, layout, "ibl_bind_group")
+	}
+
+	fn begin_bake_pass<'a>(encoder: &'a mut wgpu::CommandEncoder, view: &wgpu::TextureView) -> wgpu::RenderPass<'a> {
+		encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("IBL Bake Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+					store: wgpu::StoreOp::Store,
+				},
+				depth_slice: None,
+			})],
+			depth_stencil_attachment: None,
+			occlusion_query_set: None,
+			timestamp_writes: None,
+			multiview_mask: None,
+		})
+	}
+
+	// bakes the maps again when the environment cubemap isn't the one they were baked from
+	pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, environment: &texture::Texture) {
+		if self.source.as_ref() == Some(&environment.texture) {
+			return;
+		}
+		self.source = Some(environment.texture.clone());
+
+		let bake_bind_group = Self::bake_bind_group(device, &self.bake_layout, &environment.view, &environment.sampler, &self.params_buffer);
+		let face_view = |texture: &wgpu::Texture, face: u32, mip: u32| texture.create_view(&wgpu::TextureViewDescriptor {
+			dimension: Some(wgpu::TextureViewDimension::D2),
+			base_mip_level: mip,
+			mip_level_count: Some(1),
+			base_array_layer: face,
+			array_layer_count: Some(1),
+			..Default::default()
+		});
+
+		let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+			label: Some("IBL Bake Encoder"),
+		});
+		let mut pass = 0;
+		for face in 0..6 {
+			let view = face_view(&self.irradiance, face, 0);
+			let mut render_pass = Self::begin_bake_pass(&mut encoder, &view);
+			render_pass.set_pipeline(&self.irradiance_pipeline);
+			render_pass.set_bind_group(0, &bake_bind_group, &[(pass * Self::PARAMS_STRIDE) as u32]);
+			render_pass.draw(0..3, 0..1);
+			pass += 1;
+		}
+		for mip in 0..Self::PREFILTERED_MIPS {
+			for face in 0..6 {
+				let view = face_view(&self.prefiltered, face, mip);
+				let mut render_pass = Self::begin_bake_pass(&mut encoder, &view);
+				render_pass.set_pipeline(&self.prefilter_pipeline);
+				render_pass.set_bind_group(0, &bake_bind_group, &[(pass * Self::PARAMS_STRIDE) as u32]);
+				render_pass.draw(0..3, 0..1);
+				pass += 1;
+			}
+		}
+		queue.submit([encoder.finish()]);
+	}
+}
@@ -0,0 +1,131 @@
+// Bakes the image based lighting maps from an environment cubemap, one cube face or LUT at a
+// time: the diffuse irradiance, the specular environment prefiltered for increasing roughness
+// down its mips, and the split sum BRDF lookup table, after Karis 2013.
+
+@group(0) @binding(0)
+var source_texture: texture_cube<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> params: vec4<f32>; // face, roughness
+
+const PI: f32 = 3.14159265;
+const SAMPLES: u32 = 128u;
+
+struct VertexOutput {
+	@builtin(position) clip_position: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	// one triangle covering the target, uv from the top left
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.clip_position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+	out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+	return out;
+}
+
+// the direction through a texel of a cube face, in the order of the texture's layers
+fn face_direction(uv: vec2<f32>) -> vec3<f32> {
+	let s = uv.x * 2.0 - 1.0;
+	let t = uv.y * 2.0 - 1.0;
+	switch u32(params.x) {
+		case 0u: { return normalize(vec3<f32>(1.0, -t, -s)); }
+		case 1u: { return normalize(vec3<f32>(-1.0, -t, s)); }
+		case 2u: { return normalize(vec3<f32>(s, 1.0, t)); }
+		case 3u: { return normalize(vec3<f32>(s, -1.0, -t)); }
+		case 4u: { return normalize(vec3<f32>(s, -t, 1.0)); }
+		default: { return normalize(vec3<f32>(-s, -t, -1.0)); }
+	}
+}
+
+fn tangent_basis(normal: vec3<f32>) -> mat3x3<f32> {
+	let up = select(vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 1.0, 0.0), abs(normal.y) < 0.999);
+	let tangent = normalize(cross(up, normal));
+	return mat3x3<f32>(tangent, cross(normal, tangent), normal);
+}
+
+fn hammersley(i: u32, count: u32) -> vec2<f32> {
+	return vec2<f32>(f32(i) / f32(count), f32(reverseBits(i)) * 2.3283064365386963e-10);
+}
+
+// a half vector around the z axis, distributed like the GGX lobe of the roughness
+fn importance_sample_ggx(xi: vec2<f32>, roughness: f32) -> vec3<f32> {
+	let a = roughness * roughness;
+	let phi = 2.0 * PI * xi.x;
+	let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y));
+	let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+	return vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+}
+
+// the cosine weighted light arriving from the hemisphere around the texel's direction
+@fragment
+fn fs_irradiance(in: VertexOutput) -> @location(0) vec4<f32> {
+	let basis = tangent_basis(face_direction(in.uv));
+	let step = 0.05;
+	var irradiance = vec3<f32>(0.0);
+	var count = 0.0;
+	for (var phi = 0.0; phi < 2.0 * PI; phi += step) {
+		for (var theta = 0.0; theta < 0.5 * PI; theta += step) {
+			let direction = vec3<f32>(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+			irradiance += textureSampleLevel(source_texture, source_sampler, basis * direction, 0.0).rgb * cos(theta) * sin(theta);
+			count += 1.0;
+		}
+	}
+	return vec4<f32>(PI * irradiance / count, 1.0);
+}
+
+// the environment seen in a mirror of the mip's roughness, taking the view to be along the normal
+@fragment
+fn fs_prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+	let normal = face_direction(in.uv);
+	let roughness = params.y;
+	if roughness <= 0.0 {
+		return vec4<f32>(textureSampleLevel(source_texture, source_sampler, normal, 0.0).rgb, 1.0);
+	}
+	let basis = tangent_basis(normal);
+	var color = vec3<f32>(0.0);
+	var weight = 0.0;
+	for (var i = 0u; i < SAMPLES; i++) {
+		let half_dir = basis * importance_sample_ggx(hammersley(i, SAMPLES), roughness);
+		let light_dir = normalize(2.0 * dot(normal, half_dir) * half_dir - normal);
+		let n_dot_l = dot(normal, light_dir);
+		if n_dot_l > 0.0 {
+			color += textureSampleLevel(source_texture, source_sampler, light_dir, 0.0).rgb * n_dot_l;
+			weight += n_dot_l;
+		}
+	}
+	return vec4<f32>(color / max(weight, 0.0001), 1.0);
+}
+
+fn geometry_schlick_ggx(n_dot_v: f32, roughness: f32) -> f32 {
+	let k = roughness * roughness * 0.5;
+	return n_dot_v / (n_dot_v * (1.0 - k) + k);
+}
+
+// the scale and bias on f0 of the specular reflection, by the view angle across and roughness down
+@fragment
+fn fs_brdf(in: VertexOutput) -> @location(0) vec4<f32> {
+	let n_dot_v = max(in.uv.x, 0.001);
+	let roughness = in.uv.y;
+	let view = vec3<f32>(sqrt(1.0 - n_dot_v * n_dot_v), 0.0, n_dot_v);
+	var scale = 0.0;
+	var bias = 0.0;
+	for (var i = 0u; i < SAMPLES; i++) {
+		let half_dir = importance_sample_ggx(hammersley(i, SAMPLES), roughness);
+		let light_dir = normalize(2.0 * dot(view, half_dir) * half_dir - view);
+		let n_dot_l = saturate(light_dir.z);
+		let n_dot_h = saturate(half_dir.z);
+		let v_dot_h = saturate(dot(view, half_dir));
+		if n_dot_l > 0.0 {
+			let g = geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+			let visibility = g * v_dot_h / (n_dot_h * n_dot_v);
+			let fresnel = pow(1.0 - v_dot_h, 5.0);
+			scale += (1.0 - fresnel) * visibility;
+			bias += fresnel * visibility;
+		}
+	}
+	return vec4<f32>(scale / f32(SAMPLES), bias / f32(SAMPLES), 0.0, 1.0);
+}
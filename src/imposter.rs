@@ -0,0 +1,265 @@
+use wgpu::util::DeviceExt;
+
+use crate::{camera, model, renderer, shaders, texture};
+
+/*
+Imposters replace far away models with a billboard showing one of several views baked into
+an atlas. Views go around the model in azimuth along the atlas columns, and up from the
+horizon in elevation along the rows.
+*/
+pub const AZIMUTH_VIEWS: u32 = 8;
+pub const ELEVATION_VIEWS: u32 = 3;
+const ELEVATION_STEP: f32 = std::f32::consts::FRAC_PI_6; // 30 degrees between rows
+
+gpu_struct! {
+	Uniform
+	#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+	pub struct ImposterUniform {
+		transform: [[f32; 4]; 4],
+		center_radius: [f32; 4],
+		views: [f32; 4], // azimuth views, elevation views, elevation step
+	}
+}
+
+impl ImposterUniform {
+	pub fn new(transform: &cgmath::Matrix4<f32>, bounds: &model::Bounds) -> Self {
+		Self {
+			transform: (*transform).into(),
+			center_radius: [bounds.center[0], bounds.center[1], bounds.center[2], bounds.radius],
+			views: [AZIMUTH_VIEWS as f32, ELEVATION_VIEWS as f32, ELEVATION_STEP, 0.0],
+			..bytemuck::Zeroable::zeroed()
+		}
+	}
+}
+
+pub struct Imposter {
+	pub distance: f32, // objects further than this from the camera use the imposter
+	atlas: texture::Texture,
+	pub(crate) bind_group: wgpu::BindGroup,
+}
+
+impl Imposter {
+	pub fn atlas(&self) -> &texture::Texture {
+		&self.atlas
+	}
+}
+
+// orthographic view of the bounds for one cell of the atlas
+pub fn bake_view(bounds: &model::Bounds, column: u32, row: u32) -> camera::CameraUniform {
+	let azimuth = column as f32 / AZIMUTH_VIEWS as f32 * std::f32::consts::TAU;
+	let elevation = row as f32 * ELEVATION_STEP;
+	let dir = cgmath::Vector3::new(azimuth.sin() * elevation.cos(), elevation.sin(), azimuth.cos() * elevation.cos());
+
+	let r = bounds.radius.max(0.0001);
+	let center = cgmath::Point3::from(bounds.center);
+	let eye = center + dir * r * 2.0;
+	// looking straight down would need another up vector, but rows stop short of that
+	let view = cgmath::Matrix4::look_at_rh(eye, center, cgmath::Vector3::unit_y());
+	let proj = camera::OPENGL_TO_WGPU_MATRIX * cgmath::ortho(-r, r, -r, r, r * 0.5, r * 3.5);
+
+	let mut uniform = camera::CameraUniform::new();
+	uniform.update_matrices(view, proj, eye);
+	uniform
+}
+
+// the main view's camera and environment buffers, imposters are drawn with the camera and baked in the environment
+#[derive(Copy, Clone)]
+pub(crate) struct SceneBuffers<'a> {
+	pub camera: &'a wgpu::Buffer,
+	pub environment: &'a wgpu::Buffer,
+}
+
+/*
+Draws imposters, and holds what's needed to bake them with the material pipelines: a camera
+of its own so baking doesn't disturb the scene camera's buffer
+*/
+pub struct ImposterPipeline {
+	pipeline: wgpu::RenderPipeline,
+	atlas_layout: wgpu::BindGroupLayout,
+	layout: wgpu::BindGroupLayout,
+	bind_group: wgpu::BindGroup,
+	pub(crate) bake_camera_buffer: wgpu::Buffer,
+	pub(crate) bake_bind_group: wgpu::BindGroup, // same layout as the renderer's uniform bind group
+}
+
+impl ImposterPipeline {
+	pub fn new(
+		device: &wgpu::Device,
+		color_format: wgpu::TextureFormat,
+		shader_tier: shaders::ShaderTier,
+		ibl_layout: &wgpu::BindGroupLayout,
+		uniform_layout: &wgpu::BindGroupLayout,
+		shared: &renderer::SharedUniforms,
+		scene_buffers: SceneBuffers,
+	) -> Self {
+		let bake_camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Imposter Bake Camera Buffer"),
+			contents: bytemuck::cast_slice(&[camera::CameraUniform::new()]),
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+		});
+
+		let atlas_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("imposter_atlas_bind_group_layout"),
+			entries: &[
+				wgpu::BindGroupLayoutEntry {
+					binding: 0,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Texture {
+						multisampled: false,
+						view_dimension: wgpu::TextureViewDimension::D2,
+						sample_type: wgpu::TextureSampleType::Float { filterable: true },
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry {
+					binding: 1,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+					count: None,
+				},
+			],
+		});
+		let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("imposter_bind_group_layout"),
+			entries: &[
+				wgpu::BindGroupLayoutEntry { // camera
+					binding: 0,
+					visibility: wgpu::ShaderStages::VERTEX,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry { // imposter
+					binding: 1,
+					visibility: wgpu::ShaderStages::VERTEX,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: true,
+						min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<ImposterUniform>() as u64),
+					},
+					count: None,
+				},
+			],
+		});
+		let (bind_group, bake_bind_group) = Self::create_bind_groups(device, &layout, uniform_layout, shared, &bake_camera_buffer, scene_buffers);
+
+		let shader = device.create_shader_module(shader_tier.imposter_shader());
+		// group 1 keeps the image based lighting layout so it stays bound for the meshes drawn after
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Imposter Pipeline Layout"),
+			bind_group_layouts: &[Some(&atlas_layout), Some(ibl_layout), Some(&layout)],
+			immediate_size: 0,
+		});
+		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("Imposter Pipeline"),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: Some("vs_main"),
+				buffers: &[],
+				compilation_options: Default::default(),
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: Some("fs_main"),
+				targets: &[
+					Some(wgpu::ColorTargetState {
+						format: color_format,
+						blend: Some(wgpu::BlendState::REPLACE),
+						write_mask: wgpu::ColorWrites::ALL,
+					}),
+					shader_tier.surface_format().map(|format| wgpu::ColorTargetState {
+						format,
+						blend: None,
+						write_mask: wgpu::ColorWrites::ALL,
+					}),
+				][..shader_tier.color_target_count()],
+				compilation_options: Default::default(),
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: Some(wgpu::DepthStencilState {
+				format: texture::Texture::DEPTH_FORMAT,
+				depth_write_enabled: Some(true),
+				depth_compare: Some(wgpu::CompareFunction::Less),
+				stencil: wgpu::StencilState::default(),
+				bias: wgpu::DepthBiasState::default(),
+			}),
+			multisample: wgpu::MultisampleState::default(),
+			multiview_mask: None,
+			cache: None,
+		});
+
+		Self {
+			pipeline,
+			atlas_layout,
+			layout,
+			bind_group,
+			bake_camera_buffer,
+			bake_bind_group,
+		}
+	}
+
+	// the imposter bind group, and the bake's in the layout of the renderer's uniform bind group
+	fn create_bind_groups(
+		device: &wgpu::Device,
+		layout: &wgpu::BindGroupLayout,
+		uniform_layout: &wgpu::BindGroupLayout,
+		shared: &renderer::SharedUniforms,
+		bake_camera_buffer: &wgpu::Buffer,
+		scene_buffers: SceneBuffers,
+	) -> (wgpu::BindGroup, wgpu::BindGroup) {
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("imposter_bind_group"),
+			layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: scene_buffers.camera.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: shared.uniform_arena.binding::<ImposterUniform>(),
+				},
+			],
+		});
+		let bake_bind_group = shared.bind_group(device, uniform_layout, bake_camera_buffer.as_entire_binding(), scene_buffers.environment.as_entire_binding(), "imposter_bake_bind_group");
+		(bind_group, bake_bind_group)
+	}
+
+	// after the uniform arena's buffer was replaced
+	pub fn rebind(&mut self, device: &wgpu::Device, uniform_layout: &wgpu::BindGroupLayout, shared: &renderer::SharedUniforms, scene_buffers: SceneBuffers) {
+		(self.bind_group, self.bake_bind_group) = Self::create_bind_groups(device, &self.layout, uniform_layout, shared, &self.bake_camera_buffer, scene_buffers);
+	}
+
+	pub fn create_imposter(&self, device: &wgpu::Device, atlas: texture::Texture, distance: f32) -> Imposter {
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("imposter_atlas_bind_group"),
+			layout: &self.atlas_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&atlas.view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::Sampler(&atlas.sampler),
+				},
+			],
+		});
+		Imposter {
+			distance,
+			atlas,
+			bind_group,
+		}
+	}
+
+	pub fn draw(&self, render_pass: &mut wgpu::RenderPass, imposter: &Imposter, imposter_offset: u32) {
+		render_pass.set_pipeline(&self.pipeline);
+		render_pass.set_bind_group(0, &imposter.bind_group, &[]);
+		render_pass.set_bind_group(2, &self.bind_group, &[imposter_offset]);
+		render_pass.draw(0..6, 0..1);
+	}
+}
@@ -0,0 +1,77 @@
+// Camera facing billboard that shows the atlas view closest to the direction it's seen from
+
+struct Camera {
+	view: mat4x4<f32>,
+	proj: mat4x4<f32>,
+	view_proj: mat4x4<f32>,
+	inv_view: mat4x4<f32>,
+	inv_proj: mat4x4<f32>,
+	inv_view_proj: mat4x4<f32>,
+	position: vec4<f32>,
+};
+
+struct Imposter {
+	transform: mat4x4<f32>,
+	center_radius: vec4<f32>, // model space bounding sphere
+	views: vec4<f32>, // azimuth views, elevation views, elevation step in radians
+};
+
+@group(0) @binding(0)
+var atlas_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var atlas_sampler: sampler;
+
+@group(2) @binding(0)
+var<uniform> camera: Camera;
+@group(2) @binding(1)
+var<uniform> imposter: Imposter;
+
+struct VertexOutput {
+	@builtin(position) clip_position: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+	@location(1) normal: vec3<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	let corners = array<vec2<f32>, 6>(
+		vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(1.0, 1.0),
+		vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), vec2<f32>(-1.0, 1.0),
+	);
+	let corner = corners[index];
+
+	let t = imposter.transform;
+	let center = (t * vec4<f32>(imposter.center_radius.xyz, 1.0)).xyz;
+	let scale = max(length(t[0].xyz), max(length(t[1].xyz), length(t[2].xyz)));
+	let radius = imposter.center_radius.w * scale;
+
+	// pick the baked view from the camera direction in model space
+	let to_camera = camera.position.xyz - center;
+	let rotation = mat3x3<f32>(normalize(t[0].xyz), normalize(t[1].xyz), normalize(t[2].xyz));
+	let dir = normalize(transpose(rotation) * to_camera);
+	let azimuth = atan2(dir.x, dir.z);
+	let elevation = asin(clamp(dir.y, -1.0, 1.0));
+	let column = u32(round(fract(azimuth / 6.2831853) * imposter.views.x)) % u32(imposter.views.x);
+	let row = u32(clamp(round(elevation / imposter.views.z), 0.0, imposter.views.y - 1.0));
+
+	let right = camera.inv_view[0].xyz;
+	let up = camera.inv_view[1].xyz;
+	let world = center + (right * corner.x + up * corner.y) * radius;
+
+	var out: VertexOutput;
+	out.clip_position = camera.view_proj * vec4<f32>(world, 1.0);
+	let cell_uv = vec2<f32>(corner.x * 0.5 + 0.5, 0.5 - corner.y * 0.5);
+	out.uv = (vec2<f32>(f32(column), f32(row)) + cell_uv) / imposter.views.xy;
+	out.normal = normalize(to_camera);
+	return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+	let color = textureSample(atlas_texture, atlas_sampler, in.uv);
+	if color.a < 0.5 {
+		discard;
+	}
+	// fully rough, so no reflections
+	return write_output(vec4<f32>(color.rgb, 1.0), in.normal, 1.0);
+}
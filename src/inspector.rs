@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+
+use winit::window::Window;
+
+use crate::{assets, scene};
+
+// the UI of one update, drawn by the next render
+struct InspectorFrame {
+	primitives: Vec<egui::ClippedPrimitive>,
+	textures: egui::TexturesDelta,
+	pixels_per_point: f32,
+}
+
+/*
+egui panels over the finished frame for tweaking the scene live: light positions and colors,
+the camera, object transforms, and an asset browser with the loaded models, materials, and
+textures, where materials can be assigned to an object. run builds the panels against the scene during the update,
+and draw paints them in their own pass on the surface after every other overlay. While it's
+open, input egui wants doesn't reach the camera controllers. Toggled with F2, and only built
+with the inspector feature.
+*/
+pub struct Inspector {
+	pub open: bool,
+	context: egui::Context,
+	state: egui_winit::State,
+	renderer: egui_wgpu::Renderer,
+	frame: Option<InspectorFrame>,
+	selected: Option<usize>, // the object materials are assigned to
+	thumbnails: HashMap<assets::TextureHandle, (wgpu::Texture, egui::TextureId)>, // and the texture egui was given
+}
+
+impl Inspector {
+	pub fn new(window: &Window, device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+		let context = egui::Context::default();
+		let state = egui_winit::State::new(
+			context.clone(),
+			egui::ViewportId::ROOT,
+			window,
+			Some(window.scale_factor() as f32),
+			None,
+			Some(device.limits().max_texture_dimension_2d as usize),
+		);
+		let renderer = egui_wgpu::Renderer::new(device, format, egui_wgpu::RendererOptions::default());
+
+		Self {
+			open: false,
+			context,
+			state,
+			renderer,
+			frame: None,
+			selected: None,
+			thumbnails: HashMap::new(),
+		}
+	}
+
+	pub fn toggle(&mut self) {
+		self.open = !self.open;
+	}
+
+	// true when egui used the event, so nothing else should
+	pub fn handle_event(&mut self, window: &Window, event: &winit::event::WindowEvent) -> bool {
+		self.open && self.state.on_window_event(window, event).consumed
+	}
+
+	pub fn run(&mut self, window: &Window, scene: &mut scene::Scene, assets: &assets::Assets, device: &wgpu::Device) {
+		if !self.open {
+			self.frame = None;
+			return;
+		}
+		self.update_thumbnails(assets, device);
+		let input = self.state.take_egui_input(window);
+		let thumbnails = &self.thumbnails;
+		let selected = &mut self.selected;
+		let output = self.context.run_ui(input, |ui| Self::panels(ui.ctx(), scene, assets, thumbnails, selected));
+		self.state.handle_platform_output(window, output.platform_output);
+		self.frame = Some(InspectorFrame {
+			primitives: self.context.tessellate(output.shapes, output.pixels_per_point),
+			textures: output.textures_delta,
+			pixels_per_point: output.pixels_per_point,
+		});
+	}
+
+	// gives egui every texture it hasn't seen, and the new one behind handles that finished loading
+	fn update_thumbnails(&mut self, assets: &assets::Assets, device: &wgpu::Device) {
+		for (handle, _) in assets.list() {
+			let texture = assets.texture(handle);
+			match self.thumbnails.get_mut(&handle) {
+				Some((shown, id)) if *shown != texture.texture => {
+					self.renderer.update_egui_texture_from_wgpu_texture(device, &texture.view, wgpu::FilterMode::Linear, *id);
+					*shown = texture.texture;
+				}
+				Some(_) => {}
+				None => {
+					let id = self.renderer.register_native_texture(device, &texture.view, wgpu::FilterMode::Linear);
+					self.thumbnails.insert(handle, (texture.texture, id));
+				}
+			}
+		}
+	}
+
+	fn panels(
+		context: &egui::Context,
+		scene: &mut scene::Scene,
+		assets: &assets::Assets,
+		thumbnails: &HashMap<assets::TextureHandle, (wgpu::Texture, egui::TextureId)>,
+		selected: &mut Option<usize>,
+	) {
+		let vector = |ui: &mut egui::Ui, label: &str, values: &mut [f32; 3]| {
+			ui.horizontal(|ui| {
+				ui.label(label);
+				for value in values {
+					ui.add(egui::DragValue::new(value).speed(0.05));
+				}
+			});
+		};
+
+		egui::Window::new("Lights").show(context, |ui| {
+			for index in 0..scene.lights().len() {
+				let Some(handle) = scene.light_handle(index) else {
+					continue;
+				};
+				let light = scene.light_mut(handle);
+				egui::CollapsingHeader::new(format!("Light {} ({:?})", index, light.kind)).show(ui, |ui| {
+					vector(ui, "position", &mut light.position);
+					vector(ui, "direction", &mut light.direction);
+					ui.horizontal(|ui| {
+						ui.label("color");
+						ui.color_edit_button_rgb(&mut light.color);
+					});
+					ui.add(egui::DragValue::new(&mut light.intensity).speed(0.1).range(0.0..=f32::MAX).prefix("intensity "));
+					ui.add(egui::DragValue::new(&mut light.range).speed(0.1).range(0.0..=f32::MAX).prefix("range "));
+				});
+			}
+		});
+
+		egui::Window::new("Camera").show(context, |ui| {
+			let camera = &mut scene.camera;
+			let mut eye = [camera.eye.x, camera.eye.y, camera.eye.z];
+			let mut target = [camera.target.x, camera.target.y, camera.target.z];
+			vector(ui, "eye", &mut eye);
+			vector(ui, "target", &mut target);
+			camera.eye = eye.into();
+			camera.target = target.into();
+			ui.add(egui::Slider::new(&mut camera.fovy, 10.0..=120.0).text("fov"));
+			ui.add(egui::DragValue::new(&mut camera.znear).speed(0.01).range(0.001..=camera.zfar).prefix("near "));
+			ui.add(egui::DragValue::new(&mut camera.zfar).speed(1.0).range(camera.znear..=f32::MAX).prefix("far "));
+		});
+
+		egui::Window::new("Objects").show(context, |ui| {
+			egui::ScrollArea::vertical().show(ui, |ui| {
+				for (index, obj) in scene.objects.iter_mut().enumerate() {
+					egui::CollapsingHeader::new(format!("Object {} (model {})", index, obj.model_index)).show(ui, |ui| {
+						use cgmath::InnerSpace;
+						let mut position = [obj.transform.w.x, obj.transform.w.y, obj.transform.w.z];
+						vector(ui, "position", &mut position);
+						obj.transform.w = cgmath::Vector3::from(position).extend(1.0);
+
+						// uniform scale, from the length of the x axis
+						let old_scale = obj.transform.x.truncate().magnitude();
+						let mut scale = old_scale;
+						ui.add(egui::DragValue::new(&mut scale).speed(0.01).range(0.001..=f32::MAX).prefix("scale "));
+						if scale != old_scale && old_scale > 0.0 {
+							let ratio = scale / old_scale;
+							obj.transform.x *= ratio;
+							obj.transform.y *= ratio;
+							obj.transform.z *= ratio;
+						}
+						ui.checkbox(&mut obj.casts_shadows, "casts shadows");
+						ui.checkbox(&mut obj.receives_shadows, "receives shadows");
+					});
+				}
+			});
+		});
+
+		let thumbnail = |ui: &mut egui::Ui, handle: assets::TextureHandle, size: f32| match thumbnails.get(&handle) {
+			Some((_, id)) => ui.add(egui::Image::new((*id, egui::vec2(size, size)))),
+			None => ui.label("?"),
+		};
+
+		*selected = selected.filter(|&index| index < scene.objects.len());
+		egui::Window::new("Assets").show(context, |ui| {
+			egui::ScrollArea::vertical().show(ui, |ui| {
+				egui::CollapsingHeader::new(format!("Models ({})", scene.models.len())).show(ui, |ui| {
+					for (index, model) in scene.models.iter().enumerate() {
+						let objects = scene.objects.iter().filter(|obj| obj.model_index == index).count();
+						let name = scene.model_file(index).unwrap_or("generated");
+						ui.label(format!("{}: {} ({} meshes, {} objects)", index, name, model.meshes.len(), objects));
+					}
+				});
+
+				egui::CollapsingHeader::new(format!("Materials ({})", scene.materials.len())).show(ui, |ui| {
+					egui::ComboBox::from_label("assign to")
+						.selected_text(selected.map_or("nothing".to_string(), |index| format!("Object {}", index)))
+						.show_ui(ui, |ui| {
+							ui.selectable_value(selected, None, "nothing");
+							for index in 0..scene.objects.len() {
+								ui.selectable_value(selected, Some(index), format!("Object {}", index));
+							}
+						});
+					for (index, material) in scene.materials.iter().enumerate() {
+						ui.horizontal(|ui| {
+							for handle in material.material_type.textures() {
+								thumbnail(ui, handle, 32.0);
+							}
+							ui.label(format!("{}: {}", index, material.name));
+							// the model's meshes are shared, so every object using the model changes
+							if let Some(object) = *selected
+								&& ui.button("assign").clicked()
+							{
+								for mesh in &mut scene.models[scene.objects[object].model_index].meshes {
+									mesh.material = index;
+								}
+							}
+						});
+					}
+				});
+
+				egui::CollapsingHeader::new(format!("Textures ({})", assets.len())).show(ui, |ui| {
+					ui.horizontal_wrapped(|ui| {
+						for (handle, filename) in assets.list() {
+							let mut name = filename.unwrap_or_else(|| format!("{:?}", handle));
+							if assets.is_loading(handle) {
+								name.push_str(" (loading)");
+							}
+							thumbnail(ui, handle, 64.0).on_hover_text(name);
+						}
+					});
+				});
+			});
+		});
+	}
+
+	// paints the panels from the last run over the surface, size is the surface's in pixels
+	pub fn draw(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, size: [u32; 2]) {
+		let Some(frame) = self.frame.take() else {
+			return;
+		};
+		for (id, delta) in &frame.textures.set {
+			self.renderer.update_texture(device, queue, *id, delta);
+		}
+		let screen = egui_wgpu::ScreenDescriptor {
+			size_in_pixels: size,
+			pixels_per_point: frame.pixels_per_point,
+		};
+		// only paint callbacks record extra command buffers, and the panels have none
+		let _ = self.renderer.update_buffers(device, queue, encoder, &frame.primitives, &screen);
+
+		{
+			let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+				label: Some("Inspector Pass"),
+				color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+					view,
+					resolve_target: None,
+					ops: wgpu::Operations {
+						load: wgpu::LoadOp::Load,
+						store: wgpu::StoreOp::Store,
+					},
+					depth_slice: None,
+				})],
+				depth_stencil_attachment: None,
+				occlusion_query_set: None,
+				timestamp_writes: None,
+				multiview_mask: None,
+			}).forget_lifetime();
+			self.renderer.render(&mut render_pass, &frame.primitives, &screen);
+		}
+
+		for id in &frame.textures.free {
+			self.renderer.free_texture(id);
+		}
+	}
+}
@@ -0,0 +1,56 @@
+use crate::{model, scene, uniform_arena};
+
+// objects of one model drawn with a single instanced draw per mesh
+pub struct InstanceBatch {
+	pub model_index: usize,
+	pub offset: u32, // dynamic offset of the first of count model uniforms in a row
+	pub count: u32,
+}
+
+/*
+Groups the objects a view draws by model, so copies of a model take one draw per mesh instead
+of one per object. Each batch's model uniforms are pushed next to each other and mesh.wgsl
+picks them by instance index. Objects left out, like skinned ones that draw their own vertex
+buffers, keep their own draws.
+*/
+pub struct InstanceBatches {
+	pub batches: Vec<InstanceBatch>,
+	pub batched: Vec<bool>, // by object, drawn by a batch instead of on their own
+}
+
+impl InstanceBatches {
+	/*
+	object_offsets are the view's, None for the objects it culled. single decides which
+	objects need their own draws anyway.
+	*/
+	pub fn build(
+		uniform_arena: &mut uniform_arena::UniformArena,
+		scene: &scene::Scene,
+		object_offsets: &[Option<u32>],
+		single: impl Fn(usize) -> bool,
+	) -> Self {
+		let mut by_model = vec![vec![]; scene.models.len()];
+		let mut batched = vec![false; scene.objects.len()];
+		for (object, (obj, offset)) in scene.objects.iter().zip(object_offsets).enumerate() {
+			if offset.is_some() && obj.skin.is_none() && !single(object) {
+				by_model[obj.model_index].push(object);
+				batched[object] = true;
+			}
+		}
+
+		let mut batches = vec![];
+		for (model_index, objects) in by_model.iter().enumerate() {
+			for chunk in objects.chunks(model::ModelUniform::MAX_INSTANCES) {
+				let uniforms = chunk.iter()
+					.map(|&object| scene.objects[object].to_uniform())
+					.collect::<Vec<_>>();
+				batches.push(InstanceBatch {
+					model_index,
+					offset: uniform_arena.push_slice(&uniforms),
+					count: chunk.len() as u32,
+				});
+			}
+		}
+		Self { batches, batched }
+	}
+}
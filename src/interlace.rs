@@ -0,0 +1,241 @@
+use wgpu::util::DeviceExt;
+
+use crate::texture;
+
+gpu_struct! {
+	Uniform
+	#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+	struct InterlaceParams {
+		parity: u32,
+		history_valid: u32,
+	}
+}
+
+// the half width targets the main pass renders into, recreated on resize
+pub struct Targets {
+	pub color: texture::Texture,
+	pub surface: texture::Texture, // normal and roughness
+	pub depth: texture::Texture,
+	history: [texture::Texture; 2],
+	bind_groups: [wgpu::BindGroup; 2], // writing into history[i], reading the other
+}
+
+/*
+Experimental interlaced rendering for the WebGL2 and integrated GPU path. The main pass
+renders at half width with the projection shifted by a column every other frame, so the
+rendered columns alternate between even and odd. The full resolution image is rebuilt
+from this frame's columns and last frame's result for the rest, which roughly halves the
+fragment cost at the price of some shimmer in motion.
+The post passes that read the depth buffer are skipped in this mode.
+*/
+pub struct Interlace {
+	pub targets: Targets,
+	params_buffer: wgpu::Buffer,
+	layout: wgpu::BindGroupLayout,
+	pipeline: wgpu::RenderPipeline,
+	surface_format: wgpu::TextureFormat,
+	width: u32, // full resolution
+	frame: u32,
+	history_valid: bool,
+}
+
+impl Interlace {
+	pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, surface_format: wgpu::TextureFormat) -> Self {
+		let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Interlace Params Buffer"),
+			contents: bytemuck::cast_slice(&[InterlaceParams {
+				parity: 0,
+				history_valid: 0,
+				..bytemuck::Zeroable::zeroed()
+			}]),
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+		});
+
+		let texture = |binding| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Texture {
+				multisampled: false,
+				view_dimension: wgpu::TextureViewDimension::D2,
+				sample_type: wgpu::TextureSampleType::Float { filterable: true },
+			},
+			count: None,
+		};
+		let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("interlace_bind_group_layout"),
+			entries: &[
+				texture(0), // this frame's columns
+				texture(1), // last frame's result
+				wgpu::BindGroupLayoutEntry {
+					binding: 2,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+			],
+		});
+
+		let shader = device.create_shader_module(wgpu::include_wgsl!("interlace.wgsl"));
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Interlace Pipeline Layout"),
+			bind_group_layouts: &[Some(&layout)],
+			immediate_size: 0,
+		});
+		let target = Some(wgpu::ColorTargetState {
+			format: config.format,
+			blend: None,
+			write_mask: wgpu::ColorWrites::ALL,
+		});
+		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("Interlace Pipeline"),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: Some("vs_main"),
+				buffers: &[],
+				compilation_options: Default::default(),
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: Some("fs_main"),
+				// the frame, and the history for the next one
+				targets: &[target.clone(), target],
+				compilation_options: Default::default(),
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			multiview_mask: None,
+			cache: None,
+		});
+
+		let targets = Self::create_targets(device, config, surface_format, &layout, &params_buffer);
+
+		Self {
+			targets,
+			params_buffer,
+			layout,
+			pipeline,
+			surface_format,
+			width: config.width,
+			frame: 0,
+			history_valid: false,
+		}
+	}
+
+	fn create_targets(
+		device: &wgpu::Device,
+		config: &wgpu::SurfaceConfiguration,
+		surface_format: wgpu::TextureFormat,
+		layout: &wgpu::BindGroupLayout,
+		params_buffer: &wgpu::Buffer,
+	) -> Targets {
+		let mut half_config = config.clone();
+		half_config.width = config.width.div_ceil(2);
+		let color = texture::Texture::create_render_target(device, &half_config, config.format, wgpu::TextureUsages::RENDER_ATTACHMENT, "interlace_color_texture");
+		let surface = texture::Texture::create_render_target(device, &half_config, surface_format, wgpu::TextureUsages::RENDER_ATTACHMENT, "interlace_surface_texture");
+		let depth = texture::Texture::create_depth_texture(device, &half_config, "interlace_depth_texture");
+		let history: [texture::Texture; 2] = std::array::from_fn(|_| texture::Texture::create_render_target(
+			device,
+			config,
+			config.format,
+			wgpu::TextureUsages::RENDER_ATTACHMENT,
+			"interlace_history_texture",
+		));
+		let bind_groups = std::array::from_fn(|i| device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("interlace_bind_group"),
+			layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&color.view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::TextureView(&history[1 - i].view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: params_buffer.as_entire_binding(),
+				},
+			],
+		}));
+
+		Targets {
+			color,
+			surface,
+			depth,
+			history,
+			bind_groups,
+		}
+	}
+
+	pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+		self.targets = Self::create_targets(device, config, self.surface_format, &self.layout, &self.params_buffer);
+		self.width = config.width;
+		self.reset();
+	}
+
+	// the next frame doesn't take anything from the history, e.g. after the mode was off
+	pub fn reset(&mut self) {
+		self.history_valid = false;
+	}
+
+	fn parity(&self) -> u32 {
+		self.frame & 1
+	}
+
+	/*
+	The half width target's texel centers fall between two full resolution columns, so the
+	projection is shifted by half a column to land on this frame's column instead.
+	*/
+	pub fn jitter(&self, proj: cgmath::Matrix4<f32>) -> cgmath::Matrix4<f32> {
+		let offset = (self.parity() as f32 - 0.5) * 2.0 / self.width.max(1) as f32;
+		cgmath::Matrix4::from_translation(cgmath::Vector3::new(-offset, 0.0, 0.0)) * proj
+	}
+
+	// rebuilds the full resolution frame into view, after the main pass rendered into the targets
+	pub fn resolve(&mut self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+		let index = self.parity() as usize;
+		queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[InterlaceParams {
+			parity: self.parity(),
+			history_valid: self.history_valid as u32,
+			..bytemuck::Zeroable::zeroed()
+		}]));
+
+		{
+			let ops = wgpu::Operations {
+				load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+				store: wgpu::StoreOp::Store,
+			};
+			let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+				label: Some("Interlace Resolve Pass"),
+				color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+					view,
+					resolve_target: None,
+					ops,
+					depth_slice: None,
+				}), Some(wgpu::RenderPassColorAttachment {
+					view: &self.targets.history[index].view,
+					resolve_target: None,
+					ops,
+					depth_slice: None,
+				})],
+				depth_stencil_attachment: None,
+				occlusion_query_set: None,
+				timestamp_writes: None,
+				multiview_mask: None,
+			});
+			render_pass.set_pipeline(&self.pipeline);
+			render_pass.set_bind_group(0, &self.targets.bind_groups[index], &[]);
+			render_pass.draw(0..3, 0..1);
+		}
+
+		self.frame = self.frame.wrapping_add(1);
+		self.history_valid = true;
+	}
+}
@@ -0,0 +1,58 @@
+// Rebuilds the full resolution image from a half width render. This frame's columns come
+// straight from the render, the others from last frame's result, clamped to the columns on
+// either side so anything that moved doesn't smear.
+
+struct Params {
+	parity: u32, // which columns were rendered this frame
+	history_valid: u32, // 0 until there's a previous frame to take the other columns from
+};
+
+@group(0) @binding(0)
+var current_texture: texture_2d<f32>; // half width
+@group(0) @binding(1)
+var history_texture: texture_2d<f32>;
+@group(0) @binding(2)
+var<uniform> params: Params;
+
+struct FragmentOutput {
+	@location(0) color: vec4<f32>,
+	@location(1) history: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+	// one triangle covering the screen
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn load_current(texel: vec2<i32>) -> vec4<f32> {
+	let size = vec2<i32>(textureDimensions(current_texture));
+	return textureLoad(current_texture, clamp(texel, vec2<i32>(0), size - 1), 0);
+}
+
+@fragment
+fn fs_main(@builtin(position) frag_coord: vec4<f32>) -> FragmentOutput {
+	let pixel = vec2<i32>(frag_coord.xy);
+	let parity = i32(params.parity);
+	var color: vec4<f32>;
+	if (pixel.x & 1) == parity {
+		color = load_current(vec2<i32>(pixel.x >> 1u, pixel.y));
+	} else {
+		// the rendered columns to the left and right of this one
+		let left = (pixel.x - 1 - parity) >> 1u;
+		let right = left + 1;
+		let spatial = 0.5 * (load_current(vec2<i32>(left, pixel.y)) + load_current(vec2<i32>(right, pixel.y)));
+		var low = spatial;
+		var high = spatial;
+		for (var y = -1; y <= 1; y++) {
+			let a = load_current(vec2<i32>(left, pixel.y + y));
+			let b = load_current(vec2<i32>(right, pixel.y + y));
+			low = min(low, min(a, b));
+			high = max(high, max(a, b));
+		}
+		let history = clamp(textureLoad(history_texture, pixel, 0), low, high);
+		color = select(spatial, history, params.history_valid != 0u);
+	}
+	return FragmentOutput(color, color);
+}
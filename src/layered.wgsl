@@ -0,0 +1,57 @@
+@group(0) @binding(1)
+var diffuse_texture: texture_2d<f32>;
+@group(0) @binding(2)
+var diffuse_sampler: sampler;
+@group(0) @binding(3)
+var normal_texture: texture_2d<f32>;
+@group(0) @binding(4)
+var normal_sampler: sampler;
+@group(0) @binding(5)
+var mask_texture: texture_2d<f32>;
+@group(0) @binding(6)
+var mask_sampler: sampler;
+@group(0) @binding(7)
+var layer_0_diffuse_texture: texture_2d<f32>;
+@group(0) @binding(8)
+var layer_0_diffuse_sampler: sampler;
+@group(0) @binding(9)
+var layer_0_normal_texture: texture_2d<f32>;
+@group(0) @binding(10)
+var layer_0_normal_sampler: sampler;
+@group(0) @binding(11)
+var layer_1_diffuse_texture: texture_2d<f32>;
+@group(0) @binding(12)
+var layer_1_diffuse_sampler: sampler;
+@group(0) @binding(13)
+var layer_1_normal_texture: texture_2d<f32>;
+@group(0) @binding(14)
+var layer_1_normal_sampler: sampler;
+
+// how much of the base a layer covers where its mask channel is this value
+fn layer_weight(mask: f32, layer: vec4<f32>) -> f32 {
+	// bias grows the covered area, sharpness narrows the transition around the middle of the mask
+	return saturate((mask + layer.z - 0.5) * max(layer.w, 0.001) + 0.5) * layer.y;
+}
+
+fn material_surface(in: VertexOutput) -> MaterialSurface {
+	// the mask is stretched over the mesh once, the red channel places the first layer and green the second
+	let mask = sample_material_texture(mask_texture, mask_sampler, in, 1.0);
+	let base_col = sample_material_texture(diffuse_texture, diffuse_sampler, in, 1.0);
+	let base_norm = sample_material_normal(normal_texture, normal_sampler, in, 1.0);
+
+	let layer_0 = material.layers[0];
+	let weight_0 = layer_weight(mask.x, layer_0);
+	let col_0 = sample_material_texture(layer_0_diffuse_texture, layer_0_diffuse_sampler, in, layer_0.x).xyz;
+	let norm_0 = sample_material_normal(layer_0_normal_texture, layer_0_normal_sampler, in, layer_0.x);
+
+	let layer_1 = material.layers[1];
+	let weight_1 = layer_weight(mask.y, layer_1);
+	let col_1 = sample_material_texture(layer_1_diffuse_texture, layer_1_diffuse_sampler, in, layer_1.x).xyz;
+	let norm_1 = sample_material_normal(layer_1_normal_texture, layer_1_normal_sampler, in, layer_1.x);
+
+	// each layer is laid over everything below it, the base keeps its opacity
+	let obj_col = mix(mix(base_col.xyz, col_0, weight_0), col_1, weight_1);
+	let obj_norm = normalize(mix(mix(base_norm, norm_0, weight_0), norm_1, weight_1));
+
+	return MaterialSurface(vec4<f32>(obj_col, base_col.w), obj_norm);
+}
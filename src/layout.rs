@@ -0,0 +1,226 @@
+/*
+WGSL memory layout of the Rust types shared with shaders, so structs can be checked and padded
+at compile time. Rust lays out [f32; 3] with 4 byte alignment where a WGSL vec3 has 16, and
+uniform buffers round structs up to 16 bytes (std140) where storage buffers only round up to the
+largest member (std430). Only types with the same layout on both sides implement WgslType,
+arrays of scalars and vec2s are left out since their uniform stride differs from Rust's.
+*/
+pub trait WgslType {
+	const ALIGN: usize;
+	const SIZE: usize;
+}
+
+// types arrays can hold with the same stride in Rust as in WGSL
+pub trait ArrayElement: WgslType {}
+
+macro_rules! wgsl_type {
+	($align:literal, $size:literal: $($ty:ty),*) => {
+		$(
+			impl WgslType for $ty {
+				const ALIGN: usize = $align;
+				const SIZE: usize = $size;
+			}
+		)*
+	};
+}
+
+wgsl_type!(4, 4: f32, u32, i32);
+wgsl_type!(8, 8: [f32; 2], [u32; 2], [i32; 2]);
+wgsl_type!(16, 12: [f32; 3], [u32; 3], [i32; 3]);
+wgsl_type!(16, 16: [f32; 4], [u32; 4], [i32; 4]);
+
+impl ArrayElement for [f32; 4] {}
+impl ArrayElement for [u32; 4] {}
+impl ArrayElement for [i32; 4] {}
+
+// also the matrices, a mat4x4 is laid out like four vec4s
+impl<T: ArrayElement, const N: usize> WgslType for [T; N] {
+	const ALIGN: usize = T::ALIGN;
+	const SIZE: usize = N * round_up(T::SIZE, T::ALIGN);
+}
+
+impl<T: ArrayElement, const N: usize> ArrayElement for [T; N] {}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AddressSpace {
+	Uniform,
+	Storage,
+}
+
+pub const fn round_up(value: usize, align: usize) -> usize {
+	value.div_ceil(align) * align
+}
+
+// the alignment of a struct of the fields, as (align, size) pairs
+pub const fn struct_align(fields: &[(usize, usize)], space: AddressSpace) -> usize {
+	let mut align = match space {
+		AddressSpace::Uniform => 16,
+		AddressSpace::Storage => 4,
+	};
+	let mut i = 0;
+	while i < fields.len() {
+		if fields[i].0 > align {
+			align = fields[i].0;
+		}
+		i += 1;
+	}
+	align
+}
+
+// where the field is in the WGSL struct
+pub const fn field_offset(fields: &[(usize, usize)], index: usize) -> usize {
+	let mut offset = 0;
+	let mut i = 0;
+	while i < index {
+		offset = round_up(offset, fields[i].0) + fields[i].1;
+		i += 1;
+	}
+	round_up(offset, fields[index].0)
+}
+
+pub const fn struct_size(fields: &[(usize, usize)], space: AddressSpace) -> usize {
+	let end = match fields.len() {
+		0 => 0,
+		len => field_offset(fields, len - 1) + fields[len - 1].1,
+	};
+	round_up(end, struct_align(fields, space))
+}
+
+// bytes after the last field up to the end of the WGSL struct
+pub const fn trailing_padding(fields: &[(usize, usize)], space: AddressSpace) -> usize {
+	match fields.len() {
+		0 => 0,
+		len => struct_size(fields, space) - field_offset(fields, len - 1) - fields[len - 1].1,
+	}
+}
+
+// fails the build when a Rust field isn't where WGSL puts it
+pub const fn check_offsets(offsets: &[usize], fields: &[(usize, usize)], message: &str) {
+	let mut i = 0;
+	while i < fields.len() {
+		if offsets[i] != field_offset(fields, i) {
+			panic!("{}", message);
+		}
+		i += 1;
+	}
+}
+
+/*
+Declares a #[repr(C)] struct that matches a WGSL struct in the uniform or storage address space.
+The trailing padding is added as a private _padding field, so constructors fill it with
+..bytemuck::Zeroable::zeroed(). A field that isn't at its WGSL offset, like a vec3 right after a
+scalar, fails the build, reorder the fields or add the padding before it by hand.
+
+	gpu_struct! {
+		Uniform
+		#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+		pub struct Params {
+			pub color: [f32; 3],
+			pub strength: f32,
+			frame: u32,
+		}
+	}
+*/
+macro_rules! gpu_struct {
+	(
+		$space:ident
+		$(#[$attr:meta])*
+		$vis:vis struct $name:ident {
+			$($field_vis:vis $field:ident: $ty:ty),* $(,)?
+		}
+	) => {
+		#[repr(C)]
+		$(#[$attr])*
+		$vis struct $name {
+			$($field_vis $field: $ty,)*
+			_padding: [u8; $crate::layout::trailing_padding(
+				&[$((<$ty as $crate::layout::WgslType>::ALIGN, <$ty as $crate::layout::WgslType>::SIZE)),*],
+				$crate::layout::AddressSpace::$space,
+			)],
+		}
+
+		impl $crate::layout::WgslType for $name {
+			const ALIGN: usize = $crate::layout::struct_align(
+				&[$((<$ty as $crate::layout::WgslType>::ALIGN, <$ty as $crate::layout::WgslType>::SIZE)),*],
+				$crate::layout::AddressSpace::$space,
+			);
+			const SIZE: usize = ::std::mem::size_of::<$name>();
+		}
+
+		impl $crate::layout::ArrayElement for $name {}
+
+		const _: () = {
+			let fields = [$((<$ty as $crate::layout::WgslType>::ALIGN, <$ty as $crate::layout::WgslType>::SIZE)),*];
+			$crate::layout::check_offsets(
+				&[$(::std::mem::offset_of!($name, $field)),*],
+				&fields,
+				concat!(stringify!($name), " has a field that isn't at its WGSL offset"),
+			);
+			assert!(
+				::std::mem::size_of::<$name>() == $crate::layout::struct_size(&fields, $crate::layout::AddressSpace::$space),
+				concat!(stringify!($name), " isn't the size of its WGSL struct"),
+			);
+		};
+	};
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	gpu_struct! {
+		Uniform
+		#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+		struct UniformParams {
+			color: [f32; 3],
+			strength: f32,
+			frame: u32,
+		}
+	}
+
+	gpu_struct! {
+		Storage
+		#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+		struct StorageParams {
+			uv: [f32; 2],
+			strength: f32,
+		}
+	}
+
+	gpu_struct! {
+		Storage
+		#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+		struct Nested {
+			transform: [[f32; 4]; 4],
+			params: [StorageParams; 3],
+			frame: u32,
+		}
+	}
+
+	#[test]
+	fn structs_are_padded_to_wgsl_sizes() {
+		// uniform structs round up to 16 bytes, storage ones to their largest member
+		assert_eq!(std::mem::size_of::<UniformParams>(), 32);
+		assert_eq!(std::mem::size_of::<StorageParams>(), 16);
+		assert_eq!(StorageParams::ALIGN, 8);
+		assert_eq!(<[[f32; 4]; 4]>::SIZE, 64);
+		assert_eq!(std::mem::offset_of!(Nested, params), 64);
+		assert_eq!(std::mem::offset_of!(Nested, frame), 112);
+		assert_eq!(std::mem::size_of::<Nested>(), 128);
+	}
+
+	#[test]
+	fn fields_are_placed_by_wgsl_alignment() {
+		let scalar = (f32::ALIGN, f32::SIZE);
+		let vec3 = (<[f32; 3]>::ALIGN, <[f32; 3]>::SIZE);
+		// a vec3 after a scalar starts on the next 16 bytes, where Rust would put it at 4
+		assert_eq!(field_offset(&[scalar, vec3], 1), 16);
+		assert_eq!(struct_size(&[scalar, vec3], AddressSpace::Storage), 32);
+		// a scalar after a vec3 fills its last 4 bytes
+		assert_eq!(field_offset(&[vec3, scalar], 1), 12);
+		assert_eq!(trailing_padding(&[vec3, scalar], AddressSpace::Uniform), 0);
+		assert_eq!(trailing_padding(&[scalar], AddressSpace::Uniform), 12);
+		assert_eq!(trailing_padding(&[scalar], AddressSpace::Storage), 0);
+		assert_eq!(struct_size(&[], AddressSpace::Uniform), 0);
+	}
+}
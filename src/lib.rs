@@ -1,10 +1,80 @@
-mod texture;
-mod camera;
-mod model;
-mod resources;
-mod scene;
-mod renderer;
-mod light;
+#[macro_use]
+mod layout;
+pub mod texture;
+#[cfg(feature = "ktx2")]
+mod compressed;
+mod bind_group;
+pub mod assets;
+pub mod camera;
+pub mod model;
+pub mod resources;
+pub mod scene;
+pub mod renderer;
+pub mod light;
+mod material_file;
+mod scene_file;
+mod globals;
+pub mod environment;
+mod uniform_arena;
+mod frame;
+mod readback;
+mod skinning;
+mod crowd;
+mod ray_tracing;
+mod reflections;
+mod meshlet;
+mod imposter;
+mod capabilities;
+mod shaders;
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+mod shader_reload;
+mod viewer;
+mod console;
+mod fade;
+pub mod scene_manager;
+mod time;
+mod shadow;
+mod point_shadow;
+mod cube_scheduler;
+mod shadow_map;
+mod contact_shadow;
+mod capsule_shadow;
+mod gtao;
+mod ibl;
+mod post;
+mod temporal;
+mod upsample;
+mod interlace;
+mod render_scale;
+mod color;
+mod virtual_camera;
+mod skybox;
+mod debug_draw;
+mod instancing;
+pub mod vat;
+mod modifier;
+pub mod csg;
+pub mod normal_bake;
+mod unwrap;
+mod spline;
+mod sequencer;
+mod view_mode;
+mod navmesh;
+mod frame_graph;
+pub mod profiler;
+mod particles;
+#[cfg(test)]
+mod reflection;
+pub mod deferred;
+#[cfg(feature = "text")]
+mod text;
+pub mod prelude;
+#[cfg(feature = "inspector")]
+mod inspector;
+#[cfg(target_arch = "wasm32")]
+mod web_drop;
+#[cfg(test)]
+mod test_meshes;
 
 
 use winit::{
@@ -13,9 +83,13 @@ use winit::{
 
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
-use cgmath::prelude::*;
 use std::sync::Arc;
 
+// the crates in the public API, so users build against the same versions
+pub use cgmath;
+pub use wgpu;
+pub use winit;
+
 struct Instance {
 	position: cgmath::Vector3<f32>,
 	rotation: cgmath::Quaternion<f32>,
@@ -70,48 +144,81 @@ impl InstanceRaw {
 	}
 }
 
+const WINDOW_TITLE: &str = "WebGPU yay";
+
 pub struct State {
 	pub window: Arc<Window>,
-	renderer: renderer::Renderer,
-	scene: scene::Scene,
+	pub renderer: renderer::Renderer,
+	pub scene: scene::Scene,
+	pub scenes: scene_manager::SceneManager, // request the next scene here, it fades in once loaded
+	camera_mode: camera::CameraMode, // switched with C
 	camera_controller: camera::CameraController,
+	first_person: camera::FirstPersonController,
+	orbit: camera::OrbitController,
+	mouse_look: bool, // the right mouse button is held, the first person controller has the camera
+	light_orbit: light::LightOrbit,
+	turntable: viewer::Turntable,
+	time_control: time::TimeControl,
+	sequencer: Option<sequencer::Sequencer>, // a cinematic playing, started with the sequence command
+	console: console::Console,
+	selected: Option<usize>, // object the camera focuses on with F, the last one added
+	last_update: web_time::Instant,
+	modifiers: winit::keyboard::ModifiersState,
+	#[cfg(target_arch = "wasm32")]
+	drop_target: web_drop::DropTarget,
 }
 
 impl State {
-	pub async fn new(window: Arc<Window>) -> anyhow::Result<Self> {
+	pub async fn new(window: Arc<Window>, options: renderer::RendererOptions, startup_scene: fn(f32) -> scene_manager::SceneRequest) -> anyhow::Result<Self> {
 		// create renderer
-		let renderer = renderer::Renderer::new(&window).await.unwrap();
-
-		let mut scene = scene::Scene::new(
-			light::LightUniform::new(),
-			camera::Camera {
-				eye: (0.0, 1.0, 2.0).into(),
-				target: (0.0, 0.0, 0.0).into(),
-				up: cgmath::Vector3::unit_y(),
-				aspect: window.inner_size().width as f32 / window.inner_size().height as f32,
-				fovy: 45.0,
-				znear: 0.1,
-				zfar: 100.0,
-			},
-		);
+		let mut renderer = renderer::Renderer::new(&window, options).await?;
 
-		let camera_controller = camera::CameraController::new(0.05);
+		let aspect = window.inner_size().width as f32 / window.inner_size().height as f32;
+		let scene = scene_manager::SceneManager::load(startup_scene(aspect), &mut renderer).await?;
+		let scenes = scene_manager::SceneManager::new();
 
-		renderer.update_light(&scene.light);
+		let camera_controller = camera::CameraController::new(0.05);
+		let first_person = camera::FirstPersonController::new(0.05, 0.003);
+		let orbit = camera::OrbitController::new(0.01, 0.002, 0.1);
+
+		let light = scene.light_handle(0);
+		let light_orbit = light::LightOrbit {
+			light,
+			center: [0.0, 0.0, 0.0],
+			radius: 2.8,
+			height: 1.0,
+			speed: 0.5,
+		};
+		let turntable = viewer::Turntable::new(light);
+		let mut console = console::Console::new();
+		console.register_default_commands();
 
-		let obj = resources::load_model("dragon.obj", &renderer, &mut scene).await.unwrap();
-		scene.add_object(
-			model::ModelInstance {
-				model_index: obj,
-				transform: cgmath::Matrix4::identity(),
-			}
-		);
+		#[cfg(target_arch = "wasm32")]
+		let drop_target = {
+			use winit::platform::web::WindowExtWebSys;
+			web_drop::DropTarget::new(&window.canvas().unwrap())
+		};
 
 		Ok(Self {
 			window,
 			renderer,
 			scene,
+			scenes,
+			camera_mode: camera::CameraMode::FirstPerson,
 			camera_controller,
+			first_person,
+			orbit,
+			mouse_look: false,
+			light_orbit,
+			turntable,
+			time_control: time::TimeControl::new(),
+			sequencer: None,
+			console,
+			selected: None,
+			last_update: web_time::Instant::now(),
+			modifiers: winit::keyboard::ModifiersState::empty(),
+			#[cfg(target_arch = "wasm32")]
+			drop_target,
 		})
 	}
 
@@ -125,17 +232,267 @@ impl State {
 	pub fn handle_key(&mut self, event_loop: &ActiveEventLoop, code: KeyCode, is_pressed: bool) {
 		if code == KeyCode::Escape && is_pressed {
 			event_loop.exit();
-		} else {
+		} else if code == KeyCode::KeyO && is_pressed && self.modifiers.control_key() {
+			// ctrl+o replaces the scene's objects, ctrl+shift+o adds to them
+			#[cfg(all(not(target_arch = "wasm32"), feature = "file-dialog"))]
+			self.open_model(!self.modifiers.shift_key());
+		} else if code == KeyCode::Backquote && is_pressed {
+			self.console.toggle();
+			self.update_title();
+		} else if code == KeyCode::F1 && is_pressed {
+			let mode = self.renderer.cycle_view_mode();
+			log::info!("{:?} view", mode);
+		} else if code == KeyCode::F2 && is_pressed {
+			#[cfg(feature = "inspector")]
+			if let Some(inspector) = &mut self.renderer.inspector {
+				inspector.toggle();
+			}
+		} else if code == KeyCode::KeyF && is_pressed {
+			self.focus_selected();
+		} else if code == KeyCode::KeyT && is_pressed {
+			self.turntable.toggle(&mut self.scene);
+		} else if code == KeyCode::KeyC && is_pressed {
+			self.set_camera_mode(match self.camera_mode {
+				camera::CameraMode::FirstPerson => camera::CameraMode::Orbit,
+				camera::CameraMode::Orbit => camera::CameraMode::FirstPerson,
+			});
+		} else if !self.time_control.handle_key(code, is_pressed) && !self.turntable.handle_key(code, is_pressed) {
+			// both see every key, so a key released after switching doesn't stay held
 			self.camera_controller.handle_key(code, is_pressed);
+			self.first_person.handle_key(code, is_pressed);
+		}
+	}
+
+	fn set_camera_mode(&mut self, mode: camera::CameraMode) {
+		self.set_mouse_look(false);
+		self.camera_mode = mode;
+		log::info!("{:?} camera", mode);
+	}
+
+	// the buttons each camera mode drags with
+	fn handle_mouse_button(&mut self, button: MouseButton, is_pressed: bool) {
+		match self.camera_mode {
+			camera::CameraMode::FirstPerson if button == MouseButton::Right => self.set_mouse_look(is_pressed),
+			camera::CameraMode::FirstPerson => {}
+			camera::CameraMode::Orbit => self.orbit.handle_mouse_button(button, is_pressed),
+		}
+	}
+
+	// grabs and hides the cursor while the first person controller has the camera
+	fn set_mouse_look(&mut self, enabled: bool) {
+		if enabled == self.mouse_look {
+			return;
+		}
+		self.mouse_look = enabled;
+		if enabled {
+			self.first_person.look_from(&self.scene.camera);
+			// not every platform can lock the cursor in place
+			let grabbed = self.window.set_cursor_grab(winit::window::CursorGrabMode::Locked)
+				.or_else(|_| self.window.set_cursor_grab(winit::window::CursorGrabMode::Confined));
+			if let Err(e) = grabbed {
+				log::warn!("Unable to grab the cursor: {}", e);
+			}
+		} else {
+			let _ = self.window.set_cursor_grab(winit::window::CursorGrabMode::None);
 		}
+		self.window.set_cursor_visible(!enabled);
 	}
 
 	fn update(&mut self) {
-		self.camera_controller.update_camera(&mut self.scene.camera);
+		let now = web_time::Instant::now();
+		let delta_time = (now - self.last_update).as_secs_f32();
+		self.last_update = now;
+
+		if self.scenes.update(&mut self.renderer, &mut self.scene, delta_time) {
+			self.scene_swapped();
+		}
+		// scene time can be scaled, paused, and stepped, the camera always moves in real time
+		self.scene.delta_time = self.time_control.advance(delta_time);
+		self.scene.time += self.scene.delta_time;
+		self.scene.advance_vertex_animations(self.scene.delta_time);
+
+		// the inspector edits the scene before anything below reads it
+		#[cfg(feature = "inspector")]
+		if let Some(inspector) = &mut self.renderer.inspector {
+			inspector.run(&self.window, &mut self.scene, &self.renderer.assets, &self.renderer.device);
+		}
+		self.renderer.update_assets(&mut self.scene);
+		spline::update_ribbons(&self.renderer, &mut self.scene);
+		self.scene.update_transforms();
+		if let Some(navmesh) = &self.scene.navmesh {
+			navmesh.draw(&mut self.renderer.debug_draw);
+		}
+		self.renderer.debug_draw.draw_helpers(&self.scene);
+		#[cfg(feature = "text")]
+		if self.renderer.show_fps && delta_time > 0.0 {
+			let fps = format!("{:.0} fps\n{:.2} ms", 1.0 / delta_time, delta_time * 1000.0);
+			self.renderer.draw_text(&fps, [8.0, 8.0], 18.0, [1.0, 1.0, 0.3, 1.0]);
+		}
+		// the profiler's timings under the frame rate
+		#[cfg(feature = "text")]
+		if self.renderer.profiler.enabled {
+			let stats = self.renderer.frame_stats().to_string();
+			self.renderer.draw_text(&stats, [8.0, 56.0], 14.0, [0.8, 1.0, 0.8, 1.0]);
+		}
+		#[cfg(feature = "text")]
+		if self.console.open {
+			let text = self.console.text(12);
+			let top = self.window.inner_size().height as f32 - 8.0 - 16.0 * text.lines().count() as f32;
+			self.renderer.draw_text(&text, [8.0, top], 16.0, [1.0, 1.0, 1.0, 1.0]);
+		}
+
+		// the orbit controller moves the camera as its mouse events arrive
+		if self.camera_mode == camera::CameraMode::FirstPerson {
+			if self.mouse_look {
+				self.first_person.update_camera(&mut self.scene.camera);
+			} else {
+				self.camera_controller.update_camera(&mut self.scene.camera);
+			}
+		}
+		if self.turntable.enabled {
+			self.turntable.update(&mut self.scene, delta_time);
+		} else {
+			self.light_orbit.update(&mut self.scene);
+		}
+		// after the controllers, so the sequence has the camera while it plays
+		if let Some(sequencer) = &mut self.sequencer {
+			sequencer.update(&mut self.scene, &mut self.renderer.fade);
+			if sequencer.is_finished() {
+				self.sequencer.take().unwrap().stop(&mut self.renderer.fade);
+			}
+		}
+
+		#[cfg(target_arch = "wasm32")]
+		for batch in self.drop_target.take() {
+			self.add_dropped_files(batch);
+		}
+	}
+
+	/*
+	Makes the dropped files available to the resource loaders, then spawns every OBJ and glTF
+	among them at the origin. Textures and material files are only registered, for the models that
+	reference them.
+	*/
+	#[cfg(target_arch = "wasm32")]
+	fn add_dropped_files(&mut self, files: Vec<web_drop::DroppedFile>) {
+		let mut models = vec![];
+		for file in files {
+			let extension = std::path::Path::new(&file.name).extension()
+				.and_then(|extension| extension.to_str())
+				.map(|extension| extension.to_lowercase());
+			match extension.as_deref() {
+				Some("obj" | "gltf" | "glb") => models.push(file.name.clone()),
+				_ => {}
+			}
+			resources::add_memory_file(&file.name, file.data);
+		}
+
+		for name in models {
+			match resources::load_from_memory(resources::load_model(&name, &self.renderer, &mut self.scene)) {
+				Ok(model_index) => self.add_model_object(model_index),
+				Err(e) => log::error!("Unable to load {}: {}", name, e),
+			}
+		}
+	}
+
+	// picks a model with the file dialog, loads it, and frames the camera on it
+	#[cfg(all(not(target_arch = "wasm32"), feature = "file-dialog"))]
+	fn open_model(&mut self, replace: bool) {
+		let Some(path) = rfd::FileDialog::new()
+			.set_title("Open model")
+			.add_filter("Models", &["obj", "gltf", "glb"])
+			.pick_file()
+		else {
+			return;
+		};
+
+		let filename = path.to_string_lossy();
+		match pollster::block_on(resources::load_model(&filename, &self.renderer, &mut self.scene)) {
+			Ok(model_index) => {
+				if replace {
+					self.scene.clear_objects();
+				}
+				self.add_model_object(model_index);
+				self.focus_selected();
+			}
+			Err(e) => log::error!("Unable to load {}: {}", filename, e),
+		}
+	}
+
+	#[cfg(any(target_arch = "wasm32", feature = "file-dialog"))]
+	fn add_model_object(&mut self, model_index: usize) {
+		self.scene.add_object(model::ModelInstance::new(model_index, cgmath::Matrix4::from_scale(1.0)));
+		self.selected = Some(self.scene.objects.len() - 1);
+	}
+
+	// handles into the old scene are reset for the new one
+	fn scene_swapped(&mut self) {
+		let size = self.window.inner_size();
+		self.scene.camera.update_aspect(size.width, size.height);
+		self.selected = None;
+		// the sequence's objects were the old scene's
+		if let Some(sequencer) = self.sequencer.take() {
+			sequencer.stop(&mut self.renderer.fade);
+		}
+		let light = self.scene.light_handle(0);
+		self.light_orbit.light = light;
+		self.turntable.light = light;
+		if self.turntable.enabled {
+			self.turntable.frame(&mut self.scene);
+		}
+	}
+
+	// keys go to the console's input line while it's open
+	fn handle_console_key(&mut self, event: &KeyEvent) {
+		if let Some(line) = self.console.handle_key(event) {
+			self.console.execute(&mut console::Context {
+				renderer: &mut self.renderer,
+				scene: &mut self.scene,
+				scenes: &mut self.scenes,
+				sequencer: &mut self.sequencer,
+			}, &line);
+		}
+		self.update_title();
+	}
+
+	fn update_title(&self) {
+		if self.console.open {
+			self.window.set_title(&format!("{} > {}", WINDOW_TITLE, self.console.input()));
+		} else {
+			self.window.set_title(WINDOW_TITLE);
+		}
 	}
 
-	pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-		self.renderer.render(&self.window, &self.scene.camera, &self.scene)
+	// frames the selected object, or the whole scene if nothing is selected
+	fn focus_selected(&mut self) {
+		let aabb = match self.selected.filter(|&index| index < self.scene.objects.len()) {
+			Some(index) => Some(self.scene.object_aabb(index)),
+			None => self.scene.aabb(),
+		};
+		if let Some(aabb) = aabb {
+			self.scene.camera.fit_to_bounds(&aabb, 0.1);
+		}
+	}
+
+	pub fn render(&mut self) -> Result<(), renderer::SurfaceError> {
+		self.renderer.update_lights(&mut self.scene);
+		self.window.request_redraw();
+		self.renderer.render(&self.scene.camera, &self.scene)
+	}
+}
+
+// scene.ron, the dragon with the materials from materials.toml, shown at startup
+fn demo_scene(aspect: f32) -> scene_manager::SceneRequest {
+	scene_manager::SceneRequest {
+		files: vec!["scene.ron".to_string()],
+		camera: camera::Camera::new(aspect),
+		setup: Box::new(|renderer, scene| {
+			*scene = resources::load_from_memory(scene::Scene::from_file("scene.ron", renderer, scene.camera.aspect))?;
+			if let Some(dragon) = scene.file_model("dragon.obj") {
+				renderer.bake_imposter(scene, dragon, 128, 30.0);
+			}
+			Ok(())
+		}),
 	}
 }
 
@@ -143,6 +500,9 @@ pub struct App {
 	#[cfg(target_arch = "wasm32")]
 	proxy: Option<winit::event_loop::EventLoopProxy<State>>,
 	state: Option<State>,
+	pub gpu_info: bool, // print the capabilities report and exit instead of running
+	pub options: renderer::RendererOptions, // render path, present mode, frame latency, and which GPU
+	pub startup_scene: fn(f32) -> scene_manager::SceneRequest, // given the window's aspect ratio, the demo scene by default
 }
 
 impl App {
@@ -153,6 +513,9 @@ impl App {
 			state: None,
 			#[cfg(target_arch = "wasm32")]
 			proxy,
+			gpu_info: false,
+			options: renderer::RendererOptions::default(),
+			startup_scene: demo_scene,
 		}
 	}
 }
@@ -177,18 +540,28 @@ impl ApplicationHandler<State> for App {
 		}
 
 		let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
-		window.set_title("WebGPU yay");
+		window.set_title(WINDOW_TITLE);
 
 		#[cfg(not(target_arch = "wasm32"))]
 		{
-			self.state = Some(pollster::block_on(State::new(window)).unwrap());
+			if self.gpu_info {
+				match pollster::block_on(capabilities::Capabilities::query(window)) {
+					Ok(capabilities) => println!("{}", capabilities),
+					Err(e) => eprintln!("Unable to query the GPU: {}", e),
+				}
+				event_loop.exit();
+				return;
+			}
+			self.state = Some(pollster::block_on(State::new(window, self.options, self.startup_scene)).unwrap());
 		}
 
 		#[cfg(target_arch = "wasm32")]
 		{
 			if let Some(proxy) = self.proxy.take() {
+				let options = self.options;
+				let startup_scene = self.startup_scene;
 				wasm_bindgen_futures::spawn_local(async move {
-					assert!(proxy.send_event(State::new(window).await.expect("Unable to create canvas!")).is_ok())
+					assert!(proxy.send_event(State::new(window, options, startup_scene).await.expect("Unable to create canvas!")).is_ok())
 				});
 			}
 		}
@@ -218,14 +591,22 @@ impl ApplicationHandler<State> for App {
 			None => return,
 		};
 
+		#[cfg(feature = "inspector")]
+		if let Some(inspector) = &mut state.renderer.inspector
+			&& inspector.handle_event(&state.window, &event)
+		{
+			return;
+		}
+
 		match event {
 			WindowEvent::CloseRequested => event_loop.exit(),
 			WindowEvent::Resized(size) => state.resize(size.width, size.height),
+			WindowEvent::ModifiersChanged(modifiers) => state.modifiers = modifiers.state(),
 			WindowEvent::RedrawRequested => {
 				state.update();
 				match state.render() {
 					Ok(_) => {},
-					Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+					Err(renderer::SurfaceError::Lost | renderer::SurfaceError::Outdated) => {
 						let size = state.window.inner_size();
 						state.resize(size.width, size.height);
 					}
@@ -234,6 +615,7 @@ impl ApplicationHandler<State> for App {
 					}
 				}
 			}
+			WindowEvent::KeyboardInput { event, .. } if state.console.open => state.handle_console_key(&event),
 			WindowEvent::KeyboardInput {
 				event:
 					KeyEvent {
@@ -243,6 +625,24 @@ impl ApplicationHandler<State> for App {
 					},
 					..
 			} => state.handle_key(event_loop, code, key_state.is_pressed()),
+			WindowEvent::MouseInput { button, state: button_state, .. } => state.handle_mouse_button(button, button_state.is_pressed()),
+			WindowEvent::CursorMoved { position, .. } if state.camera_mode == camera::CameraMode::Orbit => {
+				state.orbit.handle_cursor_moved(&mut state.scene.camera, (position.x, position.y));
+			}
+			WindowEvent::MouseWheel { delta, .. } if state.camera_mode == camera::CameraMode::Orbit => {
+				state.orbit.handle_scroll(&mut state.scene.camera, delta);
+			}
+			WindowEvent::Focused(false) => state.set_mouse_look(false),
+			_ => {}
+		}
+	}
+
+	fn device_event(&mut self, _event_loop: &ActiveEventLoop, _device_id: DeviceId, event: DeviceEvent) {
+		let Some(state) = &mut self.state else {
+			return;
+		};
+		match event {
+			DeviceEvent::MouseMotion { delta } if state.mouse_look => state.first_person.handle_mouse_motion(delta),
 			_ => {}
 		}
 	}
@@ -263,6 +663,13 @@ pub fn run() -> anyhow::Result<()> {
 		#[cfg(target_arch = "wasm32")]
 		&event_loop,
 	);
+	app.gpu_info = std::env::args().any(|arg| arg == "--gpu-info");
+	if std::env::args().any(|arg| arg == "--deferred") {
+		app.options.render_path = deferred::RenderPath::Deferred;
+	}
+	if std::env::args().any(|arg| arg == "--no-vsync") {
+		app.options.present_mode = wgpu::PresentMode::AutoNoVsync;
+	}
 	event_loop.run_app(&mut app)?;
 
 	Ok(())
@@ -1,19 +1,323 @@
-#[repr(C)]
-#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct LightUniform {
-	position: [f32; 3],
-	_padding: u32,
-	color: [f32; 3],
-	_padding2: u32,
+use crate::{color, scene};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Falloff {
+	None = 0, // constant intensity at any distance
+	InverseSquare = 1, // physically based, windowed to reach zero at the range
+	Linear = 2,
 }
 
-impl LightUniform {
+// how a light's direction and attenuation are found, the values match the shaders
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LightKind {
+	Point,
+	Directional, // parallel rays along the direction, position and range are unused
+	Spot {
+		inner_angle: f32, // radians from the direction where the cone starts to fade
+		outer_angle: f32, // radians from the direction where the cone reaches zero
+	},
+}
+
+impl LightKind {
+	fn index(&self) -> u32 {
+		match self {
+			LightKind::Point => 0,
+			LightKind::Directional => 1,
+			LightKind::Spot { .. } => 2,
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Light {
+	pub kind: LightKind,
+	pub position: [f32; 3],
+	pub direction: [f32; 3], // where directional and spot lights point
+	pub color: [f32; 3],
+	pub intensity: f32, // candela for inverse square falloff, lux for directional lights, otherwise a plain multiplier
+	pub range: f32, // distance where the light reaches zero
+	pub falloff: Falloff,
+}
+
+impl Default for Light {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Light {
 	pub fn new() -> Self {
 		Self {
+			kind: LightKind::Point,
 			position: [2.0, 1.0, 2.0],
-			_padding: 0,
+			direction: [0.0, -1.0, 0.0],
 			color: [1.0, 1.0, 1.0],
-			_padding2: 0,
+			intensity: 10.0,
+			range: 20.0,
+			falloff: Falloff::InverseSquare,
+		}
+	}
+
+	pub fn directional(direction: [f32; 3], illuminance: f32) -> Self {
+		Self {
+			kind: LightKind::Directional,
+			direction,
+			intensity: illuminance,
+			..Self::new()
+		}
+	}
+
+	pub fn spot(position: [f32; 3], direction: [f32; 3], inner_angle: f32, outer_angle: f32) -> Self {
+		Self {
+			kind: LightKind::Spot { inner_angle, outer_angle },
+			position,
+			direction,
+			..Self::new()
+		}
+	}
+
+	pub fn set_temperature(&mut self, kelvin: f32) {
+		self.color = kelvin_to_rgb(kelvin);
+	}
+
+	// sets the intensity of a point light from its total luminous power
+	pub fn set_lumens(&mut self, lumens: f32) {
+		self.intensity = lumens_to_candela(lumens);
+	}
+
+	pub fn to_uniform(&self) -> LightUniform {
+		let spot_cos = match self.kind {
+			LightKind::Spot { inner_angle, outer_angle } => [outer_angle.cos(), inner_angle.cos()],
+			_ => [-1.0, -1.0],
+		};
+		LightUniform {
+			position: self.position,
+			intensity: self.intensity,
+			color: self.color,
+			range: self.range,
+			direction: self.direction,
+			kind: self.kind.index(),
+			falloff: self.falloff as u32,
+			shadow_index: -1,
+			spot_cos,
+			view_proj: [[0.0; 4]; 4],
+			..bytemuck::Zeroable::zeroed()
+		}
+	}
+}
+
+// a light as the shaders read it, see Light
+gpu_struct! {
+	Uniform
+	#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+	pub struct LightUniform {
+		pub position: [f32; 3],
+		intensity: f32,
+		color: [f32; 3],
+		pub range: f32,
+		direction: [f32; 3],
+		kind: u32,
+		falloff: u32,
+		shadow_index: i32, // cube in the point shadow maps, -1 without shadows
+		spot_cos: [f32; 2], // cosines of the outer and inner cone angles
+		view_proj: [[f32; 4]; 4], // light space of the downlevel shadow map
+	}
+}
+
+impl LightUniform {
+	pub fn is_directional(&self) -> bool {
+		self.kind == LightKind::Directional.index()
+	}
+
+	pub fn direction(&self) -> [f32; 3] {
+		self.direction
+	}
+
+	// assigned by the renderer when the light's shadows are rendered
+	pub fn set_shadow_index(&mut self, index: Option<u32>) {
+		self.shadow_index = index.map_or(-1, |index| index as i32);
+	}
+
+	pub fn set_view_proj(&mut self, view_proj: cgmath::Matrix4<f32>) {
+		self.view_proj = view_proj.into();
+	}
+}
+
+gpu_struct! {
+	Uniform
+	#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+	struct LightsHeader {
+		count: u32,
+	}
+}
+
+/*
+The scene's lights on the GPU, a count followed by the lights, uploaded every frame. The full
+tier reads them from a storage buffer, the downlevel tier from a uniform array small enough for
+WebGL2's uniform buffer limits. Lights past the capacity are dropped.
+*/
+pub struct Lights {
+	pub buffer: wgpu::Buffer,
+	storage: bool,
+	capacity: usize,
+}
+
+impl Lights {
+	pub const MAX_LIGHTS: usize = 64;
+	pub const DOWNLEVEL_MAX_LIGHTS: usize = 16; // matches the array in lights_downlevel.wgsl
+
+	// storage is whether the material shaders read a storage buffer, fragment_storage_buffers
+	// whether the full screen passes can read one anyway
+	pub fn new(device: &wgpu::Device, storage: bool, fragment_storage_buffers: bool) -> Self {
+		let capacity = if storage { Self::MAX_LIGHTS } else { Self::DOWNLEVEL_MAX_LIGHTS };
+		let mut usage = wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST;
+		if fragment_storage_buffers {
+			usage |= wgpu::BufferUsages::STORAGE;
+		}
+		let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Lights Buffer"),
+			size: (std::mem::size_of::<LightsHeader>() + capacity * std::mem::size_of::<LightUniform>()) as u64,
+			usage,
+			mapped_at_creation: false,
+		});
+		Self {
+			buffer,
+			storage,
+			capacity,
+		}
+	}
+
+	// how the material shaders bind the buffer
+	pub fn binding_type(&self) -> wgpu::BufferBindingType {
+		if self.storage {
+			wgpu::BufferBindingType::Storage { read_only: true }
+		} else {
+			wgpu::BufferBindingType::Uniform
 		}
 	}
-}
\ No newline at end of file
+
+	// the entry for full screen passes, which always read the lights from storage
+	pub fn storage_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+		wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Buffer {
+				ty: wgpu::BufferBindingType::Storage { read_only: true },
+				has_dynamic_offset: false,
+				min_binding_size: None,
+			},
+			count: None,
+		}
+	}
+
+	pub fn upload(&self, queue: &wgpu::Queue, lights: &[LightUniform]) {
+		let lights = &lights[..lights.len().min(self.capacity)];
+		let header = LightsHeader {
+			count: lights.len() as u32,
+			..bytemuck::Zeroable::zeroed()
+		};
+		queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[header]));
+		if !lights.is_empty() {
+			queue.write_buffer(&self.buffer, std::mem::size_of::<LightsHeader>() as u64, bytemuck::cast_slice(lights));
+		}
+	}
+}
+
+// linear rgb of a black body at the temperature, normalized so the brightest channel is 1
+// approximation from Tanner Helland, valid from 1000K to 40000K
+pub fn kelvin_to_rgb(kelvin: f32) -> [f32; 3] {
+	let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+
+	let r = if t <= 66.0 {
+		255.0
+	} else {
+		329.69873 * (t - 60.0).powf(-0.13320476)
+	};
+	let g = if t <= 66.0 {
+		99.4708 * t.ln() - 161.11957
+	} else {
+		288.12216 * (t - 60.0).powf(-0.075514846)
+	};
+	let b = if t >= 66.0 {
+		255.0
+	} else if t <= 19.0 {
+		0.0
+	} else {
+		138.51773 * (t - 10.0).ln() - 305.0448
+	};
+
+	[r, g, b].map(|c| color::srgb_to_linear((c / 255.0).clamp(0.0, 1.0)))
+}
+
+// luminous intensity of an isotropic point light emitting the luminous power
+pub fn lumens_to_candela(lumens: f32) -> f32 {
+	lumens / (4.0 * std::f32::consts::PI)
+}
+
+pub fn candela_to_lumens(candela: f32) -> f32 {
+	candela * 4.0 * std::f32::consts::PI
+}
+
+// illuminance at a distance from a point light, with inverse square falloff
+pub fn candela_to_lux(candela: f32, distance: f32) -> f32 {
+	candela / (distance * distance)
+}
+
+// point light intensity needed to reach the illuminance at a distance
+pub fn lux_to_candela(lux: f32, distance: f32) -> f32 {
+	lux * distance * distance
+}
+
+// exposure value at ISO 100 for a physical camera
+pub fn ev100(aperture: f32, shutter_time: f32, iso: f32) -> f32 {
+	(aperture * aperture / shutter_time * 100.0 / iso).log2()
+}
+
+// scale applied to scene luminance so the exposure value maps to the display range
+pub fn ev100_to_exposure(ev100: f32) -> f32 {
+	1.0 / (1.2 * 2.0_f32.powf(ev100))
+}
+
+// moves a light in a horizontal circle around a point over time
+pub struct LightOrbit {
+	pub light: Option<scene::LightHandle>, // None in scenes without lights
+	pub center: [f32; 3],
+	pub radius: f32,
+	pub height: f32,
+	pub speed: f32, // radians per second
+}
+
+impl LightOrbit {
+	pub fn update(&self, scene: &mut scene::Scene) {
+		let Some(light) = self.light else {
+			return;
+		};
+		let angle = scene.time * self.speed;
+		scene.set_light_position(light, [
+			self.center[0] + self.radius * angle.cos(),
+			self.center[1] + self.height,
+			self.center[2] + self.radius * angle.sin(),
+		]);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn daylight_is_about_white() {
+		let [r, g, b] = kelvin_to_rgb(6500.0);
+		assert!(r > 0.99 && g > 0.95 && b > 0.9, "{:?}", [r, g, b]);
+		assert!(r >= b, "6500K shouldn't be blue, {:?}", [r, g, b]);
+	}
+
+	#[test]
+	fn warm_and_cool_temperatures() {
+		let [r, _, b] = kelvin_to_rgb(2700.0);
+		assert!(r > b * 2.0);
+		let [r, _, b] = kelvin_to_rgb(10000.0);
+		assert!(b > r);
+	}
+}
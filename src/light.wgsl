@@ -1,5 +1,11 @@
 struct Camera {
+	view: mat4x4<f32>,
+	proj: mat4x4<f32>,
 	view_proj: mat4x4<f32>,
+	inv_view: mat4x4<f32>,
+	inv_proj: mat4x4<f32>,
+	inv_view_proj: mat4x4<f32>,
+	position: vec4<f32>,
 };
 
 @group(0) @binding(0)
@@ -7,7 +13,11 @@ var<uniform> camera: Camera;
 
 struct Light {
 	position: vec3<f32>,
+	intensity: f32,
 	color: vec3<f32>,
+	range: f32,
+	falloff: u32,
+	shadow_index: i32,
 };
 
 @group(1) @binding(0)
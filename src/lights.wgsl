@@ -0,0 +1,12 @@
+// The scene lights for the full tier, read from a storage buffer
+
+struct Lights {
+	count: u32,
+	lights: array<Light>,
+};
+@group(2) @binding(3)
+var<storage, read> lights: Lights;
+
+fn light_count() -> u32 {
+	return min(lights.count, arrayLength(&lights.lights));
+}
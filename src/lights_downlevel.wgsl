@@ -0,0 +1,14 @@
+// The scene lights for the downlevel tier, a uniform array within WebGL2's limits
+
+const MAX_LIGHTS: u32 = 16u; // Lights::DOWNLEVEL_MAX_LIGHTS
+
+struct Lights {
+	count: u32,
+	lights: array<Light, MAX_LIGHTS>,
+};
+@group(2) @binding(3)
+var<uniform> lights: Lights;
+
+fn light_count() -> u32 {
+	return min(lights.count, MAX_LIGHTS);
+}
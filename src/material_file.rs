@@ -0,0 +1,308 @@
+use std::collections::BTreeMap;
+use crate::{model, texture};
+
+/*
+Material definitions loaded from a TOML file. Each table is a material,
+keyed by the name scenes and OBJ files use to reference it:
+
+[Brick]
+shader = "diffuse_normal"
+diffuse_texture = "brick_color.png"
+normal_texture = "brick_normal.png"
+specular = 0.2
+cull = "none"
+
+A layered material blends up to two layers over its base, placed by the red and green channels
+of its mask. A layer without textures of its own uses the base's:
+
+[MossyRock]
+shader = "layered"
+diffuse_texture = "rock_color.png"
+normal_texture = "rock_normal.png"
+mask_texture = "rock_mask.png"
+[[MossyRock.layers]]
+diffuse_texture = "moss_color.png"
+tiling = 4.0
+mask_bias = 0.2
+
+A toon material is cel shaded, its ramp texture maps the light across a surface from its dark
+side on the left to the side facing the light on the right:
+
+[Hero]
+shader = "toon"
+diffuse_texture = "hero_color.png"
+ramp_texture = "ramp_3_steps.png"
+rim_strength = 0.8
+
+Materials with a wind height sway in the environment's wind, trees and grass for example:
+
+[Grass]
+shader = "diffuse"
+diffuse_texture = "grass.png"
+wind_height = 0.5
+wind_frequency = 1.2
+*/
+pub type MaterialFile = BTreeMap<String, MaterialDefinition>;
+
+#[derive(Copy, Clone, Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShaderVariant {
+	Color,
+	Diffuse,
+	#[default]
+	DiffuseNormal,
+	Detail,
+	Layered,
+	Toon,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureMapping {
+	#[default]
+	Uv,
+	Triplanar,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayerDefinition {
+	pub diffuse_texture: Option<String>,
+	pub normal_texture: Option<String>,
+	pub tiling: f32, // relative to the base
+	pub weight: f32, // scales the layer's coverage, 0 hides it
+	pub mask_bias: f32, // added to the mask, more covers more of the base
+	pub mask_sharpness: f32, // 1 follows the mask, more makes a harder edge
+}
+
+impl Default for LayerDefinition {
+	fn default() -> Self {
+		Self {
+			diffuse_texture: None,
+			normal_texture: None,
+			tiling: 1.0,
+			weight: 1.0,
+			mask_bias: 0.0,
+			mask_sharpness: 1.0,
+		}
+	}
+}
+
+impl LayerDefinition {
+	fn params(&self) -> [f32; 4] {
+		[self.tiling, self.weight, self.mask_bias, self.mask_sharpness]
+	}
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MaterialDefinition {
+	pub shader: ShaderVariant,
+	pub diffuse_texture: Option<String>,
+	pub normal_texture: Option<String>,
+	pub detail_diffuse_texture: Option<String>,
+	pub detail_normal_texture: Option<String>,
+	pub mask_texture: Option<String>,
+	pub layers: Vec<LayerDefinition>, // in blending order, at most MaterialType::LAYERS
+	pub ramp_texture: Option<String>,
+	pub rim_strength: f32,
+	pub rim_width: f32, // how far in from the silhouette, 0 to 1
+	pub specular_size: f32, // of the highlight, 0 to 1, toon materials use it instead of shininess
+	pub wind_stiffness: f32, // divides the environment's wind strength
+	pub wind_frequency: f32, // sways per second
+	pub wind_height: f32, // model space height where the sway is full, 0 doesn't sway
+	pub detail_tiling: f32,
+	pub detail_fade: [f32; 2],
+	pub detail_strength: f32,
+	pub mapping: TextureMapping,
+	pub triplanar_tiling: f32,
+	pub triplanar_sharpness: f32,
+	pub uv_offset: [f32; 2],
+	pub uv_scale: [f32; 2],
+	pub uv_rotation: f32, // degrees
+	pub flipbook_grid: [u32; 2], // columns, rows
+	pub flipbook_frames: u32,
+	pub flipbook_fps: f32,
+	pub color: [f32; 3],
+	pub specular: f32,
+	pub specular_color: [f32; 3],
+	pub shininess: f32,
+	pub roughness: f32,
+	pub metal: f32,
+	pub opacity: f32,
+	pub blend: model::BlendMode,
+	pub cull: model::CullMode,
+}
+
+impl Default for MaterialDefinition {
+	fn default() -> Self {
+		let params = model::SimpleMaterial::new();
+		Self {
+			shader: ShaderVariant::default(),
+			diffuse_texture: None,
+			normal_texture: None,
+			detail_diffuse_texture: None,
+			detail_normal_texture: None,
+			mask_texture: None,
+			layers: vec![],
+			ramp_texture: None,
+			rim_strength: params.toon[0],
+			rim_width: params.toon[1],
+			specular_size: params.toon[2],
+			wind_stiffness: params.wind[0],
+			wind_frequency: params.wind[1],
+			wind_height: params.wind[2],
+			detail_tiling: params.detail[0],
+			detail_fade: [params.detail[1], params.detail[2]],
+			detail_strength: params.detail[3],
+			mapping: TextureMapping::default(),
+			triplanar_tiling: 1.0,
+			triplanar_sharpness: params.triplanar[1],
+			uv_offset: [0.0, 0.0],
+			uv_scale: [1.0, 1.0],
+			uv_rotation: 0.0,
+			flipbook_grid: [1, 1],
+			flipbook_frames: 0,
+			flipbook_fps: 0.0,
+			color: [1.0, 1.0, 1.0],
+			specular: params.diffuse_spec[3],
+			specular_color: [
+				params.specular_shininess[0],
+				params.specular_shininess[1],
+				params.specular_shininess[2],
+			],
+			shininess: params.specular_shininess[3],
+			roughness: params.roughness,
+			metal: params.metal,
+			opacity: params.opacity,
+			blend: model::BlendMode::default(),
+			cull: model::CullMode::default(),
+		}
+	}
+}
+
+impl MaterialDefinition {
+	// every texture the material references and how it's loaded, detail diffuse and masks are linear
+	pub fn textures(&self) -> Vec<(&str, texture::TextureType)> {
+		let mut textures = vec![
+			(&self.diffuse_texture, texture::TextureType::Diffuse),
+			(&self.normal_texture, texture::TextureType::Normal),
+			(&self.detail_diffuse_texture, texture::TextureType::Normal),
+			(&self.detail_normal_texture, texture::TextureType::Normal),
+			(&self.mask_texture, texture::TextureType::Normal),
+			(&self.ramp_texture, texture::TextureType::Diffuse),
+		];
+		for layer in &self.layers {
+			textures.push((&layer.diffuse_texture, texture::TextureType::Diffuse));
+			textures.push((&layer.normal_texture, texture::TextureType::Normal));
+		}
+		textures.into_iter()
+			.filter_map(|(path, texture_type)| Some((path.as_deref()?, texture_type)))
+			.collect()
+	}
+
+	pub fn params(&self) -> model::SimpleMaterial {
+		let mut params = model::SimpleMaterial::new();
+		params.diffuse_spec = [self.color[0], self.color[1], self.color[2], self.specular];
+		params.specular_shininess = [self.specular_color[0], self.specular_color[1], self.specular_color[2], self.shininess];
+		params.roughness = self.roughness;
+		params.metal = self.metal;
+		params.opacity = self.opacity;
+		params.detail = [self.detail_tiling, self.detail_fade[0], self.detail_fade[1], self.detail_strength];
+		params.set_uv_transform(&model::UvTransform {
+			offset: self.uv_offset,
+			scale: self.uv_scale,
+			rotation: self.uv_rotation.to_radians(),
+		});
+		params.flipbook = [
+			self.flipbook_grid[0] as f32,
+			self.flipbook_grid[1] as f32,
+			self.flipbook_frames as f32,
+			self.flipbook_fps,
+		];
+		params.toon = [self.rim_strength, self.rim_width, self.specular_size, 0.0];
+		params.wind = [self.wind_stiffness, self.wind_frequency, self.wind_height, 0.0];
+		for (layer, definition) in params.layers.iter_mut().zip(&self.layers) {
+			*layer = definition.params();
+		}
+		if self.mapping == TextureMapping::Triplanar {
+			params.triplanar = [self.triplanar_tiling, self.triplanar_sharpness];
+		}
+		params
+	}
+}
+
+pub fn parse(text: &str) -> anyhow::Result<MaterialFile> {
+	Ok(toml::from_str(text)?)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn definitions_parse_into_textures_and_params() {
+		let file = parse(r#"
+			[Brick]
+			diffuse_texture = "brick_color.png"
+			normal_texture = "brick_normal.png"
+			specular = 0.2
+			cull = "none"
+			mapping = "triplanar"
+			triplanar_tiling = 0.5
+
+			[MossyRock]
+			shader = "layered"
+			diffuse_texture = "rock_color.png"
+			mask_texture = "rock_mask.png"
+			[[MossyRock.layers]]
+			diffuse_texture = "moss_color.png"
+			tiling = 4.0
+			mask_bias = 0.2
+
+			[Grass]
+			shader = "diffuse"
+			color = [0.5, 1.0, 0.25]
+			wind_height = 0.5
+			wind_frequency = 1.2
+			uv_rotation = 90.0
+		"#).unwrap();
+		assert_eq!(file.keys().collect::<Vec<_>>(), ["Brick", "Grass", "MossyRock"]);
+
+		let brick = &file["Brick"];
+		assert!(matches!(brick.shader, ShaderVariant::DiffuseNormal));
+		assert_eq!(brick.cull, model::CullMode::None);
+		assert_eq!(brick.textures(), [
+			("brick_color.png", texture::TextureType::Diffuse),
+			("brick_normal.png", texture::TextureType::Normal),
+		]);
+		let params = brick.params();
+		assert_eq!(params.diffuse_spec, [1.0, 1.0, 1.0, 0.2]);
+		assert_eq!(params.triplanar, [0.5, model::SimpleMaterial::new().triplanar[1]]);
+
+		// the mask is linear, and a layer's unset fields keep their defaults
+		let rock = &file["MossyRock"];
+		assert!(matches!(rock.shader, ShaderVariant::Layered));
+		assert_eq!(rock.textures(), [
+			("rock_color.png", texture::TextureType::Diffuse),
+			("rock_mask.png", texture::TextureType::Normal),
+			("moss_color.png", texture::TextureType::Diffuse),
+		]);
+		let params = rock.params();
+		assert_eq!(params.layers[0], [4.0, 1.0, 0.2, 1.0]);
+		assert_eq!(params.layers[1], model::SimpleMaterial::new().layers[1]);
+		assert_eq!(params.triplanar, model::SimpleMaterial::new().triplanar);
+
+		let grass = file["Grass"].params();
+		assert_eq!(grass.diffuse_spec[..3], [0.5, 1.0, 0.25]);
+		assert_eq!(grass.wind[1..3], [1.2, 0.5]);
+		assert!((grass.uv_rotation - std::f32::consts::FRAC_PI_2).abs() < 1e-6);
+	}
+
+	#[test]
+	fn unknown_fields_are_rejected() {
+		assert!(parse("[Brick]\ndiffuse = \"brick.png\"\n").is_err());
+		assert!(parse("[Brick]\nshader = \"glass\"\n").is_err());
+		assert!(parse("[Rock]\n[[Rock.layers]]\nnormal = \"moss.png\"\n").is_err());
+	}
+}
@@ -0,0 +1,513 @@
+// Vertex stage and lighting shared by the material shaders.
+// Material shaders are appended to this file and only define their textures and material_surface,
+// the fragment entry points calling it come after them from surface.wgsl.
+
+struct Camera {
+	view: mat4x4<f32>,
+	proj: mat4x4<f32>,
+	view_proj: mat4x4<f32>,
+	inv_view: mat4x4<f32>,
+	inv_proj: mat4x4<f32>,
+	inv_view_proj: mat4x4<f32>,
+	position: vec4<f32>,
+};
+@group(2) @binding(0)
+var<uniform> camera: Camera;
+
+struct Model {
+	transform: mat4x4<f32>,
+	uv_offset_scale: vec4<f32>,
+	uv_rotation: f32,
+	shadow_bias: f32, // 0 uses the default bias
+	shadow_flags: u32, // 1 casts, 2 receives
+	emissive: f32,
+	tint: vec4<f32>, // per instance, on top of the material color
+	vat: vec4<f32>, // clip start, vertex count (0 for none), frame count, frame
+};
+// one per instance of the draw, MAX_INSTANCES in model.rs
+@group(2) @binding(1)
+var<uniform> models: array<Model, 128>;
+// the instance being drawn, set from models by vs_main and shade
+var<private> model: Model;
+
+// baked vertex animations, a position and a normal per vertex per frame, see vat.rs
+@group(2) @binding(7)
+var vat_texture: texture_2d<f32>;
+
+fn vat_texel(frame: u32, vertex: u32, slot: u32) -> vec3<f32> {
+	let index = u32(model.vat.x) + (frame * u32(model.vat.y) + vertex) * 2u + slot;
+	let width = textureDimensions(vat_texture).x;
+	return textureLoad(vat_texture, vec2<u32>(index % width, index / width), 0).xyz;
+}
+
+// the vertex's position and normal between the frames around the instance's playback position
+fn vat_vertex(vertex: u32) -> array<vec3<f32>, 2> {
+	let frame_count = u32(model.vat.z);
+	let frame = u32(model.vat.w) % frame_count;
+	let next = (frame + 1u) % frame_count;
+	let t = fract(model.vat.w);
+	let position = mix(vat_texel(frame, vertex, 0u), vat_texel(next, vertex, 0u), t);
+	let normal = normalize(mix(vat_texel(frame, vertex, 1u), vat_texel(next, vertex, 1u), t));
+	return array<vec3<f32>, 2>(position, normal);
+}
+
+struct VertexInput {
+	@location(0) position: vec3<f32>,
+	@location(1) tex_coords: vec2<f32>,
+	@location(2) normal: vec3<f32>,
+	@location(3) tangent: vec4<f32>,
+};
+
+struct VertexOutput {
+	@builtin(position) clip_position: vec4<f32>,
+	@location(0) position: vec3<f32>,
+	@location(1) tex_coords: vec2<f32>,
+	@location(2) normal: vec3<f32>,
+	@location(3) tangent: vec4<f32>,
+	@location(4) @interpolate(flat) instance: u32,
+};
+
+// struct InstanceInput {
+// 	@location(5) model_matrix_0: vec4<f32>,
+// 	@location(6) model_matrix_1: vec4<f32>,
+// 	@location(7) model_matrix_2: vec4<f32>,
+// 	@location(8) model_matrix_3: vec4<f32>,
+// };
+
+struct SimpleMaterial {
+	diffuse_spec: vec4<f32>,
+	specular_shininess: vec4<f32>,
+	detail: vec4<f32>, // tiling, fade start, fade end, strength
+	uv_offset_scale: vec4<f32>,
+	flipbook: vec4<f32>, // columns, rows, frame count, fps
+	layers: array<vec4<f32>, 2>, // tiling, weight, mask bias, mask sharpness
+	toon: vec4<f32>, // rim strength, rim width, specular size
+	wind: vec4<f32>, // stiffness, frequency, height (0 doesn't sway)
+	roughness: f32,
+	metal: f32,
+	opacity: f32,
+	illum: u32,
+	triplanar: vec2<f32>, // world space tiling (0 uses mesh uvs), blend sharpness
+	uv_rotation: f32,
+};
+@group(0) @binding(0)
+var<uniform> material: SimpleMaterial;
+
+// scales, rotates around the uv center, then offsets texture coordinates
+fn transform_uv(uv: vec2<f32>, offset_scale: vec4<f32>, rotation: f32) -> vec2<f32> {
+	let centered = (uv - 0.5) * offset_scale.zw;
+	let c = cos(rotation);
+	let s = sin(rotation);
+	return vec2<f32>(c * centered.x - s * centered.y, s * centered.x + c * centered.y) + 0.5 + offset_scale.xy;
+}
+
+/*
+How far the wind moves a vertex of a swaying material, in world space. The sway grows with the
+square of the height above the model's origin up to the material's wind height, so trunks and
+the roots of grass blades stay put. Objects sway out of step by where they stand, and a faster
+flutter out of step by vertex moves leaves and blade tips.
+The vertex format has no color to weight the sway by, and shadow casters don't sway.
+*/
+fn wind_sway(height: f32, world_pos: vec3<f32>) -> vec3<f32> {
+	let weight = pow(saturate(height / material.wind.z), 2.0);
+	let direction = vec3<f32>(environment.wind_direction.x, 0.0, environment.wind_direction.y);
+	if weight <= 0.0 || dot(direction, direction) <= 0.0 {
+		return vec3<f32>(0.0);
+	}
+	let strength = environment.wind_strength / max(material.wind.x, 0.01);
+	let phase = dot(model.transform[3].xyz, vec3<f32>(0.37, 0.0, 0.61));
+	let t = globals.time * material.wind.y * 6.2831853;
+	// leans with the wind and springs back, never against it
+	let sway = 0.5 + 0.5 * sin(t + phase) + 0.15 * sin(t * 2.7 + phase + dot(world_pos, vec3<f32>(1.3, 0.7, 1.1)));
+	return normalize(direction) * strength * weight * sway;
+}
+
+@vertex
+fn vs_main(
+	vertex_input: VertexInput,
+	@builtin(instance_index) instance: u32,
+	@builtin(vertex_index) vertex: u32,
+) -> VertexOutput {
+	model = models[instance];
+	var out: VertexOutput;
+	out.instance = instance;
+	var position = vertex_input.position;
+	var normal = vertex_input.normal;
+	if model.vat.y > 0.0 {
+		let animated = vat_vertex(vertex);
+		position = animated[0];
+		normal = animated[1];
+	}
+	var world_pos = model.transform * vec4<f32>(position, 1.0);
+	if material.wind.z > 0.0 {
+		world_pos = vec4<f32>(world_pos.xyz + wind_sway(position.y, world_pos.xyz), 1.0);
+	}
+	out.position = world_pos.xyz;
+	let instance_uv = transform_uv(vertex_input.tex_coords, model.uv_offset_scale, model.uv_rotation);
+	out.tex_coords = transform_uv(instance_uv, material.uv_offset_scale, material.uv_rotation);
+	out.normal = (model.transform * vec4<f32>(normal, 0.0)).xyz;
+	var tangent = model.transform * vec4<f32>(vertex_input.tangent.xyz, 0.0);
+	out.tangent = vec4<f32>(tangent.xyz, vertex_input.tangent.w);
+	out.clip_position = camera.view_proj * world_pos;
+	return out;
+}
+
+// image based lighting from the environment, see ibl.rs
+@group(1) @binding(0)
+var prefiltered_texture: texture_cube<f32>; // rougher reflections down the mips
+@group(1) @binding(1)
+var ibl_sampler: sampler;
+@group(1) @binding(2)
+var irradiance_texture: texture_cube<f32>;
+@group(1) @binding(3)
+var brdf_lut: texture_2d<f32>;
+
+const MAX_REFLECTION_LOD: f32 = 4.0; // Ibl::PREFILTERED_MIPS - 1
+
+// the lights array at binding 3 comes from the lights file of the shader tier
+struct Light {
+	position: vec3<f32>,
+	intensity: f32,
+	color: vec3<f32>,
+	range: f32,
+	direction: vec3<f32>, // where directional and spot lights point
+	kind: u32, // 0 point, 1 directional, 2 spot
+	falloff: u32, // 0 none, 1 inverse square, 2 linear
+	shadow_index: i32, // -1 without shadows
+	spot_cos: vec2<f32>, // cosines of the outer and inner cone angles
+	view_proj: mat4x4<f32>, // light space of the downlevel shadow map
+};
+
+struct Globals {
+	camera_pos: vec3<f32>,
+	time: f32,
+	resolution: vec2<f32>,
+	delta_time: f32,
+};
+@group(2) @binding(4)
+var<uniform> globals: Globals;
+
+struct Environment {
+	fog_color: vec3<f32>,
+	fog_density: f32,
+	ambient_color: vec3<f32>,
+	ambient_intensity: f32,
+	fog_start: f32,
+	exposure: f32,
+	tonemap: u32, // 0 none, 1 Reinhard, 2 ACES
+	wetness: f32,
+	snow: f32,
+	wind_strength: f32,
+	wind_direction: vec2<f32>, // x and z
+};
+@group(2) @binding(5)
+var<uniform> environment: Environment;
+
+struct Capsule {
+	a: vec4<f32>, // xyz end, w radius
+	b: vec4<f32>,
+};
+struct CapsuleShadows {
+	capsules: array<Capsule, 16>,
+	count: u32,
+	softness: f32,
+};
+@group(2) @binding(6)
+var<uniform> capsule_shadows: CapsuleShadows;
+
+// exponential squared fog past the fog start distance
+fn apply_tonemap(color: vec3<f32>) -> vec3<f32> {
+	switch environment.tonemap {
+		case 1u: {
+			return color / (color + 1.0);
+		}
+		case 2u: {
+			return clamp((color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+		}
+		default: {
+			return color;
+		}
+	}
+}
+
+fn apply_fog(color: vec3<f32>, position: vec3<f32>) -> vec3<f32> {
+	let dist = max(distance(camera.position.xyz, position) - environment.fog_start, 0.0);
+	let fog_amount = 1.0 - exp(-pow(dist * environment.fog_density, 2.0));
+	return mix(color, environment.fog_color, fog_amount);
+}
+
+const DIRECTIONAL_DISTANCE: f32 = 1000.0; // how far shadow casters are looked for toward a directional light
+
+fn light_attenuation(light: Light, dist: f32) -> f32 {
+	switch (light.falloff) {
+		case 1u: {
+			// inverse square with a smooth window so the light reaches zero at its range
+			let window = clamp(1.0 - pow(dist / light.range, 4.0), 0.0, 1.0);
+			return window * window / max(dist * dist, 0.0001);
+		}
+		case 2u: {
+			return clamp(1.0 - dist / light.range, 0.0, 1.0);
+		}
+		default: {
+			return 1.0;
+		}
+	}
+}
+
+// unit vector from the surface toward the light in xyz, the distance to it in w
+fn light_direction(light: Light, position: vec3<f32>) -> vec4<f32> {
+	if light.kind == 1u {
+		return vec4<f32>(-normalize(light.direction), DIRECTIONAL_DISTANCE);
+	}
+	let to_light = light.position - position;
+	let dist = max(length(to_light), 0.0001);
+	return vec4<f32>(to_light / dist, dist);
+}
+
+// the light reaching the surface before shadows
+fn light_radiance(light: Light, to_light: vec4<f32>) -> vec3<f32> {
+	let radiance = light.color * light.intensity;
+	switch (light.kind) {
+		case 1u: {
+			return radiance;
+		}
+		case 2u: {
+			let cone = smoothstep(light.spot_cos.x, light.spot_cos.y, dot(-to_light.xyz, normalize(light.direction)));
+			return radiance * light_attenuation(light, to_light.w) * cone;
+		}
+		default: {
+			return radiance * light_attenuation(light, to_light.w);
+		}
+	}
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: f32) -> f32 {
+    return f0 + (1.0 - f0) * pow(clamp(1.0 - cos_theta, 0.0, 1.0), 5.0);
+}
+
+fn triplanar_weights(normal: vec3<f32>) -> vec3<f32> {
+	let weights = pow(abs(normal), vec3<f32>(material.triplanar.y));
+	return weights / (weights.x + weights.y + weights.z);
+}
+
+// maps uvs into the current frame of a flipbook sprite sheet, frames go left to right then top to bottom
+fn flipbook_uv(uv: vec2<f32>) -> vec2<f32> {
+	let frame_count = material.flipbook.z;
+	if (frame_count < 1.0) {
+		return uv;
+	}
+
+	let grid = max(material.flipbook.xy, vec2<f32>(1.0));
+	let frame = floor(globals.time * material.flipbook.w) % frame_count;
+	let cell = vec2<f32>(frame % grid.x, floor(frame / grid.x));
+	return (fract(uv) + cell) / grid;
+}
+
+// samples a material texture with the mesh uvs, or projected along the world axes for triplanar materials
+fn sample_material_texture(t: texture_2d<f32>, s: sampler, in: VertexOutput, tiling: f32) -> vec4<f32> {
+	if (material.triplanar.x <= 0.0) {
+		return textureSample(t, s, flipbook_uv(in.tex_coords * tiling));
+	}
+
+	let pos = in.position * material.triplanar.x * tiling;
+	let weights = triplanar_weights(normalize(in.normal));
+	return textureSample(t, s, pos.zy) * weights.x
+		+ textureSample(t, s, pos.xz) * weights.y
+		+ textureSample(t, s, pos.xy) * weights.z;
+}
+
+// a tangent space normal from a normal map texel, z is rebuilt from x and y so two channel BC5 maps work too
+fn unpack_normal(texel: vec4<f32>) -> vec3<f32> {
+	let xy = texel.xy * 2.0 - 1.0;
+	return vec3<f32>(xy, sqrt(saturate(1.0 - dot(xy, xy))));
+}
+
+// samples a tangent space normal map and returns the world space normal
+fn sample_material_normal(t: texture_2d<f32>, s: sampler, in: VertexOutput, tiling: f32) -> vec3<f32> {
+	if (material.triplanar.x <= 0.0) {
+		let tangent_norm = unpack_normal(textureSample(t, s, flipbook_uv(in.tex_coords * tiling)));
+		let bitangent = cross(in.normal, in.tangent.xyz) * in.tangent.w;
+		return normalize(tangent_norm.x * in.tangent.xyz + tangent_norm.y * bitangent + tangent_norm.z * in.normal);
+	}
+
+	// whiteout blend of each projection's normal with the surface normal
+	let pos = in.position * material.triplanar.x * tiling;
+	let normal = normalize(in.normal);
+	let weights = triplanar_weights(normal);
+	let norm_x = unpack_normal(textureSample(t, s, pos.zy));
+	let norm_y = unpack_normal(textureSample(t, s, pos.xz));
+	let norm_z = unpack_normal(textureSample(t, s, pos.xy));
+	let world_x = vec3<f32>(norm_x.xy + normal.zy, abs(norm_x.z) * normal.x).zyx;
+	let world_y = vec3<f32>(norm_y.xy + normal.xz, abs(norm_y.z) * normal.y).xzy;
+	let world_z = vec3<f32>(norm_z.xy + normal.xy, abs(norm_z.z) * normal.z);
+	return normalize(world_x * weights.x + world_y * weights.y + world_z * weights.z);
+}
+
+// soft shadow of a sphere on a ray, after Inigo Quilez's sphSoftShadow
+fn sphere_shadow(origin: vec3<f32>, dir: vec3<f32>, t_max: f32, center: vec3<f32>, radius: f32) -> f32 {
+	let oc = origin - center;
+	let b = dot(oc, dir);
+	let h = b * b - dot(oc, oc) + radius * radius;
+	let d = sqrt(max(radius * radius - h, 0.0)) - radius;
+	let t = -b - sqrt(max(h, 0.0));
+	if t <= 0.0 || t >= t_max {
+		return 1.0;
+	}
+	return smoothstep(0.0, 1.0, capsule_shadows.softness * d / t);
+}
+
+// light reaching the surface past the capsule shadow casters, each capsule is treated as the
+// sphere around its point closest to the light ray
+fn capsule_visibility(position: vec3<f32>, to_light: vec4<f32>) -> f32 {
+	if (model.shadow_flags & 2u) == 0u {
+		return 1.0;
+	}
+	let t_max = to_light.w;
+	let dir = to_light.xyz;
+	var visibility = 1.0;
+	for (var i = 0u; i < min(capsule_shadows.count, 16u); i++) {
+		let capsule = capsule_shadows.capsules[i];
+		let ab = capsule.b.xyz - capsule.a.xyz;
+		// closest points between the ray and the segment
+		let w = capsule.a.xyz - position;
+		let d = dot(dir, ab);
+		let e = dot(ab, ab);
+		let denom = max(e - d * d, 1e-6);
+		let s = clamp((d * dot(dir, w) - dot(ab, w)) / denom, 0.0, 1.0);
+		visibility *= sphere_shadow(position, dir, t_max, capsule.a.xyz + ab * s, capsule.a.w);
+	}
+	return visibility;
+}
+
+// a material's color and shading normal at a fragment, returned by the material file's material_surface
+struct MaterialSurface {
+	color: vec4<f32>,
+	normal: vec3<f32>,
+};
+
+// what lighting needs to know about a surface, from the material being drawn or read back from the G-buffer
+struct SurfaceParams {
+	position: vec3<f32>,
+	normal: vec3<f32>,
+	geometric_normal: vec3<f32>, // shadow lookups are offset along it
+	color: vec3<f32>,
+	emissive: f32,
+	f0: f32, // reflection fresnel
+	specular: vec3<f32>,
+	shininess: f32,
+	illum: u32,
+};
+
+// the surface of the material being drawn, with the instance's tint and the weather, model has to be set
+fn material_params(in: VertexOutput, material_col: vec4<f32>, obj_norm: vec3<f32>) -> SurfaceParams {
+	return apply_weather(SurfaceParams(
+		in.position,
+		obj_norm,
+		normalize(in.normal),
+		material_col.xyz * model.tint.xyz,
+		model.emissive,
+		material.diffuse_spec.w,
+		material.specular_shininess.xyz,
+		material.specular_shininess.w,
+		material.illum,
+	));
+}
+
+// water darkens a surface and gives it a glossy film, then snow covers what faces up, unlit surfaces are left alone
+fn apply_weather(surface: SurfaceParams) -> SurfaceParams {
+	var out = surface;
+	if (surface.illum == 0u) {
+		return out;
+	}
+
+	let wetness = environment.wetness;
+	out.color *= mix(1.0, 0.5, wetness);
+	out.f0 = mix(surface.f0, max(surface.f0, 0.1), wetness);
+	out.specular = mix(surface.specular, max(surface.specular, vec3<f32>(0.6)), wetness);
+	out.shininess = mix(surface.shininess, max(surface.shininess, 256.0), wetness);
+
+	// the threshold drops below the horizon as snow builds up, snow hides the bumps of the normal map
+	let threshold = 1.0 - environment.snow * 1.2;
+	let coverage = smoothstep(threshold, threshold + 0.2, surface.geometric_normal.y) * step(0.001, environment.snow);
+	out.color = mix(out.color, vec3<f32>(0.9, 0.92, 0.95), coverage);
+	out.normal = normalize(mix(surface.normal, surface.geometric_normal, coverage));
+	out.f0 = mix(out.f0, 0.02, coverage);
+	out.specular = mix(out.specular, vec3<f32>(0.2), coverage);
+	out.shininess = mix(out.shininess, 16.0, coverage);
+	return out;
+}
+
+// lights a surface following its illumination model (see the .mtl illum values), model has to
+// be set for its shadow flags and bias
+fn light_surface(surface: SurfaceParams) -> vec3<f32> {
+	if (surface.illum == 0u) {
+		return apply_fog(surface.color, surface.position);
+	}
+
+	let eye_dir = normalize(camera.position.xyz - surface.position);
+
+	let n_dot_v = max(dot(eye_dir, surface.normal), 0.0);
+	let reflect_strength = fresnel_schlick(n_dot_v, surface.f0);
+	// the Blinn-Phong exponent as a GGX roughness, for the prefiltered mip and the BRDF
+	let roughness = sqrt(2.0 / (max(surface.shininess, 1.0) + 2.0));
+	let prefiltered = textureSampleLevel(prefiltered_texture, ibl_sampler, reflect(-eye_dir, surface.normal), roughness * MAX_REFLECTION_LOD).xyz;
+	let brdf = textureSampleLevel(brdf_lut, ibl_sampler, vec2<f32>(n_dot_v, roughness), 0.0).xy;
+	let cubemap_col = prefiltered * (surface.f0 * brdf.x + brdf.y);
+
+	// the ambient color and intensity tint and scale the environment's irradiance
+	let irradiance = textureSampleLevel(irradiance_texture, ibl_sampler, surface.normal, 0.0).xyz;
+	let ambient_col = irradiance * environment.ambient_color * environment.ambient_intensity * (1.0 - reflect_strength);
+
+	var diffuse_col = vec3<f32>(0.0);
+	var specular_col = vec3<f32>(0.0);
+	for (var i = 0u; i < light_count(); i++) {
+		let light = lights.lights[i];
+		let to_light = light_direction(light, surface.position);
+		// light_visibility comes from the shadows file of the shader tier
+		let light_col = light_radiance(light, to_light)
+			* light_visibility(light, surface.position, surface.geometric_normal) * capsule_visibility(surface.position, to_light);
+
+		diffuse_col += light_col * max(dot(surface.normal, to_light.xyz), 0.0) * (1.0 - reflect_strength);
+		if (surface.illum >= 2u) {
+			let half_dir = normalize(to_light.xyz + eye_dir);
+			let specular_strength = pow(max(dot(surface.normal, half_dir), 0.0), max(surface.shininess, 1.0));
+			specular_col += light_col * surface.specular * specular_strength;
+		}
+	}
+
+	let emissive_col = surface.color * surface.emissive;
+	let result = ((ambient_col + diffuse_col + cubemap_col) * surface.color + specular_col + emissive_col) * environment.exposure;
+	return apply_tonemap(apply_fog(result, surface.position));
+}
+
+// lights a fragment of the material being drawn, alpha from its opacity
+fn shade(in: VertexOutput, material_col: vec4<f32>, obj_norm: vec3<f32>) -> vec4<f32> {
+	model = models[in.instance];
+	let alpha = material_col.w * model.tint.w * material.opacity;
+	return vec4<f32>(light_surface(material_params(in, material_col, obj_norm)), alpha);
+}
+
+// FragmentOutput and write_output come from the output file of the shader tier
+fn fragment_output(color: vec4<f32>, normal: vec3<f32>) -> FragmentOutput {
+	return write_output(color, normal, material.roughness);
+}
+
+// debug views, drawn by the view mode pipelines in place of the material's fs_main
+
+@fragment
+fn fs_normals(in: VertexOutput) -> FragmentOutput {
+	let normal = normalize(in.normal);
+	return fragment_output(vec4<f32>(normal * 0.5 + 0.5, 1.0), normal);
+}
+
+// log scaled so near objects still have contrast, white at the near plane and black at the far one
+@fragment
+fn fs_depth(in: VertexOutput) -> FragmentOutput {
+	let near_point = camera.inv_proj * vec4<f32>(0.0, 0.0, 0.0, 1.0);
+	let far_point = camera.inv_proj * vec4<f32>(0.0, 0.0, 1.0, 1.0);
+	let near = -near_point.z / near_point.w;
+	let far = -far_point.z / far_point.w;
+	let depth = -(camera.view * vec4<f32>(in.position, 1.0)).z;
+	let shade = 1.0 - saturate(log(max(depth, near) / near) / log(far / near));
+	return fragment_output(vec4<f32>(vec3<f32>(shade), 1.0), normalize(in.normal));
+}
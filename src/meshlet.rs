@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+
+use cgmath::InnerSpace;
+use wgpu::util::DeviceExt;
+
+use crate::{model, scene, uniform_arena};
+
+const WORKGROUP_SIZE: u32 = 64;
+const MAX_VERTICES: usize = 64;
+const MAX_TRIANGLES: usize = 124;
+
+gpu_struct! {
+	Storage
+	#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+	pub struct Meshlet {
+		center: [f32; 3],
+		radius: f32,
+		cone_axis: [f32; 3],
+		cone_cutoff: f32, // sine of the normal cone's half angle, 1 when it can't be culled
+		first_index: u32,
+		index_count: u32,
+	}
+}
+
+gpu_struct! {
+	Uniform
+	#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+	struct CullParams {
+		transform: [[f32; 4]; 4],
+		output_offset: u32,
+		meshlet_count: u32,
+		cone_culling: u32,
+	}
+}
+
+/*
+Splits a mesh into clusters of up to 64 vertices and 124 triangles. Triangles are taken in
+index buffer order, so every meshlet is a contiguous range of the original indices.
+*/
+pub fn build_meshlets(vertices: &[model::ModelVertex], indices: &[u32]) -> Vec<Meshlet> {
+	let mut meshlets = vec![];
+	let mut first = 0;
+	while first < indices.len() {
+		let mut unique = Vec::with_capacity(MAX_VERTICES);
+		let mut end = first;
+		while end < indices.len() && (end - first) / 3 < MAX_TRIANGLES {
+			let triangle = &indices[end..end + 3];
+			let new_vertices = triangle.iter().filter(|i| !unique.contains(*i)).count();
+			if unique.len() + new_vertices > MAX_VERTICES {
+				break;
+			}
+			for &i in triangle {
+				if !unique.contains(&i) {
+					unique.push(i);
+				}
+			}
+			end += 3;
+		}
+
+		meshlets.push(meshlet_bounds(vertices, &indices[first..end], &unique, first as u32));
+		first = end;
+	}
+	meshlets
+}
+
+fn meshlet_bounds(vertices: &[model::ModelVertex], indices: &[u32], unique: &[u32], first_index: u32) -> Meshlet {
+	let position = |i: u32| cgmath::Vector3::from(vertices[i as usize].position);
+
+	// bounding sphere around the center of the bounding box
+	let mut min = position(unique[0]);
+	let mut max = min;
+	for &i in unique {
+		let p = position(i);
+		min = cgmath::Vector3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z));
+		max = cgmath::Vector3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z));
+	}
+	let center = (min + max) * 0.5;
+	let radius = unique.iter().map(|&i| (position(i) - center).magnitude()).fold(0.0, f32::max);
+
+	// normal cone from the face normals
+	let normals = indices.chunks_exact(3).filter_map(|t| {
+		let n = (position(t[1]) - position(t[0])).cross(position(t[2]) - position(t[0]));
+		(n.magnitude2() > 0.0).then(|| n.normalize())
+	}).collect::<Vec<_>>();
+	let sum = normals.iter().fold(cgmath::Vector3::new(0.0, 0.0, 0.0), |a, &n| a + n);
+	let (cone_axis, cone_cutoff) = if sum.magnitude2() > 0.0 {
+		let axis = sum.normalize();
+		let min_dot = normals.iter().map(|n| n.dot(axis)).fold(1.0, f32::min);
+		// cones wider than a hemisphere always have a triangle facing the camera
+		let cutoff = if min_dot <= 0.0 { 1.0 } else { (1.0 - min_dot * min_dot).sqrt() };
+		(axis, cutoff)
+	} else {
+		(cgmath::Vector3::unit_z(), 1.0)
+	};
+
+	Meshlet {
+		center: center.into(),
+		radius,
+		cone_axis: cone_axis.into(),
+		cone_cutoff,
+		first_index,
+		index_count: indices.len() as u32,
+		..bytemuck::Zeroable::zeroed()
+	}
+}
+
+// meshlets of a loaded mesh
+pub struct MeshletMesh {
+	count: u32,
+	bind_group: wgpu::BindGroup,
+	_buffer: wgpu::Buffer,
+}
+
+// indirect draws written for one mesh of one object this frame
+#[derive(Copy, Clone, Debug)]
+pub struct MeshletDraws {
+	pub offset: wgpu::BufferAddress,
+	pub count: u32,
+}
+
+/*
+Optional meshlet path for very high poly meshes: each instance's meshlets are frustum and
+normal cone culled in a compute pass, which writes one indirect draw per meshlet
+*/
+pub struct MeshletPipeline {
+	pipeline: wgpu::ComputePipeline,
+	mesh_layout: wgpu::BindGroupLayout,
+	layout: wgpu::BindGroupLayout,
+	bind_group: wgpu::BindGroup,
+	pub draw_buffer: wgpu::Buffer,
+	jobs: Vec<(u32, MeshletDraws)>, // arena offset of the cull params and the draws they write
+}
+
+impl MeshletPipeline {
+	pub const MIN_TRIANGLES: usize = 100_000; // smaller meshes are drawn normally
+	const MAX_DRAWS: u64 = 1 << 16;
+
+	pub fn new(device: &wgpu::Device, camera_buffer: &wgpu::Buffer, uniform_arena: &uniform_arena::UniformArena) -> Self {
+		let mesh_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("meshlet_mesh_bind_group_layout"),
+			entries: &[
+				wgpu::BindGroupLayoutEntry {
+					binding: 0,
+					visibility: wgpu::ShaderStages::COMPUTE,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Storage { read_only: true },
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+			],
+		});
+		let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("meshlet_cull_bind_group_layout"),
+			entries: &[
+				wgpu::BindGroupLayoutEntry { // camera
+					binding: 0,
+					visibility: wgpu::ShaderStages::COMPUTE,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry { // cull params
+					binding: 1,
+					visibility: wgpu::ShaderStages::COMPUTE,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: true,
+						min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<CullParams>() as u64),
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry { // draws
+					binding: 2,
+					visibility: wgpu::ShaderStages::COMPUTE,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Storage { read_only: false },
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+			],
+		});
+
+		let draw_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Meshlet Draw Buffer"),
+			size: Self::MAX_DRAWS * std::mem::size_of::<wgpu::util::DrawIndexedIndirectArgs>() as u64,
+			usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::INDIRECT,
+			mapped_at_creation: false,
+		});
+		let bind_group = Self::create_bind_group(device, &layout, camera_buffer, uniform_arena, &draw_buffer);
+
+		let shader = device.create_shader_module(wgpu::include_wgsl!("meshlet.wgsl"));
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Meshlet Cull Pipeline Layout"),
+			bind_group_layouts: &[Some(&mesh_layout), Some(&layout)],
+			immediate_size: 0,
+		});
+		let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+			label: Some("Meshlet Cull Pipeline"),
+			layout: Some(&pipeline_layout),
+			module: &shader,
+			entry_point: Some("cs_main"),
+			compilation_options: Default::default(),
+			cache: None,
+		});
+
+		Self {
+			pipeline,
+			mesh_layout,
+			layout,
+			bind_group,
+			draw_buffer,
+			jobs: vec![],
+		}
+	}
+
+	fn create_bind_group(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, camera_buffer: &wgpu::Buffer, uniform_arena: &uniform_arena::UniformArena, draw_buffer: &wgpu::Buffer) -> wgpu::BindGroup {
+		device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("meshlet_cull_bind_group"),
+			layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: camera_buffer.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: uniform_arena.binding::<CullParams>(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: draw_buffer.as_entire_binding(),
+				},
+			],
+		})
+	}
+
+	// after the uniform arena's buffer was replaced
+	pub fn rebind(&mut self, device: &wgpu::Device, camera_buffer: &wgpu::Buffer, uniform_arena: &uniform_arena::UniformArena) {
+		self.bind_group = Self::create_bind_group(device, &self.layout, camera_buffer, uniform_arena, &self.draw_buffer);
+	}
+
+	// clusters a mesh at import, or returns None if it's too small to be worth it
+	pub fn build(&self, device: &wgpu::Device, vertices: &[model::ModelVertex], indices: &[u32]) -> Option<MeshletMesh> {
+		if indices.len() / 3 < Self::MIN_TRIANGLES {
+			return None;
+		}
+		let meshlets = build_meshlets(vertices, indices);
+		let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Meshlet Buffer"),
+			contents: bytemuck::cast_slice(&meshlets),
+			usage: wgpu::BufferUsages::STORAGE,
+		});
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("meshlet_mesh_bind_group"),
+			layout: &self.mesh_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: buffer.as_entire_binding(),
+				},
+			],
+		});
+
+		Some(MeshletMesh {
+			count: meshlets.len() as u32,
+			bind_group,
+			_buffer: buffer,
+		})
+	}
+
+	/*
+	Pushes the cull params of every meshlet mesh instance into the arena, before it's uploaded.
+	Returns where each (object, mesh) pair's draws are written.
+	*/
+	pub fn prepare(&mut self, uniform_arena: &mut uniform_arena::UniformArena, scene: &scene::Scene) -> HashMap<(usize, usize), MeshletDraws> {
+		self.jobs.clear();
+		let mut draws = HashMap::new();
+		let mut next_draw = 0;
+
+		for (object, obj) in scene.objects.iter().enumerate() {
+			// skinned instances draw their own vertex buffers
+			if obj.skin.is_some() {
+				continue;
+			}
+			for (mesh_index, mesh) in scene.models[obj.model_index].meshes.iter().enumerate() {
+				let Some(meshlets) = &mesh.meshlets else { continue };
+				if next_draw + meshlets.count as u64 > Self::MAX_DRAWS {
+					log::warn!("Meshlet draw buffer is full, drawing the rest without culling");
+					return draws;
+				}
+
+				let params = CullParams {
+					transform: obj.transform.into(),
+					output_offset: next_draw as u32,
+					meshlet_count: meshlets.count,
+					cone_culling: (scene.materials[mesh.material].cull == model::CullMode::Back) as u32,
+					..bytemuck::Zeroable::zeroed()
+				};
+				let job = MeshletDraws {
+					offset: next_draw * std::mem::size_of::<wgpu::util::DrawIndexedIndirectArgs>() as u64,
+					count: meshlets.count,
+				};
+				self.jobs.push((uniform_arena.push(&params), job));
+				draws.insert((object, mesh_index), job);
+				next_draw += meshlets.count as u64;
+			}
+		}
+		draws
+	}
+
+	pub fn cull(&self, encoder: &mut wgpu::CommandEncoder, scene: &scene::Scene) {
+		if self.jobs.is_empty() {
+			return;
+		}
+		let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+			label: Some("Meshlet Cull Pass"),
+			timestamp_writes: None,
+		});
+		compute_pass.set_pipeline(&self.pipeline);
+
+		let meshes = scene.objects.iter()
+			.filter(|obj| obj.skin.is_none())
+			.flat_map(|obj| &scene.models[obj.model_index].meshes)
+			.filter_map(|mesh| mesh.meshlets.as_ref());
+		for (meshlets, (params_offset, job)) in meshes.zip(&self.jobs) {
+			compute_pass.set_bind_group(0, &meshlets.bind_group, &[]);
+			compute_pass.set_bind_group(1, &self.bind_group, &[*params_offset]);
+			compute_pass.dispatch_workgroups(job.count.div_ceil(WORKGROUP_SIZE), 1, 1);
+		}
+	}
+}
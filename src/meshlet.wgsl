@@ -0,0 +1,84 @@
+// Culls the meshlets of one mesh instance and writes an indirect draw for each of them
+
+struct Camera {
+	view: mat4x4<f32>,
+	proj: mat4x4<f32>,
+	view_proj: mat4x4<f32>,
+	inv_view: mat4x4<f32>,
+	inv_proj: mat4x4<f32>,
+	inv_view_proj: mat4x4<f32>,
+	position: vec4<f32>,
+};
+
+struct Meshlet {
+	center: vec3<f32>,
+	radius: f32,
+	cone_axis: vec3<f32>,
+	cone_cutoff: f32,
+	first_index: u32,
+	index_count: u32,
+};
+
+struct CullParams {
+	transform: mat4x4<f32>,
+	output_offset: u32, // first draw written by this instance
+	meshlet_count: u32,
+	cone_culling: u32, // only back face culled materials can skip meshlets facing away
+};
+
+@group(0) @binding(0)
+var<storage, read> meshlets: array<Meshlet>;
+
+@group(1) @binding(0)
+var<uniform> camera: Camera;
+@group(1) @binding(1)
+var<uniform> params: CullParams;
+@group(1) @binding(2)
+var<storage, read_write> draws: array<u32>; // DrawIndexedIndirectArgs, 5 words each
+
+fn sphere_visible(center: vec3<f32>, radius: f32) -> bool {
+	// frustum planes from the rows of the view projection matrix
+	let m = transpose(camera.view_proj);
+	let planes = array<vec4<f32>, 6>(
+		m[3] + m[0],
+		m[3] - m[0],
+		m[3] + m[1],
+		m[3] - m[1],
+		m[2],
+		m[3] - m[2],
+	);
+	for (var i = 0u; i < 6u; i++) {
+		let plane = planes[i];
+		if dot(plane.xyz, center) + plane.w < -radius * length(plane.xyz) {
+			return false;
+		}
+	}
+	return true;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+	let index = id.x;
+	if index >= params.meshlet_count {
+		return;
+	}
+	let meshlet = meshlets[index];
+
+	let center = (params.transform * vec4<f32>(meshlet.center, 1.0)).xyz;
+	let scale = max(length(params.transform[0].xyz), max(length(params.transform[1].xyz), length(params.transform[2].xyz)));
+	let radius = meshlet.radius * scale;
+
+	var visible = sphere_visible(center, radius);
+	if visible && params.cone_culling != 0u {
+		let axis = normalize((params.transform * vec4<f32>(meshlet.cone_axis, 0.0)).xyz);
+		let to_center = center - camera.position.xyz;
+		visible = dot(to_center, axis) < meshlet.cone_cutoff * length(to_center) + radius;
+	}
+
+	let draw = (params.output_offset + index) * 5u;
+	draws[draw] = meshlet.index_count;
+	draws[draw + 1u] = select(0u, 1u, visible);
+	draws[draw + 2u] = meshlet.first_index;
+	draws[draw + 3u] = 0u;
+	draws[draw + 4u] = 0u;
+}
@@ -1,7 +1,9 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
 use std::ops::Range;
 use cgmath;
 
-use crate::texture;
+use crate::{assets, bind_group, capsule_shadow, crowd, imposter, meshlet, skinning, uniform_arena, vat};
 
 pub trait Vertex {
 	fn desc() -> wgpu::VertexBufferLayout<'static>;
@@ -14,6 +16,7 @@ pub struct ModelVertex {
 	pub tex_coords: [f32; 2],
 	pub normal: [f32; 3],
 	pub tangent: [f32; 4],
+	pub lightmap_coords: [f32; 2], // the second uv channel, without overlaps, see unwrap::unwrap
 }
 
 impl Vertex for ModelVertex {
@@ -43,6 +46,11 @@ impl Vertex for ModelVertex {
 					shader_location: 3,
 					format: wgpu::VertexFormat::Float32x4,
 				},
+				wgpu::VertexAttribute { // lightmap coords
+					offset: mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
+					shader_location: 4,
+					format: wgpu::VertexFormat::Float32x2,
+				},
 			],
 		}
 	}
@@ -50,143 +58,531 @@ impl Vertex for ModelVertex {
 
 pub struct Model {
 	pub meshes: Vec<Mesh>,
+	pub bounds: Bounds,
+	pub aabb: Aabb,
+	pub imposter: Option<imposter::Imposter>, // drawn instead of the meshes when far away
+}
+
+// bounding sphere in model space
+#[derive(Copy, Clone, Debug)]
+pub struct Bounds {
+	pub center: [f32; 3],
+	pub radius: f32,
+}
+
+impl Bounds {
+	pub fn from_vertices<'a>(vertices: impl IntoIterator<Item = &'a ModelVertex> + Clone) -> Self {
+		let center = Aabb::from_vertices(vertices.clone()).center();
+		let radius = vertices.into_iter()
+			.map(|v| (0..3).map(|i| (v.position[i] - center[i]).powi(2)).sum::<f32>())
+			.fold(0.0, f32::max)
+			.sqrt();
+		Self { center, radius }
+	}
+
+	// smallest sphere containing both
+	pub fn union(&self, other: &Bounds) -> Self {
+		use cgmath::InnerSpace;
+		let (c1, c2) = (cgmath::Vector3::from(self.center), cgmath::Vector3::from(other.center));
+		let d = (c2 - c1).magnitude();
+		if d + other.radius <= self.radius {
+			return *self;
+		}
+		if d + self.radius <= other.radius {
+			return *other;
+		}
+		let radius = (d + self.radius + other.radius) * 0.5;
+		let center = c1 + (c2 - c1) * ((radius - self.radius) / d);
+		Self { center: center.into(), radius }
+	}
+
+	// world space center and radius under a transform, scaling by its largest axis
+	pub fn transformed(&self, transform: &cgmath::Matrix4<f32>) -> (cgmath::Point3<f32>, f32) {
+		use cgmath::{InnerSpace, Transform};
+		let center = transform.transform_point(self.center.into());
+		let scale = transform.x.truncate().magnitude()
+			.max(transform.y.truncate().magnitude())
+			.max(transform.z.truncate().magnitude());
+		(center, self.radius * scale)
+	}
+}
+
+// axis aligned bounding box, in model space for models
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+	pub min: [f32; 3],
+	pub max: [f32; 3],
+}
+
+impl Aabb {
+	// empty for no vertices
+	pub fn from_vertices<'a>(vertices: impl IntoIterator<Item = &'a ModelVertex>) -> Self {
+		Self::from_points(vertices.into_iter().map(|v| v.position.into()))
+	}
+
+	pub fn from_points(points: impl IntoIterator<Item = cgmath::Point3<f32>>) -> Self {
+		let mut min = [f32::MAX; 3];
+		let mut max = [f32::MIN; 3];
+		for p in points {
+			for i in 0..3 {
+				min[i] = min[i].min(p[i]);
+				max[i] = max[i].max(p[i]);
+			}
+		}
+		if min[0] > max[0] {
+			return Self { min: [0.0; 3], max: [0.0; 3] };
+		}
+		Self { min, max }
+	}
+
+	pub fn union(&self, other: &Aabb) -> Self {
+		Self {
+			min: [0, 1, 2].map(|i| self.min[i].min(other.min[i])),
+			max: [0, 1, 2].map(|i| self.max[i].max(other.max[i])),
+		}
+	}
+
+	pub fn center(&self) -> [f32; 3] {
+		[0, 1, 2].map(|i| (self.min[i] + self.max[i]) * 0.5)
+	}
+
+	pub fn corners(&self) -> [cgmath::Point3<f32>; 8] {
+		std::array::from_fn(|i| cgmath::Point3::new(
+			if i & 1 == 0 { self.min[0] } else { self.max[0] },
+			if i & 2 == 0 { self.min[1] } else { self.max[1] },
+			if i & 4 == 0 { self.min[2] } else { self.max[2] },
+		))
+	}
+
+	// box around the transformed corners
+	pub fn transformed(&self, transform: &cgmath::Matrix4<f32>) -> Self {
+		use cgmath::Transform;
+		Self::from_points(self.corners().map(|corner| transform.transform_point(corner)))
+	}
 }
 
 pub struct ModelInstance {
 	pub model_index: usize,
 	pub transform: cgmath::Matrix4::<f32>,
+	pub uv_transform: UvTransform,
+	pub skin: Option<usize>, // index into the scene's skins, drawn instead of the model's vertices
+	pub casts_shadows: bool, // skipped by shadow passes when false
+	pub receives_shadows: bool,
+	pub shadow_bias: Option<f32>, // world space offset along the normal, None uses the pass's default
+	pub shadow_capsules: Vec<capsule_shadow::Capsule>, // analytic shadow casters, cast even when casts_shadows is off
+	pub overrides: InstanceOverrides,
+	pub vat: Option<vat::VatPlayback>, // plays a baked vertex animation instead of the vertex buffer's positions
+	pub transparent: bool, // drawn alpha blended with the sorted objects even with opaque materials, for fading with the tint's alpha
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct ModelUniform {
-	pub transform: [[f32; 4]; 4],
+/*
+Material parameters changed for one instance, so copies of a model can look different while
+sharing its materials. They go through the model uniform with the transform, uv_transform is
+the instance's uv offset.
+*/
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct InstanceOverrides {
+	pub tint: [f32; 4], // multiplies the material color and opacity, linear
+	pub emissive: f32, // the tinted color added unlit, times this
+}
+
+impl Default for InstanceOverrides {
+	fn default() -> Self {
+		Self {
+			tint: [1.0; 4],
+			emissive: 0.0,
+		}
+	}
+}
+
+impl ModelInstance {
+	pub const CASTS_SHADOWS: u32 = 0x1;
+	pub const RECEIVES_SHADOWS: u32 = 0x2;
+
+	// an opaque instance of the model that casts and receives shadows, without overrides
+	pub fn new(model_index: usize, transform: cgmath::Matrix4<f32>) -> Self {
+		Self {
+			model_index,
+			transform,
+			uv_transform: UvTransform::default(),
+			skin: None,
+			casts_shadows: true,
+			receives_shadows: true,
+			shadow_bias: None,
+			shadow_capsules: vec![],
+			overrides: InstanceOverrides::default(),
+			vat: None,
+			transparent: false,
+		}
+	}
+
+	// the material's pipeline, blended when the instance is transparent and the material isn't
+	pub fn pipeline_key(&self, material: &Material) -> PipelineKey {
+		let key = material.pipeline_key();
+		if self.transparent && !material.is_transparent() {
+			PipelineKey { blend: BlendMode::Alpha, ..key }
+		} else {
+			key
+		}
+	}
+
+	pub fn shadow_flags(&self) -> u32 {
+		let mut flags = 0;
+		if self.casts_shadows {
+			flags |= Self::CASTS_SHADOWS;
+		}
+		if self.receives_shadows {
+			flags |= Self::RECEIVES_SHADOWS;
+		}
+		flags
+	}
+
+	pub fn to_uniform(&self) -> ModelUniform {
+		ModelUniform {
+			transform: self.transform.into(),
+			uv_offset_scale: self.uv_transform.offset_scale(),
+			uv_rotation: self.uv_transform.rotation,
+			shadow_bias: self.shadow_bias.unwrap_or(0.0),
+			shadow_flags: self.shadow_flags(),
+			emissive: self.overrides.emissive,
+			tint: self.overrides.tint,
+			vat: self.vat.map_or([0.0; 4], |playback| playback.to_uniform()),
+			..bytemuck::Zeroable::zeroed()
+		}
+	}
+}
+
+gpu_struct! {
+	Uniform
+	#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+	pub struct ModelUniform {
+		pub transform: [[f32; 4]; 4],
+		pub uv_offset_scale: [f32; 4],
+		pub uv_rotation: f32,
+		pub shadow_bias: f32, // 0 uses the default bias
+		pub shadow_flags: u32,
+		pub emissive: f32,
+		pub tint: [f32; 4],
+		pub vat: [f32; 4], // clip start, vertex count, frame count, frame
+	}
+}
+
+impl Default for ModelUniform {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl ModelUniform {
+	// mesh.wgsl reads this many model uniforms from the dynamic offset, one per instance of a draw
+	pub const MAX_INSTANCES: usize = 128;
+
+	// an identity transform without overrides, neither casting nor receiving shadows
+	pub fn new() -> Self {
+		use cgmath::SquareMatrix;
+		Self {
+			transform: cgmath::Matrix4::identity().into(),
+			uv_offset_scale: UvTransform::default().offset_scale(),
+			uv_rotation: 0.0,
+			shadow_bias: 0.0,
+			shadow_flags: 0,
+			emissive: 0.0,
+			tint: [1.0; 4],
+			vat: [0.0; 4],
+			..bytemuck::Zeroable::zeroed()
+		}
+	}
+
+	// every bind group of the uniform layout binds the model uniforms like this
+	pub fn binding(uniform_arena: &uniform_arena::UniformArena) -> wgpu::BindingResource<'_> {
+		uniform_arena.binding_array::<Self>(Self::MAX_INSTANCES)
+	}
+}
+
+// the instances of a draw are bound at once, so they have to fit in the arena's binding window
+const _: () = assert!(
+	(ModelUniform::MAX_INSTANCES * std::mem::size_of::<ModelUniform>()) as u64 <= uniform_arena::UniformArena::BINDING_WINDOW,
+	"MAX_INSTANCES model uniforms don't fit in the uniform arena's binding window",
+);
+
+// texture coordinates are scaled, then rotated around the uv center, then offset
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UvTransform {
+	pub offset: [f32; 2],
+	pub scale: [f32; 2],
+	pub rotation: f32, // radians
+}
+
+impl Default for UvTransform {
+	fn default() -> Self {
+		Self {
+			offset: [0.0, 0.0],
+			scale: [1.0, 1.0],
+			rotation: 0.0,
+		}
+	}
+}
+
+impl UvTransform {
+	pub fn offset_scale(&self) -> [f32; 4] {
+		[self.offset[0], self.offset[1], self.scale[0], self.scale[1]]
+	}
 }
 
 pub enum MaterialType {
 	SingleColorMaterial([f32; 3]),
-	DiffuseMapMaterial(texture::Texture),
-	DiffuseNormalMapMaterial(texture::Texture, texture::Texture),
+	DiffuseMapMaterial(assets::TextureHandle),
+	DiffuseNormalMapMaterial(assets::TextureHandle, assets::TextureHandle),
+	// diffuse, normal, then detail diffuse and detail normal tiled on top
+	DetailMapMaterial(assets::TextureHandle, assets::TextureHandle, assets::TextureHandle, assets::TextureHandle),
+	// diffuse, normal, the mask, then each layer's diffuse and normal, blended over the base by the mask's channels in order
+	LayeredMaterial(assets::TextureHandle, assets::TextureHandle, assets::TextureHandle, [(assets::TextureHandle, assets::TextureHandle); MaterialType::LAYERS]),
+	// diffuse and the cel shading ramp, lit in steps by the ramp with a rim light and a hard specular highlight
+	ToonMaterial(assets::TextureHandle, assets::TextureHandle),
 	//PbrMaterial(texture::Texture, texture::Texture, texture::Texture),
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-pub struct SimpleMaterial {
-	pub diffuse_spec: [f32; 4],
-	pub roughness: f32,
-	pub metal: f32,
-	padding: [f32; 2],
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+	#[default]
+	Opaque,
+	Alpha,
+	Additive,
+}
+
+impl BlendMode {
+	pub const ALL: [BlendMode; 3] = [BlendMode::Opaque, BlendMode::Alpha, BlendMode::Additive];
+
+	pub fn blend_state(&self) -> wgpu::BlendState {
+		match self {
+			BlendMode::Opaque => wgpu::BlendState::REPLACE,
+			BlendMode::Alpha => wgpu::BlendState::ALPHA_BLENDING,
+			BlendMode::Additive => wgpu::BlendState {
+				color: wgpu::BlendComponent {
+					src_factor: wgpu::BlendFactor::SrcAlpha,
+					dst_factor: wgpu::BlendFactor::One,
+					operation: wgpu::BlendOperation::Add,
+				},
+				alpha: wgpu::BlendComponent::OVER,
+			},
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CullMode {
+	#[default]
+	Back,
+	Front,
+	None,
+}
+
+impl CullMode {
+	pub const ALL: [CullMode; 3] = [CullMode::Back, CullMode::Front, CullMode::None];
+
+	pub fn face(&self) -> Option<wgpu::Face> {
+		match self {
+			CullMode::Back => Some(wgpu::Face::Back),
+			CullMode::Front => Some(wgpu::Face::Front),
+			CullMode::None => None,
+		}
+	}
+}
+
+/// Identifies one of the renderer's pipelines
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+	pub material_type: usize,
+	pub blend: BlendMode,
+	pub cull: CullMode,
+}
+
+gpu_struct! {
+	Uniform
+	#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+	pub struct SimpleMaterial {
+		pub diffuse_spec: [f32; 4], // diffuse color, reflection fresnel f0
+		pub specular_shininess: [f32; 4], // specular highlight color, specular exponent
+		pub detail: [f32; 4], // detail tiling, fade start distance, fade end distance, strength
+		pub uv_offset_scale: [f32; 4],
+		pub flipbook: [f32; 4], // columns, rows, frame count (0 disables), frames per second
+		pub layers: [[f32; 4]; MaterialType::LAYERS], // tiling, weight, mask bias, mask sharpness, only read by layered materials
+		pub toon: [f32; 4], // rim strength, rim width, specular size, unused, only read by toon materials
+		pub wind: [f32; 4], // stiffness, sway frequency in hertz, height where the sway is full (0 doesn't sway), unused
+		pub roughness: f32,
+		pub metal: f32,
+		pub opacity: f32,
+		pub illum: u32, // .mtl illumination model: 0 unlit, 1 diffuse, 2 diffuse + specular
+		pub triplanar: [f32; 2], // world space tiling (0 uses the mesh uvs), blend sharpness
+		pub uv_rotation: f32,
+	}
+}
+
+impl Default for SimpleMaterial {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl SimpleMaterial {
 	pub fn new() -> Self {
 		Self {
 			diffuse_spec: [1.0, 0.0, 0.0, 0.8],
+			specular_shininess: [0.0, 0.0, 0.0, 32.0],
+			detail: [8.0, 5.0, 20.0, 1.0],
+			uv_offset_scale: UvTransform::default().offset_scale(),
+			flipbook: [1.0, 1.0, 0.0, 0.0],
+			layers: [[1.0, 0.0, 0.0, 1.0]; MaterialType::LAYERS],
+			toon: [0.5, 0.3, 0.5, 0.0],
+			wind: [1.0, 0.5, 0.0, 0.0],
 			roughness: 0.5,
 			metal: 0.0,
-			padding: [0.0; 2],
+			opacity: 1.0,
+			illum: 2,
+			triplanar: [0.0, 4.0],
+			uv_rotation: 0.0,
+			..bytemuck::Zeroable::zeroed()
 		}
 	}
+
+	pub fn set_uv_transform(&mut self, uv_transform: &UvTransform) {
+		self.uv_offset_scale = uv_transform.offset_scale();
+		self.uv_rotation = uv_transform.rotation;
+	}
 }
 
 impl MaterialType {
-	pub fn create_texture_bind_group_layouts(device: &wgpu::Device) -> [wgpu::BindGroupLayout; 2] {
-
-		let diffuse_texture_entry = wgpu::BindGroupLayoutEntry {
-			binding: 0,
-			visibility: wgpu::ShaderStages::FRAGMENT,
-			ty: wgpu::BindingType::Texture {
-				multisampled: false,
-				view_dimension: wgpu::TextureViewDimension::D2,
-				sample_type: wgpu::TextureSampleType::Float {filterable: true},
+	pub const COUNT: usize = 6;
+	pub const LAYERS: usize = 2; // blended over a layered material's base, one per mask channel
+
+	/// Index into the renderer's pipelines and texture bind group layouts
+	pub fn pipeline_index(&self) -> usize {
+		match self {
+			MaterialType::SingleColorMaterial(_) => 0,
+			MaterialType::DiffuseMapMaterial(_) => 1,
+			MaterialType::DiffuseNormalMapMaterial(_, _) => 2,
+			MaterialType::DetailMapMaterial(_, _, _, _) => 3,
+			MaterialType::LayeredMaterial(_, _, _, _) => 4,
+			MaterialType::ToonMaterial(_, _) => 5,
+		}
+	}
+
+	// in binding order, each texture's view and sampler follow the params in a pair
+	pub fn textures(&self) -> Vec<assets::TextureHandle> {
+		match *self {
+			MaterialType::SingleColorMaterial(_) => vec![],
+			MaterialType::DiffuseMapMaterial(diffuse_texture) => vec![diffuse_texture],
+			MaterialType::DiffuseNormalMapMaterial(diffuse_texture, normal_texture) => vec![diffuse_texture, normal_texture],
+			MaterialType::DetailMapMaterial(diffuse_texture, normal_texture, detail_diffuse_texture, detail_normal_texture) => {
+				vec![diffuse_texture, normal_texture, detail_diffuse_texture, detail_normal_texture]
 			},
-			count: None,
-		};
-		let diffuse_sampler_entry = wgpu::BindGroupLayoutEntry {
-			binding: 1,
-			visibility: wgpu::ShaderStages::FRAGMENT,
-			ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-			count: None,
-		};
-		let normal_texture_entry = wgpu::BindGroupLayoutEntry {
-			binding: 2,
-			visibility: wgpu::ShaderStages::FRAGMENT,
-			ty: wgpu::BindingType::Texture {
-				multisampled: false,
-				view_dimension: wgpu::TextureViewDimension::D2,
-				sample_type: wgpu::TextureSampleType::Float {filterable: true},
+			MaterialType::LayeredMaterial(diffuse_texture, normal_texture, mask_texture, layers) => {
+				[diffuse_texture, normal_texture, mask_texture].into_iter()
+					.chain(layers.into_iter().flat_map(|(diffuse, normal)| [diffuse, normal]))
+					.collect()
 			},
-			count: None,
-		};
-		let normal_sampler_entry = wgpu::BindGroupLayoutEntry {
-			binding: 3,
-			visibility: wgpu::ShaderStages::FRAGMENT,
-			ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-			count: None,
-		};
+			MaterialType::ToonMaterial(diffuse_texture, ramp_texture) => vec![diffuse_texture, ramp_texture],
+		}
+	}
+
+	pub fn create_texture_bind_group_layouts(device: &wgpu::Device) -> [wgpu::BindGroupLayout; MaterialType::COUNT] {
+
+		// each material type's layout adds texture and sampler pairs to the one before it
+		let single_color = bind_group::LayoutBuilder::new(wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT)
+			.dynamic_uniform::<SimpleMaterial>(0, 1)
+			.visibility(wgpu::ShaderStages::FRAGMENT);
+		let diffuse_map = single_color.clone().texture(1).sampler(2);
+		let diffuse_normal_map = diffuse_map.clone().texture(3).sampler(4);
+		let toon = diffuse_normal_map.clone(); // the ramp in the normal map's place
+		let detail_map = diffuse_normal_map.clone().texture(5).sampler(6).texture(7).sampler(8);
+		let layered = (0..MaterialType::LAYERS as u32).fold(
+			diffuse_normal_map.clone().texture(5).sampler(6),
+			|layout, layer| layout.texture(7 + layer * 4).sampler(8 + layer * 4).texture(9 + layer * 4).sampler(10 + layer * 4),
+		);
 
 		[
-			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-				entries: &[diffuse_texture_entry.clone(), diffuse_sampler_entry.clone()],
-				label: Some("DiffuseMap texture_bind_group_layout"),
-			}),
-			device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-				entries: &[
-					diffuse_texture_entry.clone(), 
-					diffuse_sampler_entry.clone(),
-					normal_texture_entry.clone(),
-					normal_sampler_entry.clone(),
-				],
-				label: Some("DiffuseNormalMap texture_bind_group_layout"),
-			}),
+			single_color.build(device, "SingleColor bind_group_layout"),
+			diffuse_map.build(device, "DiffuseMap texture_bind_group_layout"),
+			diffuse_normal_map.build(device, "DiffuseNormalMap texture_bind_group_layout"),
+			detail_map.build(device, "DetailMap texture_bind_group_layout"),
+			layered.build(device, "Layered texture_bind_group_layout"),
+			toon.build(device, "Toon texture_bind_group_layout"),
 		]
 	}
 }
 
 pub struct Material {
 	pub name: String,
-	pub diffuse_texture: texture::Texture,
-	pub normal_texture: texture::Texture,
+	pub material_type: MaterialType,
+	pub params: SimpleMaterial, // uploaded through the renderer's uniform arena every frame
+	pub blend: BlendMode,
+	pub cull: CullMode,
 	pub bind_group: wgpu::BindGroup,
+	pub arena_generation: u32, // of the uniform arena buffer the bind group was made with
 }
 
 impl Material {
 	pub fn new(
 		device: &wgpu::Device,
 		name: &str,
-		diffuse_texture: texture::Texture,
-		normal_texture: texture::Texture,
-		layout: &wgpu::BindGroupLayout,
+		material_type: MaterialType,
+		mut params: SimpleMaterial,
+		layouts: &[wgpu::BindGroupLayout; MaterialType::COUNT],
+		uniform_arena: &uniform_arena::UniformArena,
+		assets: &assets::Assets,
 	) -> Self {
-		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-			layout,
-			entries: &[
-				wgpu::BindGroupEntry {
-					binding: 0,
-					resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-				},
-				wgpu::BindGroupEntry {
-					binding: 1,
-					resource: wgpu::BindingResource::Sampler(&diffuse_texture.sampler),
-				},
-				wgpu::BindGroupEntry {
-					binding: 2,
-					resource: wgpu::BindingResource::TextureView(&normal_texture.view),
-				},
-				wgpu::BindGroupEntry {
-					binding: 3,
-					resource: wgpu::BindingResource::Sampler(&normal_texture.sampler),
-				},
-			],
-			label: Some(name),
-		});
+		if let MaterialType::SingleColorMaterial(color) = &material_type {
+			params.diffuse_spec = [color[0], color[1], color[2], params.diffuse_spec[3]];
+		}
+
+		let bind_group = Self::create_bind_group(device, name, &material_type, layouts, uniform_arena, assets);
 
 		Self {
 			name: String::from(name),
-			diffuse_texture,
-			normal_texture,
+			material_type,
+			params,
+			blend: BlendMode::default(),
+			cull: CullMode::default(),
 			bind_group,
+			arena_generation: uniform_arena.generation(),
+		}
+	}
+
+	fn create_bind_group(
+		device: &wgpu::Device,
+		name: &str,
+		material_type: &MaterialType,
+		layouts: &[wgpu::BindGroupLayout; MaterialType::COUNT],
+		uniform_arena: &uniform_arena::UniformArena,
+		assets: &assets::Assets,
+	) -> wgpu::BindGroup {
+		let textures = material_type.textures().into_iter().map(|handle| assets.texture(handle)).collect::<Vec<_>>();
+		textures.iter().enumerate()
+			.fold(
+				bind_group::BindGroupBuilder::default().resource(0, uniform_arena.binding::<SimpleMaterial>()),
+				|builder, (idx, texture)| builder.texture(idx as u32 * 2 + 1, texture),
+			)
+			.build(device, &layouts[material_type.pipeline_index()], name)
+	}
+
+	// after one of its textures has changed, like a placeholder that finished loading
+	pub fn update_bind_group(&mut self, device: &wgpu::Device, layouts: &[wgpu::BindGroupLayout; MaterialType::COUNT], uniform_arena: &uniform_arena::UniformArena, assets: &assets::Assets) {
+		self.bind_group = Self::create_bind_group(device, &self.name, &self.material_type, layouts, uniform_arena, assets);
+		self.arena_generation = uniform_arena.generation();
+	}
+
+	// blended materials draw after the opaque ones and the sky, sorted back to front
+	pub fn is_transparent(&self) -> bool {
+		self.blend != BlendMode::Opaque
+	}
+
+	pub fn pipeline_key(&self) -> PipelineKey {
+		PipelineKey {
+			material_type: self.material_type.pipeline_index(),
+			blend: self.blend,
+			cull: self.cull,
 		}
 	}
 }
@@ -197,30 +593,359 @@ pub struct Mesh {
 	pub index_buffer: wgpu::Buffer,
 	pub num_elements: u32,
 	pub material: usize,
+	pub blas: Option<wgpu::Blas>, // only built when ray tracing is supported
+	pub meshlets: Option<meshlet::MeshletMesh>, // only built for very high poly meshes when supported
 }
 
+// material_offset is the dynamic offset of the material's parameters in the uniform arena
 pub trait DrawModel<'a> {
 	fn draw_mesh(
 		&mut self,
 		mesh: &'a Mesh,
 		material: &'a Material,
+		material_offset: u32,
 	);
 	fn draw_mesh_instanced(
 		&mut self,
 		mesh: &'a Mesh,
 		material: &'a Material,
+		material_offset: u32,
 		instances: Range<u32>
 	);
+	fn draw_skinned_mesh(
+		&mut self,
+		mesh: &'a Mesh,
+		skinned: &'a skinning::SkinnedMesh,
+		material: &'a Material,
+		material_offset: u32,
+	);
+	fn draw_crowd_mesh(
+		&mut self,
+		mesh: &'a Mesh,
+		crowd_mesh: &'a crowd::CrowdMesh,
+		material: &'a Material,
+		material_offset: u32,
+		instance_count: u32,
+	);
+	fn draw_mesh_indirect(
+		&mut self,
+		mesh: &'a Mesh,
+		material: &'a Material,
+		material_offset: u32,
+		draws: &wgpu::Buffer,
+		draws_offset: wgpu::BufferAddress,
+		draw_count: u32,
+	);
 }
 
 impl<'a, 'b> DrawModel<'b> for wgpu::RenderPass<'a> where 'b: 'a, {
-	fn draw_mesh(&mut self, mesh: &'b Mesh, material: &'b Material) {
-		self.draw_mesh_instanced(mesh, material, 0..1);
+	fn draw_mesh(&mut self, mesh: &'b Mesh, material: &'b Material, material_offset: u32) {
+		self.draw_mesh_instanced(mesh, material, material_offset, 0..1);
 	}
-	fn draw_mesh_instanced(&mut self, mesh: &'b Mesh, material: &'b Material, instances: Range<u32>) {
+	fn draw_mesh_instanced(&mut self, mesh: &'b Mesh, material: &'b Material, material_offset: u32, instances: Range<u32>) {
 		self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
 		self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-		self.set_bind_group(0, &material.bind_group, &[]);
+		self.set_bind_group(0, &material.bind_group, &[material_offset]);
 		self.draw_indexed(0..mesh.num_elements, 0, instances);
 	}
-}
\ No newline at end of file
+	fn draw_skinned_mesh(&mut self, mesh: &'b Mesh, skinned: &'b skinning::SkinnedMesh, material: &'b Material, material_offset: u32) {
+		self.set_vertex_buffer(0, skinned.vertex_buffer.slice(..));
+		self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+		self.set_bind_group(0, &material.bind_group, &[material_offset]);
+		self.draw_indexed(0..mesh.num_elements, 0, 0..1);
+	}
+	// every instance's vertices follow the previous one's in the crowd's vertex buffer
+	fn draw_crowd_mesh(&mut self, mesh: &'b Mesh, crowd_mesh: &'b crowd::CrowdMesh, material: &'b Material, material_offset: u32, instance_count: u32) {
+		self.set_vertex_buffer(0, crowd_mesh.vertex_buffer.slice(..));
+		self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+		self.set_bind_group(0, &material.bind_group, &[material_offset]);
+		for instance in 0..instance_count {
+			self.draw_indexed(0..mesh.num_elements, (instance * crowd_mesh.vertex_count) as i32, 0..1);
+		}
+	}
+	fn draw_mesh_indirect(&mut self, mesh: &'b Mesh, material: &'b Material, material_offset: u32, draws: &wgpu::Buffer, draws_offset: wgpu::BufferAddress, draw_count: u32) {
+		self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+		self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+		self.set_bind_group(0, &material.bind_group, &[material_offset]);
+		self.multi_draw_indexed_indirect(draws, draws_offset, draw_count);
+	}
+}
+
+// the error of moving a point away from a set of planes, as the upper triangle of a symmetric 4x4 matrix
+#[derive(Copy, Clone, Debug, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+	// a plane through the point with the normal, weighted
+	fn from_plane(normal: [f64; 3], point: [f64; 3], weight: f64) -> Self {
+		let [a, b, c] = normal;
+		let d = -(a * point[0] + b * point[1] + c * point[2]);
+		Self([a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d].map(|q| q * weight))
+	}
+
+	fn add(&self, other: &Quadric) -> Self {
+		Self(std::array::from_fn(|i| self.0[i] + other.0[i]))
+	}
+
+	fn error(&self, p: [f64; 3]) -> f64 {
+		let q = &self.0;
+		let [x, y, z] = p;
+		q[0] * x * x + 2.0 * q[1] * x * y + 2.0 * q[2] * x * z + 2.0 * q[3] * x
+			+ q[4] * y * y + 2.0 * q[5] * y * z + 2.0 * q[6] * y
+			+ q[7] * z * z + 2.0 * q[8] * z
+			+ q[9]
+	}
+}
+
+// ordered by cost so the heap pops the cheapest collapse
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Collapse {
+	cost: f64,
+	from: u32,
+	to: u32,
+	version: u32, // the from vertex's version when this was queued
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+	fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for Collapse {
+	fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+		self.cost.total_cmp(&other.cost)
+	}
+}
+
+fn vertex_position(vertex: &ModelVertex) -> [f64; 3] {
+	vertex.position.map(f64::from)
+}
+
+fn triangle_normal(a: [f64; 3], b: [f64; 3], c: [f64; 3]) -> [f64; 3] {
+	let (u, v) = ([b[0] - a[0], b[1] - a[1], b[2] - a[2]], [c[0] - a[0], c[1] - a[1], c[2] - a[2]]);
+	[u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]]
+}
+
+/*
+Quadric error metric simplification, for generating LODs and cutting down imported scans. Edges
+are collapsed cheapest first until target_ratio of the triangles are left, or nothing more can
+be collapsed. A vertex is only ever moved onto its neighbour, so every kept vertex keeps its own
+uvs and normal, and vertices on open borders or on uv and normal seams never move, which keeps
+the outline and the texture layout intact. Collapses that would flip a triangle are skipped.
+The vertices come back compacted to the ones still used.
+*/
+pub fn simplify(vertices: &[ModelVertex], indices: &[u32], target_ratio: f32) -> (Vec<ModelVertex>, Vec<u32>) {
+	let mut triangles = indices.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect::<Vec<_>>();
+	let target = (triangles.len() as f32 * target_ratio.clamp(0.0, 1.0)) as usize;
+
+	let mut quadrics = vec![Quadric::default(); vertices.len()];
+	let mut vertex_triangles = vec![vec![]; vertices.len()];
+	let mut edges = HashMap::new(); // triangle count of each edge, by its lower vertex first
+	for (index, triangle) in triangles.iter().enumerate() {
+		let [a, b, c] = triangle.map(|v| vertex_position(&vertices[v as usize]));
+		let normal = triangle_normal(a, b, c);
+		let length = (normal[0] * normal[0] + normal[1] * normal[1] + normal[2] * normal[2]).sqrt();
+		if length > 0.0 {
+			// weighted by area, so small triangles don't pin down large flat regions
+			let quadric = Quadric::from_plane(normal.map(|n| n / length), a, length * 0.5);
+			for &v in triangle {
+				quadrics[v as usize] = quadrics[v as usize].add(&quadric);
+			}
+		}
+		for (i, &v) in triangle.iter().enumerate() {
+			vertex_triangles[v as usize].push(index);
+			let next = triangle[(i + 1) % 3];
+			*edges.entry((v.min(next), v.max(next))).or_insert(0) += 1;
+		}
+	}
+
+	// border and seam vertices stay where they are
+	let mut locked = vec![false; vertices.len()];
+	let mut positions = HashMap::new();
+	for (index, vertex) in vertices.iter().enumerate() {
+		if let Some(&other) = positions.get(&vertex.position.map(f32::to_bits)) {
+			locked[index] = true;
+			locked[other] = true;
+		} else {
+			positions.insert(vertex.position.map(f32::to_bits), index);
+		}
+	}
+	for (&(a, b), &count) in &edges {
+		if count != 2 {
+			locked[a as usize] = true;
+			locked[b as usize] = true;
+		}
+	}
+
+	let mut versions = vec![0u32; vertices.len()];
+	let mut heap = BinaryHeap::new();
+	let queue = |heap: &mut BinaryHeap<Reverse<Collapse>>, quadrics: &[Quadric], versions: &[u32], from: u32, to: u32| {
+		if !locked[from as usize] {
+			let cost = quadrics[from as usize].add(&quadrics[to as usize]).error(vertex_position(&vertices[to as usize]));
+			heap.push(Reverse(Collapse { cost, from, to, version: versions[from as usize] }));
+		}
+	};
+	for &(a, b) in edges.keys() {
+		queue(&mut heap, &quadrics, &versions, a, b);
+		queue(&mut heap, &quadrics, &versions, b, a);
+	}
+
+	let mut alive = vec![true; triangles.len()];
+	let mut removed_vertex = vec![false; vertices.len()];
+	let mut live_count = triangles.len();
+	while live_count > target {
+		let Some(Reverse(collapse)) = heap.pop() else {
+			break;
+		};
+		let (from, to) = (collapse.from as usize, collapse.to as usize);
+		if removed_vertex[from] || removed_vertex[to] || collapse.version != versions[from] {
+			continue;
+		}
+		// the edge may be gone after earlier collapses
+		if !vertex_triangles[from].iter().any(|&t| alive[t] && triangles[t].contains(&collapse.to)) {
+			continue;
+		}
+		// moving from onto to mustn't turn any of its other triangles over
+		let target_position = vertex_position(&vertices[to]);
+		let flips = vertex_triangles[from].iter()
+			.filter(|&&t| alive[t] && !triangles[t].contains(&collapse.to))
+			.any(|&t| {
+				let corners = triangles[t].map(|v| vertex_position(&vertices[v as usize]));
+				let moved = triangles[t].map(|v| if v == collapse.from { target_position } else { vertex_position(&vertices[v as usize]) });
+				let before = triangle_normal(corners[0], corners[1], corners[2]);
+				let after = triangle_normal(moved[0], moved[1], moved[2]);
+				let dot = |a: [f64; 3], b: [f64; 3]| a[0] * b[0] + a[1] * b[1] + a[2] * b[2];
+				dot(before, before) > 0.0 && dot(before, after) <= 0.0
+			});
+		if flips {
+			continue;
+		}
+
+		removed_vertex[from] = true;
+		quadrics[to] = quadrics[to].add(&quadrics[from]);
+		for t in std::mem::take(&mut vertex_triangles[from]) {
+			if !alive[t] {
+				continue;
+			}
+			if triangles[t].contains(&collapse.to) {
+				alive[t] = false;
+				live_count -= 1;
+			} else {
+				for v in &mut triangles[t] {
+					if *v == collapse.from {
+						*v = collapse.to;
+					}
+				}
+				vertex_triangles[to].push(t);
+			}
+		}
+		vertex_triangles[to].retain(|&t| alive[t]);
+
+		// the neighbours' costs changed with the merged quadric
+		let mut neighbours = vertex_triangles[to].iter()
+			.flat_map(|&t| triangles[t])
+			.filter(|&v| v != collapse.to)
+			.collect::<Vec<_>>();
+		neighbours.sort_unstable();
+		neighbours.dedup();
+		versions[to] += 1;
+		for &neighbour in &neighbours {
+			versions[neighbour as usize] += 1;
+		}
+		for &neighbour in &neighbours {
+			queue(&mut heap, &quadrics, &versions, collapse.to, neighbour);
+			for &next in vertex_triangles[neighbour as usize].iter().filter(|&&t| alive[t]).flat_map(|&t| &triangles[t]) {
+				if next != neighbour {
+					queue(&mut heap, &quadrics, &versions, neighbour, next);
+				}
+			}
+		}
+	}
+
+	// keep only the vertices still used
+	let mut remap = vec![u32::MAX; vertices.len()];
+	let mut simplified_vertices = vec![];
+	let mut simplified_indices = vec![];
+	for (triangle, _) in triangles.iter().zip(&alive).filter(|(_, alive)| **alive) {
+		for &v in triangle {
+			if remap[v as usize] == u32::MAX {
+				remap[v as usize] = simplified_vertices.len() as u32;
+				simplified_vertices.push(vertices[v as usize]);
+			}
+			simplified_indices.push(remap[v as usize]);
+		}
+	}
+	(simplified_vertices, simplified_indices)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// an octahedron subdivided onto the unit sphere, closed with every vertex shared
+	fn sphere(subdivisions: u32) -> (Vec<ModelVertex>, Vec<u32>) {
+		let mut positions: Vec<cgmath::Vector3<f32>> = vec![
+			cgmath::Vector3::unit_x(), -cgmath::Vector3::unit_x(),
+			cgmath::Vector3::unit_y(), -cgmath::Vector3::unit_y(),
+			cgmath::Vector3::unit_z(), -cgmath::Vector3::unit_z(),
+		];
+		let mut triangles = vec![
+			[0, 2, 4], [2, 1, 4], [1, 3, 4], [3, 0, 4],
+			[2, 0, 5], [1, 2, 5], [3, 1, 5], [0, 3, 5],
+		];
+		for _ in 0..subdivisions {
+			let mut midpoints = HashMap::new();
+			let mut midpoint = |positions: &mut Vec<cgmath::Vector3<f32>>, a: u32, b: u32| {
+				*midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+					use cgmath::InnerSpace;
+					positions.push((positions[a as usize] + positions[b as usize]).normalize());
+					positions.len() as u32 - 1
+				})
+			};
+			triangles = triangles.into_iter()
+				.flat_map(|[a, b, c]| {
+					let (ab, bc, ca) = (midpoint(&mut positions, a, b), midpoint(&mut positions, b, c), midpoint(&mut positions, c, a));
+					[[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]]
+				})
+				.collect();
+		}
+		let vertices = positions.iter()
+			.map(|&position| ModelVertex {
+				position: position.into(),
+				tex_coords: [0.0; 2],
+				normal: position.into(),
+				tangent: [0.0; 4],
+				lightmap_coords: [0.0; 2],
+			})
+			.collect();
+		(vertices, triangles.into_iter().flatten().collect())
+	}
+
+	// every edge is shared by two triangles that wind it opposite ways
+	fn assert_manifold(indices: &[u32]) {
+		let mut edges = HashMap::new();
+		for triangle in indices.chunks_exact(3) {
+			for i in 0..3 {
+				*edges.entry((triangle[i], triangle[(i + 1) % 3])).or_insert(0) += 1;
+			}
+		}
+		for (&(a, b), &count) in &edges {
+			assert_eq!(count, 1, "edge {}-{} is used {} times the same way", a, b, count);
+			assert!(edges.contains_key(&(b, a)), "edge {}-{} is open", a, b);
+		}
+	}
+
+	#[test]
+	fn simplify_reaches_the_target_and_stays_closed() {
+		let (vertices, indices) = sphere(3);
+		assert_manifold(&indices);
+		let triangle_count = indices.len() / 3;
+		let (simplified_vertices, simplified_indices) = simplify(&vertices, &indices, 0.25);
+		assert_eq!(simplified_indices.len() / 3, triangle_count / 4);
+		assert_manifold(&simplified_indices);
+		assert!(simplified_indices.iter().all(|&index| (index as usize) < simplified_vertices.len()));
+	}
+}
@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+
+use crate::{model, unwrap};
+
+// one step of a modifier stack, see ModifierStack
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Modifier {
+	Subdivide { levels: u32 }, // splits every triangle into four, the new vertices stay on the old surface
+	Smooth { iterations: u32, factor: f32 }, // moves vertices toward their neighbours, factor 0 to 1
+	Displace { amplitude: f32, frequency: f32, seed: u32 }, // pushes vertices along their normals by value noise
+	Mirror { axis: usize }, // adds a copy flipped across the plane through the origin, 0 x, 1 y, 2 z
+	Array { count: u32, offset: [f32; 3] }, // count copies in total, each offset from the last
+	Simplify { ratio: f32 }, // collapses edges until this fraction of the triangles is left, see model::simplify
+	Unwrap, // generates lightmap coords without overlaps, the uvs are kept, see unwrap::unwrap
+}
+
+impl Modifier {
+	/*
+	A modifier as typed in the console: subdivide[:levels], smooth[:iterations[:factor]],
+	noise:amplitude[:frequency[:seed]], mirror:x|y|z, array:count:dx:dy:dz, simplify:ratio, or unwrap
+	*/
+	pub fn parse(text: &str) -> anyhow::Result<Self> {
+		let mut parts = text.split(':');
+		let name = parts.next().unwrap_or_default();
+		let args = parts.collect::<Vec<_>>();
+		let arg = |index: usize, default: f32| -> anyhow::Result<f32> {
+			args.get(index).map_or(Ok(default), |arg| Ok(arg.parse()?))
+		};
+		Ok(match name {
+			"subdivide" => Self::Subdivide { levels: arg(0, 1.0)? as u32 },
+			"smooth" => Self::Smooth { iterations: arg(0, 1.0)? as u32, factor: arg(1, 0.5)? },
+			"noise" => Self::Displace { amplitude: arg(0, 0.1)?, frequency: arg(1, 1.0)?, seed: arg(2, 0.0)? as u32 },
+			"mirror" => Self::Mirror {
+				axis: match args.first().copied().unwrap_or("x") {
+					"x" => 0,
+					"y" => 1,
+					"z" => 2,
+					axis => anyhow::bail!("no axis {}", axis),
+				},
+			},
+			"array" => Self::Array { count: arg(0, 2.0)? as u32, offset: [arg(1, 1.0)?, arg(2, 0.0)?, arg(3, 0.0)?] },
+			"simplify" => Self::Simplify { ratio: arg(0, 0.5)? },
+			"unwrap" => Self::Unwrap,
+			_ => anyhow::bail!("no modifier {}", name),
+		})
+	}
+}
+
+/*
+Procedural changes to a mesh's geometry, applied in order on the CPU before the mesh is
+uploaded, either while a model loads or on demand into a new model. Tangents are generated
+afterwards like for any loaded mesh. Smoothing and displacement move vertices by the welded
+position, so vertices split along uv seams stay together, and leave smooth normals behind.
+Hard edges don't survive smoothing or displacement.
+*/
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ModifierStack {
+	pub modifiers: Vec<Modifier>,
+}
+
+impl ModifierStack {
+	pub fn new(modifiers: Vec<Modifier>) -> Self {
+		Self {
+			modifiers,
+		}
+	}
+
+	pub fn parse<'a>(modifiers: impl IntoIterator<Item = &'a str>) -> anyhow::Result<Self> {
+		Ok(Self::new(modifiers.into_iter().map(Modifier::parse).collect::<anyhow::Result<_>>()?))
+	}
+
+	pub fn apply(&self, mut vertices: Vec<model::ModelVertex>, mut indices: Vec<u32>) -> (Vec<model::ModelVertex>, Vec<u32>) {
+		for modifier in &self.modifiers {
+			match *modifier {
+				Modifier::Subdivide { levels } => {
+					for _ in 0..levels {
+						subdivide(&mut vertices, &mut indices);
+					}
+				}
+				Modifier::Smooth { iterations, factor } => {
+					smooth(&mut vertices, &indices, iterations, factor);
+					compute_normals(&mut vertices, &indices);
+				}
+				Modifier::Displace { amplitude, frequency, seed } => {
+					displace(&mut vertices, amplitude, frequency, seed);
+					compute_normals(&mut vertices, &indices);
+				}
+				Modifier::Mirror { axis } => mirror(&mut vertices, &mut indices, axis),
+				Modifier::Array { count, offset } => array(&mut vertices, &mut indices, count, offset),
+				Modifier::Simplify { ratio } => (vertices, indices) = model::simplify(&vertices, &indices, ratio),
+				Modifier::Unwrap => (vertices, indices) = unwrap::unwrap(&vertices, &indices, &unwrap::UnwrapSettings::default()).into_geometry(),
+			}
+		}
+		(vertices, indices)
+	}
+}
+
+// the index of each vertex's position among the distinct positions, and how many there are
+fn weld(vertices: &[model::ModelVertex]) -> (Vec<usize>, usize) {
+	let mut positions = HashMap::new();
+	let groups = vertices.iter()
+		.map(|vertex| {
+			let next = positions.len();
+			*positions.entry(vertex.position.map(f32::to_bits)).or_insert(next)
+		})
+		.collect();
+	(groups, positions.len())
+}
+
+fn lerp_vertex(a: &model::ModelVertex, b: &model::ModelVertex) -> model::ModelVertex {
+	let mid = |a: f32, b: f32| (a + b) * 0.5;
+	model::ModelVertex {
+		position: std::array::from_fn(|i| mid(a.position[i], b.position[i])),
+		tex_coords: std::array::from_fn(|i| mid(a.tex_coords[i], b.tex_coords[i])),
+		normal: std::array::from_fn(|i| mid(a.normal[i], b.normal[i])),
+		tangent: [0.0; 4], // regenerated after the stack
+		lightmap_coords: std::array::from_fn(|i| mid(a.lightmap_coords[i], b.lightmap_coords[i])),
+	}
+}
+
+fn subdivide(vertices: &mut Vec<model::ModelVertex>, indices: &mut Vec<u32>) {
+	let mut midpoints = HashMap::new();
+	let mut midpoint = |vertices: &mut Vec<model::ModelVertex>, a: u32, b: u32| {
+		*midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+			vertices.push(lerp_vertex(&vertices[a as usize], &vertices[b as usize]));
+			vertices.len() as u32 - 1
+		})
+	};
+	let mut subdivided = Vec::with_capacity(indices.len() * 4);
+	for triangle in indices.chunks_exact(3) {
+		let [a, b, c] = [triangle[0], triangle[1], triangle[2]];
+		let ab = midpoint(vertices, a, b);
+		let bc = midpoint(vertices, b, c);
+		let ca = midpoint(vertices, c, a);
+		subdivided.extend_from_slice(&[a, ab, ca, ab, b, bc, ca, bc, c, ab, bc, ca]);
+	}
+	*indices = subdivided;
+}
+
+fn smooth(vertices: &mut [model::ModelVertex], indices: &[u32], iterations: u32, factor: f32) {
+	let (groups, group_count) = weld(vertices);
+	let mut positions = vec![cgmath::Vector3::new(0.0, 0.0, 0.0); group_count];
+	for (vertex, &group) in vertices.iter().zip(&groups) {
+		positions[group] = vertex.position.into();
+	}
+	// edges between welded positions, shared edges count once per triangle
+	let edges = indices.chunks_exact(3)
+		.flat_map(|triangle| [(0, 1), (1, 2), (2, 0)].map(|(i, j)| (groups[triangle[i] as usize], groups[triangle[j] as usize])))
+		.collect::<Vec<_>>();
+
+	for _ in 0..iterations {
+		let mut sums = vec![cgmath::Vector3::new(0.0, 0.0, 0.0); group_count];
+		let mut counts = vec![0.0f32; group_count];
+		for &(a, b) in &edges {
+			sums[a] += positions[b];
+			sums[b] += positions[a];
+			counts[a] += 1.0;
+			counts[b] += 1.0;
+		}
+		for ((position, sum), count) in positions.iter_mut().zip(sums).zip(counts) {
+			if count > 0.0 {
+				*position += (sum / count - *position) * factor;
+			}
+		}
+	}
+	for (vertex, &group) in vertices.iter_mut().zip(&groups) {
+		vertex.position = positions[group].into();
+	}
+}
+
+// integer lattice hash to 0..1
+fn hash(x: i32, y: i32, z: i32, seed: u32) -> f32 {
+	let mut h = (x as u32).wrapping_mul(0x8da6b343) ^ (y as u32).wrapping_mul(0xd8163841) ^ (z as u32).wrapping_mul(0xcb1ab31f) ^ seed.wrapping_mul(0x9e3779b9);
+	h ^= h >> 13;
+	h = h.wrapping_mul(0x5bd1e995);
+	h ^= h >> 15;
+	(h & 0xffffff) as f32 / 0xffffff as f32
+}
+
+// smoothly interpolated value noise, -1 to 1
+fn value_noise(p: [f32; 3], seed: u32) -> f32 {
+	let cell = p.map(|c| c.floor());
+	let t = std::array::from_fn::<f32, 3, _>(|i| {
+		let f = p[i] - cell[i];
+		f * f * (3.0 - 2.0 * f)
+	});
+	let [x, y, z] = cell.map(|c| c as i32);
+	let mut value = 0.0;
+	for corner in 0..8 {
+		let (dx, dy, dz) = (corner & 1, (corner >> 1) & 1, (corner >> 2) & 1);
+		let weight = [(dx, t[0]), (dy, t[1]), (dz, t[2])].iter()
+			.map(|&(d, t)| if d == 1 { t } else { 1.0 - t })
+			.product::<f32>();
+		value += weight * hash(x + dx, y + dy, z + dz, seed);
+	}
+	value * 2.0 - 1.0
+}
+
+fn displace(vertices: &mut [model::ModelVertex], amplitude: f32, frequency: f32, seed: u32) {
+	use cgmath::InnerSpace;
+	// welded vertices move along their averaged normal, so seams don't open
+	let (groups, group_count) = weld(vertices);
+	let mut normals = vec![cgmath::Vector3::new(0.0, 0.0, 0.0); group_count];
+	for (vertex, &group) in vertices.iter().zip(&groups) {
+		normals[group] += cgmath::Vector3::from(vertex.normal);
+	}
+	for (vertex, &group) in vertices.iter_mut().zip(&groups) {
+		let normal = normals[group];
+		if normal.magnitude2() == 0.0 {
+			continue;
+		}
+		let height = value_noise(vertex.position.map(|c| c * frequency), seed) * amplitude;
+		vertex.position = (cgmath::Vector3::from(vertex.position) + normal.normalize() * height).into();
+	}
+}
+
+// smooth normals weighted by triangle area, shared by welded vertices
+fn compute_normals(vertices: &mut [model::ModelVertex], indices: &[u32]) {
+	use cgmath::InnerSpace;
+	let (groups, group_count) = weld(vertices);
+	let mut normals = vec![cgmath::Vector3::new(0.0, 0.0, 0.0); group_count];
+	for triangle in indices.chunks_exact(3) {
+		let [a, b, c] = [0, 1, 2].map(|i| cgmath::Vector3::from(vertices[triangle[i] as usize].position));
+		let normal = (b - a).cross(c - a);
+		for &index in triangle {
+			normals[groups[index as usize]] += normal;
+		}
+	}
+	for (vertex, &group) in vertices.iter_mut().zip(&groups) {
+		if normals[group].magnitude2() > 0.0 {
+			vertex.normal = normals[group].normalize().into();
+		}
+	}
+}
+
+fn mirror(vertices: &mut Vec<model::ModelVertex>, indices: &mut Vec<u32>, axis: usize) {
+	let base = vertices.len() as u32;
+	let mirrored = vertices.iter()
+		.map(|vertex| {
+			let mut vertex = *vertex;
+			vertex.position[axis] = -vertex.position[axis];
+			vertex.normal[axis] = -vertex.normal[axis];
+			vertex.tangent[axis] = -vertex.tangent[axis];
+			vertex
+		})
+		.collect::<Vec<_>>();
+	vertices.extend(mirrored);
+	// reflecting turns the triangles inside out
+	let flipped = indices.chunks_exact(3)
+		.flat_map(|triangle| [triangle[0], triangle[2], triangle[1]].map(|index| index + base))
+		.collect::<Vec<_>>();
+	indices.extend(flipped);
+}
+
+fn array(vertices: &mut Vec<model::ModelVertex>, indices: &mut Vec<u32>, count: u32, offset: [f32; 3]) {
+	let (original_vertices, original_indices) = (vertices.len(), indices.len());
+	for copy in 1..count {
+		let base = vertices.len() as u32;
+		let shift = offset.map(|c| c * copy as f32);
+		for i in 0..original_vertices {
+			let mut vertex = vertices[i];
+			vertex.position = std::array::from_fn(|axis| vertex.position[axis] + shift[axis]);
+			vertices.push(vertex);
+		}
+		for i in 0..original_indices {
+			indices.push(indices[i] + base);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_meshes::cube;
+
+	#[test]
+	fn console_modifiers_parse_with_defaults() {
+		let stack = ModifierStack::parse(["subdivide", "smooth:3", "noise:0.2:4:7", "mirror:z", "array:3:2:0:1", "unwrap"]).unwrap();
+		assert_eq!(stack.modifiers, [
+			Modifier::Subdivide { levels: 1 },
+			Modifier::Smooth { iterations: 3, factor: 0.5 },
+			Modifier::Displace { amplitude: 0.2, frequency: 4.0, seed: 7 },
+			Modifier::Mirror { axis: 2 },
+			Modifier::Array { count: 3, offset: [2.0, 0.0, 1.0] },
+			Modifier::Unwrap,
+		]);
+		assert!(Modifier::parse("mirror:w").is_err());
+		assert!(Modifier::parse("smooth:lots").is_err());
+		assert!(Modifier::parse("twist").is_err());
+	}
+
+	#[test]
+	fn subdivision_stays_on_the_surface() {
+		let (vertices, indices) = cube();
+		let (subdivided, subdivided_indices) = ModifierStack::new(vec![Modifier::Subdivide { levels: 2 }]).apply(vertices, indices.clone());
+		assert_eq!(subdivided_indices.len(), indices.len() * 16);
+		// the faces have their own vertices, so each becomes a 5 by 5 grid
+		assert_eq!(subdivided.len(), 6 * 25);
+		for vertex in &subdivided {
+			assert_eq!(vertex.position.iter().fold(0.0f32, |max, c| max.max(c.abs())), 0.5);
+		}
+	}
+
+	#[test]
+	fn smoothing_keeps_seams_closed() {
+		let (vertices, indices) = cube();
+		let (smoothed, _) = ModifierStack::new(vec![Modifier::Smooth { iterations: 4, factor: 0.5 }]).apply(vertices.clone(), indices);
+		for (i, j) in (0..vertices.len()).flat_map(|i| (0..vertices.len()).map(move |j| (i, j))) {
+			if vertices[i].position == vertices[j].position {
+				assert_eq!(smoothed[i].position, smoothed[j].position);
+			}
+		}
+		// the corners pull in toward the centre
+		assert!(smoothed.iter().all(|vertex| vertex.position.iter().all(|c| c.abs() < 0.5)));
+	}
+
+	#[test]
+	fn mirror_and_array_copy_the_mesh() {
+		use cgmath::InnerSpace;
+		let (mut vertices, indices) = cube();
+		for vertex in &mut vertices {
+			vertex.position[0] += 1.0;
+		}
+		let stack = ModifierStack::new(vec![Modifier::Mirror { axis: 0 }, Modifier::Array { count: 3, offset: [0.0, 2.0, 0.0] }]);
+		let (copied, copied_indices) = stack.apply(vertices.clone(), indices.clone());
+		assert_eq!(copied.len(), vertices.len() * 6);
+		assert_eq!(copied_indices.len(), indices.len() * 6);
+		assert_eq!(copied[vertices.len()].position, [-vertices[0].position[0], vertices[0].position[1], vertices[0].position[2]]);
+		assert_eq!(copied[vertices.len() * 4].position[1], vertices[0].position[1] + 4.0);
+
+		// the mirrored triangles still face the way their normals point
+		for triangle in copied_indices.chunks_exact(3) {
+			let [a, b, c] = [0, 1, 2].map(|i| cgmath::Vector3::from(copied[triangle[i] as usize].position));
+			let normal = cgmath::Vector3::from(copied[triangle[0] as usize].normal);
+			assert!((b - a).cross(c - a).dot(normal) > 0.0);
+		}
+	}
+}
@@ -0,0 +1,90 @@
+use crate::debug_draw;
+
+// a convex polygon of the navmesh, its vertices wind around it
+#[derive(Clone, Debug)]
+pub struct NavPolygon {
+	pub vertices: Vec<u32>, // into the navmesh's vertices
+	pub area: u8, // the area type, walkable, water, road, and so on
+}
+
+/*
+Navigation data from a pathfinding crate, converted to polygons and paths for the debug layer.
+Recast style meshes map over directly: vertices, polygons as index lists, and an area id per
+polygon. The renderer doesn't path find or read it otherwise, it's only drawn while visible.
+*/
+#[derive(Clone, Debug)]
+pub struct NavMesh {
+	pub vertices: Vec<[f32; 3]>,
+	pub polygons: Vec<NavPolygon>,
+	pub paths: Vec<Vec<[f32; 3]>>, // found paths, drawn over the polygons
+	pub visible: bool,
+}
+
+impl NavMesh {
+	const PATH_COLOR: [f32; 4] = [1.0, 0.9, 0.1, 1.0];
+	const FILL_ALPHA: f32 = 0.3; // of the lines fanned from each polygon's center
+
+	pub fn new(vertices: Vec<[f32; 3]>, polygons: Vec<NavPolygon>) -> Self {
+		Self {
+			vertices,
+			polygons,
+			paths: vec![],
+			visible: true,
+		}
+	}
+
+	// a distinct color per area id, hues a golden angle apart so neighbouring ids differ
+	pub fn area_color(area: u8) -> [f32; 4] {
+		let hue = (area as f32 * 0.618034).fract() * 6.0;
+		let x = 1.0 - (hue % 2.0 - 1.0).abs();
+		let [r, g, b] = match hue as u32 {
+			0 => [1.0, x, 0.0],
+			1 => [x, 1.0, 0.0],
+			2 => [0.0, 1.0, x],
+			3 => [0.0, x, 1.0],
+			4 => [x, 0.0, 1.0],
+			_ => [1.0, 0.0, x],
+		};
+		[r * 0.8 + 0.1, g * 0.8 + 0.1, b * 0.8 + 0.1, 1.0]
+	}
+
+	/*
+	Queues the polygon outlines and paths on the debug layer for this frame. The debug layer only
+	draws lines, so polygons are shaded by faint lines from their center to each corner.
+	*/
+	pub fn draw(&self, debug_draw: &mut debug_draw::DebugDraw) {
+		if !self.visible {
+			return;
+		}
+		let point = |index: u32| cgmath::Point3::from(self.vertices[index as usize]);
+		for polygon in &self.polygons {
+			if polygon.vertices.is_empty() {
+				continue;
+			}
+			let color = Self::area_color(polygon.area);
+			let fill = [color[0], color[1], color[2], Self::FILL_ALPHA];
+			let center = polygon.vertices.iter()
+				.map(|&index| cgmath::Vector3::from(self.vertices[index as usize]))
+				.sum::<cgmath::Vector3<f32>>() / polygon.vertices.len() as f32;
+			let center = cgmath::Point3::new(center.x, center.y, center.z);
+			for (i, &index) in polygon.vertices.iter().enumerate() {
+				let next = polygon.vertices[(i + 1) % polygon.vertices.len()];
+				debug_draw.line(point(index), point(next), color);
+				debug_draw.line(center, point(index), fill);
+			}
+		}
+
+		for path in &self.paths {
+			for segment in path.windows(2) {
+				debug_draw.line(segment[0].into(), segment[1].into(), Self::PATH_COLOR);
+			}
+			// a small cross at every waypoint
+			for &waypoint in path {
+				let waypoint = cgmath::Point3::from(waypoint);
+				for axis in [cgmath::Vector3::unit_x(), cgmath::Vector3::unit_z()] {
+					debug_draw.line(waypoint - axis * 0.1, waypoint + axis * 0.1, Self::PATH_COLOR);
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,375 @@
+use cgmath::InnerSpace;
+
+use crate::{model, resources};
+
+const LEAF_SIZE: usize = 4; // triangles per BVH leaf
+
+#[derive(Clone, Debug)]
+pub struct BakeSettings {
+	pub width: u32,
+	pub height: u32,
+	pub max_distance: f32, // how far above and below the low poly surface the high poly is looked for
+	pub ao_samples: u32, // rays per texel, 0 leaves the AO map white
+	pub ao_distance: f32, // occluders further than this don't darken
+	pub padding: u32, // texels the maps grow past the uv islands, so filtering and mips don't pull in the background
+}
+
+impl Default for BakeSettings {
+	fn default() -> Self {
+		Self {
+			width: 1024,
+			height: 1024,
+			max_distance: 0.1,
+			ao_samples: 32,
+			ao_distance: 0.5,
+			padding: 4,
+		}
+	}
+}
+
+pub struct BakedMaps {
+	pub normal: image::RgbaImage, // tangent space, stored linear like the normal maps materials sample
+	pub ao: image::GrayImage, // white is unoccluded
+}
+
+impl BakedMaps {
+	pub fn save(&self, normal_path: &str, ao_path: &str) -> anyhow::Result<()> {
+		self.normal.save(normal_path)?;
+		self.ao.save(ao_path)?;
+		Ok(())
+	}
+}
+
+#[derive(Copy, Clone, Debug)]
+struct BvhNode {
+	min: cgmath::Vector3<f32>,
+	max: cgmath::Vector3<f32>,
+	first: u32, // first triangle of a leaf, or the second child, the first child is the next node
+	count: u32, // 0 for inner nodes
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Hit {
+	t: f32,
+	triangle: usize, // in the mesh's indices
+	barycentric: [f32; 3],
+}
+
+// a bounding volume hierarchy over a mesh's triangles, for casting rays on the CPU
+struct Bvh {
+	nodes: Vec<BvhNode>,
+	triangles: Vec<[cgmath::Vector3<f32>; 3]>, // in leaf order
+	original: Vec<usize>, // the mesh triangle of each one
+}
+
+impl Bvh {
+	fn new(vertices: &[model::ModelVertex], indices: &[u32]) -> Self {
+		let mut triangles = indices.chunks_exact(3)
+			.map(|triangle| [0, 1, 2].map(|i| cgmath::Vector3::from(vertices[triangle[i] as usize].position)))
+			.enumerate()
+			.collect::<Vec<_>>();
+		let mut bvh = Self {
+			nodes: vec![],
+			triangles: Vec::with_capacity(triangles.len()),
+			original: Vec::with_capacity(triangles.len()),
+		};
+		if !triangles.is_empty() {
+			bvh.build(&mut triangles);
+		}
+		bvh
+	}
+
+	// depth first, splitting at the median centroid along the longest axis
+	fn build(&mut self, triangles: &mut [(usize, [cgmath::Vector3<f32>; 3])]) {
+		let mut min = cgmath::Vector3::new(f32::MAX, f32::MAX, f32::MAX);
+		let mut max = cgmath::Vector3::new(f32::MIN, f32::MIN, f32::MIN);
+		for point in triangles.iter().flat_map(|(_, triangle)| triangle) {
+			for axis in 0..3 {
+				min[axis] = min[axis].min(point[axis]);
+				max[axis] = max[axis].max(point[axis]);
+			}
+		}
+		let node = self.nodes.len();
+		self.nodes.push(BvhNode { min, max, first: 0, count: 0 });
+
+		if triangles.len() <= LEAF_SIZE {
+			self.nodes[node].first = self.triangles.len() as u32;
+			self.nodes[node].count = triangles.len() as u32;
+			for (index, triangle) in triangles.iter() {
+				self.original.push(*index);
+				self.triangles.push(*triangle);
+			}
+			return;
+		}
+
+		let extent = max - min;
+		let axis = if extent.x > extent.y && extent.x > extent.z { 0 } else if extent.y > extent.z { 1 } else { 2 };
+		let middle = triangles.len() / 2;
+		triangles.select_nth_unstable_by(middle, |(_, a), (_, b)| {
+			let centroid = |triangle: &[cgmath::Vector3<f32>; 3]| triangle[0][axis] + triangle[1][axis] + triangle[2][axis];
+			centroid(a).total_cmp(&centroid(b))
+		});
+		let (first, second) = triangles.split_at_mut(middle);
+		self.build(first);
+		self.nodes[node].first = self.nodes.len() as u32;
+		self.build(second);
+	}
+
+	// the nearest hit along the ray, closer than max_t
+	fn intersect(&self, origin: cgmath::Vector3<f32>, direction: cgmath::Vector3<f32>, mut max_t: f32) -> Option<Hit> {
+		if self.nodes.is_empty() {
+			return None;
+		}
+		let inverse = direction.map(|c| 1.0 / c);
+		let mut nearest = None;
+		let mut stack = vec![0];
+		while let Some(index) = stack.pop() {
+			let node = &self.nodes[index];
+			if !ray_box(origin, inverse, node.min, node.max, max_t) {
+				continue;
+			}
+			if node.count == 0 {
+				stack.push(index + 1);
+				stack.push(node.first as usize);
+				continue;
+			}
+			for i in node.first as usize..(node.first + node.count) as usize {
+				if let Some((t, u, v)) = ray_triangle(origin, direction, &self.triangles[i])
+					&& t < max_t
+				{
+					max_t = t;
+					nearest = Some(Hit { t, triangle: self.original[i], barycentric: [1.0 - u - v, u, v] });
+				}
+			}
+		}
+		nearest
+	}
+}
+
+// slab test, whether the ray enters the box before max_t
+fn ray_box(origin: cgmath::Vector3<f32>, inverse: cgmath::Vector3<f32>, min: cgmath::Vector3<f32>, max: cgmath::Vector3<f32>, max_t: f32) -> bool {
+	let (mut near, mut far) = (0.0f32, max_t);
+	for axis in 0..3 {
+		let t1 = (min[axis] - origin[axis]) * inverse[axis];
+		let t2 = (max[axis] - origin[axis]) * inverse[axis];
+		near = near.max(t1.min(t2));
+		far = far.min(t1.max(t2));
+	}
+	near <= far
+}
+
+// Möller-Trumbore from both sides, the distance and the barycentrics of the second and third corners
+fn ray_triangle(origin: cgmath::Vector3<f32>, direction: cgmath::Vector3<f32>, triangle: &[cgmath::Vector3<f32>; 3]) -> Option<(f32, f32, f32)> {
+	let edge1 = triangle[1] - triangle[0];
+	let edge2 = triangle[2] - triangle[0];
+	let p = direction.cross(edge2);
+	let determinant = edge1.dot(p);
+	if determinant.abs() < 1e-12 {
+		return None;
+	}
+	let inverse = 1.0 / determinant;
+	let s = origin - triangle[0];
+	let u = s.dot(p) * inverse;
+	if !(0.0..=1.0).contains(&u) {
+		return None;
+	}
+	let q = s.cross(edge1);
+	let v = direction.dot(q) * inverse;
+	if v < 0.0 || u + v > 1.0 {
+		return None;
+	}
+	let t = edge2.dot(q) * inverse;
+	(t > 0.0).then_some((t, u, v))
+}
+
+// integer hash to 0..1
+fn hash(mut h: u32) -> f32 {
+	h ^= h >> 16;
+	h = h.wrapping_mul(0x7feb352d);
+	h ^= h >> 15;
+	h = h.wrapping_mul(0x846ca68b);
+	h ^= h >> 16;
+	(h & 0xffffff) as f32 / 0xffffff as f32
+}
+
+// the fraction of cosine weighted rays over the surface that escape, rotated per texel so banding turns into noise
+fn ambient_occlusion(bvh: &Bvh, position: cgmath::Vector3<f32>, normal: cgmath::Vector3<f32>, settings: &BakeSettings, texel: u32) -> f32 {
+	let helper = if normal.x.abs() < 0.9 { cgmath::Vector3::unit_x() } else { cgmath::Vector3::unit_y() };
+	let tangent = helper.cross(normal).normalize();
+	let bitangent = normal.cross(tangent);
+	let origin = position + normal * settings.ao_distance * 1e-3;
+	let rotation = hash(texel);
+
+	let mut escaped = 0;
+	for i in 0..settings.ao_samples {
+		let u = (i as f32 + 0.5) / settings.ao_samples as f32;
+		let angle = ((i as f32 * 0.618034 + rotation) % 1.0) * std::f32::consts::TAU;
+		let radius = u.sqrt();
+		let direction = tangent * radius * angle.cos() + bitangent * radius * angle.sin() + normal * (1.0 - u).sqrt();
+		if bvh.intersect(origin, direction, settings.ao_distance).is_none() {
+			escaped += 1;
+		}
+	}
+	escaped as f32 / settings.ao_samples as f32
+}
+
+// fills empty texels from their filled neighbours, one ring per step
+fn dilate<T: Copy>(texels: &mut [Option<T>], width: usize, height: usize, steps: u32) {
+	for _ in 0..steps {
+		let previous = texels.to_vec();
+		for y in 0..height {
+			for x in 0..width {
+				if previous[y * width + x].is_some() {
+					continue;
+				}
+				let neighbours = [(x.wrapping_sub(1), y), (x + 1, y), (x, y.wrapping_sub(1)), (x, y + 1)];
+				texels[y * width + x] = neighbours.into_iter()
+					.filter(|&(x, y)| x < width && y < height)
+					.find_map(|(x, y)| previous[y * width + x]);
+			}
+		}
+	}
+}
+
+/*
+Bakes the detail of a high poly mesh into maps for a low poly one, offline on the CPU. Every
+texel the low poly's uvs cover casts a ray back along its interpolated normal, starting
+max_distance above the surface, and the first high poly triangle hit gives the normal, stored
+in the low poly's tangent space, and where the AO rays start. The low poly needs uvs without
+overlaps, where they do overlap the first triangle wins. Its tangents are generated here the
+way resources generates them on load, so the map matches what the shaders decode. Texels
+that miss keep the low poly's own normal.
+The texels are baked on the calling thread, big maps with AO take a while.
+*/
+pub fn bake(
+	low_vertices: &[model::ModelVertex],
+	low_indices: &[u32],
+	high_vertices: &[model::ModelVertex],
+	high_indices: &[u32],
+	settings: &BakeSettings,
+) -> BakedMaps {
+	let low_vertices = resources::generate_tangents(low_vertices.to_vec(), low_indices);
+	let bvh = Bvh::new(high_vertices, high_indices);
+	let (width, height) = (settings.width as usize, settings.height as usize);
+	let mut normals = vec![None; width * height];
+	let mut occlusion = vec![None; width * height];
+
+	let cross = |a: cgmath::Vector2<f32>, b: cgmath::Vector2<f32>| a.x * b.y - a.y * b.x;
+	for triangle in low_indices.chunks_exact(3) {
+		let corners = [0, 1, 2].map(|i| &low_vertices[triangle[i] as usize]);
+		let uvs = corners.map(|vertex| cgmath::Vector2::new(vertex.tex_coords[0] * width as f32, vertex.tex_coords[1] * height as f32));
+		let area = cross(uvs[1] - uvs[0], uvs[2] - uvs[0]);
+		if area.abs() < 1e-12 {
+			continue;
+		}
+		let texel_range = |axis: usize, size: usize| {
+			let min = uvs.iter().map(|uv| uv[axis]).fold(f32::MAX, f32::min);
+			let max = uvs.iter().map(|uv| uv[axis]).fold(f32::MIN, f32::max);
+			(min.floor().max(0.0) as usize)..(max.ceil().max(0.0) as usize).min(size)
+		};
+
+		for y in texel_range(1, height) {
+			for x in texel_range(0, width) {
+				let texel = y * width + x;
+				if normals[texel].is_some() {
+					continue;
+				}
+				// barycentrics of the texel center
+				let p = cgmath::Vector2::new(x as f32 + 0.5, y as f32 + 0.5);
+				let w0 = cross(uvs[1] - p, uvs[2] - p) / area;
+				let w1 = cross(uvs[2] - p, uvs[0] - p) / area;
+				let weights = [w0, w1, 1.0 - w0 - w1];
+				if weights.iter().any(|&w| w < -1e-4) {
+					continue;
+				}
+				let interpolate = |attribute: fn(&model::ModelVertex) -> cgmath::Vector3<f32>| {
+					(0..3).map(|i| attribute(corners[i]) * weights[i]).fold(cgmath::Vector3::new(0.0, 0.0, 0.0), |sum, v| sum + v)
+				};
+				let position = interpolate(|vertex| vertex.position.into());
+				let normal = interpolate(|vertex| vertex.normal.into()).normalize();
+				let tangent = interpolate(|vertex| cgmath::Vector3::new(vertex.tangent[0], vertex.tangent[1], vertex.tangent[2]));
+				let tangent = (tangent - normal * normal.dot(tangent)).normalize();
+				let bitangent = normal.cross(tangent) * corners[0].tangent[3];
+
+				let origin = position + normal * settings.max_distance;
+				let (high_normal, high_position) = match bvh.intersect(origin, -normal, settings.max_distance * 2.0) {
+					Some(hit) => {
+						let high_normal = (0..3)
+							.map(|i| cgmath::Vector3::from(high_vertices[high_indices[hit.triangle * 3 + i] as usize].normal) * hit.barycentric[i])
+							.fold(cgmath::Vector3::new(0.0, 0.0, 0.0), |sum, v| sum + v);
+						(high_normal.normalize(), origin - normal * hit.t)
+					}
+					None => (normal, position),
+				};
+				normals[texel] = Some([high_normal.dot(tangent), high_normal.dot(bitangent), high_normal.dot(normal)]);
+				if settings.ao_samples > 0 {
+					occlusion[texel] = Some(ambient_occlusion(&bvh, high_position, high_normal, settings, texel as u32));
+				}
+			}
+		}
+	}
+
+	dilate(&mut normals, width, height, settings.padding);
+	dilate(&mut occlusion, width, height, settings.padding);
+	let to_byte = |c: f32| ((c * 0.5 + 0.5).clamp(0.0, 1.0) * 255.0).round() as u8;
+	BakedMaps {
+		normal: image::RgbaImage::from_fn(settings.width, settings.height, |x, y| {
+			let [nx, ny, nz] = normals[y as usize * width + x as usize].unwrap_or([0.0, 0.0, 1.0]);
+			image::Rgba([to_byte(nx), to_byte(ny), to_byte(nz), 255])
+		}),
+		ao: image::GrayImage::from_fn(settings.width, settings.height, |x, y| {
+			let ao = occlusion[y as usize * width + x as usize].unwrap_or(1.0);
+			image::Luma([(ao.clamp(0.0, 1.0) * 255.0).round() as u8])
+		}),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_meshes;
+
+	fn settings() -> BakeSettings {
+		BakeSettings {
+			width: 32,
+			height: 32,
+			max_distance: 0.3,
+			ao_samples: 8,
+			ao_distance: 0.5,
+			padding: 2,
+		}
+	}
+
+	#[test]
+	fn baking_a_mesh_onto_itself_is_flat_and_unoccluded() {
+		let (vertices, indices) = test_meshes::cube();
+		let maps = bake(&vertices, &indices, &vertices, &indices, &settings());
+		for pixel in maps.normal.pixels() {
+			assert!(pixel[0].abs_diff(128) <= 2 && pixel[1].abs_diff(128) <= 2 && pixel[2] >= 253, "{:?}", pixel);
+		}
+		assert!(maps.ao.pixels().all(|pixel| pixel[0] == 255));
+	}
+
+	#[test]
+	fn a_tilted_high_poly_bends_the_normals() {
+		// the cube's +z face, and the same face tilted 20 degrees around x
+		let (vertices, indices) = test_meshes::cube();
+		let low_vertices = vertices[20..24].to_vec();
+		let low_indices = indices[30..36].iter().map(|index| index - 20).collect::<Vec<_>>();
+		let tilt = cgmath::Matrix3::from_angle_x(cgmath::Deg(20.0));
+		let high_vertices = low_vertices.iter()
+			.map(|vertex| model::ModelVertex {
+				position: (tilt * cgmath::Vector3::from(vertex.position) - cgmath::Vector3::new(0.0, 0.0, 0.5 * (1.0 - 20f32.to_radians().cos()))).into(),
+				normal: (tilt * cgmath::Vector3::from(vertex.normal)).into(),
+				..*vertex
+			})
+			.collect::<Vec<_>>();
+
+		let maps = bake(&low_vertices, &low_indices, &high_vertices, &low_indices, &settings());
+		let center = maps.normal.get_pixel(16, 16);
+		let bend = center[0].abs_diff(128).max(center[1].abs_diff(128));
+		// sin 20 is about 0.34, 44 away from the middle of the byte
+		assert!((38..=50).contains(&bend), "{:?}", center);
+		assert!(center[2] < 250, "{:?}", center);
+	}
+}
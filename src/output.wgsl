@@ -0,0 +1,12 @@
+
+struct FragmentOutput {
+	@location(0) color: vec4<f32>,
+	@location(1) surface: vec4<f32>, // world normal packed into 0..1 and roughness, read by the reflection pass
+};
+
+fn write_output(color: vec4<f32>, normal: vec3<f32>, roughness: f32) -> FragmentOutput {
+	var out: FragmentOutput;
+	out.color = color;
+	out.surface = vec4<f32>(normal * 0.5 + 0.5, roughness);
+	return out;
+}
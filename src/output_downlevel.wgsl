@@ -0,0 +1,11 @@
+
+// Downlevel targets have no surface target, the reflection pass that reads it isn't available
+struct FragmentOutput {
+	@location(0) color: vec4<f32>,
+};
+
+fn write_output(color: vec4<f32>, normal: vec3<f32>, roughness: f32) -> FragmentOutput {
+	var out: FragmentOutput;
+	out.color = color;
+	return out;
+}
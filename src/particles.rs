@@ -0,0 +1,559 @@
+use cgmath::{InnerSpace, MetricSpace};
+
+use crate::{light, model, scene, texture};
+
+const WORKGROUP_SIZE: u32 = 64;
+const MAX_LIGHTS: usize = 4; // the nearest lights to a lit emitter
+
+// the same layout in both shaders, the pool is a storage buffer to the simulation and an instance buffer to the draw
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct Particle {
+	position: [f32; 3],
+	age: f32,
+	velocity: [f32; 3],
+	lifetime: f32, // 0 for a slot that was never spawned
+}
+
+impl Particle {
+	const ATTRIBS: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32, 2 => Float32x3, 3 => Float32];
+
+	fn desc() -> wgpu::VertexBufferLayout<'static> {
+		wgpu::VertexBufferLayout {
+			array_stride: std::mem::size_of::<Particle>() as wgpu::BufferAddress,
+			step_mode: wgpu::VertexStepMode::Instance,
+			attributes: &Self::ATTRIBS,
+		}
+	}
+}
+
+gpu_struct! {
+	Uniform
+	#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+	struct EmitterUniform {
+		origin: [f32; 3],
+		delta_time: f32,
+		direction: [f32; 3],
+		spread: f32,
+		gravity: [f32; 3],
+		speed: f32,
+		start_color: [f32; 4],
+		end_color: [f32; 4],
+		lifetime: f32,
+		start_size: f32,
+		end_size: f32,
+		seed: u32,
+		spawn_start: u32,
+		spawn_count: u32,
+		capacity: u32,
+		light_count: u32,
+		ambient: [f32; 3],
+		softness: f32,
+		lights: [light::LightUniform; MAX_LIGHTS],
+		lit: u32,
+	}
+}
+
+fn buffer_entry(binding: u32, visibility: wgpu::ShaderStages, ty: wgpu::BufferBindingType) -> wgpu::BindGroupLayoutEntry {
+	wgpu::BindGroupLayoutEntry {
+		binding,
+		visibility,
+		ty: wgpu::BindingType::Buffer {
+			ty,
+			has_dynamic_offset: false,
+			min_binding_size: None,
+		},
+		count: None,
+	}
+}
+
+// the bindings of particles_simulate.wgsl, the tests check them against the shader
+fn simulate_entries() -> [wgpu::BindGroupLayoutEntry; 2] {
+	[
+		buffer_entry(0, wgpu::ShaderStages::COMPUTE, wgpu::BufferBindingType::Uniform), // emitter
+		buffer_entry(1, wgpu::ShaderStages::COMPUTE, wgpu::BufferBindingType::Storage { read_only: false }), // particles
+	]
+}
+
+// group 0 of particles.wgsl
+fn draw_entries() -> [wgpu::BindGroupLayoutEntry; 2] {
+	[
+		buffer_entry(0, wgpu::ShaderStages::VERTEX_FRAGMENT, wgpu::BufferBindingType::Uniform), // camera
+		buffer_entry(1, wgpu::ShaderStages::VERTEX_FRAGMENT, wgpu::BufferBindingType::Uniform), // emitter
+	]
+}
+
+// group 1 of particles.wgsl, the scene depth soft particles fade against
+fn depth_entries() -> [wgpu::BindGroupLayoutEntry; 1] {
+	[
+		wgpu::BindGroupLayoutEntry {
+			binding: 0,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Texture {
+				sample_type: wgpu::TextureSampleType::Depth,
+				view_dimension: wgpu::TextureViewDimension::D2,
+				multisampled: false,
+			},
+			count: None,
+		},
+	]
+}
+
+// how particles are shaded
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ParticleLighting {
+	Emissive, // the particle's color as it is, for fire and sparks
+	Lit, // the color lit by the ambient and the nearest lights, for smoke and dust
+}
+
+/*
+Spawns particles at a point, in a cone around direction, that fall under gravity and fade from
+start to end color and size over their lifetime. Particles are kept in a ring of capacity
+slots, so when more are alive than that the oldest are replaced.
+*/
+#[derive(Clone, Debug)]
+pub struct ParticleEmitter {
+	pub position: [f32; 3],
+	pub direction: [f32; 3],
+	pub spread: f32, // radians from direction
+	pub speed: f32, // units per second at spawn
+	pub gravity: [f32; 3],
+	pub rate: f32, // spawned per second
+	pub lifetime: f32, // seconds
+	pub start_color: [f32; 4], // linear, alpha fades
+	pub end_color: [f32; 4],
+	pub start_size: f32, // world space width of the billboard
+	pub end_size: f32,
+	pub capacity: u32,
+	pub blend: model::BlendMode,
+	pub lighting: ParticleLighting,
+	pub softness: f32, // the depth in world units over which particles fade into the scene, 0 for hard edges
+	pub enabled: bool, // stops spawning, the living particles still finish
+}
+
+impl ParticleEmitter {
+	pub fn new(position: [f32; 3]) -> Self {
+		Self {
+			position,
+			direction: [0.0, 1.0, 0.0],
+			spread: 0.4,
+			speed: 2.0,
+			gravity: [0.0, -2.0, 0.0],
+			rate: 100.0,
+			lifetime: 2.0,
+			start_color: [1.0, 0.8, 0.4, 1.0],
+			end_color: [1.0, 0.2, 0.0, 0.0],
+			start_size: 0.1,
+			end_size: 0.3,
+			capacity: 1024,
+			blend: model::BlendMode::Additive,
+			lighting: ParticleLighting::Emissive,
+			softness: 0.2,
+			enabled: true,
+		}
+	}
+}
+
+// the GPU side of one emitter, rebuilt when its capacity changes
+struct ParticlePool {
+	capacity: u32,
+	particle_buffer: wgpu::Buffer,
+	emitter_buffer: wgpu::Buffer,
+	simulate_bind_group: Option<wgpu::BindGroup>, // None when simulated on the CPU
+	draw_bind_group: wgpu::BindGroup,
+	cpu_particles: Vec<Particle>, // the simulation without compute shaders, empty with them
+	next_spawn: u32, // the ring slot the next particle goes in
+	spawn_accumulator: f32, // fractions of a particle carried over between frames
+	frame: u32, // seeds the spawns
+}
+
+/*
+Simulates and draws the scene's emitters. With compute shaders each pool is integrated and
+spawned into on the GPU in one dispatch per emitter, without them (WebGL2) the same simulation
+runs on the CPU and the pool is uploaded every frame. Either way the particles are drawn as
+camera facing billboards instanced from the pool, in a pass after the scene's, tested against
+its depth without writing it. Soft particles also sample that depth and fade where they meet
+the scene, which needs the depth attachment to be read only, so they are off on the downlevel
+tier (WebGL2 can't sample a texture attached to the pass).
+Alpha blended particles draw in pool order, unsorted.
+*/
+pub struct ParticleSystem {
+	pools: Vec<ParticlePool>, // by emitter
+	gpu: bool,
+	simulate_pipeline: Option<wgpu::ComputePipeline>,
+	simulate_layout: Option<wgpu::BindGroupLayout>,
+	draw_layout: wgpu::BindGroupLayout,
+	draw_pipelines: Vec<(model::BlendMode, wgpu::RenderPipeline)>,
+	depth_layout: Option<wgpu::BindGroupLayout>, // None without soft particles
+	depth_bind_groups: Option<[wgpu::BindGroup; 2]>, // the scene depth, then the interlaced depth
+}
+
+impl ParticleSystem {
+	pub fn new(
+		device: &wgpu::Device,
+		compute: bool,
+		soft: bool,
+		color_format: wgpu::TextureFormat,
+		depth_texture: &texture::Texture,
+		interlaced_depth_texture: &texture::Texture,
+	) -> Self {
+		let (simulate_pipeline, simulate_layout) = if compute {
+			let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("particle_simulate_bind_group_layout"),
+				entries: &simulate_entries(),
+			});
+			let shader = device.create_shader_module(wgpu::include_wgsl!("particles_simulate.wgsl"));
+			let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+				label: Some("Particle Simulate Pipeline Layout"),
+				bind_group_layouts: &[Some(&layout)],
+				immediate_size: 0,
+			});
+			let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+				label: Some("Particle Simulate Pipeline"),
+				layout: Some(&pipeline_layout),
+				module: &shader,
+				entry_point: Some("cs_main"),
+				compilation_options: Default::default(),
+				cache: None,
+			});
+			(Some(pipeline), Some(layout))
+		} else {
+			(None, None)
+		};
+
+		let draw_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("particle_draw_bind_group_layout"),
+			entries: &draw_entries(),
+		});
+		let depth_layout = soft.then(|| device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("particle_depth_bind_group_layout"),
+			entries: &depth_entries(),
+		}));
+		let shader = device.create_shader_module(wgpu::include_wgsl!("particles.wgsl"));
+		let mut bind_group_layouts = vec![Some(&draw_layout)];
+		if let Some(depth_layout) = &depth_layout {
+			bind_group_layouts.push(Some(depth_layout));
+		}
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Particle Draw Pipeline Layout"),
+			bind_group_layouts: &bind_group_layouts,
+			immediate_size: 0,
+		});
+		let draw_pipelines = [model::BlendMode::Alpha, model::BlendMode::Additive].into_iter()
+			.map(|blend| {
+				let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+					label: Some(&format!("Particle {:?} Pipeline", blend)),
+					layout: Some(&pipeline_layout),
+					vertex: wgpu::VertexState {
+						module: &shader,
+						entry_point: Some("vs_main"),
+						buffers: &[Particle::desc()],
+						compilation_options: Default::default(),
+					},
+					fragment: Some(wgpu::FragmentState {
+						module: &shader,
+						entry_point: Some(if soft { "fs_soft" } else { "fs_main" }),
+						targets: &[Some(wgpu::ColorTargetState {
+							format: color_format,
+							blend: Some(blend.blend_state()),
+							write_mask: wgpu::ColorWrites::ALL,
+						})],
+						compilation_options: Default::default(),
+					}),
+					primitive: wgpu::PrimitiveState::default(),
+					depth_stencil: Some(wgpu::DepthStencilState {
+						format: texture::Texture::DEPTH_FORMAT,
+						depth_write_enabled: Some(false),
+						depth_compare: Some(wgpu::CompareFunction::Less),
+						stencil: wgpu::StencilState::default(),
+						bias: wgpu::DepthBiasState::default(),
+					}),
+					multisample: wgpu::MultisampleState::default(),
+					multiview_mask: None,
+					cache: None,
+				});
+				(blend, pipeline)
+			})
+			.collect();
+
+		let mut particles = Self {
+			pools: vec![],
+			gpu: compute,
+			simulate_pipeline,
+			simulate_layout,
+			draw_layout,
+			draw_pipelines,
+			depth_layout,
+			depth_bind_groups: None,
+		};
+		particles.resize(device, depth_texture, interlaced_depth_texture);
+		particles
+	}
+
+	// the depth textures are recreated on resize
+	pub fn resize(&mut self, device: &wgpu::Device, depth_texture: &texture::Texture, interlaced_depth_texture: &texture::Texture) {
+		let Some(layout) = &self.depth_layout else {
+			return;
+		};
+		self.depth_bind_groups = Some([depth_texture, interlaced_depth_texture].map(|depth| device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("particle_depth_bind_group"),
+			layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&depth.view),
+				},
+			],
+		})));
+	}
+
+	fn create_pool(&self, device: &wgpu::Device, camera_buffer: &wgpu::Buffer, capacity: u32) -> ParticlePool {
+		let capacity = capacity.max(1);
+		let mut usage = wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST;
+		if self.gpu {
+			usage |= wgpu::BufferUsages::STORAGE;
+		}
+		// zeroed, a lifetime of 0 is a dead slot
+		let particle_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Particle Buffer"),
+			size: capacity as u64 * std::mem::size_of::<Particle>() as u64,
+			usage,
+			mapped_at_creation: false,
+		});
+		let emitter_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Particle Emitter Buffer"),
+			size: std::mem::size_of::<EmitterUniform>() as u64,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+		let simulate_bind_group = self.simulate_layout.as_ref().map(|layout| device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("particle_simulate_bind_group"),
+			layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: emitter_buffer.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: particle_buffer.as_entire_binding(),
+				},
+			],
+		}));
+		let draw_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("particle_draw_bind_group"),
+			layout: &self.draw_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: camera_buffer.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: emitter_buffer.as_entire_binding(),
+				},
+			],
+		});
+
+		ParticlePool {
+			capacity,
+			particle_buffer,
+			emitter_buffer,
+			simulate_bind_group,
+			draw_bind_group,
+			cpu_particles: if self.gpu { vec![] } else { vec![Particle::default(); capacity as usize] },
+			next_spawn: 0,
+			spawn_accumulator: 0.0,
+			frame: 0,
+		}
+	}
+
+	/*
+	Matches the pools to the emitters, works out this frame's spawns, and uploads the emitters,
+	or on the CPU path simulates and uploads the particles too.
+	*/
+	pub fn prepare(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, camera_buffer: &wgpu::Buffer, scene: &scene::Scene) {
+		let (emitters, delta_time) = (&scene.emitters, scene.delta_time);
+		self.pools.truncate(emitters.len());
+		for (index, emitter) in emitters.iter().enumerate() {
+			let capacity = emitter.capacity.max(1);
+			if self.pools.get(index).is_none_or(|pool| pool.capacity != capacity) {
+				let pool = self.create_pool(device, camera_buffer, capacity);
+				if index < self.pools.len() {
+					self.pools[index] = pool;
+				} else {
+					self.pools.push(pool);
+				}
+			}
+
+			let pool = &mut self.pools[index];
+			if emitter.enabled {
+				pool.spawn_accumulator += emitter.rate.max(0.0) * delta_time;
+			}
+			let spawn_count = (pool.spawn_accumulator as u32).min(capacity);
+			pool.spawn_accumulator -= spawn_count as f32;
+			pool.frame = pool.frame.wrapping_add(1);
+
+			let direction = cgmath::Vector3::from(emitter.direction);
+			let direction = if direction.magnitude2() > 0.0 { direction.normalize() } else { cgmath::Vector3::unit_y() };
+			let mut lights = [bytemuck::Zeroable::zeroed(); MAX_LIGHTS];
+			let mut light_count = 0;
+			if emitter.lighting == ParticleLighting::Lit {
+				for (slot, light) in lights.iter_mut().zip(nearest_lights(scene.lights(), emitter.position)) {
+					*slot = light.to_uniform();
+					light_count += 1;
+				}
+			}
+			let ambient = scene.environment.ambient_color.map(|c| c * scene.environment.ambient_intensity);
+			let uniform = EmitterUniform {
+				origin: emitter.position,
+				delta_time,
+				direction: direction.into(),
+				spread: emitter.spread,
+				gravity: emitter.gravity,
+				speed: emitter.speed,
+				start_color: emitter.start_color,
+				end_color: emitter.end_color,
+				lifetime: emitter.lifetime.max(f32::EPSILON),
+				start_size: emitter.start_size,
+				end_size: emitter.end_size,
+				seed: hash(pool.frame.wrapping_mul(0x9e37_79b9) ^ index as u32),
+				spawn_start: pool.next_spawn,
+				spawn_count,
+				capacity,
+				light_count,
+				ambient,
+				softness: emitter.softness.max(0.0),
+				lights,
+				lit: (emitter.lighting == ParticleLighting::Lit) as u32,
+				..bytemuck::Zeroable::zeroed()
+			};
+			pool.next_spawn = (pool.next_spawn + spawn_count) % capacity;
+			queue.write_buffer(&pool.emitter_buffer, 0, bytemuck::cast_slice(&[uniform]));
+
+			if !self.gpu {
+				for (slot, particle) in pool.cpu_particles.iter_mut().enumerate() {
+					simulate(particle, slot as u32, &uniform);
+				}
+				queue.write_buffer(&pool.particle_buffer, 0, bytemuck::cast_slice(&pool.cpu_particles));
+			}
+		}
+	}
+
+	// integrates and spawns every pool, nothing to do on the CPU path
+	pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder) {
+		let Some(pipeline) = &self.simulate_pipeline else {
+			return;
+		};
+		if self.pools.is_empty() {
+			return;
+		}
+		let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+			label: Some("Particle Simulate Pass"),
+			timestamp_writes: None,
+		});
+		compute_pass.set_pipeline(pipeline);
+		for pool in &self.pools {
+			if let Some(bind_group) = &pool.simulate_bind_group {
+				compute_pass.set_bind_group(0, bind_group, &[]);
+				compute_pass.dispatch_workgroups(pool.capacity.div_ceil(WORKGROUP_SIZE), 1, 1);
+			}
+		}
+	}
+
+	// draws every pool, each slot is an instance and dead ones are collapsed
+	pub fn draw(&self, render_pass: &mut wgpu::RenderPass, emitters: &[ParticleEmitter], interlaced: bool) {
+		if let Some(bind_groups) = &self.depth_bind_groups {
+			render_pass.set_bind_group(1, &bind_groups[interlaced as usize], &[]);
+		}
+		for (pool, emitter) in self.pools.iter().zip(emitters) {
+			let blend = if emitter.blend == model::BlendMode::Additive { model::BlendMode::Additive } else { model::BlendMode::Alpha };
+			let Some((_, pipeline)) = self.draw_pipelines.iter().find(|(mode, _)| *mode == blend) else {
+				continue;
+			};
+			render_pass.set_pipeline(pipeline);
+			render_pass.set_bind_group(0, &pool.draw_bind_group, &[]);
+			render_pass.set_vertex_buffer(0, pool.particle_buffer.slice(..));
+			render_pass.draw(0..6, 0..pool.capacity);
+		}
+	}
+}
+
+// directional lights first, then the rest by distance, skipping those that don't reach the emitter
+fn nearest_lights(lights: &[light::Light], position: [f32; 3]) -> Vec<&light::Light> {
+	let position = cgmath::Point3::from(position);
+	let mut nearest = lights.iter()
+		.filter_map(|light| {
+			if light.kind == light::LightKind::Directional {
+				return Some((0.0, light));
+			}
+			let distance = position.distance(light.position.into());
+			(light.falloff == light::Falloff::None || distance < light.range).then_some((distance, light))
+		})
+		.collect::<Vec<_>>();
+	nearest.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+	nearest.into_iter().take(MAX_LIGHTS).map(|(_, light)| light).collect()
+}
+
+// the same hash and steps as particles_simulate.wgsl
+fn hash(x: u32) -> u32 {
+	let state = x.wrapping_mul(747796405).wrapping_add(2891336453);
+	let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+	(word >> 22) ^ word
+}
+
+fn random(seed: &mut u32) -> f32 {
+	*seed = hash(*seed);
+	*seed as f32 / u32::MAX as f32
+}
+
+fn simulate(particle: &mut Particle, slot: u32, emitter: &EmitterUniform) {
+	// slots from spawn_start on, wrapping around the ring
+	let offset = (slot + emitter.capacity - emitter.spawn_start) % emitter.capacity;
+	if offset < emitter.spawn_count {
+		let mut seed = hash(slot ^ emitter.seed);
+		let cos_theta = 1.0 + (emitter.spread.cos() - 1.0) * random(&mut seed);
+		let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+		let phi = random(&mut seed) * std::f32::consts::TAU;
+		let direction = cgmath::Vector3::from(emitter.direction);
+		let helper = if direction.y.abs() < 0.99 { cgmath::Vector3::unit_y() } else { cgmath::Vector3::unit_x() };
+		let tangent = helper.cross(direction).normalize();
+		let bitangent = direction.cross(tangent);
+		let velocity = (tangent * (phi.cos() * sin_theta) + bitangent * (phi.sin() * sin_theta) + direction * cos_theta) * emitter.speed;
+		*particle = Particle {
+			position: emitter.origin,
+			age: 0.0,
+			velocity: velocity.into(),
+			lifetime: emitter.lifetime * (0.75 + 0.5 * random(&mut seed)),
+		};
+		return;
+	}
+	if particle.age >= particle.lifetime {
+		return;
+	}
+	let dt = emitter.delta_time;
+	let velocity = cgmath::Vector3::from(particle.velocity) + cgmath::Vector3::from(emitter.gravity) * dt;
+	particle.position = (cgmath::Vector3::from(particle.position) + velocity * dt).into();
+	particle.velocity = velocity.into();
+	particle.age += dt;
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::reflection::ShaderReflection;
+
+	#[test]
+	fn layouts_match_the_shaders() {
+		let simulate = ShaderReflection::from_descriptor(&wgpu::include_wgsl!("particles_simulate.wgsl")).unwrap();
+		assert_eq!(simulate.layout_entries(0, wgpu::ShaderStages::COMPUTE, &[]).unwrap(), simulate_entries());
+		simulate.check_binding::<EmitterUniform>(0, 0).unwrap();
+		simulate.check_binding::<Particle>(0, 1).unwrap();
+
+		let draw = ShaderReflection::from_descriptor(&wgpu::include_wgsl!("particles.wgsl")).unwrap();
+		assert_eq!(draw.layout_entries(0, wgpu::ShaderStages::VERTEX_FRAGMENT, &[]).unwrap(), draw_entries());
+		assert_eq!(draw.layout_entries(1, wgpu::ShaderStages::FRAGMENT, &[]).unwrap(), depth_entries());
+		draw.check_binding::<EmitterUniform>(0, 1).unwrap();
+	}
+}
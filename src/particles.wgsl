@@ -0,0 +1,163 @@
+// Camera facing billboards instanced from a particle pool, fading over each particle's lifetime
+// and, when soft, where they meet the scene's depth
+
+struct Camera {
+	view: mat4x4<f32>,
+	proj: mat4x4<f32>,
+	view_proj: mat4x4<f32>,
+	inv_view: mat4x4<f32>,
+	inv_proj: mat4x4<f32>,
+	inv_view_proj: mat4x4<f32>,
+	position: vec4<f32>,
+};
+@group(0) @binding(0)
+var<uniform> camera: Camera;
+
+struct Light {
+	position: vec3<f32>,
+	intensity: f32,
+	color: vec3<f32>,
+	range: f32,
+	direction: vec3<f32>,
+	kind: u32, // 0 point, 1 directional, 2 spot
+	falloff: u32, // 0 none, 1 inverse square, 2 linear
+	shadow_index: i32,
+	spot_cos: vec2<f32>,
+	view_proj: mat4x4<f32>,
+};
+
+struct Emitter {
+	origin: vec3<f32>,
+	delta_time: f32,
+	direction: vec3<f32>,
+	spread: f32,
+	gravity: vec3<f32>,
+	speed: f32,
+	start_color: vec4<f32>,
+	end_color: vec4<f32>,
+	lifetime: f32,
+	start_size: f32,
+	end_size: f32,
+	seed: u32,
+	spawn_start: u32,
+	spawn_count: u32,
+	capacity: u32,
+	light_count: u32,
+	ambient: vec3<f32>,
+	softness: f32,
+	lights: array<Light, 4>,
+	lit: u32,
+};
+@group(0) @binding(1)
+var<uniform> emitter: Emitter;
+
+@group(1) @binding(0)
+var depth_texture: texture_depth_2d;
+
+struct ParticleInput {
+	@location(0) position: vec3<f32>,
+	@location(1) age: f32,
+	@location(2) velocity: vec3<f32>,
+	@location(3) lifetime: f32,
+};
+
+struct VertexOutput {
+	@builtin(position) clip_position: vec4<f32>,
+	@location(0) corner: vec2<f32>,
+	@location(1) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, particle: ParticleInput) -> VertexOutput {
+	var out: VertexOutput;
+	// dead and never spawned slots are collapsed outside the clip volume
+	if (particle.age >= particle.lifetime) {
+		out.clip_position = vec4<f32>(0.0, 0.0, -1.0, 1.0);
+		return out;
+	}
+
+	// two triangles, corners from -1 to 1
+	var corners = array<vec2<f32>, 6>(
+		vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, -1.0), vec2<f32>(1.0, 1.0),
+		vec2<f32>(-1.0, -1.0), vec2<f32>(1.0, 1.0), vec2<f32>(-1.0, 1.0),
+	);
+	let corner = corners[index];
+	let t = particle.age / particle.lifetime;
+	let size = mix(emitter.start_size, emitter.end_size, t) * 0.5;
+	let right = camera.inv_view[0].xyz;
+	let up = camera.inv_view[1].xyz;
+	let position = particle.position + (right * corner.x + up * corner.y) * size;
+
+	out.clip_position = camera.view_proj * vec4<f32>(position, 1.0);
+	out.corner = corner;
+	out.color = mix(emitter.start_color, emitter.end_color, t);
+	if emitter.lit != 0u {
+		out.color = vec4<f32>(out.color.rgb * particle_light(particle.position), out.color.a);
+	}
+	return out;
+}
+
+// the same attenuation as mesh.wgsl
+fn light_attenuation(light: Light, dist: f32) -> f32 {
+	switch (light.falloff) {
+		case 1u: {
+			let window = clamp(1.0 - pow(dist / light.range, 4.0), 0.0, 1.0);
+			return window * window / max(dist * dist, 0.0001);
+		}
+		case 2u: {
+			return clamp(1.0 - dist / light.range, 0.0, 1.0);
+		}
+		default: {
+			return 1.0;
+		}
+	}
+}
+
+// the light reaching a particle from every side, billboards have no normal to shade with
+fn particle_light(position: vec3<f32>) -> vec3<f32> {
+	var total = emitter.ambient;
+	for (var i = 0u; i < emitter.light_count; i++) {
+		let light = emitter.lights[i];
+		var radiance = light.color * light.intensity;
+		if light.kind != 1u {
+			let to_light = light.position - position;
+			let dist = max(length(to_light), 0.0001);
+			radiance *= light_attenuation(light, dist);
+			if light.kind == 2u {
+				radiance *= smoothstep(light.spot_cos.x, light.spot_cos.y, dot(-to_light / dist, normalize(light.direction)));
+			}
+		}
+		total += radiance;
+	}
+	return total;
+}
+
+// a soft round dot
+fn particle_color(in: VertexOutput) -> vec4<f32> {
+	let falloff = saturate(1.0 - dot(in.corner, in.corner));
+	let alpha = in.color.a * falloff * falloff;
+	return vec4<f32>(in.color.rgb, alpha);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	return particle_color(in);
+}
+
+
+// distance from the camera along its view direction
+fn linear_depth(depth: f32) -> f32 {
+	let view = camera.inv_proj * vec4<f32>(0.0, 0.0, depth, 1.0);
+	return -view.z / view.w;
+}
+
+@fragment
+fn fs_soft(in: VertexOutput) -> @location(0) vec4<f32> {
+	var color = particle_color(in);
+	if emitter.softness > 0.0 {
+		let scene_depth = textureLoad(depth_texture, vec2<i32>(in.clip_position.xy), 0);
+		let gap = linear_depth(scene_depth) - linear_depth(in.clip_position.z);
+		color.a *= saturate(gap / emitter.softness);
+	}
+	return color;
+}
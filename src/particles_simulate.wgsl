@@ -0,0 +1,103 @@
+// Spawns into and integrates a particle pool, one invocation per slot. Mirrored by particles.rs for the CPU path.
+
+struct Particle {
+	position: vec3<f32>,
+	age: f32,
+	velocity: vec3<f32>,
+	lifetime: f32, // 0 for a slot that was never spawned
+};
+
+struct Light {
+	position: vec3<f32>,
+	intensity: f32,
+	color: vec3<f32>,
+	range: f32,
+	direction: vec3<f32>,
+	kind: u32, // 0 point, 1 directional, 2 spot
+	falloff: u32, // 0 none, 1 inverse square, 2 linear
+	shadow_index: i32,
+	spot_cos: vec2<f32>,
+	view_proj: mat4x4<f32>,
+};
+
+struct Emitter {
+	origin: vec3<f32>,
+	delta_time: f32,
+	direction: vec3<f32>,
+	spread: f32,
+	gravity: vec3<f32>,
+	speed: f32,
+	start_color: vec4<f32>,
+	end_color: vec4<f32>,
+	lifetime: f32,
+	start_size: f32,
+	end_size: f32,
+	seed: u32,
+	spawn_start: u32,
+	spawn_count: u32,
+	capacity: u32,
+	light_count: u32,
+	ambient: vec3<f32>,
+	softness: f32,
+	lights: array<Light, 4>,
+	lit: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> emitter: Emitter;
+@group(0) @binding(1)
+var<storage, read_write> particles: array<Particle>;
+
+// pcg
+fn hash(x: u32) -> u32 {
+	let state = x * 747796405u + 2891336453u;
+	let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+	return (word >> 22u) ^ word;
+}
+
+fn random(seed: ptr<function, u32>) -> f32 {
+	*seed = hash(*seed);
+	return f32(*seed) / 4294967295.0;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+	let slot = id.x;
+	if (slot >= emitter.capacity) {
+		return;
+	}
+
+	// slots from spawn_start on, wrapping around the ring
+	let offset = (slot + emitter.capacity - emitter.spawn_start) % emitter.capacity;
+	if (offset < emitter.spawn_count) {
+		var seed = hash(slot ^ emitter.seed);
+		let cos_theta = 1.0 + (cos(emitter.spread) - 1.0) * random(&seed);
+		let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+		let phi = random(&seed) * 6.28318530718;
+		let direction = emitter.direction;
+		var helper = vec3<f32>(0.0, 1.0, 0.0);
+		if (abs(direction.y) >= 0.99) {
+			helper = vec3<f32>(1.0, 0.0, 0.0);
+		}
+		let tangent = normalize(cross(helper, direction));
+		let bitangent = cross(direction, tangent);
+		let velocity = (tangent * (cos(phi) * sin_theta) + bitangent * (sin(phi) * sin_theta) + direction * cos_theta) * emitter.speed;
+		var particle: Particle;
+		particle.position = emitter.origin;
+		particle.age = 0.0;
+		particle.velocity = velocity;
+		particle.lifetime = emitter.lifetime * (0.75 + 0.5 * random(&seed));
+		particles[slot] = particle;
+		return;
+	}
+
+	var particle = particles[slot];
+	if (particle.age >= particle.lifetime) {
+		return;
+	}
+	let dt = emitter.delta_time;
+	particle.velocity += emitter.gravity * dt;
+	particle.position += particle.velocity * dt;
+	particle.age += dt;
+	particles[slot] = particle;
+}
@@ -0,0 +1,321 @@
+use wgpu::util::DeviceExt;
+
+use crate::{camera, cube_scheduler, light, model::{self, Vertex}, scene, shadow};
+
+// the camera of one shadow map face, also used by the downlevel shadow map
+gpu_struct! {
+	Uniform
+	#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+	pub(crate) struct FaceUniform {
+		view_proj: [[f32; 4]; 4],
+		light_position: [f32; 3],
+		range: f32,
+	}
+}
+
+impl FaceUniform {
+	pub(crate) fn new(view_proj: cgmath::Matrix4<f32>, light: &light::LightUniform) -> Self {
+		Self {
+			view_proj: view_proj.into(),
+			light_position: light.position,
+			range: light.range,
+			..bytemuck::Zeroable::zeroed()
+		}
+	}
+}
+
+/*
+Shadows for several point lights in one depth cube array, so the lighting shader reaches all
+of them through a single bind group and picks a light's cube with the shadow_index stored in
+the light buffer. The faces store the distance to the light over its range rather than the
+projected depth, so a lookup only needs the light's position. Faces are re-rendered a few per
+frame by the scheduler, a cube whose light moved going first. Casters moving around a still
+light wait for its faces' turn.
+*/
+pub struct PointShadows {
+	face_views: Vec<wgpu::TextureView>, // 6 per light, in cube face order
+	face_buffer: wgpu::Buffer,
+	face_bind_group: wgpu::BindGroup,
+	pipeline: wgpu::RenderPipeline,
+	pub layout: wgpu::BindGroupLayout, // group 3 of the material pipelines
+	pub bind_group: wgpu::BindGroup,
+	view_projs: Vec<[cgmath::Matrix4<f32>; 6]>, // for each shadowed light
+	centers: Vec<cgmath::Point3<f32>>, // the position of each shadowed light
+	pub scheduler: cube_scheduler::CubeScheduler,
+}
+
+impl PointShadows {
+	pub const MAX_LIGHTS: u32 = 4;
+	const SIZE: u32 = 512;
+	const FACE_STRIDE: u64 = 256; // min uniform buffer offset alignment
+	const NEAR: f32 = 0.05;
+	const FACES_PER_FRAME: usize = 8;
+
+	pub fn new(device: &wgpu::Device, uniform_layout: &wgpu::BindGroupLayout) -> Self {
+		let texture = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some("Point Shadow Maps"),
+			size: wgpu::Extent3d {
+				width: Self::SIZE,
+				height: Self::SIZE,
+				depth_or_array_layers: Self::MAX_LIGHTS * 6,
+			},
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: FORMAT,
+			usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+			view_formats: &[],
+		});
+		let face_views = (0..Self::MAX_LIGHTS * 6)
+			.map(|layer| texture.create_view(&wgpu::TextureViewDescriptor {
+				label: Some("point_shadow_face_view"),
+				dimension: Some(wgpu::TextureViewDimension::D2),
+				base_array_layer: layer,
+				array_layer_count: Some(1),
+				..Default::default()
+			}))
+			.collect();
+		let view = texture.create_view(&wgpu::TextureViewDescriptor {
+			label: Some("point_shadow_view"),
+			dimension: Some(wgpu::TextureViewDimension::CubeArray),
+			array_layer_count: Some(Self::MAX_LIGHTS * 6),
+			..Default::default()
+		});
+		let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+			label: Some("point_shadow_sampler"),
+			mag_filter: wgpu::FilterMode::Linear,
+			min_filter: wgpu::FilterMode::Linear,
+			compare: Some(wgpu::CompareFunction::LessEqual),
+			..Default::default()
+		});
+
+		let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("point_shadow_bind_group_layout"),
+			entries: &[
+				wgpu::BindGroupLayoutEntry {
+					binding: 0,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Texture {
+						multisampled: false,
+						view_dimension: wgpu::TextureViewDimension::CubeArray,
+						sample_type: wgpu::TextureSampleType::Depth,
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry {
+					binding: 1,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+					count: None,
+				},
+			],
+		});
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("point_shadow_bind_group"),
+			layout: &layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::Sampler(&sampler),
+				},
+			],
+		});
+
+		let face_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Point Shadow Face Buffer"),
+			contents: &vec![0; (Self::MAX_LIGHTS as u64 * 6 * Self::FACE_STRIDE) as usize],
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+		});
+		let (face_layout, pipeline) = create_caster_pipeline(device, "Point Shadow", uniform_layout);
+		let face_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("point_shadow_face_bind_group"),
+			layout: &face_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+						buffer: &face_buffer,
+						offset: 0,
+						size: wgpu::BufferSize::new(std::mem::size_of::<FaceUniform>() as u64),
+					}),
+				},
+			],
+		});
+
+		Self {
+			face_views,
+			face_buffer,
+			face_bind_group,
+			pipeline,
+			layout,
+			bind_group,
+			view_projs: vec![],
+			centers: vec![],
+			scheduler: cube_scheduler::CubeScheduler::new(Self::FACES_PER_FRAME),
+		}
+	}
+
+	/*
+	Cameras looking down each cube face, in +X, -X, +Y, -Y, +Z, -Z order. Right handed look at
+	matrices see every face mirrored compared to how cube maps are sampled, so x is flipped in
+	the projection, which is why the pass doesn't cull back faces.
+	*/
+	fn face_view_projs(light: &light::LightUniform) -> [cgmath::Matrix4<f32>; 6] {
+		use cgmath::Vector3;
+		let proj = camera::OPENGL_TO_WGPU_MATRIX
+			* cgmath::Matrix4::from_nonuniform_scale(-1.0, 1.0, 1.0)
+			* cgmath::perspective(cgmath::Deg(90.0), 1.0, Self::NEAR, light.range);
+		let eye = cgmath::Point3::from(light.position);
+		[
+			(Vector3::unit_x(), Vector3::unit_y()),
+			(-Vector3::unit_x(), Vector3::unit_y()),
+			(Vector3::unit_y(), -Vector3::unit_z()),
+			(-Vector3::unit_y(), Vector3::unit_z()),
+			(Vector3::unit_z(), Vector3::unit_y()),
+			(-Vector3::unit_z(), Vector3::unit_y()),
+		].map(|(dir, up)| proj * cgmath::Matrix4::look_to_rh(eye, dir, up))
+	}
+
+	// the first MAX_LIGHTS point and spot lights cast shadows, returns the shadow index of each light,
+	// directional lights get none
+	pub fn update(&mut self, queue: &wgpu::Queue, lights: &[light::LightUniform]) -> Vec<Option<u32>> {
+		let shadowed = lights.iter().enumerate()
+			.filter(|(_, light)| !light.is_directional())
+			.map(|(index, _)| index)
+			.take(Self::MAX_LIGHTS as usize)
+			.collect::<Vec<_>>();
+		self.scheduler.set_cube_count(shadowed.len());
+		self.view_projs.truncate(shadowed.len());
+		self.centers = shadowed.iter().map(|&index| cgmath::Point3::from(lights[index].position)).collect();
+		for (i, light) in shadowed.iter().map(|&index| &lights[index]).enumerate() {
+			let view_projs = Self::face_view_projs(light);
+			if self.view_projs.get(i) == Some(&view_projs) {
+				continue;
+			}
+			self.scheduler.invalidate(i);
+			for (face, view_proj) in view_projs.iter().enumerate() {
+				let uniform = FaceUniform::new(*view_proj, light);
+				let offset = (i * 6 + face) as u64 * Self::FACE_STRIDE;
+				queue.write_buffer(&self.face_buffer, offset, bytemuck::cast_slice(&[uniform]));
+			}
+			if i < self.view_projs.len() {
+				self.view_projs[i] = view_projs;
+			} else {
+				self.view_projs.push(view_projs);
+			}
+		}
+		(0..lights.len())
+			.map(|index| shadowed.iter().position(|&shadowed| shadowed == index).map(|cube| cube as u32))
+			.collect()
+	}
+
+	// caster_offsets are the model uniform offsets of every object that casts shadows, eye is
+	// the camera position the scheduler prioritizes faces by
+	pub fn draw(&mut self, encoder: &mut wgpu::CommandEncoder, scene: &scene::Scene, uniform_bind_group: &wgpu::BindGroup, caster_offsets: &[Option<u32>], eye: cgmath::Point3<f32>) {
+		let layers = self.scheduler.schedule(&self.centers, eye);
+		let view_projs = layers.iter().map(|&layer| self.view_projs[layer / 6][layer % 6]).collect::<Vec<_>>();
+		let casters = shadow::ShadowCasters::cull(scene, &view_projs);
+		for (&layer, objects) in layers.iter().zip(&casters.cascades) {
+			let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+				label: Some("Point Shadow Pass"),
+				color_attachments: &[],
+				depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+					view: &self.face_views[layer],
+					depth_ops: Some(wgpu::Operations {
+						load: wgpu::LoadOp::Clear(1.0),
+						store: wgpu::StoreOp::Store,
+					}),
+					stencil_ops: None,
+				}),
+				occlusion_query_set: None,
+				timestamp_writes: None,
+				multiview_mask: None,
+			});
+			render_pass.set_pipeline(&self.pipeline);
+			render_pass.set_bind_group(1, &self.face_bind_group, &[(layer as u64 * Self::FACE_STRIDE) as u32]);
+
+			for &object in objects {
+				let Some(offset) = caster_offsets[object] else {
+					continue;
+				};
+				render_pass.set_bind_group(0, uniform_bind_group, &[offset]);
+				let obj = &scene.objects[object];
+				let skin = obj.skin.map(|skin| &scene.skins[skin]);
+				for (i, mesh) in scene.models[obj.model_index].meshes.iter().enumerate() {
+					// skinned casters draw the vertices the skinning pass wrote this frame
+					let vertex_buffer = skin.map_or(&mesh.vertex_buffer, |skin| &skin.meshes[i].vertex_buffer);
+					render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+					render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+					render_pass.draw_indexed(0..mesh.num_elements, 0, 0..1);
+				}
+			}
+		}
+	}
+}
+
+pub(crate) const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+/*
+The pipeline drawing casters into a face, storing the distance to the light over its range.
+Group 0 is the renderer's uniform bind group for the object transforms, group 1 the face
+uniform at a dynamic offset.
+*/
+pub(crate) fn create_caster_pipeline(device: &wgpu::Device, label: &str, uniform_layout: &wgpu::BindGroupLayout) -> (wgpu::BindGroupLayout, wgpu::RenderPipeline) {
+	let face_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+		label: Some("point_shadow_face_bind_group_layout"),
+		entries: &[
+			wgpu::BindGroupLayoutEntry {
+				binding: 0,
+				visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+				ty: wgpu::BindingType::Buffer {
+					ty: wgpu::BufferBindingType::Uniform,
+					has_dynamic_offset: true,
+					min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<FaceUniform>() as u64),
+				},
+				count: None,
+			},
+		],
+	});
+	let shader = device.create_shader_module(wgpu::include_wgsl!("point_shadow.wgsl"));
+	let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+		label: Some(&format!("{} Pipeline Layout", label)),
+		bind_group_layouts: &[Some(uniform_layout), Some(&face_layout)],
+		immediate_size: 0,
+	});
+	let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+		label: Some(&format!("{} Pipeline", label)),
+		layout: Some(&pipeline_layout),
+		vertex: wgpu::VertexState {
+			module: &shader,
+			entry_point: Some("vs_main"),
+			buffers: &[model::ModelVertex::desc()],
+			compilation_options: Default::default(),
+		},
+		fragment: Some(wgpu::FragmentState {
+			module: &shader,
+			entry_point: Some("fs_main"),
+			targets: &[],
+			compilation_options: Default::default(),
+		}),
+		// both sides cast, which also covers open meshes
+		primitive: wgpu::PrimitiveState {
+			cull_mode: None,
+			..Default::default()
+		},
+		depth_stencil: Some(wgpu::DepthStencilState {
+			format: FORMAT,
+			depth_write_enabled: Some(true),
+			depth_compare: Some(wgpu::CompareFunction::Less),
+			stencil: wgpu::StencilState::default(),
+			bias: wgpu::DepthBiasState::default(),
+		}),
+		multisample: wgpu::MultisampleState::default(),
+		multiview_mask: None,
+		cache: None,
+	});
+	(face_layout, pipeline)
+}
@@ -0,0 +1,35 @@
+// Renders the distance to a point light into one face of its shadow cube
+
+struct Model {
+	transform: mat4x4<f32>, // only the transform is needed here
+};
+@group(0) @binding(1)
+var<uniform> model: Model;
+
+struct Face {
+	view_proj: mat4x4<f32>,
+	light_position: vec3<f32>,
+	range: f32,
+};
+@group(1) @binding(0)
+var<uniform> face: Face;
+
+struct VertexOutput {
+	@builtin(position) clip_position: vec4<f32>,
+	@location(0) position: vec3<f32>,
+};
+
+@vertex
+fn vs_main(@location(0) position: vec3<f32>) -> VertexOutput {
+	let world = model.transform * vec4<f32>(position, 1.0);
+	var out: VertexOutput;
+	out.clip_position = face.view_proj * world;
+	out.position = world.xyz;
+	return out;
+}
+
+// distance over range, so the lighting shader can compare without the face's projection
+@fragment
+fn fs_main(in: VertexOutput) -> @builtin(frag_depth) f32 {
+	return min(distance(in.position, face.light_position) / face.range, 1.0);
+}
@@ -0,0 +1,24 @@
+// which pass darkens the lit image by ambient occlusion, each method has its own params
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AoMethod {
+	Off,
+	Gtao, // screen space, works without ray queries
+	Ssao, // screen space hemisphere sampling, cheaper than GTAO and less accurate
+	RayTraced, // traced in the ray tracing pass, needs ray queries
+}
+
+// post processing options that can be changed while running
+#[derive(Clone, Debug)]
+pub struct PostSettings {
+	pub ao: AoMethod,
+	pub interlaced: bool, // experimental, render half the columns each frame
+}
+
+impl PostSettings {
+	pub fn new(ray_queries: bool) -> Self {
+		Self {
+			ao: if ray_queries { AoMethod::RayTraced } else { AoMethod::Gtao },
+			interlaced: false,
+		}
+	}
+}
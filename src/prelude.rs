@@ -0,0 +1,21 @@
+/*
+What a program built on the crate usually needs, brought in with use webgpu_test::prelude::*.
+Objects are placed with a cgmath::Matrix4<f32>, re-exported at the crate root along with wgpu and
+winit. The rest of the API is in the public modules.
+*/
+pub use crate::{App, State};
+pub use crate::assets::{Assets, TextureHandle};
+pub use crate::camera::Camera;
+pub use crate::csg::{Operation, Solid};
+pub use crate::deferred::RenderPath;
+pub use crate::light::Light;
+pub use crate::model::{DrawModel, InstanceOverrides, Material, MaterialType, Model, ModelInstance, UvTransform, Vertex};
+pub use crate::normal_bake::{BakeSettings, BakedMaps};
+pub use crate::renderer::{Renderer, RendererOptions};
+pub use crate::scene::{LightHandle, NodeHandle, Scene};
+pub use crate::scene_manager::{SceneManager, SceneRequest, SceneSetup};
+pub use crate::vat::{VatClip, VatPlayback};
+
+// the traits their methods come from, an App is run with EventLoop::run_app
+pub use cgmath::prelude::*;
+pub use winit::application::ApplicationHandler;
@@ -0,0 +1,206 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+// how long one stage of the frame took on the GPU
+#[derive(Clone, Debug)]
+pub struct PassTime {
+	pub label: &'static str,
+	pub milliseconds: f32,
+}
+
+// timings of the last frame, the GPU ones arrive a frame or two late
+#[derive(Clone, Debug, Default)]
+pub struct FrameStats {
+	pub cpu_frame_time: f32, // milliseconds since the frame before
+	pub cpu_render_time: f32, // milliseconds spent recording and submitting the frame
+	pub gpu_passes: Vec<PassTime>, // in the order they ran, empty when profiling is off or unsupported
+}
+
+impl FrameStats {
+	pub fn gpu_time(&self) -> f32 {
+		self.gpu_passes.iter().map(|pass| pass.milliseconds).sum()
+	}
+}
+
+impl fmt::Display for FrameStats {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(f, "frame: {:.2} ms", self.cpu_frame_time)?;
+		write!(f, "render (cpu): {:.2} ms", self.cpu_render_time)?;
+		if !self.gpu_passes.is_empty() {
+			write!(f, "\ngpu: {:.2} ms", self.gpu_time())?;
+			for pass in &self.gpu_passes {
+				write!(f, "\n  {}: {:.3} ms", pass.label, pass.milliseconds)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+// a frame in flight's timestamps, resolved and read back once the GPU has written them
+struct FrameQueries {
+	query_set: wgpu::QuerySet,
+	resolve_buffer: wgpu::Buffer,
+	readback_buffer: wgpu::Buffer,
+	labels: Vec<&'static str>,
+	mapped: Arc<Mutex<Option<Result<(), wgpu::BufferAsyncError>>>>, // set by the map callback
+	in_use: bool, // from when the timestamps are copied in until they've been read
+}
+
+struct Queries {
+	frames: Vec<FrameQueries>, // by frame in flight
+	period: f32, // nanoseconds per timestamp tick
+}
+
+/*
+GPU timings of each stage of the frame. The renderer marks where each stage starts, a
+timestamp is written into the encoder there, and a stage lasts until the next mark. The
+stages make their own passes, so the timestamps go between passes rather than into them,
+which needs TIMESTAMP_QUERY_INSIDE_ENCODERS. The timestamps are resolved and copied into a
+readback buffer at the end of the frame, and read without waiting once the GPU is done. Each
+frame in flight has its own queries and buffers, a frame whose previous timestamps haven't
+been read yet is skipped rather than stalled on.
+Off by default, it's toggled with the profile console command.
+*/
+pub struct GpuProfiler {
+	pub enabled: bool,
+	queries: Option<Queries>, // None without timestamp queries
+	labels: Vec<&'static str>, // this frame's stages so far
+	recording: Option<usize>, // the frame in flight whose queries this frame's timestamps go to
+	pub stats: FrameStats,
+}
+
+impl GpuProfiler {
+	const MAX_STAGES: u32 = 31;
+
+	pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, timestamp_queries: bool, frames_in_flight: usize) -> Self {
+		let queries = timestamp_queries.then(|| {
+			// a timestamp at the start of every stage, and one after the last
+			let count = Self::MAX_STAGES + 1;
+			let size = count as u64 * wgpu::QUERY_SIZE as u64;
+			Queries {
+				frames: (0..frames_in_flight).map(|_| FrameQueries {
+					query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+						label: Some("Profiler Queries"),
+						ty: wgpu::QueryType::Timestamp,
+						count,
+					}),
+					resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+						label: Some("Profiler Resolve Buffer"),
+						size,
+						usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+						mapped_at_creation: false,
+					}),
+					readback_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+						label: Some("Profiler Readback Buffer"),
+						size,
+						usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+						mapped_at_creation: false,
+					}),
+					labels: vec![],
+					mapped: Arc::new(Mutex::new(None)),
+					in_use: false,
+				}).collect(),
+				period: queue.get_timestamp_period(),
+			}
+		});
+
+		Self {
+			enabled: false,
+			queries,
+			labels: vec![],
+			recording: None,
+			stats: FrameStats::default(),
+		}
+	}
+
+	pub fn is_supported(&self) -> bool {
+		self.queries.is_some()
+	}
+
+	// reads the timestamps of frames the GPU has finished, this frame's go to the queries of its frame in flight
+	pub fn begin_frame(&mut self, device: &wgpu::Device, frame_index: usize) {
+		self.labels.clear();
+		self.recording = None;
+		let Some(queries) = &mut self.queries else {
+			return;
+		};
+		// runs the map callbacks of finished frames
+		let _ = device.poll(wgpu::PollType::Poll);
+		for readback in &mut queries.frames {
+			let Some(result) = readback.mapped.lock().unwrap().take() else {
+				continue;
+			};
+			// the frame is free again either way, a failed map only loses its timings
+			readback.in_use = false;
+			if let Err(e) = result {
+				log::warn!("Unable to read the GPU timestamps: {}", e);
+				continue;
+			}
+			{
+				let size = (readback.labels.len() as u64 + 1) * wgpu::QUERY_SIZE as u64;
+				let data = readback.readback_buffer.slice(..size).get_mapped_range();
+				let timestamps: &[u64] = bytemuck::cast_slice(&data);
+				self.stats.gpu_passes = readback.labels.iter().zip(timestamps.windows(2))
+					.map(|(&label, pair)| PassTime {
+						label,
+						milliseconds: pair[1].saturating_sub(pair[0]) as f32 * queries.period / 1_000_000.0,
+					})
+					.collect();
+			}
+			readback.readback_buffer.unmap();
+		}
+		if !self.enabled {
+			self.stats.gpu_passes.clear();
+			return;
+		}
+		self.recording = queries.frames.get(frame_index)
+			.is_some_and(|frame| !frame.in_use)
+			.then_some(frame_index);
+	}
+
+	// ends the stage before and starts one with this label
+	pub fn mark(&mut self, encoder: &mut wgpu::CommandEncoder, label: &'static str) {
+		let (Some(queries), Some(index)) = (&self.queries, self.recording) else {
+			return;
+		};
+		if self.labels.len() as u32 >= Self::MAX_STAGES {
+			return;
+		}
+		encoder.write_timestamp(&queries.frames[index].query_set, self.labels.len() as u32);
+		self.labels.push(label);
+	}
+
+	// ends the last stage and copies the frame's timestamps out to be read
+	pub fn end_frame(&mut self, encoder: &mut wgpu::CommandEncoder) {
+		let (Some(queries), Some(index)) = (&mut self.queries, self.recording) else {
+			return;
+		};
+		if self.labels.is_empty() {
+			self.recording = None;
+			return;
+		}
+		let count = self.labels.len() as u32 + 1;
+		let readback = &mut queries.frames[index];
+		encoder.write_timestamp(&readback.query_set, count - 1);
+		encoder.resolve_query_set(&readback.query_set, 0..count, &readback.resolve_buffer, 0);
+		encoder.copy_buffer_to_buffer(&readback.resolve_buffer, 0, &readback.readback_buffer, 0, count as u64 * wgpu::QUERY_SIZE as u64);
+		readback.labels = std::mem::take(&mut self.labels);
+		readback.in_use = true;
+	}
+
+	// maps the frame's timestamps once its submission is done, after the encoder has been submitted
+	pub fn after_submit(&mut self) {
+		let (Some(queries), Some(index)) = (&self.queries, self.recording.take()) else {
+			return;
+		};
+		let readback = &queries.frames[index];
+		if !readback.in_use {
+			return;
+		}
+		let mapped = readback.mapped.clone();
+		let size = (readback.labels.len() as u64 + 1) * wgpu::QUERY_SIZE as u64;
+		readback.readback_buffer.slice(..size).map_async(wgpu::MapMode::Read, move |result| {
+			*mapped.lock().unwrap() = Some(result);
+		});
+	}
+}
@@ -0,0 +1,486 @@
+use std::iter;
+
+use wgpu::util::DeviceExt;
+
+use crate::{light, model, scene, temporal, texture};
+
+/*
+Optional ray traced shadows and ambient occlusion, only created on adapters exposing
+wgpu's experimental ray queries. Each mesh gets a BLAS when it's loaded and the TLAS is
+rebuilt from the scene objects every frame, then a full screen pass traces rays from the
+depth buffer into a visibility texture, which is accumulated over frames and multiplied onto
+the lit image. Without ray queries the renderer has no RayTracing, the shadow maps still shade
+the lights, and post::PostSettings::new picks GTAO for the ambient occlusion.
+Skinned instances are traced against their bind pose BLAS.
+*/
+
+gpu_struct! {
+	Uniform
+	#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+	pub struct RayTracingParams {
+		pub shadow_strength: f32, // 0 disables shadow rays
+		pub ao_strength: f32, // 0 disables AO rays
+		pub ao_radius: f32,
+		pub ao_samples: u32,
+		frame: u32,
+	}
+}
+
+impl RayTracingParams {
+	pub fn new() -> Self {
+		Self {
+			shadow_strength: 0.8,
+			ao_strength: 0.6,
+			ao_radius: 0.3,
+			ao_samples: 4,
+			frame: 0,
+			..bytemuck::Zeroable::zeroed()
+		}
+	}
+}
+
+// per TLAS instance, lets the shadow pass find the bias and flags of the surface it shades
+gpu_struct! {
+	Storage
+	#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+	struct InstanceShadow {
+		bias: f32, // 0 uses the default bias
+		flags: u32, // ModelInstance shadow flags
+	}
+}
+
+// what the tracing pass binds besides the TLAS and the renderer's targets and buffers, kept across resizes
+struct PassInputs {
+	layout: wgpu::BindGroupLayout,
+	params_buffer: wgpu::Buffer,
+	instance_buffer: wgpu::Buffer,
+}
+
+pub struct RayTracing {
+	pub params: RayTracingParams,
+	inputs: PassInputs,
+	tlas: wgpu::Tlas,
+	bind_group: wgpu::BindGroup,
+	pipeline: wgpu::RenderPipeline,
+	visibility: texture::Texture,
+	pub temporal: temporal::TemporalAccumulator,
+	composite_layout: wgpu::BindGroupLayout,
+	composite_bind_groups: [wgpu::BindGroup; 2], // reading the accumulator's history[i]
+	composite_pipeline: wgpu::RenderPipeline,
+}
+
+impl RayTracing {
+	pub const FEATURES: wgpu::Features = wgpu::Features::EXPERIMENTAL_RAY_QUERY;
+	// instance mask bits, shadow rays only hit casters
+	pub const MASK_VISIBLE: u8 = 0x1;
+	pub const MASK_SHADOW_CASTER: u8 = 0x2;
+	const MAX_INSTANCES: u32 = 1024;
+	const VISIBILITY_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::R8Unorm;
+
+	pub fn new(
+		device: &wgpu::Device,
+		config: &wgpu::SurfaceConfiguration,
+		depth_texture: &texture::Texture,
+		camera_buffer: &wgpu::Buffer,
+		light_buffer: &wgpu::Buffer,
+	) -> Self {
+		let params = RayTracingParams::new();
+		let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Ray Tracing Params Buffer"),
+			contents: bytemuck::cast_slice(&[params]),
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+		});
+
+		let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Ray Tracing Instance Buffer"),
+			size: Self::MAX_INSTANCES as u64 * std::mem::size_of::<InstanceShadow>() as u64,
+			usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		});
+
+		let tlas = device.create_tlas(&wgpu::CreateTlasDescriptor {
+			label: Some("Scene TLAS"),
+			max_instances: Self::MAX_INSTANCES,
+			flags: wgpu::AccelerationStructureFlags::PREFER_FAST_BUILD,
+			update_mode: wgpu::AccelerationStructureUpdateMode::Build,
+		});
+
+		let uniform = |binding| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Buffer {
+				ty: wgpu::BufferBindingType::Uniform,
+				has_dynamic_offset: false,
+				min_binding_size: None,
+			},
+			count: None,
+		};
+		let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("ray_tracing_bind_group_layout"),
+			entries: &[
+				wgpu::BindGroupLayoutEntry { // depth
+					binding: 0,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Texture {
+						multisampled: false,
+						view_dimension: wgpu::TextureViewDimension::D2,
+						sample_type: wgpu::TextureSampleType::Depth,
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry { // scene
+					binding: 1,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::AccelerationStructure { vertex_return: false },
+					count: None,
+				},
+				uniform(2), // camera
+				light::Lights::storage_entry(3), // lights
+				uniform(4), // params
+				wgpu::BindGroupLayoutEntry { // instances
+					binding: 5,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Storage { read_only: true },
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+			],
+		});
+		let inputs = PassInputs {
+			layout,
+			params_buffer,
+			instance_buffer,
+		};
+		let bind_group = Self::create_bind_group(device, &inputs, &tlas, depth_texture, camera_buffer, light_buffer);
+
+		let shader = device.create_shader_module(wgpu::include_wgsl!("ray_tracing.wgsl"));
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Ray Tracing Pipeline Layout"),
+			bind_group_layouts: &[Some(&inputs.layout)],
+			immediate_size: 0,
+		});
+		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("Ray Tracing Pipeline"),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: Some("vs_main"),
+				buffers: &[],
+				compilation_options: Default::default(),
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: Some("fs_main"),
+				targets: &[Some(wgpu::ColorTargetState {
+					format: Self::VISIBILITY_FORMAT,
+					blend: None,
+					write_mask: wgpu::ColorWrites::ALL,
+				})],
+				compilation_options: Default::default(),
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			multiview_mask: None,
+			cache: None,
+		});
+
+		let visibility = Self::create_visibility_texture(device, config);
+		let temporal = temporal::TemporalAccumulator::new(device, "Ray Tracing", config, Self::VISIBILITY_FORMAT, 1, temporal::FrameInputs {
+			depth_texture,
+			camera_buffer,
+			input: &visibility.view,
+		});
+
+		let composite_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("ray_tracing_composite_bind_group_layout"),
+			entries: &[
+				wgpu::BindGroupLayoutEntry { // accumulated visibility
+					binding: 6,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Texture {
+						multisampled: false,
+						view_dimension: wgpu::TextureViewDimension::D2,
+						sample_type: wgpu::TextureSampleType::Float { filterable: true },
+					},
+					count: None,
+				},
+			],
+		});
+		let composite_bind_groups = Self::create_composite_bind_groups(device, &composite_layout, &temporal);
+		let composite_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Ray Tracing Composite Pipeline Layout"),
+			bind_group_layouts: &[Some(&composite_layout)],
+			immediate_size: 0,
+		});
+		let composite_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("Ray Tracing Composite Pipeline"),
+			layout: Some(&composite_pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: Some("vs_main"),
+				buffers: &[],
+				compilation_options: Default::default(),
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: Some("fs_composite"),
+				targets: &[Some(wgpu::ColorTargetState {
+					format: config.format,
+					// multiply the visibility onto the lit image
+					blend: Some(wgpu::BlendState {
+						color: wgpu::BlendComponent {
+							src_factor: wgpu::BlendFactor::Dst,
+							dst_factor: wgpu::BlendFactor::Zero,
+							operation: wgpu::BlendOperation::Add,
+						},
+						alpha: wgpu::BlendComponent {
+							src_factor: wgpu::BlendFactor::Zero,
+							dst_factor: wgpu::BlendFactor::One,
+							operation: wgpu::BlendOperation::Add,
+						},
+					}),
+					write_mask: wgpu::ColorWrites::ALL,
+				})],
+				compilation_options: Default::default(),
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			multiview_mask: None,
+			cache: None,
+		});
+
+		Self {
+			params,
+			inputs,
+			tlas,
+			bind_group,
+			pipeline,
+			visibility,
+			temporal,
+			composite_layout,
+			composite_bind_groups,
+			composite_pipeline,
+		}
+	}
+
+	fn create_visibility_texture(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> texture::Texture {
+		texture::Texture::create_render_target(device, config, Self::VISIBILITY_FORMAT, wgpu::TextureUsages::RENDER_ATTACHMENT, "ray_tracing_visibility_texture")
+	}
+
+	fn create_composite_bind_groups(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, temporal: &temporal::TemporalAccumulator) -> [wgpu::BindGroup; 2] {
+		std::array::from_fn(|i| device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("ray_tracing_composite_bind_group"),
+			layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 6,
+					resource: wgpu::BindingResource::TextureView(&temporal.history()[i].view),
+				},
+			],
+		}))
+	}
+
+	fn create_bind_group(
+		device: &wgpu::Device,
+		inputs: &PassInputs,
+		tlas: &wgpu::Tlas,
+		depth_texture: &texture::Texture,
+		camera_buffer: &wgpu::Buffer,
+		light_buffer: &wgpu::Buffer,
+	) -> wgpu::BindGroup {
+		device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("ray_tracing_bind_group"),
+			layout: &inputs.layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: tlas.as_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: camera_buffer.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 3,
+					resource: light_buffer.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 4,
+					resource: inputs.params_buffer.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 5,
+					resource: inputs.instance_buffer.as_entire_binding(),
+				},
+			],
+		})
+	}
+
+	pub fn tlas(&self) -> &wgpu::Tlas {
+		&self.tlas
+	}
+
+	// the depth texture is recreated on resize
+	pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, depth_texture: &texture::Texture, camera_buffer: &wgpu::Buffer, light_buffer: &wgpu::Buffer) {
+		self.bind_group = Self::create_bind_group(device, &self.inputs, &self.tlas, depth_texture, camera_buffer, light_buffer);
+		self.visibility = Self::create_visibility_texture(device, config);
+		self.temporal.resize(device, config, temporal::FrameInputs {
+			depth_texture,
+			camera_buffer,
+			input: &self.visibility.view,
+		});
+		self.composite_bind_groups = Self::create_composite_bind_groups(device, &self.composite_layout, &self.temporal);
+	}
+
+	// builds the bottom level acceleration structure of a mesh, whose buffers need BLAS_INPUT usage
+	pub fn build_blas(
+		device: &wgpu::Device,
+		encoder: &mut wgpu::CommandEncoder,
+		vertex_buffer: &wgpu::Buffer,
+		vertex_count: u32,
+		index_buffer: &wgpu::Buffer,
+		index_count: u32,
+	) -> wgpu::Blas {
+		let size = wgpu::BlasTriangleGeometrySizeDescriptor {
+			vertex_format: wgpu::VertexFormat::Float32x3,
+			vertex_count,
+			index_format: Some(wgpu::IndexFormat::Uint32),
+			index_count: Some(index_count),
+			flags: wgpu::AccelerationStructureGeometryFlags::OPAQUE,
+		};
+		let blas = device.create_blas(
+			&wgpu::CreateBlasDescriptor {
+				label: Some("Mesh BLAS"),
+				flags: wgpu::AccelerationStructureFlags::PREFER_FAST_TRACE,
+				update_mode: wgpu::AccelerationStructureUpdateMode::Build,
+			},
+			wgpu::BlasGeometrySizeDescriptors::Triangles {
+				descriptors: vec![size.clone()],
+			},
+		);
+		encoder.build_acceleration_structures(
+			iter::once(&wgpu::BlasBuildEntry {
+				blas: &blas,
+				geometry: wgpu::BlasGeometries::TriangleGeometries(vec![wgpu::BlasTriangleGeometry {
+					size: &size,
+					vertex_buffer,
+					first_vertex: 0,
+					vertex_stride: std::mem::size_of::<model::ModelVertex>() as wgpu::BufferAddress, // position comes first
+					index_buffer: Some(index_buffer),
+					first_index: Some(0),
+					transform_buffer: None,
+					transform_buffer_offset: None,
+				}]),
+			}),
+			iter::empty(),
+		);
+		blas
+	}
+
+	// rebuilds the TLAS from the scene objects and uploads the params for this frame,
+	// ao is off when another method provides the ambient occlusion
+	pub fn update(&mut self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, scene: &scene::Scene, ao: bool) {
+		let blases = scene.objects.iter().flat_map(|obj| {
+			let m = obj.transform;
+			// row major 3x4
+			let transform = [
+				m.x.x, m.y.x, m.z.x, m.w.x,
+				m.x.y, m.y.y, m.z.y, m.w.y,
+				m.x.z, m.y.z, m.z.z, m.w.z,
+			];
+			let mask = if obj.casts_shadows { Self::MASK_VISIBLE | Self::MASK_SHADOW_CASTER } else { Self::MASK_VISIBLE };
+			let shadow = InstanceShadow {
+				bias: obj.shadow_bias.unwrap_or(0.0),
+				flags: obj.shadow_flags(),
+				..bytemuck::Zeroable::zeroed()
+			};
+			scene.models[obj.model_index].meshes.iter()
+				.filter_map(move |mesh| mesh.blas.as_ref().map(|blas| (blas, transform, mesh.material, mask, shadow)))
+		});
+
+		let mut count = 0;
+		let mut instances = vec![];
+		for (i, (blas, transform, material, mask, shadow)) in blases.take(Self::MAX_INSTANCES as usize).enumerate() {
+			// the material index lets ray traced reflections look up the hit's color
+			self.tlas[i] = Some(wgpu::TlasInstance::new(blas, transform, material as u32, mask));
+			instances.push(shadow);
+			count = i + 1;
+		}
+		if !instances.is_empty() {
+			queue.write_buffer(&self.inputs.instance_buffer, 0, bytemuck::cast_slice(&instances));
+		}
+		for i in count..Self::MAX_INSTANCES as usize {
+			if self.tlas[i].is_none() {
+				break;
+			}
+			self.tlas[i] = None;
+		}
+		encoder.build_acceleration_structures(iter::empty(), iter::once(&self.tlas));
+
+		self.params.frame = self.params.frame.wrapping_add(1);
+		let mut params = self.params;
+		if !ao {
+			params.ao_strength = 0.0;
+		}
+		queue.write_buffer(&self.inputs.params_buffer, 0, bytemuck::cast_slice(&[params]));
+	}
+
+	// view_proj is this frame's camera, for the temporal reprojection
+	pub fn draw(&mut self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, view_proj: cgmath::Matrix4<f32>) {
+		if self.params.shadow_strength <= 0.0 && self.params.ao_strength <= 0.0 {
+			return;
+		}
+		{
+			let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+				label: Some("Ray Traced Shadow Pass"),
+				color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+					view: &self.visibility.view,
+					resolve_target: None,
+					ops: wgpu::Operations {
+						load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+						store: wgpu::StoreOp::Store,
+					},
+					depth_slice: None,
+				})],
+				depth_stencil_attachment: None,
+				occlusion_query_set: None,
+				timestamp_writes: None,
+				multiview_mask: None,
+			});
+			render_pass.set_pipeline(&self.pipeline);
+			render_pass.set_bind_group(0, &self.bind_group, &[]);
+			render_pass.draw(0..3, 0..1);
+		}
+
+		self.temporal.accumulate(queue, encoder, view_proj);
+
+		let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Ray Traced Shadow Composite Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: wgpu::StoreOp::Store,
+				},
+				depth_slice: None,
+			})],
+			depth_stencil_attachment: None,
+			occlusion_query_set: None,
+			timestamp_writes: None,
+			multiview_mask: None,
+		});
+		render_pass.set_pipeline(&self.composite_pipeline);
+		render_pass.set_bind_group(0, &self.composite_bind_groups[self.temporal.current()], &[]);
+		render_pass.draw(0..3, 0..1);
+	}
+}
@@ -0,0 +1,183 @@
+enable wgpu_ray_query;
+
+// Full screen pass that traces shadow and ambient occlusion rays from the depth buffer into a
+// visibility texture, and the pass that multiplies the accumulated visibility onto the lit image
+
+struct Camera {
+	view: mat4x4<f32>,
+	proj: mat4x4<f32>,
+	view_proj: mat4x4<f32>,
+	inv_view: mat4x4<f32>,
+	inv_proj: mat4x4<f32>,
+	inv_view_proj: mat4x4<f32>,
+	position: vec4<f32>,
+};
+
+struct Light {
+	position: vec3<f32>,
+	intensity: f32,
+	color: vec3<f32>,
+	range: f32,
+	direction: vec3<f32>,
+	kind: u32, // 0 point, 1 directional, 2 spot
+	falloff: u32,
+	shadow_index: i32,
+	spot_cos: vec2<f32>,
+	view_proj: mat4x4<f32>,
+};
+struct Lights {
+	count: u32,
+	lights: array<Light>,
+};
+
+struct Params {
+	shadow_strength: f32, // 0 disables shadow rays
+	ao_strength: f32, // 0 disables AO rays
+	ao_radius: f32,
+	ao_samples: u32,
+	frame: u32,
+};
+
+@group(0) @binding(0)
+var depth_texture: texture_depth_2d;
+@group(0) @binding(1)
+var scene_tlas: acceleration_structure;
+@group(0) @binding(2)
+var<uniform> camera: Camera;
+@group(0) @binding(3)
+var<storage, read> lights: Lights; // shadow rays go to the first light
+@group(0) @binding(4)
+var<uniform> params: Params;
+
+struct InstanceShadow {
+	bias: f32, // 0 uses RAY_BIAS
+	flags: u32, // 1 casts, 2 receives
+};
+@group(0) @binding(5)
+var<storage, read> instances: array<InstanceShadow>;
+
+@group(0) @binding(6)
+var history_texture: texture_2d<f32>; // accumulated visibility, only bound for the composite
+
+const RAY_BIAS: f32 = 0.002;
+const DIRECTIONAL_DISTANCE: f32 = 1000.0; // how far shadow rays toward a directional light go
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+	// one triangle covering the screen
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn world_position(pixel: vec2<f32>, depth: f32) -> vec3<f32> {
+	let size = vec2<f32>(textureDimensions(depth_texture));
+	let uv = pixel / size;
+	let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+	let world = camera.inv_view_proj * ndc;
+	return world.xyz / world.w;
+}
+
+fn occluded(origin: vec3<f32>, dir: vec3<f32>, t_max: f32) -> bool {
+	var rq: ray_query;
+	// only shadow casters (instance mask 0x2)
+	rayQueryInitialize(&rq, scene_tlas, RayDesc(RAY_FLAG_TERMINATE_ON_FIRST_HIT, 0x2u, 0.0, t_max, origin, dir));
+	rayQueryProceed(&rq);
+	return rayQueryGetCommittedIntersection(&rq).kind != RAY_QUERY_INTERSECTION_NONE;
+}
+
+// the TLAS instance seen through a pixel, or -1 when the ray misses
+fn visible_instance(position: vec3<f32>) -> i32 {
+	let to_surface = position - camera.position.xyz;
+	let distance = length(to_surface);
+	var rq: ray_query;
+	rayQueryInitialize(&rq, scene_tlas, RayDesc(0u, 0x1u, 0.0, distance * 1.01, camera.position.xyz, to_surface / distance));
+	rayQueryProceed(&rq);
+	let hit = rayQueryGetCommittedIntersection(&rq);
+	if hit.kind == RAY_QUERY_INTERSECTION_NONE {
+		return -1;
+	}
+	return i32(hit.instance_index);
+}
+
+fn hash(p: vec3<u32>) -> vec3<f32> {
+	var v = p * vec3<u32>(1664525u, 1013904223u, 2654435761u) + vec3<u32>(1013904223u, 1664525u, 374761393u);
+	v.x += v.y * v.z;
+	v.y += v.z * v.x;
+	v.z += v.x * v.y;
+	v ^= v >> vec3<u32>(16u);
+	v.x += v.y * v.z;
+	v.y += v.z * v.x;
+	v.z += v.x * v.y;
+	return vec3<f32>(v) / 4294967295.0;
+}
+
+// cosine weighted direction around n
+fn hemisphere_direction(n: vec3<f32>, r: vec2<f32>) -> vec3<f32> {
+	let phi = 6.2831853 * r.x;
+	let sin_theta = sqrt(r.y);
+	let local = vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, sqrt(1.0 - r.y));
+	let up = select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), abs(n.y) > 0.99);
+	let t = normalize(cross(up, n));
+	let b = cross(n, t);
+	return t * local.x + b * local.y + n * local.z;
+}
+
+@fragment
+fn fs_main(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
+	let depth = textureLoad(depth_texture, vec2<i32>(frag_coord.xy), 0);
+	let position = world_position(frag_coord.xy, depth);
+	// derivatives are taken before any early out so they stay defined
+	var normal = normalize(cross(dpdy(position), dpdx(position)));
+	if dot(normal, camera.position.xyz - position) < 0.0 {
+		normal = -normal;
+	}
+	if depth >= 1.0 {
+		return vec4<f32>(1.0);
+	}
+
+	// per object overrides of the surface being shaded
+	var bias = RAY_BIAS;
+	var receives = true;
+	let instance = visible_instance(position);
+	if instance >= 0 {
+		let shadow = instances[instance];
+		bias = select(RAY_BIAS, shadow.bias, shadow.bias > 0.0);
+		receives = (shadow.flags & 2u) != 0u;
+	}
+
+	let origin = position + normal * bias;
+	var visibility = 1.0;
+
+	// only the first light is traced, the visibility texture holds one shadow term
+	if params.shadow_strength > 0.0 && receives && lights.count > 0u {
+		let light = lights.lights[0];
+		var dir = -normalize(light.direction);
+		var distance = DIRECTIONAL_DISTANCE;
+		if light.kind != 1u {
+			let to_light = light.position - origin;
+			distance = length(to_light);
+			dir = to_light / distance;
+		}
+		if dot(dir, normal) > 0.0 && occluded(origin, dir, distance) {
+			visibility *= 1.0 - params.shadow_strength;
+		}
+	}
+
+	if params.ao_strength > 0.0 && params.ao_samples > 0u {
+		var hits = 0u;
+		for (var i = 0u; i < params.ao_samples; i++) {
+			let r = hash(vec3<u32>(vec2<u32>(frag_coord.xy), params.frame * params.ao_samples + i));
+			if occluded(origin, hemisphere_direction(normal, r.xy), params.ao_radius) {
+				hits++;
+			}
+		}
+		visibility *= 1.0 - params.ao_strength * f32(hits) / f32(params.ao_samples);
+	}
+
+	return vec4<f32>(vec3<f32>(visibility), 1.0);
+}
+
+@fragment
+fn fs_composite(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
+	return vec4<f32>(vec3<f32>(textureLoad(history_texture, vec2<i32>(frag_coord.xy), 0).r), 1.0);
+}
@@ -0,0 +1,158 @@
+use std::{future::Future, pin::Pin, sync::{Arc, Mutex}, task::{Context, Poll, Waker}};
+
+/*
+Copies GPU data into a mappable staging buffer and reads it back once the submission that
+wrote it has finished. Used for screenshots, picking IDs, histograms, etc.
+Record the copy with from_buffer/from_texture, submit the encoder, then await read(). Nothing
+blocks on the GPU, the read finishes once the submission has, a frame or two later.
+*/
+pub struct Readback {
+	buffer: wgpu::Buffer,
+	size: u64,
+	rows: Option<RowLayout>, // set when reading a texture, whose rows are padded in the buffer
+}
+
+#[derive(Copy, Clone)]
+struct RowLayout {
+	unpadded_bytes_per_row: u32,
+	padded_bytes_per_row: u32,
+	rows: u32,
+}
+
+impl Readback {
+	pub fn from_buffer(device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, source: &wgpu::Buffer, offset: u64, size: u64) -> Self {
+		let buffer = Self::staging_buffer(device, size);
+		encoder.copy_buffer_to_buffer(source, offset, &buffer, 0, size);
+
+		Self {
+			buffer,
+			size,
+			rows: None,
+		}
+	}
+
+	// reads mip 0, layer 0 of a texture; rows are tightly packed in the result
+	pub fn from_texture(device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder, texture: &wgpu::Texture) -> Self {
+		let block_size = texture.format().block_copy_size(Some(wgpu::TextureAspect::All))
+			.expect("texture format can't be copied to a buffer");
+		let unpadded_bytes_per_row = texture.width() * block_size;
+		let padded_bytes_per_row = unpadded_bytes_per_row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+		let rows = texture.height();
+		let size = padded_bytes_per_row as u64 * rows as u64;
+
+		let buffer = Self::staging_buffer(device, size);
+		encoder.copy_texture_to_buffer(
+			wgpu::TexelCopyTextureInfo {
+				texture,
+				mip_level: 0,
+				origin: wgpu::Origin3d::ZERO,
+				aspect: wgpu::TextureAspect::All,
+			},
+			wgpu::TexelCopyBufferInfo {
+				buffer: &buffer,
+				layout: wgpu::TexelCopyBufferLayout {
+					offset: 0,
+					bytes_per_row: Some(padded_bytes_per_row),
+					rows_per_image: Some(rows),
+				},
+			},
+			wgpu::Extent3d {
+				width: texture.width(),
+				height: texture.height(),
+				depth_or_array_layers: 1,
+			},
+		);
+
+		Self {
+			buffer,
+			size,
+			rows: Some(RowLayout {
+				unpadded_bytes_per_row,
+				padded_bytes_per_row,
+				rows,
+			}),
+		}
+	}
+
+	fn staging_buffer(device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+		device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Readback Buffer"),
+			size: size.next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT),
+			usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		})
+	}
+
+	/*
+	Maps the staging buffer once the submitted copy completes and returns its contents. On
+	native the map callback runs inside device.poll, which the future calls without waiting
+	each time it's polled, so it never blocks the thread; on the web the browser's event loop
+	runs the callback.
+	*/
+	pub async fn read(self, device: &wgpu::Device) -> anyhow::Result<Vec<u8>> {
+		let map = MapFuture {
+			shared: Arc::default(),
+			device: device.clone(),
+		};
+		let shared = map.shared.clone();
+		self.buffer.map_async(wgpu::MapMode::Read, ..self.size, move |result| {
+			let mut shared = shared.lock().unwrap();
+			shared.result = Some(result);
+			if let Some(waker) = shared.waker.take() {
+				waker.wake();
+			}
+		});
+
+		map.await?;
+
+		let data = {
+			let mapped = self.buffer.get_mapped_range(..self.size);
+			match self.rows {
+				Some(layout) => mapped
+					.chunks(layout.padded_bytes_per_row as usize)
+					.take(layout.rows as usize)
+					.flat_map(|row| &row[..layout.unpadded_bytes_per_row as usize])
+					.copied()
+					.collect(),
+				None => mapped.to_vec(),
+			}
+		};
+		self.buffer.unmap();
+
+		Ok(data)
+	}
+}
+
+#[derive(Default)]
+struct MapState {
+	result: Option<Result<(), wgpu::BufferAsyncError>>,
+	waker: Option<Waker>,
+}
+
+// resolves when the map_async callback runs
+struct MapFuture {
+	shared: Arc<Mutex<MapState>>,
+	#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+	device: wgpu::Device, // polled for the callback on native
+}
+
+impl Future for MapFuture {
+	type Output = Result<(), wgpu::BufferAsyncError>;
+
+	fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+		// before locking, the callback locks shared too
+		#[cfg(not(target_arch = "wasm32"))]
+		let _ = self.device.poll(wgpu::PollType::Poll);
+		let mut shared = self.shared.lock().unwrap();
+		match shared.result.take() {
+			Some(result) => Poll::Ready(result),
+			None => {
+				shared.waker = Some(cx.waker().clone());
+				// nothing else polls the device under block_on, so ask to be polled again
+				#[cfg(not(target_arch = "wasm32"))]
+				cx.waker().wake_by_ref();
+				Poll::Pending
+			}
+		}
+	}
+}
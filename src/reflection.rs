@@ -0,0 +1,193 @@
+/*
+Reads the resource bindings a WGSL source declares with naga, the same front end wgpu compiles
+it with. The tests check the layouts written out in Rust against it, and the Rust side of a
+uniform against the size naga lays the WGSL struct out at, which catches missing or extra
+padding before it shows up as garbage on the GPU. Only built for the tests, so release builds
+don't parse the shaders twice.
+*/
+pub struct ShaderReflection {
+	module: naga::Module,
+	layouter: naga::proc::Layouter,
+}
+
+impl ShaderReflection {
+	pub fn from_wgsl(label: &str, source: &str) -> anyhow::Result<Self> {
+		let module = naga::front::wgsl::parse_str(source)
+			.map_err(|e| anyhow::anyhow!("{}: {}", label, e.emit_to_string(source)))?;
+		let mut layouter = naga::proc::Layouter::default();
+		layouter.update(module.to_ctx())?;
+		Ok(Self {
+			module,
+			layouter,
+		})
+	}
+
+	// the source of a descriptor made with include_wgsl or a concatenation of files
+	pub fn from_descriptor(descriptor: &wgpu::ShaderModuleDescriptor) -> anyhow::Result<Self> {
+		let label = descriptor.label.unwrap_or("shader");
+		match &descriptor.source {
+			wgpu::ShaderSource::Wgsl(source) => Self::from_wgsl(label, source),
+			_ => anyhow::bail!("{} isn't WGSL", label),
+		}
+	}
+
+	fn global(&self, group: u32, binding: u32) -> anyhow::Result<&naga::GlobalVariable> {
+		self.module.global_variables.iter()
+			.map(|(_, global)| global)
+			.find(|global| global.binding.as_ref().is_some_and(|b| b.group == group && b.binding == binding))
+			.ok_or_else(|| anyhow::anyhow!("nothing is bound at group {} binding {}", group, binding))
+	}
+
+	// bytes of the binding's type, or of one element when it's an array
+	pub fn binding_size(&self, group: u32, binding: u32) -> anyhow::Result<u32> {
+		let global = self.global(group, binding)?;
+		Ok(match self.module.types[global.ty].inner {
+			naga::TypeInner::Array { stride, .. } => stride,
+			_ => self.layouter[global.ty].size,
+		})
+	}
+
+	// stride of the array a struct binding ends with, like the lights of a storage buffer
+	pub fn trailing_array_stride(&self, group: u32, binding: u32) -> anyhow::Result<u32> {
+		let global = self.global(group, binding)?;
+		if let naga::TypeInner::Struct { members, .. } = &self.module.types[global.ty].inner
+			&& let Some(last) = members.last()
+			&& let naga::TypeInner::Array { stride, .. } = self.module.types[last.ty].inner
+		{
+			return Ok(stride);
+		}
+		anyhow::bail!("group {} binding {} doesn't end with an array", group, binding)
+	}
+
+	// fails when T isn't the size of what the shader binds
+	pub fn check_binding<T>(&self, group: u32, binding: u32) -> anyhow::Result<()> {
+		check_size::<T>(self.binding_size(group, binding)?, group, binding)
+	}
+
+	pub fn check_trailing_array<T>(&self, group: u32, binding: u32) -> anyhow::Result<()> {
+		check_size::<T>(self.trailing_array_stride(group, binding)?, group, binding)
+	}
+
+	/*
+	Layout entries for every binding of the group, in binding order. Dynamic offsets and
+	unfilterable float textures can't be told from the shader, so buffers in dynamic are
+	given them and every float texture is filterable.
+	*/
+	pub fn layout_entries(&self, group: u32, visibility: wgpu::ShaderStages, dynamic: &[u32]) -> anyhow::Result<Vec<wgpu::BindGroupLayoutEntry>> {
+		let mut entries = vec![];
+		for (_, global) in self.module.global_variables.iter() {
+			let Some(binding) = global.binding.as_ref().filter(|binding| binding.group == group) else {
+				continue;
+			};
+			let buffer = |ty| wgpu::BindingType::Buffer {
+				ty,
+				has_dynamic_offset: dynamic.contains(&binding.binding),
+				min_binding_size: None,
+			};
+			let ty = match (global.space, &self.module.types[global.ty].inner) {
+				(naga::AddressSpace::Uniform, _) => buffer(wgpu::BufferBindingType::Uniform),
+				(naga::AddressSpace::Storage { access }, _) => buffer(wgpu::BufferBindingType::Storage {
+					read_only: !access.contains(naga::StorageAccess::STORE),
+				}),
+				(_, naga::TypeInner::Sampler { comparison: true }) => wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+				(_, naga::TypeInner::Sampler { comparison: false }) => wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+				(_, naga::TypeInner::Image { dim, arrayed, class }) => {
+					let view_dimension = match (dim, arrayed) {
+						(naga::ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+						(naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+						(naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+						(naga::ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+						(naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+						(naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+					};
+					match class {
+						naga::ImageClass::Sampled { kind, multi } => wgpu::BindingType::Texture {
+							sample_type: match kind {
+								naga::ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+								naga::ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+								_ => wgpu::TextureSampleType::Float { filterable: true },
+							},
+							view_dimension,
+							multisampled: *multi,
+						},
+						naga::ImageClass::Depth { multi } => wgpu::BindingType::Texture {
+							sample_type: wgpu::TextureSampleType::Depth,
+							view_dimension,
+							multisampled: *multi,
+						},
+						naga::ImageClass::Storage { format, access } => wgpu::BindingType::StorageTexture {
+							access: if access.contains(naga::StorageAccess::LOAD | naga::StorageAccess::STORE) {
+								wgpu::StorageTextureAccess::ReadWrite
+							} else if access.contains(naga::StorageAccess::STORE) {
+								wgpu::StorageTextureAccess::WriteOnly
+							} else {
+								wgpu::StorageTextureAccess::ReadOnly
+							},
+							format: storage_format(*format)?,
+							view_dimension,
+						},
+						naga::ImageClass::External => anyhow::bail!("external textures aren't supported"),
+					}
+				}
+				(_, naga::TypeInner::AccelerationStructure { vertex_return }) => wgpu::BindingType::AccelerationStructure { vertex_return: *vertex_return },
+				(_, inner) => anyhow::bail!("can't derive a layout for binding {} of type {:?}", binding.binding, inner),
+			};
+			entries.push(wgpu::BindGroupLayoutEntry {
+				binding: binding.binding,
+				visibility,
+				ty,
+				count: None,
+			});
+		}
+		entries.sort_by_key(|entry| entry.binding);
+		Ok(entries)
+	}
+}
+
+fn check_size<T>(shader_size: u32, group: u32, binding: u32) -> anyhow::Result<()> {
+	let size = std::mem::size_of::<T>();
+	if size != shader_size as usize {
+		anyhow::bail!(
+			"{} is {} bytes but group {} binding {} is {} bytes in the shader",
+			std::any::type_name::<T>(), size, group, binding, shader_size,
+		);
+	}
+	Ok(())
+}
+
+// the formats the shaders write, add more as they're used
+fn storage_format(format: naga::StorageFormat) -> anyhow::Result<wgpu::TextureFormat> {
+	Ok(match format {
+		naga::StorageFormat::R32Float => wgpu::TextureFormat::R32Float,
+		naga::StorageFormat::R32Uint => wgpu::TextureFormat::R32Uint,
+		naga::StorageFormat::Rgba8Unorm => wgpu::TextureFormat::Rgba8Unorm,
+		naga::StorageFormat::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+		naga::StorageFormat::Rgba32Float => wgpu::TextureFormat::Rgba32Float,
+		format => anyhow::bail!("storage format {:?} isn't mapped", format),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{camera, capsule_shadow, environment, globals, light, model, shaders};
+
+	// the uniforms every material shader of the tier shares, checked against the first one
+	fn check_material_uniforms(shader_tier: shaders::ShaderTier) {
+		let [(_, shader), ..] = shader_tier.material_shaders();
+		let reflection = ShaderReflection::from_wgsl(shader.label, shader.source).unwrap();
+		reflection.check_binding::<model::SimpleMaterial>(0, 0).unwrap();
+		reflection.check_binding::<camera::CameraUniform>(2, 0).unwrap();
+		reflection.check_binding::<model::ModelUniform>(2, 1).unwrap();
+		reflection.check_trailing_array::<light::LightUniform>(2, 3).unwrap();
+		reflection.check_binding::<globals::GlobalsUniform>(2, 4).unwrap();
+		reflection.check_binding::<environment::EnvironmentUniform>(2, 5).unwrap();
+		reflection.check_binding::<capsule_shadow::CapsuleShadowUniform>(2, 6).unwrap();
+	}
+
+	#[test]
+	fn material_uniforms_match_the_shaders() {
+		check_material_uniforms(shaders::ShaderTier::Full);
+		check_material_uniforms(shaders::ShaderTier::Downlevel);
+	}
+}
@@ -0,0 +1,345 @@
+use wgpu::util::DeviceExt;
+
+use crate::{light, model, scene, texture};
+
+/*
+Screen space reflections for smooth opaque surfaces, reading the lit image, depth, and the
+normal/roughness target written by the main pass. On adapters with ray queries, surfaces
+below the ray traced roughness threshold trace whatever screen space misses against the
+scene TLAS, so off screen objects still show up in reflections.
+*/
+
+gpu_struct! {
+	Uniform
+	#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+	pub struct ReflectionParams {
+		pub max_roughness: f32, // rougher surfaces get no reflections
+		pub ray_traced_roughness: f32, // smoother surfaces trace screen space misses with ray queries
+		pub max_distance: f32, // screen space march distance
+		pub thickness: f32, // how far behind the depth buffer a ray still counts as a hit
+		pub trace_distance: f32, // ray query distance
+		pub steps: u32,
+		pub strength: f32,
+	}
+}
+
+impl ReflectionParams {
+	pub fn new() -> Self {
+		Self {
+			max_roughness: 0.6,
+			ray_traced_roughness: 0.3,
+			max_distance: 4.0,
+			thickness: 0.1,
+			trace_distance: 50.0,
+			steps: 48,
+			strength: 1.0,
+			..bytemuck::Zeroable::zeroed()
+		}
+	}
+}
+
+// resources for tracing reflections with ray queries
+struct RayTracedReflections {
+	layout: wgpu::BindGroupLayout,
+	bind_group: wgpu::BindGroup,
+	material_colors: wgpu::Buffer,
+}
+
+pub struct Reflections {
+	pub params: ReflectionParams,
+	params_buffer: wgpu::Buffer,
+	scene_color: texture::Texture, // copy of the lit image, since it can't be sampled while rendering to it
+	layout: wgpu::BindGroupLayout,
+	bind_group: wgpu::BindGroup,
+	ray_traced: Option<RayTracedReflections>,
+	pipeline: wgpu::RenderPipeline,
+}
+
+impl Reflections {
+	const MAX_MATERIALS: u64 = 256;
+
+	// tlas and light_buffer enable ray traced reflections
+	pub fn new(
+		device: &wgpu::Device,
+		config: &wgpu::SurfaceConfiguration,
+		depth_texture: &texture::Texture,
+		surface_texture: &texture::Texture,
+		camera_buffer: &wgpu::Buffer,
+		ray_tracing: Option<(&wgpu::Tlas, &wgpu::Buffer)>,
+	) -> Self {
+		let params = ReflectionParams::new();
+		let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Reflection Params Buffer"),
+			contents: bytemuck::cast_slice(&[params]),
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+		});
+		let scene_color = Self::create_scene_color(device, config);
+
+		let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Texture {
+				multisampled: false,
+				view_dimension: wgpu::TextureViewDimension::D2,
+				sample_type,
+			},
+			count: None,
+		};
+		let uniform_entry = |binding| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Buffer {
+				ty: wgpu::BufferBindingType::Uniform,
+				has_dynamic_offset: false,
+				min_binding_size: None,
+			},
+			count: None,
+		};
+		let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("reflections_bind_group_layout"),
+			entries: &[
+				texture_entry(0, wgpu::TextureSampleType::Depth), // depth
+				texture_entry(1, wgpu::TextureSampleType::Float { filterable: false }), // normal and roughness
+				texture_entry(2, wgpu::TextureSampleType::Float { filterable: true }), // scene color
+				wgpu::BindGroupLayoutEntry {
+					binding: 3,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+					count: None,
+				},
+				uniform_entry(4), // camera
+				uniform_entry(5), // params
+			],
+		});
+		let bind_group = Self::create_bind_group(device, &layout, depth_texture, surface_texture, &scene_color, camera_buffer, &params_buffer);
+
+		let ray_traced = ray_tracing.map(|(tlas, light_buffer)| {
+			let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+				label: Some("ray_traced_reflections_bind_group_layout"),
+				entries: &[
+					wgpu::BindGroupLayoutEntry {
+						binding: 0,
+						visibility: wgpu::ShaderStages::FRAGMENT,
+						ty: wgpu::BindingType::AccelerationStructure { vertex_return: false },
+						count: None,
+					},
+					wgpu::BindGroupLayoutEntry {
+						binding: 1,
+						visibility: wgpu::ShaderStages::FRAGMENT,
+						ty: wgpu::BindingType::Buffer {
+							ty: wgpu::BufferBindingType::Storage { read_only: true },
+							has_dynamic_offset: false,
+							min_binding_size: None,
+						},
+						count: None,
+					},
+					light::Lights::storage_entry(2), // lights
+				],
+			});
+			let material_colors = device.create_buffer(&wgpu::BufferDescriptor {
+				label: Some("Reflection Material Colors Buffer"),
+				size: Self::MAX_MATERIALS * std::mem::size_of::<[f32; 4]>() as u64,
+				usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+				mapped_at_creation: false,
+			});
+			let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+				label: Some("ray_traced_reflections_bind_group"),
+				layout: &layout,
+				entries: &[
+					wgpu::BindGroupEntry {
+						binding: 0,
+						resource: tlas.as_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 1,
+						resource: material_colors.as_entire_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 2,
+						resource: light_buffer.as_entire_binding(),
+					},
+				],
+			});
+
+			RayTracedReflections {
+				layout,
+				bind_group,
+				material_colors,
+			}
+		});
+
+		let shader = match ray_traced {
+			Some(_) => device.create_shader_module(wgpu::ShaderModuleDescriptor {
+				label: Some("Ray Traced Reflections Shader"),
+				source: wgpu::ShaderSource::Wgsl(concat!(include_str!("reflections_rt.wgsl"), include_str!("reflections.wgsl")).into()),
+			}),
+			None => device.create_shader_module(wgpu::ShaderModuleDescriptor {
+				label: Some("Reflections Shader"),
+				source: wgpu::ShaderSource::Wgsl(concat!(include_str!("reflections_fallback.wgsl"), include_str!("reflections.wgsl")).into()),
+			}),
+		};
+		let mut bind_group_layouts = vec![Some(&layout)];
+		if let Some(ray_traced) = &ray_traced {
+			bind_group_layouts.push(Some(&ray_traced.layout));
+		}
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Reflections Pipeline Layout"),
+			bind_group_layouts: &bind_group_layouts,
+			immediate_size: 0,
+		});
+		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("Reflections Pipeline"),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: Some("vs_main"),
+				buffers: &[],
+				compilation_options: Default::default(),
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: Some("fs_main"),
+				targets: &[Some(wgpu::ColorTargetState {
+					format: config.format,
+					// mixes the reflection in by its weight in alpha
+					blend: Some(wgpu::BlendState {
+						color: wgpu::BlendComponent {
+							src_factor: wgpu::BlendFactor::SrcAlpha,
+							dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+							operation: wgpu::BlendOperation::Add,
+						},
+						alpha: wgpu::BlendComponent {
+							src_factor: wgpu::BlendFactor::Zero,
+							dst_factor: wgpu::BlendFactor::One,
+							operation: wgpu::BlendOperation::Add,
+						},
+					}),
+					write_mask: wgpu::ColorWrites::ALL,
+				})],
+				compilation_options: Default::default(),
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			multiview_mask: None,
+			cache: None,
+		});
+
+		Self {
+			params,
+			params_buffer,
+			scene_color,
+			layout,
+			bind_group,
+			ray_traced,
+			pipeline,
+		}
+	}
+
+	fn create_scene_color(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) -> texture::Texture {
+		texture::Texture::create_render_target(device, config, config.format, wgpu::TextureUsages::COPY_DST, "reflection_scene_color")
+	}
+
+	fn create_bind_group(
+		device: &wgpu::Device,
+		layout: &wgpu::BindGroupLayout,
+		depth_texture: &texture::Texture,
+		surface_texture: &texture::Texture,
+		scene_color: &texture::Texture,
+		camera_buffer: &wgpu::Buffer,
+		params_buffer: &wgpu::Buffer,
+	) -> wgpu::BindGroup {
+		device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("reflections_bind_group"),
+			layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::TextureView(&surface_texture.view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: wgpu::BindingResource::TextureView(&scene_color.view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 3,
+					resource: wgpu::BindingResource::Sampler(&scene_color.sampler),
+				},
+				wgpu::BindGroupEntry {
+					binding: 4,
+					resource: camera_buffer.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 5,
+					resource: params_buffer.as_entire_binding(),
+				},
+			],
+		})
+	}
+
+	// the screen sized textures are recreated on resize
+	pub fn resize(
+		&mut self,
+		device: &wgpu::Device,
+		config: &wgpu::SurfaceConfiguration,
+		depth_texture: &texture::Texture,
+		surface_texture: &texture::Texture,
+		camera_buffer: &wgpu::Buffer,
+	) {
+		self.scene_color = Self::create_scene_color(device, config);
+		self.bind_group = Self::create_bind_group(device, &self.layout, depth_texture, surface_texture, &self.scene_color, camera_buffer, &self.params_buffer);
+	}
+
+	pub fn update(&self, queue: &wgpu::Queue, scene: &scene::Scene) {
+		queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[self.params]));
+
+		if let Some(ray_traced) = &self.ray_traced {
+			// textured materials are approximated with a light grey
+			let colors = scene.materials.iter()
+				.take(Self::MAX_MATERIALS as usize)
+				.map(|material| match &material.material_type {
+					model::MaterialType::SingleColorMaterial(color) => [color[0], color[1], color[2], 1.0],
+					_ => [0.8, 0.8, 0.8, 1.0],
+				})
+				.collect::<Vec<_>>();
+			if !colors.is_empty() {
+				queue.write_buffer(&ray_traced.material_colors, 0, bytemuck::cast_slice(&colors));
+			}
+		}
+	}
+
+	// frame is the texture the main pass rendered to
+	pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, frame: &wgpu::Texture, view: &wgpu::TextureView) {
+		if self.params.strength <= 0.0 {
+			return;
+		}
+		encoder.copy_texture_to_texture(frame.as_image_copy(), self.scene_color.texture.as_image_copy(), frame.size());
+
+		let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Reflections Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: wgpu::StoreOp::Store,
+				},
+				depth_slice: None,
+			})],
+			depth_stencil_attachment: None,
+			occlusion_query_set: None,
+			timestamp_writes: None,
+			multiview_mask: None,
+		});
+		render_pass.set_pipeline(&self.pipeline);
+		render_pass.set_bind_group(0, &self.bind_group, &[]);
+		if let Some(ray_traced) = &self.ray_traced {
+			render_pass.set_bind_group(1, &ray_traced.bind_group, &[]);
+		}
+		render_pass.draw(0..3, 0..1);
+	}
+}
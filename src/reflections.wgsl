@@ -0,0 +1,98 @@
+// Screen space reflections, with off screen misses handed to trace_offscreen from the
+// file this one is appended to
+
+struct Camera {
+	view: mat4x4<f32>,
+	proj: mat4x4<f32>,
+	view_proj: mat4x4<f32>,
+	inv_view: mat4x4<f32>,
+	inv_proj: mat4x4<f32>,
+	inv_view_proj: mat4x4<f32>,
+	position: vec4<f32>,
+};
+
+struct ReflectionParams {
+	max_roughness: f32, // rougher surfaces get no reflections
+	ray_traced_roughness: f32, // smoother surfaces trace screen space misses with ray queries
+	max_distance: f32, // screen space march distance
+	thickness: f32, // how far behind the depth buffer a ray still counts as a hit
+	trace_distance: f32, // ray query distance
+	steps: u32,
+	strength: f32,
+};
+
+@group(0) @binding(0)
+var depth_texture: texture_depth_2d;
+@group(0) @binding(1)
+var surface_texture: texture_2d<f32>;
+@group(0) @binding(2)
+var scene_color: texture_2d<f32>;
+@group(0) @binding(3)
+var scene_sampler: sampler;
+@group(0) @binding(4)
+var<uniform> camera: Camera;
+@group(0) @binding(5)
+var<uniform> params: ReflectionParams;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+	// one triangle covering the screen
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn world_position(uv: vec2<f32>, depth: f32) -> vec3<f32> {
+	let ndc = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+	let world = camera.inv_view_proj * ndc;
+	return world.xyz / world.w;
+}
+
+@fragment
+fn fs_main(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
+	let pixel = vec2<i32>(frag_coord.xy);
+	let depth = textureLoad(depth_texture, pixel, 0);
+	let surface = textureLoad(surface_texture, pixel, 0);
+	let roughness = surface.w;
+	// transparent surfaces and the background don't write a normal
+	if depth >= 1.0 || roughness > params.max_roughness || all(surface.xyz == vec3<f32>(0.0)) {
+		return vec4<f32>(0.0);
+	}
+
+	let size = vec2<f32>(textureDimensions(depth_texture));
+	let position = world_position(frag_coord.xy / size, depth);
+	let normal = normalize(surface.xyz * 2.0 - 1.0);
+	let view_dir = normalize(position - camera.position.xyz);
+	let dir = reflect(view_dir, normal);
+
+	let fresnel = 0.04 + 0.96 * pow(1.0 - max(dot(-view_dir, normal), 0.0), 5.0);
+	let weight = params.strength * fresnel * (1.0 - roughness / max(params.max_roughness, 0.0001));
+
+	// march in world space and compare distances against the depth buffer
+	let step = params.max_distance / f32(max(params.steps, 1u));
+	for (var i = 1u; i <= params.steps; i++) {
+		let p = position + dir * step * f32(i);
+		let clip = camera.view_proj * vec4<f32>(p, 1.0);
+		if clip.w <= 0.0 {
+			break;
+		}
+		let ndc = clip.xyz / clip.w;
+		let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+		if any(uv < vec2<f32>(0.0)) || any(uv >= vec2<f32>(1.0)) {
+			break; // left the screen
+		}
+
+		let scene_depth = textureLoad(depth_texture, vec2<i32>(uv * size), 0);
+		let scene_dist = distance(camera.position.xyz, world_position(uv, scene_depth));
+		let ray_dist = distance(camera.position.xyz, p);
+		if ray_dist > scene_dist && ray_dist - scene_dist < params.thickness {
+			let color = textureSampleLevel(scene_color, scene_sampler, uv, 0.0).xyz;
+			return vec4<f32>(color, weight);
+		}
+	}
+
+	if roughness <= params.ray_traced_roughness {
+		let hit = trace_offscreen(position + normal * 0.002, dir);
+		return vec4<f32>(hit.xyz, weight * hit.w);
+	}
+	return vec4<f32>(0.0);
+}
@@ -0,0 +1,4 @@
+// Without ray queries, off screen reflections are left to the cubemap in the material shaders
+fn trace_offscreen(origin: vec3<f32>, dir: vec3<f32>) -> vec4<f32> {
+	return vec4<f32>(0.0);
+}
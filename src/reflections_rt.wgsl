@@ -0,0 +1,51 @@
+enable wgpu_ray_query;
+
+// Traces screen space misses against the scene's TLAS
+
+struct Light {
+	position: vec3<f32>,
+	intensity: f32,
+	color: vec3<f32>,
+	range: f32,
+	direction: vec3<f32>,
+	kind: u32, // 0 point, 1 directional, 2 spot
+	falloff: u32,
+	shadow_index: i32,
+	spot_cos: vec2<f32>,
+	view_proj: mat4x4<f32>,
+};
+struct Lights {
+	count: u32,
+	lights: array<Light>,
+};
+
+@group(1) @binding(0)
+var scene_tlas: acceleration_structure;
+@group(1) @binding(1)
+var<storage, read> material_colors: array<vec4<f32>>; // indexed by the instance custom data
+@group(1) @binding(2)
+var<storage, read> lights: Lights; // hits are lit by the first light
+
+// returns the hit color in xyz and whether anything was hit in w
+fn trace_offscreen(origin: vec3<f32>, dir: vec3<f32>) -> vec4<f32> {
+	var rq: ray_query;
+	rayQueryInitialize(&rq, scene_tlas, RayDesc(RAY_FLAG_NONE, 0x1u, 0.0, params.trace_distance, origin, dir));
+	while rayQueryProceed(&rq) {}
+	let hit = rayQueryGetCommittedIntersection(&rq);
+	if hit.kind == RAY_QUERY_INTERSECTION_NONE {
+		return vec4<f32>(0.0);
+	}
+
+	// hits have no normal, so they're lit as if facing back along the ray
+	let hit_pos = origin + dir * hit.t;
+	var light_color = vec3<f32>(0.0);
+	var light_dir = -dir;
+	if lights.count > 0u {
+		let light = lights.lights[0];
+		light_color = light.color;
+		light_dir = select(normalize(light.position - hit_pos), -normalize(light.direction), light.kind == 1u);
+	}
+	let diffuse = 0.1 + 0.9 * max(dot(-dir, light_dir), 0.0);
+	let color = material_colors[hit.instance_custom_data].xyz * light_color * diffuse;
+	return vec4<f32>(color, 1.0);
+}
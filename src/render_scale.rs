@@ -0,0 +1,251 @@
+use wgpu::util::DeviceExt;
+
+use crate::{color, environment, texture};
+
+gpu_struct! {
+	Uniform
+	#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+	struct OutputParams {
+		transfer: u32, // OutputTransfer::index
+		surface_srgb: u32, // the surface encodes to sRGB itself, so the shader undoes it
+		exposure: f32,
+		tonemap: u32, // Tonemap::index
+	}
+}
+
+// the scene at the internal resolution
+struct Target {
+	color: texture::Texture,
+	bind_group: wgpu::BindGroup,
+}
+
+/*
+The output stage. The scene and its post passes render into an internal target, in floating
+point when the adapter can blend into it, which is exposed, tonemapped, and encoded with the
+output transfer on its way to the surface before the overlay stage. The 3D stages can render
+at a fraction of the surface resolution, the target is then upscaled, so fades, text, and UI
+stay at native resolution whatever the scale. Post effects that work on linear light go
+between the scene and upscale.
+*/
+pub struct RenderScale {
+	scale: f32,
+	transfer: color::OutputTransfer,
+	format: wgpu::TextureFormat, // of the internal target
+	exposure: f32,
+	tonemap: environment::Tonemap,
+	params_buffer: wgpu::Buffer,
+	sampler: wgpu::Sampler,
+	layout: wgpu::BindGroupLayout,
+	pipeline: wgpu::RenderPipeline,
+	target: Option<Target>, // None until the first resize
+}
+
+impl RenderScale {
+	pub const MIN_SCALE: f32 = 0.25;
+
+	// without hdr the scene renders in the surface format, and the material shaders tonemap
+	pub fn new(device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, hdr: bool) -> Self {
+		let transfer = color::OutputTransfer::Srgb;
+		let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Output Params Buffer"),
+			contents: bytemuck::cast_slice(&[OutputParams {
+				transfer: transfer.index(),
+				surface_srgb: config.format.is_srgb() as u32,
+				exposure: 1.0,
+				tonemap: environment::Tonemap::None.index(),
+				..bytemuck::Zeroable::zeroed()
+			}]),
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+		});
+		let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+			label: Some("render_scale_sampler"),
+			mag_filter: wgpu::FilterMode::Linear,
+			min_filter: wgpu::FilterMode::Linear,
+			..Default::default()
+		});
+		let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("render_scale_bind_group_layout"),
+			entries: &[
+				wgpu::BindGroupLayoutEntry {
+					binding: 0,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Texture {
+						multisampled: false,
+						view_dimension: wgpu::TextureViewDimension::D2,
+						sample_type: wgpu::TextureSampleType::Float { filterable: true },
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry {
+					binding: 1,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry {
+					binding: 2,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+			],
+		});
+
+		let shader = device.create_shader_module(wgpu::include_wgsl!("render_scale.wgsl"));
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Render Scale Pipeline Layout"),
+			bind_group_layouts: &[Some(&layout)],
+			immediate_size: 0,
+		});
+		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("Render Scale Pipeline"),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: Some("vs_main"),
+				buffers: &[],
+				compilation_options: Default::default(),
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: Some("fs_main"),
+				targets: &[Some(wgpu::ColorTargetState {
+					format: config.format,
+					blend: None,
+					write_mask: wgpu::ColorWrites::ALL,
+				})],
+				compilation_options: Default::default(),
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			multiview_mask: None,
+			cache: None,
+		});
+
+		Self {
+			scale: 1.0,
+			transfer,
+			format: if hdr { texture::Texture::HDR_FORMAT } else { config.format },
+			exposure: 1.0,
+			tonemap: environment::Tonemap::None,
+			params_buffer,
+			sampler,
+			layout,
+			pipeline,
+			target: None,
+		}
+	}
+
+	pub fn scale(&self) -> f32 {
+		self.scale
+	}
+
+	// takes effect at the next resize
+	pub fn set_scale(&mut self, scale: f32) {
+		self.scale = scale.clamp(Self::MIN_SCALE, 1.0);
+	}
+
+	pub fn output_transfer(&self) -> color::OutputTransfer {
+		self.transfer
+	}
+
+	pub fn set_output_transfer(&mut self, transfer: color::OutputTransfer) {
+		self.transfer = transfer;
+	}
+
+	// whether the scene is linear light over 1, and tonemapped here rather than by the material shaders
+	pub fn is_hdr(&self) -> bool {
+		self.format == texture::Texture::HDR_FORMAT
+	}
+
+	// the exposure and curve the output stage grades the scene with, every frame when hdr
+	pub fn set_grading(&mut self, exposure: f32, tonemap: environment::Tonemap) {
+		self.exposure = exposure;
+		self.tonemap = tonemap;
+	}
+
+	// the config the 3D stages render with, at the internal resolution and format
+	pub fn internal_config(&self, config: &wgpu::SurfaceConfiguration) -> wgpu::SurfaceConfiguration {
+		let mut internal = config.clone();
+		internal.format = self.format;
+		internal.width = ((config.width as f32 * self.scale).round() as u32).max(1);
+		internal.height = ((config.height as f32 * self.scale).round() as u32).max(1);
+		internal
+	}
+
+	pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration) {
+		// copied from by the reflections
+		let color = texture::Texture::create_render_target(
+			device,
+			&self.internal_config(config),
+			self.format,
+			wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+			"render_scale_texture",
+		);
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("render_scale_bind_group"),
+			layout: &self.layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&color.view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::Sampler(&self.sampler),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: self.params_buffer.as_entire_binding(),
+				},
+			],
+		});
+		self.target = Some(Target {
+			color,
+			bind_group,
+		});
+	}
+
+	// the texture the 3D stages render into
+	pub fn scene_target(&self) -> &texture::Texture {
+		&self.target.as_ref().expect("render scale resized with the surface").color
+	}
+
+	// grades and encodes the internal target onto the surface, stretching it over the surface
+	pub fn upscale(&self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, surface_format: wgpu::TextureFormat) {
+		let Some(target) = &self.target else {
+			return;
+		};
+		queue.write_buffer(&self.params_buffer, 0, bytemuck::cast_slice(&[OutputParams {
+			transfer: self.transfer.index(),
+			surface_srgb: surface_format.is_srgb() as u32,
+			exposure: self.exposure,
+			tonemap: self.tonemap.index(),
+			..bytemuck::Zeroable::zeroed()
+		}]));
+		let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Upscale Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+					store: wgpu::StoreOp::Store,
+				},
+				depth_slice: None,
+			})],
+			depth_stencil_attachment: None,
+			occlusion_query_set: None,
+			timestamp_writes: None,
+			multiview_mask: None,
+		});
+		render_pass.set_pipeline(&self.pipeline);
+		render_pass.set_bind_group(0, &target.bind_group, &[]);
+		render_pass.draw(0..3, 0..1);
+	}
+}
@@ -0,0 +1,77 @@
+// Stretches the scene rendered at the internal resolution over the surface, exposing and
+// tonemapping the linear colors, then encoding them with the output transfer
+
+struct OutputParams {
+	transfer: u32, // 0 sRGB, 1 gamma 2.2, 2 linear
+	surface_srgb: u32, // the surface format applies the sRGB curve to whatever is written
+	exposure: f32,
+	tonemap: u32, // 0 none, 1 Reinhard, 2 ACES
+};
+
+@group(0) @binding(0)
+var scene_texture: texture_2d<f32>;
+@group(0) @binding(1)
+var linear_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> output: OutputParams;
+
+struct VertexOutput {
+	@builtin(position) position: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	// one triangle covering the screen
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+	out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+	return out;
+}
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+	return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+	return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+// same curves as apply_tonemap in mesh.wgsl
+fn apply_tonemap(color: vec3<f32>) -> vec3<f32> {
+	switch output.tonemap {
+		case 1u: {
+			return color / (color + 1.0);
+		}
+		case 2u: {
+			return (color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14);
+		}
+		default: {
+			return color;
+		}
+	}
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	let color = textureSampleLevel(scene_texture, linear_sampler, in.uv, 0.0);
+	let linear = clamp(apply_tonemap(max(color.rgb, vec3<f32>(0.0)) * output.exposure), vec3<f32>(0.0), vec3<f32>(1.0));
+	var encoded: vec3<f32>;
+	switch output.transfer {
+		case 0u: {
+			encoded = linear_to_srgb(linear);
+		}
+		case 1u: {
+			encoded = pow(linear, vec3<f32>(1.0 / 2.2));
+		}
+		default: {
+			encoded = linear;
+		}
+	}
+	// an sRGB surface will encode again, so hand it the value that encodes to ours
+	if output.surface_srgb != 0u {
+		encoded = srgb_to_linear(encoded);
+	}
+	return vec4<f32>(encoded, color.a);
+}
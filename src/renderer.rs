@@ -1,6 +1,14 @@
-use crate::{camera, light, model::{self, Vertex, DrawModel}, scene, texture, resources};
+use crate::{assets, bind_group, camera, environment, globals, light, model::{self, Vertex, DrawModel}, scene, texture, resources, uniform_arena, frame, fade, profiler, readback, skinning, crowd, ray_tracing, reflections, meshlet, imposter, capabilities, shaders, point_shadow, shadow_map, contact_shadow, ibl, capsule_shadow, gtao, post, interlace, render_scale, color, virtual_camera, skybox, debug_draw, instancing, vat, view_mode, frame_graph, particles, deferred};
+#[cfg(feature = "inspector")]
+use crate::inspector;
+#[cfg(feature = "text")]
+use crate::text;
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+use crate::shader_reload;
+use cgmath::{EuclideanSpace, MetricSpace};
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use cgmath::SquareMatrix;
 use winit::window::Window;
 use wgpu::util::DeviceExt;
 
@@ -11,73 +19,233 @@ pub struct Renderer {
 	pub queue: wgpu::Queue,
 	config: wgpu::SurfaceConfiguration,
 
-	pub texture_bind_group_layouts: [wgpu::BindGroupLayout; 2],
+	pub texture_bind_group_layouts: [wgpu::BindGroupLayout; model::MaterialType::COUNT],
+	pub assets: assets::Assets, // the textures materials use
 
-	cubemap_bind_group: wgpu::BindGroup,
+	environment_map: texture::Texture, // lights scenes without a skybox
+	ibl: ibl::Ibl, // the ambient and reflected light of the scene's environment
+	skybox: skybox::SkyboxPass, // the scene's skybox, under every view
 
 	// uniform buffers
+	uniform_bind_group_layout: wgpu::BindGroupLayout,
 	uniform_bind_group: wgpu::BindGroup,
 	// vertex
 	// TODO: maybe add instance buffer
 	camera_buffer: wgpu::Buffer,
-	model_buffer: wgpu::Buffer, // TODO: change to each model instance containing its own buffer, then bind each one accordingly
+	// per-object and per-material uniforms, bound with dynamic offsets
+	pub uniform_arena: uniform_arena::UniformArena,
+	frames: frame::FrameRing,
+	pub skinning: Option<skinning::SkinningPipeline>, // None when compute shaders aren't supported
+	pub vat: vat::VatAtlas,
+	pub crowds: Option<crowd::CrowdPipeline>, // None without compute shaders or enough storage buffers
+	pub ray_tracing: Option<ray_tracing::RayTracing>, // None when ray queries aren't supported
+	pub reflections: Option<reflections::Reflections>, // None when the surface can't be copied from
+	pub capabilities: capabilities::Capabilities,
+	pub features: capabilities::RendererFeatures, // what was negotiated, and what fell back
+	pub shader_tier: shaders::ShaderTier,
+	pub meshlets: Option<meshlet::MeshletPipeline>, // None when compute or indirect draws aren't supported
+	pub point_shadows: Option<point_shadow::PointShadows>, // None in the downlevel shader tier
+	pub shadow_map: Option<shadow_map::ShadowMap>, // the downlevel tier's shadows instead
+	pub contact_shadows: Option<contact_shadow::ContactShadows>, // blended with the point shadows, so None without them
+	pub gtao: gtao::Gtao,
+	deferred: Option<deferred::Deferred>, // the G-buffer and lighting pass, None on the forward render path
+	pub post: post::PostSettings,
+	pub interlace: interlace::Interlace, // used when post.interlaced is set
+	imposters: imposter::ImposterPipeline,
+	pub virtual_cameras: virtual_camera::VirtualCameras,
+	render_scale: render_scale::RenderScale, // changed with set_render_scale and set_output_transfer
+	pub test_pattern: color::TestPatternPass, // drawn instead of the scene when a pattern is set
+	pub fade: fade::FadePass, // scene transitions and letterboxing
+	pub debug_draw: debug_draw::DebugDraw, // gizmos and debug lines, queued each frame
+	particles: particles::ParticleSystem, // the scene's emitters, simulated on the CPU without compute shaders
+	pub profiler: profiler::GpuProfiler, // timings of each stage of the frame, see frame_stats
+	last_render_start: Option<web_time::Instant>, // the frame time is measured between renders, not from the scaled scene time
+	#[cfg(feature = "text")]
+	pub text: Option<text::TextRenderer>, // None when the font didn't load
+	#[cfg(feature = "text")]
+	pub show_fps: bool,
+	#[cfg(feature = "inspector")]
+	pub inspector: Option<inspector::Inspector>, // None when made from raw window handles
+
+	// last values written to the buffers, so unchanged uniforms aren't uploaded again
+	uploaded_camera: Cell<camera::CameraUniform>,
+	uploaded_environment: Cell<environment::EnvironmentUniform>,
+	uploaded_capsules: Cell<capsule_shadow::CapsuleShadowUniform>,
+	uploaded_light_bounds: Option<model::Aabb>, // the scene bounds the directional shadow was fit to
 
 	// fragment
-	simple_material_buffer: wgpu::Buffer,
-	light_buffer: wgpu::Buffer,
-	camera_pos_buffer: wgpu::Buffer,
+	lights: light::Lights,
+	globals_buffer: wgpu::Buffer,
+	environment_buffer: wgpu::Buffer,
+	capsule_buffer: wgpu::Buffer,
 
 	// rendering
 	depth_texture: texture::Texture,
-	render_pipeline: wgpu::RenderPipeline,
+	surface_texture: texture::Texture,
+	material_layouts: Vec<wgpu::PipelineLayout>, // by material type
+	render_pipelines: HashMap<model::PipelineKey, wgpu::RenderPipeline>,
+	debug_pipelines: HashMap<(view_mode::ViewMode, usize), wgpu::RenderPipeline>, // by view mode and material type, opaque and double sided
+	#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+	shader_reload: Option<shader_reload::ShaderReload>, // None when the source tree can't be watched
+	pub view_mode: view_mode::ViewMode,
+}
+
+/*
+Chosen when the renderer is created. The present mode can be changed afterwards with
+set_present_mode, a mode the surface doesn't have falls back to Fifo. Fewer frames of latency
+make input feel more responsive, at the cost of the CPU waiting on the GPU sooner.
+*/
+#[derive(Copy, Clone, Debug)]
+pub struct RendererOptions {
+	pub render_path: deferred::RenderPath,
+	pub present_mode: wgpu::PresentMode,
+	pub desired_frame_latency: u32, // frames queued ahead of the display, and in flight on the GPU, 1 to 3
+	pub power_preference: wgpu::PowerPreference,
+	pub uniform_arena_size: u64, // bytes of per-object and per-material uniforms each frame to start with, grows when a scene runs out
+}
+
+// why a frame wasn't drawn, Outdated and Lost are fixed by configuring the surface again
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SurfaceError {
+	Outdated,
+	Lost,
+	Validation,
+}
+
+impl std::fmt::Display for SurfaceError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		match self {
+			SurfaceError::Outdated => write!(f, "the surface is outdated"),
+			SurfaceError::Lost => write!(f, "the surface was lost"),
+			SurfaceError::Validation => write!(f, "acquiring the surface texture failed validation"),
+		}
+	}
+}
+
+impl Default for RendererOptions {
+	fn default() -> Self {
+		Self {
+			render_path: deferred::RenderPath::Forward,
+			present_mode: wgpu::PresentMode::AutoVsync,
+			desired_frame_latency: 2,
+			power_preference: wgpu::PowerPreference::default(),
+			uniform_arena_size: Renderer::UNIFORM_ARENA_SIZE,
+		}
+	}
 }
 
 impl Renderer {
-	pub async fn new(window: &Arc<Window>) -> anyhow::Result<Self> {
-		let size = window.inner_size();
+	const MAX_FRAMES_IN_FLIGHT: u32 = 3;
+	pub const SURFACE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm; // normal and roughness
+	const VIRTUAL_CAMERA_SIZE: u32 = 256; // pixels per side of each virtual camera's cell
+	const UNIFORM_ARENA_SIZE: u64 = 1 << 20; // per frame in flight
 
-		let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+	pub fn create_instance() -> wgpu::Instance {
+		wgpu::Instance::new(wgpu::InstanceDescriptor {
 			#[cfg(not(target_arch = "wasm32"))]
 			backends: wgpu::Backends::PRIMARY,
 			#[cfg(target_arch = "wasm32")]
 			backends: wgpu::Backends::GL,
-			..Default::default()
-		});
-
-		let surface = instance.create_surface(window.clone()).unwrap();
+			..wgpu::InstanceDescriptor::new_without_display_handle()
+		})
+	}
 
-		let adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
-			power_preference: wgpu::PowerPreference::default(),
-			compatible_surface: Some(&surface),
+	pub async fn request_adapter(instance: &wgpu::Instance, surface: &wgpu::Surface<'_>, power_preference: wgpu::PowerPreference) -> anyhow::Result<wgpu::Adapter> {
+		Ok(instance.request_adapter(&wgpu::RequestAdapterOptions {
+			power_preference,
+			compatible_surface: Some(surface),
 			force_fallback_adapter: false,
-		}).await?;
+		}).await?)
+	}
+
+	pub async fn new(window: &Arc<Window>, options: RendererOptions) -> anyhow::Result<Self> {
+		let size = window.inner_size();
+		let instance = Self::create_instance();
+		let surface = instance.create_surface(window.clone())?;
+		#[allow(unused_mut)]
+		let mut renderer = Self::from_surface(instance, surface, [size.width, size.height], options).await?;
+		#[cfg(feature = "inspector")]
+		{
+			renderer.inspector = Some(inspector::Inspector::new(window, &renderer.device, renderer.config.format));
+		}
+		Ok(renderer)
+	}
+
+	/// Draws into a window made by another windowing framework, like SDL, Qt, or Tauri, instead of
+	/// winit. The caller drives it, update_size once the window has a size and on every resize, then
+	/// render each frame. There's no inspector, its input comes through winit.
+	///
+	/// # Safety
+	/// The handles have to be valid, and stay valid until the renderer is dropped.
+	pub async unsafe fn from_raw_handle(
+		window_handle: wgpu::rwh::RawWindowHandle,
+		display_handle: wgpu::rwh::RawDisplayHandle,
+		size: [u32; 2],
+		options: RendererOptions,
+	) -> anyhow::Result<Self> {
+		let instance = Self::create_instance();
+		// SAFETY: the caller keeps the window and display alive for as long as the renderer
+		let surface = unsafe {
+			instance.create_surface_unsafe(wgpu::SurfaceTargetUnsafe::RawHandle {
+				raw_display_handle: Some(display_handle),
+				raw_window_handle: window_handle,
+			})
+		}?;
+		Self::from_surface(instance, surface, size, options).await
+	}
+
+	async fn from_surface(instance: wgpu::Instance, surface: wgpu::Surface<'static>, size: [u32; 2], options: RendererOptions) -> anyhow::Result<Self> {
+		let adapter = Self::request_adapter(&instance, &surface, options.power_preference).await?;
+
+		let capabilities = capabilities::Capabilities::new(&adapter, &surface);
+		log::info!("{}", capabilities);
+
+		let features = capabilities::RendererFeatures::negotiate(&capabilities::DesiredFeatures::default(), &capabilities);
+		for fallback in &features.fallbacks {
+			log::warn!("Falling back: {}", fallback);
+		}
 
 		let (device, queue) = adapter.request_device(&wgpu::DeviceDescriptor {
 			label: None,
-			required_features: wgpu::Features::empty(),
-			experimental_features: wgpu::ExperimentalFeatures::disabled(),
-			required_limits: if cfg!(target_arch = "wasm32") {
-				wgpu::Limits::downlevel_webgl2_defaults()
+			required_features: features.required_features(),
+			// ray queries are still experimental in wgpu, so they're only enabled when negotiated
+			experimental_features: if features.ray_queries {
+				// SAFETY: only ray queries are enabled, and they're only used by the optional ray tracing pass
+				unsafe { wgpu::ExperimentalFeatures::enabled() }
 			} else {
-				wgpu::Limits::default()
+				wgpu::ExperimentalFeatures::disabled()
 			},
+			required_limits: features.limits.clone(),
 			memory_hints: Default::default(),
 			trace: wgpu::Trace::Off,
 		}).await?;
 
 		let surface_caps = surface.get_capabilities(&adapter);
 
-		let surface_format = surface_caps.formats.iter().find(|f| f.is_srgb()).copied().unwrap_or(surface_caps.formats[0]);
+		let shader_tier = shaders::ShaderTier::select(&capabilities, &features);
+		log::info!("Using {:?} shaders", shader_tier);
+
+		// reflections read back the lit image, look up material colors from a storage buffer, and need the surface target
+		let reflections_supported = features.screen_copies && features.fragment_storage_buffers && shader_tier == shaders::ShaderTier::Full;
+		// the uniform arena and the per-frame buffers are in a ring as long as the latency the surface allows
+		let frames_in_flight = options.desired_frame_latency.clamp(1, Self::MAX_FRAMES_IN_FLIGHT) as usize;
 		let config = wgpu::SurfaceConfiguration {
-			usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-			format: surface_format,
-			width: size.width,
-			height: size.height,
-			present_mode: surface_caps.present_modes[0],
+			usage: if reflections_supported {
+				wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC
+			} else {
+				wgpu::TextureUsages::RENDER_ATTACHMENT
+			},
+			format: capabilities.preferred_surface_format(),
+			width: size[0],
+			height: size[1],
+			present_mode: capabilities.supported_present_mode(options.present_mode),
 			alpha_mode: surface_caps.alpha_modes[0],
 			view_formats: vec![],
-			desired_maximum_frame_latency: 2,
+			desired_maximum_frame_latency: frames_in_flight as u32,
 		};
+		// the 3D stages render into the output stage's target, in its format
+		let render_scale = render_scale::RenderScale::new(&device, &config, features.hdr_targets);
+		let scene_config = render_scale.internal_config(&config);
 
 		// create bind group & layouts for
 		// - texture bind group for each material type
@@ -90,182 +258,173 @@ impl Renderer {
 			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
 		});
 
-		let model_uniform: [[f32; 4]; 4] = cgmath::Matrix4::<f32>::identity().into();
-		let model_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-			label: Some("Model Buffer"),
-			contents: bytemuck::cast_slice(&[model_uniform]),
-			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-		});
+		let uniform_arena = uniform_arena::UniformArena::new(&device, options.uniform_arena_size, frames_in_flight, "Uniform Arena");
+		let frames = frame::FrameRing::new(frames_in_flight);
+		let skinning = features.compute
+			.then(|| skinning::SkinningPipeline::new(&device));
+		let crowds = features.compute
+			.then(|| crowd::CrowdPipeline::new(&device))
+			.flatten();
+
+		let lights = light::Lights::new(&device, shader_tier == shaders::ShaderTier::Full, features.fragment_storage_buffers);
+		let light_buffer = &lights.buffer;
 
-		let simple_material_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-			label: Some("Simple Material Buffer"),
-			contents: bytemuck::cast_slice(&[model::SimpleMaterial::new()]),
+		let globals_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Globals Buffer"),
+			contents: bytemuck::cast_slice(&[globals::GlobalsUniform::new()]),
 			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
 		});
 
-		let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-			label: Some("Light Buffer"),
-			contents: bytemuck::cast_slice(&[light::LightUniform::new()]),
+		let environment_uniform = environment::Environment::default().to_uniform(&camera::ViewSettings::default());
+		let environment_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Environment Buffer"),
+			contents: bytemuck::cast_slice(&[environment_uniform]),
 			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
 		});
 
-		let camera_pos: [f32; 4] = [0.0, 0.0, 0.0, 0.0];
-		let camera_pos_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-			label: Some("Camera Pos Buffer"),
-			contents: bytemuck::cast_slice(&[camera_pos]),
+		let capsule_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Capsule Shadow Buffer"),
+			contents: bytemuck::cast_slice(&[capsule_shadow::CapsuleShadowUniform::new()]),
 			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
 		});
 
-		let uniform_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-			entries: &[
-				wgpu::BindGroupLayoutEntry { // camera uniform
-					binding: 0,
-					visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-					ty: wgpu::BindingType::Buffer {
-						ty: wgpu::BufferBindingType::Uniform,
-						has_dynamic_offset: false,
-						min_binding_size: None,
-					},
-					count: None,
-				},
-				wgpu::BindGroupLayoutEntry { // model uniform
-					binding: 1,
-					visibility: wgpu::ShaderStages::VERTEX,
-					ty: wgpu::BindingType::Buffer {
-						ty: wgpu::BufferBindingType::Uniform,
-						has_dynamic_offset: false,
-						min_binding_size: None,
-					},
-					count: None,
-				},
-				wgpu::BindGroupLayoutEntry { // material uniform
-					binding: 2,
-					visibility: wgpu::ShaderStages::FRAGMENT,
-					ty: wgpu::BindingType::Buffer {
-						ty: wgpu::BufferBindingType::Uniform,
-						has_dynamic_offset: false,
-						min_binding_size: None,
-					},
-					count: None,
-				},
-				wgpu::BindGroupLayoutEntry { // light uniform
-					binding: 3,
-					visibility: wgpu::ShaderStages::FRAGMENT,
-					ty: wgpu::BindingType::Buffer {
-						ty: wgpu::BufferBindingType::Uniform,
-						has_dynamic_offset: false,
-						min_binding_size: None,
-					},
-					count: None,
-				},
-				wgpu::BindGroupLayoutEntry { // camera pos uniform
-					binding: 4,
-					visibility: wgpu::ShaderStages::FRAGMENT,
-					ty: wgpu::BindingType::Buffer {
-						ty: wgpu::BufferBindingType::Uniform,
-						has_dynamic_offset: false,
-						min_binding_size: None,
-					},
-					count: None,
-				},
-			],
-			label: Some("camera_model_bind_group_layout"),
-		});
-		let uniform_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-			layout: &uniform_bind_group_layout,
-			entries: &[
-				wgpu::BindGroupEntry {
-					binding: 0,
-					resource: camera_buffer.as_entire_binding(),
-				},
-				wgpu::BindGroupEntry {
-					binding: 1,
-					resource: model_buffer.as_entire_binding(),
-				},
-				wgpu::BindGroupEntry {
-					binding: 2,
-					resource: simple_material_buffer.as_entire_binding(),
-				},
-				wgpu::BindGroupEntry {
-					binding: 3,
-					resource: light_buffer.as_entire_binding(),
-				},
-				wgpu::BindGroupEntry {
-					binding: 4,
-					resource: camera_pos_buffer.as_entire_binding(),
-				},
-			],
-			label: Some("camera_bind_group"),
-		});
+		let vat = vat::VatAtlas::new(&device);
 
-		let depth_texture = texture::Texture::create_depth_texture(&device, &config, "depth_texture");
-
-		let cubemap_texture = resources::load_cubemap_texture("skybox", &device, &queue).await.unwrap();
-		let cubemap_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-			entries: &[
-				wgpu::BindGroupLayoutEntry {
-					binding: 0,
-					visibility: wgpu::ShaderStages::FRAGMENT,
-					ty: wgpu::BindingType::Texture {
-						multisampled: false,
-						view_dimension: wgpu::TextureViewDimension::Cube,
-						sample_type: wgpu::TextureSampleType::Float {filterable: true},
-					},
-					count: None,
-				},
-				wgpu::BindGroupLayoutEntry {
-					binding: 1,
-					visibility: wgpu::ShaderStages::FRAGMENT,
-					ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-					count: None,
-				},
-			],
-			label: Some("cubemap_bind_group_layout"),
-		});
-		let cubemap_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-			layout: &cubemap_bind_group_layout,
-			entries: &[
-				wgpu::BindGroupEntry {
-					binding: 0,
-					resource: wgpu::BindingResource::TextureView(&cubemap_texture.view),
-				},
-				wgpu::BindGroupEntry {
-					binding: 1,
-					resource: wgpu::BindingResource::Sampler(&cubemap_texture.sampler),
-				},
-			],
-			label: Some("cubemap_bind_group"),
-		});
+		let uniform_bind_group_layout = bind_group::LayoutBuilder::new(wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT)
+			.uniform(0) // camera
+			.dynamic_uniform::<model::ModelUniform>(1, model::ModelUniform::MAX_INSTANCES) // one per instance
+			.visibility(wgpu::ShaderStages::FRAGMENT)
+			.buffer(3, lights.binding_type())
+			.visibility(wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT)
+			.uniform(4) // globals
+			.uniform(5) // environment, the vertex stage reads the wind
+			.visibility(wgpu::ShaderStages::FRAGMENT)
+			.uniform(6) // capsule shadows
+			.visibility(wgpu::ShaderStages::VERTEX)
+			.texture_of(7, wgpu::TextureSampleType::Float { filterable: false }, wgpu::TextureViewDimension::D2) // vertex animation texture
+			.build(&device, "camera_model_bind_group_layout");
+		let shared = SharedUniforms {
+			uniform_arena: &uniform_arena,
+			light_buffer,
+			globals_buffer: &globals_buffer,
+			capsule_buffer: &capsule_buffer,
+			vat_view: &vat.view,
+		};
+		let uniform_bind_group = shared.bind_group(&device, &uniform_bind_group_layout, camera_buffer.as_entire_binding(), environment_buffer.as_entire_binding(), "camera_bind_group");
 
-		// create render pipeline for different material types
-		let render_pipeline = {
-			let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-				label: Some("Render Pipeline Layout"),
-				bind_group_layouts: &[
-					&texture_bind_group_layouts[1],
-					&cubemap_bind_group_layout,
-					&uniform_bind_group_layout,
-				],
-				immediate_size: 0,
-			});
+		let depth_texture = texture::Texture::create_depth_texture(&device, &scene_config, "depth_texture");
+		let surface_texture = texture::Texture::create_render_target(&device, &scene_config, Self::SURFACE_FORMAT, wgpu::TextureUsages::RENDER_ATTACHMENT, "surface_texture");
 
-			let shader = wgpu::ShaderModuleDescriptor {
-				label: Some("Normal Shader"),
-				source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-			};
+		let ray_tracing = features.ray_queries
+			.then(|| ray_tracing::RayTracing::new(&device, &scene_config, &depth_texture, &camera_buffer, light_buffer));
+		let meshlets = features.meshlets()
+			.then(|| meshlet::MeshletPipeline::new(&device, &camera_buffer, &uniform_arena));
+		let reflections = reflections_supported.then(|| reflections::Reflections::new(
+			&device,
+			&scene_config,
+			&depth_texture,
+			&surface_texture,
+			&camera_buffer,
+			ray_tracing.as_ref().map(|ray_tracing| (ray_tracing.tlas(), light_buffer)),
+		));
 
-			create_render_pipeline(
-				"Normal Render Pipeline",
+		let environment_map = resources::load_cubemap_texture("skybox", &device, &queue).await?;
+		let ibl = ibl::Ibl::new(&device, &queue, features.hdr_targets);
+		let ibl_layout = &ibl.layout;
+
+		let skybox = skybox::SkyboxPass::new(&device, ibl_layout, &uniform_bind_group_layout, scene_config.format, shader_tier.surface_format());
+
+		let point_shadows = (shader_tier == shaders::ShaderTier::Full)
+			.then(|| point_shadow::PointShadows::new(&device, &uniform_bind_group_layout));
+		let shadow_map = (shader_tier == shaders::ShaderTier::Downlevel)
+			.then(|| shadow_map::ShadowMap::new(&device, &uniform_bind_group_layout));
+		let gtao = gtao::Gtao::new(&device, &queue, &scene_config, &depth_texture, &camera_buffer);
+		// the lighting pass reads the lights from storage and the point shadows, so the deferred path needs the full tier
+		let deferred = match (options.render_path, shader_tier.deferred_lighting_shader(), &point_shadows) {
+			(deferred::RenderPath::Deferred, Some(shader), Some(point_shadows)) => Some(deferred::Deferred::new(
 				&device,
-				&layout,
-				config.format,
-				Some(texture::Texture::DEPTH_FORMAT),
-				&[model::ModelVertex::desc()],
+				&scene_config,
 				shader,
-			)
+				&depth_texture,
+				ibl_layout,
+				&uniform_bind_group_layout,
+				&point_shadows.layout,
+			)),
+			(deferred::RenderPath::Deferred, ..) => {
+				log::warn!("Falling back: the deferred render path needs the full shader tier, rendering forward");
+				None
+			}
+			(deferred::RenderPath::Forward, ..) => None,
 		};
+		let interlace = interlace::Interlace::new(&device, &scene_config, Self::SURFACE_FORMAT);
+		let post = post::PostSettings::new(ray_tracing.is_some());
+		let contact_shadows = point_shadows.as_ref()
+			.map(|point_shadows| contact_shadow::ContactShadows::new(&device, scene_config.format, &depth_texture, &camera_buffer, light_buffer, point_shadows));
 
-		Ok(Self {
+		// create render pipelines for every material type, blend mode, and cull mode
+		let test_pattern = color::TestPatternPass::new(&device, scene_config.format);
+		let fade = fade::FadePass::new(&device, config.format);
+		let debug_draw = debug_draw::DebugDraw::new(&device, scene_config.format);
+		let particles = particles::ParticleSystem::new(&device, features.compute, shader_tier == shaders::ShaderTier::Full, scene_config.format, &depth_texture, &interlace.targets.depth);
+		let profiler = profiler::GpuProfiler::new(&device, &queue, features.timestamp_queries, frames_in_flight);
+		#[cfg(feature = "text")]
+		let text = match resources::load_binary("fonts/DejaVuSansMono.ttf").await
+			.and_then(|font| text::TextRenderer::new(&device, config.format, font))
+		{
+			Ok(text) => Some(text),
+			Err(e) => {
+				log::warn!("No on-screen text: {}", e);
+				None
+			}
+		};
+		let imposters = imposter::ImposterPipeline::new(
+			&device,
+			scene_config.format,
+			shader_tier,
+			ibl_layout,
+			&uniform_bind_group_layout,
+			&shared,
+			imposter::SceneBuffers { camera: &camera_buffer, environment: &environment_buffer },
+		);
+		let virtual_cameras = virtual_camera::VirtualCameras::new(
+			&device,
+			&scene_config,
+			Self::VIRTUAL_CAMERA_SIZE,
+			&uniform_bind_group_layout,
+			&shared,
+		);
+
+		let material_layouts = texture_bind_group_layouts.iter().zip(shader_tier.material_shaders()).map(|(texture_layout, (name, _))| {
+			let mut bind_group_layouts = vec![
+				Some(texture_layout),
+				Some(ibl_layout),
+				Some(&uniform_bind_group_layout),
+			];
+			if let Some(point_shadows) = &point_shadows {
+				bind_group_layouts.push(Some(&point_shadows.layout));
+			}
+			if let Some(shadow_map) = &shadow_map {
+				bind_group_layouts.push(Some(&shadow_map.layout));
+			}
+			device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+				label: Some(&format!("{} Render Pipeline Layout", name)),
+				bind_group_layouts: &bind_group_layouts,
+				immediate_size: 0,
+			})
+		}).collect();
+		let material_modules: Vec<_> = shader_tier.material_shaders().iter()
+			.map(|(_, shader)| device.create_shader_module(shader.descriptor()))
+			.collect();
+		#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+		let shader_reload = match shader_reload::ShaderReload::new(material_modules.clone()) {
+			Ok(shader_reload) => Some(shader_reload),
+			Err(e) => {
+				log::warn!("Shaders won't hot reload: {}", e);
+				None
+			}
+		};
+
+		let mut renderer = Self {
 			surface,
 			is_surface_configured: false,
 			device,
@@ -273,20 +432,183 @@ impl Renderer {
 			config,
 
 			texture_bind_group_layouts,
+			assets: assets::Assets::default(),
 
-			cubemap_bind_group,
+			environment_map,
+			ibl,
+			skybox,
 
+			uniform_bind_group_layout,
 			uniform_bind_group,
 			camera_buffer,
-			model_buffer,
+			uniform_arena,
+			frames,
+			vat,
+			skinning,
+			crowds,
+			ray_tracing,
+			reflections,
+			capabilities,
+			features,
+			shader_tier,
+			meshlets,
+			point_shadows,
+			shadow_map,
+			contact_shadows,
+			gtao,
+			deferred,
+			interlace,
+			post,
+			imposters,
+			virtual_cameras,
+			render_scale,
+			test_pattern,
+			fade,
+			debug_draw,
+			particles,
+			profiler,
+			last_render_start: None,
+			#[cfg(feature = "text")]
+			text,
+			#[cfg(feature = "text")]
+			show_fps: false,
+			#[cfg(feature = "inspector")]
+			inspector: None,
 
-			simple_material_buffer,
-			light_buffer,
-			camera_pos_buffer,
+			uploaded_camera: Cell::new(camera::CameraUniform::new()),
+			uploaded_environment: Cell::new(environment_uniform),
+			uploaded_capsules: Cell::new(capsule_shadow::CapsuleShadowUniform::new()),
+			uploaded_light_bounds: None,
+
+			lights,
+			globals_buffer,
+			environment_buffer,
+			capsule_buffer,
 
 			depth_texture,
-			render_pipeline,
-		})
+			surface_texture,
+			material_layouts,
+			render_pipelines: HashMap::new(),
+			debug_pipelines: HashMap::new(),
+			#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+			shader_reload,
+			view_mode: view_mode::ViewMode::Lit,
+		};
+		for (material_type, module) in material_modules.iter().enumerate() {
+			renderer.create_material_pipelines(material_type, module);
+		}
+		Ok(renderer)
+	}
+
+	// a material type's pipelines for every blend and cull mode, the G-buffer, and the debug view modes, which have their own entry points
+	fn create_material_pipelines(&mut self, material_type: usize, shader: &wgpu::ShaderModule) {
+		let (name, _) = self.shader_tier.material_shaders()[material_type];
+		let layout = &self.material_layouts[material_type];
+		let scene_format = self.render_scale.internal_config(&self.config).format;
+		for blend in model::BlendMode::ALL {
+			for cull in model::CullMode::ALL {
+				let pipeline = create_render_pipeline(&self.device, &PipelineDescriptor {
+					label: &format!("{} {:?} {:?} Render Pipeline", name, blend, cull),
+					layout,
+					color_format: scene_format,
+					surface_format: self.shader_tier.surface_format(),
+					depth_format: Some(texture::Texture::DEPTH_FORMAT),
+					vertex_layouts: &[model::ModelVertex::desc()],
+					shader,
+					fragment_entry: "fs_main",
+					polygon_mode: wgpu::PolygonMode::Fill,
+					blend,
+					cull,
+				});
+				self.render_pipelines.insert(model::PipelineKey { material_type, blend, cull }, pipeline);
+			}
+		}
+		if let Some(deferred) = &mut self.deferred {
+			deferred.add_material(&self.device, material_type, name, layout, shader);
+		}
+
+		for mode in view_mode::ViewMode::ALL {
+			if mode == view_mode::ViewMode::Lit || (mode == view_mode::ViewMode::Wireframe && !self.features.wireframe) {
+				continue;
+			}
+			let pipeline = create_render_pipeline(&self.device, &PipelineDescriptor {
+				label: &format!("{} {:?} Debug Pipeline", name, mode),
+				layout,
+				color_format: scene_format,
+				surface_format: self.shader_tier.surface_format(),
+				depth_format: Some(texture::Texture::DEPTH_FORMAT),
+				vertex_layouts: &[model::ModelVertex::desc()],
+				shader,
+				fragment_entry: mode.fragment_entry(),
+				polygon_mode: mode.polygon_mode(),
+				blend: model::BlendMode::Opaque,
+				cull: model::CullMode::None,
+			});
+			self.debug_pipelines.insert((mode, material_type), pipeline);
+		}
+	}
+
+	/*
+	Rebuilds the pipelines of the material shaders made from WGSL files edited since the last
+	frame. Errors from the new shader and its pipelines are caught instead of going to the
+	device's error handler, and the pipelines are rebuilt from the last shader that worked.
+	*/
+	#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+	fn reload_shaders(&mut self) {
+		let Some(mut shader_reload) = self.shader_reload.take() else {
+			return;
+		};
+		for (material_type, source) in shader_reload.changed(self.shader_tier) {
+			let (name, _) = self.shader_tier.material_shaders()[material_type];
+			let source = match source {
+				Ok(source) => source,
+				Err(e) => {
+					log::error!("Unable to reload the {} shader: {}", name, e);
+					continue;
+				}
+			};
+			let scope = self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+			let module = self.device.create_shader_module(wgpu::ShaderModuleDescriptor {
+				label: Some(name),
+				source: wgpu::ShaderSource::Wgsl(source.into()),
+			});
+			self.create_material_pipelines(material_type, &module);
+			match pollster::block_on(scope.pop()) {
+				None => {
+					log::info!("Reloaded the {} shader", name);
+					shader_reload.modules[material_type] = module;
+				}
+				Some(e) => {
+					log::error!("The {} shader didn't compile, keeping the last one that did: {}", name, e);
+					self.create_material_pipelines(material_type, &shader_reload.modules[material_type]);
+				}
+			}
+		}
+		self.shader_reload = Some(shader_reload);
+	}
+
+	// uploads the textures that finished loading, and points the materials using them at them
+	// and rebuilds the ones made before the uniform arena last grew
+	pub fn update_assets(&self, scene: &mut scene::Scene) {
+		let loaded = self.assets.poll(&self.device, &self.queue);
+		for material in &mut scene.materials {
+			let stale = material.arena_generation != self.uniform_arena.generation();
+			if stale || material.material_type.textures().iter().any(|handle| loaded.contains(handle)) {
+				material.update_bind_group(&self.device, &self.texture_bind_group_layouts, &self.uniform_arena, &self.assets);
+			}
+		}
+	}
+
+	// rebuilds every material's bind group, for a scene that was built while another one was drawn
+	pub fn rebind_materials(&self, scene: &mut scene::Scene) {
+		for material in &mut scene.materials {
+			material.update_bind_group(&self.device, &self.texture_bind_group_layouts, &self.uniform_arena, &self.assets);
+		}
+	}
+
+	// the last frame's CPU times, and each stage's GPU time while the profiler is enabled
+	pub fn frame_stats(&self) -> &profiler::FrameStats {
+		&self.profiler.stats
 	}
 
 	pub fn update_size(&mut self, width: u32, height: u32) {
@@ -294,59 +616,335 @@ impl Renderer {
 		self.config.height = height;
 		self.surface.configure(&self.device, &self.config);
 		self.is_surface_configured = true;
-		self.depth_texture = texture::Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
+		self.resize_targets();
+	}
+
+	pub fn present_mode(&self) -> wgpu::PresentMode {
+		self.config.present_mode
+	}
+
+	// reconfigures the surface, returns the mode used, which is Fifo if the surface doesn't have the one asked for
+	pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) -> wgpu::PresentMode {
+		self.config.present_mode = self.capabilities.supported_present_mode(present_mode);
+		if self.is_surface_configured {
+			self.surface.configure(&self.device, &self.config);
+		}
+		self.config.present_mode
 	}
 
-	pub fn update_light(&self, light: &light::LightUniform) {
-		self.queue.write_buffer(&self.light_buffer, 0, bytemuck::cast_slice(&[*light]));
+	pub fn render_scale(&self) -> f32 {
+		self.render_scale.scale()
+	}
+
+	// the fraction of the surface resolution the 3D stages render at, overlays stay native
+	pub fn set_render_scale(&mut self, scale: f32) {
+		self.render_scale.set_scale(scale);
+		self.resize_targets();
+	}
+
+	pub fn output_transfer(&self) -> color::OutputTransfer {
+		self.render_scale.output_transfer()
+	}
+
+	// how the linear frame is encoded for the display, see color.rs
+	pub fn set_output_transfer(&mut self, transfer: color::OutputTransfer) {
+		self.render_scale.set_output_transfer(transfer);
+	}
+
+	// everything the 3D stages render into is at the internal resolution
+	fn resize_targets(&mut self) {
+		self.render_scale.resize(&self.device, &self.config);
+		let config = self.render_scale.internal_config(&self.config);
+		self.depth_texture = texture::Texture::create_depth_texture(&self.device, &config, "depth_texture");
+		self.surface_texture = texture::Texture::create_render_target(&self.device, &config, Self::SURFACE_FORMAT, wgpu::TextureUsages::RENDER_ATTACHMENT, "surface_texture");
+		if let Some(ray_tracing) = &mut self.ray_tracing {
+			ray_tracing.resize(&self.device, &config, &self.depth_texture, &self.camera_buffer, &self.lights.buffer);
+		}
+		self.gtao.resize(&self.device, &config, &self.depth_texture, &self.camera_buffer);
+		if let Some(deferred) = &mut self.deferred {
+			deferred.resize(&self.device, &config, &self.depth_texture);
+		}
+		self.interlace.resize(&self.device, &config);
+		self.particles.resize(&self.device, &self.depth_texture, &self.interlace.targets.depth);
+		if let Some(contact_shadows) = &mut self.contact_shadows {
+			contact_shadows.resize(&self.device, &self.depth_texture, &self.camera_buffer, &self.lights.buffer);
+		}
+		if let Some(reflections) = &mut self.reflections {
+			reflections.resize(&self.device, &config, &self.depth_texture, &self.surface_texture, &self.camera_buffer);
+		}
+	}
+
+	// uploads the scene lights, with the shadows assigned to them, when one changed or the shadow
+	// map has to be refit to the scene bounds
+	pub fn update_lights(&mut self, scene: &mut scene::Scene) {
+		let bounds = self.shadow_map.as_ref().and_then(|_| scene.aabb());
+		let refit = bounds != self.uploaded_light_bounds;
+		if !scene.take_lights_dirty() && !refit {
+			return;
+		}
+		self.uploaded_light_bounds = bounds;
+		let mut lights = scene.lights().iter().map(light::Light::to_uniform).collect::<Vec<_>>();
+		if let Some(point_shadows) = &mut self.point_shadows {
+			let shadow_indices = point_shadows.update(&self.queue, &lights);
+			for (light, index) in lights.iter_mut().zip(shadow_indices) {
+				light.set_shadow_index(index);
+			}
+		}
+		if let (Some(shadow_map), Some(light)) = (&mut self.shadow_map, lights.first_mut()) {
+			shadow_map.update(&self.queue, light, bounds);
+		}
+		self.lights.upload(&self.queue, &lights);
 	}
 
 	/*
 	Should take in a scene
 	*/
-	pub fn render(&self, window: &Arc<Window>, camera: &camera::Camera, scene: &scene::Scene) -> Result<(), wgpu::SurfaceError> {
+	pub fn render(&mut self, camera: &camera::Camera, scene: &scene::Scene) -> Result<(), SurfaceError> {
+		let render_start = web_time::Instant::now();
+		if let Some(last_render_start) = self.last_render_start.replace(render_start) {
+			self.profiler.stats.cpu_frame_time = (render_start - last_render_start).as_secs_f32() * 1000.0;
+		}
+		#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+		self.reload_shaders();
+
+		// the camera's own post settings take the place of the renderer's
+		let post_settings = camera.settings.post.clone().unwrap_or_else(|| self.post.clone());
+
 		// update camera buffer
-		let camera_uniform = camera::CameraUniform{ view_proj: camera.build_view_projection_matrix().into() };
-		self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::cast_slice(&[camera_uniform]));
-		let camera_pos: [f32; 3] = camera.eye.into();
-		self.queue.write_buffer(&self.camera_pos_buffer, 0, bytemuck::cast_slice(&[camera_pos]));
+		let mut camera_uniform = camera::CameraUniform::new();
+		if post_settings.interlaced {
+			camera_uniform.update_matrices(camera.build_view_matrix(), self.interlace.jitter(camera.build_projection_matrix()), camera.eye);
+		} else {
+			camera_uniform.update_view_proj(camera);
+		}
+		self.write_if_changed(&self.camera_buffer, &self.uploaded_camera, camera_uniform);
+		let mut globals = globals::GlobalsUniform::new();
+		globals.camera_pos = camera.eye.into();
+		globals.time = scene.time;
+		let resolution = self.render_scale.internal_config(&self.config);
+		globals.resolution = [resolution.width as f32, resolution.height as f32];
+		globals.delta_time = scene.delta_time;
+		self.queue.write_buffer(&self.globals_buffer, 0, bytemuck::cast_slice(&[globals]));
+		// an hdr scene stays linear and is graded by the output stage, as are the test patterns,
+		// the normals and depth views are shown as they are
+		let debug_colors = matches!(self.view_mode, view_mode::ViewMode::Normals | view_mode::ViewMode::Depth);
+		let environment_uniform = if self.render_scale.is_hdr() {
+			let (exposure, tonemap) = match self.test_pattern.pattern {
+				Some(_) => (1.0, environment::Tonemap::None),
+				None if debug_colors => (1.0, environment::Tonemap::None),
+				None => scene.environment.grading(&camera.settings),
+			};
+			self.render_scale.set_grading(exposure, tonemap);
+			scene.environment.to_linear_uniform()
+		} else {
+			scene.environment.to_uniform(&camera.settings)
+		};
+		self.write_if_changed(&self.environment_buffer, &self.uploaded_environment, environment_uniform);
+		self.write_if_changed(&self.capsule_buffer, &self.uploaded_capsules, capsule_shadow::CapsuleShadowUniform::from_scene(scene));
 
-		// begin render pass
-		window.request_redraw();
+		// suballocate this frame's material and object uniforms
+		self.frames.begin(&self.device);
+		self.uniform_arena.begin_frame(self.frames.index());
+		let material_offsets = scene.materials.iter()
+			.map(|material| self.uniform_arena.push(&material.params))
+			.collect::<Vec<_>>();
+		// objects outside the camera frustum aren't drawn, skinned ones can move out of their bind pose bounds
+		let frustum = camera::Frustum::from_matrix(&camera.build_view_projection_matrix());
+		let object_offsets = scene.objects.iter()
+			.map(|obj| {
+				let (center, radius) = scene.models[obj.model_index].bounds.transformed(&obj.transform);
+				(obj.skin.is_some() || frustum.intersects_sphere(center, radius))
+					.then(|| self.uniform_arena.push(&obj.to_uniform()))
+			})
+			.collect::<Vec<_>>();
+		let crowd_offsets = scene.crowds.iter()
+			.map(|crowd| self.uniform_arena.push(&crowd.to_uniform()))
+			.collect::<Vec<_>>();
+		// shadow casters outside the view still need their uniforms for the shadow passes
+		let caster_offsets = if self.point_shadows.is_some() || self.shadow_map.is_some() {
+			scene.objects.iter().zip(&object_offsets)
+				.map(|(obj, offset)| obj.casts_shadows.then(|| offset.unwrap_or_else(|| self.uniform_arena.push(&obj.to_uniform()))))
+				.collect::<Vec<_>>()
+		} else {
+			vec![]
+		};
+		// far objects with a baked imposter draw that instead
+		let imposter_offsets = scene.objects.iter()
+			.map(|obj| {
+				let model = &scene.models[obj.model_index];
+				let imposter = model.imposter.as_ref().filter(|_| obj.skin.is_none())?;
+				let (center, _) = model.bounds.transformed(&obj.transform);
+				(center.distance(camera.eye) > imposter.distance)
+					.then(|| self.uniform_arena.push(&imposter::ImposterUniform::new(&obj.transform, &model.bounds)))
+			})
+			.collect::<Vec<_>>();
+		self.skybox.prepare(&self.device, scene.skybox());
+		self.ibl.prepare(&self.device, &self.queue, scene.skybox().unwrap_or(&self.environment_map));
+		self.particles.prepare(&self.device, &self.queue, &self.camera_buffer, scene);
+		let virtual_views = self.virtual_cameras.prepare(&self.queue, &mut self.uniform_arena, scene, &object_offsets);
+		let meshlet_draws = match &mut self.meshlets {
+			Some(meshlets) => meshlets.prepare(&mut self.uniform_arena, scene),
+			None => HashMap::new(),
+		};
+		// copies of a model share instanced draws, objects with imposters, meshlets, or anything blended draw on their own
+		let meshlet_objects = meshlet_draws.keys().map(|&(object, _)| object).collect::<HashSet<_>>();
+		let instance_batches = instancing::InstanceBatches::build(&mut self.uniform_arena, scene, &object_offsets, |object| {
+			imposter_offsets[object].is_some() || meshlet_objects.contains(&object) || scene.object_is_transparent(object)
+		});
+		let virtual_batches = virtual_views.iter()
+			.map(|(_, offsets)| instancing::InstanceBatches::build(&mut self.uniform_arena, scene, offsets, |object| scene.object_is_transparent(object)))
+			.collect::<Vec<_>>();
+		if self.uniform_arena.reallocate(&self.device) {
+			self.rebind_uniform_arena();
+		}
+		self.uniform_arena.upload(&self.queue);
+		// the materials bound to the replaced buffer are rebuilt in update_assets before the next frame
+		if scene.materials.iter().any(|material| material.arena_generation != self.uniform_arena.generation()) {
+			return Ok(());
+		}
 
+		// begin render pass
 		if !self.is_surface_configured {
 			return Ok(());
 		}
 
-		let output = self.surface.get_current_texture()?;
+		let output = match self.surface.get_current_texture() {
+			wgpu::CurrentSurfaceTexture::Success(output) | wgpu::CurrentSurfaceTexture::Suboptimal(output) => output,
+			// nothing to draw into this frame, tried again on the next
+			wgpu::CurrentSurfaceTexture::Timeout | wgpu::CurrentSurfaceTexture::Occluded => return Ok(()),
+			wgpu::CurrentSurfaceTexture::Outdated => return Err(SurfaceError::Outdated),
+			wgpu::CurrentSurfaceTexture::Lost => return Err(SurfaceError::Lost),
+			wgpu::CurrentSurfaceTexture::Validation => return Err(SurfaceError::Validation),
+		};
 
 		let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+		// the 3D stages render into the output stage's target, at the internal resolution
+		let scene_target = self.render_scale.scene_target();
+		let (scene_texture, scene_view) = (&scene_target.texture, &scene_target.view);
 
 		let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
 			label: Some("Render Encoder"),
 		});
+		self.profiler.begin_frame(&self.device, self.frames.index());
+
+		// skin before any pass reads the skinned vertices
+		if let Some(skinning) = &self.skinning {
+			self.profiler.mark(&mut encoder, "skinning");
+			skinning.dispatch(&mut encoder, &scene.skins);
+		}
+		if let Some(crowds) = &self.crowds {
+			self.profiler.mark(&mut encoder, "crowds");
+			crowds.dispatch(&self.queue, &mut encoder, &scene.crowds, scene.time);
+		}
+		if let Some(meshlets) = &self.meshlets {
+			self.profiler.mark(&mut encoder, "meshlet culling");
+			meshlets.cull(&mut encoder, scene);
+		}
+		self.profiler.mark(&mut encoder, "particles");
+		self.particles.dispatch(&mut encoder);
+		if let Some(point_shadows) = &mut self.point_shadows {
+			self.profiler.mark(&mut encoder, "point shadows");
+			point_shadows.draw(&mut encoder, scene, &self.uniform_bind_group, &caster_offsets, camera.eye);
+		}
+		if let Some(shadow_map) = &self.shadow_map {
+			self.profiler.mark(&mut encoder, "shadow map");
+			shadow_map.draw(&mut encoder, scene, &self.uniform_bind_group, &caster_offsets);
+		}
 
+		// every virtual camera in one pass, without the imposters and meshlet culling of the main view
+		let no_imposters = vec![None; scene.objects.len()];
+		self.profiler.mark(&mut encoder, "virtual cameras");
+		self.virtual_cameras.draw(&mut encoder, self.shader_tier.color_target_count(), scene.environment.clear_color(), &virtual_views, |render_pass, uniform_bind_group, view| {
+			self.set_lighting_bind_groups(render_pass);
+			self.draw_scene(render_pass, deferred::ScenePass::Forward, &SceneDraws {
+				scene,
+				uniform_bind_group,
+				eye: self.virtual_cameras.eye(virtual_views[view].0),
+				material_offsets: &material_offsets,
+				object_offsets: &virtual_views[view].1,
+				instance_batches: &virtual_batches[view],
+				imposter_offsets: &no_imposters,
+				meshlet_draws: &HashMap::new(),
+				crowd_offsets: &crowd_offsets,
+			});
+		});
+
+		let draws = SceneDraws {
+			scene,
+			uniform_bind_group: &self.uniform_bind_group,
+			eye: camera.eye,
+			material_offsets: &material_offsets,
+			object_offsets: &object_offsets,
+			instance_batches: &instance_batches,
+			imposter_offsets: &imposter_offsets,
+			meshlet_draws: &meshlet_draws,
+			crowd_offsets: &crowd_offsets,
+		};
+
+		// interlaced frames render at half width and are rebuilt into the view afterwards
+		let (color_view, surface_view, depth_view) = if post_settings.interlaced {
+			(&self.interlace.targets.color.view, &self.interlace.targets.surface.view, &self.interlace.targets.depth.view)
+		} else {
+			(scene_view, &self.surface_texture.view, &self.depth_texture.view)
+		};
+		// the deferred path lights the opaque meshes before the main pass draws the rest over them,
+		// interlaced frames and the debug views are always forward
+		let deferred = self.deferred.as_ref().zip(self.point_shadows.as_ref())
+			.filter(|_| !post_settings.interlaced && self.view_mode == view_mode::ViewMode::Lit);
+		if let Some((deferred, point_shadows)) = deferred {
+			self.profiler.mark(&mut encoder, "g-buffer");
+			{
+				let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+					label: Some("G-buffer Pass"),
+					color_attachments: &deferred.attachments(surface_view),
+					depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+						view: depth_view,
+						depth_ops: Some(wgpu::Operations {
+							load: wgpu::LoadOp::Clear(1.0),
+							store: wgpu::StoreOp::Store,
+						}),
+						stencil_ops: None,
+					}),
+					occlusion_query_set: None,
+					timestamp_writes: None,
+					multiview_mask: None,
+				});
+				self.set_lighting_bind_groups(&mut render_pass);
+				self.draw_scene(&mut render_pass, deferred::ScenePass::GBuffer, &draws);
+			}
+			self.profiler.mark(&mut encoder, "deferred lighting");
+			deferred.light(&mut encoder, color_view, scene.environment.clear_color(), &self.ibl.bind_group, &self.uniform_bind_group, &point_shadows.bind_group);
+		}
+		let (scene_pass, color_load, surface_load, depth_load) = match deferred {
+			Some(_) => (deferred::ScenePass::Composite, wgpu::LoadOp::Load, wgpu::LoadOp::Load, wgpu::LoadOp::Load),
+			None => (deferred::ScenePass::Forward, wgpu::LoadOp::Clear(scene.environment.clear_color()), wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT), wgpu::LoadOp::Clear(1.0)),
+		};
+		self.profiler.mark(&mut encoder, "main pass");
 		{
 			let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
 				label: Some("Render Pass"),
+				// the downlevel tier only renders to the first attachment
 				color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-					view: &view,
+					view: color_view,
 					resolve_target: None,
 					ops: wgpu::Operations {
-						load: wgpu::LoadOp::Clear(wgpu::Color {
-							r: 0.1,
-							g: 0.2,
-							b: 0.3,
-							a: 1.0,
-						}),
+						load: color_load,
 						store: wgpu::StoreOp::Store,
 					},
 					depth_slice: None,
-				})],
+				}), Some(wgpu::RenderPassColorAttachment {
+					view: surface_view,
+					resolve_target: None,
+					ops: wgpu::Operations {
+						load: surface_load,
+						store: wgpu::StoreOp::Store,
+					},
+					depth_slice: None,
+				})][..self.shader_tier.color_target_count()],
 				depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-					view: &self.depth_texture.view,
+					view: depth_view,
 					depth_ops: Some(wgpu::Operations {
-						load: wgpu::LoadOp::Clear(1.0),
+						load: depth_load,
 						store: wgpu::StoreOp::Store,
 					}),
 					stencil_ops: None,
@@ -356,40 +954,515 @@ impl Renderer {
 				multiview_mask: None,
 			});
 
-			render_pass.set_pipeline(&self.render_pipeline);
-			render_pass.set_bind_group(1, &self.cubemap_bind_group, &[]);
-			render_pass.set_bind_group(2, &self.uniform_bind_group, &[]);
+			self.set_lighting_bind_groups(&mut render_pass);
 
-			// draw scene
-			// sort by render pipeline
-			// then sort by material type
-			// TODO: for now render by same material type, but change later
-			self.draw_scene(&mut render_pass, scene);
+			// draw scene, with the sky between the opaque and blended meshes
+			// TODO: sort by render pipeline to avoid switching pipelines per mesh
+			self.draw_scene(&mut render_pass, scene_pass, &draws);
+		}
+
+		// particles in their own pass, so soft ones can sample the depth they're tested against
+		if self.view_mode == view_mode::ViewMode::Lit && !scene.emitters.is_empty() {
+			self.profiler.mark(&mut encoder, "particle draw");
+			let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+				label: Some("Particle Pass"),
+				color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+					view: color_view,
+					resolve_target: None,
+					ops: wgpu::Operations {
+						load: wgpu::LoadOp::Load,
+						store: wgpu::StoreOp::Store,
+					},
+					depth_slice: None,
+				})],
+				depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+					view: depth_view,
+					depth_ops: None, // read only
+					stencil_ops: None,
+				}),
+				occlusion_query_set: None,
+				timestamp_writes: None,
+				multiview_mask: None,
+			});
+			self.particles.draw(&mut render_pass, &scene.emitters, post_settings.interlaced);
+		}
+
+		// the debug views skip the lighting passes, they'd shade the normals and depth
+		if post_settings.interlaced {
+			self.profiler.mark(&mut encoder, "interlace");
+			self.interlace.resolve(&self.queue, &mut encoder, scene_view);
+		} else if self.view_mode == view_mode::ViewMode::Lit {
+			if let (Some(contact_shadows), Some(point_shadows)) = (&mut self.contact_shadows, &self.point_shadows) {
+				self.profiler.mark(&mut encoder, "contact shadows");
+				contact_shadows.draw(&self.queue, &mut encoder, scene_view, point_shadows);
+			}
+
+			if matches!(post_settings.ao, post::AoMethod::Gtao | post::AoMethod::Ssao) {
+				let ssao = post_settings.ao == post::AoMethod::Ssao;
+				self.profiler.mark(&mut encoder, if ssao { "ssao" } else { "gtao" });
+				self.gtao.draw(&self.queue, &mut encoder, scene_view, camera.build_view_projection_matrix(), ssao);
+			}
+
+			// ray traced shadows and AO on top of the lit scene
+			if let Some(ray_tracing) = &mut self.ray_tracing {
+				self.profiler.mark(&mut encoder, "ray tracing");
+				ray_tracing.update(&self.queue, &mut encoder, scene, post_settings.ao == post::AoMethod::RayTraced);
+				ray_tracing.draw(&self.queue, &mut encoder, scene_view, camera.build_view_projection_matrix());
+			}
+
+			// reflections, traced against the TLAS built above when ray tracing is supported
+			if let Some(reflections) = &self.reflections {
+				self.profiler.mark(&mut encoder, "reflections");
+				reflections.update(&self.queue, scene);
+				reflections.draw(&mut encoder, scene_texture, scene_view);
+			}
+		}
+
+		// gizmos over the finished scene, interlaced frames have no depth at this resolution
+		self.profiler.mark(&mut encoder, "debug draw");
+		self.debug_draw.draw(&self.device, &self.queue, &mut encoder, camera, debug_draw::DrawTarget {
+			view: scene_view,
+			depth_view: &self.depth_texture.view,
+			scene_depth: !post_settings.interlaced,
+		});
+
+		// the color audit patterns replace the finished scene, and go through the output stage like it
+		self.test_pattern.draw(&self.queue, &mut encoder, scene_view);
+
+		// overlays from here on, at the surface resolution
+		self.profiler.mark(&mut encoder, "output");
+		self.render_scale.upscale(&self.queue, &mut encoder, &view, self.config.format);
+		self.fade.draw(&self.queue, &mut encoder, &view, [self.config.width, self.config.height]);
+		#[cfg(feature = "text")]
+		if let Some(text) = &mut self.text {
+			self.profiler.mark(&mut encoder, "text");
+			text.draw(&self.device, &self.queue, &mut encoder, &view, [self.config.width, self.config.height]);
+		}
+		#[cfg(feature = "inspector")]
+		if let Some(inspector) = &mut self.inspector {
+			self.profiler.mark(&mut encoder, "inspector");
+			inspector.draw(&self.device, &self.queue, &mut encoder, &view, [self.config.width, self.config.height]);
 		}
+		self.profiler.end_frame(&mut encoder);
 
 		// present
-		self.queue.submit(std::iter::once(encoder.finish()));
+		let submission = self.queue.submit(std::iter::once(encoder.finish()));
+		self.frames.end(submission);
+		self.profiler.after_submit();
 		output.present();
+		self.profiler.stats.cpu_render_time = render_start.elapsed().as_secs_f32() * 1000.0;
 
 		Ok(())
 	}
 
-	fn draw_scene<'a>(&self, render_pass: &mut wgpu::RenderPass<'a>, scene: &'a scene::Scene) {
+	/*
+	Describes the passes render runs for this camera and scene with the current settings, for
+	exporting with FrameGraph::to_dot or to_json. Kept in step with render by hand.
+	*/
+	pub fn frame_graph(&self, camera: &camera::Camera, scene: &scene::Scene) -> frame_graph::FrameGraph {
+		use frame_graph::PassKind::{Render, Compute, Copy};
+		let post_settings = camera.settings.post.clone().unwrap_or_else(|| self.post.clone());
+		let lit = self.view_mode == view_mode::ViewMode::Lit;
+		let surface: &[&str] = if self.shader_tier.surface_format().is_some() { &["surface"] } else { &[] };
+		let mut graph = frame_graph::FrameGraph::default();
+
+		graph.pass("upload uniforms", Copy, &[], &["camera", "uniform arena"]);
+		if self.skinning.is_some() && !scene.skins.is_empty() {
+			graph.pass("skinning", Compute, &["uniform arena"], &["skinned vertices"]);
+		}
+		if self.crowds.is_some() && !scene.crowds.is_empty() {
+			graph.pass("crowd poses", Compute, &[], &["crowd instances"]);
+		}
+		if self.meshlets.is_some() {
+			graph.pass("meshlet culling", Compute, &["camera"], &["meshlet draws"]);
+		}
+		if !scene.emitters.is_empty() {
+			let kind = if self.features.compute { Compute } else { Copy };
+			graph.pass("particle simulation", kind, &[], &["particles"]);
+		}
+		let mut shadows = vec![];
+		if self.point_shadows.is_some() {
+			graph.pass("point shadows", Render, &["uniform arena", "skinned vertices"], &["point shadow maps"]);
+			shadows.push("point shadow maps");
+		}
+		if self.shadow_map.is_some() {
+			graph.pass("shadow map", Render, &["uniform arena", "skinned vertices"], &["shadow map"]);
+			shadows.push("shadow map");
+		}
+		let mut scene_reads = vec!["camera", "uniform arena", "skinned vertices", "crowd instances", "meshlet draws"];
+		scene_reads.extend(&shadows);
+		if (0..(virtual_camera::VirtualCameras::COLUMNS * virtual_camera::VirtualCameras::ROWS) as usize).any(|cell| self.virtual_cameras.handle(cell).is_some()) {
+			graph.pass("virtual cameras", Render, &scene_reads, &["virtual camera atlas"]);
+		}
+
+		let mut scene_writes = vec!["scene color", "depth"];
+		scene_writes.extend(surface);
+		if post_settings.interlaced {
+			graph.pass("scene", Render, &scene_reads, &["interlace targets"]);
+			if lit && !scene.emitters.is_empty() {
+				graph.pass("particles", Render, &["camera", "particles", "interlace targets"], &["interlace targets"]);
+			}
+			graph.pass("interlace resolve", Render, &["interlace targets"], &["scene color"]);
+		} else {
+			if lit && self.deferred.is_some() {
+				let mut gbuffer_writes = vec!["gbuffer", "depth"];
+				gbuffer_writes.extend(surface);
+				graph.pass("gbuffer", Render, &scene_reads, &gbuffer_writes);
+				let mut lighting_reads = vec!["gbuffer", "depth", "camera"];
+				lighting_reads.extend(&shadows);
+				graph.pass("deferred lighting", Render, &lighting_reads, &["scene color"]);
+				scene_reads.extend(["scene color", "depth"]);
+			}
+			graph.pass("scene", Render, &scene_reads, &scene_writes);
+			if lit && !scene.emitters.is_empty() {
+				graph.pass("particles", Render, &["camera", "particles", "depth"], &["scene color"]);
+			}
+			if lit {
+				if self.contact_shadows.is_some() && self.point_shadows.is_some() {
+					graph.pass("contact shadows", Render, &["depth", "camera", "point shadow maps"], &["scene color"]);
+				}
+				match post_settings.ao {
+					post::AoMethod::Gtao => graph.pass("gtao", Render, &["depth", "camera"], &["scene color"]),
+					post::AoMethod::Ssao => graph.pass("ssao", Render, &["depth", "camera"], &["scene color"]),
+					_ => {}
+				}
+				if self.ray_tracing.is_some() {
+					graph.pass("build tlas", Compute, &["uniform arena"], &["tlas"]);
+					graph.pass("ray tracing", Render, &["depth", "camera", "tlas"], &["scene color"]);
+				}
+				if self.reflections.is_some() {
+					graph.pass("copy scene", Copy, &["scene color"], &["scene copy"]);
+					let mut reads = vec!["scene copy", "depth", "camera"];
+					reads.extend(surface);
+					if self.ray_tracing.is_some() {
+						reads.push("tlas");
+					}
+					graph.pass("reflections", Render, &reads, &["scene color"]);
+				}
+			}
+		}
+		graph.pass("debug draw", Render, &["depth"], &["scene color"]);
+		if self.test_pattern.pattern.is_some() {
+			graph.pass("test pattern", Render, &[], &["scene color"]);
+		}
+		graph.pass("output", Render, &["scene color"], &["swapchain"]);
+		graph.pass("fade", Render, &[], &["swapchain"]);
+		#[cfg(feature = "text")]
+		if self.text.is_some() {
+			graph.pass("text", Render, &["glyph atlas"], &["swapchain"]);
+		}
+		#[cfg(feature = "inspector")]
+		if self.inspector.is_some() {
+			graph.pass("inspector", Render, &[], &["swapchain"]);
+		}
+		graph
+	}
+
+	/*
+	Opaque meshes first, then the sky over the pixels they left, then the blended meshes back to
+	front by their distance from the eye so nearer ones blend over farther ones. Objects are sorted
+	as a whole by their bounds' center, so meshes within one can still blend out of order. The
+	deferred path splits the draws in two, see ScenePass.
+	*/
+	fn draw_scene<'a>(&self, render_pass: &mut wgpu::RenderPass<'a>, pass: deferred::ScenePass, draws: &SceneDraws<'a, '_>) {
+		let &SceneDraws { scene, uniform_bind_group, eye, material_offsets, object_offsets, instance_batches, imposter_offsets, crowd_offsets, .. } = draws;
 		let models = &scene.models;
 		let materials = &scene.materials;
-		
-		for obj in &scene.objects {
-			let transform: [[f32; 4]; 4] = obj.transform.into();
-			self.queue.write_buffer(&self.model_buffer, 0, bytemuck::cast_slice(&[transform]));
+		let mut current_pipeline = None;
+		let opaque = pass != deferred::ScenePass::Composite;
+		let forward = pass != deferred::ScenePass::GBuffer;
 
-			let model = &models[obj.model_index];
-			for mesh in &model.meshes {
+		// batches only hold opaque objects, see InstanceBatches::build in render
+		for batch in instance_batches.batches.iter().filter(|_| opaque) {
+			render_pass.set_bind_group(2, uniform_bind_group, &[batch.offset]);
+			for mesh in &models[batch.model_index].meshes {
 				let material = &materials[mesh.material];
-				render_pass.draw_mesh(mesh, material);
+				self.set_material_pipeline(render_pass, &mut current_pipeline, pass, material.pipeline_key());
+				render_pass.draw_mesh_instanced(mesh, material, material_offsets[mesh.material], 0..batch.count);
+			}
+		}
+
+		let mut transparent = vec![];
+		for (object, (obj, &object_offset)) in scene.objects.iter().zip(object_offsets).enumerate() {
+			let Some(object_offset) = object_offset.filter(|_| !instance_batches.batched[object]) else {
+				continue;
+			};
+			// imposters are drawn lit, so they go with the blended meshes on the deferred path
+			if let (Some(imposter), Some(imposter_offset)) = (&models[obj.model_index].imposter, imposter_offsets[object]) {
+				if forward {
+					self.imposters.draw(render_pass, imposter, imposter_offset);
+					current_pipeline = None;
+				}
+				continue;
+			}
+			if forward && scene.object_is_transparent(object) {
+				let (center, _) = models[obj.model_index].bounds.transformed(&obj.transform);
+				transparent.push((center.distance2(eye), SortedDraw::Object(object, object_offset)));
+			}
+
+			if opaque {
+				render_pass.set_bind_group(2, uniform_bind_group, &[object_offset]);
+				self.draw_object(render_pass, &mut current_pipeline, pass, draws, object, false);
+			}
+		}
+
+		for (crowd_index, (crowd, &crowd_offset)) in scene.crowds.iter().zip(crowd_offsets).enumerate() {
+			if forward && scene.model_is_transparent(crowd.model_index) && !crowd.instances.is_empty() {
+				let center = crowd.instances.iter()
+					.map(|instance| instance.transform.w.truncate())
+					.sum::<cgmath::Vector3<f32>>() / crowd.instances.len() as f32;
+				transparent.push((cgmath::Point3::from_vec(center).distance2(eye), SortedDraw::Crowd(crowd_index)));
+			}
+			if opaque {
+				render_pass.set_bind_group(2, uniform_bind_group, &[crowd_offset]);
+				self.draw_crowd(render_pass, &mut current_pipeline, pass, draws, crowd_index, false);
+			}
+		}
+
+		if forward && self.view_mode == view_mode::ViewMode::Lit {
+			self.skybox.draw(render_pass, uniform_bind_group);
+			current_pipeline = None;
+		}
+
+		transparent.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+		for (_, draw) in transparent {
+			match draw {
+				SortedDraw::Object(object, object_offset) => {
+					render_pass.set_bind_group(2, uniform_bind_group, &[object_offset]);
+					self.draw_object(render_pass, &mut current_pipeline, pass, draws, object, true);
+				}
+				SortedDraw::Crowd(crowd) => {
+					render_pass.set_bind_group(2, uniform_bind_group, &[crowd_offsets[crowd]]);
+					self.draw_crowd(render_pass, &mut current_pipeline, pass, draws, crowd, true);
+				}
+			}
+		}
+	}
+
+	// draws the object's opaque or transparent meshes, its model uniform has to be bound already
+	fn draw_object<'a>(&self, render_pass: &mut wgpu::RenderPass<'a>, current_pipeline: &mut Option<model::PipelineKey>, pass: deferred::ScenePass, draws: &SceneDraws<'a, '_>, object: usize, transparent: bool) {
+		let &SceneDraws { scene, material_offsets, meshlet_draws, .. } = draws;
+		let obj = &scene.objects[object];
+		let model = &scene.models[obj.model_index];
+		let skin = obj.skin.map(|skin| &scene.skins[skin]);
+		for (i, mesh) in model.meshes.iter().enumerate() {
+			let material = &scene.materials[mesh.material];
+			if (obj.transparent || material.is_transparent()) != transparent {
+				continue;
+			}
+			self.set_material_pipeline(render_pass, current_pipeline, pass, obj.pipeline_key(material));
+
+			let material_offset = material_offsets[mesh.material];
+			if let Some(skin) = skin {
+				render_pass.draw_skinned_mesh(mesh, &skin.meshes[i], material, material_offset);
+			} else if let (Some(draws), Some(meshlets)) = (meshlet_draws.get(&(object, i)), &self.meshlets) {
+				render_pass.draw_mesh_indirect(mesh, material, material_offset, &meshlets.draw_buffer, draws.offset, draws.count);
+			} else {
+				render_pass.draw_mesh(mesh, material, material_offset);
 			}
 		}
 	}
 
+	fn draw_crowd<'a>(&self, render_pass: &mut wgpu::RenderPass<'a>, current_pipeline: &mut Option<model::PipelineKey>, pass: deferred::ScenePass, draws: &SceneDraws<'a, '_>, crowd: usize, transparent: bool) {
+		let &SceneDraws { scene, material_offsets, .. } = draws;
+		let crowd = &scene.crowds[crowd];
+		for (mesh, crowd_mesh) in scene.models[crowd.model_index].meshes.iter().zip(&crowd.meshes) {
+			let material = &scene.materials[mesh.material];
+			if material.is_transparent() != transparent {
+				continue;
+			}
+			self.set_material_pipeline(render_pass, current_pipeline, pass, material.pipeline_key());
+			render_pass.draw_crowd_mesh(mesh, crowd_mesh, material, material_offsets[mesh.material], crowd.instance_count());
+		}
+	}
+
+	// only switches when the material needs a different pipeline than the last draw
+	fn set_material_pipeline(&self, render_pass: &mut wgpu::RenderPass, current_pipeline: &mut Option<model::PipelineKey>, pass: deferred::ScenePass, pipeline_key: model::PipelineKey) {
+		if *current_pipeline != Some(pipeline_key) {
+			let pipeline = match (self.view_mode, &self.deferred) {
+				(_, Some(deferred)) if pass == deferred::ScenePass::GBuffer => deferred.pipeline(pipeline_key),
+				(view_mode::ViewMode::Lit, _) => &self.render_pipelines[&pipeline_key],
+				(mode, _) => &self.debug_pipelines[&(mode, pipeline_key.material_type)],
+			};
+			render_pass.set_pipeline(pipeline);
+			*current_pipeline = Some(pipeline_key);
+		}
+	}
+
+	// the image based lighting and shadows the material pipelines light with
+	fn set_lighting_bind_groups(&self, render_pass: &mut wgpu::RenderPass) {
+		render_pass.set_bind_group(1, &self.ibl.bind_group, &[]);
+		if let Some(point_shadows) = &self.point_shadows {
+			render_pass.set_bind_group(3, &point_shadows.bind_group, &[]);
+		}
+		if let Some(shadow_map) = &self.shadow_map {
+			render_pass.set_bind_group(3, &shadow_map.bind_group, &[]);
+		}
+	}
+
+	// cycles through the view modes the device supports, returns the new one
+	pub fn cycle_view_mode(&mut self) -> view_mode::ViewMode {
+		self.view_mode = self.view_mode.next(self.features.wireframe);
+		self.view_mode
+	}
+
+	/*
+	Queues text over the finished frame, drawn at the end of the next render. The position is the
+	top left of the first line in window pixels, and size is the line height in pixels.
+	*/
+	#[cfg(feature = "text")]
+	pub fn draw_text(&mut self, text: &str, position: [f32; 2], size: f32, color: [f32; 4]) {
+		if let Some(renderer) = &mut self.text {
+			renderer.queue(text, position, size, color);
+		}
+	}
+
+	/*
+	Renders a model from every imposter view into an atlas with the material pipelines, then
+	sets it as the model's imposter. Each view is submitted separately since they share the
+	bake camera buffer.
+	*/
+	pub fn bake_imposter(&mut self, scene: &mut scene::Scene, model_index: usize, cell_size: u32, distance: f32) {
+		// in the scene's format, the imposters draw with it
+		let mut bake_config = self.render_scale.internal_config(&self.config);
+		bake_config.width = cell_size * imposter::AZIMUTH_VIEWS;
+		bake_config.height = cell_size * imposter::ELEVATION_VIEWS;
+		let atlas = texture::Texture::create_render_target(&self.device, &bake_config, bake_config.format, wgpu::TextureUsages::RENDER_ATTACHMENT, "imposter_atlas");
+		let depth_texture = texture::Texture::create_depth_texture(&self.device, &bake_config, "imposter_depth_texture");
+		let surface_texture = texture::Texture::create_render_target(&self.device, &bake_config, Self::SURFACE_FORMAT, wgpu::TextureUsages::RENDER_ATTACHMENT, "imposter_surface_texture");
+
+		// the uniforms go through the region of the frame about to be rendered
+		self.frames.begin(&self.device);
+		self.uniform_arena.begin_frame(self.frames.index());
+		let material_offsets = scene.materials.iter()
+			.map(|material| self.uniform_arena.push(&material.params))
+			.collect::<Vec<_>>();
+		let model_offset = self.uniform_arena.push(&model::ModelInstance::new(model_index, cgmath::Matrix4::from_scale(1.0)).to_uniform());
+		if self.uniform_arena.reallocate(&self.device) {
+			self.rebind_uniform_arena();
+			for material in &mut scene.materials {
+				material.update_bind_group(&self.device, &self.texture_bind_group_layouts, &self.uniform_arena, &self.assets);
+			}
+		}
+		self.uniform_arena.upload(&self.queue);
+		let model = &scene.models[model_index];
+
+		for row in 0..imposter::ELEVATION_VIEWS {
+			for column in 0..imposter::AZIMUTH_VIEWS {
+				let view = imposter::bake_view(&model.bounds, column, row);
+				self.queue.write_buffer(&self.imposters.bake_camera_buffer, 0, bytemuck::cast_slice(&[view]));
+
+				let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+					label: Some("Imposter Bake Encoder"),
+				});
+				{
+					let first_view = row == 0 && column == 0;
+					let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+						label: Some("Imposter Bake Pass"),
+						color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+							view: &atlas.view,
+							resolve_target: None,
+							ops: wgpu::Operations {
+								load: if first_view { wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT) } else { wgpu::LoadOp::Load },
+								store: wgpu::StoreOp::Store,
+							},
+							depth_slice: None,
+						}), Some(wgpu::RenderPassColorAttachment {
+							view: &surface_texture.view,
+							resolve_target: None,
+							ops: wgpu::Operations {
+								load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+								store: wgpu::StoreOp::Discard,
+							},
+							depth_slice: None,
+						})][..self.shader_tier.color_target_count()],
+						depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+							view: &depth_texture.view,
+							depth_ops: Some(wgpu::Operations {
+								load: wgpu::LoadOp::Clear(1.0),
+								store: wgpu::StoreOp::Discard,
+							}),
+							stencil_ops: None,
+						}),
+						occlusion_query_set: None,
+						timestamp_writes: None,
+						multiview_mask: None,
+					});
+
+					render_pass.set_viewport(
+						(column * cell_size) as f32,
+						(row * cell_size) as f32,
+						cell_size as f32,
+						cell_size as f32,
+						0.0,
+						1.0,
+					);
+					render_pass.set_bind_group(1, &self.ibl.bind_group, &[]);
+					render_pass.set_bind_group(2, &self.imposters.bake_bind_group, &[model_offset]);
+					if let Some(point_shadows) = &self.point_shadows {
+						render_pass.set_bind_group(3, &point_shadows.bind_group, &[]);
+					}
+					if let Some(shadow_map) = &self.shadow_map {
+						render_pass.set_bind_group(3, &shadow_map.bind_group, &[]);
+					}
+					for mesh in &model.meshes {
+						let material = &scene.materials[mesh.material];
+						render_pass.set_pipeline(&self.render_pipelines[&material.pipeline_key()]);
+						render_pass.draw_mesh(mesh, material, material_offsets[mesh.material]);
+					}
+				}
+				self.queue.submit(std::iter::once(encoder.finish()));
+			}
+		}
+
+		let imposter = self.imposters.create_imposter(&self.device, atlas, distance);
+		scene.models[model_index].imposter = Some(imposter);
+	}
+
+	// copies part of a buffer back to the CPU, submitted on its own right away and read without blocking
+	pub fn read_buffer(&self, source: &wgpu::Buffer, offset: u64, size: u64) -> impl std::future::Future<Output = anyhow::Result<Vec<u8>>> + '_ {
+		let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+			label: Some("Readback Encoder"),
+		});
+		let readback = readback::Readback::from_buffer(&self.device, &mut encoder, source, offset, size);
+		self.queue.submit(std::iter::once(encoder.finish()));
+		readback.read(&self.device)
+	}
+
+	// copies mip 0 of a texture back to the CPU, with tightly packed rows, like read_buffer
+	pub fn read_texture(&self, texture: &wgpu::Texture) -> impl std::future::Future<Output = anyhow::Result<Vec<u8>>> + '_ {
+		let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+			label: Some("Readback Encoder"),
+		});
+		let readback = readback::Readback::from_texture(&self.device, &mut encoder, texture);
+		self.queue.submit(std::iter::once(encoder.finish()));
+		readback.read(&self.device)
+	}
+
+	// after the uniform arena's buffer was replaced, rebuilds the bind groups over it the renderer owns
+	fn rebind_uniform_arena(&mut self) {
+		let shared = SharedUniforms {
+			uniform_arena: &self.uniform_arena,
+			light_buffer: &self.lights.buffer,
+			globals_buffer: &self.globals_buffer,
+			capsule_buffer: &self.capsule_buffer,
+			vat_view: &self.vat.view,
+		};
+		self.uniform_bind_group = shared.bind_group(&self.device, &self.uniform_bind_group_layout, self.camera_buffer.as_entire_binding(), self.environment_buffer.as_entire_binding(), "camera_bind_group");
+		self.imposters.rebind(&self.device, &self.uniform_bind_group_layout, &shared, imposter::SceneBuffers { camera: &self.camera_buffer, environment: &self.environment_buffer });
+		self.virtual_cameras.rebind(&self.device, &self.uniform_bind_group_layout, &shared);
+		if let Some(meshlets) = &mut self.meshlets {
+			meshlets.rebind(&self.device, &self.camera_buffer, &self.uniform_arena);
+		}
+	}
+
+	fn write_if_changed<T: bytemuck::Pod>(&self, buffer: &wgpu::Buffer, uploaded: &Cell<T>, value: T) {
+		if bytemuck::bytes_of(&uploaded.get()) != bytemuck::bytes_of(&value) {
+			self.queue.write_buffer(buffer, 0, bytemuck::bytes_of(&value));
+			uploaded.set(value);
+		}
+	}
+
 	fn create_buffers() {
 		// camera buffer
 
@@ -399,52 +1472,89 @@ impl Renderer {
 	}
 }
 
-fn create_render_pipeline(
-	label: &str,
-	device: &wgpu::Device,
-	layout: &wgpu::PipelineLayout,
+// what render worked out for one view before drawing its scene, the scene outlives the render pass
+struct SceneDraws<'a, 'f> {
+	scene: &'a scene::Scene,
+	uniform_bind_group: &'f wgpu::BindGroup,
+	eye: cgmath::Point3<f32>,
+	material_offsets: &'f [u32],
+	object_offsets: &'f [Option<u32>], // None for objects that aren't drawn
+	instance_batches: &'f instancing::InstanceBatches,
+	imposter_offsets: &'f [Option<u32>],
+	meshlet_draws: &'f HashMap<(usize, usize), meshlet::MeshletDraws>,
+	crowd_offsets: &'f [u32],
+}
+
+// a blended draw waiting to be sorted by distance
+enum SortedDraw {
+	Object(usize, u32), // with its model uniform offset
+	Crowd(usize),
+}
+
+// a material pipeline for create_render_pipeline, surface_format adds the normal and roughness target
+struct PipelineDescriptor<'a> {
+	label: &'a str,
+	layout: &'a wgpu::PipelineLayout,
 	color_format: wgpu::TextureFormat,
+	surface_format: Option<wgpu::TextureFormat>,
 	depth_format: Option<wgpu::TextureFormat>,
-	vertex_layouts: &[wgpu::VertexBufferLayout],
-	shader: wgpu::ShaderModuleDescriptor,
-) -> wgpu::RenderPipeline {
-	let shader = device.create_shader_module(shader);
+	vertex_layouts: &'a [wgpu::VertexBufferLayout<'a>],
+	shader: &'a wgpu::ShaderModule,
+	fragment_entry: &'a str,
+	polygon_mode: wgpu::PolygonMode,
+	blend: model::BlendMode,
+	cull: model::CullMode,
+}
+
+fn create_render_pipeline(device: &wgpu::Device, descriptor: &PipelineDescriptor) -> wgpu::RenderPipeline {
+	let &PipelineDescriptor { label, layout, color_format, surface_format, depth_format, vertex_layouts, shader, fragment_entry, polygon_mode, blend, cull } = descriptor;
+	let targets = [
+		Some(wgpu::ColorTargetState {
+			format: color_format,
+			blend: Some(blend.blend_state()),
+			write_mask: wgpu::ColorWrites::ALL,
+		}),
+		// only opaque surfaces write their normal and roughness
+		surface_format.map(|format| wgpu::ColorTargetState {
+			format,
+			blend: None,
+			write_mask: if blend == model::BlendMode::Opaque {
+				wgpu::ColorWrites::ALL
+			} else {
+				wgpu::ColorWrites::empty()
+			},
+		}),
+	];
+	let target_count = if surface_format.is_some() { 2 } else { 1 };
 
 	device.create_render_pipeline(&wgpu::RenderPipelineDescriptor{
 		label: Some(label),
 		layout: Some(layout),
 		vertex: wgpu::VertexState {
-			module: &shader,
+			module: shader,
 			entry_point: Some("vs_main"),
 			buffers: vertex_layouts,
 			compilation_options: Default::default(),
 		},
 		fragment: Some(wgpu::FragmentState {
-			module: &shader,
-			entry_point: Some("fs_main"),
-			targets: &[Some(wgpu::ColorTargetState {
-				format: color_format,
-				blend: Some(wgpu::BlendState {
-					alpha: wgpu::BlendComponent::REPLACE,
-					color: wgpu::BlendComponent::REPLACE,
-				}),
-				write_mask: wgpu::ColorWrites::ALL,
-			})],
+			module: shader,
+			entry_point: Some(fragment_entry),
+			targets: &targets[..target_count],
 			compilation_options: Default::default(),
 		}),
 		primitive: wgpu::PrimitiveState {
 			topology: wgpu::PrimitiveTopology::TriangleList,
 			strip_index_format: None,
 			front_face: wgpu::FrontFace::Ccw,
-			cull_mode: Some(wgpu::Face::Back),
-			polygon_mode: wgpu::PolygonMode::Fill,
+			cull_mode: cull.face(),
+			polygon_mode,
 			unclipped_depth: false,
 			conservative: false,
 		},
 		depth_stencil: depth_format.map(|format| wgpu::DepthStencilState {
 			format,
-			depth_write_enabled: true,
-			depth_compare: wgpu::CompareFunction::Less,
+			depth_write_enabled: Some(blend == model::BlendMode::Opaque),
+			depth_compare: Some(wgpu::CompareFunction::Less),
 			stencil: wgpu::StencilState::default(),
 			bias: wgpu::DepthBiasState::default(),
 		}),
@@ -456,4 +1566,30 @@ fn create_render_pipeline(
 		multiview_mask: None,
 		cache: None,
 	})
+}
+
+/*
+The buffers every bind group in the layout of the renderer's uniform bind group shares, the
+virtual cameras and the imposter bake bind the same ones with their own camera.
+*/
+pub(crate) struct SharedUniforms<'a> {
+	pub uniform_arena: &'a uniform_arena::UniformArena,
+	pub light_buffer: &'a wgpu::Buffer,
+	pub globals_buffer: &'a wgpu::Buffer,
+	pub capsule_buffer: &'a wgpu::Buffer,
+	pub vat_view: &'a wgpu::TextureView,
+}
+
+impl SharedUniforms<'_> {
+	pub fn bind_group(&self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout, camera: wgpu::BindingResource, environment: wgpu::BindingResource, label: &str) -> wgpu::BindGroup {
+		bind_group::BindGroupBuilder::default()
+			.resource(0, camera)
+			.resource(1, model::ModelUniform::binding(self.uniform_arena))
+			.buffer(3, self.light_buffer)
+			.buffer(4, self.globals_buffer)
+			.resource(5, environment)
+			.buffer(6, self.capsule_buffer)
+			.view(7, self.vat_view)
+			.build(device, layout, label)
+	}
 }
\ No newline at end of file
@@ -1,7 +1,9 @@
 use std::fmt::format;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::io::{BufReader, Cursor};
 use wgpu::util::DeviceExt;
-use crate::{model, texture, scene, renderer};
+use crate::{model, modifier, unwrap, texture, scene, renderer, material_file, scene_file, ray_tracing, color};
 
 #[cfg(target_arch = "wasm32")]
 fn format_url(filename: &str) -> reqwest::Url {
@@ -15,7 +17,190 @@ fn format_url(filename: &str) -> reqwest::Url {
 	base.join(filename).unwrap()
 }
 
+thread_local! {
+	// files added at runtime (dropped onto the canvas, etc.), looked up before the res folder
+	static MEMORY_FILES: RefCell<HashMap<String, Vec<u8>>> = RefCell::new(HashMap::new());
+	// memory files that were already decoded off the render thread, see decode_files
+	static DECODED_FILES: RefCell<HashMap<String, DecodedFile>> = RefCell::new(HashMap::new());
+}
+
+// a memory file decoded ahead of the load that uses it
+#[derive(Clone)]
+pub enum DecodedFile {
+	Image(image::DynamicImage),
+	Obj(ObjFile),
+}
+
+// an OBJ model's meshes, unwrapped where they had no uvs, and the materials of its .mtl
+#[derive(Clone)]
+pub struct ObjFile {
+	meshes: Vec<(Vec<model::ModelVertex>, Vec<u32>, Option<usize>)>, // with the index of its material
+	materials: Vec<tobj::Material>,
+}
+
+pub fn add_memory_file(filename: &str, data: Vec<u8>) {
+	MEMORY_FILES.with(|files| files.borrow_mut().insert(filename.to_string(), data));
+}
+
+pub fn remove_memory_file(filename: &str) {
+	MEMORY_FILES.with(|files| files.borrow_mut().remove(filename));
+}
+
+pub fn add_decoded_file(filename: &str, file: DecodedFile) {
+	DECODED_FILES.with(|files| files.borrow_mut().insert(filename.to_string(), file));
+}
+
+pub fn remove_decoded_file(filename: &str) {
+	DECODED_FILES.with(|files| files.borrow_mut().remove(filename));
+}
+
+fn decoded_file(filename: &str) -> Option<DecodedFile> {
+	DECODED_FILES.with(|files| files.borrow().get(filename).cloned())
+}
+
+// the image a texture load can upload without decoding it
+pub fn decoded_image(filename: &str) -> Option<image::DynamicImage> {
+	match decoded_file(filename) {
+		Some(DecodedFile::Image(image)) => Some(image),
+		_ => None,
+	}
+}
+
+/*
+Decodes the images and parses the OBJ models among files read with read_files, without creating
+any GPU resources, so it can run on the thread that read them. Loads find the results once they're
+added with add_decoded_file. A file that doesn't decode is left out, for its load to report.
+*/
+pub fn decode_files(files: &HashMap<String, Vec<u8>>) -> HashMap<String, DecodedFile> {
+	// the .mtl files the models reference are read through the memory files
+	for (name, data) in files {
+		add_memory_file(name, data.clone());
+	}
+	let mut decoded = HashMap::new();
+	for (name, data) in files {
+		let extension = std::path::Path::new(name).extension().and_then(|extension| extension.to_str());
+		let file = match extension.map(str::to_lowercase).as_deref() {
+			Some("obj") => load_from_memory(parse_obj(name)).map(DecodedFile::Obj),
+			Some("png" | "jpg" | "jpeg" | "hdr") => image::load_from_memory(data).map(DecodedFile::Image).map_err(Into::into),
+			_ => continue,
+		};
+		if let Ok(file) = file {
+			decoded.insert(name.clone(), file);
+		}
+	}
+	for name in files.keys() {
+		remove_memory_file(name);
+	}
+	decoded
+}
+
+// falls back to the bare file name, since materials often reference textures in subfolders
+fn memory_file(filename: &str) -> Option<Vec<u8>> {
+	MEMORY_FILES.with(|files| {
+		let files = files.borrow();
+		files.get(filename)
+			.or_else(|| std::path::Path::new(filename).file_name()
+				.and_then(|name| files.get(name.to_str()?)))
+			.cloned()
+	})
+}
+
+/*
+Runs a load that only reads memory files to completion. Those never wait, so a load that is
+still pending after one poll is waiting on a file that hasn't been added.
+*/
+pub fn load_from_memory<T>(load: impl std::future::Future<Output = anyhow::Result<T>>) -> anyhow::Result<T> {
+	let mut load = std::pin::pin!(load);
+	match load.as_mut().poll(&mut std::task::Context::from_waker(std::task::Waker::noop())) {
+		std::task::Poll::Ready(result) => result,
+		std::task::Poll::Pending => Err(anyhow::anyhow!("load needs a file that hasn't been added")),
+	}
+}
+
+// path of a file referenced from another file, relative to the referencing file
+fn relative_path(filename: &str, path: &str) -> String {
+	let directory = std::path::Path::new(filename).parent().unwrap_or(std::path::Path::new(""));
+	directory.join(path).to_string_lossy().into_owned()
+}
+
+/*
+Reads a file and every file it references (models, materials, and textures) into the map without
+creating any GPU resources, so it can run off the render loop. The files are then added as
+memory files and loaded with load_from_memory. Missing references are left for the load to
+report, since some of them (material files) are optional.
+*/
+pub async fn read_files(filename: &str, files: &mut HashMap<String, Vec<u8>>) -> anyhow::Result<()> {
+	if files.contains_key(filename) {
+		return Ok(());
+	}
+	let data = load_binary(filename).await?;
+
+	let extension = std::path::Path::new(filename).extension().and_then(|extension| extension.to_str());
+	let mut references = vec![];
+	match extension {
+		Some("obj") => {
+			for line in std::str::from_utf8(&data)?.lines() {
+				if let Some(mtl) = line.trim().strip_prefix("mtllib ") {
+					references.push(relative_path(filename, mtl.trim()));
+				}
+			}
+		}
+		Some("mtl") => {
+			let (materials, _) = tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(&data)))?;
+			for m in materials {
+				for texture in [&m.diffuse_texture, &m.normal_texture] {
+					if !texture.is_empty() {
+						references.push(relative_path(filename, texture));
+					}
+				}
+			}
+		}
+		#[cfg(feature = "gltf")]
+		Some("gltf" | "glb") => {
+			let gltf = gltf::Gltf::from_slice(&data)?;
+			let buffers = gltf.buffers().filter_map(|buffer| match buffer.source() {
+				gltf::buffer::Source::Uri(uri) => Some(uri),
+				gltf::buffer::Source::Bin => None,
+			});
+			let images = gltf.images().filter_map(|image| match image.source() {
+				gltf::image::Source::Uri { uri, .. } => Some(uri),
+				gltf::image::Source::View { .. } => None,
+			});
+			for uri in buffers.chain(images).filter(|uri| !uri.starts_with("data:")) {
+				references.push(relative_path(filename, uri));
+			}
+		}
+		Some("ron") => {
+			let scene = scene_file::parse(std::str::from_utf8(&data)?)?;
+			references.extend(scene.materials);
+			references.extend(scene.objects.into_iter().map(|object| object.model));
+		}
+		Some("toml") => {
+			for def in material_file::parse(std::str::from_utf8(&data)?)?.values() {
+				references.extend(def.textures().into_iter().map(|(path, _)| path.to_string()));
+			}
+		}
+		_ => {}
+	}
+	files.insert(filename.to_string(), data);
+
+	for reference in references {
+		if let Err(e) = Box::pin(read_files(&reference, files)).await {
+			log::warn!("Unable to read {} referenced by {}: {}", reference, filename, e);
+		}
+	}
+	Ok(())
+}
+
+// where a file named relative to the res folder is on disk, the web build fetches the same path
+pub fn res_path(filename: &str) -> std::path::PathBuf {
+	std::path::Path::new("src/res").join(filename)
+}
+
 pub async fn load_string(filename: &str) -> anyhow::Result<String> {
+	if let Some(data) = memory_file(filename) {
+		return Ok(String::from_utf8(data)?);
+	}
 	#[cfg(target_arch = "wasm32")]
 	let txt = {
 		let url = format_url(&format!("src/res/{}", filename).as_str());
@@ -23,13 +208,15 @@ pub async fn load_string(filename: &str) -> anyhow::Result<String> {
 	};
 	#[cfg(not(target_arch = "wasm32"))]
 	let txt = {
-		let path = std::path::Path::new("src/res").join(filename);
-		std::fs::read_to_string(path)?
+		std::fs::read_to_string(res_path(filename))?
 	};
 	Ok(txt)
 }
 
 pub async fn load_binary(filename: &str) -> anyhow::Result<Vec<u8>> {
+	if let Some(data) = memory_file(filename) {
+		return Ok(data);
+	}
 	#[cfg(target_arch = "wasm32")]
 	let data = {
 		let url = format_url(&format!("src/res/{}", filename).as_str());
@@ -37,12 +224,41 @@ pub async fn load_binary(filename: &str) -> anyhow::Result<Vec<u8>> {
 	};
 	#[cfg(not(target_arch = "wasm32"))]
 	let data = {
-		let path = std::path::Path::new("src/res").join(filename);
-		std::fs::read(path)?
+		std::fs::read(res_path(filename))?
 	};
 	Ok(data)
 }
 
+/*
+Writes a file named relative to the res folder. The web build can't write there, so it keeps
+the file as a memory file for the rest of the session, where loads find it, and offers it as a
+download.
+*/
+pub fn write_file(filename: &str, data: Vec<u8>) -> anyhow::Result<()> {
+	#[cfg(target_arch = "wasm32")]
+	{
+		download(filename, &data)?;
+		add_memory_file(filename, data);
+	}
+	#[cfg(not(target_arch = "wasm32"))]
+	std::fs::write(res_path(filename), data)?;
+	Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn download(filename: &str, data: &[u8]) -> anyhow::Result<()> {
+	use wasm_bindgen::JsCast;
+	let js_error = |e: wasm_bindgen::JsValue| anyhow::anyhow!("unable to download {}: {:?}", filename, e);
+	let blob = web_sys::Blob::new_with_u8_array_sequence(&js_sys::Array::of1(&js_sys::Uint8Array::from(data))).map_err(js_error)?;
+	let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(js_error)?;
+	let document = web_sys::window().and_then(|window| window.document()).ok_or_else(|| anyhow::anyhow!("no document to download {} from", filename))?;
+	let link = document.create_element("a").map_err(js_error)?.unchecked_into::<web_sys::HtmlAnchorElement>();
+	link.set_href(&url);
+	link.set_download(std::path::Path::new(filename).file_name().and_then(|name| name.to_str()).unwrap_or(filename));
+	link.click();
+	web_sys::Url::revoke_object_url(&url).map_err(js_error)
+}
+
 pub async fn load_texture(filename: &str, ty: texture::TextureType, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<texture::Texture> {
 	let data = load_binary(filename).await?;
 	texture::Texture::from_bytes(device, queue, &data, filename, ty)
@@ -58,14 +274,160 @@ pub async fn load_cubemap_texture(foldername: &str, device: &wgpu::Device, queue
 	texture::Texture::from_images(device, queue, &imgs, Some(foldername), texture::TextureType::Cubemap)
 }
 
-struct TobjGeometry<'a> {
+/*
+Builds a cubemap from an equirectangular panorama, like the .hdr files skies are usually shipped
+as. Each face texel takes the nearest panorama pixel in its direction. HDR images keep their
+range, other formats are taken as sRGB. face_size defaults to a quarter of the panorama's width.
+*/
+pub async fn load_equirect_cubemap(filename: &str, face_size: Option<u32>, device: &wgpu::Device, queue: &wgpu::Queue) -> anyhow::Result<texture::Texture> {
+	use std::f32::consts::PI;
+	let data = load_binary(filename).await?;
+	let img = image::load_from_memory(&data)?;
+	let linear = matches!(img, image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_));
+	let panorama = img.to_rgb32f();
+	let (width, height) = panorama.dimensions();
+	let size = face_size.unwrap_or(width / 4).max(1);
+
+	// the direction through a face at u, v from -1 to 1, v pointing down, in cube face order
+	let direction = |face: usize, u: f32, v: f32| match face {
+		0 => cgmath::Vector3::new(1.0, -v, -u),
+		1 => cgmath::Vector3::new(-1.0, -v, u),
+		2 => cgmath::Vector3::new(u, 1.0, v),
+		3 => cgmath::Vector3::new(u, -1.0, -v),
+		4 => cgmath::Vector3::new(u, -v, 1.0),
+		_ => cgmath::Vector3::new(-u, -v, -1.0),
+	};
+	let faces = (0..6)
+		.map(|face| {
+			(0..size * size)
+				.map(|i| {
+					use cgmath::InnerSpace;
+					let u = ((i % size) as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+					let v = ((i / size) as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+					let dir = direction(face, u, v).normalize();
+					let x = (0.5 + dir.x.atan2(-dir.z) / (2.0 * PI)) * width as f32;
+					let y = dir.y.clamp(-1.0, 1.0).acos() / PI * height as f32;
+					let pixel = panorama.get_pixel((x as u32).min(width - 1), (y as u32).min(height - 1)).0;
+					if linear { pixel } else { pixel.map(color::srgb_to_linear) }
+				})
+				.collect::<Vec<_>>()
+		})
+		.collect::<Vec<_>>();
+	Ok(texture::Texture::hdr_cubemap(device, queue, &faces, size, filename))
+}
+
+/*
+Loads every material in a material definition file into the scene, replacing
+materials that already have the same name. Returns the scene material ids.
+*/
+pub async fn load_materials(filename: &str, renderer: &renderer::Renderer, scene: &mut scene::Scene) -> anyhow::Result<Vec<usize>> {
+	let text = load_string(filename).await?;
+	let definitions = material_file::parse(&text)?;
+	if !scene.material_files.iter().any(|file| file == filename) {
+		scene.material_files.push(filename.to_string());
+	}
+
+	let mut material_ids = vec![];
+	for (name, def) in definitions {
+		if def.layers.len() > model::MaterialType::LAYERS {
+			anyhow::bail!("material {:?} in {:?} has {} layers, at most {} are blended", name, filename, def.layers.len(), model::MaterialType::LAYERS);
+		}
+
+		let mut handles = HashMap::new();
+		for (path, texture_type) in def.textures() {
+			let handle = renderer.assets.load_texture(path, texture_type, &renderer.device, &renderer.queue).await?;
+			handles.insert((path, texture_type), handle);
+		}
+		let texture = |path: &Option<String>, texture_type| path.as_deref().map(|path| handles[&(path, texture_type)]);
+		let diffuse_texture = texture(&def.diffuse_texture, texture::TextureType::Diffuse);
+		let normal_texture = texture(&def.normal_texture, texture::TextureType::Normal);
+		let detail_diffuse_texture = texture(&def.detail_diffuse_texture, texture::TextureType::Normal);
+		let detail_normal_texture = texture(&def.detail_normal_texture, texture::TextureType::Normal);
+		let mask_texture = texture(&def.mask_texture, texture::TextureType::Normal);
+		let layer_textures = def.layers.iter()
+			.map(|layer| (texture(&layer.diffuse_texture, texture::TextureType::Diffuse), texture(&layer.normal_texture, texture::TextureType::Normal)))
+			.collect::<Vec<_>>();
+		let ramp_texture = texture(&def.ramp_texture, texture::TextureType::Diffuse);
+
+		let material_type = match (def.shader, diffuse_texture, normal_texture, detail_diffuse_texture, detail_normal_texture) {
+			(material_file::ShaderVariant::Color, ..) => model::MaterialType::SingleColorMaterial(def.color),
+			(material_file::ShaderVariant::Diffuse, Some(diffuse), ..) => model::MaterialType::DiffuseMapMaterial(diffuse),
+			(material_file::ShaderVariant::DiffuseNormal, Some(diffuse), Some(normal), ..) => model::MaterialType::DiffuseNormalMapMaterial(diffuse, normal),
+			(material_file::ShaderVariant::Detail, Some(diffuse), Some(normal), Some(detail_diffuse), Some(detail_normal)) => {
+				model::MaterialType::DetailMapMaterial(diffuse, normal, detail_diffuse, detail_normal)
+			}
+			(material_file::ShaderVariant::Layered, Some(diffuse), Some(normal), ..) => {
+				let Some(mask) = mask_texture else {
+					anyhow::bail!("material {:?} in {:?} is missing a mask_texture for the layered shader", name, filename);
+				};
+				// layers that aren't defined have no weight, and sample the base's textures so the binding isn't empty
+				let layers = std::array::from_fn(|index| match layer_textures.get(index) {
+					Some(&(layer_diffuse, layer_normal)) => (layer_diffuse.unwrap_or(diffuse), layer_normal.unwrap_or(normal)),
+					None => (diffuse, normal),
+				});
+				model::MaterialType::LayeredMaterial(diffuse, normal, mask, layers)
+			}
+			(material_file::ShaderVariant::Toon, Some(diffuse), ..) => {
+				let Some(ramp) = ramp_texture else {
+					anyhow::bail!("material {:?} in {:?} is missing a ramp_texture for the toon shader", name, filename);
+				};
+				model::MaterialType::ToonMaterial(diffuse, ramp)
+			}
+			(shader, ..) => anyhow::bail!("material {:?} in {:?} is missing textures for the {:?} shader", name, filename, shader),
+		};
+
+		let mut material = model::Material::new(
+			&renderer.device,
+			&name,
+			material_type,
+			def.params(),
+			&renderer.texture_bind_group_layouts,
+			&renderer.uniform_arena,
+			&renderer.assets,
+		);
+		material.blend = def.blend;
+		material.cull = def.cull;
+
+		let material_id = match scene.get_material(&name) {
+			Some(material_id) => {
+				scene.materials[material_id] = material;
+				material_id
+			}
+			None => scene.add_material(material),
+		};
+		material_ids.push(material_id);
+	}
+
+	Ok(material_ids)
+}
+
+// converts the .mtl Kd/Ks/Ns/d/illum values into material parameters
+fn mtl_params(m: &tobj::Material) -> model::SimpleMaterial {
+	let illum = m.illumination_model.unwrap_or(2);
+	// only the reflective illumination models (3 and up) sample the environment
+	let reflectance = if illum >= 3 {
+		m.specular[0].max(m.specular[1]).max(m.specular[2])
+	} else {
+		0.0
+	};
+
+	let mut params = model::SimpleMaterial::new();
+	params.diffuse_spec = [m.diffuse[0], m.diffuse[1], m.diffuse[2], reflectance];
+	params.specular_shininess = [m.specular[0], m.specular[1], m.specular[2], m.shininess];
+	params.opacity = m.dissolve;
+	params.illum = illum.min(2) as u32;
+	params
+}
+
+// a mesh's vertices and triangles, for generating its tangents with mikktspace
+struct MeshGeometry<'a> {
 	vertices: Vec<model::ModelVertex>,
-	indices: &'a Vec<u32>,
+	indices: &'a [u32],
 }
 
-impl<'a> TobjGeometry<'a> {
+impl<'a> MeshGeometry<'a> {
 	fn from_tobj_mesh(tobj_mesh: &'a tobj::Mesh) -> Self {
-		Self {
+		let mut geometry = Self {
 			vertices: (0..tobj_mesh.positions.len() / 3).map(|i| {
 				model::ModelVertex {
 				position: [
@@ -73,24 +435,54 @@ impl<'a> TobjGeometry<'a> {
 					tobj_mesh.positions[i * 3 + 1],
 					tobj_mesh.positions[i * 3 + 2],
 				],
-				tex_coords: [
-					tobj_mesh.texcoords[i * 2],
-					1.0 - tobj_mesh.texcoords[i * 2 + 1],
-				],
-				normal: [
-					tobj_mesh.normals[i * 3],
-					tobj_mesh.normals[i * 3 + 1],
-					tobj_mesh.normals[i * 3 + 2],
-				],
+				// meshes without uvs are unwrapped once they're read
+				tex_coords: if tobj_mesh.texcoords.is_empty() {
+					[0.0; 2]
+				} else {
+					[tobj_mesh.texcoords[i * 2], 1.0 - tobj_mesh.texcoords[i * 2 + 1]]
+				},
+				// and meshes without normals get smooth ones below
+				normal: if tobj_mesh.normals.is_empty() {
+					[0.0; 3]
+				} else {
+					[
+						tobj_mesh.normals[i * 3],
+						tobj_mesh.normals[i * 3 + 1],
+						tobj_mesh.normals[i * 3 + 2],
+					]
+				},
 				tangent: [0.0; 4],
+				lightmap_coords: [0.0; 2],
 			}
 			}).collect::<Vec<_>>(),
 			indices: &tobj_mesh.indices,
+		};
+		if tobj_mesh.normals.is_empty() {
+			geometry.compute_normals();
+		}
+		geometry
+	}
+
+	// smooth normals weighted by triangle area, for meshes that come without them
+	fn compute_normals(&mut self) {
+		use cgmath::InnerSpace;
+		let mut normals = vec![cgmath::Vector3::new(0.0, 0.0, 0.0); self.vertices.len()];
+		for triangle in self.indices.chunks_exact(3) {
+			let [a, b, c] = [0, 1, 2].map(|i| cgmath::Vector3::from(self.vertices[triangle[i] as usize].position));
+			let normal = (b - a).cross(c - a);
+			for &index in triangle {
+				normals[index as usize] += normal;
+			}
+		}
+		for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+			if normal.magnitude2() > 0.0 {
+				vertex.normal = normal.normalize().into();
+			}
 		}
 	}
 }
 
-impl <'a>mikktspace::Geometry for TobjGeometry<'a> {
+impl <'a>mikktspace::Geometry for MeshGeometry<'a> {
 	fn num_faces(&self) -> usize {
 		self.indices.len() / 3
 	}
@@ -115,8 +507,176 @@ impl <'a>mikktspace::Geometry for TobjGeometry<'a> {
 	}
 }
 
+// uploads a mesh's buffers, with its BLAS and meshlets when those are supported
+/*
+The plain grey material of meshes that don't name one, or whose material file is missing.
+Made the first time a model needs it and found by name after that.
+*/
+fn fallback_material(renderer: &renderer::Renderer, scene: &mut scene::Scene) -> usize {
+	const NAME: &str = "fallback";
+	if let Some(material_id) = scene.get_material(NAME) {
+		return material_id;
+	}
+	scene.add_material(model::Material::new(
+		&renderer.device,
+		NAME,
+		model::MaterialType::SingleColorMaterial([0.8; 3]),
+		model::SimpleMaterial::new(),
+		&renderer.texture_bind_group_layouts,
+		&renderer.uniform_arena,
+		&renderer.assets,
+	))
+}
+
+fn create_mesh(renderer: &renderer::Renderer, filename: &str, vertices: &[model::ModelVertex], indices: &[u32], material: usize) -> model::Mesh {
+	// meshes are also ray traced against when it's supported
+	let blas_input = if renderer.ray_tracing.is_some() {
+		wgpu::BufferUsages::BLAS_INPUT
+	} else {
+		wgpu::BufferUsages::empty()
+	};
+
+	let vertex_buffer = renderer.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+		label: Some(&format!("{:?} Vertex Buffer", filename)),
+		contents: bytemuck::cast_slice(vertices),
+		usage: wgpu::BufferUsages::VERTEX | blas_input,
+	});
+	let index_buffer = renderer.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+		label: Some(&format!("{:?} Index Buffer", filename)),
+		contents: bytemuck::cast_slice(indices),
+		usage: wgpu::BufferUsages::INDEX | blas_input,
+	});
+
+	let blas = renderer.ray_tracing.as_ref().map(|_| {
+		let mut encoder = renderer.device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+			label: Some("BLAS Encoder"),
+		});
+		let blas = ray_tracing::RayTracing::build_blas(
+			&renderer.device,
+			&mut encoder,
+			&vertex_buffer,
+			vertices.len() as u32,
+			&index_buffer,
+			indices.len() as u32,
+		);
+		renderer.queue.submit(std::iter::once(encoder.finish()));
+		blas
+	});
+
+	let meshlets = renderer.meshlets.as_ref()
+		.and_then(|meshlets| meshlets.build(&renderer.device, vertices, indices));
+
+	model::Mesh {
+		name: filename.to_string(),
+		vertex_buffer,
+		index_buffer,
+		num_elements: indices.len() as u32,
+		material,
+		blas,
+		meshlets,
+	}
+}
+
+// the bounds of a model being loaded, grown by each of its meshes
+#[derive(Default)]
+struct ModelBounds {
+	bounds: Option<model::Bounds>,
+	aabb: Option<model::Aabb>,
+}
+
+impl ModelBounds {
+	// grows the model's bounds to fit the mesh
+	fn add(&mut self, vertices: &[model::ModelVertex]) {
+		let mesh_bounds = model::Bounds::from_vertices(vertices);
+		self.bounds = Some(match &self.bounds {
+			Some(bounds) => mesh_bounds.union(bounds),
+			None => mesh_bounds,
+		});
+		let mesh_aabb = model::Aabb::from_vertices(vertices);
+		self.aabb = Some(match &self.aabb {
+			Some(aabb) => mesh_aabb.union(aabb),
+			None => mesh_aabb,
+		});
+	}
+}
+
+/*
+Applies the modifiers to a mesh with its normals in place, then generates its tangents and
+uploads it, growing the model's bounds
+*/
+fn finish_mesh(
+	renderer: &renderer::Renderer,
+	filename: &str,
+	vertices: Vec<model::ModelVertex>,
+	indices: Vec<u32>,
+	material: usize,
+	modifiers: &modifier::ModifierStack,
+	bounds: &mut ModelBounds,
+) -> model::Mesh {
+	let (vertices, indices) = modifiers.apply(vertices, indices);
+	let vertices = generate_tangents(vertices, &indices);
+	bounds.add(&vertices);
+	create_mesh(renderer, filename, &vertices, &indices, material)
+}
+
+// an automatic unwrap for a mesh without uvs, so it can still be textured and baked, both channels get the generated uvs
+fn unwrap_missing_uvs(filename: &str, vertices: Vec<model::ModelVertex>, indices: &[u32]) -> (Vec<model::ModelVertex>, Vec<u32>) {
+	log::info!("{} has a mesh without uvs, unwrapping it", filename);
+	let (mut vertices, indices) = unwrap::unwrap(&vertices, indices, &unwrap::UnwrapSettings::default()).into_geometry();
+	for vertex in &mut vertices {
+		vertex.tex_coords = vertex.lightmap_coords;
+	}
+	(vertices, indices)
+}
+
+// the tangents every mesh is uploaded with, for CPU tools that need the same basis
+pub fn generate_tangents(vertices: Vec<model::ModelVertex>, indices: &[u32]) -> Vec<model::ModelVertex> {
+	let mut geometry = MeshGeometry {
+		vertices,
+		indices,
+	};
+	mikktspace::generate_tangents(&mut geometry);
+	geometry.vertices
+}
+
+fn finish_model(meshes: Vec<model::Mesh>, bounds: ModelBounds) -> model::Model {
+	model::Model {
+		meshes,
+		bounds: bounds.bounds.unwrap_or(model::Bounds { center: [0.0; 3], radius: 0.0 }),
+		aabb: bounds.aabb.unwrap_or(model::Aabb { min: [0.0; 3], max: [0.0; 3] }),
+		imposter: None,
+	}
+}
+
+// a model from generated geometry, as vertices with normals, triangles, and a scene material per mesh
+pub fn create_model(
+	name: &str,
+	meshes: Vec<(Vec<model::ModelVertex>, Vec<u32>, usize)>,
+	modifiers: &modifier::ModifierStack,
+	renderer: &renderer::Renderer,
+) -> model::Model {
+	let mut bounds = ModelBounds::default();
+	let meshes = meshes.into_iter()
+		.map(|(vertices, indices, material)| finish_mesh(renderer, name, vertices, indices, material, modifiers, &mut bounds))
+		.collect();
+	finish_model(meshes, bounds)
+}
+
+// OBJ files, or glTF by extension, a file the scene has already loaded gives the same model
 pub async fn load_model(filename: &str, renderer: &renderer::Renderer, scene: &mut scene::Scene) -> anyhow::Result<usize> {
+	if let Some(model_index) = scene.file_model(filename) {
+		return Ok(model_index);
+	}
+	let model_index = load_modified_model(filename, &modifier::ModifierStack::default(), renderer, scene).await?;
+	scene.set_file_model(filename, model_index);
+	Ok(model_index)
+}
+
+// an OBJ file's meshes and materials, without creating any GPU resources
+async fn parse_obj(filename: &str) -> anyhow::Result<ObjFile> {
 	let obj_text = load_string(filename).await?;
+	// material files are relative to the obj
+	let relative = |path: &str| relative_path(filename, path);
 	let obj_cursor = Cursor::new(obj_text);
 	let mut obj_reader = BufReader::new(obj_cursor);
 
@@ -128,71 +688,320 @@ pub async fn load_model(filename: &str, renderer: &renderer::Renderer, scene: &m
 			..Default::default()
 		},
 		|p| async move {
-			let mat_text = load_string(&p).await.unwrap();
+			let mat_text = load_string(&relative(&p)).await.map_err(|_| tobj::LoadError::OpenFileFailed)?;
 			tobj::load_mtl_buf(&mut BufReader::new(Cursor::new(mat_text)))
 		},
 	).await?;
 
-	let mut material_ids = vec![]; // mapped ids to scene materials
-	material_ids.push(0);
-	for m in obj_materials? {
+	// a missing material file leaves the meshes with the fallback material
+	let obj_materials = obj_materials.unwrap_or_else(|e| {
+		log::warn!("Unable to load the materials of {}: {}", filename, e);
+		vec![]
+	});
+
+	let meshes = models.into_iter().map(|m| {
+		let vertices = MeshGeometry::from_tobj_mesh(&m.mesh).vertices;
+		let (vertices, indices) = if m.mesh.texcoords.is_empty() {
+			unwrap_missing_uvs(filename, vertices, &m.mesh.indices)
+		} else {
+			(vertices, m.mesh.indices)
+		};
+		(vertices, indices, m.mesh.material_id)
+	}).collect();
+	Ok(ObjFile {
+		meshes,
+		materials: obj_materials,
+	})
+}
+
+// like load_model, with the modifiers applied to every mesh
+pub async fn load_modified_model(filename: &str, modifiers: &modifier::ModifierStack, renderer: &renderer::Renderer, scene: &mut scene::Scene) -> anyhow::Result<usize> {
+	let extension = std::path::Path::new(filename).extension().and_then(|extension| extension.to_str());
+	if matches!(extension.map(str::to_lowercase).as_deref(), Some("gltf" | "glb")) {
+		#[cfg(feature = "gltf")]
+		return load_gltf(filename, modifiers, renderer, scene).await;
+		#[cfg(not(feature = "gltf"))]
+		anyhow::bail!("{} is a glTF model, loading them needs the gltf feature", filename);
+	}
+
+	let obj = match decoded_file(filename) {
+		Some(DecodedFile::Obj(obj)) => obj,
+		_ => parse_obj(filename).await?,
+	};
+	// textures are relative to the obj
+	let relative = |path: &str| relative_path(filename, path);
+
+	let mut material_ids = vec![]; // OBJ material index to scene material
+	for m in obj.materials {
 
 		if let Some(material_id) = scene.get_material(&m.name) {
 			material_ids.push(material_id);
 		} else {
-			let diffuse_texture = load_texture(
-				&m.diffuse_texture,
-				texture::TextureType::Diffuse,
-				&renderer.device,
-				&renderer.queue,
-			).await?;
-			let normal_texture = load_texture(
-				&m.normal_texture,
-				texture::TextureType::Normal,
-				&renderer.device,
-				&renderer.queue,
-			).await?;
-
-			let material = model::Material::new(
+			let material_type = if m.diffuse_texture.is_empty() {
+				model::MaterialType::SingleColorMaterial(m.diffuse)
+			} else {
+				let diffuse_texture = renderer.assets.load_texture(
+					&relative(&m.diffuse_texture),
+					texture::TextureType::Diffuse,
+					&renderer.device,
+					&renderer.queue,
+				).await?;
+
+				if m.normal_texture.is_empty() {
+					model::MaterialType::DiffuseMapMaterial(diffuse_texture)
+				} else {
+					let normal_texture = renderer.assets.load_texture(
+						&relative(&m.normal_texture),
+						texture::TextureType::Normal,
+						&renderer.device,
+						&renderer.queue,
+					).await?;
+					model::MaterialType::DiffuseNormalMapMaterial(diffuse_texture, normal_texture)
+				}
+			};
+
+			let mut material = model::Material::new(
 				&renderer.device, 
 				&m.name,
-				diffuse_texture,
-				normal_texture,
-				&renderer.texture_bind_group_layouts[1],
+				material_type,
+				mtl_params(&m),
+				&renderer.texture_bind_group_layouts,
+				&renderer.uniform_arena,
+				&renderer.assets,
 			);
+			if m.dissolve < 1.0 {
+				material.blend = model::BlendMode::Alpha;
+			}
 			material_ids.push(scene.add_material(material));
 		}
 	}
 
-	let meshes = models.into_iter().map(|m| {
-		// create tobj
-		let mut mesh = TobjGeometry::from_tobj_mesh(&m.mesh);
+	let mut bounds = ModelBounds::default();
+	let meshes = obj.meshes.into_iter().map(|(vertices, indices, material)| {
+		let material_id = match material.and_then(|index| material_ids.get(index)) {
+			Some(&material_id) => material_id,
+			None => fallback_material(renderer, scene),
+		};
+		finish_mesh(renderer, filename, vertices, indices, material_id, modifiers, &mut bounds)
+	}).collect::<Vec<_>>();
+
+	Ok(scene.add_model(finish_model(meshes, bounds)))
+}
+
+// an image referenced by a glTF material, from the binary chunk or a file next to the glTF
+#[cfg(feature = "gltf")]
+async fn load_gltf_image(
+	filename: &str,
+	image: gltf::Image<'_>,
+	buffers: &[Vec<u8>],
+	ty: texture::TextureType,
+	renderer: &renderer::Renderer,
+) -> anyhow::Result<crate::assets::TextureHandle> {
+	match image.source() {
+		gltf::image::Source::View { view, .. } => {
+			let buffer = buffers.get(view.buffer().index()).ok_or_else(|| anyhow::anyhow!("missing buffer in {}", filename))?;
+			let data = buffer.get(view.offset()..view.offset() + view.length()).ok_or_else(|| anyhow::anyhow!("image out of bounds in {}", filename))?;
+			let name = format!("{}#image{}", filename, image.index());
+			renderer.assets.load_texture_from_bytes(&name, data, ty, &renderer.device, &renderer.queue)
+		}
+		gltf::image::Source::Uri { uri, .. } if uri.starts_with("data:") => anyhow::bail!("embedded images in {} aren't supported, export it as .glb", filename),
+		gltf::image::Source::Uri { uri, .. } => renderer.assets.load_texture(&relative_path(filename, uri), ty, &renderer.device, &renderer.queue).await,
+	}
+}
 
-		// create tangents
-		mikktspace::generate_tangents(&mut mesh);
+// converts the metallic roughness factors into material parameters
+#[cfg(feature = "gltf")]
+fn gltf_params(material: &gltf::Material) -> model::SimpleMaterial {
+	let pbr = material.pbr_metallic_roughness();
+	let [r, g, b, a] = pbr.base_color_factor();
+	let metal = pbr.metallic_factor();
+	let roughness = pbr.roughness_factor();
+	// dielectrics reflect about 4% head on, metals reflect their base color
+	let specular = [r, g, b].map(|c| 0.04 + (c - 0.04) * metal);
+	// the phong exponent with about the same highlight as the roughness
+	let alpha = (roughness * roughness).max(0.01);
+	let shininess = (2.0 / (alpha * alpha) - 2.0).clamp(1.0, 1024.0);
 
-		// create vertex & index buffer
-		let vertex_buffer = renderer.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-			label: Some(&format!("{:?} Vertex Buffer", filename)),
-			contents: bytemuck::cast_slice(&mesh.vertices),
-			usage: wgpu::BufferUsages::VERTEX,
-		});
-		let index_buffer = renderer.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-			label: Some(&format!("{:?} Index Buffer", filename)),
-			contents: bytemuck::cast_slice(&mesh.indices),
-			usage: wgpu::BufferUsages::INDEX,
+	let mut params = model::SimpleMaterial::new();
+	params.diffuse_spec = [r, g, b, specular[0].max(specular[1]).max(specular[2])];
+	params.specular_shininess = [specular[0], specular[1], specular[2], shininess];
+	params.roughness = roughness;
+	params.metal = metal;
+	params.opacity = if material.alpha_mode() == gltf::material::AlphaMode::Blend { a } else { 1.0 };
+	params
+}
+
+/*
+Loads a glTF 2.0 model (.gltf or .glb) into the scene. The meshes of the default scene are
+flattened into one model with their node transforms applied, and each primitive becomes a
+mesh with its own material. Buffers and images are read with load_binary like OBJ materials
+and textures, base64 data URIs aren't supported.
+Alpha mask materials, skins, and animations aren't loaded.
+*/
+#[cfg(feature = "gltf")]
+pub async fn load_gltf(filename: &str, modifiers: &modifier::ModifierStack, renderer: &renderer::Renderer, scene: &mut scene::Scene) -> anyhow::Result<usize> {
+	use cgmath::{InnerSpace, Matrix, SquareMatrix};
+	let gltf = gltf::Gltf::from_slice(&load_binary(filename).await?)?;
+
+	let mut buffers = vec![];
+	for buffer in gltf.buffers() {
+		buffers.push(match buffer.source() {
+			gltf::buffer::Source::Bin => gltf.blob.clone().ok_or_else(|| anyhow::anyhow!("{} has no binary chunk", filename))?,
+			gltf::buffer::Source::Uri(uri) if uri.starts_with("data:") => anyhow::bail!("embedded buffers in {} aren't supported, export it as .glb", filename),
+			gltf::buffer::Source::Uri(uri) => load_binary(&relative_path(filename, uri)).await?,
 		});
+	}
+
+	let mut material_ids = HashMap::new(); // glTF material index to scene material
+	for material in gltf.materials() {
+		let Some(index) = material.index() else {
+			continue;
+		};
+		// exporters reuse names like "Material", so they're scoped to the file
+		let name = format!("{}/{}", filename, material.name().map_or_else(|| index.to_string(), str::to_string));
+		if let Some(material_id) = scene.get_material(&name) {
+			material_ids.insert(index, material_id);
+			continue;
+		}
 
-		let material_id = material_ids[m.mesh.material_id.unwrap_or(0)];
+		let pbr = material.pbr_metallic_roughness();
+		let base_color = pbr.base_color_factor();
+		let material_type = match pbr.base_color_texture() {
+			None => model::MaterialType::SingleColorMaterial([base_color[0], base_color[1], base_color[2]]),
+			Some(info) => {
+				let diffuse_texture = load_gltf_image(filename, info.texture().source(), &buffers, texture::TextureType::Diffuse, renderer).await?;
+				match material.normal_texture() {
+					None => model::MaterialType::DiffuseMapMaterial(diffuse_texture),
+					Some(normal) => {
+						let normal_texture = load_gltf_image(filename, normal.texture().source(), &buffers, texture::TextureType::Normal, renderer).await?;
+						model::MaterialType::DiffuseNormalMapMaterial(diffuse_texture, normal_texture)
+					}
+				}
+			}
+		};
 
-		model::Mesh {
-			name: filename.to_string(),
-			vertex_buffer,
-			index_buffer,
-			num_elements: mesh.indices.len() as u32,
-			material: material_id,
+		let mut material_params = model::Material::new(
+			&renderer.device,
+			&name,
+			material_type,
+			gltf_params(&material),
+			&renderer.texture_bind_group_layouts,
+			&renderer.uniform_arena,
+			&renderer.assets,
+		);
+		if material.alpha_mode() == gltf::material::AlphaMode::Blend {
+			material_params.blend = model::BlendMode::Alpha;
 		}
-	}).collect::<Vec<_>>();
+		if material.double_sided() {
+			material_params.cull = model::CullMode::None;
+		}
+		material_ids.insert(index, scene.add_material(material_params));
+	}
+
+	let gltf_scene = gltf.default_scene().or_else(|| gltf.scenes().next())
+		.ok_or_else(|| anyhow::anyhow!("{} has no scenes", filename))?;
+	let mut nodes = gltf_scene.nodes().map(|node| (node, cgmath::Matrix4::identity())).collect::<Vec<_>>();
+
+	let mut bounds = ModelBounds::default();
+	let mut meshes = vec![];
+	while let Some((node, parent)) = nodes.pop() {
+		let transform = parent * cgmath::Matrix4::from(node.transform().matrix());
+		nodes.extend(node.children().map(|child| (child, transform)));
+		let Some(mesh) = node.mesh() else {
+			continue;
+		};
+		let normal_matrix = transform.invert().unwrap_or(cgmath::Matrix4::identity()).transpose();
+
+		for primitive in mesh.primitives() {
+			if primitive.mode() != gltf::mesh::Mode::Triangles {
+				log::warn!("Skipping a {:?} primitive in {}, only triangles are supported", primitive.mode(), filename);
+				continue;
+			}
+			let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(Vec::as_slice));
+			let Some(positions) = reader.read_positions() else {
+				continue;
+			};
+			let positions = positions.collect::<Vec<_>>();
+			let normals = reader.read_normals().map(|normals| normals.collect::<Vec<_>>());
+			let tex_coords = reader.read_tex_coords(0).map(|tex_coords| tex_coords.into_f32().collect::<Vec<_>>());
+			let lightmap_coords = reader.read_tex_coords(1).map(|tex_coords| tex_coords.into_f32().collect::<Vec<_>>());
+			let mut indices = match reader.read_indices() {
+				Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+				None => (0..positions.len() as u32).collect(),
+			};
+			// mirroring transforms turn the triangles inside out
+			if transform.determinant() < 0.0 {
+				for triangle in indices.chunks_exact_mut(3) {
+					triangle.swap(1, 2);
+				}
+			}
+
+			let vertices = positions.iter().enumerate().map(|(i, position)| {
+				let normal = normals.as_ref().map_or([0.0; 3], |normals| normals[i]);
+				let normal = (normal_matrix * cgmath::Vector3::from(normal).extend(0.0)).truncate();
+				model::ModelVertex {
+					position: (transform * cgmath::Vector3::from(*position).extend(1.0)).truncate().into(),
+					// glTF uvs already start at the top left
+					tex_coords: tex_coords.as_ref().map_or([0.0; 2], |tex_coords| tex_coords[i]),
+					normal: if normal.magnitude2() > 0.0 { normal.normalize().into() } else { [0.0; 3] },
+					tangent: [0.0; 4],
+					lightmap_coords: lightmap_coords.as_ref().map_or([0.0; 2], |tex_coords| tex_coords[i]),
+				}
+			}).collect::<Vec<_>>();
+
+			let mut geometry = MeshGeometry {
+				vertices,
+				indices: &indices,
+			};
+			if normals.is_none() {
+				geometry.compute_normals();
+			}
+			let (vertices, indices) = if tex_coords.is_none() {
+				unwrap_missing_uvs(filename, geometry.vertices, &indices)
+			} else {
+				(geometry.vertices, indices)
+			};
+
+			let material_id = match primitive.material().index().and_then(|index| material_ids.get(&index)) {
+				Some(&material_id) => material_id,
+				None => fallback_material(renderer, scene),
+			};
+			meshes.push(finish_mesh(renderer, filename, vertices, indices, material_id, modifiers, &mut bounds));
+		}
+	}
 
-	Ok(scene.add_model(model::Model { meshes }))
+	Ok(scene.add_model(finish_model(meshes, bounds)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn decoding_parses_models_and_images() {
+		let mut png = vec![];
+		image::DynamicImage::ImageRgba8(image::RgbaImage::new(4, 2))
+			.write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)
+			.unwrap();
+		let files = HashMap::from([
+			("quad.obj".to_string(), b"mtllib quad.mtl\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\nusemtl red\nf 1/1 2/2 3/3 4/4\n".to_vec()),
+			("quad.mtl".to_string(), b"newmtl red\nKd 1 0 0\n".to_vec()),
+			("texture.png".to_string(), png),
+			("broken.png".to_string(), b"not a png".to_vec()),
+		]);
+		let decoded = decode_files(&files);
+
+		let Some(DecodedFile::Obj(obj)) = decoded.get("quad.obj") else {
+			panic!("the model wasn't parsed");
+		};
+		assert_eq!(obj.materials.len(), 1);
+		let [(vertices, indices, material)] = obj.meshes.as_slice() else {
+			panic!("expected one mesh");
+		};
+		assert_eq!((vertices.len(), indices.len(), *material), (4, 6, Some(0)));
+		assert!(matches!(decoded.get("texture.png"), Some(DecodedFile::Image(image)) if image.width() == 4 && image.height() == 2));
+		// left for the load to report, and the memory files are gone again
+		assert!(!decoded.contains_key("broken.png") && !decoded.contains_key("quad.mtl"));
+		assert!(memory_file("quad.mtl").is_none());
+	}
 }
@@ -1,30 +1,140 @@
-use crate::{model, light, camera};
+use std::collections::HashMap;
+
+use crate::{model, light, camera, environment, skinning, texture, crowd, spline, navmesh, particles, renderer, resources, scene_file};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct LightHandle(usize);
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NodeHandle(usize);
+
+/*
+A transform in the scene's hierarchy, relative to its parent. A node with an object moves it,
+the object's transform is overwritten with the node's world transform every update. Parents
+are always added before their children, so one pass in order propagates the whole graph.
+*/
+pub struct SceneNode {
+	pub parent: Option<NodeHandle>,
+	pub local: cgmath::Matrix4<f32>,
+	pub object: Option<usize>, // index into the scene's objects, None once they're cleared
+	world: cgmath::Matrix4<f32>, // as of the last update_transforms
+}
 
 pub struct Scene {
 	pub materials: Vec<model::Material>,
 	pub models: Vec<model::Model>,
+	model_files: HashMap<String, usize>, // models loaded unmodified from a file, by filename
+	pub material_files: Vec<String>, // loaded with resources::load_materials, in order
 	pub objects: Vec<model::ModelInstance>,
+	pub skins: Vec<skinning::Skin>,
+	pub crowds: Vec<crowd::Crowd>, // drawn after the objects, without culling
+	pub ribbons: Vec<spline::RibbonModel>, // models extruded from splines, see spline::add_ribbon
+	nodes: Vec<SceneNode>, // the hierarchy objects can be attached to, see add_node
+	pub navmesh: Option<navmesh::NavMesh>, // drawn on the debug layer while visible
+	pub emitters: Vec<particles::ParticleEmitter>, // simulated and drawn by the renderer's particle system
 	
-	pub light: light::LightUniform,
+	lights: Vec<light::Light>,
+	lights_dirty: bool, // set when a light changes, so the renderer only uploads them then
 	pub camera: camera::Camera,
+	pub environment: environment::Environment,
+	pub lighting_presets: Vec<environment::LightingPreset>, // switched between with apply_lighting_preset
+	skybox: Option<texture::Texture>, // a cubemap, see set_skybox
+
+	pub time: f32, // seconds since the scene started
+	pub delta_time: f32, // seconds since the last update
 }
 
 impl Scene {
-	pub fn new(light: light::LightUniform, camera: camera::Camera) -> Self {
+	pub fn new(camera: camera::Camera) -> Self {
 		Self {
 			materials: vec![],
 			models: vec![],
+			model_files: HashMap::new(),
+			material_files: vec![],
 			objects: vec![],
-			light,
+			skins: vec![],
+			crowds: vec![],
+			ribbons: vec![],
+			nodes: vec![],
+			navmesh: None,
+			emitters: vec![],
+			lights: vec![],
+			lights_dirty: true,
 			camera,
+			environment: environment::Environment::default(),
+			lighting_presets: environment::LightingPreset::defaults(),
+			skybox: None,
+			time: 0.0,
+			delta_time: 0.0,
+		}
+	}
+
+	// a scene from a scene file, see scene_file.rs, with the camera at the aspect ratio
+	pub async fn from_file(filename: &str, renderer: &renderer::Renderer, aspect: f32) -> anyhow::Result<Self> {
+		let file = scene_file::parse(&resources::load_string(filename).await?)?;
+		let mut scene = Self::new(file.camera.camera(aspect));
+		for materials in &file.materials {
+			resources::load_materials(materials, renderer, &mut scene).await?;
+		}
+		for object in &file.objects {
+			let model_index = resources::load_model(&object.model, renderer, &mut scene).await?;
+			scene.add_object(object.instance(model_index));
+		}
+		for light in &file.lights {
+			scene.add_light(light.light());
+		}
+		scene.environment = file.environment.environment();
+		if !file.lighting_presets.is_empty() {
+			scene.lighting_presets = file.lighting_presets.iter().map(scene_file::LightingPresetDefinition::preset).collect();
+		}
+		Ok(scene)
+	}
+
+	/*
+	Writes the scene's material files, objects, lights, camera, environment, and lighting presets
+	to a scene file, named relative to the res folder like the files it references, see
+	resources::write_file for the web build. Objects of models that weren't loaded from a file as
+	they are can't be written, and are left out with a warning. The node hierarchy, skins, crowds,
+	ribbons, particle emitters, the navmesh, and the skybox aren't part of scene files and are
+	dropped.
+	*/
+	pub fn save(&self, filename: &str) -> anyhow::Result<()> {
+		let mut objects = vec![];
+		for (index, obj) in self.objects.iter().enumerate() {
+			match self.model_file(obj.model_index) {
+				Some(model) => objects.push(scene_file::ObjectDefinition::from_instance(model, obj)),
+				None => log::warn!("Leaving object {} out of {}, its model isn't from a file", index, filename),
+			}
 		}
+		let file = scene_file::SceneFile {
+			materials: self.material_files.clone(),
+			objects,
+			lights: self.lights.iter().map(scene_file::LightDefinition::from_light).collect(),
+			camera: scene_file::CameraDefinition::from_camera(&self.camera),
+			environment: scene_file::EnvironmentDefinition::from_environment(&self.environment),
+			lighting_presets: self.lighting_presets.iter().map(scene_file::LightingPresetDefinition::from_preset).collect(),
+		};
+		resources::write_file(filename, scene_file::to_string(&file)?.into_bytes())
 	}
 
 	pub fn add_model(&mut self, model: model::Model) -> usize {
 		self.models.push(model);
-		self.materials.len() - 1
+		self.models.len() - 1
 	}
 	
+	pub fn file_model(&self, filename: &str) -> Option<usize> {
+		self.model_files.get(filename).copied()
+	}
+
+	pub fn set_file_model(&mut self, filename: &str, model_index: usize) {
+		self.model_files.insert(filename.to_string(), model_index);
+	}
+
+	// the file a model was loaded from, None for generated and modified models
+	pub fn model_file(&self, model_index: usize) -> Option<&str> {
+		self.model_files.iter().find(|(_, index)| **index == model_index).map(|(filename, _)| filename.as_str())
+	}
+
 	pub fn add_material(&mut self, material: model::Material) -> usize {
 		self.materials.push(material);
 		self.materials.len() - 1
@@ -39,7 +149,258 @@ impl Scene {
 		None
 	}
 
+	pub fn add_crowd(&mut self, crowd: crowd::Crowd) -> usize {
+		self.crowds.push(crowd);
+		self.crowds.len() - 1
+	}
+
+	pub fn add_emitter(&mut self, emitter: particles::ParticleEmitter) -> usize {
+		self.emitters.push(emitter);
+		self.emitters.len() - 1
+	}
+
+	pub fn add_skin(&mut self, skin: skinning::Skin) -> usize {
+		self.skins.push(skin);
+		self.skins.len() - 1
+	}
+
+	// world space bounding box of an object
+	pub fn object_aabb(&self, index: usize) -> model::Aabb {
+		let obj = &self.objects[index];
+		self.models[obj.model_index].aabb.transformed(&obj.transform)
+	}
+
+	// world space bounding box of every object, None for an empty scene
+	pub fn aabb(&self) -> Option<model::Aabb> {
+		(0..self.objects.len())
+			.map(|index| self.object_aabb(index))
+			.reduce(|a, b| a.union(&b))
+	}
+
+	// whether any of a model's meshes has a blended material
+	pub fn model_is_transparent(&self, model_index: usize) -> bool {
+		self.models[model_index].meshes.iter().any(|mesh| self.materials[mesh.material].is_transparent())
+	}
+
+	// objects with anything blended are drawn on their own, sorted by distance
+	pub fn object_is_transparent(&self, index: usize) -> bool {
+		let obj = &self.objects[index];
+		obj.transparent || self.model_is_transparent(obj.model_index)
+	}
+
 	pub fn add_object(&mut self, obj: model::ModelInstance) {
 		self.objects.push(obj);
 	}
-}
\ No newline at end of file
+
+	// removes every object, the nodes they followed stay in the hierarchy without one
+	pub fn clear_objects(&mut self) {
+		self.objects.clear();
+		for node in &mut self.nodes {
+			node.object = None;
+		}
+	}
+
+	// moves every object's vertex animation along by the scene's delta time
+	pub fn advance_vertex_animations(&mut self, delta_time: f32) {
+		for playback in self.objects.iter_mut().filter_map(|obj| obj.vat.as_mut()) {
+			playback.advance(delta_time);
+		}
+	}
+
+	/*
+	Adds a node under parent, or at the root, placed by transform relative to the parent. With a
+	model, an object is added that follows the node.
+	*/
+	pub fn add_node(&mut self, parent: Option<NodeHandle>, transform: cgmath::Matrix4<f32>, model_index: Option<usize>) -> NodeHandle {
+		let object = model_index.map(|model_index| {
+			self.add_object(model::ModelInstance::new(model_index, transform));
+			self.objects.len() - 1
+		});
+		self.nodes.push(SceneNode {
+			parent,
+			local: transform,
+			object,
+			world: transform,
+		});
+		let node = NodeHandle(self.nodes.len() - 1);
+		self.update_node(node.0);
+		node
+	}
+
+	pub fn node_count(&self) -> usize {
+		self.nodes.len()
+	}
+
+	pub fn node_handle(&self, index: usize) -> Option<NodeHandle> {
+		(index < self.nodes.len()).then_some(NodeHandle(index))
+	}
+
+	pub fn node(&self, handle: NodeHandle) -> &SceneNode {
+		&self.nodes[handle.0]
+	}
+
+	pub fn node_mut(&mut self, handle: NodeHandle) -> &mut SceneNode {
+		&mut self.nodes[handle.0]
+	}
+
+	pub fn set_local_transform(&mut self, handle: NodeHandle, transform: cgmath::Matrix4<f32>) {
+		self.nodes[handle.0].local = transform;
+	}
+
+	// as of the last update_transforms
+	pub fn world_transform(&self, handle: NodeHandle) -> cgmath::Matrix4<f32> {
+		self.nodes[handle.0].world
+	}
+
+	// propagates the local transforms down the hierarchy and moves the nodes' objects
+	pub fn update_transforms(&mut self) {
+		for index in 0..self.nodes.len() {
+			self.update_node(index);
+		}
+	}
+
+	fn update_node(&mut self, index: usize) {
+		let node = &self.nodes[index];
+		let world = match node.parent {
+			Some(parent) => self.nodes[parent.0].world * node.local,
+			None => node.local,
+		};
+		let object = node.object;
+		self.nodes[index].world = world;
+		// objects is public, so it can still shrink without clear_objects
+		if let Some(obj) = object.and_then(|object| self.objects.get_mut(object)) {
+			obj.transform = world;
+		}
+	}
+
+	pub fn add_light(&mut self, light: light::Light) -> LightHandle {
+		self.lights.push(light);
+		self.lights_dirty = true;
+		LightHandle(self.lights.len() - 1)
+	}
+
+	// handle of the light at an index of lights()
+	pub fn light_handle(&self, index: usize) -> Option<LightHandle> {
+		(index < self.lights.len()).then_some(LightHandle(index))
+	}
+
+	pub fn light(&self, handle: LightHandle) -> &light::Light {
+		&self.lights[handle.0]
+	}
+
+	pub fn light_mut(&mut self, handle: LightHandle) -> &mut light::Light {
+		self.lights_dirty = true;
+		&mut self.lights[handle.0]
+	}
+
+	// the preset's look, with its sun as the first directional light, added if there isn't one
+	pub fn apply_lighting_preset(&mut self, name: &str) -> anyhow::Result<()> {
+		let Some(preset) = self.lighting_presets.iter().find(|preset| preset.name == name) else {
+			anyhow::bail!("no lighting preset named {}", name);
+		};
+		let sun = match self.lights.iter().position(|light| matches!(light.kind, light::LightKind::Directional)) {
+			Some(index) => index,
+			None => {
+				self.lights.push(light::Light::directional(preset.sun_direction(), preset.sun_illuminance));
+				self.lights.len() - 1
+			}
+		};
+		preset.apply(&mut self.environment, &mut self.lights[sun]);
+		self.lights_dirty = true;
+		Ok(())
+	}
+
+	// saves the current look as a preset, replacing one with the same name
+	pub fn save_lighting_preset(&mut self, name: &str) {
+		let sun = self.lights.iter().find(|light| matches!(light.kind, light::LightKind::Directional));
+		let preset = environment::LightingPreset::capture(name, &self.environment, sun);
+		match self.lighting_presets.iter_mut().find(|preset| preset.name == name) {
+			Some(existing) => *existing = preset,
+			None => self.lighting_presets.push(preset),
+		}
+	}
+
+	/*
+	Drawn behind everything in place of the clear color, None goes back to the clear color. Takes
+	a cubemap, like the ones from resources::load_cubemap_texture or load_equirect_cubemap. The
+	scene's ambient light and reflections come from it, see ibl.rs.
+	*/
+	pub fn set_skybox(&mut self, skybox: Option<texture::Texture>) {
+		self.skybox = skybox;
+	}
+
+	pub fn skybox(&self) -> Option<&texture::Texture> {
+		self.skybox.as_ref()
+	}
+
+	pub fn lights(&self) -> &[light::Light] {
+		&self.lights
+	}
+
+	// whether a light changed since the last call
+	pub fn take_lights_dirty(&mut self) -> bool {
+		std::mem::replace(&mut self.lights_dirty, false)
+	}
+
+	pub fn set_light_position(&mut self, handle: LightHandle, position: [f32; 3]) {
+		self.lights[handle.0].position = position;
+		self.lights_dirty = true;
+	}
+
+	pub fn set_light_color(&mut self, handle: LightHandle, color: [f32; 3]) {
+		self.lights[handle.0].color = color;
+		self.lights_dirty = true;
+	}
+
+	pub fn set_light_intensity(&mut self, handle: LightHandle, intensity: f32) {
+		self.lights[handle.0].intensity = intensity;
+		self.lights_dirty = true;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use cgmath::SquareMatrix;
+
+	fn close(a: cgmath::Matrix4<f32>, b: cgmath::Matrix4<f32>) -> bool {
+		let (a, b): ([[f32; 4]; 4], [[f32; 4]; 4]) = (a.into(), b.into());
+		a.as_flattened().iter().zip(b.as_flattened()).all(|(a, b)| (a - b).abs() < 1e-5)
+	}
+
+	fn translation(x: f32, y: f32, z: f32) -> cgmath::Matrix4<f32> {
+		cgmath::Matrix4::from_translation(cgmath::vec3(x, y, z))
+	}
+
+	#[test]
+	fn nodes_carry_their_children_and_objects() {
+		let mut scene = Scene::new(camera::Camera::new(1.0));
+		let root = scene.add_node(None, translation(1.0, 0.0, 0.0), None);
+		let turn = cgmath::Matrix4::from_angle_y(cgmath::Deg(90.0));
+		let arm = scene.add_node(Some(root), turn, Some(0));
+		let hand = scene.add_node(Some(arm), translation(0.0, 0.0, 2.0), Some(0));
+		assert_eq!(scene.node_count(), 3);
+		assert_eq!(scene.objects.len(), 2);
+		assert_eq!(scene.node(hand).object, Some(1));
+
+		// children added later start at their parent's world transform
+		let expected = translation(3.0, 0.0, 0.0) * turn;
+		assert!(close(scene.world_transform(hand), expected));
+		assert_eq!(scene.objects[1].transform, scene.world_transform(hand));
+
+		// moving a node moves everything under it once the transforms are updated
+		scene.set_local_transform(root, translation(0.0, 5.0, 0.0));
+		assert!(close(scene.world_transform(hand), expected));
+		scene.update_transforms();
+		assert!(close(scene.world_transform(hand), translation(2.0, 5.0, 0.0) * turn));
+		assert!(close(scene.objects[0].transform, scene.world_transform(arm)));
+		assert_eq!(scene.node_handle(2), Some(hand));
+		assert_eq!(scene.node_handle(3), None);
+
+		// nodes outlive their objects
+		scene.clear_objects();
+		scene.set_local_transform(hand, cgmath::Matrix4::identity());
+		scene.update_transforms();
+		assert!(close(scene.world_transform(hand), scene.world_transform(arm)));
+	}
+}
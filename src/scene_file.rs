@@ -0,0 +1,442 @@
+use cgmath::InnerSpace;
+
+use crate::{camera, environment, light, model};
+
+/*
+A scene described in a RON file, loaded with Scene::from_file and written with Scene::save.
+Material files are loaded before the models, so they replace the materials the models name.
+Without lighting_presets the scene gets the default ones. Paths are relative to the res
+folder, like the files they reference:
+
+(
+	materials: ["materials.toml"],
+	objects: [
+		(model: "dragon.obj", position: (0.0, 0.0, 0.0), rotation: (0.0, 90.0, 0.0), scale: (1.0, 1.0, 1.0)),
+	],
+	lights: [
+		(kind: Point, position: (2.0, 1.0, 2.0), intensity: 10.0),
+		(kind: Spot(inner_angle: 20.0, outer_angle: 30.0), position: (0.0, 3.0, 0.0)),
+	],
+	camera: (eye: (0.0, 1.0, 2.0), target: (0.0, 0.0, 0.0), fovy: 45.0),
+	environment: (exposure: 1.5, tonemap: Aces, fog: (density: 0.02)),
+	lighting_presets: [
+		(name: "dusk", sun_elevation: 5.0, sun_azimuth: 250.0, sun_illuminance: 0.5),
+	],
+)
+*/
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SceneFile {
+	pub materials: Vec<String>, // material files
+	pub objects: Vec<ObjectDefinition>,
+	pub lights: Vec<LightDefinition>,
+	pub camera: CameraDefinition,
+	pub environment: EnvironmentDefinition,
+	pub lighting_presets: Vec<LightingPresetDefinition>,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ObjectDefinition {
+	pub model: String, // OBJ or glTF file, objects with the same file share the model
+	pub position: [f32; 3],
+	pub rotation: [f32; 3], // degrees around x, then y, then z
+	pub scale: [f32; 3],
+	pub casts_shadows: bool,
+	pub receives_shadows: bool,
+}
+
+impl Default for ObjectDefinition {
+	fn default() -> Self {
+		Self {
+			model: String::new(),
+			position: [0.0; 3],
+			rotation: [0.0; 3],
+			scale: [1.0; 3],
+			casts_shadows: true,
+			receives_shadows: true,
+		}
+	}
+}
+
+impl ObjectDefinition {
+	pub fn transform(&self) -> cgmath::Matrix4<f32> {
+		let [x, y, z] = self.rotation;
+		cgmath::Matrix4::from_translation(self.position.into())
+			* cgmath::Matrix4::from_angle_z(cgmath::Deg(z))
+			* cgmath::Matrix4::from_angle_y(cgmath::Deg(y))
+			* cgmath::Matrix4::from_angle_x(cgmath::Deg(x))
+			* cgmath::Matrix4::from_nonuniform_scale(self.scale[0], self.scale[1], self.scale[2])
+	}
+
+	// the object placed by its transform, sheared transforms lose their shear
+	pub fn from_instance(model: &str, obj: &model::ModelInstance) -> Self {
+		let m = obj.transform;
+		let scale = [m.x.truncate().magnitude(), m.y.truncate().magnitude(), m.z.truncate().magnitude()];
+		let (x, y, z) = (m.x.truncate() / scale[0], m.y.truncate() / scale[1], m.z.truncate() / scale[2]);
+		// the angles of rotation z * y * x, read back from its columns
+		let rotation = [
+			cgmath::Rad(y.z.atan2(z.z)),
+			cgmath::Rad((-x.z).clamp(-1.0, 1.0).asin()),
+			cgmath::Rad(x.y.atan2(x.x)),
+		].map(|angle| cgmath::Deg::from(angle).0);
+		Self {
+			model: model.to_string(),
+			position: m.w.truncate().into(),
+			rotation,
+			scale,
+			casts_shadows: obj.casts_shadows,
+			receives_shadows: obj.receives_shadows,
+		}
+	}
+
+	pub fn instance(&self, model_index: usize) -> model::ModelInstance {
+		model::ModelInstance {
+			casts_shadows: self.casts_shadows,
+			receives_shadows: self.receives_shadows,
+			..model::ModelInstance::new(model_index, self.transform())
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub enum LightKind {
+	#[default]
+	Point,
+	Directional,
+	Spot {
+		inner_angle: f32, // degrees
+		outer_angle: f32,
+	},
+}
+
+#[derive(Copy, Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub enum Falloff {
+	None,
+	#[default]
+	InverseSquare,
+	Linear,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LightDefinition {
+	pub kind: LightKind,
+	pub position: [f32; 3],
+	pub direction: [f32; 3],
+	pub color: [f32; 3],
+	pub intensity: f32,
+	pub range: f32,
+	pub falloff: Falloff,
+}
+
+impl Default for LightDefinition {
+	fn default() -> Self {
+		Self::from_light(&light::Light::new())
+	}
+}
+
+impl LightDefinition {
+	pub fn from_light(light: &light::Light) -> Self {
+		Self {
+			kind: match light.kind {
+				light::LightKind::Point => LightKind::Point,
+				light::LightKind::Directional => LightKind::Directional,
+				light::LightKind::Spot { inner_angle, outer_angle } => LightKind::Spot {
+					inner_angle: inner_angle.to_degrees(),
+					outer_angle: outer_angle.to_degrees(),
+				},
+			},
+			position: light.position,
+			direction: light.direction,
+			color: light.color,
+			intensity: light.intensity,
+			range: light.range,
+			falloff: match light.falloff {
+				light::Falloff::None => Falloff::None,
+				light::Falloff::InverseSquare => Falloff::InverseSquare,
+				light::Falloff::Linear => Falloff::Linear,
+			},
+		}
+	}
+
+	pub fn light(&self) -> light::Light {
+		light::Light {
+			kind: match self.kind {
+				LightKind::Point => light::LightKind::Point,
+				LightKind::Directional => light::LightKind::Directional,
+				LightKind::Spot { inner_angle, outer_angle } => light::LightKind::Spot {
+					inner_angle: inner_angle.to_radians(),
+					outer_angle: outer_angle.to_radians(),
+				},
+			},
+			position: self.position,
+			direction: self.direction,
+			color: self.color,
+			intensity: self.intensity,
+			range: self.range,
+			falloff: match self.falloff {
+				Falloff::None => light::Falloff::None,
+				Falloff::InverseSquare => light::Falloff::InverseSquare,
+				Falloff::Linear => light::Falloff::Linear,
+			},
+		}
+	}
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraDefinition {
+	pub eye: [f32; 3],
+	pub target: [f32; 3],
+	pub fovy: f32, // degrees
+	pub znear: f32,
+	pub zfar: f32,
+}
+
+impl Default for CameraDefinition {
+	fn default() -> Self {
+		Self::from_camera(&camera::Camera::new(1.0))
+	}
+}
+
+impl CameraDefinition {
+	pub fn from_camera(camera: &camera::Camera) -> Self {
+		Self {
+			eye: camera.eye.into(),
+			target: camera.target.into(),
+			fovy: camera.fovy,
+			znear: camera.znear,
+			zfar: camera.zfar,
+		}
+	}
+
+	pub fn camera(&self, aspect: f32) -> camera::Camera {
+		camera::Camera {
+			eye: self.eye.into(),
+			target: self.target.into(),
+			fovy: self.fovy,
+			znear: self.znear,
+			zfar: self.zfar,
+			..camera::Camera::new(aspect)
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+pub enum Tonemap {
+	#[default]
+	None,
+	Reinhard,
+	Aces,
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FogDefinition {
+	pub color: [f32; 3],
+	pub density: f32, // 0 disables fog
+	pub start: f32,
+}
+
+impl Default for FogDefinition {
+	fn default() -> Self {
+		Self::from_fog(&environment::Environment::default().fog)
+	}
+}
+
+impl FogDefinition {
+	pub fn from_fog(fog: &environment::Fog) -> Self {
+		Self {
+			color: fog.color,
+			density: fog.density,
+			start: fog.start,
+		}
+	}
+
+	pub fn fog(&self) -> environment::Fog {
+		environment::Fog {
+			color: self.color,
+			density: self.density,
+			start: self.start,
+		}
+	}
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EnvironmentDefinition {
+	pub clear_color: [f32; 3],
+	pub ambient_color: [f32; 3],
+	pub ambient_intensity: f32,
+	pub exposure: f32,
+	pub tonemap: Tonemap,
+	pub fog: FogDefinition,
+	pub wetness: f32,
+	pub snow: f32,
+	pub wind_direction: [f32; 2],
+	pub wind_strength: f32,
+}
+
+impl Default for EnvironmentDefinition {
+	fn default() -> Self {
+		Self::from_environment(&environment::Environment::default())
+	}
+}
+
+impl EnvironmentDefinition {
+	pub fn from_environment(environment: &environment::Environment) -> Self {
+		Self {
+			clear_color: environment.clear_color,
+			ambient_color: environment.ambient_color,
+			ambient_intensity: environment.ambient_intensity,
+			exposure: environment.exposure,
+			tonemap: match environment.tonemap {
+				environment::Tonemap::None => Tonemap::None,
+				environment::Tonemap::Reinhard => Tonemap::Reinhard,
+				environment::Tonemap::Aces => Tonemap::Aces,
+			},
+			fog: FogDefinition::from_fog(&environment.fog),
+			wetness: environment.weather.wetness,
+			snow: environment.weather.snow,
+			wind_direction: environment.wind.direction,
+			wind_strength: environment.wind.strength,
+		}
+	}
+
+	pub fn environment(&self) -> environment::Environment {
+		environment::Environment {
+			clear_color: self.clear_color,
+			ambient_color: self.ambient_color,
+			ambient_intensity: self.ambient_intensity,
+			exposure: self.exposure,
+			tonemap: match self.tonemap {
+				Tonemap::None => environment::Tonemap::None,
+				Tonemap::Reinhard => environment::Tonemap::Reinhard,
+				Tonemap::Aces => environment::Tonemap::Aces,
+			},
+			fog: self.fog.fog(),
+			weather: environment::Weather {
+				wetness: self.wetness,
+				snow: self.snow,
+			},
+			wind: environment::Wind {
+				direction: self.wind_direction,
+				strength: self.wind_strength,
+			},
+		}
+	}
+}
+
+// see environment::LightingPreset, the angles are in degrees there too
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LightingPresetDefinition {
+	pub name: String,
+	pub sun_elevation: f32,
+	pub sun_azimuth: f32,
+	pub sun_color: [f32; 3],
+	pub sun_illuminance: f32,
+	pub clear_color: [f32; 3],
+	pub ambient_color: [f32; 3],
+	pub ambient_intensity: f32,
+	pub exposure: f32,
+	pub fog: FogDefinition,
+}
+
+impl Default for LightingPresetDefinition {
+	fn default() -> Self {
+		Self::from_preset(&environment::LightingPreset::capture("", &environment::Environment::default(), None))
+	}
+}
+
+impl LightingPresetDefinition {
+	pub fn from_preset(preset: &environment::LightingPreset) -> Self {
+		Self {
+			name: preset.name.clone(),
+			sun_elevation: preset.sun_elevation,
+			sun_azimuth: preset.sun_azimuth,
+			sun_color: preset.sun_color,
+			sun_illuminance: preset.sun_illuminance,
+			clear_color: preset.clear_color,
+			ambient_color: preset.ambient_color,
+			ambient_intensity: preset.ambient_intensity,
+			exposure: preset.exposure,
+			fog: FogDefinition::from_fog(&preset.fog),
+		}
+	}
+
+	pub fn preset(&self) -> environment::LightingPreset {
+		environment::LightingPreset {
+			name: self.name.clone(),
+			sun_elevation: self.sun_elevation,
+			sun_azimuth: self.sun_azimuth,
+			sun_color: self.sun_color,
+			sun_illuminance: self.sun_illuminance,
+			clear_color: self.clear_color,
+			ambient_color: self.ambient_color,
+			ambient_intensity: self.ambient_intensity,
+			exposure: self.exposure,
+			fog: self.fog.fog(),
+		}
+	}
+}
+
+pub fn parse(text: &str) -> anyhow::Result<SceneFile> {
+	Ok(ron::from_str(text)?)
+}
+
+pub fn to_string(scene: &SceneFile) -> anyhow::Result<String> {
+	Ok(ron::ser::to_string_pretty(scene, ron::ser::PrettyConfig::default())?)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn saved_scene_parses_back() {
+		let transform = cgmath::Matrix4::from_translation(cgmath::vec3(1.0, 2.0, 3.0))
+			* cgmath::Matrix4::from_angle_y(cgmath::Deg(30.0))
+			* cgmath::Matrix4::from_nonuniform_scale(2.0, 1.0, 0.5);
+		let mut instance = ObjectDefinition::default().instance(0);
+		instance.transform = transform;
+		instance.casts_shadows = false;
+		let mut spot = light::Light::new();
+		spot.kind = light::LightKind::Spot { inner_angle: 0.3, outer_angle: 0.5 };
+		let file = SceneFile {
+			materials: vec!["materials.toml".to_string()],
+			objects: vec![ObjectDefinition::from_instance("dragon.obj", &instance)],
+			lights: vec![LightDefinition::from_light(&spot)],
+			camera: CameraDefinition::default(),
+			environment: EnvironmentDefinition {
+				tonemap: Tonemap::Aces,
+				..EnvironmentDefinition::default()
+			},
+			lighting_presets: environment::LightingPreset::defaults().iter().map(LightingPresetDefinition::from_preset).collect(),
+		};
+
+		let parsed = parse(&to_string(&file).unwrap()).unwrap();
+		assert_eq!(parsed.materials, file.materials);
+		assert_eq!(parsed.objects.len(), 1);
+		let object = &parsed.objects[0];
+		assert_eq!(object.model, "dragon.obj");
+		assert!(!object.casts_shadows);
+		let round_trip: [[f32; 4]; 4] = object.transform().into();
+		let original: [[f32; 4]; 4] = transform.into();
+		for (a, b) in round_trip.as_flattened().iter().zip(original.as_flattened()) {
+			assert!((a - b).abs() < 1e-4, "{:?} != {:?}", round_trip, original);
+		}
+		match parsed.lights[0].light().kind {
+			light::LightKind::Spot { inner_angle, outer_angle } => {
+				assert!((inner_angle - 0.3).abs() < 1e-5 && (outer_angle - 0.5).abs() < 1e-5);
+			}
+			kind => panic!("expected a spot light, got {:?}", kind),
+		}
+		assert_eq!(parsed.camera.eye, file.camera.eye);
+		assert_eq!(parsed.camera.fovy, file.camera.fovy);
+		assert_eq!(parsed.environment.environment().tonemap, environment::Tonemap::Aces);
+		let names = parsed.lighting_presets.iter().map(|preset| preset.name.as_str()).collect::<Vec<_>>();
+		assert_eq!(names, ["morning", "noon", "night"]);
+	}
+}
\ No newline at end of file
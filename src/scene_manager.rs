@@ -0,0 +1,209 @@
+use std::{collections::HashMap, sync::mpsc};
+
+use crate::{camera, light, model, renderer, resources, scene};
+
+pub type SceneSetup = Box<dyn FnOnce(&mut renderer::Renderer, &mut scene::Scene) -> anyhow::Result<()>>;
+
+/*
+A scene to load. Its files are read, and its images and models decoded, in the background first,
+then setup builds the scene from them, loading through resources::load_from_memory since
+everything is in memory by then.
+*/
+pub struct SceneRequest {
+	pub files: Vec<String>, // models and material files, the files they reference are read too
+	pub camera: camera::Camera,
+	pub setup: SceneSetup,
+}
+
+// what the background thread hands over
+struct LoadedFiles {
+	files: HashMap<String, Vec<u8>>,
+	decoded: HashMap<String, resources::DecodedFile>,
+}
+
+type ReadResult = anyhow::Result<LoadedFiles>;
+
+struct PendingScene {
+	files: mpsc::Receiver<ReadResult>,
+	camera: camera::Camera,
+	setup: SceneSetup,
+}
+
+/*
+Loads scenes while the current one keeps rendering, then fades out, swaps the new scene in,
+and fades back in. Only the GPU side is built on the render thread, and the new scene's
+textures are uploaded a few a frame before the fade out starts. The old scene is dropped at
+the swap, which releases its GPU resources once the frames using them are done.
+*/
+pub struct SceneManager {
+	pub fade_time: f32, // seconds for the whole transition, 0 swaps immediately
+	pending: Option<PendingScene>,
+	next: Option<scene::Scene>, // built and waiting for the fade out to finish
+	fade: f32,
+}
+
+impl Default for SceneManager {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl SceneManager {
+	pub fn new() -> Self {
+		Self {
+			fade_time: 1.0,
+			pending: None,
+			next: None,
+			fade: 0.0,
+		}
+	}
+
+	pub fn is_loading(&self) -> bool {
+		self.pending.is_some() || self.next.is_some()
+	}
+
+	// starts reading the scene's files in the background, replacing a scene that is still being read
+	pub fn request(&mut self, request: SceneRequest) {
+		let (sender, receiver) = mpsc::channel();
+		let files = request.files;
+		let read = async move {
+			let mut data = HashMap::new();
+			for file in &files {
+				resources::read_files(file, &mut data).await?;
+			}
+			let decoded = resources::decode_files(&data);
+			Ok(LoadedFiles {
+				files: data,
+				decoded,
+			})
+		};
+
+		#[cfg(not(target_arch = "wasm32"))]
+		std::thread::spawn(move || {
+			let _ = sender.send(pollster::block_on(read));
+		});
+		#[cfg(target_arch = "wasm32")]
+		wasm_bindgen_futures::spawn_local(async move {
+			let _ = sender.send(read.await);
+		});
+
+		self.pending = Some(PendingScene {
+			files: receiver,
+			camera: request.camera,
+			setup: request.setup,
+		});
+	}
+
+	// reads and builds a scene right away, for the first scene
+	pub async fn load(request: SceneRequest, renderer: &mut renderer::Renderer) -> anyhow::Result<scene::Scene> {
+		let mut files = HashMap::new();
+		for file in &request.files {
+			resources::read_files(file, &mut files).await?;
+		}
+		let files = LoadedFiles {
+			files,
+			decoded: HashMap::new(),
+		};
+		Self::build(files, request.camera, request.setup, renderer)
+	}
+
+	fn build(
+		files: LoadedFiles,
+		camera: camera::Camera,
+		setup: SceneSetup,
+		renderer: &mut renderer::Renderer,
+	) -> anyhow::Result<scene::Scene> {
+		let names = files.files.keys().cloned().collect::<Vec<_>>();
+		let decoded_names = files.decoded.keys().cloned().collect::<Vec<_>>();
+		for (name, data) in files.files {
+			resources::add_memory_file(&name, data);
+		}
+		for (name, file) in files.decoded {
+			resources::add_decoded_file(&name, file);
+		}
+
+		let mut scene = scene::Scene::new(camera);
+		let result = setup(renderer, &mut scene);
+
+		for name in &names {
+			resources::remove_memory_file(name);
+		}
+		for name in &decoded_names {
+			resources::remove_decoded_file(name);
+		}
+		result.map(|_| scene)
+	}
+
+	// advances loading and the transition, returns true on the frame the new scene was swapped in
+	pub fn update(&mut self, renderer: &mut renderer::Renderer, scene: &mut scene::Scene, delta_time: f32) -> bool {
+		if let Some(pending) = &self.pending {
+			match pending.files.try_recv() {
+				Ok(files) => {
+					let pending = self.pending.take().unwrap();
+					match files.and_then(|files| Self::build(files, pending.camera, pending.setup, renderer)) {
+						Ok(next) => self.next = Some(next),
+						Err(e) => log::error!("Unable to load scene: {}", e),
+					}
+				}
+				Err(mpsc::TryRecvError::Empty) => {}
+				Err(mpsc::TryRecvError::Disconnected) => {
+					log::error!("Scene loading stopped before the files were read");
+					self.pending = None;
+				}
+			}
+		}
+
+		// half the fade time out, half back in, the fade out waits for the new scene's textures
+		let step = if self.fade_time > 0.0 { delta_time * 2.0 / self.fade_time } else { 1.0 };
+		let uploading = renderer.assets.is_uploading();
+		let mut swapped = false;
+		if let Some(mut next) = self.next.take_if(|_| !uploading && self.fade + step >= 1.0) {
+			// its textures were uploaded while it wasn't the scene update_assets rebinds
+			renderer.rebind_materials(&mut next);
+			drop(std::mem::replace(scene, next));
+			let in_use = scene.materials.iter().flat_map(|material| material.material_type.textures());
+			let unloaded = renderer.assets.unload_unused(in_use, &renderer.device, &renderer.queue);
+			log::info!("Unloaded {} textures of the previous scene", unloaded);
+			swapped = true;
+			self.fade = if self.fade_time > 0.0 { 1.0 } else { 0.0 };
+		} else if self.next.is_none() {
+			self.fade = (self.fade - step).max(0.0);
+		} else if !uploading {
+			self.fade += step;
+		}
+
+		renderer.fade.color = [0.0, 0.0, 0.0, self.fade];
+		swapped
+	}
+}
+
+// a scene file, see scene_file.rs
+pub fn file_scene(filename: &str, aspect: f32) -> SceneRequest {
+	let scene_file = filename.to_string();
+	SceneRequest {
+		files: vec![filename.to_string()],
+		camera: camera::Camera::new(aspect),
+		setup: Box::new(move |renderer, scene| {
+			*scene = resources::load_from_memory(scene::Scene::from_file(&scene_file, renderer, scene.camera.aspect))?;
+			Ok(())
+		}),
+	}
+}
+
+// a single model lit from above, with the camera fit to it
+pub fn model_scene(filename: &str, aspect: f32) -> SceneRequest {
+	let model_file = filename.to_string();
+	SceneRequest {
+		files: vec![filename.to_string()],
+		camera: camera::Camera::new(aspect),
+		setup: Box::new(move |renderer, scene| {
+			let model_index = resources::load_from_memory(resources::load_model(&model_file, renderer, scene))?;
+			scene.add_object(model::ModelInstance::new(model_index, cgmath::Matrix4::from_scale(1.0)));
+			scene.add_light(light::Light::new());
+			if let Some(aabb) = scene.aabb() {
+				scene.camera.fit_to_bounds(&aabb, 0.1);
+			}
+			Ok(())
+		}),
+	}
+}
@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+use cgmath::{EuclideanSpace, InnerSpace};
+
+use crate::{fade, resources, scene, spline};
+
+/*
+A timed sequence for demo reels and rendered cinematics, read from a TOML file. The camera holds
+each cut until the next cut or path starts, and follows a path through its points between its
+start and end, holding the last point until something else starts. Animations move an object
+from its position before the sequence played, and fades blend a color over the frame:
+
+letterbox = 2.39 # aspect ratio of the picture between the bars, no bars when left out
+
+[[cuts]]
+time = 0.0
+eye = [0.0, 2.0, 6.0]
+target = [0.0, 0.5, 0.0]
+fovy = 35.0 # the camera's own when left out
+
+[[paths]]
+start = 4.0
+end = 10.0
+points = [[6.0, 2.0, 0.0], [0.0, 3.0, -6.0], [-6.0, 2.0, 0.0]]
+target = [0.0, 0.5, 0.0] # looks along the path when left out
+
+[[animations]]
+object = 0
+start = 2.0
+end = 8.0
+to = [0.0, 1.0, 0.0] # offsets from where the object was, from is none when left out
+spin = 360.0 # degrees around y
+
+[[fades]]
+start = 0.0
+end = 1.5
+from = 1.0
+to = 0.0
+*/
+#[derive(Clone, Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sequence {
+	pub letterbox: Option<f32>,
+	pub cuts: Vec<CameraCut>,
+	pub paths: Vec<CameraPath>,
+	pub animations: Vec<Animation>,
+	pub fades: Vec<Fade>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraCut {
+	pub time: f32,
+	pub eye: [f32; 3],
+	pub target: [f32; 3],
+	#[serde(default)]
+	pub fovy: Option<f32>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraPath {
+	pub start: f32,
+	pub end: f32,
+	pub points: Vec<[f32; 3]>, // the eye's, a Catmull-Rom curve through them
+	#[serde(default)]
+	pub target: Option<[f32; 3]>,
+	#[serde(default)]
+	pub fovy: Option<f32>,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Animation {
+	pub object: usize,
+	pub start: f32,
+	pub end: f32,
+	#[serde(default)]
+	pub from: [f32; 3],
+	#[serde(default)]
+	pub to: [f32; 3],
+	#[serde(default)]
+	pub spin: f32,
+}
+
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fade {
+	pub start: f32,
+	pub end: f32,
+	#[serde(default)]
+	pub color: [f32; 3],
+	pub from: f32,
+	pub to: f32,
+}
+
+// a camera shot, the cut or path that started last
+enum Shot<'a> {
+	Cut(&'a CameraCut),
+	Path(&'a CameraPath),
+}
+
+// how far through start..end time is, held at the ends
+fn progress(time: f32, start: f32, end: f32) -> f32 {
+	if end <= start {
+		return if time >= start { 1.0 } else { 0.0 };
+	}
+	((time - start) / (end - start)).clamp(0.0, 1.0)
+}
+
+fn lerp(from: [f32; 3], to: [f32; 3], t: f32) -> cgmath::Vector3<f32> {
+	let (from, to): (cgmath::Vector3<f32>, cgmath::Vector3<f32>) = (from.into(), to.into());
+	from + (to - from) * t
+}
+
+impl Sequence {
+	pub fn parse(text: &str) -> anyhow::Result<Self> {
+		let sequence: Self = toml::from_str(text)?;
+		if let Some(path) = sequence.paths.iter().find(|path| path.points.len() < 2) {
+			anyhow::bail!("the camera path starting at {} needs at least two points", path.start);
+		}
+		Ok(sequence)
+	}
+
+	// when the last event ends
+	pub fn duration(&self) -> f32 {
+		self.cuts.iter().map(|cut| cut.time)
+			.chain(self.paths.iter().map(|path| path.end))
+			.chain(self.animations.iter().map(|animation| animation.end))
+			.chain(self.fades.iter().map(|fade| fade.end))
+			.fold(0.0, f32::max)
+	}
+
+	fn shot(&self, time: f32) -> Option<Shot<'_>> {
+		let cuts = self.cuts.iter().map(|cut| (cut.time, Shot::Cut(cut)));
+		let paths = self.paths.iter().map(|path| (path.start, Shot::Path(path)));
+		cuts.chain(paths)
+			.filter(|(start, _)| *start <= time)
+			.max_by(|(a, _), (b, _)| a.total_cmp(b))
+			.map(|(_, shot)| shot)
+	}
+}
+
+/*
+Plays a sequence on a scene, in scene time so it can be paused, slowed, and stepped a frame at a
+time for recording. Animated objects are placed relative to where they were when the sequence
+started, and go back there when it loops. Objects attached to nodes follow their node instead.
+*/
+pub struct Sequencer {
+	pub sequence: Sequence,
+	pub time: f32,
+	pub looping: bool,
+	rest: HashMap<usize, cgmath::Matrix4<f32>>, // the animated objects' transforms before playing
+}
+
+impl Sequencer {
+	pub fn new(sequence: Sequence) -> Self {
+		Self {
+			sequence,
+			time: 0.0,
+			looping: false,
+			rest: HashMap::new(),
+		}
+	}
+
+	pub async fn load(filename: &str) -> anyhow::Result<Self> {
+		let text = resources::load_string(filename).await?;
+		Ok(Self::new(Sequence::parse(&text)?))
+	}
+
+	pub fn is_finished(&self) -> bool {
+		!self.looping && self.time >= self.sequence.duration()
+	}
+
+	// advances the sequence by the scene's delta time and poses the camera, the animated objects, and the fade
+	pub fn update(&mut self, scene: &mut scene::Scene, fade: &mut fade::FadePass) {
+		self.time += scene.delta_time;
+		let duration = self.sequence.duration();
+		if self.looping && duration > 0.0 {
+			self.time %= duration;
+		}
+		let time = self.time;
+
+		match self.sequence.shot(time) {
+			Some(Shot::Cut(cut)) => {
+				scene.camera.eye = cut.eye.into();
+				scene.camera.target = cut.target.into();
+				if let Some(fovy) = cut.fovy {
+					scene.camera.fovy = fovy;
+				}
+			}
+			Some(Shot::Path(path)) => {
+				let spline = spline::Spline::new(path.points.clone());
+				let along = progress(time, path.start, path.end) * spline.span_count() as f32;
+				let span = (along as usize).min(spline.span_count() - 1);
+				let (eye, direction) = spline.evaluate(span, along - span as f32);
+				scene.camera.eye = cgmath::Point3::from_vec(eye);
+				scene.camera.target = match path.target {
+					Some(target) => target.into(),
+					None if direction.magnitude2() > 0.0 => cgmath::Point3::from_vec(eye + direction.normalize()),
+					None => scene.camera.target,
+				};
+				if let Some(fovy) = path.fovy {
+					scene.camera.fovy = fovy;
+				}
+			}
+			None => {}
+		}
+
+		// objects go back to where they were, then the animations that have started move them
+		for animation in &self.sequence.animations {
+			if let Some(object) = scene.objects.get(animation.object) {
+				self.rest.entry(animation.object).or_insert(object.transform);
+			}
+		}
+		for (&index, &rest) in &self.rest {
+			scene.objects[index].transform = rest;
+		}
+		for animation in self.sequence.animations.iter().filter(|animation| animation.start <= time) {
+			let Some(&rest) = self.rest.get(&animation.object) else {
+				continue;
+			};
+			let t = progress(time, animation.start, animation.end);
+			let spin = cgmath::Matrix4::from_angle_y(cgmath::Deg(animation.spin * t));
+			scene.objects[animation.object].transform = cgmath::Matrix4::from_translation(lerp(animation.from, animation.to, t)) * rest * spin;
+		}
+
+		// the fade that started last, clear before the first one
+		if !self.sequence.fades.is_empty() {
+			let current = self.sequence.fades.iter()
+				.filter(|event| event.start <= time)
+				.max_by(|a, b| a.start.total_cmp(&b.start));
+			fade.color = match current {
+				Some(event) => {
+					let [r, g, b] = event.color;
+					let t = progress(time, event.start, event.end);
+					[r, g, b, event.from + (event.to - event.from) * t]
+				}
+				None => [0.0; 4],
+			};
+		}
+		fade.letterbox = self.sequence.letterbox;
+	}
+
+	// leaves the scene as the sequence last posed it, and takes the letterbox down
+	pub fn stop(self, fade: &mut fade::FadePass) {
+		fade.letterbox = None;
+	}
+}
@@ -0,0 +1,66 @@
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::mpsc;
+
+use notify::Watcher;
+
+use crate::shaders;
+
+/*
+Hot reloading of the material shaders on native builds. The WGSL files in the source tree the
+crate was built from are watched, and once a frame the renderer asks which material shaders
+are made from files that changed, with their sources read again. The shader modules the
+current pipelines were built from are kept, to rebuild them from when a new shader fails.
+The other passes' shaders are only read at build time.
+*/
+pub struct ShaderReload {
+	_watcher: notify::RecommendedWatcher, // stops watching when dropped
+	events: mpsc::Receiver<notify::Result<notify::Event>>,
+	pub modules: Vec<wgpu::ShaderModule>, // the last good module of each material type
+}
+
+impl ShaderReload {
+	const SOURCE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src");
+
+	pub fn new(modules: Vec<wgpu::ShaderModule>) -> anyhow::Result<Self> {
+		let (sender, events) = mpsc::channel();
+		let mut watcher = notify::recommended_watcher(sender)?;
+		watcher.watch(Path::new(Self::SOURCE_DIR), notify::RecursiveMode::NonRecursive)?;
+		Ok(Self {
+			_watcher: watcher,
+			events,
+			modules,
+		})
+	}
+
+	// the material types with a shader file written since the last call, and their new source
+	pub fn changed(&self, shader_tier: shaders::ShaderTier) -> Vec<(usize, anyhow::Result<String>)> {
+		let mut files = HashSet::new();
+		for event in self.events.try_iter() {
+			match event {
+				Ok(event) if event.kind.is_create() || event.kind.is_modify() => {
+					files.extend(event.paths.iter()
+						.filter(|path| path.extension().is_some_and(|extension| extension == "wgsl"))
+						.filter_map(|path| path.file_name()?.to_str().map(String::from)));
+				}
+				Ok(_) => {}
+				Err(e) => log::warn!("Unable to watch the shaders: {}", e),
+			}
+		}
+		if files.is_empty() {
+			return vec![];
+		}
+
+		shader_tier.material_shaders().iter().enumerate()
+			.filter(|(_, (_, shader))| shader.files.iter().any(|file| files.contains(*file)))
+			.map(|(material_type, (_, shader))| (material_type, Self::read(shader)))
+			.collect()
+	}
+
+	fn read(shader: &shaders::ShaderFiles) -> anyhow::Result<String> {
+		shader.files.iter()
+			.map(|file| std::fs::read_to_string(Path::new(Self::SOURCE_DIR).join(file))
+				.map_err(|e| anyhow::anyhow!("{}: {}", file, e)))
+			.collect()
+	}
+}
@@ -0,0 +1,110 @@
+use crate::{capabilities, model, renderer};
+
+// a shader concatenated from WGSL files in this directory, keeping their names so it can be rebuilt from them
+macro_rules! wgsl_files {
+	($label:literal: $($file:literal),+) => {
+		ShaderFiles {
+			label: $label,
+			files: &[$($file),+],
+			source: concat!($(include_str!($file)),+),
+		}
+	};
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ShaderFiles {
+	pub label: &'static str,
+	pub files: &'static [&'static str], // in the order they're concatenated
+	pub source: &'static str, // the files as they were when the crate was built
+}
+
+impl ShaderFiles {
+	pub fn descriptor(&self) -> wgpu::ShaderModuleDescriptor<'static> {
+		wgpu::ShaderModuleDescriptor {
+			label: Some(self.label),
+			source: wgpu::ShaderSource::Wgsl(self.source.into()),
+		}
+	}
+}
+
+/*
+Shaders come in two tiers. The full tier is what the renderer is written against, the
+downlevel tier keeps the web build at parity on WebGL2 and other downlevel adapters. Shaders
+that differ are assembled from shared code plus a file per tier, so a feature added to the
+shared code reaches both. Passes that only have a full tier (compute, ray queries, reflections)
+are disabled by feature negotiation instead.
+*/
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ShaderTier {
+	Full,
+	Downlevel, // single color target, no storage buffers, one 2D shadow map instead of point light shadows
+}
+
+impl ShaderTier {
+	pub fn select(capabilities: &capabilities::Capabilities, features: &capabilities::RendererFeatures) -> Self {
+		if capabilities.adapter.backend == wgpu::Backend::Gl || !features.fragment_storage_buffers || !features.cube_array_textures {
+			ShaderTier::Downlevel
+		} else {
+			ShaderTier::Full
+		}
+	}
+
+	// format of the normal and roughness target, only written in the full tier
+	pub fn surface_format(&self) -> Option<wgpu::TextureFormat> {
+		match self {
+			ShaderTier::Full => Some(renderer::Renderer::SURFACE_FORMAT),
+			ShaderTier::Downlevel => None,
+		}
+	}
+
+	// number of color attachments the main passes render to
+	pub fn color_target_count(&self) -> usize {
+		if self.surface_format().is_some() { 2 } else { 1 }
+	}
+
+	// indexed by MaterialType
+	pub fn material_shaders(&self) -> [(&'static str, ShaderFiles); model::MaterialType::COUNT] {
+		match self {
+			ShaderTier::Full => [
+				("SingleColor", wgsl_files!("Color Shader": "mesh.wgsl", "output.wgsl", "lights.wgsl", "shadows.wgsl", "color.wgsl", "surface.wgsl", "gbuffer.wgsl")),
+				("DiffuseMap", wgsl_files!("Diffuse Shader": "mesh.wgsl", "output.wgsl", "lights.wgsl", "shadows.wgsl", "diffuse.wgsl", "surface.wgsl", "gbuffer.wgsl")),
+				("DiffuseNormalMap", wgsl_files!("Normal Shader": "mesh.wgsl", "output.wgsl", "lights.wgsl", "shadows.wgsl", "shader.wgsl", "surface.wgsl", "gbuffer.wgsl")),
+				("DetailMap", wgsl_files!("Detail Shader": "mesh.wgsl", "output.wgsl", "lights.wgsl", "shadows.wgsl", "detail.wgsl", "surface.wgsl", "gbuffer.wgsl")),
+				("Layered", wgsl_files!("Layered Shader": "mesh.wgsl", "output.wgsl", "lights.wgsl", "shadows.wgsl", "layered.wgsl", "surface.wgsl", "gbuffer.wgsl")),
+				("Toon", wgsl_files!("Toon Shader": "mesh.wgsl", "output.wgsl", "lights.wgsl", "shadows.wgsl", "toon.wgsl", "gbuffer.wgsl")),
+			],
+			ShaderTier::Downlevel => [
+				("SingleColor", wgsl_files!("Downlevel Color Shader": "mesh.wgsl", "output_downlevel.wgsl", "lights_downlevel.wgsl", "shadows_downlevel.wgsl", "color.wgsl", "surface.wgsl")),
+				("DiffuseMap", wgsl_files!("Downlevel Diffuse Shader": "mesh.wgsl", "output_downlevel.wgsl", "lights_downlevel.wgsl", "shadows_downlevel.wgsl", "diffuse.wgsl", "surface.wgsl")),
+				("DiffuseNormalMap", wgsl_files!("Downlevel Normal Shader": "mesh.wgsl", "output_downlevel.wgsl", "lights_downlevel.wgsl", "shadows_downlevel.wgsl", "shader.wgsl", "surface.wgsl")),
+				("DetailMap", wgsl_files!("Downlevel Detail Shader": "mesh.wgsl", "output_downlevel.wgsl", "lights_downlevel.wgsl", "shadows_downlevel.wgsl", "detail.wgsl", "surface.wgsl")),
+				("Layered", wgsl_files!("Downlevel Layered Shader": "mesh.wgsl", "output_downlevel.wgsl", "lights_downlevel.wgsl", "shadows_downlevel.wgsl", "layered.wgsl", "surface.wgsl")),
+				("Toon", wgsl_files!("Downlevel Toon Shader": "mesh.wgsl", "output_downlevel.wgsl", "lights_downlevel.wgsl", "shadows_downlevel.wgsl", "toon.wgsl")),
+			],
+		}
+	}
+
+	// the lighting pass of the deferred render path, only the full tier has one
+	pub fn deferred_lighting_shader(&self) -> Option<wgpu::ShaderModuleDescriptor<'static>> {
+		match self {
+			ShaderTier::Full => Some(wgpu::ShaderModuleDescriptor {
+				label: Some("Deferred Lighting Shader"),
+				source: wgpu::ShaderSource::Wgsl(concat!(include_str!("mesh.wgsl"), include_str!("output.wgsl"), include_str!("lights.wgsl"), include_str!("shadows.wgsl"), include_str!("deferred.wgsl")).into()),
+			}),
+			ShaderTier::Downlevel => None,
+		}
+	}
+
+	pub fn imposter_shader(&self) -> wgpu::ShaderModuleDescriptor<'static> {
+		match self {
+			ShaderTier::Full => wgpu::ShaderModuleDescriptor {
+				label: Some("Imposter Shader"),
+				source: wgpu::ShaderSource::Wgsl(concat!(include_str!("imposter.wgsl"), include_str!("output.wgsl")).into()),
+			},
+			ShaderTier::Downlevel => wgpu::ShaderModuleDescriptor {
+				label: Some("Downlevel Imposter Shader"),
+				source: wgpu::ShaderSource::Wgsl(concat!(include_str!("imposter.wgsl"), include_str!("output_downlevel.wgsl")).into()),
+			},
+		}
+	}
+}
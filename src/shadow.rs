@@ -0,0 +1,64 @@
+use crate::{camera, scene};
+
+/*
+Objects to draw into each shadow cascade. Casters are culled against the light's frustum for
+the cascade instead of the camera's, since objects outside the view still cast shadows into
+it, and the near plane is dropped so casters between the light and the cascade are kept.
+Objects that don't cast shadows are skipped entirely.
+*/
+pub struct ShadowCasters {
+	pub cascades: Vec<Vec<usize>>, // object indices for each cascade
+}
+
+impl ShadowCasters {
+	pub fn cull(scene: &scene::Scene, cascade_view_projs: &[cgmath::Matrix4<f32>]) -> Self {
+		let casters = scene.objects.iter().enumerate()
+			.filter(|(_, obj)| obj.casts_shadows)
+			.map(|(index, obj)| {
+				let (center, radius) = scene.models[obj.model_index].bounds.transformed(&obj.transform);
+				(index, center, radius)
+			})
+			.collect::<Vec<_>>();
+
+		let cascades = cascade_view_projs.iter()
+			.map(|view_proj| {
+				let frustum = camera::Frustum::from_matrix(view_proj).without_near();
+				casters.iter()
+					.filter(|(_, center, radius)| frustum.intersects_sphere(*center, *radius))
+					.map(|(index, ..)| *index)
+					.collect()
+			})
+			.collect();
+
+		Self { cascades }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::model;
+
+	#[test]
+	fn casters_are_culled_per_cascade() {
+		let mut scene = scene::Scene::new(camera::Camera::new(1.0));
+		scene.add_model(model::Model {
+			meshes: vec![],
+			bounds: model::Bounds { center: [0.0; 3], radius: 0.5 },
+			aabb: model::Aabb { min: [-0.5; 3], max: [0.5; 3] },
+			imposter: None,
+		});
+		for position in [[0.0, 0.0, 0.0], [20.0, 0.0, 0.0], [0.0, 30.0, 0.0], [0.0, 0.0, 0.0]] {
+			scene.add_object(model::ModelInstance::new(0, cgmath::Matrix4::from_translation(position.into())));
+		}
+		scene.objects[3].casts_shadows = false;
+
+		// a light straight above, the second cascade covers more of the ground
+		let view = cgmath::Matrix4::look_at_rh(cgmath::point3(0.0, 10.0, 0.0), cgmath::point3(0.0, 0.0, 0.0), cgmath::Vector3::unit_z());
+		let cascade = |extent: f32| camera::OPENGL_TO_WGPU_MATRIX * cgmath::ortho(-extent, extent, -extent, extent, 1.0, 20.0) * view;
+		let casters = ShadowCasters::cull(&scene, &[cascade(2.0), cascade(25.0)]);
+
+		// the object above the light is behind the near plane, casters there are kept
+		assert_eq!(casters.cascades, [vec![0, 2], vec![0, 1, 2]]);
+	}
+}
@@ -0,0 +1,211 @@
+use wgpu::util::DeviceExt;
+
+use crate::{camera, light, model, point_shadow, scene, shadow};
+
+/*
+Shadows for the downlevel tier, which has no cube arrays for the point shadows. The first light
+renders one depth map aimed at the scene's bounds, a perspective one like a spot light covering
+every object, or an orthographic one for a directional light, and the lighting shader finds a
+surface in it with the light space matrix stored in the light buffer. The map stores the distance to the light over its range, the same as the point
+shadow faces, so it shares their caster pipeline. Surfaces outside the bounds, or behind a point
+light inside them, are left unshadowed.
+*/
+pub struct ShadowMap {
+	view: wgpu::TextureView,
+	face_buffer: wgpu::Buffer,
+	face_bind_group: wgpu::BindGroup,
+	pipeline: wgpu::RenderPipeline,
+	pub layout: wgpu::BindGroupLayout, // group 3 of the material pipelines
+	pub bind_group: wgpu::BindGroup,
+	view_proj: Option<cgmath::Matrix4<f32>>, // None until a light is shadowed
+}
+
+impl ShadowMap {
+	const SIZE: u32 = 1024;
+	const NEAR: f32 = 0.05;
+	const MAX_FOV: f32 = 120.0; // degrees, when the light is close to the scene
+
+	pub fn new(device: &wgpu::Device, uniform_layout: &wgpu::BindGroupLayout) -> Self {
+		let texture = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some("Shadow Map"),
+			size: wgpu::Extent3d {
+				width: Self::SIZE,
+				height: Self::SIZE,
+				depth_or_array_layers: 1,
+			},
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: point_shadow::FORMAT,
+			usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+			view_formats: &[],
+		});
+		let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+		let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+			label: Some("shadow_map_sampler"),
+			mag_filter: wgpu::FilterMode::Linear,
+			min_filter: wgpu::FilterMode::Linear,
+			compare: Some(wgpu::CompareFunction::LessEqual),
+			..Default::default()
+		});
+
+		let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("shadow_map_bind_group_layout"),
+			entries: &[
+				wgpu::BindGroupLayoutEntry {
+					binding: 0,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Texture {
+						multisampled: false,
+						view_dimension: wgpu::TextureViewDimension::D2,
+						sample_type: wgpu::TextureSampleType::Depth,
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry {
+					binding: 1,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+					count: None,
+				},
+			],
+		});
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("shadow_map_bind_group"),
+			layout: &layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::Sampler(&sampler),
+				},
+			],
+		});
+
+		let face_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Shadow Map Face Buffer"),
+			contents: bytemuck::cast_slice(&[point_shadow::FaceUniform::new(cgmath::SquareMatrix::identity(), &light::Light::new().to_uniform())]),
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+		});
+		let (face_layout, pipeline) = point_shadow::create_caster_pipeline(device, "Shadow Map", uniform_layout);
+		let face_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("shadow_map_face_bind_group"),
+			layout: &face_layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: face_buffer.as_entire_binding(),
+				},
+			],
+		});
+
+		Self {
+			view,
+			face_buffer,
+			face_bind_group,
+			pipeline,
+			layout,
+			bind_group,
+			view_proj: None,
+		}
+	}
+
+	// a perspective from the light that fits the bounds, or looks straight at them when it can't
+	fn fit_view_proj(light: &light::LightUniform, bounds: &model::Aabb) -> cgmath::Matrix4<f32> {
+		use cgmath::MetricSpace;
+		let eye = cgmath::Point3::from(light.position);
+		let center = cgmath::Point3::from(bounds.center());
+		let radius = cgmath::Point3::from(bounds.min).distance(cgmath::Point3::from(bounds.max)) * 0.5;
+		let distance = eye.distance(center);
+		let fov = if distance > radius {
+			cgmath::Deg::from(cgmath::Rad(2.0 * (radius / distance).asin())).0.min(Self::MAX_FOV)
+		} else {
+			Self::MAX_FOV
+		};
+		let dir = if distance > 0.0 { (center - eye) / distance } else { -cgmath::Vector3::unit_y() };
+		let up = if dir.y.abs() > 0.99 { cgmath::Vector3::unit_z() } else { cgmath::Vector3::unit_y() };
+		let proj = camera::OPENGL_TO_WGPU_MATRIX * cgmath::perspective(cgmath::Deg(fov), 1.0, Self::NEAR, light.range);
+		proj * cgmath::Matrix4::look_to_rh(eye, dir, up)
+	}
+
+	// orthographic view of the bounds along the direction, from a point outside them, and the range that covers them from there
+	fn fit_directional(direction: [f32; 3], bounds: &model::Aabb) -> (cgmath::Matrix4<f32>, cgmath::Point3<f32>, f32) {
+		use cgmath::{InnerSpace, MetricSpace};
+		let center = cgmath::Point3::from(bounds.center());
+		let radius = (cgmath::Point3::from(bounds.min).distance(cgmath::Point3::from(bounds.max)) * 0.5).max(0.01);
+		let dir = cgmath::Vector3::from(direction).normalize();
+		let up = if dir.y.abs() > 0.99 { cgmath::Vector3::unit_z() } else { cgmath::Vector3::unit_y() };
+		let eye = center - dir * radius * 2.0;
+		let proj = camera::OPENGL_TO_WGPU_MATRIX * cgmath::ortho(-radius, radius, -radius, radius, radius, radius * 3.0);
+		(proj * cgmath::Matrix4::look_to_rh(eye, dir, up), eye, radius * 4.0)
+	}
+
+	/*
+	Aims the map at the scene from the light, and sets the light's shadow index and light space
+	matrix. Directional lights have no position, so they're given the one the map is rendered
+	from and a range covering the bounds, which only the shadow lookup reads.
+	*/
+	pub fn update(&mut self, queue: &wgpu::Queue, light: &mut light::LightUniform, bounds: Option<model::Aabb>) {
+		self.view_proj = bounds.map(|bounds| {
+			if light.is_directional() {
+				let (view_proj, eye, range) = Self::fit_directional(light.direction(), &bounds);
+				light.position = eye.into();
+				light.range = range;
+				view_proj
+			} else {
+				Self::fit_view_proj(light, &bounds)
+			}
+		});
+		match self.view_proj {
+			Some(view_proj) => {
+				queue.write_buffer(&self.face_buffer, 0, bytemuck::cast_slice(&[point_shadow::FaceUniform::new(view_proj, light)]));
+				light.set_shadow_index(Some(0));
+				light.set_view_proj(view_proj);
+			}
+			None => light.set_shadow_index(None),
+		}
+	}
+
+	// caster_offsets are the model uniform offsets of every object that casts shadows
+	pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, scene: &scene::Scene, uniform_bind_group: &wgpu::BindGroup, caster_offsets: &[Option<u32>]) {
+		let Some(view_proj) = self.view_proj else {
+			return;
+		};
+		let casters = shadow::ShadowCasters::cull(scene, &[view_proj]);
+		let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Shadow Map Pass"),
+			color_attachments: &[],
+			depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+				view: &self.view,
+				depth_ops: Some(wgpu::Operations {
+					load: wgpu::LoadOp::Clear(1.0),
+					store: wgpu::StoreOp::Store,
+				}),
+				stencil_ops: None,
+			}),
+			occlusion_query_set: None,
+			timestamp_writes: None,
+			multiview_mask: None,
+		});
+		render_pass.set_pipeline(&self.pipeline);
+		render_pass.set_bind_group(1, &self.face_bind_group, &[0]);
+
+		for &object in &casters.cascades[0] {
+			let Some(offset) = caster_offsets[object] else {
+				continue;
+			};
+			render_pass.set_bind_group(0, uniform_bind_group, &[offset]);
+			let obj = &scene.objects[object];
+			let skin = obj.skin.map(|skin| &scene.skins[skin]);
+			for (i, mesh) in scene.models[obj.model_index].meshes.iter().enumerate() {
+				let vertex_buffer = skin.map_or(&mesh.vertex_buffer, |skin| &skin.meshes[i].vertex_buffer);
+				render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+				render_pass.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+				render_pass.draw_indexed(0..mesh.num_elements, 0, 0..1);
+			}
+		}
+	}
+}
@@ -0,0 +1,35 @@
+// Point light shadows for the full tier, sampled from the cube array the shadow pass renders
+
+@group(3) @binding(0)
+var point_shadow_maps: texture_depth_cube_array;
+@group(3) @binding(1)
+var point_shadow_sampler: sampler_comparison;
+
+const SHADOW_BIAS: f32 = 0.02; // world units along the normal
+
+// 1 where the light reaches the surface, 0 in its shadow, filtered over 3x3 texels
+fn light_visibility(light: Light, position: vec3<f32>, normal: vec3<f32>) -> f32 {
+	if light.shadow_index < 0 || (model.shadow_flags & 2u) == 0u {
+		return 1.0;
+	}
+	let bias = select(SHADOW_BIAS, model.shadow_bias, model.shadow_bias > 0.0);
+	let to_surface = position + normal * bias - light.position;
+	let distance = length(to_surface);
+	let depth = min(distance / light.range, 1.0);
+
+	// taps are spread across the face around the lookup direction, a texel apart where the
+	// direction meets the face
+	let dir = to_surface / distance;
+	let up = select(vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(1.0, 0.0, 0.0), abs(dir.y) > 0.99);
+	let tangent = normalize(cross(up, dir));
+	let bitangent = cross(dir, tangent);
+	let texel = 2.0 / f32(textureDimensions(point_shadow_maps).x);
+	var visibility = 0.0;
+	for (var y = -1; y <= 1; y++) {
+		for (var x = -1; x <= 1; x++) {
+			let offset = (tangent * f32(x) + bitangent * f32(y)) * texel;
+			visibility += textureSampleCompareLevel(point_shadow_maps, point_shadow_sampler, dir + offset, light.shadow_index, depth);
+		}
+	}
+	return visibility / 9.0;
+}
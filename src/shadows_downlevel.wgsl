@@ -0,0 +1,37 @@
+// No cube arrays in the downlevel tier, the first light casts shadows through one perspective
+// map aimed at the scene, found with the light space matrix in the light buffer
+
+@group(3) @binding(0)
+var shadow_map: texture_depth_2d;
+@group(3) @binding(1)
+var shadow_sampler: sampler_comparison;
+
+const SHADOW_BIAS: f32 = 0.02; // world units along the normal
+
+// 1 where the light reaches the surface, 0 in its shadow, filtered over 3x3 texels
+fn light_visibility(light: Light, position: vec3<f32>, normal: vec3<f32>) -> f32 {
+	if light.shadow_index < 0 || (model.shadow_flags & 2u) == 0u {
+		return 1.0;
+	}
+	let bias = select(SHADOW_BIAS, model.shadow_bias, model.shadow_bias > 0.0);
+	let biased = position + normal * bias;
+	let clip = light.view_proj * vec4<f32>(biased, 1.0);
+	if clip.w <= 0.0 {
+		return 1.0;
+	}
+	let ndc = clip.xy / clip.w;
+	let uv = vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+	if any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) {
+		return 1.0;
+	}
+	// the map stores the distance to the light over its range, like the point shadows
+	let depth = min(distance(biased, light.position) / light.range, 1.0);
+	let texel = 1.0 / vec2<f32>(textureDimensions(shadow_map));
+	var visibility = 0.0;
+	for (var y = -1; y <= 1; y++) {
+		for (var x = -1; x <= 1; x++) {
+			visibility += textureSampleCompareLevel(shadow_map, shadow_sampler, uv + vec2<f32>(f32(x), f32(y)) * texel, depth);
+		}
+	}
+	return visibility / 9.0;
+}
@@ -0,0 +1,177 @@
+use cgmath::SquareMatrix;
+use wgpu::util::DeviceExt;
+
+use crate::model;
+
+const WORKGROUP_SIZE: u32 = 64;
+
+// up to four joints per vertex; weights should add up to 1
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct VertexInfluence {
+	pub joints: [u32; 4],
+	pub weights: [f32; 4],
+}
+
+/*
+Compute pass that skins every skinned mesh into its own vertex buffer before any render pass
+runs, so shadow, depth, and main passes all draw already skinned geometry
+*/
+pub struct SkinningPipeline {
+	pipeline: wgpu::ComputePipeline,
+	layout: wgpu::BindGroupLayout,
+}
+
+impl SkinningPipeline {
+	pub fn new(device: &wgpu::Device) -> Self {
+		let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::COMPUTE,
+			ty: wgpu::BindingType::Buffer {
+				ty: wgpu::BufferBindingType::Storage { read_only },
+				has_dynamic_offset: false,
+				min_binding_size: None,
+			},
+			count: None,
+		};
+		let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("skinning_bind_group_layout"),
+			entries: &[
+				storage(0, true), // source vertices
+				storage(1, true), // influences
+				storage(2, true), // joint matrices
+				storage(3, false), // skinned vertices
+			],
+		});
+
+		let shader = device.create_shader_module(wgpu::include_wgsl!("skinning.wgsl"));
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Skinning Pipeline Layout"),
+			bind_group_layouts: &[Some(&layout)],
+			immediate_size: 0,
+		});
+		let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+			label: Some("Skinning Pipeline"),
+			layout: Some(&pipeline_layout),
+			module: &shader,
+			entry_point: Some("cs_main"),
+			compilation_options: Default::default(),
+			cache: None,
+		});
+
+		Self {
+			pipeline,
+			layout,
+		}
+	}
+
+	pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, skins: &[Skin]) {
+		if skins.is_empty() {
+			return;
+		}
+		let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+			label: Some("Skinning Pass"),
+			timestamp_writes: None,
+		});
+		compute_pass.set_pipeline(&self.pipeline);
+		for mesh in skins.iter().flat_map(|skin| &skin.meshes) {
+			compute_pass.set_bind_group(0, &mesh.bind_group, &[]);
+			compute_pass.dispatch_workgroups(mesh.vertex_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+		}
+	}
+}
+
+pub struct SkinnedMesh {
+	pub vertex_buffer: wgpu::Buffer, // skinned output, drawn instead of the mesh's own vertices
+	vertex_count: u32,
+	bind_group: wgpu::BindGroup,
+	_source: wgpu::Buffer,
+	_influences: wgpu::Buffer,
+}
+
+// the skinned geometry of one model instance, with one SkinnedMesh per mesh of the model
+pub struct Skin {
+	pub meshes: Vec<SkinnedMesh>,
+	joint_buffer: wgpu::Buffer,
+	joint_count: usize,
+}
+
+impl Skin {
+	pub fn new(
+		device: &wgpu::Device,
+		skinning: &SkinningPipeline,
+		meshes: &[(&[model::ModelVertex], &[VertexInfluence])],
+		joint_count: usize,
+	) -> Self {
+		let identity: [[f32; 4]; 4] = cgmath::Matrix4::identity().into();
+		let joint_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Joint Buffer"),
+			contents: bytemuck::cast_slice(&vec![identity; joint_count.max(1)]),
+			usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+		});
+
+		let meshes = meshes.iter().map(|(vertices, influences)| {
+			assert_eq!(vertices.len(), influences.len(), "every vertex needs an influence");
+
+			let source = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Skinning Source Buffer"),
+				contents: bytemuck::cast_slice(vertices),
+				usage: wgpu::BufferUsages::STORAGE,
+			});
+			let influences = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Skinning Influence Buffer"),
+				contents: bytemuck::cast_slice(influences),
+				usage: wgpu::BufferUsages::STORAGE,
+			});
+			let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+				label: Some("Skinned Vertex Buffer"),
+				contents: bytemuck::cast_slice(vertices),
+				usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::VERTEX,
+			});
+
+			let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+				label: Some("skinning_bind_group"),
+				layout: &skinning.layout,
+				entries: &[
+					wgpu::BindGroupEntry {
+						binding: 0,
+						resource: source.as_entire_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 1,
+						resource: influences.as_entire_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 2,
+						resource: joint_buffer.as_entire_binding(),
+					},
+					wgpu::BindGroupEntry {
+						binding: 3,
+						resource: vertex_buffer.as_entire_binding(),
+					},
+				],
+			});
+
+			SkinnedMesh {
+				vertex_buffer,
+				vertex_count: vertices.len() as u32,
+				bind_group,
+				_source: source,
+				_influences: influences,
+			}
+		}).collect();
+
+		Self {
+			meshes,
+			joint_buffer,
+			joint_count,
+		}
+	}
+
+	// joint matrices take a vertex from bind pose to its posed position in model space
+	pub fn set_joints(&self, queue: &wgpu::Queue, joints: &[cgmath::Matrix4<f32>]) {
+		assert!(joints.len() <= self.joint_count, "skin has {} joints, got {}", self.joint_count, joints.len());
+		let joints = joints.iter().map(|&joint| joint.into()).collect::<Vec<[[f32; 4]; 4]>>();
+		queue.write_buffer(&self.joint_buffer, 0, bytemuck::cast_slice(&joints));
+	}
+}
@@ -0,0 +1,57 @@
+// Skins vertices once per frame so every pass can draw the result as a regular mesh
+
+// matches ModelVertex, read as floats since a vec3 field would be padded to 16 bytes
+const VERTEX_FLOATS: u32 = 14u;
+
+struct Influence {
+	joints: vec4<u32>,
+	weights: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var<storage, read> source: array<f32>;
+@group(0) @binding(1)
+var<storage, read> influences: array<Influence>;
+@group(0) @binding(2)
+var<storage, read> joints: array<mat4x4<f32>>;
+@group(0) @binding(3)
+var<storage, read_write> skinned: array<f32>;
+
+fn read_vec3(i: u32) -> vec3<f32> {
+	return vec3<f32>(source[i], source[i + 1u], source[i + 2u]);
+}
+
+fn write_vec3(i: u32, v: vec3<f32>) {
+	skinned[i] = v.x;
+	skinned[i + 1u] = v.y;
+	skinned[i + 2u] = v.z;
+}
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+	let vertex = id.x;
+	if vertex >= arrayLength(&influences) {
+		return;
+	}
+	let base = vertex * VERTEX_FLOATS;
+
+	let influence = influences[vertex];
+	let skin = joints[influence.joints.x] * influence.weights.x
+		+ joints[influence.joints.y] * influence.weights.y
+		+ joints[influence.joints.z] * influence.weights.z
+		+ joints[influence.joints.w] * influence.weights.w;
+
+	// position
+	write_vec3(base, (skin * vec4<f32>(read_vec3(base), 1.0)).xyz);
+	// tex coords
+	skinned[base + 3u] = source[base + 3u];
+	skinned[base + 4u] = source[base + 4u];
+	// normal
+	write_vec3(base + 5u, normalize((skin * vec4<f32>(read_vec3(base + 5u), 0.0)).xyz));
+	// tangent, keeping the handedness in w
+	write_vec3(base + 8u, normalize((skin * vec4<f32>(read_vec3(base + 8u), 0.0)).xyz));
+	skinned[base + 11u] = source[base + 11u];
+	// lightmap coords
+	skinned[base + 12u] = source[base + 12u];
+	skinned[base + 13u] = source[base + 13u];
+}
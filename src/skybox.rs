@@ -0,0 +1,122 @@
+use crate::{bind_group, texture};
+
+/*
+Draws the scene's skybox, set with Scene::set_skybox, as one triangle on the far plane. It goes
+through the renderer's uniform bind group for the camera and the environment, so every view
+that can draw the scene can draw the sky, with its own exposure and tonemap. Without a skybox
+the views keep the environment's clear color.
+*/
+pub struct SkyboxPass {
+	layout: wgpu::BindGroupLayout, // the sky's cubemap
+	pipeline: wgpu::RenderPipeline,
+	bind_group: Option<(wgpu::Texture, wgpu::BindGroup)>, // for the texture last set on the scene
+}
+
+impl SkyboxPass {
+	pub fn new(
+		device: &wgpu::Device,
+		ibl_layout: &wgpu::BindGroupLayout,
+		uniform_layout: &wgpu::BindGroupLayout,
+		color_format: wgpu::TextureFormat,
+		surface_format: Option<wgpu::TextureFormat>,
+	) -> Self {
+		let shader = device.create_shader_module(wgpu::include_wgsl!("skybox.wgsl"));
+		let layout = bind_group::LayoutBuilder::new(wgpu::ShaderStages::FRAGMENT)
+			.texture_of(0, wgpu::TextureSampleType::Float { filterable: true }, wgpu::TextureViewDimension::Cube)
+			.sampler(1)
+			.build(device, "skybox_bind_group_layout");
+		// group 1 is the renderer's image based lighting, which the sky doesn't read but the scene pipelines leave bound
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Skybox Pipeline Layout"),
+			bind_group_layouts: &[Some(&layout), Some(ibl_layout), Some(uniform_layout)],
+			immediate_size: 0,
+		});
+		let targets = [
+			Some(wgpu::ColorTargetState {
+				format: color_format,
+				blend: None,
+				write_mask: wgpu::ColorWrites::ALL,
+			}),
+			// the sky has no surface, the target keeps its clear value
+			surface_format.map(|format| wgpu::ColorTargetState {
+				format,
+				blend: None,
+				write_mask: wgpu::ColorWrites::empty(),
+			}),
+		];
+		let target_count = if surface_format.is_some() { 2 } else { 1 };
+		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("Skybox Pipeline"),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: Some("vs_main"),
+				buffers: &[],
+				compilation_options: Default::default(),
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: Some("fs_main"),
+				targets: &targets[..target_count],
+				compilation_options: Default::default(),
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: Some(wgpu::DepthStencilState {
+				format: texture::Texture::DEPTH_FORMAT,
+				depth_write_enabled: Some(false),
+				depth_compare: Some(wgpu::CompareFunction::LessEqual),
+				stencil: wgpu::StencilState::default(),
+				bias: wgpu::DepthBiasState::default(),
+			}),
+			multisample: wgpu::MultisampleState::default(),
+			multiview_mask: None,
+			cache: None,
+		});
+
+		Self {
+			layout,
+			pipeline,
+			bind_group: None,
+		}
+	}
+
+	// rebuilds the bind group when the scene's skybox changed
+	pub fn prepare(&mut self, device: &wgpu::Device, skybox: Option<&texture::Texture>) {
+		let Some(skybox) = skybox else {
+			self.bind_group = None;
+			return;
+		};
+		if self.bind_group.as_ref().is_some_and(|(texture, _)| *texture == skybox.texture) {
+			return;
+		}
+		let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("skybox_bind_group"),
+			layout: &self.layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&skybox.view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: wgpu::BindingResource::Sampler(&skybox.sampler),
+				},
+			],
+		});
+		self.bind_group = Some((skybox.texture.clone(), bind_group));
+	}
+
+	/*
+	Expects the cubemap bind group at group 1, like the scene pipelines. Any model offset will do
+	for the uniform bind group, the sky only reads the camera and environment.
+	*/
+	pub fn draw(&self, render_pass: &mut wgpu::RenderPass, uniform_bind_group: &wgpu::BindGroup) {
+		let Some((_, bind_group)) = &self.bind_group else {
+			return;
+		};
+		render_pass.set_pipeline(&self.pipeline);
+		render_pass.set_bind_group(0, bind_group, &[]);
+		render_pass.set_bind_group(2, uniform_bind_group, &[0]);
+		render_pass.draw(0..3, 0..1);
+	}
+}
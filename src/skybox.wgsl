@@ -0,0 +1,70 @@
+// Draws the scene's skybox cubemap behind everything, on the far plane
+
+struct Camera {
+	view: mat4x4<f32>,
+	proj: mat4x4<f32>,
+	view_proj: mat4x4<f32>,
+	inv_view: mat4x4<f32>,
+	inv_proj: mat4x4<f32>,
+	inv_view_proj: mat4x4<f32>,
+	position: vec4<f32>,
+};
+@group(2) @binding(0)
+var<uniform> camera: Camera;
+
+struct Environment {
+	fog_color: vec3<f32>,
+	fog_density: f32,
+	ambient_color: vec3<f32>,
+	ambient_intensity: f32,
+	fog_start: f32,
+	exposure: f32,
+	tonemap: u32,
+	wetness: f32,
+	snow: f32,
+};
+@group(2) @binding(5)
+var<uniform> environment: Environment;
+
+@group(0) @binding(0)
+var t_sky: texture_cube<f32>;
+@group(0) @binding(1)
+var s_sky: sampler;
+
+struct VertexOutput {
+	@builtin(position) clip_position: vec4<f32>,
+	@location(0) ndc: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	// one triangle covering the screen, at depth 1 so anything drawn passes in front
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.ndc = uv * 2.0 - 1.0;
+	out.clip_position = vec4<f32>(out.ndc, 1.0, 1.0);
+	return out;
+}
+
+// same curves as apply_tonemap in mesh.wgsl
+fn apply_tonemap(color: vec3<f32>) -> vec3<f32> {
+	switch environment.tonemap {
+		case 1u: {
+			return color / (color + 1.0);
+		}
+		case 2u: {
+			return clamp((color * (2.51 * color + 0.03)) / (color * (2.43 * color + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+		}
+		default: {
+			return color;
+		}
+	}
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	let far = camera.inv_view_proj * vec4<f32>(in.ndc, 1.0, 1.0);
+	let dir = far.xyz / far.w - camera.position.xyz;
+	let color = textureSample(t_sky, s_sky, dir).rgb * environment.exposure;
+	return vec4<f32>(apply_tonemap(color), 1.0);
+}
@@ -0,0 +1,219 @@
+use cgmath::InnerSpace;
+
+use crate::{model, modifier, renderer, resources, scene};
+
+// a Catmull-Rom curve through its control points
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Spline {
+	pub points: Vec<[f32; 3]>,
+	pub closed: bool, // the last point joins back to the first
+}
+
+impl Spline {
+	pub fn new(points: Vec<[f32; 3]>) -> Self {
+		Self {
+			points,
+			closed: false,
+		}
+	}
+
+	// curve pieces between control points
+	pub fn span_count(&self) -> usize {
+		match self.points.len() {
+			0 | 1 => 0,
+			count if self.closed => count,
+			count => count - 1,
+		}
+	}
+
+	// the control point at an index, clamped at the ends of an open curve
+	fn control(&self, index: isize) -> cgmath::Vector3<f32> {
+		let count = self.points.len() as isize;
+		let index = if self.closed { index.rem_euclid(count) } else { index.clamp(0, count - 1) };
+		self.points[index as usize].into()
+	}
+
+	// the position and direction at t along a span, t from 0 to 1
+	pub fn evaluate(&self, span: usize, t: f32) -> (cgmath::Vector3<f32>, cgmath::Vector3<f32>) {
+		let span = span as isize;
+		let [p0, p1, p2, p3] = [-1, 0, 1, 2].map(|offset| self.control(span + offset));
+		let (t2, t3) = (t * t, t * t * t);
+		let position = ((p1 * 2.0) + (p2 - p0) * t + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2 + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3) * 0.5;
+		let derivative = ((p2 - p0) + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * (2.0 * t) + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * (3.0 * t2)) * 0.5;
+		(position, derivative)
+	}
+}
+
+/*
+A flat strip swept along a spline, for roads, rivers, paths, and cables. The strip lies across
+the up vector, so roads follow hills without rolling, and its width is interpolated between the
+widths at the control points. u goes across the strip and v along it, tiling uv_tiling times
+per world unit so textures keep their scale however long the strip is.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ribbon {
+	pub spline: Spline,
+	pub widths: Vec<f32>, // at each control point, the last one carries on for points without one
+	pub subdivisions: u32, // quads per span
+	pub uv_tiling: f32,
+	pub up: [f32; 3],
+}
+
+impl Ribbon {
+	pub fn new(spline: Spline, width: f32) -> Self {
+		Self {
+			spline,
+			widths: vec![width],
+			subdivisions: 8,
+			uv_tiling: 1.0,
+			up: [0.0, 1.0, 0.0],
+		}
+	}
+
+	fn width(&self, span: usize, t: f32) -> f32 {
+		let width = |index: usize| {
+			let index = if self.spline.closed { index % self.spline.points.len() } else { index };
+			self.widths.get(index).or(self.widths.last()).copied().unwrap_or(1.0)
+		};
+		width(span) + (width(span + 1) - width(span)) * t
+	}
+
+	// vertices with normals and uvs, and triangles facing up, empty for fewer than two points
+	pub fn extrude(&self) -> (Vec<model::ModelVertex>, Vec<u32>) {
+		let mut vertices = vec![];
+		let mut indices = vec![];
+		let spans = self.spline.span_count();
+		if spans == 0 {
+			return (vertices, indices);
+		}
+		let subdivisions = self.subdivisions.max(1) as usize;
+		let up = cgmath::Vector3::from(self.up);
+		let mut distance = 0.0;
+		let mut previous: Option<cgmath::Vector3<f32>> = None;
+
+		for step in 0..=spans * subdivisions {
+			let (span, t) = if step == spans * subdivisions {
+				(spans - 1, 1.0)
+			} else {
+				(step / subdivisions, (step % subdivisions) as f32 / subdivisions as f32)
+			};
+			let (position, direction) = self.spline.evaluate(span, t);
+			if let Some(previous) = previous {
+				distance += (position - previous).magnitude();
+			}
+			previous = Some(position);
+
+			// across the strip, falling back to any horizontal when the curve runs along up
+			let side = direction.cross(up);
+			let side = if side.magnitude2() > 1e-12 { side.normalize() } else { up.cross(cgmath::Vector3::unit_z()).normalize() };
+			let normal = side.cross(direction);
+			let normal = if normal.magnitude2() > 1e-12 { normal.normalize() } else { up };
+			let half_width = self.width(span, t) * 0.5;
+			for (u, offset) in [(0.0, -half_width), (1.0, half_width)] {
+				vertices.push(model::ModelVertex {
+					position: (position + side * offset).into(),
+					tex_coords: [u, distance * self.uv_tiling],
+					normal: normal.into(),
+					tangent: [0.0; 4], // generated when the mesh is uploaded
+					lightmap_coords: [0.0; 2],
+				});
+			}
+			if step > 0 {
+				let base = (step as u32 - 1) * 2;
+				indices.extend_from_slice(&[base, base + 1, base + 2, base + 1, base + 3, base + 2]);
+			}
+		}
+		(vertices, indices)
+	}
+}
+
+// a ribbon drawn as one of the scene's models, rebuilt by update_ribbons when it's edited
+pub struct RibbonModel {
+	pub ribbon: Ribbon,
+	pub model_index: usize,
+	pub material: usize,
+	extruded: Ribbon, // what the model was last built from
+}
+
+fn create_ribbon_model(renderer: &renderer::Renderer, ribbon: &Ribbon, material: usize) -> model::Model {
+	let (vertices, indices) = ribbon.extrude();
+	resources::create_model("ribbon", vec![(vertices, indices, material)], &modifier::ModifierStack::default(), renderer)
+}
+
+// adds the ribbon's model to the scene, returns the index of the model to place objects with
+pub fn add_ribbon(renderer: &renderer::Renderer, scene: &mut scene::Scene, ribbon: Ribbon, material: usize) -> usize {
+	let model_index = scene.add_model(create_ribbon_model(renderer, &ribbon, material));
+	scene.ribbons.push(RibbonModel {
+		extruded: ribbon.clone(),
+		ribbon,
+		model_index,
+		material,
+	});
+	model_index
+}
+
+// re-extrudes the ribbons whose control points or settings changed since they were built
+pub fn update_ribbons(renderer: &renderer::Renderer, scene: &mut scene::Scene) {
+	for index in 0..scene.ribbons.len() {
+		let ribbon = &scene.ribbons[index];
+		if ribbon.ribbon == ribbon.extruded {
+			continue;
+		}
+		let model = create_ribbon_model(renderer, &ribbon.ribbon, ribbon.material);
+		let model_index = ribbon.model_index;
+		scene.models[model_index] = model;
+		let ribbon = &mut scene.ribbons[index];
+		ribbon.extruded = ribbon.ribbon.clone();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn close(a: cgmath::Vector3<f32>, b: [f32; 3]) -> bool {
+		(a - cgmath::Vector3::from(b)).magnitude() < 1e-5
+	}
+
+	#[test]
+	fn curve_passes_through_its_points() {
+		let mut spline = Spline::new(vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 2.0]]);
+		assert_eq!(spline.span_count(), 3);
+		for span in 0..3 {
+			assert!(close(spline.evaluate(span, 0.0).0, spline.points[span]));
+			assert!(close(spline.evaluate(span, 1.0).0, spline.points[span + 1]));
+		}
+		// a point's tangent is parallel to the chord between its neighbours
+		let (_, direction) = spline.evaluate(1, 0.0);
+		assert!(close(direction, [0.5, 0.5, 0.0]));
+
+		spline.closed = true;
+		assert_eq!(spline.span_count(), 4);
+		assert!(close(spline.evaluate(3, 1.0).0, spline.points[0]));
+		assert_eq!(Spline::new(vec![[0.0; 3]]).span_count(), 0);
+	}
+
+	#[test]
+	fn ribbon_follows_the_curve() {
+		let mut ribbon = Ribbon::new(Spline::new(vec![[0.0, 0.0, 0.0], [2.0, 0.0, 0.0], [4.0, 0.0, 0.0]]), 1.0);
+		ribbon.widths.push(3.0);
+		ribbon.subdivisions = 4;
+		ribbon.uv_tiling = 0.5;
+		let (vertices, indices) = ribbon.extrude();
+		assert_eq!(vertices.len(), (2 * 4 + 1) * 2);
+		assert_eq!(indices.len(), 2 * 4 * 6);
+
+		// the width grows over the first span and stays once the widths run out
+		let width = |step: usize| vertices[step * 2 + 1].position[2] - vertices[step * 2].position[2];
+		assert_eq!([width(0), width(2), width(4), width(8)], [1.0, 2.0, 3.0, 3.0]);
+		// v counts world distance, and the strip faces up
+		assert!((vertices[16].tex_coords[1] - 2.0).abs() < 1e-5);
+		for triangle in indices.chunks_exact(3) {
+			let [a, b, c] = [0, 1, 2].map(|i| cgmath::Vector3::from(vertices[triangle[i] as usize].position));
+			assert!((b - a).cross(c - a).y > 0.0);
+		}
+		assert!(vertices.iter().all(|vertex| vertex.normal == [0.0, 1.0, 0.0]));
+
+		assert!(Ribbon::new(Spline::new(vec![[0.0; 3]]), 1.0).extrude().0.is_empty());
+	}
+}
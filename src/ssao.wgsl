@@ -0,0 +1,87 @@
+// Hemisphere ambient occlusion, the classic SSAO of Crytek and Chapman. Points in a kernel around
+// each pixel's surface are checked against the depth buffer, the kernel rotated per pixel by a
+// tiling noise texture. Traced at half resolution and shares GTAO's temporal accumulation and
+// upsampling, which blur away the noise pattern.
+
+struct Camera {
+	view: mat4x4<f32>,
+	proj: mat4x4<f32>,
+	view_proj: mat4x4<f32>,
+	inv_view: mat4x4<f32>,
+	inv_proj: mat4x4<f32>,
+	inv_view_proj: mat4x4<f32>,
+	position: vec4<f32>,
+};
+
+struct Params {
+	strength: f32, // 0 disables the pass
+	radius: f32, // world units
+	frame: u32,
+};
+
+const KERNEL_SIZE: u32 = 16u;
+const NOISE_SIZE: u32 = 4u;
+const BIAS: f32 = 0.02;
+
+@group(0) @binding(0)
+var depth_texture: texture_depth_2d;
+@group(0) @binding(1)
+var<uniform> camera: Camera;
+@group(0) @binding(2)
+var<uniform> params: Params;
+@group(0) @binding(3)
+var noise_texture: texture_2d<f32>; // rotations around the normal, 0 to 1
+@group(0) @binding(4)
+var<uniform> kernel: array<vec4<f32>, KERNEL_SIZE>; // in the hemisphere above z, denser near the center
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+	// one triangle covering the screen
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// view space position of a full resolution pixel
+fn view_position(pixel: vec2<i32>) -> vec3<f32> {
+	let size = vec2<i32>(textureDimensions(depth_texture));
+	let p = clamp(pixel, vec2<i32>(0), size - 1);
+	let depth = textureLoad(depth_texture, p, 0);
+	let uv = (vec2<f32>(p) + 0.5) / vec2<f32>(size);
+	let view = camera.inv_proj * vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+	return view.xyz / view.w;
+}
+
+@fragment
+fn fs_main(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
+	let pixel = vec2<i32>(frag_coord.xy) * 2;
+	if textureLoad(depth_texture, pixel, 0) >= 1.0 {
+		return vec4<f32>(1.0);
+	}
+	let position = view_position(pixel);
+	var normal = normalize(cross(view_position(pixel + vec2<i32>(0, 1)) - position, view_position(pixel + vec2<i32>(1, 0)) - position));
+	if dot(normal, -position) < 0.0 {
+		normal = -normal;
+	}
+
+	// the noise tile shifts every frame so the history converges
+	let shift = vec2<u32>(params.frame % NOISE_SIZE, (params.frame / NOISE_SIZE) % NOISE_SIZE);
+	let noise = textureLoad(noise_texture, (vec2<u32>(frag_coord.xy) + shift) % NOISE_SIZE, 0).xy * 2.0 - 1.0;
+	let random = vec3<f32>(noise, 0.0);
+	let tangent = normalize(random - normal * dot(random, normal));
+	let tbn = mat3x3<f32>(tangent, cross(normal, tangent), normal);
+
+	let full_size = vec2<f32>(textureDimensions(depth_texture));
+	var occlusion = 0.0;
+	for (var i = 0u; i < KERNEL_SIZE; i++) {
+		let sample = position + tbn * kernel[i].xyz * params.radius;
+		let clip = camera.proj * vec4<f32>(sample, 1.0);
+		let ndc = clip.xy / clip.w;
+		let sample_pixel = vec2<i32>(vec2<f32>(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5) * full_size);
+		let surface = view_position(sample_pixel).z;
+		// surfaces far in front of the sample are something else, not occluders
+		let range = smoothstep(0.0, 1.0, params.radius / abs(position.z - surface));
+		occlusion += select(0.0, range, surface >= sample.z + BIAS);
+	}
+	let visibility = 1.0 - occlusion / f32(KERNEL_SIZE);
+	return vec4<f32>(vec3<f32>(mix(1.0, visibility, params.strength)), 1.0);
+}
@@ -0,0 +1,7 @@
+// Fragment entry points of the material shaders, after the material file's material_surface
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+	let surface = material_surface(in);
+	return fragment_output(shade(in, surface.color, surface.normal), surface.normal);
+}
@@ -0,0 +1,271 @@
+use wgpu::util::DeviceExt;
+
+use crate::{texture, upsample};
+
+gpu_struct! {
+	Uniform
+	#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+	struct TemporalParams {
+		prev_view_proj: [[f32; 4]; 4],
+		history_weight: f32,
+	}
+}
+
+// what the accumulation reads each frame, bound again when the targets are resized
+pub struct FrameInputs<'a> {
+	pub depth_texture: &'a texture::Texture,
+	pub camera_buffer: &'a wgpu::Buffer,
+	pub input: &'a wgpu::TextureView, // the effect's output for the frame, at the history's size
+}
+
+// what the accumulation pass binds besides the frame inputs and the history, kept across resizes
+struct PassInputs {
+	layout: wgpu::BindGroupLayout,
+	params_buffer: wgpu::Buffer,
+	sampler: wgpu::Sampler,
+}
+
+/*
+Temporal accumulation for stochastic effects, so AO, ray traced shadows, and the like can take
+few samples per pixel and converge over frames. Each frame the effect's output is blended into
+the history from last frame, which is reprojected with last frame's camera and clamped to the
+neighbourhood of the new samples to limit ghosting. The two history textures swap every frame,
+so passes reading the result bind both and pick the one at current().
+There are no velocity vectors, so only the camera's motion is reprojected, moving and skinned
+objects ghost until the clamp catches them.
+*/
+pub struct TemporalAccumulator {
+	pub history_weight: f32, // how much of the history is kept each frame
+	label: String,
+	format: wgpu::TextureFormat,
+	divisor: u32, // the history is the surface size divided by this
+	inputs: PassInputs,
+	pipeline: wgpu::RenderPipeline,
+	history: [texture::Texture; 2],
+	bind_groups: [wgpu::BindGroup; 2], // writing into history[i], reading the other
+	current: usize,
+	prev_view_proj: Option<cgmath::Matrix4<f32>>, // None until the history has a frame in it
+}
+
+impl TemporalAccumulator {
+	pub fn new(
+		device: &wgpu::Device,
+		label: &str,
+		config: &wgpu::SurfaceConfiguration,
+		format: wgpu::TextureFormat,
+		divisor: u32,
+		frame: FrameInputs,
+	) -> Self {
+		use cgmath::SquareMatrix;
+		let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some(&format!("{} Temporal Params Buffer", label)),
+			contents: bytemuck::cast_slice(&[TemporalParams {
+				prev_view_proj: cgmath::Matrix4::identity().into(),
+				history_weight: 0.0,
+				..bytemuck::Zeroable::zeroed()
+			}]),
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+		});
+		let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+			label: Some("temporal_sampler"),
+			mag_filter: wgpu::FilterMode::Linear,
+			min_filter: wgpu::FilterMode::Linear,
+			..Default::default()
+		});
+
+		let uniform = |binding| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Buffer {
+				ty: wgpu::BufferBindingType::Uniform,
+				has_dynamic_offset: false,
+				min_binding_size: None,
+			},
+			count: None,
+		};
+		let texture = |binding| wgpu::BindGroupLayoutEntry {
+			binding,
+			visibility: wgpu::ShaderStages::FRAGMENT,
+			ty: wgpu::BindingType::Texture {
+				multisampled: false,
+				view_dimension: wgpu::TextureViewDimension::D2,
+				sample_type: wgpu::TextureSampleType::Float { filterable: true },
+			},
+			count: None,
+		};
+		let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("temporal_bind_group_layout"),
+			entries: &[
+				wgpu::BindGroupLayoutEntry { // depth
+					binding: 0,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Texture {
+						multisampled: false,
+						view_dimension: wgpu::TextureViewDimension::D2,
+						sample_type: wgpu::TextureSampleType::Depth,
+					},
+					count: None,
+				},
+				uniform(1), // camera
+				uniform(2), // params
+				texture(3), // input
+				texture(4), // history
+				wgpu::BindGroupLayoutEntry {
+					binding: 5,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+					count: None,
+				},
+			],
+		});
+
+		let shader = device.create_shader_module(wgpu::include_wgsl!("temporal.wgsl"));
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some(&format!("{} Temporal Pipeline Layout", label)),
+			bind_group_layouts: &[Some(&layout)],
+			immediate_size: 0,
+		});
+		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some(&format!("{} Temporal Pipeline", label)),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: Some("vs_main"),
+				buffers: &[],
+				compilation_options: Default::default(),
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: Some("fs_main"),
+				targets: &[Some(wgpu::ColorTargetState {
+					format,
+					blend: None,
+					write_mask: wgpu::ColorWrites::ALL,
+				})],
+				compilation_options: Default::default(),
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			multiview_mask: None,
+			cache: None,
+		});
+
+		let inputs = PassInputs {
+			layout,
+			params_buffer,
+			sampler,
+		};
+		let (history, bind_groups) = Self::create_history(device, label, config, format, divisor, &inputs, frame);
+
+		Self {
+			history_weight: 0.9,
+			label: label.to_string(),
+			format,
+			divisor,
+			inputs,
+			pipeline,
+			history,
+			bind_groups,
+			current: 0,
+			prev_view_proj: None,
+		}
+	}
+
+	fn create_history(
+		device: &wgpu::Device,
+		label: &str,
+		config: &wgpu::SurfaceConfiguration,
+		format: wgpu::TextureFormat,
+		divisor: u32,
+		inputs: &PassInputs,
+		frame: FrameInputs,
+	) -> ([texture::Texture; 2], [wgpu::BindGroup; 2]) {
+		let history_config = upsample::scaled_config(config, divisor);
+		let history = std::array::from_fn(|_| texture::Texture::create_render_target(
+			device,
+			&history_config,
+			format,
+			wgpu::TextureUsages::RENDER_ATTACHMENT,
+			&format!("{} History Texture", label),
+		));
+		let bind_groups = std::array::from_fn(|i| device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("temporal_bind_group"),
+			layout: &inputs.layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&frame.depth_texture.view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: frame.camera_buffer.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: inputs.params_buffer.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 3,
+					resource: wgpu::BindingResource::TextureView(frame.input),
+				},
+				wgpu::BindGroupEntry {
+					binding: 4,
+					resource: wgpu::BindingResource::TextureView(&history[1 - i].view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 5,
+					resource: wgpu::BindingResource::Sampler(&inputs.sampler),
+				},
+			],
+		}));
+		(history, bind_groups)
+	}
+
+	// the history starts over at the new size
+	pub fn resize(&mut self, device: &wgpu::Device, config: &wgpu::SurfaceConfiguration, frame: FrameInputs) {
+		(self.history, self.bind_groups) = Self::create_history(device, &self.label, config, self.format, self.divisor, &self.inputs, frame);
+		self.prev_view_proj = None;
+	}
+
+	// the textures the history alternates between, the result is at current()
+	pub fn history(&self) -> &[texture::Texture; 2] {
+		&self.history
+	}
+
+	pub fn current(&self) -> usize {
+		self.current
+	}
+
+	// blends the input into the history, view_proj is this frame's camera
+	pub fn accumulate(&mut self, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, view_proj: cgmath::Matrix4<f32>) {
+		self.current = 1 - self.current;
+		let params = TemporalParams {
+			prev_view_proj: self.prev_view_proj.unwrap_or(view_proj).into(),
+			history_weight: if self.prev_view_proj.is_some() { self.history_weight } else { 0.0 },
+			..bytemuck::Zeroable::zeroed()
+		};
+		queue.write_buffer(&self.inputs.params_buffer, 0, bytemuck::cast_slice(&[params]));
+		self.prev_view_proj = Some(view_proj);
+
+		let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some(&format!("{} Temporal Pass", self.label)),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view: &self.history[self.current].view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Clear(wgpu::Color::WHITE),
+					store: wgpu::StoreOp::Store,
+				},
+				depth_slice: None,
+			})],
+			depth_stencil_attachment: None,
+			occlusion_query_set: None,
+			timestamp_writes: None,
+			multiview_mask: None,
+		});
+		render_pass.set_pipeline(&self.pipeline);
+		render_pass.set_bind_group(0, &self.bind_groups[self.current], &[]);
+		render_pass.draw(0..3, 0..1);
+	}
+}
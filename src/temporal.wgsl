@@ -0,0 +1,68 @@
+// Blends a noisy effect into its history from last frame, reprojected with last frame's camera
+// and clamped to the current neighbourhood so disoccluded pixels don't keep stale values
+
+struct Camera {
+	view: mat4x4<f32>,
+	proj: mat4x4<f32>,
+	view_proj: mat4x4<f32>,
+	inv_view: mat4x4<f32>,
+	inv_proj: mat4x4<f32>,
+	inv_view_proj: mat4x4<f32>,
+	position: vec4<f32>,
+};
+
+struct Params {
+	prev_view_proj: mat4x4<f32>,
+	history_weight: f32, // how much of the history is kept each frame
+};
+
+@group(0) @binding(0)
+var depth_texture: texture_depth_2d;
+@group(0) @binding(1)
+var<uniform> camera: Camera;
+@group(0) @binding(2)
+var<uniform> params: Params;
+@group(0) @binding(3)
+var input_texture: texture_2d<f32>;
+@group(0) @binding(4)
+var history_texture: texture_2d<f32>;
+@group(0) @binding(5)
+var linear_sampler: sampler;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+	// one triangle covering the screen
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
+	let pixel = vec2<i32>(frag_coord.xy);
+	let size = vec2<i32>(textureDimensions(input_texture));
+	let current = textureLoad(input_texture, pixel, 0);
+	var low = current;
+	var high = current;
+	for (var y = -1; y <= 1; y++) {
+		for (var x = -1; x <= 1; x++) {
+			let neighbour = textureLoad(input_texture, clamp(pixel + vec2<i32>(x, y), vec2<i32>(0), size - 1), 0);
+			low = min(low, neighbour);
+			high = max(high, neighbour);
+		}
+	}
+
+	// the input can be lower resolution than the depth buffer
+	let depth_size = vec2<f32>(textureDimensions(depth_texture));
+	let uv = frag_coord.xy / vec2<f32>(size);
+	let depth = textureLoad(depth_texture, vec2<i32>(uv * depth_size), 0);
+	let world = camera.inv_view_proj * vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, depth, 1.0);
+	let prev_clip = params.prev_view_proj * vec4<f32>(world.xyz / world.w, 1.0);
+	let prev_ndc = prev_clip.xy / prev_clip.w;
+	let prev_uv = vec2<f32>(prev_ndc.x * 0.5 + 0.5, 0.5 - prev_ndc.y * 0.5);
+
+	if any(prev_uv < vec2<f32>(0.0)) || any(prev_uv > vec2<f32>(1.0)) {
+		return current;
+	}
+	let history = clamp(textureSampleLevel(history_texture, linear_sampler, prev_uv, 0.0), low, high);
+	return mix(current, history, params.history_weight);
+}
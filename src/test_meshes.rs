@@ -0,0 +1,32 @@
+// meshes the tests share
+
+use crate::model;
+
+// a unit cube around the origin, four vertices per face wound counter clockwise from outside
+pub fn cube() -> (Vec<model::ModelVertex>, Vec<u32>) {
+	let mut vertices = vec![];
+	let mut indices = vec![];
+	for axis in 0..3 {
+		for side in [-0.5, 0.5] {
+			let base = vertices.len() as u32;
+			for (u, v) in [(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)] {
+				let mut position = [0.0; 3];
+				position[axis] = side;
+				position[(axis + 1) % 3] = u;
+				position[(axis + 2) % 3] = v;
+				let mut normal = [0.0; 3];
+				normal[axis] = side * 2.0;
+				vertices.push(model::ModelVertex {
+					position,
+					tex_coords: [u + 0.5, v + 0.5],
+					normal,
+					tangent: [0.0; 4],
+					lightmap_coords: [0.0; 2],
+				});
+			}
+			let quad = if side > 0.0 { [0, 1, 2, 0, 2, 3] } else { [0, 2, 1, 0, 3, 2] };
+			indices.extend(quad.map(|i| base + i));
+		}
+	}
+	(vertices, indices)
+}
@@ -0,0 +1,79 @@
+// Calibrated test patterns, written as linear working space values like any lit surface
+
+@group(0) @binding(0)
+var<uniform> pattern: u32; // the TestPattern
+
+struct VertexOutput {
+	@builtin(position) position: vec4<f32>,
+	@location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+	// one triangle covering the screen
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	var out: VertexOutput;
+	out.position = vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+	out.uv = vec2<f32>(uv.x, 1.0 - uv.y);
+	return out;
+}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+	return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+// four bands of gray, red, green, and blue, each split into a linear ramp above a perceptual one
+fn ramps(uv: vec2<f32>) -> vec3<f32> {
+	let band = min(u32(uv.y * 4.0), 3u);
+	let tint = array<vec3<f32>, 4>(vec3<f32>(1.0), vec3<f32>(1.0, 0.0, 0.0), vec3<f32>(0.0, 1.0, 0.0), vec3<f32>(0.0, 0.0, 1.0))[band];
+	let perceptual = fract(uv.y * 4.0) >= 0.5;
+	let t = vec3<f32>(uv.x);
+	return tint * select(t, srgb_to_linear(t), perceptual);
+}
+
+/*
+Eleven steps of sRGB gray across the top, evenly spaced to the eye when the output is right.
+Below, a checker of black and white pixels beside linear 0.5, which should look the same from
+a distance since the checker averages to half the light.
+*/
+fn gray_chart(uv: vec2<f32>, pixel: vec2<u32>) -> vec3<f32> {
+	if uv.y < 0.5 {
+		let step = f32(min(u32(uv.x * 11.0), 10u)) / 10.0;
+		return srgb_to_linear(vec3<f32>(step));
+	}
+	if uv.x < 0.5 {
+		return vec3<f32>(f32((pixel.x + pixel.y) & 1u));
+	}
+	return vec3<f32>(0.5);
+}
+
+fn primaries(uv: vec2<f32>) -> vec3<f32> {
+	let bars = array<vec3<f32>, 8>(
+		vec3<f32>(1.0, 0.0, 0.0),
+		vec3<f32>(0.0, 1.0, 0.0),
+		vec3<f32>(0.0, 0.0, 1.0),
+		vec3<f32>(0.0, 1.0, 1.0),
+		vec3<f32>(1.0, 0.0, 1.0),
+		vec3<f32>(1.0, 1.0, 0.0),
+		vec3<f32>(1.0),
+		vec3<f32>(0.0),
+	);
+	return bars[min(u32(uv.x * 8.0), 7u)];
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+	var color: vec3<f32>;
+	switch pattern {
+		case 0u: {
+			color = ramps(in.uv);
+		}
+		case 1u: {
+			color = gray_chart(in.uv, vec2<u32>(in.position.xy));
+		}
+		default: {
+			color = primaries(in.uv);
+		}
+	}
+	return vec4<f32>(color, 1.0);
+}
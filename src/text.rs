@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+
+use ab_glyph::{Font, ScaleFont};
+use wgpu::util::DeviceExt;
+
+use crate::bind_group;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct TextVertex {
+	position: [f32; 2], // pixels from the top left
+	tex_coords: [f32; 2],
+	color: [f32; 4],
+}
+
+impl TextVertex {
+	const ATTRIBS: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![0 => Float32x2, 1 => Float32x2, 2 => Float32x4];
+
+	fn desc() -> wgpu::VertexBufferLayout<'static> {
+		wgpu::VertexBufferLayout {
+			array_stride: std::mem::size_of::<TextVertex>() as wgpu::BufferAddress,
+			step_mode: wgpu::VertexStepMode::Vertex,
+			attributes: &Self::ATTRIBS,
+		}
+	}
+}
+
+// where a rasterized glyph is in the atlas, and where it goes from the pen
+#[derive(Copy, Clone, Debug)]
+struct AtlasGlyph {
+	min: [f32; 2], // offset of the top left from the pen on the baseline, in pixels
+	size: [u32; 2],
+	uv_min: [f32; 2],
+	uv_max: [f32; 2],
+}
+
+struct TextRun {
+	text: String,
+	position: [f32; 2],
+	size: f32,
+	color: [f32; 4],
+}
+
+/*
+Screen space text over the finished frame, for HUDs and counters. Text is queued with
+Renderer::draw_text during the frame and laid out when the overlay is drawn, after the output
+stage, so it's sharp at the window resolution whatever the render scale. Glyphs are rasterized
+with ab_glyph the first time a glyph is used at a pixel size, and packed onto shelves of a
+single channel atlas. The queue is cleared after every frame.
+The atlas is cleared and refilled when it runs out of space.
+*/
+pub struct TextRenderer {
+	font: ab_glyph::FontArc,
+	runs: Vec<TextRun>,
+	glyphs: HashMap<(ab_glyph::GlyphId, u32), Option<AtlasGlyph>>, // None for glyphs without an outline, like spaces
+	atlas: wgpu::Texture,
+	shelf: [u32; 3], // x and y of the next glyph, and the tallest glyph on the shelf
+	screen_buffer: wgpu::Buffer,
+	bind_group: wgpu::BindGroup,
+	pipeline: wgpu::RenderPipeline,
+	vertex_buffer: wgpu::Buffer,
+	capacity: usize, // in vertices
+}
+
+impl TextRenderer {
+	const ATLAS_SIZE: u32 = 1024;
+	const PADDING: u32 = 1;
+	const INITIAL_CAPACITY: usize = 6 * 256;
+
+	pub fn new(device: &wgpu::Device, color_format: wgpu::TextureFormat, font_data: Vec<u8>) -> anyhow::Result<Self> {
+		let font = ab_glyph::FontArc::try_from_vec(font_data)?;
+		let atlas = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some("Text Atlas"),
+			size: wgpu::Extent3d {
+				width: Self::ATLAS_SIZE,
+				height: Self::ATLAS_SIZE,
+				depth_or_array_layers: 1,
+			},
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: wgpu::TextureFormat::R8Unorm,
+			usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+			view_formats: &[],
+		});
+		let view = atlas.create_view(&wgpu::TextureViewDescriptor::default());
+		let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+			mag_filter: wgpu::FilterMode::Linear,
+			min_filter: wgpu::FilterMode::Linear,
+			..Default::default()
+		});
+		let screen_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Text Screen Buffer"),
+			contents: bytemuck::cast_slice(&[1.0f32; 4]),
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+		});
+
+		let layout = bind_group::LayoutBuilder::new(wgpu::ShaderStages::VERTEX)
+			.uniform(0)
+			.visibility(wgpu::ShaderStages::FRAGMENT)
+			.texture(1)
+			.sampler(2)
+			.build(device, "text_bind_group_layout");
+		let bind_group = bind_group::BindGroupBuilder::default()
+			.buffer(0, &screen_buffer)
+			.view(1, &view)
+			.sampler(2, &sampler)
+			.build(device, &layout, "text_bind_group");
+
+		let shader = device.create_shader_module(wgpu::include_wgsl!("text.wgsl"));
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some("Text Pipeline Layout"),
+			bind_group_layouts: &[Some(&layout)],
+			immediate_size: 0,
+		});
+		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some("Text Pipeline"),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: Some("vs_main"),
+				buffers: &[TextVertex::desc()],
+				compilation_options: Default::default(),
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: Some("fs_main"),
+				targets: &[Some(wgpu::ColorTargetState {
+					format: color_format,
+					blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+					write_mask: wgpu::ColorWrites::COLOR,
+				})],
+				compilation_options: Default::default(),
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			multiview_mask: None,
+			cache: None,
+		});
+
+		Ok(Self {
+			font,
+			runs: vec![],
+			glyphs: HashMap::new(),
+			atlas,
+			shelf: [0; 3],
+			screen_buffer,
+			bind_group,
+			pipeline,
+			vertex_buffer: Self::create_vertex_buffer(device, Self::INITIAL_CAPACITY),
+			capacity: Self::INITIAL_CAPACITY,
+		})
+	}
+
+	fn create_vertex_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+		device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some("Text Vertex Buffer"),
+			size: (capacity * std::mem::size_of::<TextVertex>()) as u64,
+			usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		})
+	}
+
+	// queues text for this frame, position is the top left in pixels, size is the line height in pixels
+	pub fn queue(&mut self, text: &str, position: [f32; 2], size: f32, color: [f32; 4]) {
+		self.runs.push(TextRun {
+			text: text.to_string(),
+			position,
+			size,
+			color,
+		});
+	}
+
+	// rasterizes a glyph into the atlas, None when it has no outline or doesn't fit
+	fn rasterize(&mut self, queue: &wgpu::Queue, id: ab_glyph::GlyphId, size: u32) -> Option<AtlasGlyph> {
+		let glyph = id.with_scale_and_position(size as f32, ab_glyph::point(0.0, 0.0));
+		let outlined = self.font.outline_glyph(glyph)?;
+		let bounds = outlined.px_bounds();
+		let (width, height) = (bounds.width().ceil() as u32, bounds.height().ceil() as u32);
+		if width == 0 || height == 0 || width + Self::PADDING > Self::ATLAS_SIZE || height + Self::PADDING > Self::ATLAS_SIZE {
+			return None;
+		}
+
+		// the next shelf when the glyph doesn't fit on this one
+		let [mut x, mut y, mut shelf_height] = self.shelf;
+		if x + width + Self::PADDING > Self::ATLAS_SIZE {
+			x = 0;
+			y += shelf_height + Self::PADDING;
+			shelf_height = 0;
+		}
+		if y + height + Self::PADDING > Self::ATLAS_SIZE {
+			return None;
+		}
+		self.shelf = [x + width + Self::PADDING, y, shelf_height.max(height)];
+
+		let mut coverage = vec![0u8; (width * height) as usize];
+		outlined.draw(|gx, gy, c| {
+			if gx < width && gy < height {
+				coverage[(gy * width + gx) as usize] = (c.clamp(0.0, 1.0) * 255.0) as u8;
+			}
+		});
+		queue.write_texture(
+			wgpu::TexelCopyTextureInfo {
+				texture: &self.atlas,
+				mip_level: 0,
+				origin: wgpu::Origin3d { x, y, z: 0 },
+				aspect: wgpu::TextureAspect::All,
+			},
+			&coverage,
+			wgpu::TexelCopyBufferLayout {
+				offset: 0,
+				bytes_per_row: Some(width),
+				rows_per_image: Some(height),
+			},
+			wgpu::Extent3d {
+				width,
+				height,
+				depth_or_array_layers: 1,
+			},
+		);
+
+		let atlas_size = Self::ATLAS_SIZE as f32;
+		Some(AtlasGlyph {
+			min: [bounds.min.x, bounds.min.y],
+			size: [width, height],
+			uv_min: [x as f32 / atlas_size, y as f32 / atlas_size],
+			uv_max: [(x + width) as f32 / atlas_size, (y + height) as f32 / atlas_size],
+		})
+	}
+
+	fn glyph(&mut self, queue: &wgpu::Queue, id: ab_glyph::GlyphId, size: u32) -> Option<AtlasGlyph> {
+		if let Some(glyph) = self.glyphs.get(&(id, size)) {
+			return *glyph;
+		}
+		let mut glyph = self.rasterize(queue, id, size);
+		// a full atlas starts over, the glyphs this frame already placed are rasterized again next frame
+		if glyph.is_none() && self.font.outline_glyph(id.with_scale(size as f32)).is_some() {
+			self.glyphs.clear();
+			self.shelf = [0; 3];
+			glyph = self.rasterize(queue, id, size);
+		}
+		self.glyphs.insert((id, size), glyph);
+		glyph
+	}
+
+	// lays out the queued text into quads, rasterizing new glyphs on the way
+	fn layout(&mut self, queue: &wgpu::Queue) -> Vec<TextVertex> {
+		let mut vertices = vec![];
+		for run in std::mem::take(&mut self.runs) {
+			let size = run.size.round().max(1.0) as u32;
+			let font = self.font.clone();
+			let scaled = font.as_scaled(size as f32);
+			let [left, top] = run.position;
+			let mut pen = [left, top + scaled.ascent()];
+			let mut previous = None;
+			for c in run.text.chars() {
+				if c == '\n' {
+					pen = [left, pen[1] + scaled.height() + scaled.line_gap()];
+					previous = None;
+					continue;
+				}
+				let id = scaled.glyph_id(c);
+				if let Some(previous) = previous {
+					pen[0] += scaled.kern(previous, id);
+				}
+				previous = Some(id);
+				if let Some(glyph) = self.glyph(queue, id, size) {
+					let x0 = (pen[0] + glyph.min[0]).round();
+					let y0 = (pen[1] + glyph.min[1]).round();
+					let (x1, y1) = (x0 + glyph.size[0] as f32, y0 + glyph.size[1] as f32);
+					let corner = |x, y, u, v| TextVertex {
+						position: [x, y],
+						tex_coords: [u, v],
+						color: run.color,
+					};
+					let [u0, v0] = glyph.uv_min;
+					let [u1, v1] = glyph.uv_max;
+					vertices.extend_from_slice(&[
+						corner(x0, y0, u0, v0), corner(x0, y1, u0, v1), corner(x1, y1, u1, v1),
+						corner(x0, y0, u0, v0), corner(x1, y1, u1, v1), corner(x1, y0, u1, v0),
+					]);
+				}
+				pen[0] += scaled.h_advance(id);
+			}
+		}
+		vertices
+	}
+
+	// draws the queued text over view, which is screen_size pixels, and clears the queue
+	pub fn draw(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, screen_size: [u32; 2]) {
+		if self.runs.is_empty() {
+			return;
+		}
+		let vertices = self.layout(queue);
+		if vertices.is_empty() {
+			return;
+		}
+		if vertices.len() > self.capacity {
+			self.capacity = vertices.len().next_power_of_two();
+			self.vertex_buffer = Self::create_vertex_buffer(device, self.capacity);
+		}
+		queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&vertices));
+		queue.write_buffer(&self.screen_buffer, 0, bytemuck::cast_slice(&[screen_size[0] as f32, screen_size[1] as f32, 0.0, 0.0]));
+
+		let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Text Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: wgpu::StoreOp::Store,
+				},
+				depth_slice: None,
+			})],
+			depth_stencil_attachment: None,
+			occlusion_query_set: None,
+			timestamp_writes: None,
+			multiview_mask: None,
+		});
+		render_pass.set_pipeline(&self.pipeline);
+		render_pass.set_bind_group(0, &self.bind_group, &[]);
+		render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+		render_pass.draw(0..vertices.len() as u32, 0..1);
+	}
+}
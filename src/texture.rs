@@ -1,14 +1,18 @@
 use image::GenericImageView;
 use anyhow::*;
 
+#[cfg(feature = "ktx2")]
+use crate::compressed;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum TextureType {
 	Diffuse,
 	Normal,
 	Cubemap,
 }
 
+#[derive(Clone)]
 pub struct Texture {
-	#[allow(unused)]
 	pub texture: wgpu::Texture,
 	pub view: wgpu::TextureView,
 	pub sampler: wgpu::Sampler,
@@ -22,6 +26,10 @@ impl Texture {
 		label: &str,
 		ty: TextureType,
 	) -> Result<Self> {
+		#[cfg(feature = "ktx2")]
+		if compressed::is_ktx2(bytes) {
+			return compressed::load_ktx2(device, queue, bytes, label, ty);
+		}
 		let img = image::load_from_memory(bytes)?;
 		Self::from_images(device, queue, &vec![img], Some(label), ty)
 	}
@@ -95,17 +103,89 @@ impl Texture {
 			},
 			..Default::default()
 		});
+		// material textures repeat so they can be tiled
+		let address_mode = match ty {
+			TextureType::Cubemap => wgpu::AddressMode::ClampToEdge,
+			_ => wgpu::AddressMode::Repeat,
+		};
+		let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+			address_mode_u: address_mode,
+			address_mode_v: address_mode,
+			address_mode_w: address_mode,
+			mag_filter: wgpu::FilterMode::Linear,
+			min_filter: wgpu::FilterMode::Nearest,
+			mipmap_filter: wgpu::MipmapFilterMode::Nearest,
+			..Default::default()
+		});
+
+		Ok(Self{ texture, view, sampler })
+	}
+
+	pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+	// a cubemap that keeps values over 1, faces are linear rgb in cube face order, size * size each
+	pub fn hdr_cubemap(device: &wgpu::Device, queue: &wgpu::Queue, faces: &[Vec<[f32; 3]>], size: u32, label: &str) -> Self {
+		let texture = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some(label),
+			size: wgpu::Extent3d {
+				width: size,
+				height: size,
+				depth_or_array_layers: 6,
+			},
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: Self::HDR_FORMAT,
+			usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+			view_formats: &[],
+		});
+
+		for (idx, face) in faces.iter().enumerate() {
+			let texels = face.iter()
+				.flat_map(|&[r, g, b]| [r, g, b, 1.0])
+				.map(|c| half::f16::from_f32(c).to_bits())
+				.collect::<Vec<u16>>();
+			queue.write_texture(
+				wgpu::TexelCopyTextureInfo {
+					texture: &texture,
+					mip_level: 0,
+					origin: wgpu::Origin3d {
+						x: 0,
+						y: 0,
+						z: idx as u32,
+					},
+					aspect: wgpu::TextureAspect::All,
+				},
+				bytemuck::cast_slice(&texels),
+				wgpu::TexelCopyBufferLayout {
+					offset: 0,
+					bytes_per_row: Some(8 * size),
+					rows_per_image: Some(size),
+				},
+				wgpu::Extent3d {
+					width: size,
+					height: size,
+					depth_or_array_layers: 1,
+				},
+			);
+		}
+
+		let view = texture.create_view(&wgpu::TextureViewDescriptor {
+			label: Some("Texture View"),
+			dimension: Some(wgpu::TextureViewDimension::Cube),
+			..Default::default()
+		});
 		let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
 			address_mode_u: wgpu::AddressMode::ClampToEdge,
 			address_mode_v: wgpu::AddressMode::ClampToEdge,
 			address_mode_w: wgpu::AddressMode::ClampToEdge,
 			mag_filter: wgpu::FilterMode::Linear,
-			min_filter: wgpu::FilterMode::Nearest,
+			min_filter: wgpu::FilterMode::Linear,
 			mipmap_filter: wgpu::MipmapFilterMode::Nearest,
 			..Default::default()
 		});
 
-		Ok(Self{ texture, view, sampler })
+		Self { texture, view, sampler }
 	}
 
 	pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
@@ -144,4 +224,42 @@ impl Texture {
 
 		Self {texture, view, sampler}
 	}
-}
\ No newline at end of file
+
+	// screen sized texture that's rendered to and then sampled by a later pass
+	pub fn create_render_target(
+		device: &wgpu::Device,
+		config: &wgpu::SurfaceConfiguration,
+		format: wgpu::TextureFormat,
+		usage: wgpu::TextureUsages,
+		label: &str,
+	) -> Self {
+		let size = wgpu::Extent3d {
+			width: config.width.max(1),
+			height: config.height.max(1),
+			depth_or_array_layers: 1,
+		};
+		let texture = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some(label),
+			size,
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format,
+			usage: usage | wgpu::TextureUsages::TEXTURE_BINDING,
+			view_formats: &[],
+		});
+
+		let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+		let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+			address_mode_u: wgpu::AddressMode::ClampToEdge,
+			address_mode_v: wgpu::AddressMode::ClampToEdge,
+			address_mode_w: wgpu::AddressMode::ClampToEdge,
+			mag_filter: wgpu::FilterMode::Linear,
+			min_filter: wgpu::FilterMode::Linear,
+			mipmap_filter: wgpu::MipmapFilterMode::Nearest,
+			..Default::default()
+		});
+
+		Self {texture, view, sampler}
+	}
+}
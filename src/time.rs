@@ -0,0 +1,58 @@
+use winit::keyboard::KeyCode;
+
+/*
+Scales the time scene updates and animations advance by, the camera keeps using real time.
+P pauses, [ and ] halve and double the time scale, and . steps a single frame while paused.
+*/
+pub struct TimeControl {
+	pub scale: f32,
+	pub paused: bool,
+	step_pending: bool,
+}
+
+impl TimeControl {
+	const STEP: f32 = 1.0 / 60.0; // scene time a frame step advances
+	const MIN_SCALE: f32 = 1.0 / 64.0;
+	const MAX_SCALE: f32 = 16.0;
+
+	pub fn new() -> Self {
+		Self {
+			scale: 1.0,
+			paused: false,
+			step_pending: false,
+		}
+	}
+
+	// pauses if needed and advances the next frame by one step
+	pub fn step(&mut self) {
+		self.paused = true;
+		self.step_pending = true;
+	}
+
+	// scene time to advance by for a frame that took delta_time
+	pub fn advance(&mut self, delta_time: f32) -> f32 {
+		if std::mem::take(&mut self.step_pending) {
+			Self::STEP
+		} else if self.paused {
+			0.0
+		} else {
+			delta_time * self.scale
+		}
+	}
+
+	// returns true if the key was used
+	pub fn handle_key(&mut self, code: KeyCode, is_pressed: bool) -> bool {
+		if !is_pressed {
+			return false;
+		}
+		match code {
+			KeyCode::KeyP => self.paused = !self.paused,
+			KeyCode::BracketLeft => self.scale = (self.scale * 0.5).max(Self::MIN_SCALE),
+			KeyCode::BracketRight => self.scale = (self.scale * 2.0).min(Self::MAX_SCALE),
+			KeyCode::Period => self.step(),
+			_ => return false,
+		}
+		log::info!("Time scale {}{}", self.scale, if self.paused { " (paused)" } else { "" });
+		true
+	}
+}
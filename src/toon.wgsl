@@ -0,0 +1,75 @@
+// Cel shading, in place of surface.wgsl: the diffuse light steps through the ramp texture, with a
+// rim light around the silhouette and a hard edged specular highlight. It lights with the same
+// lights and shadows as the other materials, but the deferred path lights its G-buffer with the
+// regular model since the ramp isn't there, so toon materials look their part on the forward path.
+
+@group(0) @binding(1)
+var diffuse_texture: texture_2d<f32>;
+@group(0) @binding(2)
+var diffuse_sampler: sampler;
+@group(0) @binding(3)
+var ramp_texture: texture_2d<f32>; // dark to light across, read at the half Lambert term
+@group(0) @binding(4)
+var ramp_sampler: sampler;
+
+// ramp samplers repeat like every material texture, so stay half a texel from the ends
+fn toon_ramp(n_dot_l: f32) -> vec3<f32> {
+	let width = f32(textureDimensions(ramp_texture).x);
+	let u = clamp(n_dot_l * 0.5 + 0.5, 0.5 / width, 1.0 - 0.5 / width);
+	return textureSampleLevel(ramp_texture, ramp_sampler, vec2<f32>(u, 0.5), 0.0).xyz;
+}
+
+fn light_toon(surface: SurfaceParams) -> vec3<f32> {
+	if (surface.illum == 0u) {
+		return apply_fog(surface.color, surface.position);
+	}
+
+	let eye_dir = normalize(camera.position.xyz - surface.position);
+	let n_dot_v = max(dot(eye_dir, surface.normal), 0.0);
+	let irradiance = textureSampleLevel(irradiance_texture, ibl_sampler, surface.normal, 0.0).xyz;
+	let ambient_col = irradiance * environment.ambient_color * environment.ambient_intensity;
+
+	// shadows darken the ramp's input rather than its output, so they fall on its dark steps
+	var diffuse_col = vec3<f32>(0.0);
+	var specular_col = vec3<f32>(0.0);
+	let specular_edge = 1.0 - material.toon.z * 0.1;
+	for (var i = 0u; i < light_count(); i++) {
+		let light = lights.lights[i];
+		let to_light = light_direction(light, surface.position);
+		let visibility = light_visibility(light, surface.position, surface.geometric_normal) * capsule_visibility(surface.position, to_light);
+		let light_col = light_radiance(light, to_light);
+
+		let n_dot_l = dot(surface.normal, to_light.xyz);
+		diffuse_col += light_col * toon_ramp(mix(-1.0, n_dot_l, visibility));
+		if (surface.illum >= 2u) {
+			let half_dir = normalize(to_light.xyz + eye_dir);
+			let n_dot_h = max(dot(surface.normal, half_dir), 0.0);
+			specular_col += light_col * surface.specular * smoothstep(specular_edge - 0.005, specular_edge + 0.005, n_dot_h) * visibility;
+		}
+	}
+
+	// the rim catches the light falling on the surface, so it doesn't glow on the dark side
+	let rim_edge = 1.0 - material.toon.y;
+	let rim = smoothstep(rim_edge - 0.02, rim_edge + 0.02, 1.0 - n_dot_v) * material.toon.x;
+	let rim_col = (ambient_col + diffuse_col) * rim;
+
+	let emissive_col = surface.color * surface.emissive;
+	let result = ((ambient_col + diffuse_col + rim_col) * surface.color + specular_col + emissive_col) * environment.exposure;
+	return apply_tonemap(apply_fog(result, surface.position));
+}
+
+fn material_surface(in: VertexOutput) -> MaterialSurface {
+	let obj_col = sample_material_texture(diffuse_texture, diffuse_sampler, in, 1.0);
+	let obj_norm = normalize(in.normal);
+
+	return MaterialSurface(obj_col, obj_norm);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+	let surface = material_surface(in);
+	model = models[in.instance];
+	let alpha = surface.color.w * model.tint.w * material.opacity;
+	let color = light_toon(material_params(in, surface.color, surface.normal));
+	return fragment_output(vec4<f32>(color, alpha), surface.normal);
+}
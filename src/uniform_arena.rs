@@ -0,0 +1,222 @@
+/*
+One large uniform buffer that per-object and per-material uniforms are suballocated from
+every frame, then bound with dynamic offsets. The buffer is split into one region per frame
+in flight, so writing the current frame's uniforms never touches a region the GPU may still
+be reading from. The buffer has room past the last region so a binding wider than what was
+pushed at its offset, like an array of model uniforms, still fits.
+
+A frame that runs out of room doubles the regions. The pushes only write the CPU copy, so the
+buffer is replaced afterwards in reallocate, and every bind group over the old buffer has to be
+rebuilt before drawing, the generation tells which ones are out of date.
+*/
+pub struct UniformArena {
+	buffer: wgpu::Buffer,
+	regions: Regions,
+	label: String,
+	generation: u32, // counts the buffers, bumped every time it's replaced
+}
+
+impl UniformArena {
+	pub const BINDING_WINDOW: u64 = 16 * 1024; // WebGL2's max uniform binding size
+
+	// region_size is the space available to a single frame, to start with
+	pub fn new(device: &wgpu::Device, region_size: u64, frames_in_flight: usize, label: &str) -> Self {
+		let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+		let regions = Regions::new(region_size, alignment, frames_in_flight);
+		Self {
+			buffer: Self::create_buffer(device, regions.capacity(), label),
+			regions,
+			label: label.to_string(),
+			generation: 0,
+		}
+	}
+
+	fn create_buffer(device: &wgpu::Device, capacity: u64, label: &str) -> wgpu::Buffer {
+		device.create_buffer(&wgpu::BufferDescriptor {
+			label: Some(label),
+			size: capacity + Self::BINDING_WINDOW,
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+			mapped_at_creation: false,
+		})
+	}
+
+	pub fn buffer(&self) -> &wgpu::Buffer {
+		&self.buffer
+	}
+
+	pub fn capacity(&self) -> u64 {
+		self.regions.capacity()
+	}
+
+	pub fn generation(&self) -> u32 {
+		self.generation
+	}
+
+	// binding for a single T at a dynamic offset, used when creating bind groups
+	pub fn binding<T: bytemuck::Pod>(&self) -> wgpu::BindingResource<'_> {
+		wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+			buffer: &self.buffer,
+			offset: 0,
+			size: wgpu::BufferSize::new(std::mem::size_of::<T>() as u64),
+		})
+	}
+
+	// binding for count Ts in a row at a dynamic offset, see push_slice
+	pub fn binding_array<T: bytemuck::Pod>(&self, count: usize) -> wgpu::BindingResource<'_> {
+		let size = (std::mem::size_of::<T>() * count) as u64;
+		assert!(size <= Self::BINDING_WINDOW, "binding of {} bytes is wider than the arena allows", size);
+		wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+			buffer: &self.buffer,
+			offset: 0,
+			size: wgpu::BufferSize::new(size),
+		})
+	}
+
+	// starts allocating from the region belonging to the given frame in flight
+	pub fn begin_frame(&mut self, frame_index: usize) {
+		self.regions.begin_frame(frame_index);
+	}
+
+	// copies the value into the arena and returns its dynamic offset
+	pub fn push<T: bytemuck::Pod>(&mut self, value: &T) -> u32 {
+		self.regions.push(bytemuck::bytes_of(value))
+	}
+
+	// copies the values next to each other, the offset is the first one's
+	pub fn push_slice<T: bytemuck::Pod>(&mut self, values: &[T]) -> u32 {
+		self.regions.push(bytemuck::cast_slice(values))
+	}
+
+	// replaces the buffer when this frame's pushes grew the regions, true when the bind groups over it need rebuilding
+	pub fn reallocate(&mut self, device: &wgpu::Device) -> bool {
+		if !std::mem::take(&mut self.regions.grown) {
+			return false;
+		}
+		log::info!("{} grew to {} bytes per frame", self.label, self.regions.region_size);
+		self.buffer = Self::create_buffer(device, self.regions.capacity(), &self.label);
+		self.generation += 1;
+		true
+	}
+
+	// writes everything pushed this frame with a single write
+	pub fn upload(&self, queue: &wgpu::Queue) {
+		assert!(!self.regions.grown, "the uniform arena grew, reallocate it before uploading");
+		let start = self.regions.region_start;
+		// writes have to be a multiple of 4 bytes
+		let end = self.regions.head.next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT);
+		if end > start {
+			queue.write_buffer(&self.buffer, start, &self.regions.contents[start as usize..end as usize]);
+		}
+	}
+}
+
+// the CPU copy of the buffer and where each frame's region is in it
+struct Regions {
+	contents: Vec<u8>,
+	alignment: u64,
+	region_size: u64,
+	region_starts: Vec<u64>, // by frame in flight
+	region_start: u64,
+	head: u64,
+	grown: bool, // since the buffer was last created
+}
+
+impl Regions {
+	fn new(region_size: u64, alignment: u64, frames_in_flight: usize) -> Self {
+		let region_size = region_size.next_multiple_of(alignment);
+		let region_starts = (0..frames_in_flight as u64).map(|index| region_size * index).collect::<Vec<_>>();
+		Self {
+			contents: vec![0; (region_size * frames_in_flight as u64) as usize],
+			alignment,
+			region_size,
+			region_starts,
+			region_start: 0,
+			head: 0,
+			grown: false,
+		}
+	}
+
+	fn capacity(&self) -> u64 {
+		self.contents.len() as u64
+	}
+
+	fn begin_frame(&mut self, frame_index: usize) {
+		let Some(&start) = self.region_starts.get(frame_index) else {
+			panic!("frame index {} has no uniform arena region", frame_index);
+		};
+		self.region_start = start;
+		self.head = start;
+	}
+
+	fn push(&mut self, bytes: &[u8]) -> u32 {
+		let size = bytes.len() as u64;
+
+		let offset = self.head.next_multiple_of(self.alignment);
+		if offset + size > self.region_start + self.region_size {
+			self.grow(offset + size - self.region_start);
+		}
+
+		self.contents[offset as usize..(offset + size) as usize].copy_from_slice(bytes);
+		self.head = offset + size;
+
+		offset as u32
+	}
+
+	/*
+	Doubles the regions until this frame's fits. The current region keeps its start so the
+	offsets already handed out this frame stay valid, the others move past it, what they held
+	belonged to earlier frames and is pushed again when they come around.
+	*/
+	fn grow(&mut self, needed: u64) {
+		let mut region_size = self.region_size * 2;
+		while region_size < needed {
+			region_size *= 2;
+		}
+		let mut next = self.region_start + region_size;
+		for start in &mut self.region_starts {
+			if *start != self.region_start {
+				*start = next;
+				next += region_size;
+			}
+		}
+		self.contents.resize(next as usize, 0);
+		self.region_size = region_size;
+		self.grown = true;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn overflowing_a_region_grows_it() {
+		let mut regions = Regions::new(256, 256, 2);
+		regions.begin_frame(1);
+		let first = regions.push(&[1; 200]);
+		let second = regions.push(&[2; 200]);
+		assert!(regions.grown);
+		assert_eq!(first, 256, "an offset handed out before growing moved");
+		assert_eq!(second, 512);
+		assert!(regions.region_size >= 456);
+		assert!(regions.contents[first as usize..first as usize + 200].iter().all(|&byte| byte == 1));
+		assert!(regions.contents[second as usize..second as usize + 200].iter().all(|&byte| byte == 2));
+
+		// the other frame's region moved out of the way of the grown one
+		regions.begin_frame(0);
+		let other = regions.push(&[3; 200]) as u64;
+		assert!(other >= 256 + regions.region_size);
+		assert!(other + 200 <= regions.capacity());
+	}
+
+	#[test]
+	fn one_push_larger_than_double_the_region() {
+		let mut regions = Regions::new(256, 256, 3);
+		regions.begin_frame(2);
+		let offset = regions.push(&[7; 2000]) as u64;
+		assert_eq!(offset, 512);
+		assert!(regions.region_size >= 2000);
+		let ends = regions.region_starts.iter().map(|&start| start + regions.region_size);
+		assert!(ends.max().unwrap() <= regions.capacity());
+	}
+}
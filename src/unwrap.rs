@@ -0,0 +1,208 @@
+use std::collections::{HashMap, VecDeque};
+
+use cgmath::InnerSpace;
+
+use crate::model;
+
+#[derive(Clone, Debug)]
+pub struct UnwrapSettings {
+	pub max_angle: f32, // degrees a triangle can turn from its chart's normal and still join it
+	pub resolution: u32, // of the texture the uvs are meant for, padding is counted in its texels
+	pub padding: u32, // texels between charts, so bakes can dilate without bleeding into their neighbours
+}
+
+impl Default for UnwrapSettings {
+	fn default() -> Self {
+		Self {
+			max_angle: 45.0,
+			resolution: 1024,
+			padding: 4,
+		}
+	}
+}
+
+/*
+A mesh cut along its chart borders, with a uv in the second channel for every vertex. The
+vertices keep their own tex_coords, vertices on a border are copied once per chart.
+*/
+pub struct UnwrappedMesh {
+	pub vertices: Vec<model::ModelVertex>,
+	pub indices: Vec<u32>,
+	pub uvs: Vec<[f32; 2]>, // by vertex, 0 to 1 and without overlaps
+}
+
+impl UnwrappedMesh {
+	// the generated uvs in the vertices' lightmap coords, their tex_coords are left as they were
+	pub fn into_geometry(self) -> (Vec<model::ModelVertex>, Vec<u32>) {
+		let vertices = self.vertices.into_iter().zip(self.uvs)
+			.map(|(vertex, uv)| model::ModelVertex { lightmap_coords: uv, ..vertex })
+			.collect();
+		(vertices, self.indices)
+	}
+}
+
+// triangles that are flattened together
+struct Chart {
+	triangles: Vec<usize>,
+	normal: cgmath::Vector3<f32>,
+	min: cgmath::Vector2<f32>, // bounds of the projected triangles
+	max: cgmath::Vector2<f32>,
+	offset: cgmath::Vector2<f32>, // where the bounds' min goes in the atlas, in world units
+}
+
+// a tangent and bitangent across the plane of a normal
+fn plane_basis(normal: cgmath::Vector3<f32>) -> (cgmath::Vector3<f32>, cgmath::Vector3<f32>) {
+	let helper = if normal.y.abs() < 0.9 { cgmath::Vector3::unit_y() } else { cgmath::Vector3::unit_x() };
+	let tangent = helper.cross(normal).normalize();
+	(tangent, normal.cross(tangent))
+}
+
+/*
+Generates non-overlapping uvs for lightmaps and texture bakes, for meshes that have none or
+whose uvs overlap. Like xatlas, but much simpler: triangles are grown into charts while they
+face within max_angle of the chart, each chart is projected flat onto its plane at world scale,
+and the charts are packed onto shelves, tallest first, then scaled into the unit square. Charts
+keep their proportions, so texel density is even across the mesh.
+Charts aren't rotated before packing, so long diagonal ones waste space.
+*/
+pub fn unwrap(vertices: &[model::ModelVertex], indices: &[u32], settings: &UnwrapSettings) -> UnwrappedMesh {
+	let triangle_count = indices.len() / 3;
+	let position = |triangle: usize, corner: usize| cgmath::Vector3::from(vertices[indices[triangle * 3 + corner] as usize].position);
+	// area weighted normals
+	let normals = (0..triangle_count)
+		.map(|triangle| (position(triangle, 1) - position(triangle, 0)).cross(position(triangle, 2) - position(triangle, 0)))
+		.collect::<Vec<_>>();
+
+	// triangles sharing an edge, by welded position so uv seams in the input don't split charts
+	let key = |index: u32| vertices[index as usize].position.map(f32::to_bits);
+	let mut edges = HashMap::<_, Vec<usize>>::new();
+	for triangle in 0..triangle_count {
+		for (i, j) in [(0, 1), (1, 2), (2, 0)] {
+			let (a, b) = (key(indices[triangle * 3 + i]), key(indices[triangle * 3 + j]));
+			edges.entry(if a < b { (a, b) } else { (b, a) }).or_default().push(triangle);
+		}
+	}
+	let mut neighbours = vec![vec![]; triangle_count];
+	for triangles in edges.values() {
+		for &a in triangles {
+			neighbours[a].extend(triangles.iter().copied().filter(|&b| b != a));
+		}
+	}
+
+	// grow charts breadth first from the first unassigned triangle
+	let min_cos = settings.max_angle.to_radians().cos();
+	let mut chart_of = vec![usize::MAX; triangle_count];
+	let mut charts = vec![];
+	for seed in 0..triangle_count {
+		if chart_of[seed] != usize::MAX {
+			continue;
+		}
+		let chart = charts.len();
+		let mut normal_sum = normals[seed];
+		let mut triangles = vec![seed];
+		chart_of[seed] = chart;
+		let mut queue = VecDeque::from([seed]);
+		while let Some(triangle) = queue.pop_front() {
+			for &neighbour in &neighbours[triangle] {
+				if chart_of[neighbour] != usize::MAX {
+					continue;
+				}
+				let normal = normals[neighbour];
+				if normal.magnitude2() > 0.0 && normal_sum.magnitude2() > 0.0 && normal.normalize().dot(normal_sum.normalize()) < min_cos {
+					continue;
+				}
+				chart_of[neighbour] = chart;
+				normal_sum += normal;
+				triangles.push(neighbour);
+				queue.push_back(neighbour);
+			}
+		}
+		let normal = if normal_sum.magnitude2() > 0.0 { normal_sum.normalize() } else { cgmath::Vector3::unit_z() };
+		charts.push(Chart {
+			triangles,
+			normal,
+			min: cgmath::Vector2::new(f32::MAX, f32::MAX),
+			max: cgmath::Vector2::new(f32::MIN, f32::MIN),
+			offset: cgmath::Vector2::new(0.0, 0.0),
+		});
+	}
+
+	// one output vertex per input vertex per chart, projected onto the chart's plane
+	let mut remap = HashMap::new();
+	let mut out_vertices = vec![];
+	let mut projected = vec![];
+	let mut out_charts = vec![];
+	let mut out_indices = vec![0; triangle_count * 3];
+	for (index, chart) in charts.iter_mut().enumerate() {
+		let (tangent, bitangent) = plane_basis(chart.normal);
+		for &triangle in &chart.triangles {
+			for corner in 0..3 {
+				let vertex = indices[triangle * 3 + corner];
+				out_indices[triangle * 3 + corner] = *remap.entry((index, vertex)).or_insert_with(|| {
+					let position = cgmath::Vector3::from(vertices[vertex as usize].position);
+					let uv = cgmath::Vector2::new(position.dot(tangent), position.dot(bitangent));
+					chart.min = cgmath::Vector2::new(chart.min.x.min(uv.x), chart.min.y.min(uv.y));
+					chart.max = cgmath::Vector2::new(chart.max.x.max(uv.x), chart.max.y.max(uv.y));
+					out_vertices.push(vertices[vertex as usize]);
+					projected.push(uv);
+					out_charts.push(index);
+					out_vertices.len() as u32 - 1
+				});
+			}
+		}
+	}
+
+	// shelves about as wide as the charts would be tall packed into a square
+	let area = charts.iter().map(|chart| (chart.max.x - chart.min.x) * (chart.max.y - chart.min.y)).sum::<f32>();
+	let widest = charts.iter().map(|chart| chart.max.x - chart.min.x).fold(0.0, f32::max);
+	let width = (area.sqrt() * 1.1).max(widest);
+	let padding = width * settings.padding as f32 / settings.resolution.max(1) as f32;
+	let mut order = (0..charts.len()).collect::<Vec<_>>();
+	order.sort_by(|&a, &b| (charts[b].max.y - charts[b].min.y).total_cmp(&(charts[a].max.y - charts[a].min.y)));
+	let (mut x, mut y, mut shelf_height, mut used_width) = (padding, padding, 0.0f32, 0.0f32);
+	for chart in order {
+		let size = charts[chart].max - charts[chart].min;
+		if x + size.x + padding > width + padding * 2.0 && x > padding {
+			x = padding;
+			y += shelf_height + padding;
+			shelf_height = 0.0;
+		}
+		charts[chart].offset = cgmath::Vector2::new(x, y);
+		x += size.x + padding;
+		used_width = used_width.max(x);
+		shelf_height = shelf_height.max(size.y);
+	}
+	let scale = 1.0 / used_width.max(y + shelf_height + padding).max(f32::EPSILON);
+
+	let uvs = projected.iter().zip(&out_charts)
+		.map(|(uv, &chart)| {
+			let chart = &charts[chart];
+			((uv - chart.min + chart.offset) * scale).into()
+		})
+		.collect();
+	UnwrappedMesh {
+		vertices: out_vertices,
+		indices: out_indices,
+		uvs,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::test_meshes;
+
+	#[test]
+	fn unwrapping_keeps_the_original_uvs() {
+		let (vertices, indices) = test_meshes::cube();
+		let (unwrapped, unwrapped_indices) = unwrap(&vertices, &indices, &UnwrapSettings::default()).into_geometry();
+		assert_eq!(unwrapped_indices.len(), indices.len());
+		for vertex in &unwrapped {
+			let original = vertices.iter()
+				.find(|original| original.position == vertex.position && original.normal == vertex.normal)
+				.expect("the unwrap made a vertex that wasn't in the mesh");
+			assert_eq!(vertex.tex_coords, original.tex_coords);
+			assert!(vertex.lightmap_coords.iter().all(|&uv| (0.0..=1.0).contains(&uv)), "{:?}", vertex.lightmap_coords);
+		}
+	}
+}
\ No newline at end of file
@@ -0,0 +1,167 @@
+use crate::texture;
+
+// how the upsampled effect is combined with the full resolution image
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Composite {
+	Multiply, // occlusion and shadowing, rgb is multiplied onto the image
+}
+
+impl Composite {
+	fn blend(self) -> wgpu::BlendState {
+		match self {
+			Composite::Multiply => wgpu::BlendState {
+				color: wgpu::BlendComponent {
+					src_factor: wgpu::BlendFactor::Dst,
+					dst_factor: wgpu::BlendFactor::Zero,
+					operation: wgpu::BlendOperation::Add,
+				},
+				alpha: wgpu::BlendComponent {
+					src_factor: wgpu::BlendFactor::Zero,
+					dst_factor: wgpu::BlendFactor::One,
+					operation: wgpu::BlendOperation::Add,
+				},
+			},
+		}
+	}
+}
+
+// the surface config for a target at the surface size divided by divisor, rounded up
+pub fn scaled_config(config: &wgpu::SurfaceConfiguration, divisor: u32) -> wgpu::SurfaceConfiguration {
+	let mut scaled = config.clone();
+	scaled.width = config.width.div_ceil(divisor);
+	scaled.height = config.height.div_ceil(divisor);
+	scaled
+}
+
+/*
+Composites an effect rendered at reduced resolution onto the full resolution image, so
+expensive passes like AO can run at half resolution. The upsample is
+bilateral, weighting the nearest low resolution texels by depth similarity so edges stay
+sharp. Low resolution passes should render texel (x, y) from full resolution pixel
+(x, y) * divisor, which is the depth the weights compare against.
+*/
+pub struct BilateralUpsample {
+	layout: wgpu::BindGroupLayout,
+	pipeline: wgpu::RenderPipeline,
+}
+
+impl BilateralUpsample {
+	pub fn new(device: &wgpu::Device, label: &str, format: wgpu::TextureFormat, composite: Composite) -> Self {
+		let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+			label: Some("upsample_bind_group_layout"),
+			entries: &[
+				wgpu::BindGroupLayoutEntry { // depth
+					binding: 0,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Texture {
+						multisampled: false,
+						view_dimension: wgpu::TextureViewDimension::D2,
+						sample_type: wgpu::TextureSampleType::Depth,
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry { // camera
+					binding: 1,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Buffer {
+						ty: wgpu::BufferBindingType::Uniform,
+						has_dynamic_offset: false,
+						min_binding_size: None,
+					},
+					count: None,
+				},
+				wgpu::BindGroupLayoutEntry { // low resolution input
+					binding: 2,
+					visibility: wgpu::ShaderStages::FRAGMENT,
+					ty: wgpu::BindingType::Texture {
+						multisampled: false,
+						view_dimension: wgpu::TextureViewDimension::D2,
+						sample_type: wgpu::TextureSampleType::Float { filterable: true },
+					},
+					count: None,
+				},
+			],
+		});
+
+		let shader = device.create_shader_module(wgpu::include_wgsl!("upsample.wgsl"));
+		let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+			label: Some(&format!("{} Upsample Pipeline Layout", label)),
+			bind_group_layouts: &[Some(&layout)],
+			immediate_size: 0,
+		});
+		let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+			label: Some(&format!("{} Upsample Pipeline", label)),
+			layout: Some(&pipeline_layout),
+			vertex: wgpu::VertexState {
+				module: &shader,
+				entry_point: Some("vs_main"),
+				buffers: &[],
+				compilation_options: Default::default(),
+			},
+			fragment: Some(wgpu::FragmentState {
+				module: &shader,
+				entry_point: Some("fs_main"),
+				targets: &[Some(wgpu::ColorTargetState {
+					format,
+					blend: Some(composite.blend()),
+					write_mask: wgpu::ColorWrites::ALL,
+				})],
+				compilation_options: Default::default(),
+			}),
+			primitive: wgpu::PrimitiveState::default(),
+			depth_stencil: None,
+			multisample: wgpu::MultisampleState::default(),
+			multiview_mask: None,
+			cache: None,
+		});
+
+		Self {
+			layout,
+			pipeline,
+		}
+	}
+
+	// recreate with the depth texture on resize
+	pub fn create_bind_group(&self, device: &wgpu::Device, depth_texture: &texture::Texture, camera_buffer: &wgpu::Buffer, input: &wgpu::TextureView) -> wgpu::BindGroup {
+		device.create_bind_group(&wgpu::BindGroupDescriptor {
+			label: Some("upsample_bind_group"),
+			layout: &self.layout,
+			entries: &[
+				wgpu::BindGroupEntry {
+					binding: 0,
+					resource: wgpu::BindingResource::TextureView(&depth_texture.view),
+				},
+				wgpu::BindGroupEntry {
+					binding: 1,
+					resource: camera_buffer.as_entire_binding(),
+				},
+				wgpu::BindGroupEntry {
+					binding: 2,
+					resource: wgpu::BindingResource::TextureView(input),
+				},
+			],
+		})
+	}
+
+	pub fn draw(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView, bind_group: &wgpu::BindGroup) {
+		let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Upsample Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Load,
+					store: wgpu::StoreOp::Store,
+				},
+				depth_slice: None,
+			})],
+			depth_stencil_attachment: None,
+			occlusion_query_set: None,
+			timestamp_writes: None,
+			multiview_mask: None,
+		});
+		render_pass.set_pipeline(&self.pipeline);
+		render_pass.set_bind_group(0, bind_group, &[]);
+		render_pass.draw(0..3, 0..1);
+	}
+}
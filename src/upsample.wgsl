@@ -0,0 +1,61 @@
+// Depth aware upsample of a low resolution effect onto the full resolution image. Each of the
+// four nearest low resolution texels is weighted by its bilinear weight and by how close its
+// depth is to this pixel's, so the effect doesn't bleed across silhouettes.
+
+struct Camera {
+	view: mat4x4<f32>,
+	proj: mat4x4<f32>,
+	view_proj: mat4x4<f32>,
+	inv_view: mat4x4<f32>,
+	inv_proj: mat4x4<f32>,
+	inv_view_proj: mat4x4<f32>,
+	position: vec4<f32>,
+};
+
+@group(0) @binding(0)
+var depth_texture: texture_depth_2d;
+@group(0) @binding(1)
+var<uniform> camera: Camera;
+@group(0) @binding(2)
+var input_texture: texture_2d<f32>; // rendered at the full size divided by some integer
+
+const DEPTH_EPSILON: f32 = 0.001;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+	// one triangle covering the screen
+	let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+	return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+fn linear_depth(pixel: vec2<i32>) -> f32 {
+	let size = vec2<i32>(textureDimensions(depth_texture));
+	let depth = textureLoad(depth_texture, clamp(pixel, vec2<i32>(0), size - 1), 0);
+	let view = camera.inv_proj * vec4<f32>(0.0, 0.0, depth, 1.0);
+	return -view.z / view.w;
+}
+
+@fragment
+fn fs_main(@builtin(position) frag_coord: vec4<f32>) -> @location(0) vec4<f32> {
+	let pixel = vec2<i32>(frag_coord.xy);
+	let low_size = vec2<i32>(textureDimensions(input_texture));
+	// the low resolution texel at (x, y) was rendered from the full resolution pixel at (x, y) * divisor
+	let divisor = max(round(f32(textureDimensions(depth_texture).x) / f32(low_size.x)), 1.0);
+	let f = frag_coord.xy / divisor - 0.5;
+	let base = vec2<i32>(floor(f));
+	let t = fract(f);
+	let center = linear_depth(pixel);
+
+	var sum = vec4<f32>(0.0);
+	var total = 0.0;
+	for (var i = 0; i < 4; i++) {
+		let offset = vec2<i32>(i & 1, i >> 1u);
+		let texel = clamp(base + offset, vec2<i32>(0), low_size - 1);
+		let bilinear = select(1.0 - t.x, t.x, offset.x == 1) * select(1.0 - t.y, t.y, offset.y == 1);
+		let depth = linear_depth(vec2<i32>(vec2<f32>(texel) * divisor));
+		let weight = bilinear / (DEPTH_EPSILON + abs(depth - center) / center);
+		sum += textureLoad(input_texture, texel, 0) * weight;
+		total += weight;
+	}
+	return sum / max(total, 1e-6);
+}
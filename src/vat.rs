@@ -0,0 +1,167 @@
+use crate::{model, skinning};
+
+// texels per vertex per frame, the position then the normal
+const TEXELS_PER_VERTEX: u32 = 2;
+
+// where a baked animation is in the atlas
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VatClip {
+	pub start: u32, // first texel, counting along the rows
+	pub vertex_count: u32,
+	pub frame_count: u32,
+	pub frame_rate: f32,
+}
+
+// a clip playing on one instance, advanced by Scene::advance_vertex_animations
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VatPlayback {
+	pub clip: VatClip,
+	pub frame: f32, // blended with the next frame by the fraction, wraps around at the clip's end
+	pub speed: f32,
+}
+
+impl VatPlayback {
+	pub fn new(clip: VatClip) -> Self {
+		Self {
+			clip,
+			frame: 0.0,
+			speed: 1.0,
+		}
+	}
+
+	pub fn advance(&mut self, delta_time: f32) {
+		self.frame = (self.frame + delta_time * self.speed * self.clip.frame_rate).rem_euclid(self.clip.frame_count.max(1) as f32);
+	}
+
+	// the vat field of the model uniform, zero vertices turns the lookup off
+	pub fn to_uniform(self) -> [f32; 4] {
+		[self.clip.start as f32, self.clip.vertex_count as f32, self.clip.frame_count as f32, self.frame]
+	}
+}
+
+/*
+Vertex animation textures, a cheap stand-in for skinning on background characters and foliage.
+Every frame of an animation is baked into one texture as a position and normal per vertex, and
+the vertex shader of an instance with a VatPlayback reads its vertex from the two frames around
+its playback position instead of the vertex buffer, so copies at different times still draw in
+one instanced draw and nothing is skinned at runtime. Clips are bump allocated from whole rows
+and stay until the atlas is dropped.
+The shader only knows the vertex index within a mesh, so every mesh of a model reads the same
+clip, and shadow casters draw the rest pose.
+*/
+pub struct VatAtlas {
+	texture: wgpu::Texture,
+	pub view: wgpu::TextureView, // bound at 7 of the uniform bind groups
+	head: u32, // first free row
+}
+
+impl VatAtlas {
+	pub const WIDTH: u32 = 1024;
+	pub const HEIGHT: u32 = 1024;
+	pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+	pub fn new(device: &wgpu::Device) -> Self {
+		let texture = device.create_texture(&wgpu::TextureDescriptor {
+			label: Some("Vertex Animation Texture"),
+			size: wgpu::Extent3d {
+				width: Self::WIDTH,
+				height: Self::HEIGHT,
+				depth_or_array_layers: 1,
+			},
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: wgpu::TextureDimension::D2,
+			format: Self::FORMAT,
+			usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+			view_formats: &[],
+		});
+		let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+		Self {
+			texture,
+			view,
+			head: 0,
+		}
+	}
+
+	/*
+	Bakes frames of model space vertices, each frame with the same vertex count as the mesh it
+	plays on. None when the atlas has no room left for it.
+	*/
+	pub fn bake(&mut self, queue: &wgpu::Queue, frames: &[Vec<model::ModelVertex>], frame_rate: f32) -> Option<VatClip> {
+		let vertex_count = frames.first().map_or(0, Vec::len);
+		assert!(frames.iter().all(|frame| frame.len() == vertex_count), "every frame needs the same vertices");
+		let texel_count = frames.len() as u32 * vertex_count as u32 * TEXELS_PER_VERTEX;
+		let rows = texel_count.div_ceil(Self::WIDTH);
+		if texel_count == 0 || self.head + rows > Self::HEIGHT {
+			return None;
+		}
+
+		let texel = |v: [f32; 3]| [v[0], v[1], v[2], 1.0].map(|c| half::f16::from_f32(c).to_bits());
+		let mut data = frames.iter()
+			.flatten()
+			.flat_map(|vertex| [texel(vertex.position), texel(vertex.normal)])
+			.collect::<Vec<_>>();
+		data.resize((rows * Self::WIDTH) as usize, [0u16; 4]);
+		queue.write_texture(
+			wgpu::TexelCopyTextureInfo {
+				texture: &self.texture,
+				mip_level: 0,
+				origin: wgpu::Origin3d { x: 0, y: self.head, z: 0 },
+				aspect: wgpu::TextureAspect::All,
+			},
+			bytemuck::cast_slice(&data),
+			wgpu::TexelCopyBufferLayout {
+				offset: 0,
+				bytes_per_row: Some(Self::WIDTH * 8),
+				rows_per_image: Some(rows),
+			},
+			wgpu::Extent3d {
+				width: Self::WIDTH,
+				height: rows,
+				depth_or_array_layers: 1,
+			},
+		);
+
+		let clip = VatClip {
+			start: self.head * Self::WIDTH,
+			vertex_count: vertex_count as u32,
+			frame_count: frames.len() as u32,
+			frame_rate,
+		};
+		self.head += rows;
+		Some(clip)
+	}
+
+	// rows left for clips
+	pub fn free_rows(&self) -> u32 {
+		Self::HEIGHT - self.head
+	}
+}
+
+/*
+Skins the vertices on the CPU once per pose, giving the frames for VatAtlas::bake. poses are
+the joint matrices of each frame, like the crowd's.
+*/
+pub fn skin_frames(vertices: &[model::ModelVertex], influences: &[skinning::VertexInfluence], poses: &[Vec<cgmath::Matrix4<f32>>]) -> Vec<Vec<model::ModelVertex>> {
+	use cgmath::{InnerSpace, Zero};
+	assert_eq!(vertices.len(), influences.len(), "every vertex needs an influence");
+	poses.iter()
+		.map(|joints| vertices.iter().zip(influences)
+			.map(|(vertex, influence)| {
+				let skin = (0..4)
+					.map(|i| joints[influence.joints[i] as usize] * influence.weights[i])
+					.fold(cgmath::Matrix4::zero(), |sum, joint| sum + joint);
+				let position = skin * cgmath::Vector3::from(vertex.position).extend(1.0);
+				let normal = (skin * cgmath::Vector3::from(vertex.normal).extend(0.0)).truncate();
+				let tangent = (skin * cgmath::Vector3::new(vertex.tangent[0], vertex.tangent[1], vertex.tangent[2]).extend(0.0)).truncate();
+				model::ModelVertex {
+					position: position.truncate().into(),
+					normal: normal.normalize().into(),
+					tangent: tangent.normalize().extend(vertex.tangent[3]).into(),
+					..*vertex
+				}
+			})
+			.collect())
+		.collect()
+}
@@ -0,0 +1,44 @@
+// what the material pipelines draw, everything but Lit is for looking at the geometry
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ViewMode {
+	#[default]
+	Lit,
+	Wireframe, // needs line polygon mode
+	Normals, // world space, mapped to 0 to 1
+	Depth, // view distance, log scaled between the near and far planes
+}
+
+impl ViewMode {
+	pub const ALL: [ViewMode; 4] = [ViewMode::Lit, ViewMode::Wireframe, ViewMode::Normals, ViewMode::Depth];
+
+	// the next mode, skipping the wireframe without line polygon mode
+	pub fn next(&self, wireframe: bool) -> Self {
+		let next = match self {
+			ViewMode::Lit => ViewMode::Wireframe,
+			ViewMode::Wireframe => ViewMode::Normals,
+			ViewMode::Normals => ViewMode::Depth,
+			ViewMode::Depth => ViewMode::Lit,
+		};
+		if next == ViewMode::Wireframe && !wireframe {
+			next.next(wireframe)
+		} else {
+			next
+		}
+	}
+
+	// mesh.wgsl has the debug entry points, the wireframe shades lines like the lit view
+	pub fn fragment_entry(&self) -> &'static str {
+		match self {
+			ViewMode::Lit | ViewMode::Wireframe => "fs_main",
+			ViewMode::Normals => "fs_normals",
+			ViewMode::Depth => "fs_depth",
+		}
+	}
+
+	pub fn polygon_mode(&self) -> wgpu::PolygonMode {
+		match self {
+			ViewMode::Wireframe => wgpu::PolygonMode::Line,
+			_ => wgpu::PolygonMode::Fill,
+		}
+	}
+}
@@ -0,0 +1,95 @@
+use winit::keyboard::KeyCode;
+
+use crate::scene;
+
+/*
+Look-dev viewer mode: frames everything in the scene, orbits the camera around it, and
+places the light from a direction that can be adjusted along with the exposure.
+J/L turn the light, I/K raise and lower it, and -/= change the exposure by a stop.
+*/
+pub struct Turntable {
+	pub enabled: bool,
+	pub speed: f32, // radians per second the camera orbits
+	pub light: Option<scene::LightHandle>, // None in scenes without lights
+	pub light_azimuth: f32, // radians around the up axis
+	pub light_elevation: f32, // radians above the horizon
+	pub exposure_stops: f32, // exposure is 2^stops
+	light_turn: f32,
+	light_raise: f32,
+}
+
+impl Turntable {
+	const LIGHT_SPEED: f32 = 1.0; // radians per second while a light key is held
+	const PADDING: f32 = 0.1;
+
+	pub fn new(light: Option<scene::LightHandle>) -> Self {
+		Self {
+			enabled: false,
+			speed: 0.3,
+			light,
+			light_azimuth: std::f32::consts::FRAC_PI_4,
+			light_elevation: std::f32::consts::FRAC_PI_4,
+			exposure_stops: 0.0,
+			light_turn: 0.0,
+			light_raise: 0.0,
+		}
+	}
+
+	pub fn toggle(&mut self, scene: &mut scene::Scene) {
+		self.enabled = !self.enabled;
+		if self.enabled {
+			self.frame(scene);
+		}
+	}
+
+	pub fn frame(&self, scene: &mut scene::Scene) {
+		if let Some(aabb) = scene.aabb() {
+			scene.camera.fit_to_bounds(&aabb, Self::PADDING);
+		}
+	}
+
+	// returns true if the key was used
+	pub fn handle_key(&mut self, code: KeyCode, is_pressed: bool) -> bool {
+		if !self.enabled {
+			return false;
+		}
+		let amount = if is_pressed { 1.0 } else { 0.0 };
+		match code {
+			KeyCode::KeyJ => self.light_turn = -amount,
+			KeyCode::KeyL => self.light_turn = amount,
+			KeyCode::KeyI => self.light_raise = amount,
+			KeyCode::KeyK => self.light_raise = -amount,
+			KeyCode::Minus if is_pressed => self.exposure_stops -= 1.0,
+			KeyCode::Equal if is_pressed => self.exposure_stops += 1.0,
+			_ => return false,
+		}
+		true
+	}
+
+	// takes real time rather than scene time, so the turntable keeps going while paused
+	pub fn update(&mut self, scene: &mut scene::Scene, dt: f32) {
+		use cgmath::{InnerSpace, Rotation3};
+
+		let camera = &mut scene.camera;
+		let rotation = cgmath::Quaternion::from_axis_angle(camera.up.normalize(), cgmath::Rad(self.speed * dt));
+		camera.eye = camera.target + rotation * (camera.eye - camera.target);
+
+		self.light_azimuth += self.light_turn * Self::LIGHT_SPEED * dt;
+		self.light_elevation = (self.light_elevation + self.light_raise * Self::LIGHT_SPEED * dt)
+			.clamp(-std::f32::consts::FRAC_PI_2, std::f32::consts::FRAC_PI_2);
+
+		// far enough out to light the whole model evenly
+		let distance = (camera.eye - camera.target).magnitude();
+		let direction = cgmath::Vector3::new(
+			self.light_elevation.cos() * self.light_azimuth.cos(),
+			self.light_elevation.sin(),
+			self.light_elevation.cos() * self.light_azimuth.sin(),
+		);
+		let position = camera.target + direction * distance;
+		if let Some(light) = self.light {
+			scene.set_light_position(light, position.into());
+		}
+
+		scene.environment.exposure = 2.0_f32.powf(self.exposure_stops);
+	}
+}
@@ -0,0 +1,225 @@
+use wgpu::util::DeviceExt;
+
+use crate::{camera, environment, renderer, scene, texture, uniform_arena};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct VirtualCameraHandle(usize);
+
+/*
+Small extra views of the scene, like character portraits or security cameras, rendered into
+the cells of one atlas. All of them are drawn in a single pass before the main view, each with
+its own viewport and a uniform bind group pointing at its camera and environment in shared
+buffers, instead of running the whole frame once per view, so each camera keeps its own exposure
+and tonemap. Post effects, imposters, and meshlet culling only run
+for the main view. The atlas can be sampled like any other texture, at uv_rect of the view.
+Every view is redrawn every frame.
+*/
+pub struct VirtualCameras {
+	cameras: Vec<Option<camera::Camera>>, // by atlas cell, None for a free cell
+	camera_buffer: wgpu::Buffer,
+	environment_buffer: wgpu::Buffer, // at the same stride as the cameras
+	bind_groups: Vec<wgpu::BindGroup>, // same layout as the renderer's uniform bind group
+	cell_size: u32,
+	atlas: texture::Texture,
+	surface_texture: texture::Texture,
+	depth_texture: texture::Texture,
+}
+
+impl VirtualCameras {
+	pub const COLUMNS: u32 = 4;
+	pub const ROWS: u32 = 4;
+	const CAMERA_STRIDE: u64 = 256; // min uniform buffer offset alignment
+
+	pub(crate) fn new(
+		device: &wgpu::Device,
+		config: &wgpu::SurfaceConfiguration,
+		cell_size: u32,
+		uniform_layout: &wgpu::BindGroupLayout,
+		shared: &renderer::SharedUniforms,
+	) -> Self {
+		let cell_count = (Self::COLUMNS * Self::ROWS) as usize;
+		let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Virtual Camera Buffer"),
+			contents: &vec![0; cell_count * Self::CAMERA_STRIDE as usize],
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+		});
+		let environment_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+			label: Some("Virtual Camera Environment Buffer"),
+			contents: &vec![0; cell_count * Self::CAMERA_STRIDE as usize],
+			usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+		});
+		let bind_groups = Self::create_bind_groups(device, uniform_layout, shared, &camera_buffer, &environment_buffer);
+
+		let mut atlas_config = config.clone();
+		atlas_config.width = cell_size * Self::COLUMNS;
+		atlas_config.height = cell_size * Self::ROWS;
+		let atlas = texture::Texture::create_render_target(device, &atlas_config, config.format, wgpu::TextureUsages::RENDER_ATTACHMENT, "virtual_camera_atlas");
+		let surface_texture = texture::Texture::create_render_target(device, &atlas_config, renderer::Renderer::SURFACE_FORMAT, wgpu::TextureUsages::RENDER_ATTACHMENT, "virtual_camera_surface_texture");
+		let depth_texture = texture::Texture::create_depth_texture(device, &atlas_config, "virtual_camera_depth_texture");
+
+		Self {
+			cameras: vec![None; cell_count],
+			camera_buffer,
+			environment_buffer,
+			bind_groups,
+			cell_size,
+			atlas,
+			surface_texture,
+			depth_texture,
+		}
+	}
+
+	// one per cell, in the layout of the renderer's uniform bind group
+	fn create_bind_groups(
+		device: &wgpu::Device,
+		uniform_layout: &wgpu::BindGroupLayout,
+		shared: &renderer::SharedUniforms,
+		camera_buffer: &wgpu::Buffer,
+		environment_buffer: &wgpu::Buffer,
+	) -> Vec<wgpu::BindGroup> {
+		(0..(Self::COLUMNS * Self::ROWS) as u64)
+			.map(|cell| {
+				let camera = wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+					buffer: camera_buffer,
+					offset: cell * Self::CAMERA_STRIDE,
+					size: wgpu::BufferSize::new(std::mem::size_of::<camera::CameraUniform>() as u64),
+				});
+				let environment = wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+					buffer: environment_buffer,
+					offset: cell * Self::CAMERA_STRIDE,
+					size: wgpu::BufferSize::new(std::mem::size_of::<environment::EnvironmentUniform>() as u64),
+				});
+				shared.bind_group(device, uniform_layout, camera, environment, "virtual_camera_bind_group")
+			})
+			.collect()
+	}
+
+	// after the uniform arena's buffer was replaced
+	pub(crate) fn rebind(&mut self, device: &wgpu::Device, uniform_layout: &wgpu::BindGroupLayout, shared: &renderer::SharedUniforms) {
+		self.bind_groups = Self::create_bind_groups(device, uniform_layout, shared, &self.camera_buffer, &self.environment_buffer);
+	}
+
+	// takes the first free cell, None when the atlas is full
+	pub fn add(&mut self, camera: camera::Camera) -> Option<VirtualCameraHandle> {
+		let cell = self.cameras.iter().position(Option::is_none)?;
+		self.cameras[cell] = Some(camera);
+		Some(VirtualCameraHandle(cell))
+	}
+
+	pub fn remove(&mut self, handle: VirtualCameraHandle) {
+		self.cameras[handle.0] = None;
+	}
+
+	pub fn camera_mut(&mut self, handle: VirtualCameraHandle) -> Option<&mut camera::Camera> {
+		self.cameras[handle.0].as_mut()
+	}
+
+	// the handle of an occupied cell, counting along the rows
+	pub fn handle(&self, cell: usize) -> Option<VirtualCameraHandle> {
+		self.cameras.get(cell)?.as_ref().map(|_| VirtualCameraHandle(cell))
+	}
+
+	pub fn atlas(&self) -> &texture::Texture {
+		&self.atlas
+	}
+
+	// where the view is in the atlas, as uv x, y, width, height
+	pub fn uv_rect(&self, handle: VirtualCameraHandle) -> [f32; 4] {
+		let column = handle.0 as u32 % Self::COLUMNS;
+		let row = handle.0 as u32 / Self::COLUMNS;
+		let (width, height) = (1.0 / Self::COLUMNS as f32, 1.0 / Self::ROWS as f32);
+		[column as f32 * width, row as f32 * height, width, height]
+	}
+
+	/*
+	Uploads the cameras and their environments, and finds the objects in each view, pushing uniforms for the ones the
+	main view culled. Returns the cell and object offsets of every view, for draw.
+	*/
+	pub(crate) fn prepare(&self, queue: &wgpu::Queue, uniform_arena: &mut uniform_arena::UniformArena, scene: &scene::Scene, object_offsets: &[Option<u32>]) -> Vec<(usize, Vec<Option<u32>>)> {
+		self.cameras.iter().enumerate()
+			.filter_map(|(cell, camera)| Some((cell, camera.as_ref()?)))
+			.map(|(cell, camera)| {
+				// the cells are square whatever the camera was made with
+				let camera = camera::Camera { aspect: 1.0, ..camera.clone() };
+				let mut uniform = camera::CameraUniform::new();
+				uniform.update_view_proj(&camera);
+				queue.write_buffer(&self.camera_buffer, cell as u64 * Self::CAMERA_STRIDE, bytemuck::cast_slice(&[uniform]));
+				// the atlas is sampled as it is, so the views are graded in the shaders even when the main view isn't
+				queue.write_buffer(&self.environment_buffer, cell as u64 * Self::CAMERA_STRIDE, bytemuck::cast_slice(&[scene.environment.to_uniform(&camera.settings)]));
+
+				let frustum = camera::Frustum::from_matrix(&camera.build_view_projection_matrix());
+				let offsets = scene.objects.iter().zip(object_offsets)
+					.map(|(obj, offset)| {
+						let (center, radius) = scene.models[obj.model_index].bounds.transformed(&obj.transform);
+						(obj.skin.is_some() || frustum.intersects_sphere(center, radius))
+							.then(|| offset.unwrap_or_else(|| uniform_arena.push(&obj.to_uniform())))
+					})
+					.collect();
+				(cell, offsets)
+			})
+			.collect()
+	}
+
+	pub(crate) fn eye(&self, cell: usize) -> cgmath::Point3<f32> {
+		self.cameras[cell].as_ref().map_or(cgmath::Point3::new(0.0, 0.0, 0.0), |camera| camera.eye)
+	}
+
+	// one pass over the atlas, draw_view is called with each view's uniform bind group and its index in views
+	pub(crate) fn draw<'a>(
+		&self,
+		encoder: &'a mut wgpu::CommandEncoder,
+		color_target_count: usize,
+		clear_color: wgpu::Color,
+		views: &[(usize, Vec<Option<u32>>)],
+		mut draw_view: impl FnMut(&mut wgpu::RenderPass<'a>, &wgpu::BindGroup, usize),
+	) {
+		if views.is_empty() {
+			return;
+		}
+		let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+			label: Some("Virtual Camera Pass"),
+			color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+				view: &self.atlas.view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Clear(clear_color),
+					store: wgpu::StoreOp::Store,
+				},
+				depth_slice: None,
+			}), Some(wgpu::RenderPassColorAttachment {
+				view: &self.surface_texture.view,
+				resolve_target: None,
+				ops: wgpu::Operations {
+					load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+					store: wgpu::StoreOp::Discard,
+				},
+				depth_slice: None,
+			})][..color_target_count],
+			depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+				view: &self.depth_texture.view,
+				depth_ops: Some(wgpu::Operations {
+					load: wgpu::LoadOp::Clear(1.0),
+					store: wgpu::StoreOp::Discard,
+				}),
+				stencil_ops: None,
+			}),
+			occlusion_query_set: None,
+			timestamp_writes: None,
+			multiview_mask: None,
+		});
+
+		for (view, (cell, _)) in views.iter().enumerate() {
+			let column = *cell as u32 % Self::COLUMNS;
+			let row = *cell as u32 / Self::COLUMNS;
+			render_pass.set_viewport(
+				(column * self.cell_size) as f32,
+				(row * self.cell_size) as f32,
+				self.cell_size as f32,
+				self.cell_size as f32,
+				0.0,
+				1.0,
+			);
+			draw_view(&mut render_pass, &self.bind_groups[*cell], view);
+		}
+	}
+}
@@ -0,0 +1,66 @@
+use std::{cell::RefCell, rc::Rc};
+
+use wasm_bindgen::{prelude::*, JsCast};
+
+pub struct DroppedFile {
+	pub name: String,
+	pub data: Vec<u8>,
+}
+
+/*
+Collects files dragged onto the canvas. The File API reads them asynchronously, so every
+drop is queued as one batch once all of its files are read, and picked up by the next
+update. Keeping a drop together lets an OBJ find the MTL and textures dropped with it.
+*/
+pub struct DropTarget {
+	batches: Rc<RefCell<Vec<Vec<DroppedFile>>>>,
+	_dragover: Closure<dyn FnMut(web_sys::DragEvent)>,
+	_drop: Closure<dyn FnMut(web_sys::DragEvent)>,
+}
+
+impl DropTarget {
+	pub fn new(canvas: &web_sys::HtmlCanvasElement) -> Self {
+		let batches = Rc::new(RefCell::new(vec![]));
+
+		// the browser only fires drop if dragover is cancelled
+		let dragover = Closure::<dyn FnMut(_)>::new(|event: web_sys::DragEvent| event.prevent_default());
+		let drop = {
+			let batches = batches.clone();
+			Closure::<dyn FnMut(_)>::new(move |event: web_sys::DragEvent| {
+				event.prevent_default();
+				let Some(list) = event.data_transfer().and_then(|transfer| transfer.files()) else {
+					return;
+				};
+				let files = (0..list.length()).filter_map(|i| list.get(i)).collect::<Vec<_>>();
+
+				let batches = batches.clone();
+				wasm_bindgen_futures::spawn_local(async move {
+					let mut batch = vec![];
+					for file in files {
+						match wasm_bindgen_futures::JsFuture::from(file.array_buffer()).await {
+							Ok(buffer) => batch.push(DroppedFile {
+								name: file.name(),
+								data: js_sys::Uint8Array::new(&buffer).to_vec(),
+							}),
+							Err(e) => log::error!("Unable to read {}: {:?}", file.name(), e),
+						}
+					}
+					batches.borrow_mut().push(batch);
+				});
+			})
+		};
+
+		canvas.add_event_listener_with_callback("dragover", dragover.as_ref().unchecked_ref()).unwrap_throw();
+		canvas.add_event_listener_with_callback("drop", drop.as_ref().unchecked_ref()).unwrap_throw();
+
+		Self {
+			batches,
+			_dragover: dragover,
+			_drop: drop,
+		}
+	}
+
+	pub fn take(&self) -> Vec<Vec<DroppedFile>> {
+		std::mem::take(&mut *self.batches.borrow_mut())
+	}
+}